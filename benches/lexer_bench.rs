@@ -0,0 +1,109 @@
+//! Tokenizing with one combined DFA versus one DFA per rule tried in order.
+//!
+//! Both lexers run the automata built by `DfaBuilder` through
+//! `longest_match`, so the difference is only in how many automata each
+//! token has to go through.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use minipg::ast::Grammar;
+use minipg::codegen::dfa::{longest_match, DfaBuilder, DfaState};
+use minipg::parser::GrammarParser;
+use minipg::traits::GrammarParser as GrammarParserTrait;
+
+const KEYWORDS: [&str; 50] = [
+    "abstract", "as", "async", "await", "break", "case", "catch", "class", "const", "continue",
+    "default", "defer", "do", "else", "enum", "export", "extends", "extern", "final", "finally",
+    "fn", "for", "from", "goto", "if", "impl", "import", "in", "interface", "let",
+    "loop", "match", "mod", "module", "mut", "new", "package", "private", "protected", "pub",
+    "return", "static", "struct", "switch", "throw", "trait", "try", "type", "use", "while",
+];
+
+fn keyword_grammar() -> Grammar {
+    let mut text = String::from("grammar Keywords;\nprog: .*;\n");
+    for keyword in KEYWORDS {
+        text.push_str(&format!("KW_{}: '{}';\n", keyword.to_uppercase(), keyword));
+    }
+    text.push_str("ID: [a-zA-Z_] [a-zA-Z0-9_]*;\n");
+    GrammarParser::new()
+        .parse_string(&text, "Keywords.g4")
+        .expect("Failed to parse grammar")
+}
+
+/// Keywords and identifiers that share their prefixes, separated by spaces.
+fn keyword_input(words: usize) -> String {
+    (0..words)
+        .map(|i| match i % 3 {
+            0 => KEYWORDS[i % KEYWORDS.len()].to_string(),
+            1 => format!("{}_{}", KEYWORDS[i % KEYWORDS.len()], i),
+            _ => format!("name{}", i),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn tokenize_combined(states: &[DfaState], input: &str) -> usize {
+    let mut rest = input;
+    let mut count = 0;
+    while let Some(ch) = rest.chars().next() {
+        if ch == ' ' {
+            rest = &rest[1..];
+            continue;
+        }
+        let (len, _) = longest_match(states, rest).expect("no token matched");
+        rest = &rest[len..];
+        count += 1;
+    }
+    count
+}
+
+fn tokenize_sequential(per_rule: &[Vec<DfaState>], input: &str) -> usize {
+    let mut rest = input;
+    let mut count = 0;
+    while let Some(ch) = rest.chars().next() {
+        if ch == ' ' {
+            rest = &rest[1..];
+            continue;
+        }
+        // Longest match wins; the earliest rule wins ties
+        let mut best: Option<(usize, &str)> = None;
+        for states in per_rule {
+            if let Some(found) = longest_match(states, rest)
+                && best.is_none_or(|(len, _)| found.0 > len)
+            {
+                best = Some(found);
+            }
+        }
+        let (len, _) = best.expect("no token matched");
+        rest = &rest[len..];
+        count += 1;
+    }
+    count
+}
+
+fn bench_keyword_lexing(c: &mut Criterion) {
+    let grammar = keyword_grammar();
+    let rules: Vec<_> = grammar.lexer_rules().collect();
+    let combined = DfaBuilder::new().build_from_rules(&rules);
+    let per_rule: Vec<Vec<DfaState>> = rules
+        .iter()
+        .map(|rule| DfaBuilder::new().build_from_rules(&[rule]))
+        .collect();
+
+    let input = keyword_input(3000);
+    assert_eq!(
+        tokenize_combined(&combined, &input),
+        tokenize_sequential(&per_rule, &input)
+    );
+
+    let mut group = c.benchmark_group("lexer_50_keywords");
+    group.bench_function("combined_dfa", |b| {
+        b.iter(|| tokenize_combined(black_box(&combined), black_box(&input)))
+    });
+    group.bench_function("sequential_rules", |b| {
+        b.iter(|| tokenize_sequential(black_box(&per_rule), black_box(&input)))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_keyword_lexing);
+criterion_main!(benches);
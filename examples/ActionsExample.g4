@@ -53,7 +53,7 @@ factor
   : NUMBER
   | '(' expression ')'
   | ID
-  | ID '/' NUMBER {self.position > 0}?  // Semantic predicate
+  | ID '/' NUMBER {self.tokens.position() > 0}?  // Semantic predicate
   ;
 
 // Lexer rules
@@ -3,6 +3,9 @@
 
 grammar PythonSubset;
 
+// No lexer rule matches these; an indentation-tracking lexer emits them
+tokens { INDENT, DEDENT }
+
 // Parser Rules
 file_input
     : (NEWLINE | statement)* EOF
@@ -341,14 +344,6 @@ NEWLINE
     : '\r'? '\n'
     ;
 
-INDENT
-    : '    ' // Simplified: 4 spaces
-    ;
-
-DEDENT
-    : // Handled by lexer logic
-    ;
-
 WS
     : [ \t]+ -> skip
     ;
@@ -1,4 +1,4 @@
-grammar parser Calculator;
+grammar Calculator;
 
 // Parser rules
 expr: term;
@@ -1,4 +1,4 @@
-grammar parser JSON;
+grammar JSON;
 
 json: value;
 
@@ -1,9 +1,91 @@
 //! Ambiguous alternative detection.
 
-use super::first_follow::FirstFollowComputer;
-use crate::ast::{Grammar, Rule};
+use super::first_follow::{FirstFollowComputer, FirstFollowSets};
+use crate::ast::{Alternative, Element, Grammar, Rule};
+use crate::Diagnostic;
 use std::collections::HashSet;
 
+/// Warn about alternatives of the same rule that can start with the same token.
+///
+/// A predictive parser cannot choose between such alternatives without
+/// backtracking. Alternatives that begin with the same tokens and then
+/// diverge, like `X Y | X Z`, are not reported: one token past the common
+/// prefix decides between them. Neither are left-recursive alternatives,
+/// which are rewritten into a loop before code generation.
+pub fn detect(grammar: &Grammar, sets: &FirstFollowSets) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+
+    for rule in grammar.parser_rules() {
+        let alternatives = &rule.alternatives;
+        for i in 0..alternatives.len() {
+            for j in (i + 1)..alternatives.len() {
+                if is_left_recursive(rule, &alternatives[i]) || is_left_recursive(rule, &alternatives[j]) {
+                    continue;
+                }
+                let mut shared: Vec<String> = sets
+                    .first_of_sequence(&alternatives[i].elements)
+                    .intersection(&sets.first_of_sequence(&alternatives[j].elements))
+                    .cloned()
+                    .collect();
+                if shared.is_empty() || diverge_after_prefix(&alternatives[i], &alternatives[j], sets) {
+                    continue;
+                }
+                shared.sort();
+
+                let mut diagnostic = Diagnostic::warning(format!(
+                    "alternatives {} and {} of rule '{}' can both start with {}",
+                    i + 1,
+                    j + 1,
+                    rule.name,
+                    shared.join(", ")
+                ))
+                .with_code("W004");
+                if let Some(location) = &rule.location {
+                    diagnostic = diagnostic.with_location(location.clone());
+                }
+                diagnostics.push(diagnostic);
+            }
+        }
+    }
+
+    diagnostics
+}
+
+fn is_left_recursive(rule: &Rule, alternative: &Alternative) -> bool {
+    matches!(alternative.elements.first(), Some(Element::RuleRef { name, .. }) if *name == rule.name)
+}
+
+/// Whether two alternatives share a prefix of tokens and rule references
+/// after which their FIRST sets are disjoint.
+fn diverge_after_prefix(first: &Alternative, second: &Alternative, sets: &FirstFollowSets) -> bool {
+    let prefix = first
+        .elements
+        .iter()
+        .zip(&second.elements)
+        .take_while(|(a, b)| same_symbol(a, b))
+        .count();
+    if prefix == 0 {
+        return false;
+    }
+
+    let (rest1, rest2) = (&first.elements[prefix..], &second.elements[prefix..]);
+    if sets.is_sequence_nullable(rest1) || sets.is_sequence_nullable(rest2) {
+        return false;
+    }
+    sets.first_of_sequence(rest1)
+        .is_disjoint(&sets.first_of_sequence(rest2))
+}
+
+/// Whether two elements match the same symbol, ignoring labels.
+fn same_symbol(a: &Element, b: &Element) -> bool {
+    match (a, b) {
+        (Element::RuleRef { name: a, .. }, Element::RuleRef { name: b, .. })
+        | (Element::Terminal { value: a, .. }, Element::Terminal { value: b, .. })
+        | (Element::StringLiteral { value: a, .. }, Element::StringLiteral { value: b, .. }) => a == b,
+        _ => false,
+    }
+}
+
 /// Detects ambiguous alternatives in grammar rules.
 pub struct AmbiguityDetector {
     first_follow: FirstFollowComputer,
@@ -71,42 +153,8 @@ impl AmbiguityDetector {
         }
     }
 
-    fn first_of_alternative(&self, alt: &crate::ast::Alternative) -> HashSet<String> {
-        let mut result = HashSet::new();
-
-        for element in &alt.elements {
-            match element {
-                crate::ast::Element::RuleRef { name, .. } => {
-                    if let Some(first) = self.first_follow.first(name) {
-                        result.extend(first.iter().cloned());
-                    }
-                    // If not nullable, stop here
-                    if !self.first_follow.is_rule_nullable(name) {
-                        break;
-                    }
-                }
-                crate::ast::Element::Terminal { value, .. }
-                | crate::ast::Element::StringLiteral { value, .. } => {
-                    result.insert(value.clone());
-                    break;
-                }
-                crate::ast::Element::Optional { .. } | crate::ast::Element::ZeroOrMore { .. } => {
-                    // Nullable, continue
-                }
-                crate::ast::Element::OneOrMore { element, .. } => {
-                    // Get first of inner element
-                    result.extend(self.first_of_alternative(&crate::ast::Alternative {
-                        elements: vec![*element.clone()],
-                        label: None,
-                        lexer_command: None,
-                    }));
-                    break;
-                }
-                _ => break,
-            }
-        }
-
-        result
+    fn first_of_alternative(&self, alt: &Alternative) -> HashSet<String> {
+        self.first_follow.first_of_sequence(&alt.elements)
     }
 }
 
@@ -185,6 +233,37 @@ mod tests {
 
         assert_eq!(results.len(), 1);
         assert_eq!(results[0].rule_name, "expr");
-        assert!(results[0].conflicting_tokens.contains(&"x".to_string()));
+        assert!(results[0].conflicting_tokens.contains(&"'x'".to_string()));
+    }
+
+    fn detect_in(text: &str) -> Vec<Diagnostic> {
+        use crate::parser::GrammarParser;
+        use crate::traits::GrammarParser as GrammarParserTrait;
+
+        let grammar = GrammarParser::new().parse_string(text, "T.g4").unwrap();
+        let sets = crate::analysis::SemanticAnalyzer::compute_first_follow(&grammar);
+        detect(&grammar, &sets)
+    }
+
+    #[test]
+    fn test_detect_shared_first_token() {
+        let diagnostics = detect_in("grammar T; s: a | b | 'x'; a: ID '='; b: ID '('; ID: [a-z]+;");
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].code.as_deref(), Some("W004"));
+        assert_eq!(
+            diagnostics[0].message,
+            "alternatives 1 and 2 of rule 's' can both start with ID"
+        );
+        assert!(diagnostics[0].location.is_some());
+    }
+
+    #[test]
+    fn test_detect_ignores_prefix_that_diverges() {
+        assert!(detect_in("grammar T; s: ID '=' ID | ID '(' ')'; ID: [a-z]+;").is_empty());
+
+        // Same prefix, but one alternative may stop right after it
+        let diagnostics = detect_in("grammar T; s: ID | ID '(' ')'; ID: [a-z]+;");
+        assert_eq!(diagnostics.len(), 1);
     }
 }
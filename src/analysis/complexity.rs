@@ -0,0 +1,129 @@
+//! Generated code size estimates for grammar rules.
+//!
+//! Every group, optional block and loop becomes a backtracking block in
+//! the generated parser, and everything nested inside it is emitted once
+//! more per level of indentation. The estimate below charges each construct
+//! its rough line count, weighted by nesting depth, so deeply nested rules
+//! grow quadratically just like the real output.
+
+use crate::ast::{Alternative, Element, Grammar, Rule};
+use crate::Diagnostic;
+
+/// Default estimate above which a rule is reported as too large.
+pub const DEFAULT_MAX_RULE_SIZE: usize = 2_000;
+
+/// Lines spent saving and restoring state around one alternative.
+const ALTERNATIVE_COST: usize = 8;
+/// Lines spent on a repetition or optional loop.
+const LOOP_COST: usize = 12;
+/// Lines spent opening a group.
+const GROUP_COST: usize = 4;
+
+/// Estimate the amount of code generated for `rule`.
+///
+/// The unit is roughly one line of generated code at the top level of
+/// the rule method; content `n` levels deep costs `n + 1` times as much.
+pub fn estimated_rule_size(rule: &Rule) -> usize {
+    if rule.alternatives.len() == 1 {
+        alternative_size(&rule.alternatives[0], 0)
+    } else {
+        alternatives_size(&rule.alternatives, 0)
+    }
+}
+
+/// Warn about every rule whose estimated size exceeds `max_size`.
+pub fn check_rule_sizes(grammar: &Grammar, max_size: usize) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+
+    for rule in grammar.parser_rules() {
+        let size = estimated_rule_size(rule);
+        if size <= max_size {
+            continue;
+        }
+
+        let mut diagnostic = Diagnostic::warning(format!(
+            "rule '{}' has an estimated generated size of {} (threshold {}); \
+             consider moving nested groups into separate rules",
+            rule.name, size, max_size
+        ))
+        .with_code("W006");
+        if let Some(location) = &rule.location {
+            diagnostic = diagnostic.with_location(location.clone());
+        }
+        diagnostics.push(diagnostic);
+    }
+
+    diagnostics
+}
+
+fn alternatives_size(alternatives: &[Alternative], depth: usize) -> usize {
+    alternatives
+        .iter()
+        .map(|alt| ALTERNATIVE_COST * (depth + 1) + alternative_size(alt, depth + 1))
+        .sum()
+}
+
+fn alternative_size(alternative: &Alternative, depth: usize) -> usize {
+    alternative
+        .elements
+        .iter()
+        .map(|element| element_size(element, depth))
+        .sum()
+}
+
+fn element_size(element: &Element, depth: usize) -> usize {
+    let weight = depth + 1;
+    match element {
+        Element::Group { alternatives } => {
+            GROUP_COST * weight + alternatives_size(alternatives, depth + 1)
+        }
+        Element::Optional { element, .. }
+        | Element::ZeroOrMore { element, .. }
+        | Element::OneOrMore { element, .. }
+        | Element::Not { element } => LOOP_COST * weight + element_size(element, depth + 1),
+        _ => weight,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::GrammarParser;
+    use crate::traits::GrammarParser as GrammarParserTrait;
+
+    fn parse(text: &str) -> Grammar {
+        GrammarParser::new().parse_string(text, "test.g4").unwrap()
+    }
+
+    #[test]
+    fn test_flat_rule_size() {
+        let grammar = parse("grammar T; r: A B C; A: 'a'; B: 'b'; C: 'c';");
+        assert_eq!(estimated_rule_size(grammar.get_rule("r").unwrap()), 3);
+    }
+
+    #[test]
+    fn test_nesting_grows_faster_than_length() {
+        let grammar = parse(
+            "grammar T; flat: A B A B A B A B; nested: ((((A B)*)*)*)*; A: 'a'; B: 'b';",
+        );
+        let flat = estimated_rule_size(grammar.get_rule("flat").unwrap());
+        let nested = estimated_rule_size(grammar.get_rule("nested").unwrap());
+        assert!(nested > 10 * flat, "flat {} nested {}", flat, nested);
+    }
+
+    #[test]
+    fn test_check_rule_sizes_warns_on_nested_rule() {
+        let nested = format!("{}A{}", "(A | (B ".repeat(12), "))*".repeat(12));
+        let grammar = parse(&format!(
+            "grammar T; ok: A B; deep: {}; A: 'a'; B: 'b';",
+            nested
+        ));
+
+        let diagnostics = check_rule_sizes(&grammar, DEFAULT_MAX_RULE_SIZE);
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].code.as_deref(), Some("W006"));
+        assert!(diagnostics[0].message.starts_with("rule 'deep'"));
+        assert!(diagnostics[0].location.is_some());
+    }
+}
@@ -1,8 +1,9 @@
 //! Grammar composition and import resolution.
 
-use crate::ast::Grammar;
+use crate::ast::{AstVisitor, Element, Grammar};
 use crate::traits::GrammarParser as GrammarParserTrait;
 use crate::{Error, Result};
+use std::cell::RefCell;
 use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
 
@@ -21,49 +22,154 @@ impl GrammarComposer {
         }
     }
 
-    /// Add a search path for grammar files.
-    pub fn add_search_path(&mut self, path: impl AsRef<Path>) {
-        self.search_paths.push(path.as_ref().to_path_buf());
+    /// Flatten `base` and everything it imports into a single grammar.
+    ///
+    /// `resolver` loads an imported grammar by name. Imports are resolved
+    /// depth-first in declaration order and, as in ANTLR4, a rule is only
+    /// taken from an import when no grammar earlier in that order already
+    /// defines it, so the importing grammar can override imported rules.
+    /// Options and named actions follow the same precedence; lexer modes,
+    /// channels and declared tokens are unioned. An import cycle is reported as an error naming
+    /// the chain of grammars involved.
+    ///
+    /// A selective import such as `import Base = {ID};` takes only the named
+    /// rules and the fragments they use; see [`Self::select_rules`].
+    pub fn compose(base: &Grammar, resolver: &dyn Fn(&str) -> Result<Grammar>) -> Result<Grammar> {
+        let mut stack = vec![base.name.clone()];
+        Self::compose_imports(base, resolver, &mut stack)
     }
 
-    /// Resolve all imports in a grammar.
-    pub fn resolve_imports(&mut self, grammar: &mut Grammar) -> Result<()> {
-        let imports = grammar.imports.clone();
+    fn compose_imports(
+        grammar: &Grammar,
+        resolver: &dyn Fn(&str) -> Result<Grammar>,
+        stack: &mut Vec<String>,
+    ) -> Result<Grammar> {
+        let mut composed = grammar.clone();
+        composed.imports.clear();
 
-        for import_name in imports {
-            self.resolve_import(grammar, &import_name)?;
+        for import_name in &grammar.imports {
+            if let Some(start) = stack.iter().position(|name| name == import_name) {
+                let mut cycle = stack[start..].to_vec();
+                cycle.push(import_name.clone());
+                return Err(Error::parse(
+                    "grammar composition".to_string(),
+                    format!("circular import detected: {}", cycle.join(" -> ")),
+                ));
+            }
+
+            let imported = resolver(import_name)?;
+            stack.push(import_name.clone());
+            let mut flattened = Self::compose_imports(&imported, resolver, stack)?;
+            stack.pop();
+            if let Some(selected) = grammar.imported_rules.get(import_name) {
+                flattened = Self::select_rules(flattened, selected)?;
+            }
+
+            Self::absorb(&mut composed, flattened);
         }
 
-        Ok(())
+        Ok(composed)
     }
 
-    /// Resolve a single import.
-    fn resolve_import(&mut self, grammar: &mut Grammar, import_name: &str) -> Result<()> {
-        // Check if already cached
-        if self.grammar_cache.contains_key(import_name) {
-            let imported = self.grammar_cache.get(import_name).unwrap().clone();
-            self.merge_grammar(grammar, &imported)?;
-            return Ok(());
+    /// Narrow an imported grammar down to the `selected` rules.
+    ///
+    /// Fragments the selected rules use, directly or through other
+    /// fragments, are kept so they still compile. Options and named actions
+    /// are dropped, and lexer modes keep only the selected rules. Naming a
+    /// rule the grammar does not define is an error.
+    pub fn select_rules(mut grammar: Grammar, selected: &[String]) -> Result<Grammar> {
+        if let Some(missing) = selected.iter().find(|name| grammar.get_rule(name).is_none()) {
+            return Err(Error::parse(
+                "grammar composition".to_string(),
+                format!(
+                    "rule '{}' is not defined in imported grammar '{}'",
+                    missing, grammar.name
+                ),
+            ));
         }
 
-        // Try to find and load the grammar file
-        let grammar_file = self.find_grammar_file(import_name)?;
+        let mut keep: HashSet<String> = selected.iter().cloned().collect();
+        let mut pending = selected.to_vec();
+        while let Some(name) = pending.pop() {
+            let mut collector = RuleRefCollector::default();
+            if let Some(rule) = grammar.get_rule(&name) {
+                collector.visit_rule(rule);
+            }
+            for reference in collector.names {
+                let is_fragment = grammar.get_rule(&reference).is_some_and(|r| r.is_fragment);
+                if is_fragment && keep.insert(reference.clone()) {
+                    pending.push(reference);
+                }
+            }
+        }
 
-        // Parse the imported grammar
-        let parser = crate::parser::GrammarParser::new();
-        let imported_grammar = parser.parse_file(&grammar_file)?;
+        grammar.rules.retain(|rule| keep.contains(&rule.name));
+        grammar.options.clear();
+        grammar.named_actions.clear();
+        for rules in grammar.lexer_modes.values_mut() {
+            rules.retain(|name| keep.contains(name));
+        }
+        grammar.lexer_modes.retain(|_, rules| !rules.is_empty());
+        Ok(grammar)
+    }
 
-        // Cache it
-        self.grammar_cache
-            .insert(import_name.to_string(), imported_grammar.clone());
+    /// Add whatever `target` does not already define from `source`.
+    fn absorb(target: &mut Grammar, source: Grammar) {
+        for rule in source.rules {
+            if target.get_rule(&rule.name).is_none() {
+                target.add_rule(rule);
+            }
+        }
 
-        // Recursively resolve imports in the imported grammar
-        let mut imported = imported_grammar;
-        self.resolve_imports(&mut imported)?;
+        for (key, value) in source.options {
+            target.options.entry(key).or_insert(value);
+        }
+
+        for (name, code) in source.named_actions {
+            target.named_actions.entry(name).or_insert(code);
+        }
 
-        // Merge into current grammar
-        self.merge_grammar(grammar, &imported)?;
+        for (mode_name, rules) in source.lexer_modes {
+            let mode = target.lexer_modes.entry(mode_name).or_default();
+            for rule in rules {
+                if !mode.contains(&rule) {
+                    mode.push(rule);
+                }
+            }
+        }
 
+        target.channels.extend(source.channels);
+        for token in source.declared_tokens {
+            if !target.declared_tokens.contains(&token) {
+                target.declared_tokens.push(token);
+            }
+        }
+    }
+
+    /// Add a search path for grammar files.
+    pub fn add_search_path(&mut self, path: impl AsRef<Path>) {
+        self.search_paths.push(path.as_ref().to_path_buf());
+    }
+
+    /// Resolve all imports in a grammar, replacing it with the result of
+    /// [`Self::compose`].
+    ///
+    /// Imported grammars are read from `<name>.g4` in the first search path
+    /// that has one, and parsed once per composer.
+    pub fn resolve_imports(&mut self, grammar: &mut Grammar) -> Result<()> {
+        let cache = RefCell::new(std::mem::take(&mut self.grammar_cache));
+        let composed = Self::compose(grammar, &|import_name| {
+            if let Some(imported) = cache.borrow().get(import_name) {
+                return Ok(imported.clone());
+            }
+            let grammar_file = self.find_grammar_file(import_name)?;
+            let imported = crate::parser::GrammarParser::new().parse_file(&grammar_file)?;
+            cache.borrow_mut().insert(import_name.to_string(), imported.clone());
+            Ok(imported)
+        });
+        self.grammar_cache = cache.into_inner();
+
+        *grammar = composed?;
         Ok(())
     }
 
@@ -87,7 +193,11 @@ impl GrammarComposer {
         ))
     }
 
-    /// Merge an imported grammar into the current grammar.
+    /// Merge the rules, options and the like of `source` into `target`.
+    ///
+    /// Unlike [`Self::compose`], where the importing grammar overrides what
+    /// it imports, a rule both grammars define is an error here, and the
+    /// options and named actions of `source` win.
     pub fn merge_grammar(&self, target: &mut Grammar, source: &Grammar) -> Result<()> {
         // Check for rule conflicts
         let target_rule_names: HashSet<String> =
@@ -128,6 +238,13 @@ impl GrammarComposer {
             target.add_channel(channel.clone());
         }
 
+        // Merge declared tokens
+        for token in &source.declared_tokens {
+            if !target.declared_tokens.contains(token) {
+                target.declared_tokens.push(token.clone());
+            }
+        }
+
         Ok(())
     }
 
@@ -162,6 +279,21 @@ impl GrammarComposer {
     }
 }
 
+/// Visitor to collect the names of referenced rules.
+#[derive(Default)]
+struct RuleRefCollector {
+    names: Vec<String>,
+}
+
+impl AstVisitor for RuleRefCollector {
+    fn visit_element(&mut self, element: &Element) {
+        if let Element::RuleRef { name, .. } = element {
+            self.names.push(name.clone());
+        }
+        self.walk_element(element);
+    }
+}
+
 impl Default for GrammarComposer {
     fn default() -> Self {
         Self::new()
@@ -310,6 +442,146 @@ mod tests {
         assert!(result.is_err());
     }
 
+    fn resolve_from(sources: &[(&str, &str)], name: &str) -> Result<Grammar> {
+        let parser = crate::parser::GrammarParser::new();
+        let (_, text) = sources
+            .iter()
+            .find(|(grammar_name, _)| *grammar_name == name)
+            .ok_or_else(|| Error::parse("import resolution", format!("unknown grammar {}", name)))?;
+        parser.parse_string(text, &format!("{}.g4", name))
+    }
+
+    #[test]
+    fn test_compose_flattens_nested_imports() {
+        let sources = [
+            ("Common", "grammar Common; import Tokens; atom: ID | NUMBER; ID: [a-z]+;"),
+            ("Tokens", "grammar Tokens; NUMBER: [0-9]+; ID: 'id' | [A-Z]+; WS: [ ]+ -> skip;"),
+        ];
+        let parser = crate::parser::GrammarParser::new();
+        let base = parser
+            .parse_string("grammar Main; import Common; expr: atom ('+' atom)*;", "Main.g4")
+            .unwrap();
+
+        let composed = GrammarComposer::compose(&base, &|name| resolve_from(&sources, name)).unwrap();
+
+        let names: Vec<&str> = composed.rules.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, vec!["expr", "atom", "ID", "NUMBER", "WS"]);
+        assert!(composed.imports.is_empty());
+
+        // Common's ID overrides the one it imports from Tokens
+        let id = composed.get_rule("ID").unwrap();
+        assert_eq!(id.alternatives.len(), 1);
+    }
+
+    #[test]
+    fn test_compose_base_overrides_imported_rules_and_options() {
+        let mut base = Grammar::new("Main".to_string(), crate::types::GrammarType::Combined);
+        base.add_import("Lib".to_string());
+        base.add_option("language".to_string(), "rust".to_string());
+        base.add_rule(crate::ast::Rule::parser_rule("expr".to_string()));
+
+        let composed = GrammarComposer::compose(&base, &|_| {
+            let mut lib = Grammar::new("Lib".to_string(), crate::types::GrammarType::Combined);
+            lib.add_option("language".to_string(), "python".to_string());
+            lib.add_option("tokenVocab".to_string(), "Lib".to_string());
+            lib.add_rule(crate::ast::Rule::lexer_rule("ID".to_string()));
+            let mut expr = crate::ast::Rule::parser_rule("expr".to_string());
+            expr.add_alternative(crate::ast::Alternative::new());
+            lib.add_rule(expr);
+            lib.add_channel("COMMENTS".to_string());
+            Ok(lib)
+        })
+        .unwrap();
+
+        assert_eq!(composed.rules.len(), 2);
+        assert!(composed.get_rule("expr").unwrap().alternatives.is_empty());
+        assert_eq!(composed.options.get("language").map(|s| s.as_str()), Some("rust"));
+        assert_eq!(composed.options.get("tokenVocab").map(|s| s.as_str()), Some("Lib"));
+        assert!(composed.channels.contains("COMMENTS"));
+    }
+
+    #[test]
+    fn test_resolve_imports_reads_files_and_lets_the_importer_override() {
+        let dir = std::env::temp_dir().join(format!("minipg_resolve_imports_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("Base.g4"), "grammar Base; import Tokens; expr: ID; ID: [a-z]+;").unwrap();
+        std::fs::write(dir.join("Tokens.g4"), "grammar Tokens; ID: [A-Z]+; WS: [ ]+ -> skip;").unwrap();
+        let parser = crate::parser::GrammarParser::new();
+        let mut grammar = parser
+            .parse_string("grammar Main; import Base; expr: ID ID;", "Main.g4")
+            .unwrap();
+
+        let mut composer = GrammarComposer::new();
+        composer.search_paths = vec![dir.clone()];
+        composer.resolve_imports(&mut grammar).unwrap();
+
+        let names: Vec<&str> = grammar.rules.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, vec!["expr", "ID", "WS"]);
+        assert_eq!(grammar.get_rule("expr").unwrap().alternatives[0].elements.len(), 2);
+        assert!(grammar.imports.is_empty());
+
+        // Imports cached by an earlier resolution are not read again
+        std::fs::remove_dir_all(&dir).unwrap();
+        let mut again = parser.parse_string("grammar Other; import Base; r: ID;", "Other.g4").unwrap();
+        composer.resolve_imports(&mut again).unwrap();
+        assert!(again.get_rule("WS").is_some());
+    }
+
+    #[test]
+    fn test_compose_selective_import() {
+        let sources = [(
+            "Base",
+            "grammar Base; options { tokenVocab = Base; } atom: ID; ID: LETTER+; NUMBER: DIGIT+; fragment LETTER: [a-z]; fragment DIGIT: [0-9];",
+        )];
+        let parser = crate::parser::GrammarParser::new();
+        let base = parser
+            .parse_string("grammar Main; import Base = {ID}; expr: ID+;", "Main.g4")
+            .unwrap();
+        assert_eq!(base.imports, vec!["Base".to_string()]);
+        assert_eq!(base.imported_rules.get("Base"), Some(&vec!["ID".to_string()]));
+
+        let composed = GrammarComposer::compose(&base, &|name| resolve_from(&sources, name)).unwrap();
+
+        let names: Vec<&str> = composed.rules.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, vec!["expr", "ID", "LETTER"]);
+        assert!(composed.options.is_empty());
+    }
+
+    #[test]
+    fn test_compose_selective_import_of_unknown_rule() {
+        let sources = [("Base", "grammar Base; ID: [a-z]+;")];
+        let parser = crate::parser::GrammarParser::new();
+        let base = parser
+            .parse_string("grammar Main; import Base = {ID, STRING}; expr: ID;", "Main.g4")
+            .unwrap();
+
+        let err = GrammarComposer::compose(&base, &|name| resolve_from(&sources, name)).unwrap_err();
+        assert!(err.to_string().contains("rule 'STRING' is not defined in imported grammar 'Base'"));
+    }
+
+    #[test]
+    fn test_compose_detects_import_cycle() {
+        let sources = [
+            ("A", "grammar A; import B; a: 'a';"),
+            ("B", "grammar B; import A; b: 'b';"),
+        ];
+        let base = resolve_from(&sources, "A").unwrap();
+
+        let err = GrammarComposer::compose(&base, &|name| resolve_from(&sources, name)).unwrap_err();
+        assert!(err.to_string().contains("A -> B -> A"));
+    }
+
+    #[test]
+    fn test_compose_propagates_resolver_errors() {
+        let mut base = Grammar::new("Main".to_string(), crate::types::GrammarType::Combined);
+        base.add_import("Missing".to_string());
+
+        let result = GrammarComposer::compose(&base, &|name| {
+            Err(Error::parse("import resolution", format!("grammar file not found: {}.g4", name)))
+        });
+        assert!(result.is_err());
+    }
+
     #[test]
     fn test_grammar_composer_default() {
         let composer = GrammarComposer::default();
@@ -0,0 +1,228 @@
+//! Desugaring of EBNF quantifiers into plain BNF rules.
+
+use crate::ast::{Alternative, Element, Grammar, Rule};
+use std::collections::{HashMap, HashSet};
+
+/// Rewrites `?`, `*` and `+` in parser rules into generated recursive rules.
+///
+/// This is an optional transform for consumers that want canonical BNF;
+/// nothing in the normal pipeline applies it. For example `a*` becomes a
+/// reference to a new rule `a_star: a a_star | ;`, and a quantified group
+/// such as `('+' term)*` in rule `expr` becomes `expr_star: '+' term expr_star | ;`.
+///
+/// Lexer rules are left untouched since the lexer DFA handles quantifiers
+/// directly. Non-greedy markers have no BNF equivalent and are dropped, and
+/// labels inside a quantified element move with it into the generated rule.
+pub struct EbnfDesugarer {
+    generated: Vec<Rule>,
+    used_names: HashSet<String>,
+    /// Generated rule for an unlabeled `x?`/`x*`/`x+` on a parser rule `x`.
+    rule_ref_cache: HashMap<(String, &'static str), String>,
+}
+
+impl EbnfDesugarer {
+    pub fn new() -> Self {
+        Self {
+            generated: Vec::new(),
+            used_names: HashSet::new(),
+            rule_ref_cache: HashMap::new(),
+        }
+    }
+
+    /// Return a copy of `grammar` whose parser rules contain no quantifiers.
+    ///
+    /// Generated rules are appended after the existing rules, so the start
+    /// rule is unchanged.
+    pub fn desugar(&mut self, grammar: &Grammar) -> Grammar {
+        self.generated.clear();
+        self.rule_ref_cache.clear();
+        self.used_names = grammar.rules.iter().map(|r| r.name.clone()).collect();
+
+        let mut result = grammar.clone();
+        for rule in result.rules.iter_mut().filter(|r| r.is_parser_rule()) {
+            let name = rule.name.clone();
+            for alt in &mut rule.alternatives {
+                self.desugar_alternative(alt, &name);
+            }
+        }
+
+        result.rules.append(&mut self.generated);
+        result
+    }
+
+    fn desugar_alternative(&mut self, alt: &mut Alternative, rule_name: &str) {
+        let elements = std::mem::take(&mut alt.elements);
+        alt.elements = elements
+            .into_iter()
+            .map(|element| self.desugar_element(element, rule_name))
+            .collect();
+    }
+
+    fn desugar_element(&mut self, element: Element, rule_name: &str) -> Element {
+        match element {
+            Element::Optional { element, .. } => self.quantified(*element, "opt", rule_name),
+            Element::ZeroOrMore { element, .. } => self.quantified(*element, "star", rule_name),
+            Element::OneOrMore { element, .. } => self.quantified(*element, "plus", rule_name),
+            Element::Group { mut alternatives } => {
+                for alt in &mut alternatives {
+                    self.desugar_alternative(alt, rule_name);
+                }
+                Element::Group { alternatives }
+            }
+            Element::Not { element } => Element::Not {
+                element: Box::new(self.desugar_element(*element, rule_name)),
+            },
+            other => other,
+        }
+    }
+
+    /// Replace a quantified element with a reference to a generated rule.
+    fn quantified(&mut self, element: Element, kind: &'static str, rule_name: &str) -> Element {
+        let cache_key = match &element {
+            Element::RuleRef { name, label: None, .. }
+                if name.starts_with(|c: char| c.is_lowercase()) =>
+            {
+                Some((name.clone(), kind))
+            }
+            _ => None,
+        };
+        if let Some(existing) = cache_key.as_ref().and_then(|key| self.rule_ref_cache.get(key)) {
+            return Element::rule_ref(existing.clone());
+        }
+
+        let base = match &cache_key {
+            Some((name, _)) => name.as_str(),
+            None => rule_name,
+        };
+        let name = self.fresh_name(&format!("{}_{}", base, kind));
+        if let Some(key) = cache_key {
+            self.rule_ref_cache.insert(key, name.clone());
+        }
+
+        // Quantifiers nested inside the element become rules of their own first
+        let body: Vec<Alternative> = match self.desugar_element(element, rule_name) {
+            Element::Group { alternatives } => alternatives,
+            single => {
+                let mut alt = Alternative::new();
+                alt.add_element(single);
+                vec![alt]
+            }
+        };
+
+        let repeat = |alt: &Alternative| {
+            let mut alt = alt.clone();
+            alt.add_element(Element::rule_ref(name.clone()));
+            alt
+        };
+
+        let mut rule = Rule::parser_rule(name.clone());
+        match kind {
+            "opt" => {
+                rule.alternatives.extend(body);
+                rule.add_alternative(Alternative::new());
+            }
+            "star" => {
+                rule.alternatives.extend(body.iter().map(repeat));
+                rule.add_alternative(Alternative::new());
+            }
+            _ => {
+                rule.alternatives.extend(body.iter().map(repeat));
+                rule.alternatives.extend(body);
+            }
+        }
+        self.generated.push(rule);
+
+        Element::rule_ref(name)
+    }
+
+    fn fresh_name(&mut self, base: &str) -> String {
+        let mut name = base.to_string();
+        let mut n = 2;
+        while self.used_names.contains(&name) {
+            name = format!("{}_{}", base, n);
+            n += 1;
+        }
+        self.used_names.insert(name.clone());
+        name
+    }
+}
+
+impl Default for EbnfDesugarer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::GrammarParser;
+    use crate::traits::GrammarParser as GrammarParserTrait;
+
+    fn has_quantifier(element: &Element) -> bool {
+        match element {
+            Element::Optional { .. } | Element::ZeroOrMore { .. } | Element::OneOrMore { .. } => true,
+            Element::Group { alternatives } => alternatives
+                .iter()
+                .any(|alt| alt.elements.iter().any(has_quantifier)),
+            Element::Not { element } => has_quantifier(element),
+            _ => false,
+        }
+    }
+
+    fn parse(text: &str) -> Grammar {
+        GrammarParser::new().parse_string(text, "test.g4").unwrap()
+    }
+
+    #[test]
+    fn test_desugar_removes_quantifiers() {
+        let grammar = parse(
+            "grammar T; list: item (',' item)* ';'?; item: ID+ | '(' list? ')'; ID: [a-z]+;",
+        );
+
+        let desugared = EbnfDesugarer::new().desugar(&grammar);
+
+        for rule in desugared.parser_rules() {
+            for alt in &rule.alternatives {
+                assert!(!alt.elements.iter().any(has_quantifier), "{} still has a quantifier", rule.name);
+            }
+        }
+        // Lexer rules keep their quantifiers
+        let id = desugared.get_rule("ID").unwrap();
+        assert!(has_quantifier(&id.alternatives[0].elements[0]));
+
+        let names: Vec<&str> = desugared.rules.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec!["list", "item", "ID", "list_star", "list_opt", "item_plus", "list_opt_2"]
+        );
+    }
+
+    #[test]
+    fn test_desugar_rule_shapes() {
+        let grammar = parse("grammar T; s: a* b+; a: 'a'; b: 'b';");
+
+        let desugared = EbnfDesugarer::new().desugar(&grammar);
+
+        let star = desugared.get_rule("a_star").unwrap();
+        assert_eq!(star.alternatives.len(), 2);
+        assert_eq!(star.alternatives[0].elements.len(), 2);
+        assert!(matches!(&star.alternatives[0].elements[1], Element::RuleRef { name, .. } if name == "a_star"));
+        assert!(star.alternatives[1].elements.is_empty());
+
+        let plus = desugared.get_rule("b_plus").unwrap();
+        assert_eq!(plus.alternatives.len(), 2);
+        assert_eq!(plus.alternatives[0].elements.len(), 2);
+        assert_eq!(plus.alternatives[1].elements.len(), 1);
+    }
+
+    #[test]
+    fn test_desugar_reuses_rule_for_same_reference() {
+        let grammar = parse("grammar T; s: a* ';' a*; a: 'a';");
+
+        let desugared = EbnfDesugarer::new().desugar(&grammar);
+
+        assert_eq!(desugared.rules.len(), 3);
+        assert!(desugared.get_rule("a_star").is_some());
+    }
+}
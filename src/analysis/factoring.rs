@@ -0,0 +1,173 @@
+//! Right-factoring of alternatives that end in the same elements.
+
+use super::{FirstFollowSets, SemanticAnalyzer};
+use crate::ast::{Alternative, Element, Grammar, Rule};
+
+/// Merge consecutive parser rule alternatives that share a common suffix.
+///
+/// `a: x ';' | y ';';` becomes `a: (x | y) ';';`, so the suffix is parsed by
+/// one piece of generated code instead of once per alternative. This is an
+/// optional transform; nothing in the normal pipeline applies it.
+///
+/// Only neighbouring alternatives are merged, so the order in which the
+/// parser tries them is unchanged. A run is merged only when the prefixes
+/// left in the group are non-nullable and start with disjoint tokens, so the
+/// group always picks the alternative the original rule would have.
+/// Alternatives with a `#` label or `<...>` options are left alone, and the
+/// suffix must repeat labels exactly, so labeled fields keep their shape.
+///
+/// Returns the names of the rules that changed.
+pub fn factor_common_suffixes(grammar: &mut Grammar) -> Vec<String> {
+    let sets = SemanticAnalyzer::compute_first_follow(grammar);
+
+    let mut changed = Vec::new();
+    for rule in grammar.rules.iter_mut().filter(|r| r.is_parser_rule()) {
+        if factor_rule(rule, &sets) {
+            changed.push(rule.name.clone());
+        }
+    }
+    changed
+}
+
+fn factor_rule(rule: &mut Rule, sets: &FirstFollowSets) -> bool {
+    let alternatives = std::mem::take(&mut rule.alternatives);
+    let mut changed = false;
+
+    let mut start = 0;
+    while start < alternatives.len() {
+        // Grow the run while it still shares a suffix that is safe to factor
+        let mut end = start + 1;
+        let mut suffix = 0;
+        while end < alternatives.len() {
+            let run = &alternatives[start..=end];
+            let len = common_suffix_len(run);
+            if len == 0 || !prefixes_are_disjoint(run, len, sets) {
+                break;
+            }
+            suffix = len;
+            end += 1;
+        }
+
+        if suffix == 0 {
+            rule.alternatives.push(alternatives[start].clone());
+            start += 1;
+        } else {
+            rule.alternatives.push(factor_run(&alternatives[start..end], suffix));
+            changed = true;
+            start = end;
+        }
+    }
+
+    changed
+}
+
+/// Length of the suffix every alternative in `run` ends with, leaving each
+/// of them at least one element of prefix.
+fn common_suffix_len(run: &[Alternative]) -> usize {
+    if run.iter().any(|alt| alt.label.is_some() || !alt.options.is_empty()) {
+        return 0;
+    }
+
+    let first = &run[0].elements;
+    let max = run.iter().map(|alt| alt.elements.len()).min().unwrap_or(0);
+    (0..max)
+        .take_while(|&n| {
+            let expected = &first[first.len() - 1 - n];
+            run.iter()
+                .all(|alt| same_element(&alt.elements[alt.elements.len() - 1 - n], expected))
+        })
+        .count()
+        .min(max.saturating_sub(1))
+}
+
+/// Whether the prefixes left after removing `suffix` elements can never
+/// start the same way or match nothing.
+fn prefixes_are_disjoint(run: &[Alternative], suffix: usize, sets: &FirstFollowSets) -> bool {
+    let prefixes: Vec<&[Element]> = run
+        .iter()
+        .map(|alt| &alt.elements[..alt.elements.len() - suffix])
+        .collect();
+    if prefixes.iter().any(|prefix| sets.is_sequence_nullable(prefix)) {
+        return false;
+    }
+
+    let firsts: Vec<_> = prefixes
+        .iter()
+        .map(|prefix| sets.first_of_sequence(prefix))
+        .collect();
+    firsts
+        .iter()
+        .enumerate()
+        .all(|(i, first)| firsts[i + 1..].iter().all(|other| first.is_disjoint(other)))
+}
+
+fn factor_run(run: &[Alternative], suffix: usize) -> Alternative {
+    let group = Element::Group {
+        alternatives: run
+            .iter()
+            .map(|alt| Alternative {
+                elements: alt.elements[..alt.elements.len() - suffix].to_vec(),
+                ..Alternative::new()
+            })
+            .collect(),
+    };
+
+    let first = &run[0].elements;
+    let mut alt = Alternative::new();
+    alt.add_element(group);
+    alt.elements.extend(first[first.len() - suffix..].iter().cloned());
+    alt
+}
+
+/// Whether two elements match the same symbol with the same label.
+fn same_element(a: &Element, b: &Element) -> bool {
+    match (a, b) {
+        (
+            Element::RuleRef { name: a, label: la, is_list: xa, .. },
+            Element::RuleRef { name: b, label: lb, is_list: xb, .. },
+        )
+        | (
+            Element::Terminal { value: a, label: la, is_list: xa, .. },
+            Element::Terminal { value: b, label: lb, is_list: xb, .. },
+        )
+        | (
+            Element::StringLiteral { value: a, label: la, is_list: xa, .. },
+            Element::StringLiteral { value: b, label: lb, is_list: xb, .. },
+        ) => a == b && la == lb && xa == xb,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::GrammarParser;
+    use crate::traits::GrammarParser as GrammarParserTrait;
+
+    fn parse(text: &str) -> Grammar {
+        GrammarParser::new().parse_string(text, "test.g4").unwrap()
+    }
+
+    #[test]
+    fn test_factor_shared_suffix() {
+        let mut grammar = parse("grammar T; a: x ';' | y ';' | z; x: 'x'; y: 'y'; z: 'z';");
+
+        assert_eq!(factor_common_suffixes(&mut grammar), vec!["a".to_string()]);
+
+        let a = grammar.get_rule("a").unwrap();
+        assert_eq!(a.alternatives.len(), 2);
+        let factored = &a.alternatives[0].elements;
+        assert_eq!(factored.len(), 2);
+        assert!(matches!(&factored[0], Element::Group { alternatives } if alternatives.len() == 2));
+        assert!(matches!(&factored[1], Element::StringLiteral { value, .. } if value == ";"));
+    }
+
+    #[test]
+    fn test_factor_skips_overlapping_prefixes() {
+        // Both prefixes start with 'x', so the group could pick the wrong one
+        let mut grammar = parse("grammar T; a: 'x' ';' | 'x' 'y' ';';");
+
+        assert!(factor_common_suffixes(&mut grammar).is_empty());
+        assert_eq!(grammar.get_rule("a").unwrap().alternatives.len(), 2);
+    }
+}
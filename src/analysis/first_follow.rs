@@ -3,10 +3,14 @@
 use crate::ast::{Element, Grammar};
 use std::collections::{HashMap, HashSet};
 
-const EPSILON: &str = "ε";
-const EOF: &str = "$";
+/// Marker for end of input in FOLLOW sets.
+pub const EOF: &str = "EOF";
 
 /// Computes First and Follow sets for grammar rules.
+///
+/// Sets are computed for parser rules. Terminals are named the way ANTLR
+/// displays them: token references by token name (`NUMBER`) and literals
+/// in quotes (`'('`). FOLLOW of the start rule contains [`EOF`].
 pub struct FirstFollowComputer {
     first_sets: HashMap<String, HashSet<String>>,
     follow_sets: HashMap<String, HashSet<String>>,
@@ -30,13 +34,17 @@ impl FirstFollowComputer {
     }
 
     fn initialize(&mut self, grammar: &Grammar) {
-        for rule in &grammar.rules {
+        self.first_sets.clear();
+        self.follow_sets.clear();
+        self.nullable.clear();
+
+        for rule in grammar.parser_rules() {
             self.first_sets.insert(rule.name.clone(), HashSet::new());
             self.follow_sets.insert(rule.name.clone(), HashSet::new());
         }
 
         // Add EOF to follow set of start rule
-        if let Some(start_rule) = grammar.rules.first() {
+        if let Some(start_rule) = grammar.parser_rules().next() {
             self.follow_sets
                 .get_mut(&start_rule.name)
                 .unwrap()
@@ -50,96 +58,44 @@ impl FirstFollowComputer {
         while changed {
             changed = false;
 
-            for rule in &grammar.rules {
-                let old_size = self.first_sets.get(&rule.name).unwrap().len();
-
+            for rule in grammar.parser_rules() {
+                let mut first = HashSet::new();
+                let mut nullable = false;
                 for alt in &rule.alternatives {
-                    self.compute_first_of_sequence(&alt.elements, grammar);
-
-                    // Add first of alternative to first of rule
-                    let first_of_alt = self.first_of_sequence(&alt.elements, grammar);
-                    if let Some(first_set) = self.first_sets.get_mut(&rule.name) {
-                        first_set.extend(first_of_alt);
-                    }
-
-                    // Check if alternative is nullable
-                    if self.is_sequence_nullable(&alt.elements) {
-                        self.nullable.insert(rule.name.clone());
-                    }
+                    first.extend(self.first_of_sequence(&alt.elements));
+                    nullable |= self.is_sequence_nullable(&alt.elements);
                 }
 
-                let new_size = self.first_sets.get(&rule.name).unwrap().len();
-                if new_size > old_size {
+                if nullable && self.nullable.insert(rule.name.clone()) {
                     changed = true;
                 }
-            }
-        }
-    }
-
-    #[allow(clippy::only_used_in_recursion)]
-    fn compute_first_of_sequence(&mut self, elements: &[Element], grammar: &Grammar) {
-        for element in elements {
-            match element {
-                Element::RuleRef { name, .. } => {
-                    // First set already computed in previous iteration
-                    if !self.is_nullable(name) {
-                        break;
-                    }
-                }
-                Element::Terminal { .. } | Element::StringLiteral { .. } => {
-                    break;
-                }
-                Element::Optional { .. } | Element::ZeroOrMore { .. } => {
-                    // These are nullable, continue
-                }
-                Element::OneOrMore { element, .. } => {
-                    // Not nullable, process inner element
-                    self.compute_first_of_sequence(&[*element.clone()], grammar);
-                    break;
+                let rule_first = self.first_sets.get_mut(&rule.name).unwrap();
+                let old_size = rule_first.len();
+                rule_first.extend(first);
+                if rule_first.len() > old_size {
+                    changed = true;
                 }
-                _ => break,
             }
         }
     }
 
-    #[allow(clippy::only_used_in_recursion)]
-    fn first_of_sequence(&self, elements: &[Element], grammar: &Grammar) -> HashSet<String> {
-        let mut result = HashSet::new();
+    /// FIRST set of a sequence of elements, using the sets computed so far.
+    ///
+    /// Whether the sequence can also match empty input is reported
+    /// separately by [`is_sequence_nullable`](Self::is_sequence_nullable).
+    pub fn first_of_sequence(&self, elements: &[Element]) -> HashSet<String> {
+        self.view().first_of_sequence(elements)
+    }
 
-        for element in elements {
-            match element {
-                Element::RuleRef { name, .. } => {
-                    if let Some(first) = self.first_sets.get(name) {
-                        result.extend(first.iter().filter(|s| *s != EPSILON).cloned());
-                    }
-                    if !self.is_nullable(name) {
-                        break;
-                    }
-                }
-                Element::Terminal { value, .. } | Element::StringLiteral { value, .. } => {
-                    result.insert(value.clone());
-                    break;
-                }
-                Element::Optional { .. } | Element::ZeroOrMore { .. } => {
-                    // Nullable, continue
-                }
-                Element::OneOrMore { element, .. } => {
-                    result.extend(self.first_of_sequence(&[*element.clone()], grammar));
-                    break;
-                }
-                Element::Wildcard => {
-                    result.insert("ANY".to_string());
-                    break;
-                }
-                _ => break,
-            }
-        }
+    fn first_of_element(&self, element: &Element) -> HashSet<String> {
+        self.view().first_of_element(element)
+    }
 
-        if result.is_empty() {
-            result.insert(EPSILON.to_string());
+    fn view(&self) -> SetsView<'_> {
+        SetsView {
+            first_sets: &self.first_sets,
+            nullable: &self.nullable,
         }
-
-        result
     }
 
     fn compute_follow_sets(&mut self, grammar: &Grammar) {
@@ -148,75 +104,74 @@ impl FirstFollowComputer {
         while changed {
             changed = false;
 
-            for rule in &grammar.rules {
+            for rule in grammar.parser_rules() {
+                let rule_follow = self.follow_sets.get(&rule.name).cloned().unwrap_or_default();
                 for alt in &rule.alternatives {
-                    for (i, element) in alt.elements.iter().enumerate() {
-                        if let Element::RuleRef { name, .. } = element {
-                            // Skip if rule doesn't exist (undefined rule)
-                            if !self.follow_sets.contains_key(name) {
-                                continue;
-                            }
-
-                            let old_size = self.follow_sets.get(name).unwrap().len();
-
-                            // Get first of rest
-                            let rest = &alt.elements[i + 1..];
-                            let first_of_rest = self.first_of_sequence(rest, grammar);
-
-                            // Add first of rest (except epsilon) to follow
-                            if let Some(follow) = self.follow_sets.get_mut(name) {
-                                follow.extend(
-                                    first_of_rest.iter().filter(|s| *s != EPSILON).cloned(),
-                                );
-                            }
-
-                            // If rest is nullable, add follow of rule
-                            if self.is_sequence_nullable(rest)
-                                && let Some(rule_follow) = self.follow_sets.get(&rule.name).cloned()
-                                    && let Some(follow) = self.follow_sets.get_mut(name) {
-                                        follow.extend(rule_follow);
-                                    }
-
-                            let new_size = self.follow_sets.get(name).unwrap().len();
-                            if new_size > old_size {
-                                changed = true;
-                            }
-                        }
-                    }
+                    changed |= self.follow_sequence(&alt.elements, &rule_follow);
                 }
             }
         }
     }
 
-    fn is_nullable(&self, rule_name: &str) -> bool {
-        self.nullable.contains(rule_name)
-    }
+    /// Propagate `after`, the tokens that can follow `elements`, to the rule
+    /// references inside the sequence. Returns whether any set grew.
+    fn follow_sequence(&mut self, elements: &[Element], after: &HashSet<String>) -> bool {
+        let mut changed = false;
+        let mut after = after.clone();
 
-    fn is_sequence_nullable(&self, elements: &[Element]) -> bool {
-        if elements.is_empty() {
-            return true;
+        for element in elements.iter().rev() {
+            changed |= self.follow_element(element, &after);
+
+            let mut before = self.first_of_element(element);
+            if self.is_element_nullable(element) {
+                before.extend(after);
+            }
+            after = before;
         }
 
-        for element in elements {
-            match element {
-                Element::RuleRef { name, .. } => {
-                    if !self.is_nullable(name) {
-                        return false;
-                    }
-                }
-                Element::Optional { .. } | Element::ZeroOrMore { .. } => {
-                    // Nullable, continue
+        changed
+    }
+
+    fn follow_element(&mut self, element: &Element, after: &HashSet<String>) -> bool {
+        match element {
+            Element::RuleRef { name, .. } => match self.follow_sets.get_mut(name) {
+                Some(follow) => {
+                    let old_size = follow.len();
+                    follow.extend(after.iter().cloned());
+                    follow.len() > old_size
                 }
-                Element::Terminal { .. }
-                | Element::StringLiteral { .. }
-                | Element::OneOrMore { .. } => {
-                    return false;
+                // Token references and undefined rules have no follow set
+                None => false,
+            },
+            Element::Optional { element, .. } => self.follow_element(element, after),
+            Element::ZeroOrMore { element, .. } | Element::OneOrMore { element, .. } => {
+                // A repeated element can also be followed by itself
+                let mut repeated = after.clone();
+                repeated.extend(self.first_of_element(element));
+                self.follow_element(element, &repeated)
+            }
+            Element::Group { alternatives } => {
+                let mut changed = false;
+                for alt in alternatives {
+                    changed |= self.follow_sequence(&alt.elements, after);
                 }
-                _ => return false,
+                changed
             }
+            _ => false,
         }
+    }
 
-        true
+    fn is_nullable(&self, rule_name: &str) -> bool {
+        self.nullable.contains(rule_name)
+    }
+
+    /// Whether `elements` can match empty input.
+    pub fn is_sequence_nullable(&self, elements: &[Element]) -> bool {
+        self.view().is_sequence_nullable(elements)
+    }
+
+    fn is_element_nullable(&self, element: &Element) -> bool {
+        self.view().is_element_nullable(element)
     }
 
     /// Get First set for a rule.
@@ -233,6 +188,16 @@ impl FirstFollowComputer {
     pub fn is_rule_nullable(&self, rule_name: &str) -> bool {
         self.is_nullable(rule_name)
     }
+
+    /// Take the computed sets as a [`FirstFollowSets`].
+    pub fn into_sets(self) -> FirstFollowSets {
+        FirstFollowSets {
+            first_sets: self.first_sets,
+            follow_sets: self.follow_sets,
+            nullable: self.nullable,
+            empty: HashSet::new(),
+        }
+    }
 }
 
 impl Default for FirstFollowComputer {
@@ -241,6 +206,173 @@ impl Default for FirstFollowComputer {
     }
 }
 
+/// FIRST and nullability queries over sets computed so far.
+struct SetsView<'a> {
+    first_sets: &'a HashMap<String, HashSet<String>>,
+    nullable: &'a HashSet<String>,
+}
+
+impl SetsView<'_> {
+    fn first_of_sequence(&self, elements: &[Element]) -> HashSet<String> {
+        let mut result = HashSet::new();
+
+        for element in elements {
+            result.extend(self.first_of_element(element));
+            if !self.is_element_nullable(element) {
+                break;
+            }
+        }
+
+        result
+    }
+
+    fn first_of_element(&self, element: &Element) -> HashSet<String> {
+        let mut result = HashSet::new();
+
+        match element {
+            Element::RuleRef { name, .. } if is_token_name(name) => {
+                result.insert(name.clone());
+            }
+            Element::RuleRef { name, .. } => {
+                if let Some(first) = self.first_sets.get(name) {
+                    result.extend(first.iter().cloned());
+                }
+            }
+            Element::Terminal { value, .. } => {
+                result.insert(value.clone());
+            }
+            Element::StringLiteral { value, .. } => {
+                result.insert(format!("'{}'", value));
+            }
+            Element::Eof => {
+                result.insert(EOF.to_string());
+            }
+            Element::Optional { element, .. }
+            | Element::ZeroOrMore { element, .. }
+            | Element::OneOrMore { element, .. } => {
+                result.extend(self.first_of_element(element));
+            }
+            Element::Group { alternatives } => {
+                for alt in alternatives {
+                    result.extend(self.first_of_sequence(&alt.elements));
+                }
+            }
+            Element::Wildcard | Element::Not { .. } => {
+                result.insert("ANY".to_string());
+            }
+            Element::CharRange { .. }
+            | Element::CharClass { .. }
+            | Element::UnicodeClass { .. }
+            | Element::Action { .. }
+            | Element::Predicate { .. } => {}
+        }
+
+        result
+    }
+
+    fn is_sequence_nullable(&self, elements: &[Element]) -> bool {
+        elements.iter().all(|element| self.is_element_nullable(element))
+    }
+
+    fn is_element_nullable(&self, element: &Element) -> bool {
+        match element {
+            // Only rules known to be nullable are, so token references are
+            // nullable only when lexer rules were checked
+            Element::RuleRef { name, .. } => self.nullable.contains(name),
+            Element::Optional { .. } | Element::ZeroOrMore { .. } => true,
+            // `[]` consumes no characters
+            Element::CharClass { negated: false, ranges } => ranges.is_empty(),
+            Element::OneOrMore { element, .. } => self.is_element_nullable(element),
+            Element::Group { alternatives } => alternatives
+                .iter()
+                .any(|alt| self.is_sequence_nullable(&alt.elements)),
+            Element::Action { .. } | Element::Predicate { .. } => true,
+            _ => false,
+        }
+    }
+}
+
+/// Names of the lexer rules, fragments included, that can match empty input.
+///
+/// Uses the same nullability rules as FIRST/FOLLOW computation, applied to
+/// lexer rules and the fragments and tokens they reference.
+pub fn nullable_lexer_rules(grammar: &Grammar) -> HashSet<String> {
+    let first_sets = HashMap::new();
+    let mut nullable = HashSet::new();
+    let mut changed = true;
+
+    while changed {
+        changed = false;
+        for rule in grammar.lexer_rules() {
+            let view = SetsView {
+                first_sets: &first_sets,
+                nullable: &nullable,
+            };
+            let is_nullable = rule
+                .alternatives
+                .iter()
+                .any(|alt| view.is_sequence_nullable(&alt.elements));
+            if is_nullable && !nullable.contains(&rule.name) {
+                nullable.insert(rule.name.clone());
+                changed = true;
+            }
+        }
+    }
+
+    nullable
+}
+
+/// Token references start with an uppercase letter, as in ANTLR.
+fn is_token_name(name: &str) -> bool {
+    name.chars().next().is_some_and(|c| c.is_uppercase())
+}
+
+/// Computed FIRST and FOLLOW sets of a grammar's parser rules.
+///
+/// Obtained from [`SemanticAnalyzer::compute_first_follow`](super::SemanticAnalyzer::compute_first_follow).
+#[derive(Debug, Clone)]
+pub struct FirstFollowSets {
+    first_sets: HashMap<String, HashSet<String>>,
+    follow_sets: HashMap<String, HashSet<String>>,
+    nullable: HashSet<String>,
+    empty: HashSet<String>,
+}
+
+impl FirstFollowSets {
+    /// Tokens that can start `rule`; empty for unknown rules.
+    pub fn first(&self, rule: &str) -> &HashSet<String> {
+        self.first_sets.get(rule).unwrap_or(&self.empty)
+    }
+
+    /// Tokens that can follow `rule`, including [`EOF`] for the start rule;
+    /// empty for unknown rules.
+    pub fn follow(&self, rule: &str) -> &HashSet<String> {
+        self.follow_sets.get(rule).unwrap_or(&self.empty)
+    }
+
+    /// Whether `rule` can match empty input.
+    pub fn is_nullable(&self, rule: &str) -> bool {
+        self.nullable.contains(rule)
+    }
+
+    /// Tokens that can start `elements`.
+    pub fn first_of_sequence(&self, elements: &[Element]) -> HashSet<String> {
+        self.view().first_of_sequence(elements)
+    }
+
+    /// Whether `elements` can match empty input.
+    pub fn is_sequence_nullable(&self, elements: &[Element]) -> bool {
+        self.view().is_sequence_nullable(elements)
+    }
+
+    fn view(&self) -> SetsView<'_> {
+        SetsView {
+            first_sets: &self.first_sets,
+            nullable: &self.nullable,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -261,7 +393,7 @@ mod tests {
         computer.compute(&grammar);
 
         let first = computer.first("expr").unwrap();
-        assert!(first.contains("x"));
+        assert!(first.contains("'x'"));
     }
 
     #[test]
@@ -286,7 +418,7 @@ mod tests {
         computer.compute(&grammar);
 
         let first = computer.first("expr").unwrap();
-        assert!(first.contains("x"));
+        assert!(first.contains("'x'"));
     }
 
     #[test]
@@ -312,6 +444,33 @@ mod tests {
         computer.compute(&grammar);
 
         let follow = computer.follow("term").unwrap();
-        assert!(follow.contains("y"));
+        assert!(follow.contains("'y'"));
+    }
+
+    #[test]
+    fn test_first_follow_through_groups_and_loops() {
+        use crate::parser::GrammarParser;
+        use crate::traits::GrammarParser as GrammarParserTrait;
+
+        let grammar = GrammarParser::new()
+            .parse_string(
+                "grammar T; s: a? (b | 'c')* 'd'; a: 'a'; b: ID | ; ID: [a-z]+;",
+                "T.g4",
+            )
+            .unwrap();
+
+        let mut computer = FirstFollowComputer::new();
+        computer.compute(&grammar);
+        let sets = computer.into_sets();
+
+        let first: HashSet<&str> = sets.first("s").iter().map(|s| s.as_str()).collect();
+        assert_eq!(first, HashSet::from(["'a'", "ID", "'c'", "'d'"]));
+        assert!(sets.is_nullable("b"));
+        assert!(!sets.is_nullable("s"));
+
+        let follow_b: HashSet<&str> = sets.follow("b").iter().map(|s| s.as_str()).collect();
+        assert_eq!(follow_b, HashSet::from(["ID", "'c'", "'d'"]));
+        assert_eq!(sets.follow("s"), &HashSet::from([EOF.to_string()]));
+        assert!(sets.first("ID").is_empty());
     }
 }
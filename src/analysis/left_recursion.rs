@@ -1,6 +1,8 @@
-//! Left recursion detection (direct and indirect).
+//! Left recursion detection (direct and indirect) and elimination.
 
-use crate::ast::{Element, Grammar, Rule};
+use super::first_follow::FirstFollowComputer;
+use crate::ast::{Alternative, Element, Grammar, Rule};
+use crate::Diagnostic;
 use std::collections::{HashMap, HashSet};
 
 /// Detects both direct and indirect left recursion in grammar rules.
@@ -157,6 +159,439 @@ impl Default for LeftRecursionDetector {
     }
 }
 
+/// Cycles of parser rules that reach one another in left position, which
+/// no rewrite of a single rule can remove.
+///
+/// A rule is in left position when an alternative can start with it: as
+/// its leftmost element, or after a prefix that can match nothing, such as
+/// the `b?` of `b? a`. Each cycle is a path from the cycle's earliest rule
+/// in declaration order back to that rule, as in `["a", "b", "a"]`, and is
+/// reported once. A rule that starts one of its own alternatives, as in
+/// `expr: expr '+' expr`, is direct left recursion and is not a cycle here,
+/// but a rule that reaches itself only after a nullable prefix is.
+pub fn find_indirect_cycles(grammar: &Grammar) -> Vec<Vec<String>> {
+    let mut sets = FirstFollowComputer::new();
+    sets.compute(grammar);
+
+    let rules: Vec<&Rule> = grammar.parser_rules().collect();
+    let index: HashMap<&str, usize> = rules
+        .iter()
+        .enumerate()
+        .map(|(i, rule)| (rule.name.as_str(), i))
+        .collect();
+    let edges: Vec<Vec<usize>> = rules
+        .iter()
+        .map(|rule| {
+            let mut targets = Vec::new();
+            for alt in &rule.alternatives {
+                let mut refs = Vec::new();
+                left_refs(&alt.elements, &sets, &mut refs);
+                let direct = is_directly_left_recursive(alt, &rule.name);
+                targets.extend(
+                    refs.into_iter()
+                        .filter(|name| !(direct && *name == rule.name))
+                        .filter_map(|name| index.get(name).copied()),
+                );
+            }
+            targets.sort_unstable();
+            targets.dedup();
+            targets
+        })
+        .collect();
+
+    let mut cycles = Vec::new();
+    for start in 0..rules.len() {
+        let mut path = vec![start];
+        walk_cycles(start, &edges, &mut path, &mut cycles);
+    }
+    cycles
+        .into_iter()
+        .map(|cycle| cycle.into_iter().map(|i| rules[i].name.clone()).collect())
+        .collect()
+}
+
+/// Extend `path` along `edges`, recording every way back to its first rule
+/// that only passes through rules declared after it.
+pub(super) fn walk_cycles(start: usize, edges: &[Vec<usize>], path: &mut Vec<usize>, cycles: &mut Vec<Vec<usize>>) {
+    let current = *path.last().unwrap();
+    for &next in &edges[current] {
+        if next == start {
+            let mut cycle = path.clone();
+            cycle.push(start);
+            cycles.push(cycle);
+        } else if next > start && !path.contains(&next) {
+            path.push(next);
+            walk_cycles(start, edges, path, cycles);
+            path.pop();
+        }
+    }
+}
+
+/// Names referenced where `elements` can start matching.
+fn left_refs<'g>(elements: &'g [Element], sets: &FirstFollowComputer, refs: &mut Vec<&'g str>) {
+    for element in elements {
+        match element {
+            Element::RuleRef { name, .. } => refs.push(name),
+            Element::Optional { element, .. }
+            | Element::ZeroOrMore { element, .. }
+            | Element::OneOrMore { element, .. } => {
+                left_refs(std::slice::from_ref(element.as_ref()), sets, refs)
+            }
+            Element::Group { alternatives } => {
+                for alt in alternatives {
+                    left_refs(&alt.elements, sets, refs);
+                }
+            }
+            _ => {}
+        }
+        if !sets.is_sequence_nullable(std::slice::from_ref(element)) {
+            break;
+        }
+    }
+}
+
+/// Rewrite directly left-recursive parser rules into iterative form.
+///
+/// `expr: expr '+' term | expr '-' term | term;` becomes
+/// `expr: term ('+' term | '-' term)*;`. Labels on the recursive
+/// reference itself are dropped, and `#Label`s on the alternatives, which
+/// the loop has no place for, are reported as an error.
+///
+/// As in ANTLR, earlier alternatives bind tighter, which matters for binary
+/// alternatives such as `expr '-' expr`: their right operand may only hold
+/// the operators of earlier alternatives. It becomes a reference to a new
+/// rule `expr_<k>` looping over the `k` tightest operators, or the
+/// non-recursive alternatives when there are none, so
+/// `expr: expr '*' expr | expr '+' expr | NUMBER;` becomes
+/// `expr: NUMBER ('*' NUMBER | '+' expr_1)*; expr_1: NUMBER ('*' NUMBER)*;`.
+/// Alternatives marked `<assoc=right>` also take their own operator in the
+/// right operand, so `2^3^4` recurses into `2^(3^4)`. The operand of a
+/// prefix alternative such as `'-' expr` holds the operators of the
+/// recursive alternatives before it in the same way, so with `'-' expr`
+/// first, `-1+2` is `(-1)+2`.
+///
+/// Indirect left recursion cannot be rewritten locally, so it is reported as
+/// a warning, as is a rule whose every alternative is left-recursive.
+pub fn eliminate_direct(grammar: &mut Grammar) -> Vec<Diagnostic> {
+    eliminate_direct_except(grammar, |_| false)
+}
+
+/// Like [`eliminate_direct`], leaving the operator rules that
+/// [`precedence_table`] reads alone when `precedence_climbing` is set,
+/// since a generated parser climbs those as they are.
+///
+/// This is what code generation needs: every other directly left-recursive
+/// rule would recurse forever in the generated parser.
+pub fn eliminate_unclimbable(grammar: &mut Grammar, precedence_climbing: bool) -> Vec<Diagnostic> {
+    eliminate_direct_except(grammar, |rule| {
+        precedence_climbing && precedence_table(rule).is_some()
+    })
+}
+
+fn eliminate_direct_except(grammar: &mut Grammar, keep: impl Fn(&Rule) -> bool) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let mut taken: HashSet<String> = grammar.rules.iter().map(|r| r.name.clone()).collect();
+    let mut rules = Vec::with_capacity(grammar.rules.len());
+
+    for mut rule in std::mem::take(&mut grammar.rules) {
+        let (recursive, base): (Vec<&Alternative>, Vec<&Alternative>) = rule
+            .alternatives
+            .iter()
+            .partition(|alt| is_directly_left_recursive(alt, &rule.name));
+        if !rule.is_parser_rule() || recursive.is_empty() || keep(&rule) {
+            rules.push(rule);
+            continue;
+        }
+        let located = |diagnostic: Diagnostic| match &rule.location {
+            Some(location) => diagnostic.with_location(location.clone()),
+            None => diagnostic,
+        };
+        if base.is_empty() {
+            diagnostics.push(located(
+                Diagnostic::warning(format!(
+                    "rule '{}' is left-recursive in every alternative and can never match",
+                    rule.name
+                ))
+                .with_code("W002"),
+            ));
+            rules.push(rule);
+            continue;
+        }
+        if rule.alternatives.iter().any(|alt| alt.label.is_some()) {
+            diagnostics.push(located(
+                Diagnostic::error(format!(
+                    "rule '{}' labels its alternatives, but the loop its left recursion is rewritten into has none; remove the labels or generate with --precedence-climbing (Rust target)",
+                    rule.name
+                ))
+                .with_code("E011"),
+            ));
+        }
+
+        // The number of tightest operators the right operand of each
+        // binary alternative may hold
+        let operand_levels: Vec<Option<usize>> = recursive
+            .iter()
+            .enumerate()
+            .map(|(i, alt)| {
+                ends_in_recursion(alt, &rule.name).then_some(if alt.is_right_assoc() { i + 1 } else { i })
+            })
+            .collect();
+        // Likewise for the operand of each prefix alternative such as
+        // `'-' expr`, which holds the operators of the recursive
+        // alternatives before it
+        let mut prefix_levels: Vec<Option<usize>> = Vec::with_capacity(base.len());
+        let mut preceding = 0;
+        for alt in &rule.alternatives {
+            if is_directly_left_recursive(alt, &rule.name) {
+                preceding += 1;
+            } else {
+                prefix_levels.push(ends_in_recursion(alt, &rule.name).then_some(preceding));
+            }
+        }
+
+        // A binary operand of level 0 is the operand itself, but a prefix
+        // operand of level 0 needs a rule to recurse into
+        let mut level_names: HashMap<usize, String> = HashMap::new();
+        let binary = operand_levels.iter().flatten().filter(|&&level| level != 0);
+        for &level in binary.chain(prefix_levels.iter().flatten()) {
+            if level == recursive.len() || level_names.contains_key(&level) {
+                continue;
+            }
+            let mut name = format!("{}_{}", rule.name, level);
+            while !taken.insert(name.clone()) {
+                name.push('_');
+            }
+            level_names.insert(level, name);
+        }
+        let base: Vec<Alternative> = base
+            .iter()
+            .zip(&prefix_levels)
+            .map(|(alt, prefix_level)| {
+                let mut alt = (*alt).clone();
+                if let (Some(level), Some(Element::RuleRef { name, .. })) =
+                    (prefix_level, alt.elements.last_mut())
+                    && *level != recursive.len()
+                {
+                    *name = level_names[level].clone();
+                }
+                alt
+            })
+            .collect();
+
+        let operand = match base.as_slice() {
+            [single] if single.elements.len() == 1 => single.elements[0].clone(),
+            _ => Element::Group { alternatives: base.clone() },
+        };
+        let operand_elements = match base.as_slice() {
+            [single] => single.elements.clone(),
+            _ => vec![operand.clone()],
+        };
+        let level_ref = |level: usize| match level {
+            0 => operand.clone(),
+            _ if level == recursive.len() => Element::rule_ref(rule.name.clone()),
+            _ => Element::rule_ref(level_names[&level].clone()),
+        };
+
+        // The operand followed by a loop over the `level` tightest operators
+        let level_alternative = |level: usize| {
+            let tails: Vec<Alternative> = recursive[..level]
+                .iter()
+                .zip(&operand_levels)
+                .filter(|(alt, _)| alt.elements.len() > 1)
+                .map(|(alt, operand_level)| {
+                    let mut tail = Alternative::new();
+                    tail.elements = alt.elements[1..].to_vec();
+                    if let Some(operand_level) = operand_level {
+                        *tail.elements.last_mut().unwrap() = level_ref(*operand_level);
+                    }
+                    tail
+                })
+                .collect();
+            let mut alternative = Alternative::new();
+            alternative.elements = operand_elements.clone();
+            if !tails.is_empty() {
+                alternative.add_element(Element::zero_or_more(Element::Group { alternatives: tails }));
+            }
+            alternative
+        };
+
+        let mut levels: Vec<usize> = level_names.keys().copied().collect();
+        levels.sort_unstable_by(|a, b| b.cmp(a));
+        let level_rules: Vec<Rule> = levels
+            .into_iter()
+            .map(|level| {
+                let mut level_rule = Rule::parser_rule(level_names[&level].clone());
+                level_rule.location = rule.location.clone();
+                level_rule.add_alternative(level_alternative(level));
+                level_rule
+            })
+            .collect();
+
+        rule.alternatives = vec![level_alternative(recursive.len())];
+        rules.push(rule);
+        rules.extend(level_rules);
+    }
+    grammar.rules = rules;
+
+    for recursion in LeftRecursionDetector::new().detect(grammar) {
+        if recursion.kind == LeftRecursionKind::Indirect {
+            let mut diagnostic = Diagnostic::warning(format!(
+                "indirect left recursion in rule '{}' must be removed by hand: {}",
+                recursion.rule_name,
+                recursion.cycle_description()
+            ))
+            .with_code("W002");
+            if let Some(location) = grammar.get_rule(&recursion.rule_name).and_then(|rule| rule.location.clone()) {
+                diagnostic = diagnostic.with_location(location);
+            }
+            diagnostics.push(diagnostic);
+        }
+    }
+
+    diagnostics
+}
+
+fn is_directly_left_recursive(alt: &Alternative, rule_name: &str) -> bool {
+    matches!(alt.elements.first(), Some(Element::RuleRef { name, .. }) if name == rule_name)
+}
+
+/// Whether `alt` ends in a reference to `rule_name` after something else,
+/// as binary and prefix operator alternatives do.
+fn ends_in_recursion(alt: &Alternative, rule_name: &str) -> bool {
+    alt.elements.len() > 1
+        && matches!(alt.elements.last(), Some(Element::RuleRef { name, .. }) if name == rule_name)
+}
+
+/// A binary operator of a left-recursive expression rule.
+#[derive(Debug, Clone)]
+pub struct BinaryOperator {
+    /// Literals and token references, any of which is this operator.
+    pub tokens: Vec<Element>,
+    /// Binding strength; higher binds tighter.
+    pub precedence: u8,
+    pub right_assoc: bool,
+    /// The `#Label` of the operator's alternative. Its nodes are nodes of
+    /// the rule, which visitors reach as `visit_<label>`.
+    pub label: Option<String>,
+    /// Labels on the left operand, the operator and the right operand, as
+    /// in `left=expr op='*' right=expr`, naming the fields they go in.
+    pub element_labels: [Option<String>; 3],
+}
+
+/// Operators and operands of an expression rule, for precedence climbing.
+#[derive(Debug, Clone)]
+pub struct PrecedenceTable {
+    /// Operators in rule order, so from tightest to loosest binding.
+    pub operators: Vec<BinaryOperator>,
+    /// The alternatives that are not left-recursive. The operand of a
+    /// prefix alternative carries its precedence as a `<prec=N>` option.
+    pub primaries: Vec<Alternative>,
+}
+
+/// Read `rule` as binary operators over primary expressions, as in
+/// `expr: expr '*' expr | expr ('+'|'-') expr | NUMBER;`.
+///
+/// As in ANTLR, earlier alternatives bind tighter, and `<assoc=right>`
+/// makes an operator right-associative. Every left-recursive alternative
+/// must be `rule op rule`, where `op` is a literal, a token or a group of
+/// those; the alternative and its three elements may be labeled. Otherwise,
+/// without a primary alternative, or when the rule takes arguments or
+/// returns values, the rule is not an operator rule and `None` is returned.
+///
+/// A prefix alternative such as `'-' expr` takes a precedence level of its
+/// own too. Its operand is given a `<prec=N>` option so that it only takes
+/// the operators binding at least as tightly, making `-1*2` `(-1)*2` when
+/// the prefix alternative comes first.
+pub fn precedence_table(rule: &Rule) -> Option<PrecedenceTable> {
+    if !rule.arguments.is_empty() || !rule.returns.is_empty() {
+        return None;
+    }
+    let is_prefix = |alt: &Alternative| {
+        alt.elements.len() > 1
+            && matches!(alt.elements.last(), Some(Element::RuleRef { name, .. }) if *name == rule.name)
+    };
+    let levels = rule
+        .alternatives
+        .iter()
+        .filter(|alt| is_directly_left_recursive(alt, &rule.name) || is_prefix(alt))
+        .count();
+    let levels = u8::try_from(levels).ok()?;
+
+    let mut operators = Vec::new();
+    let mut primaries = Vec::new();
+    let mut precedence = levels;
+    for alt in &rule.alternatives {
+        if is_directly_left_recursive(alt, &rule.name) {
+            let [left, op, right @ Element::RuleRef { name, .. }] = alt.elements.as_slice() else {
+                return None;
+            };
+            if *name != rule.name {
+                return None;
+            }
+            let (op, operator_label) = without_label(op);
+            operators.push(BinaryOperator {
+                tokens: operator_tokens(&op)?,
+                precedence,
+                right_assoc: alt.is_right_assoc(),
+                label: alt.label.clone(),
+                element_labels: [without_label(left).1, operator_label, without_label(right).1],
+            });
+            precedence -= 1;
+        } else if is_prefix(alt) {
+            let mut alt = alt.clone();
+            if let Some(Element::RuleRef { options, .. }) = alt.elements.last_mut() {
+                options.retain(|(key, _)| key != "prec");
+                options.push(("prec".to_string(), precedence.to_string()));
+            }
+            primaries.push(alt);
+            precedence -= 1;
+        } else {
+            primaries.push(alt.clone());
+        }
+    }
+    if operators.is_empty() || primaries.iter().all(is_prefix) {
+        return None;
+    }
+
+    Some(PrecedenceTable { operators, primaries })
+}
+
+/// `element` without its `name=` or `name+=` label, and the label.
+fn without_label(element: &Element) -> (Element, Option<String>) {
+    let mut element = element.clone();
+    let label = match &mut element {
+        Element::RuleRef { label, .. }
+        | Element::Terminal { label, .. }
+        | Element::StringLiteral { label, .. } => label.take(),
+        _ => None,
+    };
+    (element, label)
+}
+
+/// The single tokens `op` matches, if it is a literal, a token or a group
+/// of those.
+fn operator_tokens(op: &Element) -> Option<Vec<Element>> {
+    match op {
+        Element::StringLiteral { label: None, .. } | Element::Terminal { label: None, .. } => {
+            Some(vec![op.clone()])
+        }
+        Element::RuleRef { name, label: None, .. }
+            if name.starts_with(|c: char| c.is_ascii_uppercase()) && name != "EOF" =>
+        {
+            Some(vec![op.clone()])
+        }
+        Element::Group { alternatives } => alternatives
+            .iter()
+            .map(|alt| match alt.elements.as_slice() {
+                [element] => operator_tokens(element),
+                _ => None,
+            })
+            .collect::<Option<Vec<Vec<Element>>>>()
+            .map(|tokens| tokens.concat()),
+        _ => None,
+    }
+}
+
 /// Type of left recursion detected.
 #[derive(Debug, Clone, PartialEq)]
 pub enum LeftRecursionKind {
@@ -230,6 +665,227 @@ mod tests {
             .any(|r| r.kind == LeftRecursionKind::Indirect));
     }
 
+    #[test]
+    fn test_eliminate_direct_left_recursion() {
+        use crate::parser::GrammarParser;
+        use crate::traits::GrammarParser as GrammarParserTrait;
+
+        let mut grammar = GrammarParser::new()
+            .parse_string(
+                "grammar T; expr: expr '+' term | expr '-' term | term; term: NUMBER; NUMBER: [0-9]+;",
+                "test.g4",
+            )
+            .unwrap();
+
+        let diagnostics = eliminate_direct(&mut grammar);
+
+        assert!(diagnostics.is_empty());
+        let expr = grammar.get_rule("expr").unwrap();
+        assert_eq!(expr.alternatives.len(), 1);
+        let elements = &expr.alternatives[0].elements;
+        assert!(matches!(&elements[0], Element::RuleRef { name, .. } if name == "term"));
+        match &elements[1] {
+            Element::ZeroOrMore { element, .. } => match element.as_ref() {
+                Element::Group { alternatives } => {
+                    assert_eq!(alternatives.len(), 2);
+                    assert!(matches!(&alternatives[0].elements[0], Element::StringLiteral { value, .. } if value == "+"));
+                    assert!(matches!(&alternatives[1].elements[0], Element::StringLiteral { value, .. } if value == "-"));
+                }
+                other => panic!("expected group, got {:?}", other),
+            },
+            other => panic!("expected loop, got {:?}", other),
+        }
+        assert!(LeftRecursionDetector::new().detect(&grammar).is_empty());
+    }
+
+    #[test]
+    fn test_eliminate_direct_honors_right_assoc() {
+        use crate::parser::GrammarParser;
+        use crate::traits::GrammarParser as GrammarParserTrait;
+
+        let mut grammar = GrammarParser::new()
+            .parse_string(
+                "grammar T; expr: <assoc=right> expr '^' expr | expr '-' expr | NUMBER; NUMBER: [0-9]+;",
+                "test.g4",
+            )
+            .unwrap();
+
+        eliminate_direct(&mut grammar);
+
+        let expr = grammar.get_rule("expr").unwrap();
+        let tails = match &expr.alternatives[0].elements[1] {
+            Element::ZeroOrMore { element, .. } => match element.as_ref() {
+                Element::Group { alternatives } => alternatives,
+                other => panic!("expected group, got {:?}", other),
+            },
+            other => panic!("expected loop, got {:?}", other),
+        };
+        // Both right operands hold only '^', which keeps recursing into itself
+        assert!(matches!(&tails[0].elements[1], Element::RuleRef { name, .. } if name == "expr_1"));
+        assert!(matches!(&tails[1].elements[1], Element::RuleRef { name, .. } if name == "expr_1"));
+        let expr_1 = grammar.get_rule("expr_1").unwrap();
+        let Element::ZeroOrMore { element, .. } = &expr_1.alternatives[0].elements[1] else {
+            panic!("expected loop");
+        };
+        let Element::Group { alternatives: tails } = element.as_ref() else {
+            panic!("expected group");
+        };
+        assert_eq!(tails.len(), 1);
+        assert!(matches!(&tails[0].elements[1], Element::RuleRef { name, .. } if name == "expr_1"));
+    }
+
+    #[test]
+    fn test_eliminate_direct_honors_element_assoc() {
+        use crate::parser::GrammarParser;
+        use crate::traits::GrammarParser as GrammarParserTrait;
+
+        let mut grammar = GrammarParser::new()
+            .parse_string(
+                "grammar T; expr: expr '^'<assoc=right> expr | expr '-' expr | NUMBER; NUMBER: [0-9]+;",
+                "test.g4",
+            )
+            .unwrap();
+
+        eliminate_direct(&mut grammar);
+
+        let expr = grammar.get_rule("expr").unwrap();
+        let Element::ZeroOrMore { element, .. } = &expr.alternatives[0].elements[1] else {
+            panic!("expected loop");
+        };
+        let Element::Group { alternatives: tails } = element.as_ref() else {
+            panic!("expected group");
+        };
+        assert!(matches!(&tails[0].elements[1], Element::RuleRef { name, .. } if name == "expr_1"));
+        assert!(matches!(&tails[1].elements[1], Element::RuleRef { name, .. } if name == "expr_1"));
+    }
+
+    #[test]
+    fn test_eliminate_direct_keeps_precedence_levels() {
+        use crate::format::format_grammar;
+        use crate::parser::GrammarParser;
+        use crate::traits::GrammarParser as GrammarParserTrait;
+
+        let mut grammar = GrammarParser::new()
+            .parse_string(
+                "grammar T; expr: <assoc=right> expr '^' expr | expr '*' expr | expr '-' expr | expr '!' | NUMBER; \
+                 expr_1: NUMBER; NUMBER: [0-9]+;",
+                "test.g4",
+            )
+            .unwrap();
+
+        assert!(eliminate_direct(&mut grammar).is_empty());
+
+        // So 2^3-4 is (2^3)-4, 2-3*4 is 2-(3*4), 2*3^4 is 2*(3^4), and the
+        // postfix '!' applies to everything before it
+        let formatted = format_grammar(&grammar);
+        assert!(
+            formatted.contains(
+                "expr: NUMBER ('^' expr_1_ | '*' expr_1_ | '-' expr_2 | '!')*;\n\n\
+                 expr_2: NUMBER ('^' expr_1_ | '*' expr_1_)*;\n\n\
+                 expr_1_: NUMBER ('^' expr_1_)*;\n"
+            ),
+            "{}",
+            formatted
+        );
+        assert!(LeftRecursionDetector::new().detect(&grammar).is_empty());
+    }
+
+    #[test]
+    fn test_eliminate_direct_levels_prefix_operands() {
+        use crate::format::format_grammar;
+        use crate::parser::GrammarParser;
+        use crate::traits::GrammarParser as GrammarParserTrait;
+
+        let mut grammar = GrammarParser::new()
+            .parse_string(
+                "grammar T; expr: '-' expr | expr '*' expr | 'not' expr | expr '+' expr | NUMBER; NUMBER: [0-9]+;",
+                "test.g4",
+            )
+            .unwrap();
+
+        assert!(eliminate_direct(&mut grammar).is_empty());
+
+        // So -1+2 is (-1)+2, and not 1*2 is not (1*2) but not 1+2 is (not 1)+2
+        let formatted = format_grammar(&grammar);
+        assert!(
+            formatted.contains(
+                "expr: ('-' expr_0 | 'not' expr_1 | NUMBER) ('*' ('-' expr_0 | 'not' expr_1 | NUMBER) | '+' expr_1)*;\n\n\
+                 expr_1: ('-' expr_0 | 'not' expr_1 | NUMBER) ('*' ('-' expr_0 | 'not' expr_1 | NUMBER))*;\n\n\
+                 expr_0: ('-' expr_0 | 'not' expr_1 | NUMBER);\n"
+            ),
+            "{}",
+            formatted
+        );
+        assert!(LeftRecursionDetector::new().detect(&grammar).is_empty());
+    }
+
+    #[test]
+    fn test_eliminate_direct_reports_indirect() {
+        use crate::parser::GrammarParser;
+        use crate::traits::GrammarParser as GrammarParserTrait;
+
+        let mut grammar = GrammarParser::new()
+            .parse_string("grammar T; a: b 'x' | 'y'; b: a 'z'; c: c;", "test.g4")
+            .unwrap();
+
+        let diagnostics = eliminate_direct(&mut grammar);
+
+        let messages: Vec<&str> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+        assert!(messages.iter().any(|m| m.contains("rule 'c' is left-recursive in every alternative")));
+        assert!(messages.iter().any(|m| m.contains("indirect left recursion in rule 'a'")));
+        // Rules that are not directly left-recursive are left alone
+        assert_eq!(grammar.get_rule("a").unwrap().alternatives.len(), 2);
+    }
+
+    #[test]
+    fn test_eliminate_direct_rejects_alternative_labels() {
+        use crate::parser::GrammarParser;
+        use crate::traits::GrammarParser as GrammarParserTrait;
+
+        let source = "grammar T; expr: expr '+' expr #Add | NUMBER #Num; NUMBER: [0-9]+;";
+        let mut grammar = GrammarParser::new().parse_string(source, "test.g4").unwrap();
+
+        let diagnostics = eliminate_unclimbable(&mut grammar, false);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].code.as_deref(), Some("E011"));
+        assert_eq!(diagnostics[0].location.as_ref().map(|l| l.line), Some(1));
+
+        // Climbing keeps the alternatives and their labels
+        let mut grammar = GrammarParser::new().parse_string(source, "test.g4").unwrap();
+        assert!(eliminate_unclimbable(&mut grammar, true).is_empty());
+    }
+
+    #[test]
+    fn test_find_indirect_cycles() {
+        use crate::parser::GrammarParser;
+        use crate::traits::GrammarParser as GrammarParserTrait;
+
+        let grammar = GrammarParser::new()
+            .parse_string(
+                "grammar T; \
+                 a: b 'x' | 'y'; b: c | a 'z'; c: a 'w' | 'v'; \
+                 expr: expr '+' expr | NUMBER; \
+                 list: SIGN? list ',' NUMBER | NUMBER; \
+                 p: (q | 'r') 's'; q: {true}? p; \
+                 ok: NUMBER ok | NUMBER; \
+                 SIGN: '-'; NUMBER: [0-9]+;",
+                "test.g4",
+            )
+            .unwrap();
+
+        let cycles = find_indirect_cycles(&grammar);
+
+        assert_eq!(
+            cycles,
+            vec![
+                vec!["a", "b", "a"],
+                vec!["a", "b", "c", "a"],
+                vec!["list", "list"],
+                vec!["p", "q", "p"],
+            ]
+        );
+    }
+
     #[test]
     fn test_no_left_recursion() {
         let mut grammar = Grammar::new("Test".to_string(), GrammarType::Parser);
@@ -245,4 +901,80 @@ mod tests {
 
         assert_eq!(results.len(), 0);
     }
+
+    #[test]
+    fn test_precedence_table() {
+        use crate::parser::GrammarParser;
+        use crate::traits::GrammarParser as GrammarParserTrait;
+
+        let grammar = GrammarParser::new()
+            .parse_string(
+                "grammar Calc; expr: expr ('*'|'/') expr | <assoc=right> expr POW expr | expr '+' expr | NUMBER | '(' expr ')'; POW: '^'; NUMBER: [0-9]+;",
+                "Calc.g4",
+            )
+            .unwrap();
+        let table = precedence_table(grammar.get_rule("expr").unwrap()).unwrap();
+
+        let summary: Vec<(usize, u8, bool)> = table
+            .operators
+            .iter()
+            .map(|op| (op.tokens.len(), op.precedence, op.right_assoc))
+            .collect();
+        assert_eq!(summary, vec![(2, 3, false), (1, 2, true), (1, 1, false)]);
+        assert_eq!(table.primaries.len(), 2);
+
+        // Labels are kept for the nodes the operators build
+        let grammar = GrammarParser::new()
+            .parse_string(
+                "grammar T; expr: left=expr op='*' right=expr #Mul | expr '+' expr #Add | NUMBER #Num; NUMBER: [0-9]+;",
+                "T.g4",
+            )
+            .unwrap();
+        let table = precedence_table(grammar.get_rule("expr").unwrap()).unwrap();
+        let mul = &table.operators[0];
+        assert_eq!(mul.label.as_deref(), Some("Mul"));
+        assert_eq!(
+            mul.element_labels,
+            [Some("left".to_string()), Some("op".to_string()), Some("right".to_string())]
+        );
+        assert_eq!(table.operators[1].label.as_deref(), Some("Add"));
+        assert_eq!(table.operators[1].element_labels, [None, None, None]);
+
+        // A prefix alternative takes a level of its own, which its operand
+        // is parsed at
+        let grammar = GrammarParser::new()
+            .parse_string(
+                "grammar T; expr: '-' expr | expr '*' expr | '!' expr | expr '+' expr | NUMBER; NUMBER: [0-9]+;",
+                "T.g4",
+            )
+            .unwrap();
+        let table = precedence_table(grammar.get_rule("expr").unwrap()).unwrap();
+        let precedences: Vec<u8> = table.operators.iter().map(|op| op.precedence).collect();
+        assert_eq!(precedences, vec![3, 1]);
+        let operand_prec: Vec<Option<&str>> = table
+            .primaries
+            .iter()
+            .map(|alt| match alt.elements.last() {
+                Some(Element::RuleRef { options, .. }) => options
+                    .iter()
+                    .find(|(key, _)| key == "prec")
+                    .map(|(_, value)| value.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(operand_prec, vec![Some("4"), Some("2"), None]);
+
+        // A unary alternative is not a binary operator
+        for rule in [
+            "expr: expr '!' | NUMBER;",
+            "expr: expr '+' expr;",
+            "expr: '-' expr | expr '+' expr;",
+            "expr[int x]: expr '+' expr | NUMBER;",
+        ] {
+            let grammar = GrammarParser::new()
+                .parse_string(&format!("grammar T; {} NUMBER: [0-9]+;", rule), "T.g4")
+                .unwrap();
+            assert!(precedence_table(grammar.get_rule("expr").unwrap()).is_none(), "{}", rule);
+        }
+    }
 }
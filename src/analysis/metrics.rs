@@ -0,0 +1,152 @@
+//! Size and shape metrics for whole grammars.
+
+use crate::ast::{Alternative, Element, Grammar, LexerCommand};
+use crate::codegen::ll1::Ll1Table;
+use serde::Serialize;
+use std::collections::BTreeSet;
+
+/// Metrics of a grammar, as reported by `minipg stats`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct GrammarStats {
+    /// Name of the grammar
+    pub grammar: String,
+    pub parser_rules: usize,
+    /// Token rules, not counting fragments
+    pub lexer_rules: usize,
+    pub fragment_rules: usize,
+    /// Alternatives of all rules, not counting those inside groups
+    pub alternatives: usize,
+    /// Most alternatives of any one rule
+    pub max_alternatives: usize,
+    /// The first rule with `max_alternatives` alternatives
+    pub max_alternatives_rule: Option<String>,
+    /// How deeply parenthesized groups nest inside any rule, so `a (b (c | d))*`
+    /// has a depth of 2 and a rule without groups one of 0
+    pub max_nesting_depth: usize,
+    /// Lexer modes other than `DEFAULT_MODE`
+    pub modes: usize,
+    /// Channels declared in a `channels` block or named by `-> channel(...)`
+    pub channels: usize,
+    /// Whether the parser rules can be parsed with one token of lookahead,
+    /// i.e. a table-driven parser can be generated for them
+    pub ll1: bool,
+}
+
+/// Count the rules, alternatives, modes and channels of `grammar` and
+/// measure how deeply it nests groups and whether it is LL(1).
+pub fn stats(grammar: &Grammar) -> GrammarStats {
+    let mut max_alternatives = 0;
+    let mut max_alternatives_rule = None;
+    for rule in &grammar.rules {
+        if rule.alternatives.len() > max_alternatives {
+            max_alternatives = rule.alternatives.len();
+            max_alternatives_rule = Some(rule.name.clone());
+        }
+    }
+
+    let mut channels: BTreeSet<&str> = grammar.channels.iter().map(String::as_str).collect();
+    for alt in grammar.rules.iter().flat_map(|rule| &rule.alternatives) {
+        for command in &alt.lexer_commands {
+            // `channel(2)` refers to a channel by number, not by name
+            if let LexerCommand::Channel(name) = command
+                && name.parse::<u32>().is_err()
+            {
+                channels.insert(name);
+            }
+        }
+    }
+
+    GrammarStats {
+        grammar: grammar.name.clone(),
+        parser_rules: grammar.parser_rules().count(),
+        lexer_rules: grammar.lexer_rules().filter(|rule| !rule.is_fragment).count(),
+        fragment_rules: grammar.lexer_rules().filter(|rule| rule.is_fragment).count(),
+        alternatives: grammar.rules.iter().map(|rule| rule.alternatives.len()).sum(),
+        max_alternatives,
+        max_alternatives_rule,
+        max_nesting_depth: grammar
+            .rules
+            .iter()
+            .map(|rule| alternatives_depth(&rule.alternatives))
+            .max()
+            .unwrap_or(0),
+        modes: grammar.lexer_modes.len(),
+        channels: channels.len(),
+        ll1: Ll1Table::build(grammar).is_ok(),
+    }
+}
+
+fn alternatives_depth(alternatives: &[Alternative]) -> usize {
+    alternatives
+        .iter()
+        .flat_map(|alt| &alt.elements)
+        .map(element_depth)
+        .max()
+        .unwrap_or(0)
+}
+
+/// How many groups deep `element` goes, looking through `?`, `*`, `+`
+/// and `~`.
+fn element_depth(element: &Element) -> usize {
+    match element {
+        Element::Group { alternatives } => 1 + alternatives_depth(alternatives),
+        Element::Optional { element, .. }
+        | Element::ZeroOrMore { element, .. }
+        | Element::OneOrMore { element, .. }
+        | Element::Not { element } => element_depth(element),
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::GrammarParser;
+    use crate::traits::GrammarParser as GrammarParserTrait;
+
+    fn parse(source: &str) -> Grammar {
+        GrammarParser::new().parse_string(source, "Test.g4").unwrap()
+    }
+
+    #[test]
+    fn test_counts_rules_and_nesting() {
+        let grammar = parse(
+            "grammar Calc;\n\
+             channels { COMMENTS }\n\
+             prog: stat+ EOF;\n\
+             stat: ID '=' expr ';' | expr ';' | ';';\n\
+             expr: term (('+' | '-') (term | '(' expr ')'))*;\n\
+             term: NUMBER | ID;\n\
+             ID: LETTER+;\n\
+             NUMBER: [0-9]+;\n\
+             fragment LETTER: [a-z];\n\
+             COMMENT: '#' ~[\\n]* -> channel(COMMENTS);\n\
+             WS: [ \\n]+ -> channel(HIDDEN);\n\
+             STR: '\"' -> pushMode(IN_STR);\n\
+             mode IN_STR;\n\
+             END: '\"' -> popMode;\n",
+        );
+
+        let stats = stats(&grammar);
+        assert_eq!(stats.grammar, "Calc");
+        assert_eq!((stats.parser_rules, stats.lexer_rules, stats.fragment_rules), (4, 6, 1));
+        assert_eq!(stats.alternatives, 14);
+        assert_eq!(stats.max_alternatives, 3);
+        assert_eq!(stats.max_alternatives_rule.as_deref(), Some("stat"));
+        assert_eq!(stats.max_nesting_depth, 2);
+        assert_eq!(stats.modes, 1);
+        assert_eq!(stats.channels, 2);
+        // Both `stat` alternatives can start with ID
+        assert!(!stats.ll1);
+    }
+
+    #[test]
+    fn test_ll1_grammar() {
+        let grammar = parse("grammar List;\nlist: '[' (item (',' item)*)? ']';\nitem: NUMBER | list;\nNUMBER: [0-9]+;\n");
+
+        let stats = stats(&grammar);
+        assert!(stats.ll1);
+        assert_eq!(stats.max_nesting_depth, 2);
+        assert_eq!(stats.channels, 0);
+    }
+}
@@ -1,22 +1,67 @@
 //! Semantic analysis for grammars.
 
 pub mod ambiguity;
+pub mod complexity;
 pub mod composition;
+pub mod desugar;
+pub mod factoring;
 pub mod first_follow;
 pub mod hooks;
 pub mod left_recursion;
+pub mod metrics;
 pub mod reachability;
 pub mod semantic;
 pub mod validator;
 
 pub use composition::GrammarComposer;
+pub use desugar::EbnfDesugarer;
+pub use first_follow::FirstFollowSets;
 pub use hooks::{AnalysisContext, AnalysisHook, HookRegistry, HookResult};
 pub use hooks::{ComplexityHook, NamingConventionHook};
+pub use metrics::{stats, GrammarStats};
 pub use semantic::SemanticAnalyzer;
 pub use validator::GrammarValidator;
 
 use crate::ast::Grammar;
-use crate::Diagnostic;
+use crate::{Diagnostic, DiagnosticSeverity};
+use std::collections::HashSet;
+
+/// Run every analysis pass over `grammar` and return all they report.
+///
+/// This covers undefined rules and tokens, duplicate rules, empty
+/// alternatives, left recursion, recursive lexer rules, unreachable rules,
+/// ambiguous alternatives and oversized rules. Unlike
+/// [`SemanticAnalyzer::analyze`], later passes run even when earlier
+/// ones report errors. Repeated diagnostics are reported once. The rest are
+/// sorted by location, with those that have none last, then errors before
+/// warnings.
+pub fn validate(grammar: &Grammar) -> Vec<Diagnostic> {
+    let mut diagnostics = SemanticAnalyzer::all_diagnostics(grammar);
+
+    let mut seen = HashSet::new();
+    diagnostics.retain(|d| seen.insert(d.to_string()));
+
+    let severity_rank = |severity: DiagnosticSeverity| match severity {
+        DiagnosticSeverity::Error => 0,
+        DiagnosticSeverity::Warning => 1,
+        DiagnosticSeverity::Info => 2,
+    };
+    diagnostics.sort_by(|a, b| {
+        let position = |d: &Diagnostic| {
+            d.location
+                .as_ref()
+                .map(|loc| (loc.file.clone(), loc.line, loc.column))
+        };
+        // None sorts before Some, so compare "has no location" first
+        (position(a).is_none(), position(a), severity_rank(a.severity), &a.message).cmp(&(
+            position(b).is_none(),
+            position(b),
+            severity_rank(b.severity),
+            &b.message,
+        ))
+    });
+    diagnostics
+}
 
 /// Analysis result containing validated grammar and diagnostics.
 #[derive(Debug)]
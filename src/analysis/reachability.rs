@@ -1,7 +1,9 @@
 //! Reachability analysis for grammar rules.
 
-use crate::ast::{AstVisitor, Element, Grammar};
-use std::collections::{HashMap, HashSet};
+use super::first_follow::EOF;
+use super::left_recursion::walk_cycles;
+use crate::ast::{AstVisitor, Element, Grammar, Rule};
+use std::collections::{HashMap, HashSet, VecDeque};
 
 /// Analyzes which rules are reachable from the start rule.
 pub struct ReachabilityAnalyzer {
@@ -75,23 +77,154 @@ impl Default for ReachabilityAnalyzer {
     }
 }
 
-/// Visitor to collect rule dependencies.
+/// Find the rules that can never take part in a parse, in declaration order.
+///
+/// The search is a breadth-first walk of rule references starting at the
+/// first parser rule. Lexer rules are also roots when a parser rule refers
+/// to them or when they carry a lexer command such as `-> skip`, since
+/// those affect the token stream even if nothing names them. Fragments are
+/// only reachable through the lexer rules that use them.
+pub fn find_unreachable(grammar: &Grammar) -> Vec<String> {
+    let dependencies: HashMap<&str, Vec<String>> = grammar
+        .rules
+        .iter()
+        .map(|rule| {
+            let mut collector = DependencyCollector::new();
+            collector.visit_rule(rule);
+            (rule.name.as_str(), collector.dependencies)
+        })
+        .collect();
+
+    let mut queue: VecDeque<&str> = VecDeque::new();
+    if let Some(start) = grammar.parser_rules().next() {
+        queue.push_back(&start.name);
+    }
+    for rule in grammar.lexer_rules().filter(|rule| !rule.is_fragment) {
+        let has_command = rule.alternatives.iter().any(|alt| !alt.lexer_commands.is_empty());
+        let referenced = grammar
+            .parser_rules()
+            .any(|parser_rule| dependencies[parser_rule.name.as_str()].contains(&rule.name));
+        if has_command || referenced {
+            queue.push_back(&rule.name);
+        }
+    }
+
+    let mut reachable = HashSet::new();
+    while let Some(name) = queue.pop_front() {
+        if !reachable.insert(name) {
+            continue;
+        }
+        if let Some(deps) = dependencies.get(name) {
+            queue.extend(deps.iter().map(String::as_str));
+        }
+    }
+
+    grammar
+        .rules
+        .iter()
+        .filter(|rule| !reachable.contains(rule.name.as_str()))
+        .map(|rule| rule.name.clone())
+        .collect()
+}
+
+/// Cycles of lexer rules that refer back to themselves, as in
+/// `fragment A: 'x' B?; fragment B: 'y' A;`.
+///
+/// Token rules are matched by a DFA with their fragments inlined, which a
+/// rule that contains itself cannot be. Each cycle is a path from its
+/// earliest rule in declaration order back to that rule, as in
+/// `["A", "B", "A"]`, and is reported once.
+pub fn find_recursive_token_rules(grammar: &Grammar) -> Vec<Vec<String>> {
+    let rules: Vec<&Rule> = grammar.lexer_rules().collect();
+    let index: HashMap<&str, usize> = rules
+        .iter()
+        .enumerate()
+        .map(|(i, rule)| (rule.name.as_str(), i))
+        .collect();
+    let edges: Vec<Vec<usize>> = rules
+        .iter()
+        .map(|rule| {
+            let mut collector = DependencyCollector::new();
+            collector.visit_rule(rule);
+            collector
+                .dependencies
+                .iter()
+                .filter_map(|name| index.get(name.as_str()).copied())
+                .collect()
+        })
+        .collect();
+
+    let mut cycles = Vec::new();
+    for start in 0..rules.len() {
+        walk_cycles(start, &edges, &mut vec![start], &mut cycles);
+    }
+    cycles
+        .into_iter()
+        .map(|cycle| cycle.into_iter().map(|i| rules[i].name.clone()).collect())
+        .collect()
+}
+
+/// Render the rule dependency graph as a Graphviz DOT digraph.
+///
+/// Each rule is a node and each rule reference an edge, in declaration
+/// order. Parser rules are boxes, lexer rules grey ellipses (dashed for
+/// fragments), and the start rule is drawn with a double border.
+pub fn rule_graph_dot(grammar: &Grammar) -> String {
+    let start = grammar
+        .parser_rules()
+        .next()
+        .or_else(|| grammar.rules.first())
+        .map(|rule| rule.name.as_str());
+
+    let mut dot = format!("digraph {:?} {{\n", grammar.name);
+    dot.push_str("    rankdir=LR;\n");
+
+    for rule in &grammar.rules {
+        let mut attrs = if rule.is_lexer_rule() {
+            let style = if rule.is_fragment { "\"filled,dashed\"" } else { "filled" };
+            format!("shape=ellipse, style={}, fillcolor=lightgrey", style)
+        } else {
+            "shape=box".to_string()
+        };
+        if Some(rule.name.as_str()) == start {
+            attrs.push_str(", peripheries=2, label=\"");
+            attrs.push_str(&rule.name);
+            attrs.push_str(" (start)\"");
+        }
+        dot.push_str(&format!("    {:?} [{}];\n", rule.name, attrs));
+    }
+
+    for rule in &grammar.rules {
+        let mut collector = DependencyCollector::new();
+        collector.visit_rule(rule);
+        for dependency in collector.dependencies.iter().filter(|d| *d != EOF) {
+            dot.push_str(&format!("    {:?} -> {:?};\n", rule.name, dependency));
+        }
+    }
+
+    dot.push_str("}\n");
+    dot
+}
+
+/// Visitor to collect rule dependencies in order of first reference.
 struct DependencyCollector {
-    dependencies: HashSet<String>,
+    dependencies: Vec<String>,
 }
 
 impl DependencyCollector {
     fn new() -> Self {
         Self {
-            dependencies: HashSet::new(),
+            dependencies: Vec::new(),
         }
     }
 }
 
 impl AstVisitor for DependencyCollector {
     fn visit_element(&mut self, element: &Element) {
-        if let Element::RuleRef { name, .. } = element {
-            self.dependencies.insert(name.clone());
+        if let Element::RuleRef { name, .. } = element
+            && !self.dependencies.contains(name)
+        {
+            self.dependencies.push(name.clone());
         }
         self.walk_element(element);
     }
@@ -148,4 +281,71 @@ mod tests {
         assert_eq!(unreachable.len(), 1);
         assert_eq!(unreachable[0], "unreachable");
     }
+
+    #[test]
+    fn test_find_unreachable() {
+        use crate::parser::GrammarParser;
+        use crate::traits::GrammarParser as GrammarParserTrait;
+
+        let grammar = GrammarParser::new()
+            .parse_string(
+                "grammar T; ID_FIRST: [a-z]+; start: (item | '(' nested ')')*; item: ID_FIRST; nested: NUMBER?; \
+                 orphan: helper; helper: ID_FIRST; \
+                 NUMBER: DIGIT+; fragment DIGIT: [0-9]; fragment UNUSED: 'u'; \
+                 WS: [ \\t]+ -> skip; STRAY: '!';",
+                "test.g4",
+            )
+            .unwrap();
+
+        // The start rule is the first parser rule, even after a lexer rule.
+        // `helper` is only used by the unreachable `orphan`.
+        assert_eq!(find_unreachable(&grammar), vec!["orphan", "helper", "UNUSED", "STRAY"]);
+    }
+
+    #[test]
+    fn test_find_recursive_token_rules() {
+        use crate::parser::GrammarParser;
+        use crate::traits::GrammarParser as GrammarParserTrait;
+
+        let grammar = GrammarParser::new()
+            .parse_string(
+                "grammar T; s: NUMBER NESTED EOF; NUMBER: HEXPAIR+; fragment HEXPAIR: HEX HEX; \
+                 fragment HEX: [0-9a-f]; NESTED: '(' NESTED* ')' | A; fragment A: 'x' B?; fragment B: 'y' A;",
+                "test.g4",
+            )
+            .unwrap();
+
+        // Nested fragments are fine as long as none leads back to itself
+        assert_eq!(
+            find_recursive_token_rules(&grammar),
+            vec![vec!["NESTED", "NESTED"], vec!["A", "B", "A"]]
+        );
+    }
+
+    #[test]
+    fn test_rule_graph_dot() {
+        use crate::parser::GrammarParser;
+        use crate::traits::GrammarParser as GrammarParserTrait;
+
+        let grammar = GrammarParser::new()
+            .parse_string(
+                "grammar Calc; calc: expr EOF; expr: expr '+' NUMBER | NUMBER; NUMBER: DIGIT+; fragment DIGIT: [0-9];",
+                "test.g4",
+            )
+            .unwrap();
+
+        let dot = rule_graph_dot(&grammar);
+
+        assert!(dot.starts_with("digraph \"Calc\" {\n"));
+        assert!(dot.contains("\"calc\" [shape=box, peripheries=2, label=\"calc (start)\"];"));
+        assert!(dot.contains("\"expr\" [shape=box];"));
+        assert!(dot.contains("\"NUMBER\" [shape=ellipse, style=filled, fillcolor=lightgrey];"));
+        assert!(dot.contains("\"DIGIT\" [shape=ellipse, style=\"filled,dashed\", fillcolor=lightgrey];"));
+        assert!(dot.contains("\"calc\" -> \"expr\";"));
+        assert!(dot.contains("\"NUMBER\" -> \"DIGIT\";"));
+        assert!(!dot.contains("\"EOF\""));
+        // Repeated references produce a single edge
+        assert_eq!(dot.matches("\"expr\" -> \"NUMBER\";").count(), 1);
+        assert!(dot.ends_with("}\n"));
+    }
 }
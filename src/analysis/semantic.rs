@@ -1,75 +1,162 @@
 //! Semantic analysis implementation.
 
 use super::{
-    ambiguity::AmbiguityDetector, left_recursion::LeftRecursionDetector,
-    reachability::ReachabilityAnalyzer, AnalysisResult,
+    ambiguity,
+    complexity::{check_rule_sizes, DEFAULT_MAX_RULE_SIZE},
+    first_follow::{FirstFollowComputer, FirstFollowSets, EOF},
+    left_recursion::{find_indirect_cycles, precedence_table, LeftRecursionDetector, LeftRecursionKind},
+    reachability::{find_recursive_token_rules, find_unreachable},
+    validator::GrammarValidator,
+    AnalysisResult,
 };
-use crate::ast::{AstVisitor, Grammar};
-use crate::{Diagnostic, Result, SemanticAnalyzer as SemanticAnalyzerTrait};
-use std::collections::{HashMap, HashSet};
+use crate::ast::{AstVisitor, Element, Grammar};
+use crate::{Diagnostic, DiagnosticSeverity, Location, Result, SemanticAnalyzer as SemanticAnalyzerTrait};
+use std::collections::HashSet;
 
 /// Semantic analyzer for grammars.
 pub struct SemanticAnalyzer {
     diagnostics: Vec<Diagnostic>,
+    precedence_climbing: bool,
 }
 
 impl SemanticAnalyzer {
     pub fn new() -> Self {
         Self {
             diagnostics: Vec::new(),
+            precedence_climbing: false,
         }
     }
 
-    pub fn analyze(&mut self, grammar: &mut Grammar) -> AnalysisResult {
+    /// Whether code is generated with precedence climbing, so left-recursive
+    /// operator rules are parsed as they are and not reported.
+    pub fn with_precedence_climbing(mut self, enabled: bool) -> Self {
+        self.precedence_climbing = enabled;
+        self
+    }
+
+    /// Run every check over `grammar` and return it with all they report.
+    ///
+    /// The passes run in order:
+    ///
+    /// 1. Undefined references, duplicate rules and the other checks of
+    ///    [`GrammarValidator`]. If any of them reports an error, the
+    ///    remaining passes are skipped: they assume each name refers to
+    ///    exactly one rule, and would only add noise.
+    /// 2. Empty alternatives, lexer commands on parser rules, which are
+    ///    errors, left recursion, where cycles through several rules and
+    ///    rules with no other alternative are errors, and recursive lexer
+    ///    rules, which are errors.
+    /// 3. Unreachable rules, ambiguous alternatives and oversized rules,
+    ///    which are only ever warnings.
+    ///
+    /// Channels named by lexer commands are then declared on the grammar,
+    /// whatever the passes found. Check [`AnalysisResult::has_errors`]
+    /// before generating code from the result.
+    pub fn analyze(grammar: Grammar) -> AnalysisResult {
+        Self::new().run(grammar)
+    }
+
+    /// Like [`SemanticAnalyzer::analyze`], with this analyzer's settings.
+    pub fn run(mut self, grammar: Grammar) -> AnalysisResult {
+        self.run_passes(&grammar, true);
+
+        let mut result = AnalysisResult::new(grammar);
+        self.extract_channels(&mut result.grammar);
+        result.diagnostics = self.diagnostics;
+        result
+    }
+
+    /// The old in-place form of [`SemanticAnalyzer::analyze`]: declares the
+    /// channels on `grammar` itself, returns a copy of it in the result and
+    /// keeps the diagnostics for [`SemanticAnalyzerTrait::diagnostics`].
+    #[deprecated(
+        since = "0.2.0",
+        note = "use `SemanticAnalyzer::analyze(grammar)`, or `run` to keep this analyzer's settings"
+    )]
+    pub fn analyze_in_place(&mut self, grammar: &mut Grammar) -> AnalysisResult {
+        let analyzer = Self::new().with_precedence_climbing(self.precedence_climbing);
+        let result = analyzer.run(grammar.clone());
+        grammar.channels = result.grammar.channels.clone();
+        self.diagnostics = result.diagnostics.clone();
+        result
+    }
+
+    /// Everything the passes of [`SemanticAnalyzer::analyze`] report,
+    /// running all of them even after an error.
+    pub(crate) fn all_diagnostics(grammar: &Grammar) -> Vec<Diagnostic> {
+        let mut analyzer = Self::new();
+        analyzer.run_passes(grammar, false);
+        analyzer.diagnostics
+    }
+
+    /// The passes of [`SemanticAnalyzer::analyze`], in order.
+    fn run_passes(&mut self, grammar: &Grammar, stop_at_errors: bool) {
         self.check_undefined_rules(grammar);
         self.check_duplicate_rules(grammar);
+        if stop_at_errors && self.has_errors() {
+            return;
+        }
+
         self.check_empty_alternatives(grammar);
+        self.check_parser_rule_commands(grammar);
         self.check_left_recursion(grammar);
+        self.check_recursive_token_rules(grammar);
         self.check_unreachable_rules(grammar);
         self.check_ambiguous_alternatives(grammar);
-        self.extract_channels(grammar);
+        self.check_rule_sizes(grammar);
+    }
 
-        AnalysisResult {
-            grammar: grammar.clone(),
-            diagnostics: std::mem::take(&mut self.diagnostics),
-        }
+    fn has_errors(&self) -> bool {
+        self.diagnostics
+            .iter()
+            .any(|d| d.severity == DiagnosticSeverity::Error)
+    }
+
+    /// Compute the FIRST and FOLLOW sets of the grammar's parser rules.
+    pub fn compute_first_follow(grammar: &Grammar) -> FirstFollowSets {
+        let mut computer = FirstFollowComputer::new();
+        computer.compute(grammar);
+        computer.into_sets()
     }
 
     fn check_undefined_rules(&mut self, grammar: &Grammar) {
-        let defined_rules: HashSet<String> = grammar.rules.iter().map(|r| r.name.clone()).collect();
-        let mut referenced_rules: HashSet<String> = HashSet::new();
+        let mut defined_rules: HashSet<String> = grammar.rules.iter().map(|r| r.name.clone()).collect();
+        defined_rules.extend(grammar.declared_tokens.iter().cloned());
+        if let Some(vocab) = &grammar.token_vocab {
+            defined_rules.extend(vocab.tokens().iter().map(|(name, _)| name.clone()));
+        }
 
         // Collect all rule references
         let mut visitor = RuleRefCollector::new();
         visitor.visit_grammar(grammar);
-        referenced_rules.extend(visitor.references);
-
-        // Check for undefined rules
-        for rule_ref in &referenced_rules {
-            if !defined_rules.contains(rule_ref) {
-                self.diagnostics.push(
-                    Diagnostic::error(format!("undefined rule: {}", rule_ref)).with_code("E001"),
-                );
+
+        // Report each undefined name once, at its first reference. Uppercase
+        // names refer to tokens, and EOF is always defined.
+        let mut reported = HashSet::new();
+        for (rule_ref, span) in visitor.references {
+            if rule_ref == EOF
+                || defined_rules.contains(&rule_ref)
+                || !reported.insert(rule_ref.clone())
+            {
+                continue;
             }
+            let kind = if rule_ref.starts_with(|c: char| c.is_uppercase()) {
+                "token"
+            } else {
+                "rule"
+            };
+            let mut diagnostic =
+                Diagnostic::error(format!("undefined {}: {}", kind, rule_ref)).with_code("E001");
+            if let Some(location) = span {
+                diagnostic = diagnostic.with_location(location);
+            }
+            self.diagnostics.push(diagnostic);
         }
     }
 
     fn check_duplicate_rules(&mut self, grammar: &Grammar) {
-        let mut seen: HashMap<String, usize> = HashMap::new();
-
-        for rule in &grammar.rules {
-            if let Some(count) = seen.get_mut(&rule.name) {
-                *count += 1;
-                if *count == 2 {
-                    self.diagnostics.push(
-                        Diagnostic::error(format!("duplicate rule definition: {}", rule.name))
-                            .with_code("E002"),
-                    );
-                }
-            } else {
-                seen.insert(rule.name.clone(), 1);
-            }
-        }
+        self.diagnostics
+            .extend(GrammarValidator::new().diagnostics(grammar));
     }
 
     fn check_empty_alternatives(&mut self, grammar: &Grammar) {
@@ -89,56 +176,134 @@ impl SemanticAnalyzer {
         }
     }
 
+    /// Report `->` commands on parser rule alternatives.
+    ///
+    /// Skipping, channels, modes and token types only mean something to the
+    /// lexer, so a command on a parser rule is most likely a token rule
+    /// written in lowercase, or an ANTLR 3 rewrite.
+    fn check_parser_rule_commands(&mut self, grammar: &Grammar) {
+        for rule in grammar.parser_rules() {
+            for (i, alt) in rule.alternatives.iter().enumerate() {
+                if alt.lexer_commands.is_empty() {
+                    continue;
+                }
+                let commands: Vec<String> = alt.lexer_commands.iter().map(|c| c.to_string()).collect();
+                let mut diagnostic = Diagnostic::error(format!(
+                    "lexer command '-> {}' in parser rule '{}' (alternative {}); commands only apply to lexer rules",
+                    commands.join(", "),
+                    rule.name,
+                    i + 1
+                ))
+                .with_code("E008");
+                if let Some(location) = &rule.location {
+                    diagnostic = diagnostic.with_location(location.clone());
+                }
+                self.diagnostics.push(diagnostic);
+            }
+        }
+    }
+
     fn check_left_recursion(&mut self, grammar: &Grammar) {
-        // Use advanced left recursion detector
+        // Operator rules can be parsed by precedence climbing; code generation
+        // rewrites any other direct left recursion into a loop, which only a
+        // rule without a non-recursive alternative defeats
         let mut detector = LeftRecursionDetector::new();
-        let recursions = detector.detect(grammar);
-
-        for recursion in recursions {
-            let message = match recursion.kind {
-                crate::analysis::left_recursion::LeftRecursionKind::Direct => {
-                    format!("direct left recursion in rule '{}'", recursion.rule_name)
-                }
-                crate::analysis::left_recursion::LeftRecursionKind::Indirect => {
-                    format!(
-                        "indirect left recursion in rule '{}': {}",
-                        recursion.rule_name,
-                        recursion.cycle_description()
-                    )
+        for recursion in detector.detect(grammar) {
+            if recursion.kind != LeftRecursionKind::Direct {
+                continue;
+            }
+            let Some(rule) = grammar.get_rule(&recursion.rule_name) else { continue };
+            if rule
+                .alternatives
+                .iter()
+                .all(|alt| matches!(alt.elements.first(), Some(Element::RuleRef { name, .. }) if *name == rule.name))
+            {
+                let mut diagnostic = Diagnostic::error(format!(
+                    "rule '{}' is left-recursive in every alternative and can never match",
+                    rule.name
+                ))
+                .with_code("E010");
+                if let Some(location) = &rule.location {
+                    diagnostic = diagnostic.with_location(location.clone());
                 }
+                self.diagnostics.push(diagnostic);
+                continue;
+            }
+            let message = match (precedence_table(rule).is_some(), self.precedence_climbing) {
+                (true, true) => continue,
+                (true, false) => format!(
+                    "direct left recursion in rule '{}' is rewritten into a loop; generate with --precedence-climbing (Rust target) to keep its operator nodes",
+                    rule.name
+                ),
+                (false, _) => format!(
+                    "direct left recursion in rule '{}' is rewritten into a loop, as it cannot be parsed by precedence climbing; every left-recursive alternative would have to be '{} op {}'",
+                    rule.name, rule.name, rule.name
+                ),
             };
+            let mut diagnostic = Diagnostic::warning(message).with_code("W002");
+            if let Some(location) = &rule.location {
+                diagnostic = diagnostic.with_location(location.clone());
+            }
+            self.diagnostics.push(diagnostic);
+        }
+
+        // Cycles through several rules, or through a nullable prefix, are not
+        for cycle in find_indirect_cycles(grammar) {
+            let mut diagnostic = Diagnostic::error(format!(
+                "mutually left-recursive rules cannot be rewritten: {}",
+                cycle.join(" -> ")
+            ))
+            .with_code("E005");
+            if let Some(location) = grammar.get_rule(&cycle[0]).and_then(|rule| rule.location.clone()) {
+                diagnostic = diagnostic.with_location(location);
+            }
+            self.diagnostics.push(diagnostic);
+        }
+    }
 
-            self.diagnostics
-                .push(Diagnostic::warning(message).with_code("W002"));
+    /// Report lexer rules that contain themselves, directly or through
+    /// fragments, since the lexer inlines fragments into one DFA.
+    fn check_recursive_token_rules(&mut self, grammar: &Grammar) {
+        for cycle in find_recursive_token_rules(grammar) {
+            let mut diagnostic = Diagnostic::error(format!(
+                "recursive lexer rules cannot be inlined: {}",
+                cycle.join(" -> ")
+            ))
+            .with_code("E009");
+            if let Some(location) = grammar.get_rule(&cycle[0]).and_then(|rule| rule.location.clone()) {
+                diagnostic = diagnostic.with_location(location);
+            }
+            self.diagnostics.push(diagnostic);
         }
     }
 
+    /// Warn about parser rules that cannot be reached from the start rule.
+    ///
+    /// Unused lexer rules are not reported: they still define token types.
     fn check_unreachable_rules(&mut self, grammar: &Grammar) {
-        let mut analyzer = ReachabilityAnalyzer::new();
-        analyzer.analyze(grammar);
-        let unreachable = analyzer.unreachable_rules(grammar);
-
-        for rule_name in unreachable {
-            self.diagnostics.push(
-                Diagnostic::warning(format!("unreachable rule: {}", rule_name)).with_code("W003"),
-            );
+        for rule_name in find_unreachable(grammar) {
+            let Some(rule) = grammar.get_rule(&rule_name) else { continue };
+            if !rule.is_parser_rule() {
+                continue;
+            }
+
+            let mut diagnostic =
+                Diagnostic::warning(format!("unreachable rule: {}", rule_name)).with_code("W003");
+            if let Some(location) = &rule.location {
+                diagnostic = diagnostic.with_location(location.clone());
+            }
+            self.diagnostics.push(diagnostic);
         }
     }
 
     fn check_ambiguous_alternatives(&mut self, grammar: &Grammar) {
-        let mut detector = AmbiguityDetector::new();
-        let ambiguities = detector.detect(grammar);
-
-        for ambiguity in ambiguities {
-            self.diagnostics.push(
-                Diagnostic::warning(format!(
-                    "ambiguous alternatives in rule '{}': {}",
-                    ambiguity.rule_name,
-                    ambiguity.description()
-                ))
-                .with_code("W004"),
-            );
-        }
+        let sets = Self::compute_first_follow(grammar);
+        self.diagnostics.extend(ambiguity::detect(grammar, &sets));
+    }
+
+    fn check_rule_sizes(&mut self, grammar: &Grammar) {
+        self.diagnostics
+            .extend(check_rule_sizes(grammar, DEFAULT_MAX_RULE_SIZE));
     }
 
     fn extract_channels(&self, grammar: &mut Grammar) {
@@ -147,9 +312,11 @@ impl SemanticAnalyzer {
             .rules
             .iter()
             .flat_map(|rule| rule.alternatives.iter())
-            .filter_map(|alt| {
-                if let Some(crate::ast::LexerCommand::Channel(channel_name)) = &alt.lexer_command {
-                    if !channel_name.is_empty() {
+            .flat_map(|alt| alt.lexer_commands.iter())
+            .filter_map(|command| {
+                if let crate::ast::LexerCommand::Channel(channel_name) = command {
+                    // `channel(2)` refers to a channel by number, not by name
+                    if !channel_name.is_empty() && channel_name.parse::<u32>().is_err() {
                         Some(channel_name.clone())
                     } else {
                         None
@@ -177,23 +344,8 @@ impl SemanticAnalyzerTrait for SemanticAnalyzer {
     type Output = AnalysisResult;
 
     fn analyze(&self, input: &Self::Input) -> Result<Self::Output> {
-        let mut analyzer = Self::new();
-
-        analyzer.check_undefined_rules(input);
-        analyzer.check_duplicate_rules(input);
-        analyzer.check_empty_alternatives(input);
-        analyzer.check_left_recursion(input);
-        analyzer.check_unreachable_rules(input);
-        analyzer.check_ambiguous_alternatives(input);
-
-        let mut result = AnalysisResult::new(input.clone());
-
-        // Extract channels from lexer commands
-        analyzer.extract_channels(&mut result.grammar);
-
-        result.diagnostics = analyzer.diagnostics;
-
-        Ok(result)
+        let analyzer = Self::new().with_precedence_climbing(self.precedence_climbing);
+        Ok(analyzer.run(input.clone()))
     }
 
     fn diagnostics(&self) -> &[Diagnostic] {
@@ -203,21 +355,22 @@ impl SemanticAnalyzerTrait for SemanticAnalyzer {
 
 /// Visitor to collect rule references.
 struct RuleRefCollector {
-    references: HashSet<String>,
+    /// Every rule reference and where it is written, in grammar order.
+    references: Vec<(String, Option<Location>)>,
 }
 
 impl RuleRefCollector {
     fn new() -> Self {
         Self {
-            references: HashSet::new(),
+            references: Vec::new(),
         }
     }
 }
 
 impl AstVisitor for RuleRefCollector {
     fn visit_element(&mut self, element: &crate::ast::Element) {
-        if let crate::ast::Element::RuleRef { name, .. } = element {
-            self.references.insert(name.clone());
+        if let crate::ast::Element::RuleRef { name, span, .. } = element {
+            self.references.push((name.clone(), span.clone()));
         }
         self.walk_element(element);
     }
@@ -1,7 +1,10 @@
 //! Grammar validation.
 
+use super::first_follow::nullable_lexer_rules;
 use crate::ast::Grammar;
-use crate::{Error, GrammarValidator as GrammarValidatorTrait, Result};
+use crate::types::GrammarType;
+use crate::{Diagnostic, DiagnosticSeverity, Error, GrammarValidator as GrammarValidatorTrait, Result};
+use std::collections::HashMap;
 
 /// Grammar validator.
 pub struct GrammarValidator;
@@ -10,6 +13,92 @@ impl GrammarValidator {
     pub fn new() -> Self {
         Self
     }
+
+    /// Collect diagnostics for structural problems in the grammar.
+    pub fn diagnostics(&self, grammar: &Grammar) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        self.check_duplicate_rules(grammar, &mut diagnostics);
+        self.check_rule_kinds(grammar, &mut diagnostics);
+        self.check_empty_tokens(grammar, &mut diagnostics);
+        diagnostics
+    }
+
+    /// Report every redefinition of a rule name.
+    ///
+    /// Names are compared case-sensitively, so `expr` and `Expr` are distinct
+    /// rules, but a fragment and a token of the same name collide.
+    fn check_duplicate_rules(&self, grammar: &Grammar, diagnostics: &mut Vec<Diagnostic>) {
+        let mut first_definitions = HashMap::new();
+
+        for rule in &grammar.rules {
+            match first_definitions.get(rule.name.as_str()) {
+                None => {
+                    first_definitions.insert(rule.name.as_str(), rule);
+                }
+                Some(first) => {
+                    let mut message = format!("duplicate rule definition: {}", rule.name);
+                    if let Some(location) = &first.location {
+                        message.push_str(&format!(" (first defined at {})", location));
+                    }
+
+                    let mut diagnostic = Diagnostic::error(message).with_code("E002");
+                    if let Some(location) = &rule.location {
+                        diagnostic = diagnostic.with_location(location.clone());
+                    }
+                    diagnostics.push(diagnostic);
+                }
+            }
+        }
+    }
+
+    /// Report rules of the wrong kind for the grammar type.
+    ///
+    /// Lexer grammars may only define token rules and parser grammars only
+    /// parser rules; combined grammars allow both. Literals used in a parser
+    /// grammar are not rules, so they never trip this check.
+    fn check_rule_kinds(&self, grammar: &Grammar, diagnostics: &mut Vec<Diagnostic>) {
+        for rule in &grammar.rules {
+            let message = match grammar.grammar_type {
+                GrammarType::Lexer if rule.is_parser_rule() => format!(
+                    "parser rule {} is not allowed in lexer grammar {}",
+                    rule.name, grammar.name
+                ),
+                GrammarType::Parser if rule.is_lexer_rule() => format!(
+                    "lexer rule {} is not allowed in parser grammar {}",
+                    rule.name, grammar.name
+                ),
+                _ => continue,
+            };
+
+            let mut diagnostic = Diagnostic::error(message).with_code("E003");
+            if let Some(location) = &rule.location {
+                diagnostic = diagnostic.with_location(location.clone());
+            }
+            diagnostics.push(diagnostic);
+        }
+    }
+
+    /// Report token rules that can match empty input.
+    ///
+    /// Such a rule would let the lexer produce tokens without consuming
+    /// anything, forever. Fragments may be empty, as they only ever match as
+    /// part of another rule.
+    fn check_empty_tokens(&self, grammar: &Grammar, diagnostics: &mut Vec<Diagnostic>) {
+        let nullable = nullable_lexer_rules(grammar);
+
+        for rule in grammar.lexer_rules() {
+            if rule.is_fragment || !nullable.contains(&rule.name) {
+                continue;
+            }
+
+            let message = format!("lexer rule {} can match empty input", rule.name);
+            let mut diagnostic = Diagnostic::error(message).with_code("E004");
+            if let Some(location) = &rule.location {
+                diagnostic = diagnostic.with_location(location.clone());
+            }
+            diagnostics.push(diagnostic);
+        }
+    }
 }
 
 impl Default for GrammarValidator {
@@ -33,6 +122,14 @@ impl GrammarValidatorTrait for GrammarValidator {
             ));
         }
 
+        if let Some(error) = self
+            .diagnostics(input)
+            .into_iter()
+            .find(|d| d.severity == DiagnosticSeverity::Error)
+        {
+            return Err(Error::invalid_grammar(error.to_string()));
+        }
+
         Ok(())
     }
 }
@@ -0,0 +1,205 @@
+//! Fluent construction of grammars in code.
+
+use super::element::{Alternative, Element, LexerCommand};
+use super::grammar::Grammar;
+use super::rule::{Rule, RuleType};
+use crate::types::GrammarType;
+use crate::{Error, Result};
+
+/// Builds a [`Grammar`] without parsing a `.g4` file.
+///
+/// Rules are added in order with [`rule`](Self::rule); names starting with
+/// an uppercase letter are lexer rules, as in a grammar file. Each
+/// [`alt`](Self::alt) starts a new alternative of the current rule, and
+/// element methods append to the current alternative, starting the first
+/// one if needed.
+///
+/// ```
+/// use minipg::ast::{Element, GrammarBuilder};
+///
+/// let grammar = GrammarBuilder::new("Calc")
+///     .rule("expr").alt().rule_ref("term").terminal("PLUS").rule_ref("expr").alt().rule_ref("term")
+///     .rule("term").alt().terminal("NUMBER")
+///     .rule("PLUS").alt().literal("+")
+///     .rule("NUMBER").alt().element(Element::one_or_more(Element::CharClass {
+///         negated: false,
+///         ranges: vec![('0', '9')],
+///     }))
+///     .build()
+///     .unwrap();
+/// assert_eq!(grammar.parser_rules().count(), 2);
+/// ```
+#[derive(Debug, Clone)]
+pub struct GrammarBuilder {
+    grammar: Grammar,
+    /// First misuse of the builder, reported by `build`.
+    error: Option<String>,
+}
+
+impl GrammarBuilder {
+    /// Start a combined grammar named `name`.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self::with_type(name, GrammarType::Combined)
+    }
+
+    /// Start a grammar of the given type.
+    pub fn with_type(name: impl Into<String>, grammar_type: GrammarType) -> Self {
+        Self {
+            grammar: Grammar::new(name.into(), grammar_type),
+            error: None,
+        }
+    }
+
+    /// Set a grammar-level option such as `tokenVocab`.
+    pub fn option(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.grammar.add_option(key.into(), value.into());
+        self
+    }
+
+    /// Start a new rule; later alternatives and elements belong to it.
+    pub fn rule(mut self, name: impl Into<String>) -> Self {
+        let name = name.into();
+        let rule_type = if name.chars().next().is_some_and(|c| c.is_uppercase()) {
+            RuleType::Lexer
+        } else {
+            RuleType::Parser
+        };
+        self.grammar.add_rule(Rule::new(name, rule_type));
+        self
+    }
+
+    /// Start a new fragment lexer rule.
+    pub fn fragment(mut self, name: impl Into<String>) -> Self {
+        let mut rule = Rule::lexer_rule(name.into());
+        rule.set_fragment(true);
+        self.grammar.add_rule(rule);
+        self
+    }
+
+    /// Start a new alternative of the current rule.
+    pub fn alt(mut self) -> Self {
+        match self.grammar.rules.last_mut() {
+            Some(rule) => rule.add_alternative(Alternative::new()),
+            None => self.fail("alternative added before any rule"),
+        }
+        self
+    }
+
+    /// Append a reference to a parser or lexer rule.
+    pub fn rule_ref(self, name: impl Into<String>) -> Self {
+        self.element(Element::rule_ref(name.into()))
+    }
+
+    /// Append a token reference.
+    pub fn terminal(self, name: impl Into<String>) -> Self {
+        self.element(Element::terminal(name.into()))
+    }
+
+    /// Append a string literal such as `'+'`.
+    pub fn literal(self, text: impl Into<String>) -> Self {
+        self.element(Element::string_literal(text.into()))
+    }
+
+    /// Append any element to the current alternative.
+    pub fn element(mut self, element: Element) -> Self {
+        match self.current_alternative() {
+            Some(alt) => alt.add_element(element),
+            None => self.fail("element added before any rule"),
+        }
+        self
+    }
+
+    /// Set a lexer command such as `-> skip` on the current alternative.
+    pub fn command(mut self, command: LexerCommand) -> Self {
+        match self.current_alternative() {
+            Some(alt) => alt.add_lexer_command(command),
+            None => self.fail("lexer command added before any rule"),
+        }
+        self
+    }
+
+    /// Finish the grammar.
+    ///
+    /// Fails if an alternative or element was added before the first
+    /// rule, or if a rule has no alternatives.
+    pub fn build(self) -> Result<Grammar> {
+        if let Some(message) = self.error {
+            return Err(Error::invalid_grammar(message));
+        }
+        if let Some(rule) = self.grammar.rules.iter().find(|rule| rule.alternatives.is_empty()) {
+            return Err(Error::invalid_grammar(format!(
+                "rule {} has no alternatives",
+                rule.name
+            )));
+        }
+        Ok(self.grammar)
+    }
+
+    fn current_alternative(&mut self) -> Option<&mut Alternative> {
+        let rule = self.grammar.rules.last_mut()?;
+        if rule.alternatives.is_empty() {
+            rule.add_alternative(Alternative::new());
+        }
+        rule.alternatives.last_mut()
+    }
+
+    fn fail(&mut self, message: &str) {
+        if self.error.is_none() {
+            self.error = Some(message.to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::SemanticAnalyzer;
+    use crate::codegen::RustCodeGenerator;
+    use crate::types::CodeGenConfig;
+    use crate::CodeGenerator;
+
+    fn calc() -> GrammarBuilder {
+        GrammarBuilder::new("Calc")
+            .rule("expr").alt().rule_ref("term").terminal("PLUS").rule_ref("expr").alt().rule_ref("term")
+            .rule("term").alt().terminal("NUMBER").alt().literal("(").rule_ref("expr").literal(")")
+            .rule("PLUS").literal("+")
+            .rule("NUMBER").element(Element::one_or_more(Element::rule_ref("DIGIT".to_string())))
+            .fragment("DIGIT").element(Element::CharClass {
+                negated: false,
+                ranges: vec![('0', '9')],
+            })
+            .rule("WS").literal(" ").command(LexerCommand::Skip)
+    }
+
+    #[test]
+    fn test_builder_produces_usable_grammar() {
+        let grammar = calc().build().unwrap();
+
+        assert_eq!(grammar.name, "Calc");
+        let names: Vec<&str> = grammar.parser_rules().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, ["expr", "term"]);
+        assert_eq!(grammar.get_rule("expr").unwrap().alternatives.len(), 2);
+        assert_eq!(grammar.get_rule("term").unwrap().alternatives[1].elements.len(), 3);
+        assert!(grammar.get_rule("DIGIT").unwrap().is_fragment);
+        assert_eq!(
+            grammar.get_rule("WS").unwrap().alternatives[0].lexer_command(),
+            Some(&LexerCommand::Skip)
+        );
+
+        let result = SemanticAnalyzer::analyze(grammar.clone());
+        assert!(!result.has_errors(), "{:?}", result.diagnostics);
+        let code = RustCodeGenerator::new()
+            .generate(&grammar, &CodeGenConfig::default())
+            .unwrap();
+        assert!(code.contains("pub fn parse_expr"));
+    }
+
+    #[test]
+    fn test_builder_rejects_rule_without_alternatives() {
+        let err = calc().rule("stmt").build().unwrap_err();
+        assert_eq!(err.to_string(), "Invalid grammar: rule stmt has no alternatives");
+
+        let err = GrammarBuilder::new("Empty").alt().rule("a").literal("a").build().unwrap_err();
+        assert_eq!(err.to_string(), "Invalid grammar: alternative added before any rule");
+    }
+}
@@ -0,0 +1,419 @@
+//! Structural comparison of grammars.
+
+use super::element::Element;
+use super::grammar::Grammar;
+use super::rule::Rule;
+use super::visitor::AstVisitorMut;
+use crate::types::GrammarType;
+use std::collections::BTreeSet;
+use std::fmt;
+
+/// A difference between two grammars, as reported by [`Grammar::diff`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GrammarChange {
+    /// `lexer grammar`, `parser grammar` or a combined `grammar`.
+    GrammarTypeChanged { old: GrammarType, new: GrammarType },
+    RuleAdded(String),
+    RuleRemoved(String),
+    /// The rule's alternatives, elements, commands or other settings differ.
+    RuleModified(String),
+    /// Token rules present in both grammars are defined in a different order,
+    /// which decides which of them wins a tie.
+    LexerRulesReordered,
+    OptionAdded { key: String, value: String },
+    OptionRemoved { key: String, value: String },
+    OptionChanged { key: String, old: String, new: String },
+    ChannelAdded(String),
+    ChannelRemoved(String),
+    /// A name in `tokens { ... }`.
+    TokenDeclared(String),
+    TokenUndeclared(String),
+    ModeAdded(String),
+    ModeRemoved(String),
+    /// The token rules in the mode, or their order, differ.
+    ModeModified(String),
+    NamedActionAdded(String),
+    NamedActionRemoved(String),
+    NamedActionModified(String),
+    ImportAdded(String),
+    ImportRemoved(String),
+    /// A selective import names different rules, or became a whole import.
+    ImportModified(String),
+    /// Imports present in both grammars are listed in a different order,
+    /// which decides whose rules win.
+    ImportsReordered,
+}
+
+impl fmt::Display for GrammarChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GrammarChange::GrammarTypeChanged { old, new } => write!(
+                f,
+                "grammar type changed from {} to {}",
+                grammar_keyword(*old),
+                grammar_keyword(*new)
+            ),
+            GrammarChange::RuleAdded(name) => write!(f, "rule {} added", name),
+            GrammarChange::RuleRemoved(name) => write!(f, "rule {} removed", name),
+            GrammarChange::RuleModified(name) => write!(f, "rule {} modified", name),
+            GrammarChange::LexerRulesReordered => write!(f, "token rules reordered"),
+            GrammarChange::OptionAdded { key, value } => write!(f, "option {} = {} added", key, value),
+            GrammarChange::OptionRemoved { key, value } => {
+                write!(f, "option {} = {} removed", key, value)
+            }
+            GrammarChange::OptionChanged { key, old, new } => {
+                write!(f, "option {} changed from {} to {}", key, old, new)
+            }
+            GrammarChange::ChannelAdded(name) => write!(f, "channel {} added", name),
+            GrammarChange::ChannelRemoved(name) => write!(f, "channel {} removed", name),
+            GrammarChange::TokenDeclared(name) => write!(f, "token {} declared", name),
+            GrammarChange::TokenUndeclared(name) => write!(f, "token {} no longer declared", name),
+            GrammarChange::ModeAdded(name) => write!(f, "mode {} added", name),
+            GrammarChange::ModeRemoved(name) => write!(f, "mode {} removed", name),
+            GrammarChange::ModeModified(name) => write!(f, "mode {} modified", name),
+            GrammarChange::NamedActionAdded(name) => write!(f, "action @{} added", name),
+            GrammarChange::NamedActionRemoved(name) => write!(f, "action @{} removed", name),
+            GrammarChange::NamedActionModified(name) => write!(f, "action @{} modified", name),
+            GrammarChange::ImportAdded(name) => write!(f, "import {} added", name),
+            GrammarChange::ImportRemoved(name) => write!(f, "import {} removed", name),
+            GrammarChange::ImportModified(name) => write!(f, "import {} modified", name),
+            GrammarChange::ImportsReordered => write!(f, "imports reordered"),
+        }
+    }
+}
+
+fn grammar_keyword(grammar_type: GrammarType) -> &'static str {
+    match grammar_type {
+        GrammarType::Lexer => "lexer",
+        GrammarType::Parser => "parser",
+        GrammarType::Combined => "combined",
+    }
+}
+
+/// Changes that turn `old` into `new`.
+///
+/// A change of grammar type comes first. Removed and modified rules follow
+/// in `old`'s order and added rules in `new`'s, then option, channel,
+/// declared token, mode and named action changes sorted by name, and
+/// import changes in `old`'s and then `new`'s order. Where a rule or
+/// element is written is ignored, as are doc comments and the order of
+/// options, channels, declared tokens, modes and named actions, but the
+/// order of alternatives is not, since it decides which one is tried
+/// first, nor is the order of imports.
+pub(crate) fn diff(old: &Grammar, new: &Grammar) -> Vec<GrammarChange> {
+    let mut changes = Vec::new();
+
+    if old.grammar_type != new.grammar_type {
+        changes.push(GrammarChange::GrammarTypeChanged {
+            old: old.grammar_type,
+            new: new.grammar_type,
+        });
+    }
+
+    for rule in &old.rules {
+        match new.get_rule(&rule.name) {
+            None => changes.push(GrammarChange::RuleRemoved(rule.name.clone())),
+            Some(other) if comparable(rule) != comparable(other) => {
+                changes.push(GrammarChange::RuleModified(rule.name.clone()));
+            }
+            Some(_) => {}
+        }
+    }
+    for rule in &new.rules {
+        if old.get_rule(&rule.name).is_none() {
+            changes.push(GrammarChange::RuleAdded(rule.name.clone()));
+        }
+    }
+
+    let common_tokens = |grammar: &Grammar, other: &Grammar| -> Vec<String> {
+        grammar
+            .lexer_rules()
+            .filter(|rule| !rule.is_fragment && other.get_rule(&rule.name).is_some())
+            .map(|rule| rule.name.clone())
+            .collect()
+    };
+    if common_tokens(old, new) != common_tokens(new, old) {
+        changes.push(GrammarChange::LexerRulesReordered);
+    }
+
+    let keys: BTreeSet<&String> = old.options.keys().chain(new.options.keys()).collect();
+    for key in keys {
+        match (old.options.get(key), new.options.get(key)) {
+            (None, Some(value)) => changes.push(GrammarChange::OptionAdded {
+                key: key.clone(),
+                value: value.clone(),
+            }),
+            (Some(value), None) => changes.push(GrammarChange::OptionRemoved {
+                key: key.clone(),
+                value: value.clone(),
+            }),
+            (Some(old_value), Some(new_value)) if old_value != new_value => {
+                changes.push(GrammarChange::OptionChanged {
+                    key: key.clone(),
+                    old: old_value.clone(),
+                    new: new_value.clone(),
+                })
+            }
+            _ => {}
+        }
+    }
+
+    let old_channels: BTreeSet<&String> = old.channels.iter().collect();
+    let new_channels: BTreeSet<&String> = new.channels.iter().collect();
+    for channel in old_channels.difference(&new_channels) {
+        changes.push(GrammarChange::ChannelRemoved((*channel).clone()));
+    }
+    for channel in new_channels.difference(&old_channels) {
+        changes.push(GrammarChange::ChannelAdded((*channel).clone()));
+    }
+
+    let old_tokens: BTreeSet<&String> = old.declared_tokens.iter().collect();
+    let new_tokens: BTreeSet<&String> = new.declared_tokens.iter().collect();
+    for token in old_tokens.difference(&new_tokens) {
+        changes.push(GrammarChange::TokenUndeclared((*token).clone()));
+    }
+    for token in new_tokens.difference(&old_tokens) {
+        changes.push(GrammarChange::TokenDeclared((*token).clone()));
+    }
+
+    let modes: BTreeSet<&String> = old.lexer_modes.keys().chain(new.lexer_modes.keys()).collect();
+    for mode in modes {
+        match (old.lexer_modes.get(mode), new.lexer_modes.get(mode)) {
+            (None, Some(_)) => changes.push(GrammarChange::ModeAdded(mode.clone())),
+            (Some(_), None) => changes.push(GrammarChange::ModeRemoved(mode.clone())),
+            (Some(old_rules), Some(new_rules)) if old_rules != new_rules => {
+                changes.push(GrammarChange::ModeModified(mode.clone()))
+            }
+            _ => {}
+        }
+    }
+
+    let actions: BTreeSet<&String> =
+        old.named_actions.keys().chain(new.named_actions.keys()).collect();
+    for name in actions {
+        match (old.named_actions.get(name), new.named_actions.get(name)) {
+            (None, Some(_)) => changes.push(GrammarChange::NamedActionAdded(name.clone())),
+            (Some(_), None) => changes.push(GrammarChange::NamedActionRemoved(name.clone())),
+            (Some(old_code), Some(new_code)) if old_code.trim() != new_code.trim() => {
+                changes.push(GrammarChange::NamedActionModified(name.clone()))
+            }
+            _ => {}
+        }
+    }
+
+    for import in &old.imports {
+        if !new.imports.contains(import) {
+            changes.push(GrammarChange::ImportRemoved(import.clone()));
+        } else if old.imported_rules.get(import) != new.imported_rules.get(import) {
+            changes.push(GrammarChange::ImportModified(import.clone()));
+        }
+    }
+    for import in &new.imports {
+        if !old.imports.contains(import) {
+            changes.push(GrammarChange::ImportAdded(import.clone()));
+        }
+    }
+    let common_imports = |grammar: &Grammar, other: &Grammar| -> Vec<String> {
+        grammar
+            .imports
+            .iter()
+            .filter(|import| other.imports.contains(import))
+            .cloned()
+            .collect()
+    };
+    if common_imports(old, new) != common_imports(new, old) {
+        changes.push(GrammarChange::ImportsReordered);
+    }
+
+    changes
+}
+
+/// A copy of `rule` with its doc comment and the source locations of it
+/// and its elements cleared.
+fn comparable(rule: &Rule) -> Rule {
+    struct ClearLocations;
+
+    impl AstVisitorMut for ClearLocations {
+        fn visit_rule_mut(&mut self, rule: &mut Rule) {
+            rule.location = None;
+            rule.doc = None;
+            self.walk_rule_mut(rule);
+        }
+
+        fn visit_element_mut(&mut self, element: &mut Element) {
+            if let Element::RuleRef { span, .. }
+            | Element::Terminal { span, .. }
+            | Element::StringLiteral { span, .. } = element
+            {
+                *span = None;
+            }
+            self.walk_element_mut(element);
+        }
+    }
+
+    let mut rule = rule.clone();
+    ClearLocations.visit_rule_mut(&mut rule);
+    rule
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::GrammarParser;
+    use crate::traits::GrammarParser as GrammarParserTrait;
+
+    fn parse(source: &str) -> Grammar {
+        GrammarParser::new().parse_string(source, "Calc.g4").unwrap()
+    }
+
+    #[test]
+    fn test_layout_and_option_order_are_ignored() {
+        let old = parse(
+            "grammar Calc; options { a = 1; b = 2; } channels { A, B } \
+             expr: NUMBER '+' NUMBER | NUMBER; NUMBER: [0-9]+; WS: ' ' -> skip;",
+        );
+        let new = parse(
+            "grammar Calc;\noptions { b = 2; a = 1; }\nchannels { B, A }\n\nexpr\n  : NUMBER '+' NUMBER\n  | NUMBER\n  ;\n\
+             NUMBER: [0-9]+;\nWS: ' ' -> skip;\n",
+        );
+
+        assert!(old.structural_eq(&new));
+        assert!(old.diff(&new).is_empty());
+    }
+
+    #[test]
+    fn test_changes_are_enumerated() {
+        let old = parse(
+            "grammar Calc; options { a = 1; b = 2; } channels { OLD } \
+             expr: NUMBER '+' NUMBER | NUMBER; term: NUMBER; NUMBER: [0-9]+; ID: [a-z]+; WS: ' ' -> skip;",
+        );
+        let new = parse(
+            "grammar Calc; options { a = 3; c = 4; } channels { COMMENTS } \
+             expr: NUMBER | NUMBER '+' NUMBER; atom: NUMBER; ID: [a-z]+; NUMBER: [0-9]+; WS: ' ' -> channel(HIDDEN);",
+        );
+
+        let changes = old.diff(&new);
+        assert_eq!(
+            changes,
+            vec![
+                GrammarChange::RuleModified("expr".to_string()),
+                GrammarChange::RuleRemoved("term".to_string()),
+                GrammarChange::RuleModified("WS".to_string()),
+                GrammarChange::RuleAdded("atom".to_string()),
+                GrammarChange::LexerRulesReordered,
+                GrammarChange::OptionChanged {
+                    key: "a".to_string(),
+                    old: "1".to_string(),
+                    new: "3".to_string(),
+                },
+                GrammarChange::OptionRemoved { key: "b".to_string(), value: "2".to_string() },
+                GrammarChange::OptionAdded { key: "c".to_string(), value: "4".to_string() },
+                GrammarChange::ChannelRemoved("OLD".to_string()),
+                GrammarChange::ChannelAdded("COMMENTS".to_string()),
+            ]
+        );
+        assert!(!old.structural_eq(&new));
+        assert_eq!(changes[0].to_string(), "rule expr modified");
+    }
+
+    #[test]
+    fn test_grammar_type_change() {
+        let old = parse("grammar Calc; ID: [a-z]+;");
+        let new = parse("lexer grammar Calc; ID: [a-z]+;");
+
+        let changes = old.diff(&new);
+        assert_eq!(
+            changes,
+            vec![GrammarChange::GrammarTypeChanged {
+                old: GrammarType::Combined,
+                new: GrammarType::Lexer,
+            }]
+        );
+        assert!(!old.structural_eq(&new));
+        assert_eq!(changes[0].to_string(), "grammar type changed from combined to lexer");
+    }
+
+    #[test]
+    fn test_declared_token_changes() {
+        let old = parse("grammar Calc; tokens { INDENT, DEDENT } s: ID; ID: [a-z]+;");
+        let same = parse("grammar Calc; tokens { DEDENT, INDENT } s: ID; ID: [a-z]+;");
+        let new = parse("grammar Calc; tokens { INDENT, NEWLINE } s: ID; ID: [a-z]+;");
+
+        assert!(old.structural_eq(&same));
+        assert_eq!(
+            old.diff(&new),
+            vec![
+                GrammarChange::TokenUndeclared("DEDENT".to_string()),
+                GrammarChange::TokenDeclared("NEWLINE".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_mode_changes() {
+        let old = parse(
+            "lexer grammar Calc; OPEN: '<' -> pushMode(TAG); \
+             mode TAG; CLOSE: '>' -> popMode; NAME: [a-z]+; \
+             mode STR; TEXT: [a-z]+;",
+        );
+        let new = parse(
+            "lexer grammar Calc; OPEN: '<' -> pushMode(TAG); \
+             mode TAG; NAME: [a-z]+; CLOSE: '>' -> popMode; \
+             mode ATTR; VALUE: [a-z]+;",
+        );
+
+        let changes = old.diff(&new);
+        assert!(changes.contains(&GrammarChange::ModeAdded("ATTR".to_string())));
+        assert!(changes.contains(&GrammarChange::ModeRemoved("STR".to_string())));
+        assert!(changes.contains(&GrammarChange::ModeModified("TAG".to_string())));
+        let modified = changes
+            .iter()
+            .filter(|change| matches!(change, GrammarChange::ModeModified(_)))
+            .count();
+        assert_eq!(modified, 1);
+    }
+
+    #[test]
+    fn test_named_action_changes() {
+        let old = parse(
+            "grammar Calc; @header { use a; } @members { count: u32, } s: ID; ID: [a-z]+;",
+        );
+        let new = parse(
+            "grammar Calc; @members {count: u64,} @lexer::members { depth: u32, } s: ID; ID: [a-z]+;",
+        );
+
+        assert_eq!(
+            old.diff(&new),
+            vec![
+                GrammarChange::NamedActionRemoved("header".to_string()),
+                GrammarChange::NamedActionAdded("lexer::members".to_string()),
+                GrammarChange::NamedActionModified("members".to_string()),
+            ]
+        );
+        let reformatted = parse(
+            "grammar Calc; @members {\n    count: u32,\n} @header {use a;} s: ID; ID: [a-z]+;",
+        );
+        assert!(old.structural_eq(&reformatted));
+    }
+
+    #[test]
+    fn test_import_changes() {
+        let old = parse("grammar Calc; import Common, Tokens, Extra = {ID}; s: ID;");
+        let new = parse("grammar Calc; import Tokens, Common, Extra, Exprs; s: ID;");
+
+        let changes = old.diff(&new);
+        assert_eq!(
+            changes,
+            vec![
+                GrammarChange::ImportModified("Extra".to_string()),
+                GrammarChange::ImportAdded("Exprs".to_string()),
+                GrammarChange::ImportsReordered,
+            ]
+        );
+        assert_eq!(changes[2].to_string(), "imports reordered");
+        assert_eq!(
+            new.diff(&parse("grammar Calc; import Tokens, Common, Exprs; s: ID;")),
+            vec![GrammarChange::ImportRemoved("Extra".to_string())]
+        );
+    }
+}
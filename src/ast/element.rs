@@ -1,6 +1,8 @@
 //! Grammar element AST nodes.
 
+use crate::diagnostic::Location;
 use serde::{Deserialize, Serialize};
+use std::fmt;
 
 /// Lexer command (e.g., skip, channel, mode, type)
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -21,12 +23,42 @@ pub enum LexerCommand {
     More,
 }
 
+/// The command as written after `->`, e.g. `channel(HIDDEN)`.
+impl fmt::Display for LexerCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LexerCommand::Skip => write!(f, "skip"),
+            LexerCommand::More => write!(f, "more"),
+            LexerCommand::PopMode => write!(f, "popMode"),
+            LexerCommand::Channel(channel) => write!(f, "channel({})", channel),
+            LexerCommand::Mode(mode) => write!(f, "mode({})", mode),
+            LexerCommand::Type(token) => write!(f, "type({})", token),
+            LexerCommand::PushMode(mode) => write!(f, "pushMode({})", mode),
+        }
+    }
+}
+
+/// Where a semantic predicate stands in its alternative.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum PredicatePosition {
+    /// Before any element of the alternative, as in `{p}? a | b`: the
+    /// predicate decides whether the alternative is taken at all.
+    #[default]
+    Leading,
+    /// After some of the alternative's elements, as in `a {p}? b`: the
+    /// predicate checks what was matched, and failing it is an error.
+    Trailing,
+}
+
 /// An alternative in a rule (sequence of elements).
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Alternative {
     pub elements: Vec<Element>,
     pub label: Option<String>,
-    pub lexer_command: Option<LexerCommand>,
+    /// Lexer commands after `->`, in the order they are written.
+    pub lexer_commands: Vec<LexerCommand>,
+    /// Options from `<key=value>` blocks, such as `<assoc=right>`.
+    pub options: Vec<(String, String)>,
 }
 
 impl Alternative {
@@ -34,7 +66,8 @@ impl Alternative {
         Self {
             elements: Vec::new(),
             label: None,
-            lexer_command: None,
+            lexer_commands: Vec::new(),
+            options: Vec::new(),
         }
     }
 
@@ -44,7 +77,7 @@ impl Alternative {
     }
 
     pub fn with_lexer_command(mut self, command: LexerCommand) -> Self {
-        self.lexer_command = Some(command);
+        self.lexer_commands.push(command);
         self
     }
 
@@ -52,8 +85,36 @@ impl Alternative {
         self.elements.push(element);
     }
 
+    /// Replace the alternative's lexer commands with `command`.
     pub fn set_lexer_command(&mut self, command: LexerCommand) {
-        self.lexer_command = Some(command);
+        self.lexer_commands = vec![command];
+    }
+
+    pub fn add_lexer_command(&mut self, command: LexerCommand) {
+        self.lexer_commands.push(command);
+    }
+
+    /// The first lexer command, if any.
+    pub fn lexer_command(&self) -> Option<&LexerCommand> {
+        self.lexer_commands.first()
+    }
+
+    /// Look up an option set with `<key=value>`.
+    pub fn option(&self, key: &str) -> Option<&str> {
+        self.options
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Whether the alternative is marked `<assoc=right>`, either up front
+    /// or on one of its elements as in `expr '^'<assoc=right> expr`.
+    pub fn is_right_assoc(&self) -> bool {
+        self.option("assoc") == Some("right")
+            || self
+                .elements
+                .iter()
+                .any(|element| element.option("assoc") == Some("right"))
     }
 }
 
@@ -64,25 +125,33 @@ impl Default for Alternative {
 }
 
 /// Grammar element (terminal, non-terminal, etc.).
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Element {
     /// Reference to another rule
     RuleRef {
         name: String,
         label: Option<String>,
         is_list: bool, // true for ids+=ID, false for id=ID
+        /// Where the reference is written in the grammar, if known
+        span: Option<Location>,
+        /// Options from a `<key=value>` block after the reference
+        options: Vec<(String, String)>,
     },
     /// Terminal token
     Terminal {
         value: String,
         label: Option<String>,
         is_list: bool, // true for tokens+=TOKEN, false for token=TOKEN
+        span: Option<Location>,
+        options: Vec<(String, String)>,
     },
     /// String literal
     StringLiteral {
         value: String,
         label: Option<String>,
         is_list: bool, // true for strs+='string', false for str='string'
+        span: Option<Location>,
+        options: Vec<(String, String)>,
     },
     /// Character range (for lexer rules)
     CharRange {
@@ -94,6 +163,11 @@ pub enum Element {
         negated: bool,
         ranges: Vec<(char, char)>, // Vec of (start, end) - single char is (c, c)
     },
+    /// Unicode property class (e.g., \p{L}, \P{Digit})
+    UnicodeClass {
+        negated: bool,
+        category: String,
+    },
     /// Optional element (?)
     Optional {
         element: Box<Element>,
@@ -130,20 +204,30 @@ pub enum Element {
     Predicate {
         code: String,
         language: Option<String>,
+        /// Options such as `<fail='message'>` after the `?`
+        options: Vec<(String, String)>,
+        #[serde(default)]
+        position: PredicatePosition,
     },
 }
 
 impl Element {
     pub fn rule_ref(name: String) -> Self {
-        Element::RuleRef { name, label: None, is_list: false }
+        Element::RuleRef { name, label: None, is_list: false, span: None, options: Vec::new() }
     }
 
     pub fn terminal(value: String) -> Self {
-        Element::Terminal { value, label: None, is_list: false }
+        Element::Terminal { value, label: None, is_list: false, span: None, options: Vec::new() }
     }
 
     pub fn string_literal(value: String) -> Self {
-        Element::StringLiteral { value, label: None, is_list: false }
+        Element::StringLiteral {
+            value,
+            label: None,
+            is_list: false,
+            span: None,
+            options: Vec::new(),
+        }
     }
 
     pub fn optional(element: Element) -> Self {
@@ -189,44 +273,73 @@ impl Element {
     }
 
     pub fn with_label(self, label: String) -> Self {
+        self.with_label_kind(label, false)
+    }
+
+    pub fn with_list_label(self, label: String) -> Self {
+        self.with_label_kind(label, true)
+    }
+
+    fn with_label_kind(mut self, name: String, list: bool) -> Self {
+        if let Element::RuleRef { label, is_list, .. }
+        | Element::Terminal { label, is_list, .. }
+        | Element::StringLiteral { label, is_list, .. } = &mut self
+        {
+            *label = Some(name);
+            *is_list = list;
+        }
+        self
+    }
+
+    /// Record where a rule reference, token or literal is written.
+    ///
+    /// Other elements carry no location and are returned unchanged.
+    pub fn with_span(mut self, location: Location) -> Self {
+        if let Element::RuleRef { span, .. }
+        | Element::Terminal { span, .. }
+        | Element::StringLiteral { span, .. } = &mut self
+        {
+            *span = Some(location);
+        }
+        self
+    }
+
+    /// Where the element is written in the grammar, if known.
+    pub fn span(&self) -> Option<&Location> {
         match self {
-            Element::RuleRef { name, is_list, .. } => Element::RuleRef {
-                name,
-                label: Some(label),
-                is_list,
-            },
-            Element::Terminal { value, is_list, .. } => Element::Terminal {
-                value,
-                label: Some(label),
-                is_list,
-            },
-            Element::StringLiteral { value, is_list, .. } => Element::StringLiteral {
-                value,
-                label: Some(label),
-                is_list,
-            },
-            other => other,
+            Element::RuleRef { span, .. }
+            | Element::Terminal { span, .. }
+            | Element::StringLiteral { span, .. } => span.as_ref(),
+            _ => None,
         }
     }
-    
-    pub fn with_list_label(self, label: String) -> Self {
+
+    /// Attach the options of a `<key=value>` block, as in `ID<assoc=right>`.
+    ///
+    /// Only references, literals and predicates take options; other
+    /// elements are returned unchanged.
+    pub fn with_options(mut self, new_options: Vec<(String, String)>) -> Self {
+        if let Element::RuleRef { options, .. }
+        | Element::Terminal { options, .. }
+        | Element::StringLiteral { options, .. }
+        | Element::Predicate { options, .. } = &mut self
+        {
+            options.extend(new_options);
+        }
+        self
+    }
+
+    /// Look up an element option set with `<key=value>`.
+    pub fn option(&self, key: &str) -> Option<&str> {
         match self {
-            Element::RuleRef { name, .. } => Element::RuleRef {
-                name,
-                label: Some(label),
-                is_list: true,
-            },
-            Element::Terminal { value, .. } => Element::Terminal {
-                value,
-                label: Some(label),
-                is_list: true,
-            },
-            Element::StringLiteral { value, .. } => Element::StringLiteral {
-                value,
-                label: Some(label),
-                is_list: true,
-            },
-            other => other,
+            Element::RuleRef { options, .. }
+            | Element::Terminal { options, .. }
+            | Element::StringLiteral { options, .. }
+            | Element::Predicate { options, .. } => options
+                .iter()
+                .find(|(k, _)| k == key)
+                .map(|(_, v)| v.as_str()),
+            _ => None,
         }
     }
 
@@ -248,6 +361,8 @@ impl Element {
         Element::Predicate {
             code,
             language: None,
+            options: Vec::new(),
+            position: PredicatePosition::Leading,
         }
     }
 
@@ -255,10 +370,60 @@ impl Element {
         Element::Predicate {
             code,
             language: Some(language),
+            options: Vec::new(),
+            position: PredicatePosition::Leading,
+        }
+    }
+
+    pub fn unicode_class(category: String) -> Self {
+        Element::UnicodeClass {
+            negated: false,
+            category,
         }
     }
 }
 
+/// Name of the `char` method testing a Unicode property, and a function
+/// calling it.
+pub type PropertyTest = (&'static str, fn(char) -> bool);
+
+/// Unicode properties accepted in `\p{...}`, by the `char` method testing them.
+///
+/// `Digit` and `Nd` are approximated by `is_numeric`, which also accepts
+/// numeric chars such as `½` that are not decimal digits.
+const UNICODE_PROPERTIES: &[(PropertyTest, &[&str])] = &[
+    (("is_alphabetic", char::is_alphabetic), &["L", "Letter", "Alpha", "Alphabetic"]),
+    (("is_uppercase", char::is_uppercase), &["Lu", "Uppercase_Letter", "Upper", "Uppercase"]),
+    (("is_lowercase", char::is_lowercase), &["Ll", "Lowercase_Letter", "Lower", "Lowercase"]),
+    (("is_numeric", char::is_numeric), &["N", "Number", "Numeric", "Nd", "Digit"]),
+    (("is_alphanumeric", char::is_alphanumeric), &["Alnum"]),
+    (("is_whitespace", char::is_whitespace), &["White_Space", "WSpace", "Space", "Whitespace"]),
+    (("is_control", char::is_control), &["Cc", "Control"]),
+    (("is_ascii", |ch| ch.is_ascii()), &["ASCII"]),
+];
+
+/// The `char` method testing the Unicode property `name`, with a function
+/// doing the same test, or `None` for unsupported properties.
+pub fn unicode_property(name: &str) -> Option<PropertyTest> {
+    UNICODE_PROPERTIES
+        .iter()
+        .find(|(_, names)| names.contains(&name))
+        .map(|(test, _)| *test)
+}
+
+/// The test behind the `char` method `method` of a supported property.
+pub(crate) fn unicode_method(method: &str) -> Option<fn(char) -> bool> {
+    UNICODE_PROPERTIES
+        .iter()
+        .find(|((name, _), _)| *name == method)
+        .map(|((_, test), _)| *test)
+}
+
+/// Every property name accepted in `\p{...}`.
+pub fn unicode_property_names() -> impl Iterator<Item = &'static str> {
+    UNICODE_PROPERTIES.iter().flat_map(|(_, names)| names.iter().copied())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -283,6 +448,8 @@ mod tests {
             value: "ID".to_string(),
             label: None,
             is_list: false,
+            span: None,
+            options: Vec::new(),
         });
         assert_eq!(alt.elements.len(), 1);
     }
@@ -293,6 +460,8 @@ mod tests {
             name: "expr".to_string(),
             label: Some("e".to_string()),
             is_list: false,
+            span: None,
+            options: Vec::new(),
         };
         match elem {
             Element::RuleRef { name, label, .. } => {
@@ -309,6 +478,8 @@ mod tests {
             value: "ID".to_string(),
             label: None,
             is_list: false,
+            span: None,
+            options: Vec::new(),
         };
         match elem {
             Element::Terminal { value, label, .. } => {
@@ -335,7 +506,7 @@ mod tests {
     fn test_element_predicate() {
         let elem = Element::predicate("x > 0".to_string());
         match elem {
-            Element::Predicate { code, language } => {
+            Element::Predicate { code, language, .. } => {
                 assert_eq!(code, "x > 0");
                 assert_eq!(language, None);
             }
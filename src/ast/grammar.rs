@@ -1,7 +1,10 @@
 //! Grammar AST node definitions.
 
+use super::diff::{self, GrammarChange};
 use super::rule::Rule;
+use super::token_vocab::TokenVocab;
 use crate::types::GrammarType;
+use crate::{Error, Result};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
@@ -13,12 +16,23 @@ pub struct Grammar {
     pub options: HashMap<String, String>,
     pub rules: Vec<Rule>,
     pub imports: Vec<String>,
+    /// Rules named by a selective import such as `import Base = {ID, WS};`,
+    /// keyed by grammar. Grammars imported whole have no entry.
+    pub imported_rules: HashMap<String, Vec<String>>,
     /// Named actions like @header, @members, etc.
     pub named_actions: HashMap<String, String>,
     /// Lexer modes: mode_name -> rules in that mode
     pub lexer_modes: HashMap<String, Vec<String>>,
     /// Channel names used in the grammar
     pub channels: std::collections::HashSet<String>,
+    /// Tokens declared in a `tokens { ... }` block. No lexer rule matches
+    /// them; lexer actions emit them, e.g. `INDENT` and `DEDENT`.
+    #[serde(default)]
+    pub declared_tokens: Vec<String>,
+    /// Vocabulary loaded for the `tokenVocab` option, giving a split
+    /// parser grammar the token names and numbers of its lexer.
+    #[serde(default)]
+    pub token_vocab: Option<TokenVocab>,
 }
 
 impl Grammar {
@@ -29,16 +43,159 @@ impl Grammar {
             options: HashMap::new(),
             rules: Vec::new(),
             imports: Vec::new(),
+            imported_rules: HashMap::new(),
             named_actions: HashMap::new(),
             lexer_modes: HashMap::new(),
             channels: std::collections::HashSet::new(),
+            declared_tokens: Vec::new(),
+            token_vocab: None,
         }
     }
 
+    /// Combine a lexer grammar and a parser grammar into a combined grammar.
+    ///
+    /// The result is named after the parser grammar without a `Parser`
+    /// suffix, so `CalcLexer` and `CalcParser` make `Calc`. Parser rules come
+    /// first, keeping the parser's start rule, followed by the lexer rules,
+    /// so tokens the parser only references resolve to the lexer's rules.
+    /// Options of both are kept, the parser's winning, except `tokenVocab`,
+    /// which a combined grammar does not need. Imports, channels, declared
+    /// tokens and lexer modes are unioned. The lexer grammar's unscoped named actions such as
+    /// `@members` become `@lexer::members` so they stay with the lexer.
+    ///
+    /// A rule defined in both grammars is an error, as is passing a parser
+    /// grammar as `lexer` or a lexer grammar as `parser`.
+    pub fn merge(lexer: Grammar, parser: Grammar) -> Result<Grammar> {
+        if lexer.grammar_type == GrammarType::Parser {
+            return Err(Error::invalid_grammar(format!(
+                "cannot merge parser grammar {} as the lexer grammar",
+                lexer.name
+            )));
+        }
+        if parser.grammar_type == GrammarType::Lexer {
+            return Err(Error::invalid_grammar(format!(
+                "cannot merge lexer grammar {} as the parser grammar",
+                parser.name
+            )));
+        }
+        if let Some(rule) = parser.rules.iter().find(|rule| lexer.get_rule(&rule.name).is_some()) {
+            return Err(Error::invalid_grammar(format!(
+                "rule {} is defined in both {} and {}",
+                rule.name, lexer.name, parser.name
+            )));
+        }
+
+        let name = parser
+            .name
+            .strip_suffix("Parser")
+            .filter(|name| !name.is_empty())
+            .unwrap_or(&parser.name)
+            .to_string();
+        let mut merged = Grammar::new(name, GrammarType::Combined);
+
+        let (lexer_rules, lexer_parser_rules): (Vec<Rule>, Vec<Rule>) =
+            lexer.rules.into_iter().partition(|rule| rule.is_lexer_rule());
+        let (parser_lexer_rules, parser_rules): (Vec<Rule>, Vec<Rule>) =
+            parser.rules.into_iter().partition(|rule| rule.is_lexer_rule());
+        merged.rules = parser_rules
+            .into_iter()
+            .chain(lexer_parser_rules)
+            .chain(lexer_rules)
+            .chain(parser_lexer_rules)
+            .collect();
+
+        merged.options = lexer.options;
+        merged.options.extend(parser.options);
+        merged.options.remove("tokenVocab");
+
+        for import in lexer.imports.into_iter().chain(parser.imports) {
+            if !merged.imports.contains(&import) {
+                merged.imports.push(import);
+            }
+        }
+        merged.imported_rules = lexer.imported_rules;
+        merged.imported_rules.extend(parser.imported_rules);
+
+        for (name, code) in lexer.named_actions {
+            let name = if name.contains("::") { name } else { format!("lexer::{}", name) };
+            merged.named_actions.insert(name, code);
+        }
+        for (name, code) in parser.named_actions {
+            match merged.named_actions.get_mut(&name) {
+                Some(existing) => {
+                    existing.push('\n');
+                    existing.push_str(&code);
+                }
+                None => {
+                    merged.named_actions.insert(name, code);
+                }
+            }
+        }
+
+        for (mode_name, rules) in lexer.lexer_modes.into_iter().chain(parser.lexer_modes) {
+            let mode = merged.lexer_modes.entry(mode_name).or_default();
+            for rule in rules {
+                if !mode.contains(&rule) {
+                    mode.push(rule);
+                }
+            }
+        }
+        merged.channels = lexer.channels;
+        merged.channels.extend(parser.channels);
+        for token in lexer.declared_tokens.into_iter().chain(parser.declared_tokens) {
+            if !merged.declared_tokens.contains(&token) {
+                merged.declared_tokens.push(token);
+            }
+        }
+
+        Ok(merged)
+    }
+
+    /// Run every analysis pass and return the consolidated diagnostics.
+    ///
+    /// See [`crate::analysis::validate`] for what is checked.
+    pub fn validate(&self) -> Vec<crate::Diagnostic> {
+        crate::analysis::validate(self)
+    }
+
+    /// Whether `other` parses the same way: [`diff`](Self::diff) finds no changes.
+    pub fn structural_eq(&self, other: &Grammar) -> bool {
+        self.diff(other).is_empty()
+    }
+
+    /// Rules, options, channels, declared tokens, modes, named actions and
+    /// imports added, removed or modified in `other`, and a change of
+    /// grammar type.
+    ///
+    /// Source locations and the order of options and channels are ignored;
+    /// the order of alternatives within a rule, of token rules and of
+    /// imports is not.
+    pub fn diff(&self, other: &Grammar) -> Vec<GrammarChange> {
+        diff::diff(self, other)
+    }
+
+    /// Contents of this grammar's `.tokens` file: `NAME=number` lines in
+    /// declaration order followed by `'literal'=number` lines.
+    pub fn export_token_vocab(&self) -> String {
+        TokenVocab::from_grammar(self).to_string()
+    }
+
     pub fn add_rule(&mut self, rule: Rule) {
         self.rules.push(rule);
     }
 
+    /// Replace the rule with the same name as `rule`, keeping its position,
+    /// and return the old one. A rule with a new name is added at the end.
+    pub fn replace_rule(&mut self, rule: Rule) -> Option<Rule> {
+        match self.rules.iter_mut().find(|existing| existing.name == rule.name) {
+            Some(existing) => Some(std::mem::replace(existing, rule)),
+            None => {
+                self.rules.push(rule);
+                None
+            }
+        }
+    }
+
     pub fn add_option(&mut self, key: String, value: String) {
         self.options.insert(key, value);
     }
@@ -47,6 +204,12 @@ impl Grammar {
         self.imports.push(import);
     }
 
+    /// Import only `rules` from the grammar `import`.
+    pub fn add_selective_import(&mut self, import: String, rules: Vec<String>) {
+        self.imported_rules.insert(import.clone(), rules);
+        self.imports.push(import);
+    }
+
     pub fn add_named_action(&mut self, name: String, code: String) {
         self.named_actions.insert(name, code);
     }
@@ -1,11 +1,17 @@
 //! Abstract Syntax Tree (AST) definitions for grammar files.
 
+pub mod builder;
+pub mod diff;
 pub mod grammar;
 pub mod rule;
 pub mod element;
+pub mod token_vocab;
 pub mod visitor;
 
+pub use builder::GrammarBuilder;
+pub use diff::GrammarChange;
 pub use grammar::{Grammar, GrammarNode};
 pub use rule::{Rule, RuleType};
-pub use element::{Element, Alternative, LexerCommand};
+pub use element::{unicode_property, Element, Alternative, LexerCommand, PredicatePosition};
+pub use token_vocab::TokenVocab;
 pub use visitor::{AstVisitor, AstVisitorMut};
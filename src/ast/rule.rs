@@ -1,7 +1,9 @@
 //! Rule AST node definitions.
 
 use super::element::Alternative;
+use crate::diagnostic::Location;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 /// Type of rule.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -11,28 +13,43 @@ pub enum RuleType {
 }
 
 /// Rule argument definition
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct RuleArg {
     pub name: String,
     pub arg_type: Option<String>,
 }
 
 /// Rule return value definition
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct RuleReturn {
     pub name: String,
     pub return_type: Option<String>,
 }
 
 /// Rule local variable definition
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct RuleLocal {
     pub name: String,
     pub local_type: Option<String>,
 }
 
+/// Exception handler after a rule: catch [Exception e] { action }
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CatchClause {
+    /// Text between the brackets, e.g. `RecognitionException e`
+    pub exception: String,
+    pub action: String,
+}
+
+impl CatchClause {
+    /// Name the caught exception is bound to: the last word of `exception`.
+    pub fn variable(&self) -> Option<&str> {
+        self.exception.split_whitespace().last()
+    }
+}
+
 /// A grammar rule.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Rule {
     pub name: String,
     pub rule_type: RuleType,
@@ -44,6 +61,18 @@ pub struct Rule {
     pub returns: Vec<RuleReturn>,
     /// Local variables: locals [Type var]
     pub locals: Vec<RuleLocal>,
+    /// Where the rule name appears in the source grammar, if parsed from one
+    pub location: Option<Location>,
+    /// Exception handlers: catch [Exception e] { ... }
+    pub catch_clauses: Vec<CatchClause>,
+    /// Cleanup action run however the rule ends: finally { ... }
+    pub finally_clause: Option<String>,
+    /// Options of the rule's own block: ID options { caseInsensitive = true; }
+    #[serde(default)]
+    pub options: HashMap<String, String>,
+    /// Text of the `/** ... */` comment written just before the rule
+    #[serde(default)]
+    pub doc: Option<String>,
 }
 
 impl Rule {
@@ -56,6 +85,11 @@ impl Rule {
             arguments: Vec::new(),
             returns: Vec::new(),
             locals: Vec::new(),
+            location: None,
+            catch_clauses: Vec::new(),
+            finally_clause: None,
+            options: HashMap::new(),
+            doc: None,
         }
     }
     
@@ -71,6 +105,10 @@ impl Rule {
         self.locals.push(RuleLocal { name, local_type });
     }
 
+    pub fn add_catch(&mut self, exception: String, action: String) {
+        self.catch_clauses.push(CatchClause { exception, action });
+    }
+
     pub fn parser_rule(name: String) -> Self {
         Self::new(name, RuleType::Parser)
     }
@@ -94,6 +132,10 @@ impl Rule {
     pub fn set_fragment(&mut self, is_fragment: bool) {
         self.is_fragment = is_fragment;
     }
+
+    pub fn set_location(&mut self, location: Location) {
+        self.location = Some(location);
+    }
 }
 
 #[cfg(test)]
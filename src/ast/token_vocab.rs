@@ -0,0 +1,204 @@
+//! Token vocabularies shared between split lexer and parser grammars.
+
+use super::{Element, Grammar};
+use crate::codegen::common::implicit_literal_tokens;
+use crate::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Token names and numbers, in the format of an ANTLR `.tokens` file.
+///
+/// Each line is either `NAME=number` or `'literal'=number`, the latter
+/// recording the literal a token is defined as so that a parser grammar
+/// can write `'+'` for a token its lexer grammar calls `PLUS`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenVocab {
+    tokens: Vec<(String, u32)>,
+    literals: Vec<(String, u32)>,
+}
+
+impl TokenVocab {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Build the vocabulary a grammar defines: implicit literal tokens
+    /// first, then tokens declared in `tokens { ... }`, then non-fragment
+    /// lexer rules, numbered from 1 in
+    /// declaration order. Tokens already in the grammar's own
+    /// `token_vocab` keep their numbers.
+    pub fn from_grammar(grammar: &Grammar) -> Self {
+        let mut vocab = grammar.token_vocab.clone().unwrap_or_default();
+        let mut next = vocab.max_token() + 1;
+
+        let mut add = |vocab: &mut Self, name: &str, literal: Option<&str>| {
+            let number = match vocab.token(name) {
+                Some(number) => number,
+                None => {
+                    vocab.add_token(name, next);
+                    next += 1;
+                    next - 1
+                }
+            };
+            if let Some(literal) = literal
+                && vocab.literal_token(literal).is_none()
+            {
+                vocab.add_literal(literal, number);
+            }
+        };
+
+        for (name, literal) in implicit_literal_tokens(grammar) {
+            add(&mut vocab, &name, Some(&literal));
+        }
+        for name in &grammar.declared_tokens {
+            add(&mut vocab, name, None);
+        }
+        for rule in grammar.lexer_rules().filter(|r| !r.is_fragment) {
+            let literal = match rule.alternatives.as_slice() {
+                [alt] => match alt.elements.as_slice() {
+                    [Element::StringLiteral { value, .. }] => Some(value.as_str()),
+                    _ => None,
+                },
+                _ => None,
+            };
+            add(&mut vocab, &rule.name, literal);
+        }
+        vocab
+    }
+
+    pub fn add_token(&mut self, name: impl Into<String>, number: u32) {
+        self.tokens.push((name.into(), number));
+    }
+
+    pub fn add_literal(&mut self, literal: impl Into<String>, number: u32) {
+        self.literals.push((literal.into(), number));
+    }
+
+    /// Number of the token called `name`.
+    pub fn token(&self, name: &str) -> Option<u32> {
+        self.tokens.iter().find(|(n, _)| n == name).map(|(_, number)| *number)
+    }
+
+    /// Name of the token defined as exactly `literal`.
+    pub fn literal_token(&self, literal: &str) -> Option<&str> {
+        let number = self.literals.iter().find(|(l, _)| l == literal)?.1;
+        self.tokens
+            .iter()
+            .find(|(_, n)| *n == number)
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// `(name, number)` pairs in the order they were added.
+    pub fn tokens(&self) -> &[(String, u32)] {
+        &self.tokens
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    fn max_token(&self) -> u32 {
+        self.tokens.iter().map(|(_, n)| *n).max().unwrap_or(0)
+    }
+}
+
+impl FromStr for TokenVocab {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<Self> {
+        let mut vocab = Self::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let location = format!("line {}", index + 1);
+            let (key, number) = line
+                .rsplit_once('=')
+                .ok_or_else(|| Error::parse(&location, format!("expected NAME=number, found '{}'", line)))?;
+            let number: u32 = number
+                .trim()
+                .parse()
+                .map_err(|_| Error::parse(&location, format!("invalid token number '{}'", number.trim())))?;
+            let key = key.trim();
+            match key.strip_prefix('\'').and_then(|k| k.strip_suffix('\'')) {
+                Some(literal) => vocab.add_literal(unescape_literal(literal), number),
+                None if !key.is_empty() => vocab.add_token(key, number),
+                None => return Err(Error::parse(&location, "missing token name")),
+            }
+        }
+        Ok(vocab)
+    }
+}
+
+impl fmt::Display for TokenVocab {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, number) in &self.tokens {
+            writeln!(f, "{}={}", name, number)?;
+        }
+        for (literal, number) in &self.literals {
+            writeln!(f, "'{}'={}", escape_literal(literal), number)?;
+        }
+        Ok(())
+    }
+}
+
+fn escape_literal(literal: &str) -> String {
+    let mut escaped = String::new();
+    for c in literal.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\'' => escaped.push_str("\\'"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn unescape_literal(literal: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = literal.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some('r') => unescaped.push('\r'),
+            Some('t') => unescaped.push('\t'),
+            Some(escaped) => unescaped.push(escaped),
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_vocab_round_trip() {
+        let text = "PLUS=1\nID=2\n'+'=1\n'\\''=3\nQUOTE=3\n'\\n'=4\nNL=4\n";
+        let vocab: TokenVocab = text.parse().unwrap();
+        assert_eq!(vocab.token("ID"), Some(2));
+        assert_eq!(vocab.literal_token("+"), Some("PLUS"));
+        assert_eq!(vocab.literal_token("'"), Some("QUOTE"));
+        assert_eq!(vocab.literal_token("\n"), Some("NL"));
+
+        let reparsed: TokenVocab = vocab.to_string().parse().unwrap();
+        assert_eq!(reparsed, vocab);
+    }
+
+    #[test]
+    fn test_token_vocab_rejects_bad_lines() {
+        let err = "PLUS=1\nID two\n".parse::<TokenVocab>().unwrap_err();
+        assert!(err.to_string().contains("line 2"), "{}", err);
+        assert!("ID=x".parse::<TokenVocab>().is_err());
+    }
+}
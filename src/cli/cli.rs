@@ -1,6 +1,9 @@
 //! CLI argument definitions.
 
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
+use crate::codegen::dfa::DEFAULT_MAX_INLINE_DEPTH;
+use crate::types::{BraceStyle, IndentStyle, ParserStrategy, Visibility};
+use clap_complete::Shell;
 use std::path::PathBuf;
 use anyhow::Result;
 
@@ -10,10 +13,31 @@ use super::commands;
 #[command(name = "minipg")]
 #[command(author, version, about, long_about = None)]
 pub struct Cli {
+    /// Only print errors
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    pub quiet: bool,
+
+    /// Print debug output
+    #[arg(short, long, global = true)]
+    pub verbose: bool,
+
     #[command(subcommand)]
     pub command: Commands,
 }
 
+impl Cli {
+    /// Tracing filter selected by `--quiet`/`--verbose`, if either was given.
+    pub fn log_filter(&self) -> Option<&'static str> {
+        if self.quiet {
+            Some("error")
+        } else if self.verbose {
+            Some("debug")
+        } else {
+            None
+        }
+    }
+}
+
 #[derive(Subcommand, Debug)]
 pub enum Commands {
     /// Generate parser from grammar file
@@ -26,9 +50,9 @@ pub enum Commands {
         #[arg(short, long, default_value = ".")]
         output: PathBuf,
 
-        /// Target language
-        #[arg(short = 'l', long, default_value = "rust")]
-        target_language: String,
+        /// Target language [default: the grammar's language option, or rust]
+        #[arg(short = 'l', long, visible_alias = "lang")]
+        target_language: Option<String>,
 
         /// Package name for generated code
         #[arg(short, long)]
@@ -41,6 +65,63 @@ pub enum Commands {
         /// Generate listener pattern
         #[arg(long, default_value = "true")]
         listener: bool,
+
+        /// Make the generated `parse` recover and return every error
+        /// instead of stopping at the first one
+        #[arg(long)]
+        collect_errors: bool,
+
+        /// Print the files that would be generated and their sizes
+        /// instead of writing them
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Keep running and regenerate whenever the grammar or a grammar
+        /// it imports changes
+        #[arg(long, conflicts_with = "dry_run")]
+        watch: bool,
+
+        /// Parse left-recursive binary-operator rules with a single
+        /// precedence-climbing method (Rust target)
+        #[arg(long)]
+        precedence_climbing: bool,
+
+        /// Indentation of the generated code: `tabs` or `spaces-N`
+        /// [default: the target language's usual indentation]
+        #[arg(long, value_name = "STYLE")]
+        indent: Option<IndentStyle>,
+
+        /// Opening brace placement: `same-line` or `next-line`
+        #[arg(long, value_name = "STYLE", default_value = "same-line")]
+        brace_style: BraceStyle,
+
+        /// Also build a concrete syntax tree of every rule invocation and
+        /// token while parsing (Rust target)
+        #[arg(long)]
+        cst: bool,
+
+        /// Visibility of the generated items: `public`, `crate` or `module`
+        #[arg(long, value_name = "LEVEL", default_value = "public")]
+        visibility: Visibility,
+
+        /// How the parser chooses between alternatives: `recursive-descent`,
+        /// or `table-driven` for LL(1) grammars (Rust target)
+        #[arg(long, value_name = "STRATEGY", default_value = "recursive-descent")]
+        parser_strategy: ParserStrategy,
+
+        /// Emit JavaScript that assigns `module.exports` for `require()`
+        /// instead of an ES module
+        #[arg(long)]
+        commonjs: bool,
+
+        /// How many fragment references deep a token rule may inline
+        #[arg(long, value_name = "N", default_value_t = DEFAULT_MAX_INLINE_DEPTH)]
+        max_inline_depth: usize,
+
+        /// Derive serde's `Serialize` and `Deserialize` for the AST nodes
+        /// and tokens (Rust target)
+        #[arg(long)]
+        derive_serde: bool,
     },
 
     /// Validate grammar file
@@ -48,6 +129,10 @@ pub enum Commands {
         /// Grammar file to validate
         #[arg(value_name = "FILE")]
         input: PathBuf,
+
+        /// How to print diagnostics
+        #[arg(long, value_enum, default_value = "text")]
+        format: DiagnosticFormat,
     },
 
     /// Show grammar information
@@ -56,18 +141,108 @@ pub enum Commands {
         #[arg(value_name = "FILE")]
         input: PathBuf,
     },
+
+    /// Export the rule dependency graph in Graphviz DOT format
+    Graph {
+        /// Grammar file to visualize
+        #[arg(value_name = "FILE")]
+        input: PathBuf,
+
+        /// Output file (prints to stdout if omitted)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Write the parsed grammar as JSON, for tools written in other languages
+    Ast {
+        /// Grammar file to parse
+        #[arg(value_name = "FILE")]
+        input: PathBuf,
+
+        /// Output file (prints to stdout if omitted)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Reformat a grammar file in the canonical layout
+    Fmt {
+        /// Grammar file to format
+        #[arg(value_name = "FILE")]
+        input: PathBuf,
+
+        /// Rewrite the file in place instead of printing to stdout; refused
+        /// when formatting would drop comments or parser rule actions
+        #[arg(short, long)]
+        write: bool,
+    },
+
+    /// Print size and complexity metrics of a grammar
+    Stats {
+        /// Grammar file to measure
+        #[arg(value_name = "FILE")]
+        input: PathBuf,
+
+        /// How to print the metrics
+        #[arg(long, value_enum, default_value = "table")]
+        format: StatsFormat,
+    },
+
+    /// Parse sample input with the grammar, without generating a parser
+    Test {
+        /// Grammar file to test
+        #[arg(value_name = "FILE")]
+        grammar: PathBuf,
+
+        /// File to parse (reads stdin if omitted)
+        #[arg(value_name = "INPUT")]
+        input: Option<PathBuf>,
+
+        /// Rule to start parsing from [default: the first parser rule]
+        #[arg(short, long)]
+        rule: Option<String>,
+    },
+
+    /// Print a shell completion script to stdout
+    Completions {
+        /// Shell to generate completions for
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+}
+
+/// Output format for diagnostics.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DiagnosticFormat {
+    /// One `file:line:col: severity: message` line per diagnostic
+    Text,
+    /// A JSON array of diagnostics
+    Json,
+}
+
+/// Output format for `minipg stats`.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StatsFormat {
+    /// One metric per line, aligned
+    Table,
+    /// A JSON object with one field per metric
+    Json,
 }
 
 /// Run the CLI application
 pub fn run_cli() -> Result<()> {
-    // Initialize tracing
+    let cli = Cli::parse();
+
+    // Initialize tracing; explicit flags win over RUST_LOG
+    let filter = match cli.log_filter() {
+        Some(level) => tracing_subscriber::EnvFilter::new(level),
+        None => tracing_subscriber::EnvFilter::try_from_default_env()
+            .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info")),
+    };
+    // Logs go to stderr so commands can write their output to stdout
     tracing_subscriber::fmt()
-        .with_env_filter(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info")),
-        )
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr)
         .init();
 
-    let cli = Cli::parse();
     commands::execute(cli)
 }
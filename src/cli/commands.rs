@@ -1,15 +1,29 @@
 //! Command implementations.
 
-use super::{Cli, Commands};
+use super::{Cli, Commands, DiagnosticFormat, StatsFormat};
 use anyhow::{Context, Result};
-use crate::analysis::SemanticAnalyzer;
+use clap::CommandFactory;
+use clap_complete::Shell;
+use crate::analysis::left_recursion::eliminate_unclimbable;
+use crate::analysis::reachability::rule_graph_dot;
+use crate::analysis::{stats, GrammarComposer, SemanticAnalyzer};
 use crate::codegen::registry::LanguageRegistry;
+use crate::codegen::{resolve_target_language, unsupported_option_warnings};
+use crate::format::{dropped_by_format, format_grammar};
+use crate::interpret::parse_input;
 use crate::parser::{Lexer, Parser};
-use crate::types::CodeGenConfig;
+use crate::types::{
+    BraceStyle, CodeGenConfig, CstMode, ErrorMode, GrammarType, IndentStyle, ParserStrategy,
+    Visibility,
+};
+use crate::Grammar;
 use crate::DiagnosticSeverity;
 use std::fs;
-use std::path::Path;
-use tracing::{error, info};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{debug, error, info};
 
 /// Execute the CLI command based on the parsed arguments.
 ///
@@ -30,32 +44,119 @@ pub fn execute(cli: Cli) -> Result<()> {
             package,
             visitor,
             listener,
-        } => generate_command(input, output, target_language, package, visitor, listener),
-        Commands::Validate { input } => validate_command(input),
+            collect_errors,
+            dry_run,
+            watch,
+            precedence_climbing,
+            indent,
+            brace_style,
+            cst,
+            visibility,
+            parser_strategy,
+            commonjs,
+            max_inline_depth,
+            derive_serde,
+        } => {
+            let options = GenerateOptions {
+                target_language,
+                package,
+                visitor,
+                listener,
+                collect_errors,
+                dry_run,
+                precedence_climbing,
+                indent,
+                brace_style,
+                cst,
+                visibility,
+                parser_strategy,
+                commonjs,
+                max_inline_depth,
+                derive_serde,
+            };
+            if watch {
+                watch_command(input, output, options)
+            } else {
+                generate_command(input, output, options)
+            }
+        }
+        Commands::Validate { input, format } => validate_command(input, format),
         Commands::Info { input } => info_command(input),
+        Commands::Graph { input, output } => graph_command(input, output),
+        Commands::Ast { input, output } => ast_command(input, output),
+        Commands::Fmt { input, write } => fmt_command(input, write),
+        Commands::Stats { input, format } => stats_command(input, format),
+        Commands::Test { grammar, input, rule } => test_command(grammar, input, rule),
+        Commands::Completions { shell } => completions_command(shell),
     }
 }
 
+/// Flags of the `generate` command.
+#[derive(Clone)]
+struct GenerateOptions {
+    /// Target programming language; overrides the grammar's `language` option
+    target_language: Option<String>,
+    /// Optional package/module name for generated code
+    package: Option<String>,
+    /// Whether to generate visitor pattern
+    visitor: bool,
+    /// Whether to generate listener pattern
+    listener: bool,
+    /// Whether the generated `parse` collects errors instead of failing fast
+    collect_errors: bool,
+    /// Print each file that would be written and its size in bytes instead of writing
+    dry_run: bool,
+    /// Whether operator rules are parsed by precedence climbing
+    precedence_climbing: bool,
+    /// Indentation of the generated code, if not the target's default
+    indent: Option<IndentStyle>,
+    /// Opening brace placement in the generated code
+    brace_style: BraceStyle,
+    /// Whether the generated parser also builds a concrete syntax tree
+    cst: bool,
+    /// Visibility of the generated items
+    visibility: Visibility,
+    /// How the generated parser chooses between alternatives
+    parser_strategy: ParserStrategy,
+    /// Emit CommonJS instead of an ES module (JavaScript target)
+    commonjs: bool,
+    /// How deep token rules may nest fragment references
+    max_inline_depth: usize,
+    /// Derive serde traits for the AST (Rust target)
+    derive_serde: bool,
+}
+
 /// Generate a parser from the given grammar file.
 ///
 /// # Arguments
 /// * `input` - Path to the grammar file (.g4 format)
 /// * `output` - Directory where generated parser code will be written
-/// * `target_language` - Target programming language for code generation
-/// * `package` - Optional package/module name for generated code
-/// * `visitor` - Whether to generate visitor pattern
-/// * `listener` - Whether to generate listener pattern
+/// * `options` - The remaining command-line flags
 ///
 /// # Returns
 /// * `Result<()>` - Ok(()) if successful, or an error if generation fails
 fn generate_command(
     input: impl AsRef<Path>,
     output: impl AsRef<Path>,
-    target_language: String,
-    package: Option<String>,
-    visitor: bool,
-    listener: bool,
+    options: GenerateOptions,
 ) -> Result<()> {
+    let GenerateOptions {
+        target_language,
+        package,
+        visitor,
+        listener,
+        collect_errors,
+        dry_run,
+        precedence_climbing,
+        indent,
+        brace_style,
+        cst,
+        visibility,
+        parser_strategy,
+        commonjs,
+        max_inline_depth,
+        derive_serde,
+    } = options;
     let input = input.as_ref();
     let output = output.as_ref();
 
@@ -64,22 +165,57 @@ fn generate_command(
     // Parse grammar
     let content = fs::read_to_string(input)
         .context("Failed to read grammar file")?;
-    let filename = input.to_string_lossy().to_string();
-    let lexer = Lexer::new(&content, &filename);
-    let mut parser = Parser::new(lexer);
-    let grammar = parser
-        .parse_grammar()
-        .context("Failed to parse grammar file")?;
+    let mut grammar = parse_grammar_source(&content, input)?;
 
     info!("Parsed grammar: {}", grammar.name);
 
+    resolve_imports(&mut grammar, input)?;
+    load_token_vocab(&mut grammar, input, output)?;
+
+    let (target_language, conflict) = resolve_target_language(
+        target_language.as_deref(),
+        &grammar,
+        &CodeGenConfig::default().target_language,
+    );
+    if let Some(warning) = conflict {
+        tracing::warn!("{}", warning);
+    }
+
+    // These change the generated Rust parser; other targets would ignore them
+    let rust_only: Vec<&str> = [
+        (precedence_climbing, "--precedence-climbing"),
+        (cst, "--cst"),
+        (parser_strategy == ParserStrategy::TableDriven, "--parser-strategy table-driven"),
+        (derive_serde, "--derive-serde"),
+    ]
+    .into_iter()
+    .filter_map(|(set, flag)| set.then_some(flag))
+    .collect();
+    if target_language != "rust" && !rust_only.is_empty() {
+        let verb = if rust_only.len() == 1 { "is" } else { "are" };
+        anyhow::bail!(
+            "{} {} only supported by the rust target, not {}",
+            rust_only.join(", "),
+            verb,
+            target_language
+        );
+    }
+
     // Analyze grammar
-    let mut analyzer = SemanticAnalyzer::new();
-    let mut grammar = grammar; // Make mutable for analysis
-    let analysis = analyzer.analyze(&mut grammar);
+    let analysis = SemanticAnalyzer::new()
+        .with_precedence_climbing(precedence_climbing)
+        .run(grammar);
+    let mut diagnostics = analysis.diagnostics;
+
+    // Left recursion that is not climbed would recurse forever
+    let mut grammar = analysis.grammar;
+    let has_errors = diagnostics.iter().any(|d| d.severity == DiagnosticSeverity::Error);
+    if !has_errors {
+        diagnostics.extend(eliminate_unclimbable(&mut grammar, precedence_climbing));
+    }
 
     // Report diagnostics
-    for diagnostic in &analysis.diagnostics {
+    for diagnostic in &diagnostics {
         match diagnostic.severity {
             DiagnosticSeverity::Error => error!("{}", diagnostic),
             DiagnosticSeverity::Warning => {
@@ -89,7 +225,7 @@ fn generate_command(
         }
     }
 
-    if analysis.has_errors() {
+    if diagnostics.iter().any(|d| d.severity == DiagnosticSeverity::Error) {
         anyhow::bail!("Grammar has errors, aborting code generation");
     }
 
@@ -100,70 +236,241 @@ fn generate_command(
         package_name: package,
         generate_listener: listener,
         generate_visitor: visitor,
+        error_mode: if collect_errors {
+            ErrorMode::Collect
+        } else {
+            ErrorMode::FailFast
+        },
+        alt_stats: false,
+        precedence_climbing,
+        indent,
+        brace_style,
+        cst_mode: if cst { CstMode::On } else { CstMode::Off },
+        visibility,
+        parser_strategy,
+        commonjs,
+        max_inline_depth,
+        derive_serde,
     };
 
     let registry = LanguageRegistry::new();
     let generator = registry
         .get(&target_language)
         .ok_or_else(|| anyhow::anyhow!("Unsupported target language: {}", target_language))?;
+    for diagnostic in unsupported_option_warnings(&grammar, generator) {
+        tracing::warn!("{}", diagnostic);
+    }
     let code = generator
         .generate(&grammar, &config)
         .context("Failed to generate code")?;
 
+    let mut paths = registry.layout(&target_language).files(&grammar).into_iter();
+    let mut files = vec![(output.join(paths.next().unwrap()), code)];
+    if let Some(vocab) = paths.next() {
+        files.push((output.join(vocab), grammar.export_token_vocab()));
+    }
+
+    if dry_run {
+        for (path, contents) in &files {
+            println!("{} ({} bytes)", path.display(), contents.len());
+        }
+        return Ok(());
+    }
+
     // Write output
-    fs::create_dir_all(output).context("Failed to create output directory")?;
-    let output_file = output.join(format!("{}_parser.rs", grammar.name.to_lowercase()));
-    fs::write(&output_file, code).context("Failed to write output file")?;
+    for (path, contents) in files {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).context("Failed to create output directory")?;
+        }
+        fs::write(&path, contents).context("Failed to write output file")?;
+        info!("Generated parser: {}", path.display());
+    }
+    Ok(())
+}
+
+/// How often watched grammar files are checked for changes.
+const WATCH_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long watched files must stay unchanged before regenerating, so the
+/// several writes of one editor save lead to a single run.
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// Generate a parser, then regenerate it each time the grammar or a grammar
+/// it imports changes.
+///
+/// Every run goes through [`generate_command`] and prints its diagnostics;
+/// a run that fails is reported and the watch goes on. Imported grammars
+/// are looked up as `<name>.g4` next to the input and are re-read after
+/// each run, so adding an import starts watching it. Runs until killed.
+fn watch_command(input: PathBuf, output: PathBuf, options: GenerateOptions) -> Result<()> {
+    loop {
+        if let Err(err) = generate_command(&input, &output, options.clone()) {
+            error!("{:#}", err);
+        }
+
+        let watched = watched_grammar_files(&input);
+        info!("Watching {} file(s) for changes", watched.len());
+        wait_for_change(&watched);
+    }
+}
+
+/// The grammar file and the grammars it imports, directly or through other
+/// imports, found next to it.
+fn watched_grammar_files(input: &Path) -> Vec<PathBuf> {
+    let mut files = vec![input.to_path_buf()];
+    let mut pending = vec![input.to_path_buf()];
+    while let Some(path) = pending.pop() {
+        let Ok(content) = fs::read_to_string(&path) else {
+            continue;
+        };
+        let Ok(grammar) = parse_grammar_source(&content, &path) else {
+            continue;
+        };
+        for name in &grammar.imports {
+            let import = grammar_dir(input).join(format!("{}.g4", name));
+            if import.exists() && !files.contains(&import) {
+                files.push(import.clone());
+                pending.push(import);
+            }
+        }
+    }
+    files
+}
+
+/// Block until one of `files` is modified, created or removed and then
+/// left alone for [`WATCH_DEBOUNCE`].
+fn wait_for_change(files: &[PathBuf]) {
+    let snapshot = || -> Vec<Option<SystemTime>> {
+        files
+            .iter()
+            .map(|path| fs::metadata(path).and_then(|meta| meta.modified()).ok())
+            .collect()
+    };
+
+    let mut last = snapshot();
+    let mut changed_at: Option<Instant> = None;
+    loop {
+        thread::sleep(WATCH_POLL_INTERVAL);
+        let current = snapshot();
+        if current != last {
+            last = current;
+            changed_at = Some(Instant::now());
+        } else if changed_at.is_some_and(|at| at.elapsed() >= WATCH_DEBOUNCE) {
+            return;
+        }
+    }
+}
+
+/// Parse the grammar `content` read from `input`.
+///
+/// A parse error shows the offending source line with a caret under the
+/// column where parsing failed.
+fn parse_grammar_source(content: &str, input: &Path) -> Result<Grammar> {
+    let filename = input.to_string_lossy().to_string();
+    let mut parser = Parser::new(Lexer::new(content, &filename));
+    parser
+        .parse_grammar()
+        .map_err(|err| anyhow::anyhow!(err.to_diagnostic(&filename).render_with_source(content)))
+        .context("Failed to parse grammar file")
+}
+
+/// Directory of a grammar file, where its imports and vocabulary are looked up.
+fn grammar_dir(input: &Path) -> &Path {
+    input
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."))
+}
 
-    info!("Generated parser: {}", output_file.display());
+/// Merge the grammars `grammar` imports into it, reading them as
+/// `<name>.g4` next to `input`. See [`GrammarComposer::compose`].
+fn resolve_imports(grammar: &mut Grammar, input: &Path) -> Result<()> {
+    if grammar.imports.is_empty() {
+        return Ok(());
+    }
+    let mut composer = GrammarComposer::new();
+    composer.search_paths = vec![grammar_dir(input).to_path_buf()];
+    composer
+        .resolve_imports(grammar)
+        .context("Failed to resolve grammar imports")
+}
+
+/// Attach the vocabulary named by a parser grammar's `tokenVocab` option,
+/// read from `<name>.tokens` next to the grammar or in the output directory.
+fn load_token_vocab(grammar: &mut Grammar, input: &Path, output: &Path) -> Result<()> {
+    if grammar.grammar_type != GrammarType::Parser {
+        return Ok(());
+    }
+    let Some(vocab_name) = grammar.options.get("tokenVocab") else {
+        return Ok(());
+    };
+    let file_name = format!("{}.tokens", vocab_name);
+    let grammar_dir = grammar_dir(input);
+    let path = [grammar_dir.join(&file_name), output.join(&file_name)]
+        .into_iter()
+        .find(|path| path.exists())
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "Token vocabulary {} not found in {} or {}; generate the lexer grammar first",
+                file_name,
+                grammar_dir.display(),
+                output.display()
+            )
+        })?;
+    let text = fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let vocab = text
+        .parse()
+        .with_context(|| format!("Failed to parse {}", path.display()))?;
+    debug!("Loaded token vocabulary from {}", path.display());
+    grammar.token_vocab = Some(vocab);
     Ok(())
 }
 
 /// Validate a grammar file and report any issues.
 ///
-/// This function parses and analyzes the grammar file, reporting any
-/// semantic issues or validation errors found.
+/// This function parses and analyzes the grammar file and prints every
+/// diagnostic to stdout, one per line or as a JSON array.
 ///
 /// # Arguments
 /// * `input` - Path to the grammar file to validate
+/// * `format` - How to print the diagnostics
 ///
 /// # Returns
-/// * `Result<()>` - Ok(()) if validation passes, or an error if issues are found
-fn validate_command(input: impl AsRef<Path>) -> Result<()> {
+/// * `Result<()>` - Ok(()) if validation passes, or an error if any diagnostic is an error
+fn validate_command(input: impl AsRef<Path>, format: DiagnosticFormat) -> Result<()> {
     let input = input.as_ref();
     info!("Validating grammar: {}", input.display());
 
     // Parse grammar
     let content = fs::read_to_string(input)
         .context("Failed to read grammar file")?;
-    let filename = input.to_string_lossy().to_string();
-    let lexer = Lexer::new(&content, &filename);
-    let mut parser = Parser::new(lexer);
-    let grammar = parser
-        .parse_grammar()
-        .context("Failed to parse grammar file")?;
+    let mut grammar = parse_grammar_source(&content, input)?;
+    resolve_imports(&mut grammar, input)?;
 
-    // Analyze grammar
-    let mut analyzer = SemanticAnalyzer::new();
-    let mut grammar = grammar; // Make mutable for analysis
-    let analysis = analyzer.analyze(&mut grammar);
+    // Analyze grammar, running every pass so all problems are listed
+    let diagnostics = grammar.validate();
+    debug!(
+        "Analyzed {} rules, {} diagnostics",
+        grammar.rules.len(),
+        diagnostics.len()
+    );
 
     // Report diagnostics
-    let mut has_errors = false;
-    for diagnostic in &analysis.diagnostics {
-        match diagnostic.severity {
-            DiagnosticSeverity::Error => {
-                error!("{}", diagnostic);
-                has_errors = true;
+    match format {
+        DiagnosticFormat::Text => {
+            for diagnostic in &diagnostics {
+                println!("{}", diagnostic.render_with_source(&content));
             }
-            DiagnosticSeverity::Warning => {
-                tracing::warn!("{}", diagnostic)
-            }
-            DiagnosticSeverity::Info => info!("{}", diagnostic),
+        }
+        DiagnosticFormat::Json => {
+            let json = serde_json::to_string_pretty(&diagnostics)
+                .context("Failed to serialize diagnostics")?;
+            println!("{}", json);
         }
     }
 
-    if has_errors {
+    if diagnostics.iter().any(|d| d.severity == DiagnosticSeverity::Error) {
         anyhow::bail!("Grammar validation failed");
     } else {
         info!("Grammar is valid");
@@ -189,12 +496,7 @@ fn info_command(input: impl AsRef<Path>) -> Result<()> {
     // Parse grammar
     let content = fs::read_to_string(input)
         .context("Failed to read grammar file")?;
-    let filename = input.to_string_lossy().to_string();
-    let lexer = Lexer::new(&content, &filename);
-    let mut parser = Parser::new(lexer);
-    let grammar = parser
-        .parse_grammar()
-        .context("Failed to parse grammar file")?;
+    let grammar = parse_grammar_source(&content, input)?;
 
     // Print grammar information
     println!("Grammar: {}", grammar.name);
@@ -219,3 +521,224 @@ fn info_command(input: impl AsRef<Path>) -> Result<()> {
 
     Ok(())
 }
+
+/// Write the grammar's rule dependency graph as Graphviz DOT.
+///
+/// # Arguments
+/// * `input` - Path to the grammar file to visualize
+/// * `output` - File to write the graph to, or `None` for stdout
+///
+/// # Returns
+/// * `Result<()>` - Ok(()) if successful, or an error if parsing or writing fails
+fn graph_command(input: impl AsRef<Path>, output: Option<PathBuf>) -> Result<()> {
+    let input = input.as_ref();
+    info!("Building rule graph: {}", input.display());
+
+    // Parse grammar
+    let content = fs::read_to_string(input)
+        .context("Failed to read grammar file")?;
+    let mut grammar = parse_grammar_source(&content, input)?;
+    resolve_imports(&mut grammar, input)?;
+
+    let dot = rule_graph_dot(&grammar);
+    match output {
+        Some(path) => {
+            fs::write(&path, dot).context("Failed to write graph file")?;
+            info!("Wrote rule graph: {}", path.display());
+        }
+        None => print!("{}", dot),
+    }
+
+    Ok(())
+}
+
+/// Write the parsed grammar as pretty-printed JSON.
+///
+/// # Arguments
+/// * `input` - Path to the grammar file to parse
+/// * `output` - File to write the JSON to, or `None` for stdout
+///
+/// # Returns
+/// * `Result<()>` - Ok(()) if successful, or an error if parsing or writing fails
+fn ast_command(input: impl AsRef<Path>, output: Option<PathBuf>) -> Result<()> {
+    let input = input.as_ref();
+    info!("Parsing grammar: {}", input.display());
+
+    let content = fs::read_to_string(input)
+        .context("Failed to read grammar file")?;
+    let grammar = parse_grammar_source(&content, input)?;
+
+    let json = serde_json::to_string_pretty(&grammar)
+        .context("Failed to serialize grammar")?;
+    match output {
+        Some(path) => {
+            fs::write(&path, json).context("Failed to write AST file")?;
+            info!("Wrote grammar AST: {}", path.display());
+        }
+        None => println!("{}", json),
+    }
+
+    Ok(())
+}
+
+/// Print a grammar file in the canonical layout, or rewrite it in place.
+///
+/// Rewriting is refused, leaving the file untouched, when the formatted text
+/// would lose comments or actions; see [`dropped_by_format`].
+fn fmt_command(input: impl AsRef<Path>, write: bool) -> Result<()> {
+    let input = input.as_ref();
+    info!("Formatting grammar: {}", input.display());
+
+    let content = fs::read_to_string(input)
+        .context("Failed to read grammar file")?;
+    let grammar = parse_grammar_source(&content, input)?;
+
+    let formatted = format_grammar(&grammar);
+    if !write {
+        print!("{}", formatted);
+    } else if formatted != content {
+        let dropped = dropped_by_format(&content, &formatted);
+        if !dropped.is_empty() {
+            anyhow::bail!(
+                "Not rewriting {}: formatting would drop\n  {}",
+                input.display(),
+                dropped.join("\n  ")
+            );
+        }
+        fs::write(input, formatted).context("Failed to write grammar file")?;
+        info!("Formatted {}", input.display());
+    }
+
+    Ok(())
+}
+
+/// Print the grammar's metrics as a table or as JSON.
+///
+/// # Arguments
+/// * `input` - Path to the grammar file to measure
+/// * `format` - How to print the metrics
+///
+/// # Returns
+/// * `Result<()>` - Ok(()) if successful, or an error if parsing fails
+fn stats_command(input: impl AsRef<Path>, format: StatsFormat) -> Result<()> {
+    let input = input.as_ref();
+    info!("Measuring grammar: {}", input.display());
+
+    let content = fs::read_to_string(input)
+        .context("Failed to read grammar file")?;
+    let mut grammar = parse_grammar_source(&content, input)?;
+    resolve_imports(&mut grammar, input)?;
+    let stats = stats(&grammar);
+
+    match format {
+        StatsFormat::Table => {
+            let max_alternatives = match &stats.max_alternatives_rule {
+                Some(rule) => format!("{} ({})", stats.max_alternatives, rule),
+                None => stats.max_alternatives.to_string(),
+            };
+            let rows = [
+                ("Grammar", stats.grammar.clone()),
+                ("Parser rules", stats.parser_rules.to_string()),
+                ("Lexer rules", stats.lexer_rules.to_string()),
+                ("Fragment rules", stats.fragment_rules.to_string()),
+                ("Alternatives", stats.alternatives.to_string()),
+                ("Max alternatives", max_alternatives),
+                ("Max nesting depth", stats.max_nesting_depth.to_string()),
+                ("Modes", stats.modes.to_string()),
+                ("Channels", stats.channels.to_string()),
+                ("LL(1)", if stats.ll1 { "yes" } else { "no" }.to_string()),
+            ];
+            for (label, value) in rows {
+                println!("{:<18} {}", label, value);
+            }
+        }
+        StatsFormat::Json => {
+            let json = serde_json::to_string_pretty(&stats)
+                .context("Failed to serialize grammar stats")?;
+            println!("{}", json);
+        }
+    }
+
+    Ok(())
+}
+
+/// Parse a sample input with the grammar's rules and report the result.
+///
+/// # Arguments
+/// * `grammar_path` - Path to the grammar file
+/// * `input` - File to parse, or `None` to read stdin
+/// * `rule` - Rule to start from, or `None` for the first parser rule
+///
+/// # Returns
+/// * `Result<()>` - Ok(()) if the input is accepted, or an error if it is
+///   rejected or the grammar has errors
+fn test_command(grammar_path: impl AsRef<Path>, input: Option<PathBuf>, rule: Option<String>) -> Result<()> {
+    let grammar_path = grammar_path.as_ref();
+    info!("Testing grammar: {}", grammar_path.display());
+
+    let content = fs::read_to_string(grammar_path)
+        .context("Failed to read grammar file")?;
+    let mut grammar = parse_grammar_source(&content, grammar_path)?;
+    resolve_imports(&mut grammar, grammar_path)?;
+    let analysis = SemanticAnalyzer::analyze(grammar);
+    if analysis.has_errors() {
+        for diagnostic in analysis.diagnostics.iter().filter(|d| d.severity == DiagnosticSeverity::Error) {
+            error!("{}", diagnostic.render_with_source(&content));
+        }
+        anyhow::bail!("Grammar has errors");
+    }
+    let grammar = analysis.grammar;
+
+    let rule = match rule {
+        Some(rule) => rule,
+        None => grammar
+            .parser_rules()
+            .next()
+            .map(|rule| rule.name.clone())
+            .context("Grammar has no parser rules to start from")?,
+    };
+    let (name, text) = match &input {
+        Some(path) => (
+            path.display().to_string(),
+            fs::read_to_string(path).context("Failed to read input file")?,
+        ),
+        None => {
+            let mut text = String::new();
+            std::io::stdin()
+                .read_to_string(&mut text)
+                .context("Failed to read stdin")?;
+            ("<stdin>".to_string(), text)
+        }
+    };
+
+    match parse_input(&grammar, &text, &rule) {
+        Ok(()) => {
+            println!("accepted");
+            Ok(())
+        }
+        Err(diagnostics) => {
+            println!("rejected");
+            for mut diagnostic in diagnostics {
+                if let Some(location) = &mut diagnostic.location {
+                    location.file = name.clone();
+                }
+                println!("{}", diagnostic.render_with_source(&text));
+            }
+            anyhow::bail!("Input does not match rule {}", rule)
+        }
+    }
+}
+
+/// Print a completion script for `shell` to stdout.
+///
+/// # Arguments
+/// * `shell` - Shell to generate the completion script for
+///
+/// # Returns
+/// * `Result<()>` - Always Ok(())
+fn completions_command(shell: Shell) -> Result<()> {
+    let mut command = Cli::command();
+    let name = command.get_name().to_string();
+    clap_complete::generate(shell, &mut command, name, &mut std::io::stdout());
+    Ok(())
+}
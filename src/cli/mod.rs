@@ -2,5 +2,5 @@
 pub mod cli;
 pub mod commands;
 
-pub use cli::{Cli, Commands, run_cli};
+pub use cli::{Cli, Commands, DiagnosticFormat, StatsFormat, run_cli};
 pub use commands::execute;
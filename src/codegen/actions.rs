@@ -43,7 +43,7 @@ pub fn translate_action_element(element: &Element, target_lang: &str) -> Option<
             };
             Some(translated)
         }
-        Element::Predicate { code, language } => {
+        Element::Predicate { code, language, .. } => {
             let source_lang = language.as_deref().unwrap_or("generic");
             let translated = if source_lang == target_lang || source_lang == "generic" {
                 code.clone()
@@ -176,7 +176,7 @@ fn translate_rust_to_go(code: &str) -> String {
 /// Generate semantic predicate code for Rust.
 pub fn generate_rust_predicate(code: &str) -> String {
     format!(
-        "if !({{ {} }}) {{\n            return Err(ParseError::new(\"Predicate failed\".to_string(), self.position));\n        }}",
+        "if !({{ {} }}) {{\n            return Err(ParseError::new(\"Predicate failed\".to_string(), self.tokens.position()));\n        }}",
         code
     )
 }
@@ -1,6 +1,8 @@
 //! Common code generation utilities shared across all code generators.
 
-use crate::ast::{Grammar, Rule};
+use crate::ast::{Element, Grammar, LexerCommand, Rule};
+use crate::types::{BraceStyle, CodeGenConfig, IndentStyle};
+use std::collections::HashMap;
 
 /// Common patterns and utilities for code generation.
 /// Token type information for code generation
@@ -27,6 +29,158 @@ pub fn extract_token_types(grammar: &Grammar) -> Vec<String> {
         .collect()
 }
 
+/// Implicit tokens for string literals used directly in parser rules.
+///
+/// As in ANTLR, each distinct literal such as `'+'` gets its own token
+/// (`T__0`, `T__1`, ...) unless a lexer rule is defined as exactly that
+/// literal. A literal the grammar's token vocabulary defines takes that
+/// token's name instead. Returns `(token_name, literal)` pairs in order of
+/// first use.
+pub fn implicit_literal_tokens(grammar: &Grammar) -> Vec<(String, String)> {
+    let defined: Vec<&String> = grammar
+        .lexer_rules()
+        .filter(|r| !r.is_fragment)
+        .filter_map(|r| match r.alternatives.as_slice() {
+            [alt] => match alt.elements.as_slice() {
+                [Element::StringLiteral { value, .. }] => Some(value),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect();
+
+    let mut literals: Vec<String> = Vec::new();
+    for rule in grammar.parser_rules() {
+        for alt in &rule.alternatives {
+            for element in &alt.elements {
+                collect_literals(element, &mut literals);
+            }
+        }
+    }
+
+    let vocab = grammar.token_vocab.as_ref();
+    let mut next = 0;
+    literals
+        .into_iter()
+        .filter(|literal| !defined.contains(&literal))
+        .map(|literal| match vocab.and_then(|v| v.literal_token(&literal)) {
+            Some(name) => (name.to_string(), literal),
+            None => {
+                next += 1;
+                (format!("T__{}", next - 1), literal)
+            }
+        })
+        .collect()
+}
+
+/// Token kind the lexer gives each literal used in parser rules: its
+/// implicit token, or the lexer rule defined as exactly that literal.
+pub fn literal_token_kinds(grammar: &Grammar) -> HashMap<String, String> {
+    let mut kinds: HashMap<String, String> = implicit_literal_tokens(grammar)
+        .into_iter()
+        .map(|(name, literal)| (literal, name))
+        .collect();
+    for rule in grammar.lexer_rules().filter(|rule| !rule.is_fragment) {
+        if let [alt] = rule.alternatives.as_slice()
+            && let [Element::StringLiteral { value, .. }] = alt.elements.as_slice()
+        {
+            kinds.entry(value.clone()).or_insert_with(|| rule.name.clone());
+        }
+    }
+    kinds
+}
+
+fn collect_literals(element: &Element, literals: &mut Vec<String>) {
+    match element {
+        Element::StringLiteral { value, .. } if !literals.contains(value) => {
+            literals.push(value.clone());
+        }
+        Element::Optional { element, .. }
+        | Element::ZeroOrMore { element, .. }
+        | Element::OneOrMore { element, .. }
+        | Element::Not { element } => collect_literals(element, literals),
+        Element::Group { alternatives } => {
+            for alt in alternatives {
+                for elem in &alt.elements {
+                    collect_literals(elem, literals);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Lexer commands of a token rule: those of its first alternative that has any.
+///
+/// The generated DFA only reports which rule matched, not which
+/// alternative, so commands apply to the rule as a whole.
+pub fn rule_commands(rule: &Rule) -> &[LexerCommand] {
+    rule.alternatives
+        .iter()
+        .map(|alt| alt.lexer_commands.as_slice())
+        .find(|commands| !commands.is_empty())
+        .unwrap_or_default()
+}
+
+/// The rules and tokens `rule` references without a label, in the order
+/// they first appear, each with whether some alternative can match it more
+/// than once.
+pub fn unlabeled_references(rule: &Rule) -> Vec<(String, bool)> {
+    fn count(element: &Element, repeated: bool, counts: &mut Vec<(String, usize)>) {
+        match element {
+            Element::RuleRef { name, label: None, .. } if name != "EOF" => {
+                let n = if repeated { 2 } else { 1 };
+                match counts.iter_mut().find(|(seen, _)| seen == name) {
+                    Some((_, total)) => *total += n,
+                    None => counts.push((name.clone(), n)),
+                }
+            }
+            Element::Optional { element, .. } => count(element, repeated, counts),
+            Element::ZeroOrMore { element, .. } | Element::OneOrMore { element, .. } => {
+                count(element, true, counts)
+            }
+            Element::Group { alternatives } => {
+                // Alternatives of a group are exclusive, so take the largest count
+                let mut merged: Vec<(String, usize)> = Vec::new();
+                for alt in alternatives {
+                    let mut alt_counts = Vec::new();
+                    for element in &alt.elements {
+                        count(element, repeated, &mut alt_counts);
+                    }
+                    for (name, n) in alt_counts {
+                        match merged.iter_mut().find(|(seen, _)| *seen == name) {
+                            Some((_, total)) => *total = (*total).max(n),
+                            None => merged.push((name, n)),
+                        }
+                    }
+                }
+                for (name, n) in merged {
+                    match counts.iter_mut().find(|(seen, _)| *seen == name) {
+                        Some((_, total)) => *total += n,
+                        None => counts.push((name, n)),
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut references: Vec<(String, bool)> = Vec::new();
+    for alt in &rule.alternatives {
+        let mut counts = Vec::new();
+        for element in &alt.elements {
+            count(element, false, &mut counts);
+        }
+        for (name, n) in counts {
+            match references.iter_mut().find(|(seen, _)| *seen == name) {
+                Some((_, is_list)) => *is_list |= n > 1,
+                None => references.push((name, n > 1)),
+            }
+        }
+    }
+    references
+}
+
 /// Extract all lexer rules (including fragments) for reference
 pub fn extract_all_lexer_rules(grammar: &Grammar) -> Vec<&Rule> {
     grammar.lexer_rules().collect()
@@ -278,6 +432,122 @@ pub fn format_type_name(name: &str, language: &str) -> String {
     }
 }
 
+/// Leading whitespace for code `columns` spaces deep.
+///
+/// Generators lay code out with a fixed number of spaces per level and
+/// leave conversion to the configured style to [`Emitter::finish`].
+pub fn indentation(columns: usize) -> String {
+    " ".repeat(columns)
+}
+
+/// `value` as a single-quoted string literal, valid in both Python and
+/// JavaScript.
+pub fn single_quoted(value: &str) -> String {
+    let mut quoted = String::from("'");
+    for c in value.chars() {
+        match c {
+            '\\' => quoted.push_str("\\\\"),
+            '\'' => quoted.push_str("\\'"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\x{:02x}", c as u32)),
+            // Line terminators in JavaScript, even inside a string
+            '\u{2028}' | '\u{2029}' => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('\'');
+    quoted
+}
+
+/// Rewrites generated code into the indentation and brace style of a
+/// [`CodeGenConfig`].
+///
+/// Generators write `width` spaces per indentation level with opening
+/// braces at the end of the line; with the default config the code passes
+/// through unchanged.
+pub struct Emitter {
+    indent: IndentStyle,
+    brace_style: BraceStyle,
+    width: usize,
+}
+
+impl Emitter {
+    pub fn new(config: &CodeGenConfig, width: usize) -> Self {
+        Self {
+            indent: config.indent.unwrap_or(IndentStyle::Spaces(width)),
+            brace_style: config.brace_style,
+            width,
+        }
+    }
+
+    /// Apply the configured indentation and brace style to `code`.
+    pub fn finish(&self, code: String) -> String {
+        self.rewrite(code, self.brace_style)
+    }
+
+    /// Apply only the configured indentation, for targets without braces.
+    pub fn finish_indentation(&self, code: String) -> String {
+        self.rewrite(code, BraceStyle::SameLine)
+    }
+
+    fn rewrite(&self, code: String, brace_style: BraceStyle) -> String {
+        if self.indent == IndentStyle::Spaces(self.width) && brace_style == BraceStyle::SameLine {
+            return code;
+        }
+
+        let mut out = String::with_capacity(code.len());
+        for line in code.split_inclusive('\n') {
+            let (text, newline) = match line.strip_suffix('\n') {
+                Some(text) => (text, "\n"),
+                None => (line, ""),
+            };
+            let body = text.trim_start_matches(' ');
+            if body.is_empty() {
+                out.push_str(newline);
+                continue;
+            }
+            let pad = self.pad(text.len() - body.len());
+            match opened_block(body).filter(|_| brace_style == BraceStyle::NextLine) {
+                Some(head) => {
+                    out.push_str(&format!("{pad}{head}\n{pad}{{{newline}"));
+                }
+                None => {
+                    out.push_str(&pad);
+                    out.push_str(body);
+                    out.push_str(newline);
+                }
+            }
+        }
+        out
+    }
+
+    /// Indentation in the configured style for `columns` spaces of the
+    /// generator's layout. Columns short of a full level stay spaces.
+    fn pad(&self, columns: usize) -> String {
+        let (levels, rest) = (columns / self.width, columns % self.width);
+        match self.indent {
+            IndentStyle::Spaces(n) => " ".repeat(levels * n + rest),
+            IndentStyle::Tabs => format!("{}{}", "\t".repeat(levels), " ".repeat(rest)),
+        }
+    }
+}
+
+/// The statement before a trailing ` {` that opens a block, if the brace
+/// can move to its own line. Comments are left alone, as are object and
+/// struct literals after `=`, `(`, `,` or `:` and a `return` whose value
+/// would change meaning in JavaScript.
+fn opened_block(body: &str) -> Option<&str> {
+    let head = body.strip_suffix(" {")?;
+    let is_comment = ["//", "/*", "*", "#"].iter().any(|c| head.starts_with(c));
+    let continues_expression = head.ends_with(['=', '(', ',', ':']) && !head.ends_with("::");
+    if head.is_empty() || is_comment || continues_expression || head.ends_with("return") {
+        return None;
+    }
+    Some(head)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -290,4 +560,71 @@ mod tests {
         assert_eq!(format_identifier("MyVariable", "javascript"), "myVariable");
         assert_eq!(format_identifier("MyVariable", "go"), "MyVariable");
     }
+
+    #[test]
+    fn test_single_quoted() {
+        assert_eq!(single_quoted("+"), "'+'");
+        assert_eq!(single_quoted("it's\n"), "'it\\'s\\n'");
+        assert_eq!(single_quoted("a\\b\t\u{1}"), "'a\\\\b\\t\\x01'");
+        assert_eq!(single_quoted("→"), "'→'");
+    }
+
+    #[test]
+    fn test_implicit_literal_tokens() {
+        use crate::ast::Alternative;
+        use crate::types::GrammarType;
+
+        let mut grammar = Grammar::new("Calc".to_string(), GrammarType::Combined);
+
+        let mut expr = Rule::parser_rule("expr".to_string());
+        let mut alt = Alternative::new();
+        alt.add_element(Element::rule_ref("NUMBER".to_string()));
+        alt.add_element(Element::string_literal("+".to_string()));
+        alt.add_element(Element::string_literal("*".to_string()));
+        alt.add_element(Element::string_literal("+".to_string()));
+        expr.add_alternative(alt);
+        grammar.add_rule(expr);
+
+        // '*' is covered by an explicit lexer rule
+        let mut star = Rule::lexer_rule("STAR".to_string());
+        let mut alt = Alternative::new();
+        alt.add_element(Element::string_literal("*".to_string()));
+        star.add_alternative(alt);
+        grammar.add_rule(star);
+
+        assert_eq!(
+            implicit_literal_tokens(&grammar),
+            vec![("T__0".to_string(), "+".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_emitter_default_config_is_unchanged() {
+        let code = "fn f() {\n    if x {\n      y();\n    }\n}\n".to_string();
+        let emitter = Emitter::new(&CodeGenConfig::default(), 4);
+        assert_eq!(emitter.finish(code.clone()), code);
+    }
+
+    #[test]
+    fn test_emitter_indent_and_brace_style() {
+        let code = "fn f() {\n    let p = Point {\n        x: 1,\n    };\n    if x {\n      y();\n    }\n\n}\n";
+        let config = CodeGenConfig {
+            indent: Some(IndentStyle::Tabs),
+            brace_style: BraceStyle::NextLine,
+            ..CodeGenConfig::default()
+        };
+        assert_eq!(
+            Emitter::new(&config, 4).finish(code.to_string()),
+            "fn f()\n{\n\tlet p = Point\n\t{\n\t\tx: 1,\n\t};\n\tif x\n\t{\n\t  y();\n\t}\n\n}\n"
+        );
+
+        let config = CodeGenConfig {
+            indent: Some(IndentStyle::Spaces(2)),
+            ..CodeGenConfig::default()
+        };
+        assert_eq!(
+            Emitter::new(&config, 4).finish_indentation("def f():\n    return {\n        1: 2,\n    }\n".to_string()),
+            "def f():\n  return {\n    1: 2,\n  }\n"
+        );
+    }
 }
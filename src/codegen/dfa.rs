@@ -2,9 +2,30 @@
 //!
 //! This module generates optimized DFA code at compile time, which is then
 //! inlined into the generated lexer as match statements.
+//!
+//! Lexer rules are first compiled into an NFA (Thompson construction) and
+//! then determinized with the subset construction. All token rules share a
+//! single start state, so the resulting DFA implements maximal munch with
+//! ties going to the rule declared first.
+//!
+//! A rule containing a non-greedy quantifier (`*?`, `+?`, `??`) stops at its
+//! first complete match instead: as soon as a DFA state accepts the rule, the
+//! rule's other NFA states are dropped so it cannot extend further. This is
+//! what makes `'/*' .*? '*/'` end at the first `*/`.
+//!
+//! References to fragments and other lexer rules, as in `NUMBER: DIGIT+;`,
+//! are replaced by the referenced rule's alternatives before the NFA is
+//! built; see [`inline_fragments`].
+//!
+//! Unicode properties such as `\p{L}` are kept as properties rather than
+//! expanded into ranges: an edge that depends on them carries a guard
+//! calling the matching `char` method, e.g. `if ch.is_alphabetic()`.
 
-use crate::ast::{Element, Rule};
-use std::collections::{HashMap, HashSet};
+use crate::ast::element::{unicode_method, PropertyTest};
+use super::common::implicit_literal_tokens;
+use crate::ast::{unicode_property, Alternative, Element, Grammar, Rule};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::ops::Range;
 
 /// DFA state representation
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -15,17 +36,61 @@ pub struct DfaState {
 }
 
 /// Character class for efficient matching
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum CharClass {
     Single(char),
     Range(char, char),
     Any,
+    /// Chars of the class whose Unicode properties have the given values,
+    /// each property named by the `char` method testing it.
+    Guarded(Box<CharClass>, Guard),
+}
+
+/// Values Unicode properties must have, each property named by the `char`
+/// method testing it.
+pub type Guard = Vec<(&'static str, bool)>;
+
+impl CharClass {
+    /// Whether `ch` belongs to the class.
+    pub fn contains(&self, ch: char) -> bool {
+        match self {
+            CharClass::Single(c) => *c == ch,
+            CharClass::Range(a, b) => *a <= ch && ch <= *b,
+            CharClass::Any => true,
+            CharClass::Guarded(class, guard) => {
+                class.contains(ch)
+                    && guard.iter().all(|(method, expected)| {
+                        unicode_method(method).is_some_and(|test| test(ch) == *expected)
+                    })
+            }
+        }
+    }
+}
+
+/// Code point ranges covering every valid `char` (surrogates excluded).
+const ALL_CHARS: [(u32, u32); 2] = [(0, 0xD7FF), (0xE000, 0x10FFFF)];
+
+/// NFA state used while building the DFA.
+#[derive(Debug, Default)]
+struct NfaState {
+    epsilon: Vec<usize>,
+    transitions: Vec<(Vec<(u32, u32)>, usize)>,
+    /// Edges taken by chars whose property (an index into
+    /// `DfaBuilder::properties`) has the given value.
+    property_transitions: Vec<(usize, bool, usize)>,
+    /// Declaration index of the token rule accepted here.
+    accepting: Option<usize>,
 }
 
 /// DFA builder for lexer rules
 pub struct DfaBuilder {
     states: Vec<DfaState>,
     next_state_id: usize,
+    nfa: Vec<NfaState>,
+    /// NFA states of each rule that stops at its first match.
+    shortest_match_rules: Vec<Range<usize>>,
+    /// Unicode properties used by the rules, as `char` method name and test.
+    properties: Vec<PropertyTest>,
 }
 
 impl DfaBuilder {
@@ -37,77 +102,330 @@ impl DfaBuilder {
                 transitions: HashMap::new(),
             }],
             next_state_id: 1,
+            nfa: vec![NfaState::default()],
+            shortest_match_rules: Vec::new(),
+            properties: Vec::new(),
         }
     }
 
     /// Build DFA from lexer rules
+    ///
+    /// Fragment rules are skipped; token rules are matched in declaration
+    /// order when two of them accept the same input.
     pub fn build_from_rules(&mut self, rules: &[&Rule]) -> Vec<DfaState> {
-        for rule in rules {
-            if !rule.is_fragment {
-                self.add_rule(rule);
-            }
+        let rules = inline_fragments(rules);
+        let token_rules: Vec<&Rule> = rules.iter().filter(|r| !r.is_fragment).collect();
+
+        for (index, rule) in token_rules.iter().enumerate() {
+            self.add_rule(index, rule);
         }
+
+        self.determinize(&token_rules);
         self.states.clone()
     }
 
-    fn add_rule(&mut self, rule: &Rule) {
-        // Start from initial state
-        let mut current_state = 0;
+    fn add_rule(&mut self, index: usize, rule: &Rule) {
+        let start = self.add_nfa_state();
+        self.nfa[0].epsilon.push(start);
 
-        // Process each element in the rule
+        let end = self.add_nfa_state();
         for alt in &rule.alternatives {
+            let alt_start = self.add_nfa_state();
+            self.nfa[start].epsilon.push(alt_start);
+            let mut current = alt_start;
             for element in &alt.elements {
-                current_state = self.add_element(current_state, element);
+                current = self.add_element(current, element);
             }
+            self.nfa[current].epsilon.push(end);
         }
 
         // Mark final state as accepting
-        if let Some(state) = self.states.get_mut(current_state) {
-            state.accepting = Some(rule.name.clone());
+        self.nfa[end].accepting = Some(index);
+
+        let has_non_greedy = rule
+            .alternatives
+            .iter()
+            .any(|alt| alt.elements.iter().any(contains_non_greedy));
+        if has_non_greedy {
+            self.shortest_match_rules.push(start..self.nfa.len());
         }
     }
 
-    fn add_element(&mut self, from_state: usize, element: &Element) -> usize {
+    /// Add the NFA fragment for `element` starting at `from`, returning the
+    /// state reached after matching it.
+    fn add_element(&mut self, from: usize, element: &Element) -> usize {
         match element {
             Element::Terminal { value, .. } | Element::StringLiteral { value, .. } => {
-                let mut current = from_state;
+                let mut current = from;
                 for ch in value.chars() {
-                    current = self.add_transition(current, CharClass::Single(ch));
+                    current = self.add_transition(current, vec![(ch as u32, ch as u32)]);
                 }
                 current
             }
             Element::CharRange { start, end } => {
-                self.add_transition(from_state, CharClass::Range(*start, *end))
+                self.add_transition(from, split_surrogates(*start as u32, *end as u32))
+            }
+            Element::CharClass { .. } => match char_set(element) {
+                Some(set) => self.add_transition(from, set),
+                None => from,
+            },
+            Element::UnicodeClass { negated, category } => {
+                self.add_property_transition(from, category, !negated)
+            }
+            Element::Not { element } => match (element.as_ref(), char_set(element)) {
+                (Element::UnicodeClass { negated, category }, _) => {
+                    self.add_property_transition(from, category, *negated)
+                }
+                (_, Some(set)) => self.add_transition(from, complement(&set)),
+                (_, None) => from,
+            },
+            Element::Wildcard => self.add_transition(from, ALL_CHARS.to_vec()),
+            Element::Optional { element, .. } => {
+                let end = self.add_element(from, element);
+                self.nfa[from].epsilon.push(end);
+                end
+            }
+            Element::ZeroOrMore { element, .. } => {
+                let loop_state = self.add_nfa_state();
+                self.nfa[from].epsilon.push(loop_state);
+                let end = self.add_element(loop_state, element);
+                self.nfa[end].epsilon.push(loop_state);
+                loop_state
+            }
+            Element::OneOrMore { element, .. } => {
+                let loop_state = self.add_nfa_state();
+                self.nfa[from].epsilon.push(loop_state);
+                let end = self.add_element(loop_state, element);
+                self.nfa[end].epsilon.push(loop_state);
+                end
             }
-            Element::Wildcard => self.add_transition(from_state, CharClass::Any),
-            _ => from_state, // Handle other elements as needed
+            Element::Group { alternatives } => {
+                let end = self.add_nfa_state();
+                for alt in alternatives {
+                    let alt_start = self.add_nfa_state();
+                    self.nfa[from].epsilon.push(alt_start);
+                    let mut current = alt_start;
+                    for elem in &alt.elements {
+                        current = self.add_element(current, elem);
+                    }
+                    self.nfa[current].epsilon.push(end);
+                }
+                end
+            }
+            // Left over by `inline_fragments` only when the reference is
+            // cyclic or names no rule, so it can never match
+            Element::RuleRef { .. } => self.add_transition(from, Vec::new()),
+            _ => from, // Handle other elements as needed
         }
     }
 
-    fn add_transition(&mut self, from_state: usize, char_class: CharClass) -> usize {
-        // Check if transition already exists
-        if let Some(state) = self.states.get(from_state)
-            && let Some(&next_state) = state.transitions.get(&char_class) {
-                return next_state;
-            }
+    fn add_nfa_state(&mut self) -> usize {
+        self.nfa.push(NfaState::default());
+        self.nfa.len() - 1
+    }
 
-        // Create new state
-        let new_state_id = self.next_state_id;
-        self.next_state_id += 1;
+    fn add_transition(&mut self, from: usize, set: Vec<(u32, u32)>) -> usize {
+        let to = self.add_nfa_state();
+        self.nfa[from].transitions.push((set, to));
+        to
+    }
 
-        let new_state = DfaState {
-            id: new_state_id,
-            accepting: None,
-            transitions: HashMap::new(),
+    /// Add an edge for chars whose Unicode property `category` is `expected`.
+    /// Unknown properties match nothing.
+    fn add_property_transition(&mut self, from: usize, category: &str, expected: bool) -> usize {
+        let Some((method, test)) = unicode_property(category) else {
+            return self.add_transition(from, Vec::new());
+        };
+        let index = match self.properties.iter().position(|(m, _)| *m == method) {
+            Some(index) => index,
+            None => {
+                self.properties.push((method, test));
+                self.properties.len() - 1
+            }
         };
-        self.states.push(new_state);
+        let to = self.add_nfa_state();
+        self.nfa[from].property_transitions.push((index, expected, to));
+        to
+    }
+
+    /// The property values occurring among the chars of `lo..=hi`.
+    fn property_combinations(&self, lo: u32, hi: u32) -> BTreeSet<Vec<bool>> {
+        if self.properties.is_empty() {
+            return BTreeSet::from([Vec::new()]);
+        }
+        (lo..=hi)
+            .filter_map(char::from_u32)
+            .map(|ch| self.properties.iter().map(|(_, test)| test(ch)).collect())
+            .collect()
+    }
+
+    fn epsilon_closure(&self, seeds: impl IntoIterator<Item = usize>) -> BTreeSet<usize> {
+        let mut closure = BTreeSet::new();
+        let mut stack: Vec<usize> = seeds.into_iter().collect();
+        while let Some(state) = stack.pop() {
+            if closure.insert(state) {
+                stack.extend(self.nfa[state].epsilon.iter().copied());
+            }
+        }
+        closure
+    }
+
+    /// Drop the states of shortest-match rules that have already accepted,
+    /// keeping only their accepting state.
+    fn prune_non_greedy(&self, mut states: BTreeSet<usize>) -> BTreeSet<usize> {
+        for range in &self.shortest_match_rules {
+            let accepted = states
+                .range(range.clone())
+                .any(|s| self.nfa[*s].accepting.is_some());
+            if accepted {
+                states.retain(|s| !range.contains(s) || self.nfa[*s].accepting.is_some());
+            }
+        }
+        states
+    }
+
+    /// Subset construction over the elementary intervals of all transition
+    /// sets, so every DFA edge covers a contiguous code point range.
+    fn determinize(&mut self, token_rules: &[&Rule]) {
+        let mut points = BTreeSet::new();
+        for state in &self.nfa {
+            for (set, _) in &state.transitions {
+                for (lo, hi) in set {
+                    points.insert(*lo);
+                    points.insert(hi + 1);
+                }
+            }
+        }
+        if !self.properties.is_empty() {
+            // Property edges can be taken by any char
+            points.insert(0);
+            points.insert(0x110000);
+        }
+        let points: Vec<u32> = points.into_iter().collect();
+        let intervals: Vec<(u32, u32)> = points.windows(2).map(|w| (w[0], w[1] - 1)).collect();
+        let combinations: Vec<BTreeSet<Vec<bool>>> = intervals
+            .iter()
+            .map(|&(lo, hi)| self.property_combinations(lo, hi))
+            .collect();
+
+        let start = self.epsilon_closure([0]);
+        let mut ids: HashMap<BTreeSet<usize>, usize> = HashMap::new();
+        let mut sets = vec![start.clone()];
+        ids.insert(start, 0);
+
+        self.states.clear();
+        let mut index = 0;
+        while index < sets.len() {
+            let current = sets[index].clone();
+            let accepting = current
+                .iter()
+                .filter_map(|s| self.nfa[*s].accepting)
+                .min()
+                .map(|rule_index| token_rules[rule_index].name.clone());
+
+            // Properties this state has edges for; the others do not matter here
+            let relevant: BTreeSet<usize> = current
+                .iter()
+                .flat_map(|s| self.nfa[*s].property_transitions.iter())
+                .map(|(index, _, _)| *index)
+                .collect();
+
+            let mut edges: Vec<((u32, u32), Guard, usize)> = Vec::new();
+            for (&(lo, hi), combinations) in intervals.iter().zip(&combinations) {
+                // Intervals holding no chars, such as the surrogates, have
+                // no property values and lead nowhere
+                if combinations.is_empty() {
+                    continue;
+                }
+                let mut outcomes: Vec<(Guard, BTreeSet<usize>)> = Vec::new();
+                for values in combinations {
+                    let guard: Guard = relevant
+                        .iter()
+                        .map(|index| (self.properties[*index].0, values[*index]))
+                        .collect();
+                    if outcomes.iter().any(|(seen, _)| *seen == guard) {
+                        continue;
+                    }
+                    let targets: Vec<usize> = current
+                        .iter()
+                        .flat_map(|s| {
+                            let state = &self.nfa[*s];
+                            let by_range = state
+                                .transitions
+                                .iter()
+                                .filter(|(set, _)| set.iter().any(|(a, b)| *a <= lo && lo <= *b))
+                                .map(|(_, to)| *to);
+                            let by_property = state
+                                .property_transitions
+                                .iter()
+                                .filter(|(index, expected, _)| values[*index] == *expected)
+                                .map(|(_, _, to)| *to);
+                            by_range.chain(by_property)
+                        })
+                        .collect();
+                    let target = if targets.is_empty() {
+                        BTreeSet::new()
+                    } else {
+                        self.prune_non_greedy(self.epsilon_closure(targets))
+                    };
+                    outcomes.push((guard, target));
+                }
+
+                // Only guard the edge when the properties change where it leads
+                if outcomes.iter().all(|(_, target)| *target == outcomes[0].1) {
+                    outcomes.truncate(1);
+                    outcomes[0].0.clear();
+                }
+
+                for (guard, target) in outcomes {
+                    if target.is_empty() {
+                        continue;
+                    }
+                    let target_id = match ids.get(&target) {
+                        Some(id) => *id,
+                        None => {
+                            let id = sets.len();
+                            ids.insert(target.clone(), id);
+                            sets.push(target);
+                            id
+                        }
+                    };
+
+                    // Merge with the previous edge when contiguous
+                    match edges.last_mut() {
+                        Some(((_, prev_hi), prev_guard, prev_target))
+                            if *prev_hi + 1 == lo
+                                && *prev_guard == guard
+                                && *prev_target == target_id =>
+                        {
+                            *prev_hi = hi;
+                        }
+                        _ => edges.push(((lo, hi), guard, target_id)),
+                    }
+                }
+            }
+
+            let transitions = edges
+                .into_iter()
+                .filter_map(|((lo, hi), guard, target)| {
+                    let class = to_char_class(lo, hi)?;
+                    if guard.is_empty() {
+                        Some((class, target))
+                    } else {
+                        Some((CharClass::Guarded(Box::new(class), guard), target))
+                    }
+                })
+                .collect();
 
-        // Add transition
-        if let Some(state) = self.states.get_mut(from_state) {
-            state.transitions.insert(char_class, new_state_id);
+            self.states.push(DfaState {
+                id: index,
+                accepting,
+                transitions,
+            });
+            index += 1;
         }
 
-        new_state_id
+        self.next_state_id = self.states.len();
     }
 }
 
@@ -117,29 +435,432 @@ impl Default for DfaBuilder {
     }
 }
 
+/// Default for [`CodeGenConfig::max_inline_depth`](crate::types::CodeGenConfig::max_inline_depth).
+pub const DEFAULT_MAX_INLINE_DEPTH: usize = 32;
+
+/// The longest chain of references [`inline_fragments`] follows from one of
+/// `rules`, starting with that rule, e.g. `["NUMBER", "DIGITS", "DIGIT"]`.
+///
+/// Every step copies the referenced rule into the one before it, so the
+/// inlined rules can grow exponentially with the length of the chain.
+pub fn longest_inline_chain(rules: &[&Rule]) -> Vec<String> {
+    let by_name: HashMap<&str, &Rule> = rules.iter().map(|rule| (rule.name.as_str(), *rule)).collect();
+    let mut memo = HashMap::new();
+    rules
+        .iter()
+        .map(|rule| inline_chain(rule, &by_name, &mut Vec::new(), &mut memo))
+        .max_by_key(Vec::len)
+        .unwrap_or_default()
+}
+
+fn inline_chain<'a>(
+    rule: &'a Rule,
+    rules: &HashMap<&str, &'a Rule>,
+    expanding: &mut Vec<&'a str>,
+    memo: &mut HashMap<&'a str, Vec<String>>,
+) -> Vec<String> {
+    if let Some(chain) = memo.get(rule.name.as_str()) {
+        return chain.clone();
+    }
+    let mut names = Vec::new();
+    for alt in &rule.alternatives {
+        for element in &alt.elements {
+            referenced_rules(element, &mut names);
+        }
+    }
+
+    expanding.push(&rule.name);
+    let mut longest = Vec::new();
+    for name in names {
+        let Some(next) = rules.get(name).copied() else { continue };
+        // Cyclic references are left in place, as `inline_fragments` does
+        if expanding.contains(&next.name.as_str()) {
+            continue;
+        }
+        let chain = inline_chain(next, rules, expanding, memo);
+        if chain.len() > longest.len() {
+            longest = chain;
+        }
+    }
+    expanding.pop();
+
+    longest.insert(0, rule.name.clone());
+    memo.insert(&rule.name, longest.clone());
+    longest
+}
+
+fn referenced_rules<'a>(element: &'a Element, names: &mut Vec<&'a str>) {
+    match element {
+        Element::RuleRef { name, .. } => names.push(name),
+        Element::Optional { element, .. }
+        | Element::ZeroOrMore { element, .. }
+        | Element::OneOrMore { element, .. }
+        | Element::Not { element } => referenced_rules(element, names),
+        Element::Group { alternatives } => {
+            for alt in alternatives {
+                for element in &alt.elements {
+                    referenced_rules(element, names);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Copies of `rules` with every reference to one of them replaced by a group
+/// of the referenced rule's alternatives, so `NUMBER: DIGIT+;` with
+/// `fragment DIGIT: [0-9];` becomes `NUMBER: ([0-9])+;`.
+///
+/// Inlining is transitive. A reference back into a rule that is still being
+/// expanded, as with mutually recursive fragments, is left in place, as is
+/// a reference to a rule not in `rules`.
+pub fn inline_fragments(rules: &[&Rule]) -> Vec<Rule> {
+    let by_name: HashMap<&str, &Rule> = rules.iter().map(|rule| (rule.name.as_str(), *rule)).collect();
+    rules
+        .iter()
+        .map(|rule| {
+            let mut expanding = vec![rule.name.as_str()];
+            let mut inlined = (*rule).clone();
+            for alt in &mut inlined.alternatives {
+                for element in &mut alt.elements {
+                    inline_element(element, &by_name, &mut expanding);
+                }
+            }
+            inlined
+        })
+        .collect()
+}
+
+fn inline_element<'a>(
+    element: &mut Element,
+    rules: &HashMap<&str, &'a Rule>,
+    expanding: &mut Vec<&'a str>,
+) {
+    match element {
+        Element::RuleRef { name, .. } => {
+            let Some(rule) = rules.get(name.as_str()).copied() else {
+                return;
+            };
+            if expanding.contains(&rule.name.as_str()) {
+                return;
+            }
+            expanding.push(&rule.name);
+            let mut alternatives = rule.alternatives.clone();
+            for alt in &mut alternatives {
+                for element in &mut alt.elements {
+                    inline_element(element, rules, expanding);
+                }
+            }
+            expanding.pop();
+            *element = Element::Group { alternatives };
+        }
+        Element::Optional { element, .. }
+        | Element::ZeroOrMore { element, .. }
+        | Element::OneOrMore { element, .. }
+        | Element::Not { element } => inline_element(element, rules, expanding),
+        Element::Group { alternatives } => {
+            for alt in alternatives {
+                for element in &mut alt.elements {
+                    inline_element(element, rules, expanding);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Copies of `rules` whose literals and character sets match letters in
+/// either case, for the rules that are case-insensitive.
+///
+/// A rule is case-insensitive if its own `caseInsensitive` option says so,
+/// or else if `default`, the grammar's option, does; a literal's
+/// `<caseInsensitive=...>` option overrides both. `'select'` becomes
+/// `[sS] [eE] [lL] [eE] [cC] [tT]` and `[a-c]` becomes `[a-cA-C]`, so the
+/// DFA does the folding and matching costs nothing extra.
+pub fn fold_case(rules: &[Rule], default: bool) -> Vec<Rule> {
+    rules
+        .iter()
+        .map(|rule| {
+            let mut folded = rule.clone();
+            let enabled = option_flag(rule.options.get("caseInsensitive"), default);
+            for alt in &mut folded.alternatives {
+                for element in &mut alt.elements {
+                    fold_element(element, enabled);
+                }
+            }
+            folded
+        })
+        .collect()
+}
+
+/// Literals of `grammar`'s parser rules that the lexer matches in either
+/// case: those a case-insensitive lexer rule consists of, and implicit
+/// tokens when the grammar itself is case-insensitive.
+pub fn case_insensitive_literals(grammar: &Grammar) -> HashSet<String> {
+    let default = option_flag(grammar.options.get("caseInsensitive"), false);
+    let mut literals: HashSet<String> = grammar
+        .lexer_rules()
+        .filter(|rule| !rule.is_fragment)
+        .filter_map(|rule| match rule.alternatives.as_slice() {
+            [alt] => match alt.elements.as_slice() {
+                [literal @ Element::StringLiteral { value, .. }] => {
+                    let enabled = option_flag(rule.options.get("caseInsensitive"), default);
+                    option_flag(literal.option("caseInsensitive"), enabled).then(|| value.clone())
+                }
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect();
+    if default {
+        literals.extend(implicit_literal_tokens(grammar).into_iter().map(|(_, literal)| literal));
+    }
+    literals
+}
+
+fn option_flag(value: Option<impl AsRef<str>>, default: bool) -> bool {
+    value.map_or(default, |value| value.as_ref() == "true")
+}
+
+fn fold_element(element: &mut Element, enabled: bool) {
+    match element {
+        Element::StringLiteral { value, .. } => {
+            let value = value.clone();
+            if !option_flag(element.option("caseInsensitive"), enabled)
+                || !value.chars().any(|ch| case_variants(ch).next().is_some())
+            {
+                return;
+            }
+            let mut alt = Alternative::new();
+            for ch in value.chars() {
+                alt.add_element(Element::CharClass {
+                    negated: false,
+                    ranges: fold_ranges(&[(ch, ch)]),
+                });
+            }
+            *element = match alt.elements.as_slice() {
+                [class] => class.clone(),
+                _ => Element::Group { alternatives: vec![alt] },
+            };
+        }
+        Element::CharClass { ranges, .. } if enabled => *ranges = fold_ranges(ranges),
+        Element::CharRange { start, end } if enabled => {
+            *element = Element::CharClass {
+                negated: false,
+                ranges: fold_ranges(&[(*start, *end)]),
+            };
+        }
+        Element::Optional { element, .. }
+        | Element::ZeroOrMore { element, .. }
+        | Element::OneOrMore { element, .. }
+        | Element::Not { element } => fold_element(element, enabled),
+        Element::Group { alternatives } => {
+            for alt in alternatives {
+                for element in &mut alt.elements {
+                    fold_element(element, enabled);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// `ranges` plus the other-case forms of the chars in them.
+fn fold_ranges(ranges: &[(char, char)]) -> Vec<(char, char)> {
+    let variants: BTreeSet<char> = ranges
+        .iter()
+        .flat_map(|&(lo, hi)| lo..=hi)
+        .flat_map(case_variants)
+        .collect();
+    let mut folded = ranges.to_vec();
+    let mut run: Option<(char, char)> = None;
+    for ch in variants {
+        run = match run {
+            Some((lo, hi)) if char::from_u32(hi as u32 + 1) == Some(ch) => Some((lo, ch)),
+            Some(done) => {
+                folded.push(done);
+                Some((ch, ch))
+            }
+            None => Some((ch, ch)),
+        };
+    }
+    folded.extend(run);
+    folded
+}
+
+/// The lowercase and uppercase forms of `ch` that differ from it and are
+/// single chars (so `ß`, uppercased to `SS`, has none).
+fn case_variants(ch: char) -> impl Iterator<Item = char> {
+    let lower: Vec<char> = ch.to_lowercase().collect();
+    let upper: Vec<char> = ch.to_uppercase().collect();
+    [lower, upper]
+        .into_iter()
+        .filter_map(|forms| match forms.as_slice() {
+            [form] => Some(*form),
+            _ => None,
+        })
+        .filter(move |&variant| variant != ch)
+}
+
+/// Whether `element` uses a non-greedy quantifier anywhere inside it.
+fn contains_non_greedy(element: &Element) -> bool {
+    match element {
+        Element::Optional { element, greedy }
+        | Element::ZeroOrMore { element, greedy }
+        | Element::OneOrMore { element, greedy } => !greedy || contains_non_greedy(element),
+        Element::Group { alternatives } => alternatives
+            .iter()
+            .any(|alt| alt.elements.iter().any(contains_non_greedy)),
+        Element::Not { element } => contains_non_greedy(element),
+        _ => false,
+    }
+}
+
+/// Split a code point range so it never spans the surrogate block.
+fn split_surrogates(lo: u32, hi: u32) -> Vec<(u32, u32)> {
+    ALL_CHARS
+        .iter()
+        .filter_map(|(a, b)| {
+            let lo = lo.max(*a);
+            let hi = hi.min(*b);
+            (lo <= hi).then_some((lo, hi))
+        })
+        .collect()
+}
+
+/// The set of chars matched by a single-character element, if it is one.
+///
+/// Covers everything `~` can negate: char classes, ranges, one-char
+/// literals and alternations of these.
+fn char_set(element: &Element) -> Option<Vec<(u32, u32)>> {
+    let set = match element {
+        Element::CharClass { negated, ranges } => {
+            let set = normalize(
+                ranges
+                    .iter()
+                    .flat_map(|(start, end)| split_surrogates(*start as u32, *end as u32))
+                    .collect(),
+            );
+            return Some(if *negated { complement(&set) } else { set });
+        }
+        Element::CharRange { start, end } => split_surrogates(*start as u32, *end as u32),
+        Element::Terminal { value, .. } | Element::StringLiteral { value, .. } => {
+            let mut chars = value.chars();
+            match (chars.next(), chars.next()) {
+                (Some(ch), None) => vec![(ch as u32, ch as u32)],
+                _ => return None,
+            }
+        }
+        Element::Group { alternatives } => {
+            let mut set = Vec::new();
+            for alt in alternatives {
+                match alt.elements.as_slice() {
+                    [single] => set.extend(char_set(single)?),
+                    _ => return None,
+                }
+            }
+            set
+        }
+        Element::Not { element } => complement(&char_set(element)?),
+        _ => return None,
+    };
+    Some(normalize(set))
+}
+
+/// Sort ranges and merge overlapping or adjacent ones.
+fn normalize(mut ranges: Vec<(u32, u32)>) -> Vec<(u32, u32)> {
+    ranges.sort();
+    let mut merged: Vec<(u32, u32)> = Vec::new();
+    for (lo, hi) in ranges {
+        match merged.last_mut() {
+            Some((_, prev_hi)) if lo <= *prev_hi + 1 => *prev_hi = (*prev_hi).max(hi),
+            _ => merged.push((lo, hi)),
+        }
+    }
+    merged
+}
+
+/// All valid chars not covered by the normalized `set`.
+fn complement(set: &[(u32, u32)]) -> Vec<(u32, u32)> {
+    let mut result = Vec::new();
+    let mut next = 0u32;
+    for (lo, hi) in set {
+        if *lo > next {
+            result.extend(split_surrogates(next, lo - 1));
+        }
+        next = hi + 1;
+    }
+    if next <= 0x10FFFF {
+        result.extend(split_surrogates(next, 0x10FFFF));
+    }
+    result
+}
+
+fn to_char_class(lo: u32, hi: u32) -> Option<CharClass> {
+    let start = char::from_u32(lo)?;
+    let end = char::from_u32(hi)?;
+    if start == end {
+        Some(CharClass::Single(start))
+    } else {
+        Some(CharClass::Range(start, end))
+    }
+}
+
+/// Pattern matching the chars of `class`, without its guard.
+fn char_class_pattern(class: &CharClass) -> String {
+    match class {
+        CharClass::Single(ch) => format!("{:?}", ch),
+        CharClass::Range(start, end) => format!("{:?}..={:?}", start, end),
+        CharClass::Any => "_".to_string(),
+        CharClass::Guarded(class, _) => char_class_pattern(class),
+    }
+}
+
+/// Match guard testing the Unicode properties of `ch`, e.g. ` if ch.is_alphabetic()`.
+fn guard_condition(guard: &[(&'static str, bool)]) -> String {
+    let condition = guard
+        .iter()
+        .map(|(method, expected)| format!("{}ch.{}()", if *expected { "" } else { "!" }, method))
+        .collect::<Vec<_>>()
+        .join(" && ");
+    format!(" if {}", condition)
+}
+
 /// Generate Rust code for DFA as match statements
+///
+/// The emitted `next_token_dfa` runs the automaton as far as it can and
+/// then rewinds to the end of the longest accepted prefix.
 pub fn generate_dfa_match(states: &[DfaState]) -> String {
+    generate_named_dfa_match(states, "next_token_dfa")
+}
+
+/// Like [`generate_dfa_match`], naming the emitted method `fn_name`.
+///
+/// Lexers with modes emit one of these per mode.
+pub fn generate_named_dfa_match(states: &[DfaState], fn_name: &str) -> String {
     let mut code = String::new();
 
-    code.push_str("    fn next_token_dfa(&mut self) -> Option<Token> {\n");
+    code.push_str(&format!("    fn {}(&mut self) -> Option<Token> {{\n", fn_name));
+    code.push_str("        let token_start = self.position;\n");
     code.push_str("        let mut state = 0;\n");
-    code.push_str("        let mut token_start = self.position;\n");
-    code.push_str("        let mut last_accepting: Option<(usize, &str)> = None;\n\n");
+    code.push_str("        let mut last_accepting: Option<(usize, TokenKind)> = None;\n\n");
 
     code.push_str("        loop {\n");
-    code.push_str("            // Check if current state is accepting\n");
-    code.push_str("            match state {\n");
+    code.push_str("            // Remember the longest non-empty match so far\n");
+    code.push_str("            if self.position > token_start {\n");
+    code.push_str("                match state {\n");
 
     for state in states {
         if let Some(token_name) = &state.accepting {
             code.push_str(&format!(
-                "                {} => last_accepting = Some((self.position, \"{}\")),\n",
+                "                    {} => last_accepting = Some((self.position, TokenKind::{})),\n",
                 state.id, token_name
             ));
         }
     }
 
-    code.push_str("                _ => {}\n");
+    code.push_str("                    _ => {}\n");
+    code.push_str("                }\n");
     code.push_str("            }\n\n");
 
     code.push_str("            // Get next character\n");
@@ -152,21 +873,19 @@ pub fn generate_dfa_match(states: &[DfaState]) -> String {
     code.push_str("            state = match (state, ch) {\n");
 
     for state in states {
-        for (char_class, next_state) in &state.transitions {
-            let pattern = match char_class {
-                CharClass::Single(ch) => format!("'{}'", ch.escape_default()),
-                CharClass::Range(start, end) => {
-                    format!(
-                        "'{}' ..= '{}'",
-                        start.escape_default(),
-                        end.escape_default()
-                    )
-                }
-                CharClass::Any => "_".to_string(),
+        let mut transitions: Vec<_> = state.transitions.iter().collect();
+        transitions.sort();
+        for (char_class, next_state) in transitions {
+            let (pattern, guard) = match char_class {
+                CharClass::Guarded(class, guard) => (class.as_ref(), guard_condition(guard)),
+                class => (class, String::new()),
             };
             code.push_str(&format!(
-                "                ({}, {}) => {},\n",
-                state.id, pattern, next_state
+                "                ({}, {}){} => {},\n",
+                state.id,
+                char_class_pattern(pattern),
+                guard,
+                next_state
             ));
         }
     }
@@ -176,45 +895,381 @@ pub fn generate_dfa_match(states: &[DfaState]) -> String {
     code.push_str("            self.position += 1;\n");
     code.push_str("        }\n\n");
 
-    code.push_str("        // Return token if we found an accepting state\n");
-    code.push_str("        if let Some((end_pos, token_name)) = last_accepting {\n");
+    code.push_str("        // Rewind to the end of the longest match\n");
+    code.push_str("        match last_accepting {\n");
+    code.push_str("            Some((end_pos, kind)) => {\n");
+    code.push_str("                self.position = end_pos;\n");
     code.push_str(
-        "            let text: String = self.input[token_start..end_pos].iter().collect();\n",
+        "                let text: String = self.input[token_start..end_pos].iter().collect();\n",
     );
-    code.push_str("            Some(Token {\n");
-    code.push_str("                position: token_start,\n");
-    code.push_str("                kind: match token_name {\n");
+    code.push_str("                Some(self.make_token(kind, text, token_start, end_pos))\n");
+    code.push_str("            }\n");
+    code.push_str("            None => {\n");
+    code.push_str("                self.position = token_start;\n");
+    code.push_str("                None\n");
+    code.push_str("            }\n");
+    code.push_str("        }\n");
+    code.push_str("    }\n");
+
+    code
+}
 
-    // Generate token kind matching
-    let token_names: HashSet<String> = states.iter().filter_map(|s| s.accepting.clone()).collect();
+/// The grammar's token rules as the lexer sees them: an implicit token for
+/// each literal used in parser rules but not defined by a lexer rule,
+/// followed by the lexer rules, fragments included, with case folded where
+/// `caseInsensitive` asks for it.
+pub fn token_rules(grammar: &Grammar) -> Vec<Rule> {
+    let mut rules: Vec<Rule> = implicit_literal_tokens(grammar)
+        .into_iter()
+        .map(|(name, literal)| {
+            let mut rule = Rule::lexer_rule(name);
+            let mut alt = Alternative::new();
+            alt.add_element(Element::string_literal(literal));
+            rule.add_alternative(alt);
+            rule
+        })
+        .collect();
+    rules.extend(grammar.lexer_rules().cloned());
+    let case_insensitive = grammar.options.get("caseInsensitive").is_some_and(|v| v == "true");
+    fold_case(&rules, case_insensitive)
+}
 
-    for token_name in token_names {
-        code.push_str(&format!(
-            "                    \"{}\" => TokenKind::{},\n",
-            token_name, token_name
-        ));
+/// `rules` split by lexer mode, `DEFAULT_MODE` first and then the named
+/// modes by name.
+///
+/// Token rules declared before any `mode` section, implicit literal tokens
+/// included, belong to `DEFAULT_MODE`. Every mode sees all fragments,
+/// wherever they are declared.
+pub fn rules_by_mode<'a>(grammar: &'a Grammar, rules: &[&'a Rule]) -> Vec<(&'a str, Vec<&'a Rule>)> {
+    let in_named_mode: HashSet<&str> = grammar
+        .lexer_modes
+        .values()
+        .flatten()
+        .map(String::as_str)
+        .collect();
+    let mut mode_names: Vec<&String> = grammar.lexer_modes.keys().collect();
+    mode_names.sort();
+
+    let mut modes: Vec<(&str, Vec<&Rule>)> = vec![(
+        "DEFAULT_MODE",
+        rules
+            .iter()
+            .copied()
+            .filter(|rule| rule.is_fragment || !in_named_mode.contains(rule.name.as_str()))
+            .collect(),
+    )];
+    for name in mode_names {
+        let members = &grammar.lexer_modes[name];
+        let mode_rules = rules
+            .iter()
+            .copied()
+            .filter(|rule| rule.is_fragment || members.contains(&rule.name))
+            .collect();
+        modes.push((name.as_str(), mode_rules));
     }
+    modes
+}
 
-    code.push_str("                    _ => TokenKind::Eof,\n");
-    code.push_str("                },\n");
-    code.push_str("                text,\n");
-    code.push_str("            })\n");
-    code.push_str("        } else {\n");
-    code.push_str("            None\n");
-    code.push_str("        }\n");
-    code.push_str("    }\n");
+/// The whitespace skipped between tokens of the default mode: spaces,
+/// tabs and line breaks, except those a token of that mode names as its
+/// first character, as in `NL: '\n';` or `WS: [ \t\r\n]+;`, which are
+/// left for the token to match. Sets reaching past the control characters
+/// and space, such as `.`, `~["]` or `\P{Alnum}`, do not stop whitespace
+/// from being skipped.
+pub fn skipped_whitespace(grammar: &Grammar, token_rules: &[Rule]) -> Vec<char> {
+    let rules: Vec<&Rule> = token_rules.iter().collect();
+    let modes = rules_by_mode(grammar, &rules);
+    let states = DfaBuilder::new().build_from_rules(&modes[0].1);
+    let names = |class: &CharClass, c: char| match class {
+        CharClass::Single(_) => class.contains(c),
+        CharClass::Range(_, last) => *last <= ' ' && class.contains(c),
+        CharClass::Any | CharClass::Guarded(..) => false,
+    };
+    [' ', '\t', '\r', '\n']
+        .into_iter()
+        .filter(|&c| !states[0].transitions.keys().any(|class| names(class, c)))
+        .collect()
+}
 
-    code
+/// Run the DFA over the start of `input` the way the generated lexer does.
+///
+/// Returns the length in characters of the longest non-empty match and the
+/// token it is for, or `None` when no token matches. Useful for checking a
+/// grammar's tokens without generating and compiling a lexer.
+pub fn longest_match<'a>(states: &'a [DfaState], input: &str) -> Option<(usize, &'a str)> {
+    let mut state = 0;
+    let mut last = None;
+    for (i, ch) in input.chars().enumerate() {
+        let next = states[state].transitions.iter().find(|(class, _)| class.contains(ch));
+        match next {
+            Some((_, target)) => state = *target,
+            None => break,
+        }
+        if let Some(name) = &states[state].accepting {
+            last = Some((i + 1, name.as_str()));
+        }
+    }
+    last
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::ast::Alternative;
+
+    fn lexer_rule(name: &str, element: Element) -> Rule {
+        let mut rule = Rule::lexer_rule(name.to_string());
+        let mut alt = Alternative::new();
+        alt.add_element(element);
+        rule.add_alternative(alt);
+        rule
+    }
 
     #[test]
     fn test_dfa_builder_simple() {
-        let mut builder = DfaBuilder::new();
+        let builder = DfaBuilder::new();
         assert_eq!(builder.states.len(), 1);
         assert_eq!(builder.next_state_id, 1);
     }
+
+    #[test]
+    fn test_dfa_char_class_repetition() {
+        let number = lexer_rule(
+            "NUMBER",
+            Element::one_or_more(Element::CharClass {
+                negated: false,
+                ranges: vec![('0', '9')],
+            }),
+        );
+        let states = DfaBuilder::new().build_from_rules(&[&number]);
+
+        assert_eq!(longest_match(&states, "123+4"), Some((3, "NUMBER")));
+        assert_eq!(longest_match(&states, "+4"), None);
+    }
+
+    #[test]
+    fn test_dfa_wildcard_matches_one_char() {
+        let any = lexer_rule("ANY", Element::Wildcard);
+        let states = DfaBuilder::new().build_from_rules(&[&any]);
+
+        assert_eq!(longest_match(&states, "ab"), Some((1, "ANY")));
+        assert_eq!(longest_match(&states, "\n"), Some((1, "ANY")));
+        assert_eq!(longest_match(&states, "\u{1F600}x"), Some((1, "ANY")));
+        assert_eq!(longest_match(&states, ""), None);
+    }
+
+    #[test]
+    fn test_dfa_fold_case() {
+        let select = lexer_rule("SELECT", Element::string_literal("select".to_string()));
+        let mut id = lexer_rule(
+            "ID",
+            Element::one_or_more(Element::CharClass {
+                negated: false,
+                ranges: vec![('a', 'z')],
+            }),
+        );
+        id.options.insert("caseInsensitive".to_string(), "false".to_string());
+        let not = lexer_rule(
+            "NOT",
+            Element::Not {
+                element: Box::new(Element::string_literal("x".to_string())),
+            },
+        );
+        let rules = fold_case(&[select, id, not], true);
+        let rules: Vec<&Rule> = rules.iter().collect();
+        let states = DfaBuilder::new().build_from_rules(&rules);
+
+        assert_eq!(longest_match(&states, "SeLeCt"), Some((6, "SELECT")));
+        assert_eq!(longest_match(&states, "select"), Some((6, "SELECT")));
+        assert_eq!(longest_match(&states, "Abc"), Some((1, "NOT")));
+        assert_eq!(longest_match(&states, "abc"), Some((3, "ID")));
+        assert_eq!(longest_match(&states, "X"), None);
+        assert_eq!(fold_ranges(&[('a', 'c'), ('0', '9')]), vec![('a', 'c'), ('0', '9'), ('A', 'C')]);
+    }
+
+    #[test]
+    fn test_dfa_inlines_fragments() {
+        let digit_class = Element::CharClass {
+            negated: false,
+            ranges: vec![('0', '9')],
+        };
+        let mut digit = lexer_rule("DIGIT", digit_class);
+        digit.is_fragment = true;
+        let number = lexer_rule("NUMBER", Element::one_or_more(Element::rule_ref("DIGIT".to_string())));
+        // Mutually recursive fragments are left unexpanded and match nothing
+        let mut a = lexer_rule("A", Element::rule_ref("B".to_string()));
+        a.is_fragment = true;
+        let mut b = lexer_rule("B", Element::rule_ref("A".to_string()));
+        b.is_fragment = true;
+        let loops = lexer_rule("LOOP", Element::rule_ref("A".to_string()));
+
+        let states = DfaBuilder::new().build_from_rules(&[&number, &digit, &a, &b, &loops]);
+
+        assert_eq!(longest_match(&states, "123+4"), Some((3, "NUMBER")));
+        assert_eq!(longest_match(&states, "x"), None);
+        assert_eq!(longest_match(&states, ""), None);
+    }
+
+    #[test]
+    fn test_longest_inline_chain() {
+        let mut digit = lexer_rule("DIGIT", Element::CharRange { start: '0', end: '9' });
+        digit.is_fragment = true;
+        let mut digits = lexer_rule("DIGITS", Element::one_or_more(Element::rule_ref("DIGIT".to_string())));
+        digits.is_fragment = true;
+        let number = lexer_rule("NUMBER", Element::rule_ref("DIGITS".to_string()));
+        // The cycle back to A is not followed
+        let mut a = lexer_rule("A", Element::rule_ref("B".to_string()));
+        a.is_fragment = true;
+        let mut b = lexer_rule("B", Element::rule_ref("A".to_string()));
+        b.is_fragment = true;
+
+        assert_eq!(
+            longest_inline_chain(&[&digit, &number, &digits, &a, &b]),
+            vec!["NUMBER", "DIGITS", "DIGIT"]
+        );
+        assert_eq!(longest_inline_chain(&[&a, &b]), vec!["A", "B"]);
+        assert!(longest_inline_chain(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_dfa_longest_match_then_declaration_order() {
+        let kw_if = lexer_rule("IF", Element::string_literal("if".to_string()));
+        let id = lexer_rule(
+            "ID",
+            Element::one_or_more(Element::CharClass {
+                negated: false,
+                ranges: vec![('a', 'z')],
+            }),
+        );
+        let states = DfaBuilder::new().build_from_rules(&[&kw_if, &id]);
+
+        assert_eq!(longest_match(&states, "if "), Some((2, "IF")));
+        assert_eq!(longest_match(&states, "iffy"), Some((4, "ID")));
+    }
+
+    #[test]
+    fn test_combined_dfa_agrees_with_rules_tried_in_order() {
+        let mut rules: Vec<Rule> = (0..50)
+            .map(|i| lexer_rule(&format!("KW{}", i), Element::string_literal(format!("kw{}x", i))))
+            .collect();
+        rules.push(lexer_rule(
+            "ID",
+            Element::one_or_more(Element::CharClass {
+                negated: false,
+                ranges: vec![('a', 'z'), ('0', '9')],
+            }),
+        ));
+        let refs: Vec<&Rule> = rules.iter().collect();
+        let combined = DfaBuilder::new().build_from_rules(&refs);
+        let per_rule: Vec<Vec<DfaState>> = refs
+            .iter()
+            .map(|rule| DfaBuilder::new().build_from_rules(&[rule]))
+            .collect();
+
+        for input in ["kw7x", "kw42x", "kw4", "kw49xy", "x"] {
+            // Longest match across rules, earliest rule on ties
+            let mut expected: Option<(usize, &str)> = None;
+            for states in &per_rule {
+                if let Some(found) = longest_match(states, input)
+                    && expected.is_none_or(|(len, _)| found.0 > len)
+                {
+                    expected = Some(found);
+                }
+            }
+            assert_eq!(longest_match(&combined, input), expected, "{}", input);
+        }
+        assert_eq!(longest_match(&combined, "kw42x"), Some((5, "KW42")));
+        assert_eq!(longest_match(&combined, "kw49xy"), Some((6, "ID")));
+    }
+
+    #[test]
+    fn test_dfa_non_greedy_stops_at_first_match() {
+        let comment = |greedy: bool| {
+            let mut rule = Rule::lexer_rule("COMMENT".to_string());
+            let mut alt = Alternative::new();
+            alt.add_element(Element::string_literal("/*".to_string()));
+            alt.add_element(if greedy {
+                Element::zero_or_more(Element::Wildcard)
+            } else {
+                Element::zero_or_more_non_greedy(Element::Wildcard)
+            });
+            alt.add_element(Element::string_literal("*/".to_string()));
+            rule.add_alternative(alt);
+            rule
+        };
+
+        let greedy = comment(true);
+        let states = DfaBuilder::new().build_from_rules(&[&greedy]);
+        assert_eq!(longest_match(&states, "/* a */ /* b */"), Some((15, "COMMENT")));
+
+        let non_greedy = comment(false);
+        let states = DfaBuilder::new().build_from_rules(&[&non_greedy]);
+        assert_eq!(longest_match(&states, "/* a */ /* b */"), Some((7, "COMMENT")));
+        assert_eq!(longest_match(&states, "/**/"), Some((4, "COMMENT")));
+    }
+
+    #[test]
+    fn test_dfa_unicode_property_overlapping_range() {
+        // A single 'y' ties and goes to XYZ, declared first; longer runs are LETTERs
+        let xyz = lexer_rule(
+            "XYZ",
+            Element::CharClass {
+                negated: false,
+                ranges: vec![('x', 'z')],
+            },
+        );
+        let letter = lexer_rule("LETTER", Element::one_or_more(Element::unicode_class("L".to_string())));
+        let states = DfaBuilder::new().build_from_rules(&[&xyz, &letter]);
+
+        assert_eq!(longest_match(&states, "y"), Some((1, "XYZ")));
+        assert_eq!(longest_match(&states, "yé"), Some((2, "LETTER")));
+        assert_eq!(longest_match(&states, "Ωmega1"), Some((5, "LETTER")));
+        assert_eq!(longest_match(&states, "1"), None);
+
+        let code = generate_dfa_match(&states);
+        assert!(code.contains(" if ch.is_alphabetic() =>"));
+    }
+
+    #[test]
+    fn test_dfa_unicode_property_with_wildcard_and_negated_set() {
+        // `.` and `~[...]` split out the surrogate gap, which holds no chars
+        let letters = lexer_rule("LETTERS", Element::one_or_more(Element::unicode_class("L".to_string())));
+        let any = lexer_rule("ANY", Element::Wildcard);
+        let states = DfaBuilder::new().build_from_rules(&[&letters, &any]);
+        assert_eq!(longest_match(&states, "héllo!"), Some((5, "LETTERS")));
+        assert_eq!(longest_match(&states, "\u{E000}"), Some((1, "ANY")));
+
+        let not_a = lexer_rule(
+            "S",
+            Element::CharClass {
+                negated: true,
+                ranges: vec![('a', 'a')],
+            },
+        );
+        let letter = lexer_rule("LETTER", Element::unicode_class("L".to_string()));
+        let states = DfaBuilder::new().build_from_rules(&[&not_a, &letter]);
+        assert_eq!(longest_match(&states, "b"), Some((1, "S")));
+        assert_eq!(longest_match(&states, "a"), Some((1, "LETTER")));
+        assert_eq!(longest_match(&states, "\u{FFFF}"), Some((1, "S")));
+    }
+
+    #[test]
+    fn test_skipped_whitespace_leaves_chars_tokens_start_with() {
+        use crate::parser::GrammarParser;
+        use crate::traits::GrammarParser as GrammarParserTrait;
+
+        let skipped = |source: &str| {
+            let grammar = GrammarParser::new().parse_string(source, "test.g4").unwrap();
+            skipped_whitespace(&grammar, &token_rules(&grammar))
+        };
+
+        assert_eq!(skipped("grammar T; a: ID; ID: [a-z]+;"), vec![' ', '\t', '\r', '\n']);
+        assert_eq!(skipped("grammar T; a: ID; ID: [a-z]+; NL: [\\t\\n]+;"), vec![' ', '\r']);
+        assert!(skipped("grammar T; a: ID; ID: [a-z]+; WS: [ \\t\\r\\n]+ -> channel(HIDDEN);").is_empty());
+        // Sets past the whitespace do not count
+        assert_eq!(
+            skipped("grammar T; a: ID; ID: [a-z]+; S: ~[\"]; ANY: .;"),
+            vec![' ', '\t', '\r', '\n']
+        );
+    }
 }
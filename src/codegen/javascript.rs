@@ -1,7 +1,11 @@
 //! JavaScript code generator.
 
-use crate::ast::{Grammar, Rule};
-use crate::{types::CodeGenConfig, CodeGenerator as CodeGeneratorTrait, Result};
+use super::actions::translate_action_element;
+use super::common::{single_quoted, unlabeled_references, Emitter};
+use super::python::element_description;
+use crate::ast::{Alternative, Element, Grammar, Rule};
+use crate::types::{CodeGenConfig, Visibility};
+use crate::{CodeGenerator as CodeGeneratorTrait, Error, Result};
 
 /// JavaScript code generator.
 pub struct JavaScriptCodeGenerator;
@@ -11,17 +15,19 @@ impl JavaScriptCodeGenerator {
         Self
     }
 
-    fn generate_lexer(&self, grammar: &Grammar) -> String {
+    fn generate_lexer(&self, grammar: &Grammar, export: &str) -> String {
         let mut code = String::new();
         let class_name = format!("{}Lexer", grammar.name);
 
         code.push_str("/**\n");
         code.push_str(" * Lexer with DFA-based tokenization and error recovery.\n");
         code.push_str(" */\n");
-        code.push_str(&format!("class {} {{\n", class_name));
+        code.push_str(&format!("{}class {} {{\n", export, class_name));
         code.push_str("  constructor(input) {\n");
         code.push_str("    this.input = input;\n");
         code.push_str("    this.position = 0;\n");
+        code.push_str("    // Last located position, so locating the next token walks on from it\n");
+        code.push_str("    this._cursor = { index: 0, line: 1, column: 0, byte: 0 };\n");
         code.push_str("  }\n\n");
 
         code.push_str("  /**\n");
@@ -35,13 +41,12 @@ impl JavaScriptCodeGenerator {
         code.push_str("    const startPos = this.position;\n\n");
         code.push_str("    // Check for EOF\n");
         code.push_str("    if (this.position >= this.input.length) {\n");
-        code.push_str("      return new Token(TokenKind.EOF, '', startPos);\n");
+        code.push_str("      return this._place(new Token(TokenKind.EOF, '', startPos), startPos, startPos);\n");
         code.push_str("    }\n\n");
         code.push_str("    // Use DFA for tokenization\n");
         code.push_str("    const token = this._nextTokenDfa();\n");
         code.push_str("    if (token) {\n");
-        code.push_str("      token.position = startPos;\n");
-        code.push_str("      return token;\n");
+        code.push_str("      return this._place(token, startPos, this.position);\n");
         code.push_str("    }\n\n");
         code.push_str("    // Error recovery: skip invalid character\n");
         code.push_str("    const invalidChar = this.input[this.position];\n");
@@ -55,34 +60,86 @@ impl JavaScriptCodeGenerator {
         code.push_str("  }\n\n");
 
         code.push_str("  /**\n");
-        code.push_str("   * Tokenize all input and collect errors.\n");
-        code.push_str("   * @returns {{tokens: Token[], errors: ParseError[]}}\n");
+        code.push_str("   * Yield tokens one at a time, ending with the EOF token.\n");
+        code.push_str("   * Errors are yielded rather than thrown, so lexing goes on after them.\n");
+        code.push_str("   * @yields {Token|ParseError}\n");
         code.push_str("   */\n");
-        code.push_str("  tokenizeAll() {\n");
-        code.push_str("    const tokens = [];\n");
-        code.push_str("    const errors = [];\n\n");
+        code.push_str("  *[Symbol.iterator]() {\n");
         code.push_str("    while (true) {\n");
+        code.push_str("      let token;\n");
         code.push_str("      try {\n");
-        code.push_str("        const token = this.nextToken();\n");
-        code.push_str("        tokens.push(token);\n");
-        code.push_str("        if (token.kind === TokenKind.EOF) {\n");
-        code.push_str("          break;\n");
-        code.push_str("        }\n");
+        code.push_str("        token = this.nextToken();\n");
         code.push_str("      } catch (err) {\n");
-        code.push_str("        if (err instanceof ParseError) {\n");
-        code.push_str("          errors.push(err);\n");
-        code.push_str("          // Continue tokenizing after error\n");
-        code.push_str("          if (this.position >= this.input.length) {\n");
-        code.push_str("            break;\n");
-        code.push_str("          }\n");
-        code.push_str("        } else {\n");
+        code.push_str("        if (!(err instanceof ParseError)) {\n");
         code.push_str("          throw err;\n");
         code.push_str("        }\n");
+        code.push_str("        yield err;\n");
+        code.push_str("        continue;\n");
         code.push_str("      }\n");
-        code.push_str("    }\n\n");
+        code.push_str("      yield token;\n");
+        code.push_str("      if (token.kind === TokenKind.EOF) {\n");
+        code.push_str("        return;\n");
+        code.push_str("      }\n");
+        code.push_str("    }\n");
+        code.push_str("  }\n\n");
+
+        code.push_str("  /**\n");
+        code.push_str("   * Tokenize all input and collect errors, draining the lexer.\n");
+        code.push_str("   * @returns {{tokens: Token[], errors: ParseError[]}}\n");
+        code.push_str("   */\n");
+        code.push_str("  tokenizeAll() {\n");
+        code.push_str("    const tokens = [];\n");
+        code.push_str("    const errors = [];\n");
+        code.push_str("    for (const item of this) {\n");
+        code.push_str("      if (item instanceof ParseError) {\n");
+        code.push_str("        errors.push(item);\n");
+        code.push_str("      } else {\n");
+        code.push_str("        tokens.push(item);\n");
+        code.push_str("      }\n");
+        code.push_str("    }\n");
         code.push_str("    return { tokens, errors };\n");
         code.push_str("  }\n\n");
 
+        code.push_str("  /**\n");
+        code.push_str("   * Set the position, line, column and UTF-8 byte offsets of a token\n");
+        code.push_str("   * spanning the code units `start` to `end`.\n");
+        code.push_str("   */\n");
+        code.push_str("  _place(token, start, end) {\n");
+        code.push_str("    const { line, column, byte } = this._locate(start);\n");
+        code.push_str("    token.position = start;\n");
+        code.push_str("    token.line = line;\n");
+        code.push_str("    token.column = column;\n");
+        code.push_str("    token.start = byte;\n");
+        code.push_str("    token.stop = this._locate(end).byte;\n");
+        code.push_str("    return token;\n");
+        code.push_str("  }\n\n");
+
+        code.push_str("  /**\n");
+        code.push_str("   * Line, column and UTF-8 byte offset of the code unit at `index`.\n");
+        code.push_str("   * Columns count code points, so a surrogate pair is one column. Walks\n");
+        code.push_str("   * on from the last located position; an earlier `index` starts over\n");
+        code.push_str("   * from the beginning of the input.\n");
+        code.push_str("   */\n");
+        code.push_str("  _locate(index) {\n");
+        code.push_str("    if (index < this._cursor.index) {\n");
+        code.push_str("      this._cursor = { index: 0, line: 1, column: 0, byte: 0 };\n");
+        code.push_str("    }\n");
+        code.push_str("    const cursor = { ...this._cursor };\n");
+        code.push_str("    while (cursor.index < index) {\n");
+        code.push_str("      const code = this.input.codePointAt(cursor.index);\n");
+        code.push_str("      cursor.index += code > 0xffff ? 2 : 1;\n");
+        code.push_str("      cursor.byte += code < 0x80 ? 1 : code < 0x800 ? 2 : code < 0x10000 ? 3 : 4;\n");
+        code.push_str("      if (code === 0x0a) {\n");
+        code.push_str("        cursor.line++;\n");
+        code.push_str("        cursor.column = 0;\n");
+        code.push_str("      } else {\n");
+        code.push_str("        cursor.column++;\n");
+        code.push_str("      }\n");
+        code.push_str("    }\n");
+        code.push_str("    this._cursor = cursor;\n");
+        code.push_str("    return cursor;\n");
+        code.push_str("  }\n\n");
+
         code.push_str("  _skipWhitespace() {\n");
         code.push_str("    while (this.position < this.input.length) {\n");
         code.push_str("      const ch = this.input[this.position];\n");
@@ -103,12 +160,21 @@ impl JavaScriptCodeGenerator {
         code
     }
 
-    fn generate_parser(&self, grammar: &Grammar) -> String {
+    fn generate_parser(&self, grammar: &Grammar, typescript: bool, export: &str) -> Result<String> {
         let mut code = String::new();
         let class_name = format!("{}Parser", grammar.name);
 
-        code.push_str(&format!("class {} {{\n", class_name));
+        // `superClass` must be in scope, e.g. imported in `@header`, and
+        // take no constructor arguments
+        let super_class = grammar.options.get("superClass");
+        match super_class {
+            Some(base) => code.push_str(&format!("{}class {} extends {} {{\n", export, class_name, base)),
+            None => code.push_str(&format!("{}class {} {{\n", export, class_name)),
+        }
         code.push_str("  constructor(tokens) {\n");
+        if super_class.is_some() {
+            code.push_str("    super();\n");
+        }
         code.push_str("    this.tokens = tokens;\n");
         code.push_str("    this.position = 0;\n");
 
@@ -124,20 +190,31 @@ impl JavaScriptCodeGenerator {
 
         // Generate parse methods for each rule
         for rule in grammar.parser_rules() {
-            code.push_str(&self.generate_rule_method(rule));
+            code.push_str(&self.generate_rule_method(rule, typescript)?);
         }
 
         code.push_str("}\n\n");
-        code
+        Ok(code)
     }
 
-    fn generate_rule_method(&self, rule: &Rule) -> String {
+    fn generate_rule_method(&self, rule: &Rule, typescript: bool) -> Result<String> {
         let mut code = String::new();
         let method_name = to_camel_case(&rule.name);
 
         // Generate JSDoc comment
         code.push_str("  /**\n");
-        code.push_str(&format!("   * Parse {} rule.\n", rule.name));
+        match &rule.doc {
+            Some(doc) => {
+                for line in doc.lines() {
+                    if line.is_empty() {
+                        code.push_str("   *\n");
+                    } else {
+                        code.push_str(&format!("   * {}\n", line));
+                    }
+                }
+            }
+            None => code.push_str(&format!("   * Parse {} rule.\n", rule.name)),
+        }
         if !rule.arguments.is_empty() {
             for arg in &rule.arguments {
                 let type_str = arg
@@ -192,11 +269,11 @@ impl JavaScriptCodeGenerator {
         // Generate rule body
         if rule.alternatives.is_empty() {
             code.push_str("    // Empty rule\n");
-            code.push_str(&format!("    return new {}Node();\n", to_pascal_case(&rule.name)));
+            code.push_str(&format!("    return {};\n", node_expression(rule, "[]", &[], typescript)));
         } else if rule.alternatives.len() == 1 {
             // Single alternative
             let alt = &rule.alternatives[0];
-            code.push_str(&self.generate_javascript_alternative(alt, rule));
+            code.push_str(&self.generate_javascript_alternative(alt, rule, typescript)?);
         } else {
             // Multiple alternatives - try each in order
             code.push_str("    // Try alternatives\n");
@@ -207,7 +284,7 @@ impl JavaScriptCodeGenerator {
                     code.push_str("    this.position = startPos;\n");
                 }
                 code.push_str("    try {\n");
-                let alt_code = self.generate_javascript_alternative(alt, rule);
+                let alt_code = self.generate_javascript_alternative(alt, rule, typescript)?;
                 // Indent the alternative code
                 for line in alt_code.lines() {
                     if !line.trim().is_empty() {
@@ -225,20 +302,43 @@ impl JavaScriptCodeGenerator {
         }
         code.push_str("  }\n\n");
 
-        code
+        Ok(code)
     }
 
-    fn generate_token_types(&self, grammar: &Grammar) -> String {
+    /// Token kinds as a frozen object, or a string `enum` for TypeScript.
+    ///
+    /// Each kind's value is its own name, so `TokenKind[name]` and the
+    /// value itself map between the two in either flavour.
+    fn generate_token_types(&self, grammar: &Grammar, config: &CodeGenConfig, export: &str) -> String {
         let mut code = String::new();
 
-        code.push_str("const TokenKind = {\n");
-        for rule in grammar.lexer_rules() {
-            if !rule.is_fragment {
-                code.push_str(&format!("  {}: '{}',\n", rule.name, rule.name));
+        let mut names: Vec<&str> = grammar
+            .declared_tokens
+            .iter()
+            .filter(|name| grammar.get_rule(name).is_none())
+            .map(String::as_str)
+            .collect();
+        names.extend(
+            grammar
+                .lexer_rules()
+                .filter(|rule| !rule.is_fragment)
+                .map(|rule| rule.name.as_str()),
+        );
+        names.push("EOF");
+
+        if is_typescript(config) {
+            code.push_str(&format!("{}enum TokenKind {{\n", export));
+            for name in &names {
+                code.push_str(&format!("  {} = '{}',\n", name, name));
             }
+            code.push_str("}\n\n");
+        } else {
+            code.push_str(&format!("{}const TokenKind = Object.freeze({{\n", export));
+            for name in &names {
+                code.push_str(&format!("  {}: '{}',\n", name, name));
+            }
+            code.push_str("});\n\n");
         }
-        code.push_str("  EOF: 'EOF'\n");
-        code.push_str("};\n\n");
 
         code
     }
@@ -250,239 +350,102 @@ impl JavaScriptCodeGenerator {
         
         for rule in grammar.parser_rules() {
             let class_name = to_pascal_case(&rule.name);
-            let fields = self.extract_labeled_fields(rule);
-            
+            let mut params = vec!["children".to_string()];
+            params.extend(node_fields(rule).into_iter().map(|(name, _, _)| name));
+
             code.push_str("/**\n");
             code.push_str(&format!(" * AST node for {} rule.\n", rule.name));
             code.push_str(" */\n");
             code.push_str(&format!("class {}Node {{\n", class_name));
-            
-            if fields.is_empty() {
-                code.push_str("  constructor() {}\n");
-            } else {
-                code.push_str("  constructor(");
-                for (i, (field_name, _, _)) in fields.iter().enumerate() {
-                    if i > 0 {
-                        code.push_str(", ");
-                    }
-                    code.push_str(field_name);
-                }
-                code.push_str(") {\n");
-                for (field_name, _, _) in &fields {
-                    code.push_str(&format!("    this.{} = {};\n", field_name, field_name));
-                }
-                code.push_str("  }\n");
+            code.push_str(&format!("  constructor({}) {{\n", params.join(", ")));
+            for param in &params {
+                code.push_str(&format!("    this.{} = {};\n", param, param));
             }
+            code.push_str("  }\n");
             code.push_str("}\n\n");
         }
 
         code
     }
 
-    fn extract_labeled_fields(&self, rule: &Rule) -> Vec<(String, String, bool)> {
-        let mut fields = Vec::new();
-        let mut seen_labels = std::collections::HashSet::new();
-
-        for alt in &rule.alternatives {
-            for elem in &alt.elements {
-                self.collect_labels_from_element(elem, &mut fields, &mut seen_labels);
-            }
-        }
+    /// TypeScript node interfaces, each tagged with a literal `kind`, and the
+    /// `AstNode` union over them so a `switch (node.kind)` narrows the type.
+    ///
+    /// Like the JavaScript classes, nodes hold their child nodes in
+    /// `children`, and labeled and unlabeled references in fields. A field
+    /// is optional unless every alternative of the rule sets it.
+    fn generate_ast_interfaces(&self, grammar: &Grammar) -> String {
+        let mut code = String::new();
 
-        fields
-    }
+        code.push_str("// AST node type definitions\n\n");
 
-    fn collect_labels_from_element(
-        &self,
-        element: &crate::ast::Element,
-        fields: &mut Vec<(String, String, bool)>,
-        seen_labels: &mut std::collections::HashSet<String>,
-    ) {
-        use crate::ast::Element;
+        let mut node_names = Vec::new();
+        for rule in grammar.parser_rules() {
+            let node_name = format!("{}Node", to_pascal_case(&rule.name));
 
-        match element {
-            Element::Terminal { label: Some(label), is_list, .. } => {
-                if seen_labels.insert(label.clone()) {
-                    fields.push((label.clone(), "Token".to_string(), *is_list));
-                }
-            }
-            Element::RuleRef { label: Some(label), is_list, .. } => {
-                if seen_labels.insert(label.clone()) {
-                    fields.push((label.clone(), "AstNode".to_string(), *is_list));
-                }
-            }
-            Element::StringLiteral { label: Some(label), is_list, .. } => {
-                if seen_labels.insert(label.clone()) {
-                    fields.push((label.clone(), "Token".to_string(), *is_list));
-                }
-            }
-            Element::Group { alternatives } => {
-                for alt in alternatives {
-                    for elem in &alt.elements {
-                        self.collect_labels_from_element(elem, fields, seen_labels);
-                    }
-                }
-            }
-            Element::Optional { element, .. } => {
-                self.collect_labels_from_element(element, fields, seen_labels);
-            }
-            Element::ZeroOrMore { element, .. } => {
-                self.collect_labels_from_element(element, fields, seen_labels);
-            }
-            Element::OneOrMore { element, .. } => {
-                self.collect_labels_from_element(element, fields, seen_labels);
+            code.push_str("/**\n");
+            code.push_str(&format!(" * AST node for {} rule.\n", rule.name));
+            code.push_str(" */\n");
+            code.push_str(&format!("interface {} {{\n", node_name));
+            code.push_str(&format!("  kind: '{}';\n", rule.name));
+            code.push_str("  children: AstNode[];\n");
+            for (field_name, field_type, is_list) in node_fields(rule) {
+                let optional = if in_every_alternative(rule, &field_name) {
+                    ""
+                } else {
+                    "?"
+                };
+                let suffix = if is_list { "[]" } else { "" };
+                code.push_str(&format!(
+                    "  {}{}: {}{};\n",
+                    field_name, optional, field_type, suffix
+                ));
             }
-            _ => {}
+            code.push_str("}\n\n");
+
+            node_names.push(node_name);
+        }
+
+        if node_names.is_empty() {
+            code.push_str("type AstNode = never;\n\n");
+        } else {
+            code.push_str(&format!("type AstNode = {};\n\n", node_names.join(" | ")));
         }
+
+        code
     }
 
-    fn generate_javascript_alternative(&self, alt: &crate::ast::Alternative, rule: &Rule) -> String {
-        use crate::ast::Element;
+    fn generate_javascript_alternative(&self, alt: &Alternative, rule: &Rule, typescript: bool) -> Result<String> {
         let mut code = String::new();
 
-        // Collect labeled variables
-        let mut collected_vars = Vec::new();
-        let mut list_vars = Vec::new();
-
-        for element in &alt.elements {
-            match element {
-                Element::RuleRef { label: Some(lbl), is_list, .. }
-                | Element::Terminal { label: Some(lbl), is_list, .. }
-                | Element::StringLiteral { label: Some(lbl), is_list, .. } => {
-                    if *is_list {
-                        list_vars.push(lbl.clone());
-                    } else {
-                        collected_vars.push(lbl.clone());
-                    }
-                }
-                _ => {}
+        // Declare the fields this alternative sets up front, so nested blocks
+        // assign to them
+        let fields = alternative_fields(alt, rule);
+        code.push_str("      const children = [];\n");
+        for (name, is_list) in &fields {
+            if *is_list {
+                code.push_str(&format!("      const {} = [];\n", name));
+            } else {
+                code.push_str(&format!("      let {} = null;\n", name));
             }
         }
-
-        // Initialize list variables
-        for lbl in &list_vars {
-            code.push_str(&format!("      const {} = [];\\n", lbl));
-        }
-        if !list_vars.is_empty() {
-            code.push_str("\\n");
-        }
+        code.push('\n');
 
         // Generate parsing code for each element
+        let mut ids = 0;
         for element in &alt.elements {
-            match element {
-                Element::RuleRef { name, label, is_list } => {
-                    let method_name = to_camel_case(name);
-                    if *is_list {
-                        if let Some(lbl) = label {
-                            code.push_str(&format!("      {}.push(this.parse{}());\\n", lbl, capitalize(&method_name)));
-                        } else {
-                            code.push_str(&format!("      this.parse{}();\\n", capitalize(&method_name)));
-                        }
-                    } else if let Some(lbl) = label {
-                        code.push_str(&format!("      const {} = this.parse{}();\\n", lbl, capitalize(&method_name)));
-                    } else {
-                        code.push_str(&format!("      this.parse{}();\\n", capitalize(&method_name)));
-                    }
-                }
-                Element::Terminal { value, label, is_list } => {
-                    code.push_str(&format!("      // Match terminal: {}\\n", value));
-                    code.push_str("      if (this.position >= this.tokens.length) {\\n");
-                    code.push_str(&format!("        throw new ParseError('Unexpected EOF, expected: {}', this.position);\\n", value));
-                    code.push_str("      }\\n");
-                    code.push_str(&format!("      if (this.tokens[this.position].kind === TokenKind.{}) {{\\n", value));
-                    if *is_list {
-                        if let Some(lbl) = label {
-                            code.push_str(&format!("        {}.push(this.tokens[this.position]);\\n", lbl));
-                        }
-                    } else if let Some(lbl) = label {
-                        code.push_str(&format!("        const {} = this.tokens[this.position];\\n", lbl));
-                    }
-                    code.push_str("        this.position++;\\n");
-                    code.push_str("      } else {\\n");
-                    code.push_str(&format!("        throw new ParseError(`Expected {}, got ${{this.tokens[this.position].kind}}`, this.position);\\n", value));
-                    code.push_str("      }\\n");
-                }
-                Element::StringLiteral { value, label, is_list } => {
-                    code.push_str(&format!("      // Match string literal: '{}'\\n", value));
-                    code.push_str("      if (this.position >= this.tokens.length) {\\n");
-                    code.push_str(&format!("        throw new ParseError('Unexpected EOF, expected: \"{}\"', this.position);\\n", value));
-                    code.push_str("      }\\n");
-                    code.push_str(&format!("      if (this.tokens[this.position].value === '{}') {{\\n", value));
-                    if *is_list {
-                        if let Some(lbl) = label {
-                            code.push_str(&format!("        {}.push(this.tokens[this.position]);\\n", lbl));
-                        }
-                    } else if let Some(lbl) = label {
-                        code.push_str(&format!("        const {} = this.tokens[this.position];\\n", lbl));
-                    }
-                    code.push_str("        this.position++;\\n");
-                    code.push_str("      } else {\\n");
-                    code.push_str(&format!("        throw new ParseError(`Expected \\\"{}\\\", got ${{this.tokens[this.position].value}}`, this.position);\\n", value));
-                    code.push_str("      }\\n");
-                }
-                Element::Optional { element: _, .. } => {
-                    code.push_str("      // Optional element\\n");
-                    code.push_str("      const savedPos = this.position;\\n");
-                    code.push_str("      try {\\n");
-                    // Recursively generate code for the optional element
-                    code.push_str("        // Try to match optional element\\n");
-                    code.push_str("      } catch (err) {\\n");
-                    code.push_str("        // Optional failed, restore position\\n");
-                    code.push_str("        this.position = savedPos;\\n");
-                    code.push_str("      }\\n");
-                }
-                Element::ZeroOrMore { element: _, .. } => {
-                    code.push_str("      // Zero or more repetition\\n");
-                    code.push_str("      while (this.position < this.tokens.length) {\\n");
-                    code.push_str("        const savedPos = this.position;\\n");
-                    code.push_str("        try {\\n");
-                    code.push_str("          // Try to match element\\n");
-                    code.push_str("        } catch (err) {\\n");
-                    code.push_str("          this.position = savedPos;\\n");
-                    code.push_str("          break;\\n");
-                    code.push_str("        }\\n");
-                    code.push_str("      }\\n");
-                }
-                Element::OneOrMore { element: _, .. } => {
-                    code.push_str("      // One or more repetition\\n");
-                    code.push_str("      let matchCount = 0;\\n");
-                    code.push_str("      while (this.position < this.tokens.length) {\\n");
-                    code.push_str("        const savedPos = this.position;\\n");
-                    code.push_str("        try {\\n");
-                    code.push_str("          // Try to match element\\n");
-                    code.push_str("          matchCount++;\\n");
-                    code.push_str("        } catch (err) {\\n");
-                    code.push_str("          this.position = savedPos;\\n");
-                    code.push_str("          break;\\n");
-                    code.push_str("        }\\n");
-                    code.push_str("      }\\n");
-                    code.push_str("      if (matchCount === 0) {\\n");
-                    code.push_str("        throw new ParseError('Expected at least one match', this.position);\\n");
-                    code.push_str("      }\\n");
-                }
-                _ => {
-                    code.push_str("      // TODO: Handle other element types\\n");
-                }
-            }
+            code.push_str(&js_element(element, rule, typescript, "      ", &mut ids)?);
         }
 
         // Build result node
-        if !collected_vars.is_empty() || !list_vars.is_empty() {
-            code.push_str("\\n      // Build AST node\\n");
-            code.push_str(&format!("      return new {}Node(", to_pascal_case(&rule.name)));
-            let all_vars: Vec<_> = collected_vars.iter().chain(list_vars.iter()).collect();
-            for (i, var) in all_vars.iter().enumerate() {
-                if i > 0 {
-                    code.push_str(", ");
-                }
-                code.push_str(var);
-            }
-            code.push_str(");\\n");
-        } else {
-            code.push_str(&format!("      return new {}Node();\\n", to_pascal_case(&rule.name)));
-        }
+        let names: Vec<String> = fields.into_iter().map(|(name, _)| name).collect();
+        code.push_str("\n      // Build AST node\n");
+        code.push_str(&format!(
+            "      return {};\n",
+            node_expression(rule, "children", &names, typescript)
+        ));
 
-        code
+        Ok(code)
     }
 }
 
@@ -496,13 +459,24 @@ impl CodeGeneratorTrait for JavaScriptCodeGenerator {
     type Input = Grammar;
     type Config = CodeGenConfig;
 
-    fn generate(&self, input: &Self::Input, _config: &Self::Config) -> Result<String> {
+    fn generate(&self, input: &Self::Input, config: &Self::Config) -> Result<String> {
         let mut code = String::new();
+        let typescript = is_typescript(config);
+        // TypeScript leaves the module format to the compiler's settings
+        let commonjs = config.commonjs && !typescript;
+        // Module-private code exports nothing; JavaScript has no level
+        // between that and public for `Visibility::Crate`
+        let exported = config.visibility != Visibility::Module;
+        let export = if exported && !commonjs { "export " } else { "" };
 
         // Header with browser compatibility note
         code.push_str(&format!("// Generated parser for {} grammar\n", input.name));
         code.push_str("// DO NOT EDIT - This file is automatically generated\n");
-        code.push_str("// Browser-compatible ES6 module - no Node.js dependencies\n\n");
+        if commonjs {
+            code.push_str("// CommonJS-compatible script - no Node.js dependencies\n\n");
+        } else {
+            code.push_str("// Browser-compatible ES6 module - no Node.js dependencies\n\n");
+        }
 
         // Insert @header named action if present
         if let Some(header_code) = input.named_actions.get("header") {
@@ -515,7 +489,7 @@ impl CodeGeneratorTrait for JavaScriptCodeGenerator {
         code.push_str("/**\n");
         code.push_str(" * Parse error with context information.\n");
         code.push_str(" */\n");
-        code.push_str("class ParseError extends Error {\n");
+        code.push_str(&format!("{}class ParseError extends Error {{\n", export));
         code.push_str("  constructor(message, position, expected = [], found = null) {\n");
         code.push_str("    super(message);\n");
         code.push_str("    this.name = 'ParseError';\n");
@@ -542,40 +516,543 @@ impl CodeGeneratorTrait for JavaScriptCodeGenerator {
         code.push_str("/**\n");
         code.push_str(" * Token with position information.\n");
         code.push_str(" */\n");
-        code.push_str("class Token {\n");
-        code.push_str("  constructor(kind, text, position) {\n");
+        code.push_str(&format!("{}class Token {{\n", export));
+        code.push_str("  constructor(kind, text, position, line = 1, column = 0, start = 0, stop = 0) {\n");
         code.push_str("    this.kind = kind;\n");
         code.push_str("    this.text = text;\n");
+        code.push_str("    // Index of the first UTF-16 code unit\n");
         code.push_str("    this.position = position;\n");
+        code.push_str("    // Line counting from 1, column in code points counting from 0\n");
+        code.push_str("    this.line = line;\n");
+        code.push_str("    this.column = column;\n");
+        code.push_str("    // UTF-8 byte offsets of the token and just past it\n");
+        code.push_str("    this.start = start;\n");
+        code.push_str("    this.stop = stop;\n");
         code.push_str("  }\n");
         code.push_str("}\n\n");
 
         // Token types
-        code.push_str(&self.generate_token_types(input));
+        code.push_str(&self.generate_token_types(input, config, export));
 
         // AST types
-        code.push_str(&self.generate_ast_types(input));
+        if typescript {
+            code.push_str(&self.generate_ast_interfaces(input));
+        } else {
+            code.push_str(&self.generate_ast_types(input));
+        }
 
         // Lexer
-        code.push_str(&self.generate_lexer(input));
+        code.push_str(&self.generate_lexer(input, export));
 
         // Generate parser
-        code.push_str(&self.generate_parser(input));
+        code.push_str(&self.generate_parser(input, typescript, export)?);
 
-        // ES6 module exports for browser compatibility
-        code.push_str("\n// ES6 Module Exports\n");
-        code.push_str(&format!("export {{ ParseError, Token, TokenKind, {}Lexer, {}Parser }};\n", 
-            input.name, input.name));
-        code.push_str("\n// Default export\n");
-        code.push_str(&format!("export default {{ ParseError, Token, TokenKind, {}Lexer, {}Parser }};\n", 
-            input.name, input.name));
+        let names = format!(
+            "ParseError, Token, TokenKind, {}Lexer, {}Parser",
+            input.name, input.name
+        );
+        if exported && commonjs {
+            // `require()` support; the guard keeps the file loadable as a
+            // plain browser script
+            code.push_str("\n// CommonJS exports\n");
+            code.push_str("if (typeof module !== 'undefined' && module.exports) {\n");
+            code.push_str(&format!("  module.exports = {{ {} }};\n", names));
+            code.push_str("}\n");
+        } else if exported {
+            if typescript {
+                let mut types = vec!["AstNode".to_string()];
+                types.extend(
+                    input
+                        .parser_rules()
+                        .map(|rule| format!("{}Node", to_pascal_case(&rule.name))),
+                );
+                code.push_str(&format!("\nexport type {{ {} }};\n", types.join(", ")));
+            }
+            code.push_str("\n// Default export\n");
+            code.push_str(&format!("export default {{ {} }};\n", names));
+        }
 
-        Ok(code)
+        Ok(Emitter::new(config, 2).finish(code))
     }
 
     fn target_language(&self) -> &str {
         "javascript"
     }
+
+    fn supported_options(&self) -> &[&str] {
+        &["superClass"]
+    }
+}
+
+/// JavaScript statements matching `element`, each line starting with `indent`.
+///
+/// Statements throw `ParseError` when the input does not match; optional
+/// parts, loops and groups catch it and restore the position saved in a
+/// `const`. Those take the next number from `ids`, as a block cannot declare
+/// the same name twice. Loops stop once an iteration consumes nothing.
+/// Elements this target cannot match in a parser rule, such as Unicode
+/// property classes, are an error.
+fn js_element(element: &Element, rule: &Rule, typescript: bool, indent: &str, ids: &mut usize) -> Result<String> {
+    let mut code = String::new();
+    let id = *ids;
+    let saved = format!("savedPos{}", id);
+    let saved_children = format!("savedChildren{}", id);
+    let nested = format!("{}  ", indent);
+    // Going back to the saved position also drops the nodes parsed since
+    let save = |indent: &str| {
+        format!(
+            "{indent}const {saved} = this.position;\n{indent}const {saved_children} = children.length;\n"
+        )
+    };
+    let restore = |indent: &str| {
+        format!("{indent}this.position = {saved};\n{indent}children.length = {saved_children};\n")
+    };
+    let store = |label: &Option<String>, is_list: bool, value: &str| match label {
+        Some(lbl) if is_list => format!("{}.push({});", lbl, value),
+        Some(lbl) => format!("{} = {};", lbl, value),
+        None => format!("{};", value),
+    };
+    // Rethrow anything but a parse error from a `catch (err)` block
+    let rethrow = format!("{}  if (!(err instanceof ParseError)) throw err;\n", indent);
+
+    match element {
+        Element::RuleRef { name, .. } if name == "EOF" => return js_element(&Element::Eof, rule, typescript, indent, ids),
+        // A token reference matches the token like a terminal
+        Element::RuleRef { name, label, is_list, .. } if name.starts_with(|c: char| c.is_uppercase()) => {
+            // An unlabeled token is kept in the field named after it
+            let (label, is_list) = match label {
+                Some(_) => (label.clone(), *is_list),
+                None => match reference_field(rule, name) {
+                    Some((field, is_list)) => (Some(field), is_list),
+                    None => (None, false),
+                },
+            };
+            let terminal = Element::Terminal {
+                value: name.clone(),
+                label,
+                is_list,
+                span: None,
+                options: Vec::new(),
+            };
+            return js_element(&terminal, rule, typescript, indent, ids);
+        }
+        Element::RuleRef { name, label, is_list, .. } => {
+            // The node goes in its label, or the field named after the rule,
+            // and in `children`
+            let (field, is_list) = match label {
+                Some(_) => (label.clone(), *is_list),
+                None => match reference_field(rule, name) {
+                    Some((field, is_list)) => (Some(field), is_list),
+                    None => (None, false),
+                },
+            };
+            let call = format!("this.parse{}()", capitalize(&to_camel_case(name)));
+            code.push_str(&format!("{}children.push({});\n", indent, call));
+            if field.is_some() {
+                let last = "children[children.length - 1]";
+                code.push_str(&format!("{}{}\n", indent, store(&field, is_list, last)));
+            }
+        }
+        Element::Terminal { value, label, is_list, .. } => {
+            code.push_str(&format!("{}// Match terminal: {}\n", indent, value));
+            code.push_str(&format!("{}if (this.position >= this.tokens.length) {{\n", indent));
+            code.push_str(&format!("{}  throw new ParseError('Unexpected EOF, expected: {}', this.position);\n", indent, value));
+            code.push_str(&format!("{}}}\n", indent));
+            code.push_str(&format!("{}if (this.tokens[this.position].kind === TokenKind.{}) {{\n", indent, value));
+            if label.is_some() {
+                code.push_str(&format!("{}  {}\n", indent, store(label, *is_list, "this.tokens[this.position]")));
+            }
+            code.push_str(&format!("{}  this.position++;\n", indent));
+            code.push_str(&format!("{}}} else {{\n", indent));
+            code.push_str(&format!("{}  throw new ParseError(`Expected {}, got ${{this.tokens[this.position].kind}}`, this.position);\n", indent, value));
+            code.push_str(&format!("{}}}\n", indent));
+        }
+        Element::StringLiteral { value, label, is_list, .. } => {
+            let literal = single_quoted(value);
+            code.push_str(&format!("{}// Match string literal: {}\n", indent, literal));
+            code.push_str(&format!("{}if (this.position >= this.tokens.length) {{\n", indent));
+            code.push_str(&format!(
+                "{}  throw new ParseError({}, this.position);\n",
+                indent,
+                single_quoted(&format!("Unexpected EOF, expected: {}", literal))
+            ));
+            code.push_str(&format!("{}}}\n", indent));
+            code.push_str(&format!("{}if (this.tokens[this.position].text === {}) {{\n", indent, literal));
+            if label.is_some() {
+                code.push_str(&format!("{}  {}\n", indent, store(label, *is_list, "this.tokens[this.position]")));
+            }
+            code.push_str(&format!("{}  this.position++;\n", indent));
+            code.push_str(&format!("{}}} else {{\n", indent));
+            code.push_str(&format!(
+                "{}  throw new ParseError({} + this.tokens[this.position].text, this.position);\n",
+                indent,
+                single_quoted(&format!("Expected {}, got ", literal))
+            ));
+            code.push_str(&format!("{}}}\n", indent));
+        }
+        Element::Wildcard => {
+            code.push_str(&format!("{}// Match any token\n", indent));
+            code.push_str(&format!("{}if (this.position >= this.tokens.length || this.tokens[this.position].kind === TokenKind.EOF) {{\n", indent));
+            code.push_str(&format!("{}  throw new ParseError('Unexpected EOF, expected any token', this.position);\n", indent));
+            code.push_str(&format!("{}}}\n", indent));
+            code.push_str(&format!("{}this.position++;\n", indent));
+        }
+        Element::CharClass { .. } | Element::CharRange { .. } => {
+            *ids += 1;
+            let ch = format!("ch{}", id);
+            code.push_str(&format!("{}// Match single-character token in class\n", indent));
+            code.push_str(&format!(
+                "{}const {} = this.position < this.tokens.length ? this.tokens[this.position].text : '';\n",
+                indent, ch
+            ));
+            code.push_str(&format!("{}if (!([...{}].length === 1 && {})) {{\n", indent, ch, js_char_condition(element, &ch)));
+            code.push_str(&format!("{}  throw new ParseError('Expected a character in class', this.position, [], {});\n", indent, ch));
+            code.push_str(&format!("{}}}\n", indent));
+            code.push_str(&format!("{}this.position++;\n", indent));
+        }
+        Element::Not { element: negated } => {
+            *ids += 1;
+            let matched = format!("matched{}", id);
+            code.push_str(&format!("{}// Negation - any token the element does not match\n", indent));
+            code.push_str(&save(indent));
+            code.push_str(&format!("{}let {} = true;\n", indent, matched));
+            code.push_str(&format!("{}try {{\n", indent));
+            code.push_str(&js_element(negated, rule, typescript, &nested, ids)?);
+            code.push_str(&format!("{}}} catch (err) {{\n", indent));
+            code.push_str(&rethrow);
+            code.push_str(&format!("{}  {} = false;\n", indent, matched));
+            code.push_str(&format!("{}}}\n", indent));
+            code.push_str(&restore(indent));
+            code.push_str(&format!(
+                "{}if ({} || this.position >= this.tokens.length || this.tokens[this.position].kind === TokenKind.EOF) {{\n",
+                indent, matched
+            ));
+            code.push_str(&format!("{}  throw new ParseError('Expected a token outside the negated set', this.position);\n", indent));
+            code.push_str(&format!("{}}}\n", indent));
+            code.push_str(&format!("{}this.position++;\n", indent));
+        }
+        Element::Predicate { .. } => {
+            let language = if typescript { "typescript" } else { "javascript" };
+            let predicate = translate_action_element(element, language).unwrap_or_default();
+            // <fail='text'> replaces the message; a <fail={code}> message is Rust code
+            let message = match element.option("fail") {
+                Some(fail) if !fail.starts_with('{') => fail.to_string(),
+                _ => "Semantic predicate failed".to_string(),
+            };
+            code.push_str(&format!("{}// Semantic predicate\n", indent));
+            code.push_str(&format!("{}if (!({})) {{\n", indent, predicate));
+            code.push_str(&format!("{}  throw new ParseError({}, this.position);\n", indent, single_quoted(&message)));
+            code.push_str(&format!("{}}}\n", indent));
+        }
+        Element::Optional { element, .. } => {
+            *ids += 1;
+            code.push_str(&format!("{}// Optional element\n", indent));
+            code.push_str(&save(indent));
+            code.push_str(&format!("{}try {{\n", indent));
+            code.push_str(&js_element(element, rule, typescript, &nested, ids)?);
+            code.push_str(&format!("{}}} catch (err) {{\n", indent));
+            code.push_str(&rethrow);
+            code.push_str(&format!("{}  // Optional failed, restore position\n", indent));
+            code.push_str(&restore(&nested));
+            code.push_str(&format!("{}}}\n", indent));
+        }
+        Element::ZeroOrMore { element: repeated, .. } | Element::OneOrMore { element: repeated, .. } => {
+            let one_or_more = matches!(element, Element::OneOrMore { .. });
+            *ids += 1;
+            let count = format!("matchCount{}", id);
+            if one_or_more {
+                code.push_str(&format!("{}// One or more repetition\n", indent));
+                code.push_str(&format!("{}let {} = 0;\n", indent, count));
+            } else {
+                code.push_str(&format!("{}// Zero or more repetition\n", indent));
+            }
+            code.push_str(&format!("{}while (this.position < this.tokens.length) {{\n", indent));
+            code.push_str(&save(&nested));
+            code.push_str(&format!("{}  try {{\n", indent));
+            code.push_str(&js_element(repeated, rule, typescript, &format!("{}    ", indent), ids)?);
+            code.push_str(&format!("{}  }} catch (err) {{\n", indent));
+            code.push_str(&format!("{}    if (!(err instanceof ParseError)) throw err;\n", indent));
+            code.push_str(&restore(&format!("{}    ", indent)));
+            code.push_str(&format!("{}    break;\n", indent));
+            code.push_str(&format!("{}  }}\n", indent));
+            if one_or_more {
+                code.push_str(&format!("{}  {}++;\n", indent, count));
+            }
+            code.push_str(&format!("{}  if (this.position === {}) break;\n", indent, saved));
+            code.push_str(&format!("{}}}\n", indent));
+            if one_or_more {
+                code.push_str(&format!("{}if ({} === 0) {{\n", indent, count));
+                code.push_str(&format!("{}  throw new ParseError('Expected at least one match', this.position);\n", indent));
+                code.push_str(&format!("{}}}\n", indent));
+            }
+        }
+        Element::Group { alternatives } => {
+            *ids += 1;
+            code.push_str(&format!("{}// Group\n", indent));
+            code.push_str(&save(indent));
+            code.push_str(&js_group(alternatives, rule, typescript, indent, id, ids)?);
+        }
+        Element::Eof => {
+            code.push_str(&format!("{}// Match end of input\n", indent));
+            code.push_str(&format!("{}if (this.position < this.tokens.length && this.tokens[this.position].kind !== TokenKind.EOF) {{\n", indent));
+            code.push_str(&format!("{}  throw new ParseError(`Expected EOF, got ${{this.tokens[this.position].kind}}`, this.position);\n", indent));
+            code.push_str(&format!("{}}}\n", indent));
+        }
+        other => {
+            return Err(Error::codegen(format!(
+                "rule {}: the {} target cannot generate {} in a parser rule",
+                rule.name,
+                if typescript { "typescript" } else { "javascript" },
+                element_description(other)
+            )));
+        }
+    }
+    Ok(code)
+}
+
+/// Try each of a group's alternatives in turn, going back to the position
+/// in `savedPos{id}` and the nodes in `savedChildren{id}` after one fails. The last one's error propagates.
+fn js_group(alternatives: &[Alternative], rule: &Rule, typescript: bool, indent: &str, id: usize, ids: &mut usize) -> Result<String> {
+    let body = |alt: &Alternative, indent: &str, ids: &mut usize| -> Result<String> {
+        let mut code = String::new();
+        for element in &alt.elements {
+            code.push_str(&js_element(element, rule, typescript, indent, ids)?);
+        }
+        Ok(code)
+    };
+
+    match alternatives {
+        [] => Ok(String::new()),
+        [only] => body(only, indent, ids),
+        [first, rest @ ..] => {
+            let nested = format!("{}  ", indent);
+            let mut code = format!("{}try {{\n", indent);
+            code.push_str(&body(first, &nested, ids)?);
+            code.push_str(&format!("{}}} catch (err) {{\n", indent));
+            code.push_str(&format!("{}  if (!(err instanceof ParseError)) throw err;\n", indent));
+            code.push_str(&format!(
+                "{indent}  this.position = savedPos{id};\n{indent}  children.length = savedChildren{id};\n"
+            ));
+            code.push_str(&js_group(rest, rule, typescript, &nested, id, ids)?);
+            code.push_str(&format!("{}}}\n", indent));
+            Ok(code)
+        }
+    }
+}
+
+/// JavaScript condition on the string `ch` for a character class or range.
+fn js_char_condition(element: &Element, ch: &str) -> String {
+    let ranges = match element {
+        Element::CharClass { ranges, .. } => ranges.clone(),
+        Element::CharRange { start, end } => vec![(*start, *end)],
+        _ => Vec::new(),
+    };
+    let condition = ranges
+        .iter()
+        .map(|(start, end)| {
+            let (start, end) = (single_quoted(&start.to_string()), single_quoted(&end.to_string()));
+            if start == end {
+                format!("{} === {}", ch, start)
+            } else {
+                format!("({} <= {} && {} <= {})", start, ch, ch, end)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" || ");
+    match element {
+        Element::CharClass { negated: true, .. } => format!("!({})", condition),
+        _ => format!("({})", condition),
+    }
+}
+
+fn is_typescript(config: &CodeGenConfig) -> bool {
+    matches!(config.target_language.as_str(), "typescript" | "ts")
+}
+
+/// The expression that builds a rule's node from `children` and the
+/// variables of the fields in `present`: a class instance in JavaScript,
+/// with `null` or `[]` for the other fields, and an object literal with its
+/// `kind` in TypeScript.
+fn node_expression(rule: &Rule, children: &str, present: &[String], typescript: bool) -> String {
+    if typescript {
+        let mut fields = vec![format!("kind: '{}'", rule.name)];
+        if children == "children" {
+            fields.push(children.to_string());
+        } else {
+            fields.push(format!("children: {}", children));
+        }
+        fields.extend(present.iter().cloned());
+        format!("{{ {} }}", fields.join(", "))
+    } else {
+        let mut args = vec![children.to_string()];
+        for (name, _, is_list) in node_fields(rule) {
+            if present.contains(&name) {
+                args.push(name);
+            } else if is_list {
+                args.push("[]".to_string());
+            } else {
+                args.push("null".to_string());
+            }
+        }
+        format!("new {}Node({})", to_pascal_case(&rule.name), args.join(", "))
+    }
+}
+
+/// Fields for the labeled elements of `rule`, in the order they first
+/// appear.
+fn labeled_fields(rule: &Rule) -> Vec<(String, String, bool)> {
+    let mut fields = Vec::new();
+    let mut seen_labels = std::collections::HashSet::new();
+
+    for alt in &rule.alternatives {
+        for elem in &alt.elements {
+            collect_labels_from_element(elem, &mut fields, &mut seen_labels);
+        }
+    }
+
+    fields
+}
+
+fn collect_labels_from_element(
+    element: &Element,
+    fields: &mut Vec<(String, String, bool)>,
+    seen_labels: &mut std::collections::HashSet<String>,
+) {
+    match element {
+        Element::Terminal { label: Some(label), is_list, .. } if seen_labels.insert(label.clone()) => {
+            fields.push((label.clone(), "Token".to_string(), *is_list));
+        }
+        Element::RuleRef { name, label: Some(label), is_list, .. } if seen_labels.insert(label.clone()) => {
+            fields.push((label.clone(), reference_type(name), *is_list));
+        }
+        Element::StringLiteral { label: Some(label), is_list, .. } if seen_labels.insert(label.clone()) => {
+            fields.push((label.clone(), "Token".to_string(), *is_list));
+        }
+        Element::Group { alternatives } => {
+            for alt in alternatives {
+                for elem in &alt.elements {
+                    collect_labels_from_element(elem, fields, seen_labels);
+                }
+            }
+        }
+        Element::Optional { element, .. }
+        | Element::ZeroOrMore { element, .. }
+        | Element::OneOrMore { element, .. } => {
+            collect_labels_from_element(element, fields, seen_labels);
+        }
+        _ => {}
+    }
+}
+
+/// Type of a reference: `Token` for tokens, the node type for rules.
+fn reference_type(name: &str) -> String {
+    if name.starts_with(|c: char| c.is_uppercase()) {
+        "Token".to_string()
+    } else {
+        format!("{}Node", to_pascal_case(name))
+    }
+}
+
+/// Fields of the node of `rule`: its labels, then one per rule or token it
+/// references without a label, named after it in camelCase, unless a label
+/// takes that name. A reference field is a list when some alternative can
+/// match the reference more than once.
+fn node_fields(rule: &Rule) -> Vec<(String, String, bool)> {
+    let mut fields = labeled_fields(rule);
+    let labels = fields.len();
+    for (name, is_list) in unlabeled_references(rule) {
+        let field_name = reference_field_name(&name);
+        match fields.iter_mut().position(|(seen, _, _)| *seen == field_name) {
+            Some(index) if index >= labels => fields[index].2 |= is_list,
+            Some(_) => {}
+            None => fields.push((field_name, reference_type(&name), is_list)),
+        }
+    }
+    fields
+}
+
+/// Field name of unlabeled references to `name`, with a trailing `_` if it
+/// is a reserved word or a name nodes already use.
+fn reference_field_name(name: &str) -> String {
+    const RESERVED: &[&str] = &[
+        "await", "break", "case", "catch", "children", "class", "const", "continue", "debugger",
+        "default", "delete", "do", "else", "enum", "export", "extends", "false", "finally", "for",
+        "function", "if", "implements", "import", "in", "instanceof", "interface", "kind", "let",
+        "new", "null", "package", "private", "protected", "public", "return", "static", "super",
+        "switch", "this", "throw", "true", "try", "typeof", "var", "void", "while", "with", "yield",
+    ];
+    let field_name = to_camel_case(&name.to_lowercase());
+    if RESERVED.contains(&field_name.as_str()) {
+        format!("{}_", field_name)
+    } else {
+        field_name
+    }
+}
+
+/// Field an unlabeled reference to `name` in `rule` is stored in, and
+/// whether it is a list, unless a label took its name.
+fn reference_field(rule: &Rule, name: &str) -> Option<(String, bool)> {
+    let field_name = reference_field_name(name);
+    if labeled_fields(rule).iter().any(|(label, _, _)| *label == field_name) {
+        return None;
+    }
+    node_fields(rule)
+        .into_iter()
+        .find(|(field, _, _)| *field == field_name)
+        .map(|(field, _, is_list)| (field, is_list))
+}
+
+/// The fields of `rule`'s node that elements of `alt` set, in field order.
+fn alternative_fields(alt: &Alternative, rule: &Rule) -> Vec<(String, bool)> {
+    fn collect(element: &Element, rule: &Rule, set: &mut Vec<String>) {
+        match element {
+            Element::RuleRef { label: Some(label), .. }
+            | Element::Terminal { label: Some(label), .. }
+            | Element::StringLiteral { label: Some(label), .. } => set.push(label.clone()),
+            Element::RuleRef { name, label: None, .. } => {
+                if let Some((field, _)) = reference_field(rule, name) {
+                    set.push(field);
+                }
+            }
+            Element::Group { alternatives } => {
+                for alt in alternatives {
+                    for element in &alt.elements {
+                        collect(element, rule, set);
+                    }
+                }
+            }
+            Element::Optional { element, .. }
+            | Element::ZeroOrMore { element, .. }
+            | Element::OneOrMore { element, .. } => collect(element, rule, set),
+            _ => {}
+        }
+    }
+
+    let mut set = Vec::new();
+    for element in &alt.elements {
+        collect(element, rule, &mut set);
+    }
+    node_fields(rule)
+        .into_iter()
+        .filter(|(name, _, _)| set.contains(name))
+        .map(|(name, _, is_list)| (name, is_list))
+        .collect()
+}
+
+/// Whether every alternative of the rule sets `field` with an element of
+/// its own, rather than one in a group, optional part or loop.
+fn in_every_alternative(rule: &Rule, field: &str) -> bool {
+    rule.alternatives.iter().all(|alt| {
+        alt.elements.iter().any(|element| match element {
+            Element::RuleRef { label: Some(l), .. }
+            | Element::Terminal { label: Some(l), .. }
+            | Element::StringLiteral { label: Some(l), .. } => l == field,
+            Element::RuleRef { name, label: None, .. } => {
+                reference_field(rule, name).is_some_and(|(name, _)| name == field)
+            }
+            _ => false,
+        })
+    })
 }
 
 fn to_camel_case(s: &str) -> String {
@@ -630,6 +1107,180 @@ mod tests {
         assert!(code.contains("class CalculatorLexer"));
         assert!(code.contains("class CalculatorParser"));
         assert!(code.contains("parseExpr"));
-        assert!(code.contains("export {") || code.contains("export default"));
+        assert!(code.contains("export default"));
+
+        let config = CodeGenConfig {
+            visibility: Visibility::Module,
+            ..CodeGenConfig::default()
+        };
+        let code = generator.generate(&grammar, &config).unwrap();
+        assert!(code.contains("class CalculatorParser"));
+        assert!(!code.contains("export"));
+    }
+
+    #[test]
+    fn test_super_class() {
+        let mut grammar = Grammar::new("Calculator".to_string(), GrammarType::Parser);
+        grammar.add_rule(Rule::parser_rule("expr".to_string()));
+        grammar.add_option("superClass".to_string(), "BaseParser".to_string());
+
+        let generator = JavaScriptCodeGenerator::new();
+        for target_language in ["javascript", "typescript"] {
+            let config = CodeGenConfig {
+                target_language: target_language.to_string(),
+                ..CodeGenConfig::default()
+            };
+            let code = generator.generate(&grammar, &config).unwrap();
+            assert!(code.contains(
+                "export class CalculatorParser extends BaseParser {\n  constructor(tokens) {\n    super();\n    this.tokens = tokens;\n"
+            ));
+            assert!(code.contains("export class CalculatorLexer {"));
+        }
+    }
+
+    #[test]
+    fn test_es_module_exports() {
+        let mut grammar = Grammar::new("Calculator".to_string(), GrammarType::Parser);
+        grammar.add_rule(Rule::parser_rule("expr".to_string()));
+        grammar.add_rule(Rule::lexer_rule("NUMBER".to_string()));
+
+        let generator = JavaScriptCodeGenerator::new();
+        let code = generator.generate(&grammar, &CodeGenConfig::default()).unwrap();
+        assert!(code.contains("export class CalculatorLexer {"));
+        assert!(code.contains("export class CalculatorParser {"));
+        assert!(code.contains("export class ParseError extends Error {"));
+        assert!(code.contains("export const TokenKind = Object.freeze({"));
+        assert!(!code.contains("module.exports"));
+
+        let config = CodeGenConfig {
+            commonjs: true,
+            ..CodeGenConfig::default()
+        };
+        let code = generator.generate(&grammar, &config).unwrap();
+        assert!(!code.contains("export "));
+        assert!(code.contains("\nclass CalculatorParser {"));
+        assert!(code.contains("\nconst TokenKind = Object.freeze({"));
+        assert!(code.contains(
+            "  module.exports = { ParseError, Token, TokenKind, CalculatorLexer, CalculatorParser };\n"
+        ));
+
+        // TypeScript keeps ES exports whatever the flag says
+        let config = CodeGenConfig {
+            target_language: "typescript".to_string(),
+            commonjs: true,
+            ..CodeGenConfig::default()
+        };
+        let code = generator.generate(&grammar, &config).unwrap();
+        assert!(code.contains("export enum TokenKind {"));
+        assert!(!code.contains("module.exports"));
+    }
+
+    #[test]
+    fn test_tokens_carry_line_column_and_byte_offsets() {
+        let mut grammar = Grammar::new("Calculator".to_string(), GrammarType::Parser);
+        grammar.add_rule(Rule::parser_rule("expr".to_string()));
+        grammar.add_rule(Rule::lexer_rule("NUMBER".to_string()));
+
+        let code = JavaScriptCodeGenerator::new().generate(&grammar, &CodeGenConfig::default()).unwrap();
+
+        assert!(code.contains("  constructor(kind, text, position, line = 1, column = 0, start = 0, stop = 0) {\n"));
+        assert!(code.contains("      return this._place(token, startPos, this.position);\n"));
+        // Astral characters take two code units but one column and four bytes
+        assert!(code.contains("      cursor.index += code > 0xffff ? 2 : 1;\n"));
+        assert!(code.contains("code < 0x10000 ? 3 : 4;\n"));
+    }
+
+    #[test]
+    fn test_lexer_iterator_protocol() {
+        let mut grammar = Grammar::new("Calculator".to_string(), GrammarType::Parser);
+        grammar.add_rule(Rule::parser_rule("expr".to_string()));
+        grammar.add_rule(Rule::lexer_rule("NUMBER".to_string()));
+
+        for language in ["javascript", "typescript"] {
+            let config = CodeGenConfig {
+                target_language: language.to_string(),
+                ..CodeGenConfig::default()
+            };
+            let code = JavaScriptCodeGenerator::new().generate(&grammar, &config).unwrap();
+            assert!(code.contains("  *[Symbol.iterator]() {\n"));
+            assert!(code.contains("      yield token;\n      if (token.kind === TokenKind.EOF) {\n        return;\n"));
+            assert!(code.contains("    for (const item of this) {\n"));
+        }
+    }
+
+    #[test]
+    fn test_token_kind_enum() {
+        let mut grammar = Grammar::new("Calculator".to_string(), GrammarType::Parser);
+        grammar.add_rule(Rule::parser_rule("expr".to_string()));
+        grammar.add_rule(Rule::lexer_rule("NUMBER".to_string()));
+
+        let generator = JavaScriptCodeGenerator::new();
+        let code = generator.generate(&grammar, &CodeGenConfig::default()).unwrap();
+        assert!(code.contains("const TokenKind = Object.freeze({"));
+        assert!(code.contains("  NUMBER: 'NUMBER',"));
+
+        let config = CodeGenConfig {
+            target_language: "typescript".to_string(),
+            ..CodeGenConfig::default()
+        };
+        let code = generator.generate(&grammar, &config).unwrap();
+        assert!(code.contains("enum TokenKind {"));
+        assert!(code.contains("  NUMBER = 'NUMBER',"));
+        assert!(code.contains("  EOF = 'EOF',"));
+        assert!(!code.contains("const TokenKind"));
+    }
+
+    #[test]
+    fn test_typescript_ast_discriminated_union() {
+        use crate::parser::GrammarParser;
+        use crate::traits::GrammarParser as GrammarParserTrait;
+
+        let grammar = GrammarParser::new()
+            .parse_string(
+                "grammar Calc; assignment: name=ID '=' value=expr; expr: left=ID | INT; ID: [a-z]+; INT: [0-9]+;",
+                "Calc.g4",
+            )
+            .unwrap();
+
+        let config = CodeGenConfig {
+            target_language: "typescript".to_string(),
+            ..CodeGenConfig::default()
+        };
+        let code = JavaScriptCodeGenerator::new().generate(&grammar, &config).unwrap();
+
+        assert!(code.contains(
+            "interface AssignmentNode {\n  kind: 'assignment';\n  children: AstNode[];\n  name: Token;\n  value: ExprNode;\n}"
+        ));
+        // The unlabeled INT gets a field of its own
+        assert!(code.contains(
+            "interface ExprNode {\n  kind: 'expr';\n  children: AstNode[];\n  left?: Token;\n  int?: Token;\n}"
+        ));
+        assert!(code.contains("type AstNode = AssignmentNode | ExprNode;"));
+        assert!(code.contains("{ kind: 'assignment', children, name, value }"));
+        assert!(code.contains("{ kind: 'expr', children, int }"));
+        assert!(code.contains("export type { AstNode, AssignmentNode, ExprNode };"));
+        assert!(!code.contains("class AssignmentNode"));
+    }
+
+    #[test]
+    fn test_unsupported_parser_element_is_an_error() {
+        let mut grammar = Grammar::new("Letters".to_string(), GrammarType::Parser);
+        let mut rule = Rule::parser_rule("letter".to_string());
+        let mut alt = Alternative::new();
+        alt.add_element(Element::UnicodeClass {
+            negated: false,
+            category: "L".to_string(),
+        });
+        rule.add_alternative(alt);
+        grammar.add_rule(rule);
+
+        let config = CodeGenConfig {
+            target_language: "typescript".to_string(),
+            ..CodeGenConfig::default()
+        };
+        let err = JavaScriptCodeGenerator::new().generate(&grammar, &config).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("rule letter: the typescript target cannot generate a Unicode property class in a parser rule"));
     }
 }
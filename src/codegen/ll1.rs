@@ -0,0 +1,541 @@
+//! LL(1) parse tables for table-driven parsers.
+//!
+//! Parser rules are flattened into BNF productions: each group, optional
+//! part and loop becomes a nonterminal of its own that belongs to the rule
+//! it is written in, so what it matches ends up in that rule's node. The
+//! FIRST and FOLLOW sets of the productions give the table, which maps a
+//! nonterminal and the kind of the next token to the production to
+//! expand. A grammar is LL(1) when no cell of the table is claimed by two
+//! productions.
+
+use super::common::literal_token_kinds;
+use super::rule_body::{rust_labeled_fields, rust_rule_ref_fields};
+use crate::ast::{Alternative, Element, Grammar, Rule};
+use crate::Diagnostic;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// Token kind standing for the end of input.
+pub const END: &str = "Eof";
+
+/// What a nonterminal of an [`Ll1Table`] stands for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NonterminalKind {
+    /// A parser rule, whose expansion builds the rule's node.
+    Rule,
+    /// A parenthesized group of alternatives.
+    Group,
+    /// An element marked `?`.
+    Optional,
+    /// The repetition of an element marked `*` or `+`.
+    Loop,
+}
+
+/// A nonterminal of an [`Ll1Table`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Nonterminal {
+    /// Index among the grammar's parser rules of the rule it belongs to.
+    pub rule: usize,
+    pub kind: NonterminalKind,
+}
+
+/// A symbol on the right-hand side of a production.
+///
+/// `field` is the index, among [`rust_labeled_fields`] of the enclosing
+/// rule, of the node field the matched token or rule node is stored in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Symbol {
+    /// A token of the given kind ([`END`] for the end of input).
+    Token { kind: String, field: Option<usize> },
+    Nonterminal { id: usize, field: Option<usize> },
+}
+
+/// A production `lhs -> symbols`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Production {
+    pub lhs: usize,
+    pub symbols: Vec<Symbol>,
+}
+
+/// LL(1) parse table of a grammar's parser rules.
+///
+/// The first nonterminals are the parser rules, in grammar order.
+#[derive(Debug, Clone)]
+pub struct Ll1Table {
+    pub nonterminals: Vec<Nonterminal>,
+    pub productions: Vec<Production>,
+    /// Per nonterminal, the production to expand for each token kind that
+    /// can come next, sorted by kind.
+    pub table: Vec<Vec<(String, usize)>>,
+    /// Per nonterminal, its production matching empty input, if any. It is
+    /// expanded on tokens missing from the table, leaving the error to
+    /// whatever has to come next.
+    pub defaults: Vec<Option<usize>>,
+    /// Per nonterminal, the tokens in its row of the table as shown in errors.
+    pub expected: Vec<String>,
+    /// How token kinds are shown in messages, e.g. `'+'` for `T__0`.
+    pub displays: HashMap<String, String>,
+}
+
+impl Ll1Table {
+    /// Build the table, or report why the grammar's parser rules cannot be
+    /// parsed by one: constructs with no LL(1) equivalent (E007) and
+    /// table cells claimed by several productions (E006).
+    pub fn build(grammar: &Grammar) -> Result<Self, Vec<Diagnostic>> {
+        let mut flattener = Flattener::new(grammar);
+        for (index, rule) in grammar.parser_rules().enumerate() {
+            flattener.flatten_rule(index, rule);
+        }
+        if !flattener.diagnostics.is_empty() {
+            return Err(flattener.diagnostics);
+        }
+
+        let Flattener {
+            nonterminals,
+            productions,
+            displays,
+            ..
+        } = flattener;
+        let sets = Sets::compute(nonterminals.len(), &productions);
+
+        let mut table = vec![BTreeMap::<String, usize>::new(); nonterminals.len()];
+        let mut defaults = vec![None; nonterminals.len()];
+        // Per (nonterminal, first production, second production), the
+        // tokens for which both apply
+        let mut conflicts: BTreeMap<(usize, usize, usize), BTreeSet<String>> = BTreeMap::new();
+        for (index, production) in productions.iter().enumerate() {
+            let (mut predict, nullable) = sets.first_of(&production.symbols);
+            if nullable {
+                predict.extend(sets.follow[production.lhs].iter().cloned());
+                defaults[production.lhs].get_or_insert(index);
+            }
+            for kind in predict {
+                if let Some(&other) = table[production.lhs].get(&kind) {
+                    conflicts
+                        .entry((production.lhs, other, index))
+                        .or_default()
+                        .insert(kind);
+                } else {
+                    table[production.lhs].insert(kind, index);
+                }
+            }
+        }
+
+        if !conflicts.is_empty() {
+            let rules: Vec<&Rule> = grammar.parser_rules().collect();
+            return Err(conflicts
+                .into_iter()
+                .map(|((lhs, first, second), kinds)| {
+                    let alt = |production: usize| {
+                        productions[..production]
+                            .iter()
+                            .filter(|p| p.lhs == lhs)
+                            .count()
+                            + 1
+                    };
+                    let tokens: Vec<&str> = kinds
+                        .iter()
+                        .map(|kind| displays.get(kind).map_or(kind.as_str(), String::as_str))
+                        .collect();
+                    let rule = rules[nonterminals[lhs].rule];
+                    let both = match nonterminals[lhs].kind {
+                        NonterminalKind::Rule => {
+                            format!("alternatives {} and {}", alt(first), alt(second))
+                        }
+                        NonterminalKind::Group => {
+                            format!("alternatives {} and {} of a group", alt(first), alt(second))
+                        }
+                        NonterminalKind::Optional => {
+                            "matching and skipping an optional part".to_string()
+                        }
+                        NonterminalKind::Loop => "repeating and leaving a loop".to_string(),
+                    };
+                    let mut diagnostic = Diagnostic::error(format!(
+                        "rule '{}' is not LL(1): {} both apply when the next token is {}",
+                        rule.name,
+                        both,
+                        tokens.join(" or ")
+                    ))
+                    .with_code("E006");
+                    if let Some(location) = &rule.location {
+                        diagnostic = diagnostic.with_location(location.clone());
+                    }
+                    diagnostic
+                })
+                .collect());
+        }
+
+        let expected = table
+            .iter()
+            .map(|row| {
+                let tokens: Vec<&str> = row
+                    .keys()
+                    .map(|kind| displays.get(kind).map_or(kind.as_str(), String::as_str))
+                    .collect();
+                match tokens.as_slice() {
+                    [] => "end of input".to_string(),
+                    [token] => token.to_string(),
+                    _ => format!("one of {}", tokens.join(", ")),
+                }
+            })
+            .collect();
+
+        Ok(Self {
+            nonterminals,
+            productions,
+            table: table.into_iter().map(|row| row.into_iter().collect()).collect(),
+            defaults,
+            expected,
+            displays,
+        })
+    }
+}
+
+/// Errors explaining why `grammar` cannot be parsed by a table-driven
+/// parser, empty when it can.
+pub fn ll1_diagnostics(grammar: &Grammar) -> Vec<Diagnostic> {
+    Ll1Table::build(grammar).err().unwrap_or_default()
+}
+
+/// Turns parser rules into productions.
+struct Flattener<'a> {
+    grammar: &'a Grammar,
+    literal_kinds: HashMap<String, String>,
+    rule_ids: HashMap<&'a str, usize>,
+    nonterminals: Vec<Nonterminal>,
+    productions: Vec<Production>,
+    /// How token kinds are shown in messages, e.g. `'+'` for `T__0`
+    displays: HashMap<String, String>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+/// Fields of the rule being flattened.
+struct RuleFields {
+    labels: Vec<String>,
+    refs: HashMap<String, String>,
+}
+
+impl RuleFields {
+    fn of(&self, label: &Option<String>, rule_ref: Option<&str>) -> Option<usize> {
+        let name = match (label, rule_ref) {
+            (Some(label), _) => label,
+            (None, Some(rule)) => self.refs.get(rule)?,
+            (None, None) => return None,
+        };
+        self.labels.iter().position(|field| field == name)
+    }
+}
+
+impl<'a> Flattener<'a> {
+    fn new(grammar: &'a Grammar) -> Self {
+        let rule_ids: HashMap<&str, usize> = grammar
+            .parser_rules()
+            .enumerate()
+            .map(|(index, rule)| (rule.name.as_str(), index))
+            .collect();
+        let nonterminals = (0..rule_ids.len())
+            .map(|rule| Nonterminal {
+                rule,
+                kind: NonterminalKind::Rule,
+            })
+            .collect();
+        Self {
+            grammar,
+            literal_kinds: literal_token_kinds(grammar),
+            rule_ids,
+            nonterminals,
+            productions: Vec::new(),
+            displays: HashMap::from([(END.to_string(), "end of input".to_string())]),
+            diagnostics: Vec::new(),
+        }
+    }
+
+    fn unsupported(&mut self, rule: &Rule, what: &str) {
+        let mut diagnostic = Diagnostic::error(format!(
+            "rule '{}' uses {}, which table-driven parsers do not support",
+            rule.name, what
+        ))
+        .with_code("E007");
+        if let Some(location) = &rule.location {
+            diagnostic = diagnostic.with_location(location.clone());
+        }
+        if !self.diagnostics.iter().any(|d| d.message == diagnostic.message) {
+            self.diagnostics.push(diagnostic);
+        }
+    }
+
+    fn flatten_rule(&mut self, index: usize, rule: &Rule) {
+        for (present, what) in [
+            (!rule.arguments.is_empty(), "arguments"),
+            (!rule.returns.is_empty(), "return values"),
+            (!rule.locals.is_empty(), "locals"),
+            (!rule.catch_clauses.is_empty() || rule.finally_clause.is_some(), "exception handlers"),
+        ] {
+            if present {
+                self.unsupported(rule, what);
+            }
+        }
+
+        let fields = RuleFields {
+            labels: rust_labeled_fields(rule, self.grammar)
+                .into_iter()
+                .map(|(name, _, _)| name)
+                .collect(),
+            refs: rust_rule_ref_fields(rule, self.grammar).into_iter().collect(),
+        };
+        if rule.alternatives.is_empty() {
+            self.productions.push(Production {
+                lhs: index,
+                symbols: Vec::new(),
+            });
+        }
+        for alt in &rule.alternatives {
+            let symbols = self.sequence(rule, index, alt, &fields);
+            self.productions.push(Production { lhs: index, symbols });
+        }
+    }
+
+    fn sequence(
+        &mut self,
+        rule: &Rule,
+        index: usize,
+        alt: &Alternative,
+        fields: &RuleFields,
+    ) -> Vec<Symbol> {
+        let mut symbols = Vec::new();
+        for element in &alt.elements {
+            self.element(rule, index, element, fields, &mut symbols);
+        }
+        symbols
+    }
+
+    /// A nonterminal of `rule` with the given productions.
+    fn nonterminal(&mut self, index: usize, kind: NonterminalKind, bodies: Vec<Vec<Symbol>>) -> usize {
+        let id = self.nonterminals.len();
+        self.nonterminals.push(Nonterminal { rule: index, kind });
+        for symbols in bodies {
+            self.productions.push(Production { lhs: id, symbols });
+        }
+        id
+    }
+
+    fn token(&mut self, kind: &str, display: String, field: Option<usize>) -> Symbol {
+        self.displays.entry(kind.to_string()).or_insert(display);
+        Symbol::Token {
+            kind: kind.to_string(),
+            field,
+        }
+    }
+
+    fn element(
+        &mut self,
+        rule: &Rule,
+        index: usize,
+        element: &Element,
+        fields: &RuleFields,
+        symbols: &mut Vec<Symbol>,
+    ) {
+        match element {
+            Element::Eof => symbols.push(self.token(END, "end of input".to_string(), None)),
+            Element::RuleRef { name, .. } if name == "EOF" => {
+                symbols.push(self.token(END, "end of input".to_string(), None));
+            }
+            Element::RuleRef { name, label, .. } => match self.rule_ids.get(name.as_str()) {
+                Some(&id) => symbols.push(Symbol::Nonterminal {
+                    id,
+                    field: fields.of(label, Some(name)),
+                }),
+                None => symbols.push(self.token(name, name.clone(), fields.of(label, None))),
+            },
+            Element::Terminal { value, label, .. } => {
+                symbols.push(self.token(value, value.clone(), fields.of(label, None)));
+            }
+            Element::StringLiteral { value, label, .. } => {
+                let kind = self
+                    .literal_kinds
+                    .get(value)
+                    .cloned()
+                    .unwrap_or_else(|| value.clone());
+                let display = format!("'{}'", value);
+                symbols.push(self.token(&kind, display, fields.of(label, None)));
+            }
+            Element::Group { alternatives } if alternatives.len() == 1 => {
+                symbols.extend(self.sequence(rule, index, &alternatives[0], fields));
+            }
+            Element::Group { alternatives } => {
+                let bodies = alternatives
+                    .iter()
+                    .map(|alt| self.sequence(rule, index, alt, fields))
+                    .collect();
+                let id = self.nonterminal(index, NonterminalKind::Group, bodies);
+                symbols.push(Symbol::Nonterminal { id, field: None });
+            }
+            Element::Optional { element, .. } => {
+                let mut body = Vec::new();
+                self.element(rule, index, element, fields, &mut body);
+                let id = self.nonterminal(index, NonterminalKind::Optional, vec![body, Vec::new()]);
+                symbols.push(Symbol::Nonterminal { id, field: None });
+            }
+            Element::ZeroOrMore { element: repeated, .. }
+            | Element::OneOrMore { element: repeated, .. } => {
+                // x* is L where L: x L | ε, and x+ is x L
+                let mut body = Vec::new();
+                self.element(rule, index, repeated, fields, &mut body);
+                let id = self.nonterminals.len();
+                let mut repeat = body.clone();
+                repeat.push(Symbol::Nonterminal { id, field: None });
+                self.nonterminal(index, NonterminalKind::Loop, vec![repeat, Vec::new()]);
+                if matches!(element, Element::OneOrMore { .. }) {
+                    symbols.extend(body);
+                }
+                symbols.push(Symbol::Nonterminal { id, field: None });
+            }
+            Element::Action { .. } => self.unsupported(rule, "actions"),
+            Element::Predicate { .. } => self.unsupported(rule, "semantic predicates"),
+            Element::Wildcard => self.unsupported(rule, "the wildcard '.'"),
+            Element::Not { .. } => self.unsupported(rule, "negated sets"),
+            Element::CharRange { .. } | Element::CharClass { .. } | Element::UnicodeClass { .. } => {
+                self.unsupported(rule, "character classes")
+            }
+        }
+    }
+}
+
+/// FIRST and FOLLOW sets of the nonterminals of a list of productions.
+struct Sets {
+    first: Vec<BTreeSet<String>>,
+    nullable: Vec<bool>,
+    follow: Vec<BTreeSet<String>>,
+}
+
+impl Sets {
+    fn compute(count: usize, productions: &[Production]) -> Self {
+        let mut sets = Self {
+            first: vec![BTreeSet::new(); count],
+            nullable: vec![false; count],
+            follow: vec![BTreeSet::new(); count],
+        };
+        if count == 0 {
+            return sets;
+        }
+        sets.follow[0].insert(END.to_string());
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for production in productions {
+                let (first, nullable) = sets.first_of(&production.symbols);
+                let lhs = production.lhs;
+                let before = sets.first[lhs].len();
+                sets.first[lhs].extend(first);
+                changed |= sets.first[lhs].len() != before;
+                if nullable && !sets.nullable[lhs] {
+                    sets.nullable[lhs] = true;
+                    changed = true;
+                }
+            }
+        }
+
+        changed = true;
+        while changed {
+            changed = false;
+            for production in productions {
+                for (position, symbol) in production.symbols.iter().enumerate() {
+                    let Symbol::Nonterminal { id, .. } = symbol else {
+                        continue;
+                    };
+                    let (mut follow, nullable) = sets.first_of(&production.symbols[position + 1..]);
+                    if nullable {
+                        follow.extend(sets.follow[production.lhs].iter().cloned());
+                    }
+                    let before = sets.follow[*id].len();
+                    sets.follow[*id].extend(follow);
+                    changed |= sets.follow[*id].len() != before;
+                }
+            }
+        }
+
+        sets
+    }
+
+    /// Tokens `symbols` can start with, and whether they can match empty input.
+    fn first_of(&self, symbols: &[Symbol]) -> (BTreeSet<String>, bool) {
+        let mut first = BTreeSet::new();
+        for symbol in symbols {
+            match symbol {
+                Symbol::Token { kind, .. } => {
+                    first.insert(kind.clone());
+                    return (first, false);
+                }
+                Symbol::Nonterminal { id, .. } => {
+                    first.extend(self.first[*id].iter().cloned());
+                    if !self.nullable[*id] {
+                        return (first, false);
+                    }
+                }
+            }
+        }
+        (first, true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::GrammarParser;
+    use crate::traits::GrammarParser as GrammarParserTrait;
+
+    fn parse(source: &str) -> Grammar {
+        GrammarParser::new().parse_string(source, "Test.g4").unwrap()
+    }
+
+    fn messages(source: &str) -> Vec<String> {
+        ll1_diagnostics(&parse(source))
+            .into_iter()
+            .map(|d| format!("{} {}", d.code.unwrap_or_default(), d.message))
+            .collect()
+    }
+
+    #[test]
+    fn test_table_of_ll1_grammar() {
+        let grammar = parse("grammar T; list: '[' ID (',' ID)* ']'; ID: [a-z]+;");
+        let table = Ll1Table::build(&grammar).unwrap();
+
+        // list, then the loop, whose productions come first
+        assert_eq!(table.nonterminals.len(), 2);
+        assert_eq!(table.nonterminals[1].kind, NonterminalKind::Loop);
+        assert_eq!(table.table[0], vec![("T__0".to_string(), 2)]);
+        assert_eq!(
+            table.table[1],
+            vec![("T__1".to_string(), 0), ("T__2".to_string(), 1)]
+        );
+        assert_eq!(table.defaults, vec![None, Some(1)]);
+        assert_eq!(table.expected[1], "one of ',', ']'");
+    }
+
+    #[test]
+    fn test_conflicts_are_reported() {
+        assert_eq!(
+            messages("grammar T; stmt: ID '=' ID | ID ';'; ID: [a-z]+;"),
+            vec!["E006 rule 'stmt' is not LL(1): alternatives 1 and 2 both apply when the next token is ID"]
+        );
+        assert_eq!(
+            messages("grammar T; args: ID* ID; ID: [a-z]+;"),
+            vec!["E006 rule 'args' is not LL(1): repeating and leaving a loop both apply when the next token is ID"]
+        );
+        assert_eq!(
+            messages("grammar T; expr: expr '+' NUM | NUM; NUM: [0-9]+;"),
+            vec!["E006 rule 'expr' is not LL(1): alternatives 1 and 2 both apply when the next token is NUM"]
+        );
+    }
+
+    #[test]
+    fn test_unsupported_constructs_are_reported() {
+        assert_eq!(
+            messages("grammar T; stmt: ID . . ; block[int depth]: ID; ID: [a-z]+;"),
+            vec![
+                "E007 rule 'stmt' uses the wildcard '.', which table-driven parsers do not support",
+                "E007 rule 'block' uses arguments, which table-driven parsers do not support",
+            ]
+        );
+    }
+}
@@ -1,38 +1,52 @@
 //! Lookup table generation for efficient character class matching.
 //!
 //! This module generates const lookup tables at compile time for fast
-//! character classification without runtime branching.
+//! character classification without runtime branching. Characters below
+//! U+0100 are classified by indexing an array; the rest of the code space,
+//! up to U+10FFFF, by binary search over a sorted list of intervals.
 
+use super::dfa::inline_fragments;
 use crate::ast::Rule;
-use std::collections::HashMap;
 
 /// Character class ID for lookup table
 pub type CharClassId = u8;
 
+/// Class ID of characters that are in no class.
+pub const NO_CLASS: CharClassId = CharClassId::MAX;
+
+/// Characters below this are classified by the array.
+const TABLE_SIZE: u32 = 256;
+
 /// Lookup table builder for character classes
 pub struct LookupTableBuilder {
-    /// Map from character to class ID
-    char_to_class: HashMap<char, CharClassId>,
+    /// Disjoint `(start, end, class)` intervals sorted by start
+    intervals: Vec<(char, char, CharClassId)>,
     /// Next available class ID
     next_class_id: CharClassId,
     /// Class names for documentation
-    class_names: HashMap<CharClassId, String>,
+    class_names: Vec<String>,
+    /// Excluded ranges of each distinct negated set (`~[...]`, `~'x'`)
+    negated_sets: Vec<Vec<(char, char)>>,
 }
 
 impl LookupTableBuilder {
     pub fn new() -> Self {
         Self {
-            char_to_class: HashMap::new(),
+            intervals: Vec::new(),
             next_class_id: 0,
-            class_names: HashMap::new(),
+            class_names: Vec::new(),
+            negated_sets: Vec::new(),
         }
     }
 
     /// Build lookup table from lexer rules
+    ///
+    /// Fragment references are inlined first, so the characters of a
+    /// fragment are classified for each token rule that uses it.
     pub fn build_from_rules(&mut self, rules: &[&Rule]) {
-        for rule in rules {
+        for rule in inline_fragments(rules) {
             if !rule.is_fragment {
-                self.analyze_rule(rule);
+                self.analyze_rule(&rule);
             }
         }
     }
@@ -51,29 +65,18 @@ impl LookupTableBuilder {
 
         match element {
             Element::Terminal { value, .. } | Element::StringLiteral { value, .. } => {
-                // Add each character to the lookup table
+                // Each character of a literal gets a class of its own
                 for ch in value.chars() {
-                    if !self.char_to_class.contains_key(&ch) {
-                        let class_id = self.next_class_id;
-                        self.char_to_class.insert(ch, class_id);
-                        self.class_names
-                            .insert(class_id, format!("{}_{}", rule_name, ch.escape_default()));
-                        self.next_class_id += 1;
-                    }
+                    self.add_class(&[(ch, ch)], || format!("{}_{}", rule_name, ch.escape_default()));
                 }
             }
             Element::CharRange { start, end } => {
-                // Add range to lookup table
-                for ch in *start..=*end {
-                    if !self.char_to_class.contains_key(&ch) {
-                        let class_id = self.next_class_id;
-                        self.char_to_class.insert(ch, class_id);
-                        self.class_names
-                            .insert(class_id, format!("{}_range", rule_name));
-                        self.next_class_id += 1;
-                    }
-                }
+                self.add_class(&[(*start, *end)], || format!("{}_range", rule_name));
             }
+            Element::CharClass {
+                negated: false,
+                ranges,
+            } => self.add_class(ranges, || format!("{}_range", rule_name)),
             Element::Group { alternatives } => {
                 for alt in alternatives {
                     for elem in &alt.elements {
@@ -81,16 +84,105 @@ impl LookupTableBuilder {
                     }
                 }
             }
+            Element::CharClass {
+                negated: true,
+                ranges,
+            } => self.add_negated_set(ranges.clone()),
+            Element::Not { element } => {
+                let excluded = match element.as_ref() {
+                    Element::CharClass {
+                        negated: false,
+                        ranges,
+                    } => Some(ranges.clone()),
+                    Element::CharRange { start, end } => Some(vec![(*start, *end)]),
+                    Element::Terminal { value, .. } | Element::StringLiteral { value, .. }
+                        if value.chars().count() == 1 =>
+                    {
+                        let ch = value.chars().next().unwrap();
+                        Some(vec![(ch, ch)])
+                    }
+                    _ => None,
+                };
+                if let Some(excluded) = excluded {
+                    self.add_negated_set(excluded);
+                }
+                self.analyze_element(element, rule_name);
+            }
             Element::Optional { element, .. }
             | Element::ZeroOrMore { element, .. }
-            | Element::OneOrMore { element, .. }
-            | Element::Not { element } => {
+            | Element::OneOrMore { element, .. } => {
                 self.analyze_element(element, rule_name);
             }
             _ => {}
         }
     }
 
+    /// Put the characters of `ranges` not classified yet into a new class.
+    /// Characters keep the class they were first seen in, and once every
+    /// ID below [`NO_CLASS`] is taken, new ones stay unclassified.
+    fn add_class(&mut self, ranges: &[(char, char)], name: impl FnOnce() -> String) {
+        if self.next_class_id == NO_CLASS {
+            return;
+        }
+
+        // Gaps between the intervals overlapping each range
+        let mut gaps = Vec::new();
+        for &(start, end) in ranges.iter().filter(|(start, end)| start <= end) {
+            let mut next = Some(start);
+            for &(lo, hi, _) in self.intervals.iter().filter(|&&(lo, hi, _)| lo <= end && hi >= start) {
+                if let Some(from) = next.filter(|from| *from < lo) {
+                    gaps.push((from, char_before(lo)));
+                }
+                next = char_after(hi);
+            }
+            if let Some(from) = next.filter(|from| *from <= end) {
+                gaps.push((from, end));
+            }
+        }
+        if gaps.is_empty() {
+            return;
+        }
+
+        let class_id = self.next_class_id;
+        self.next_class_id += 1;
+        self.class_names.push(name());
+        self.intervals
+            .extend(gaps.into_iter().map(|(lo, hi)| (lo, hi, class_id)));
+        self.intervals.sort_by_key(|&(lo, _, _)| lo);
+    }
+
+    /// Class of `ch`, or [`NO_CLASS`].
+    pub fn class_of(&self, ch: char) -> CharClassId {
+        match self.intervals.binary_search_by(|&(lo, hi, _)| interval_order(lo, hi, ch)) {
+            Ok(index) => self.intervals[index].2,
+            Err(_) => NO_CLASS,
+        }
+    }
+
+    fn add_negated_set(&mut self, mut ranges: Vec<(char, char)>) {
+        ranges.sort();
+        if !self.negated_sets.contains(&ranges) {
+            self.negated_sets.push(ranges);
+        }
+    }
+
+    /// Intervals of characters at or above [`TABLE_SIZE`], which the array
+    /// does not cover.
+    fn high_intervals(&self) -> Vec<(char, char, CharClassId)> {
+        self.intervals
+            .iter()
+            .filter(|&&(_, hi, _)| hi as u32 >= TABLE_SIZE)
+            .map(|&(lo, hi, class)| {
+                let lo = if (lo as u32) < TABLE_SIZE {
+                    char::from_u32(TABLE_SIZE).unwrap()
+                } else {
+                    lo
+                };
+                (lo, hi, class)
+            })
+            .collect()
+    }
+
     /// Generate Rust code for const lookup table
     pub fn generate_lookup_table(&self) -> String {
         let mut code = String::new();
@@ -98,17 +190,17 @@ impl LookupTableBuilder {
         code.push_str("    /// Character class lookup table.\n");
         code.push_str("    /// \n");
         code.push_str(
-            "    /// Maps each character to its character class ID for efficient matching.\n",
+            "    /// Maps each character below U+0100 to its character class ID for efficient matching.\n",
         );
         code.push_str(
             "    /// This table is generated at compile time and stored as a const array.\n",
         );
-        code.push_str("    const CHAR_CLASS_TABLE: [u8; 256] = [\n");
+        code.push_str(&format!("    const CHAR_CLASS_TABLE: [u8; {}] = [\n", TABLE_SIZE));
 
         // Generate lookup table for ASCII characters (0-255)
-        for i in 0..256 {
-            let ch = i as u8 as char;
-            let class_id = self.char_to_class.get(&ch).copied().unwrap_or(255);
+        for i in 0..TABLE_SIZE {
+            let ch = char::from_u32(i).unwrap();
+            let class_id = self.class_of(ch);
 
             if i % 16 == 0 {
                 code.push_str("        ");
@@ -116,7 +208,7 @@ impl LookupTableBuilder {
 
             code.push_str(&format!("{:3}", class_id));
 
-            if i < 255 {
+            if i < TABLE_SIZE - 1 {
                 code.push_str(", ");
             }
 
@@ -127,17 +219,41 @@ impl LookupTableBuilder {
 
         code.push_str("\n    ];\n\n");
 
+        let high = self.high_intervals();
+        code.push_str("    /// Character classes of the characters from U+0100 up, as disjoint\n");
+        code.push_str("    /// `(start, end, class)` intervals sorted by start.\n");
+        code.push_str(&format!(
+            "    const CHAR_CLASS_RANGES: [(char, char, u8); {}] = [\n",
+            high.len()
+        ));
+        for (lo, hi, class) in &high {
+            code.push_str(&format!(
+                "        ('\\u{{{:X}}}', '\\u{{{:X}}}', {}),\n",
+                *lo as u32, *hi as u32, class
+            ));
+        }
+        code.push_str("    ];\n\n");
+
         // Generate helper function to get character class
         code.push_str("    /// Get the character class ID for a given character.\n");
         code.push_str("    /// \n");
         code.push_str("    /// Returns 255 for characters not in any class.\n");
         code.push_str("    #[inline]\n");
         code.push_str("    fn get_char_class(ch: char) -> u8 {\n");
-        code.push_str("        if (ch as u32) < 256 {\n");
-        code.push_str("            Self::CHAR_CLASS_TABLE[ch as usize]\n");
-        code.push_str("        } else {\n");
-        code.push_str("            255 // Unknown class for non-ASCII\n");
+        code.push_str(&format!("        if (ch as u32) < {} {{\n", TABLE_SIZE));
+        code.push_str("            return Self::CHAR_CLASS_TABLE[ch as usize];\n");
         code.push_str("        }\n");
+        code.push_str("        Self::CHAR_CLASS_RANGES\n");
+        code.push_str("            .binary_search_by(|&(lo, hi, _)| {\n");
+        code.push_str("                if hi < ch {\n");
+        code.push_str("                    std::cmp::Ordering::Less\n");
+        code.push_str("                } else if lo > ch {\n");
+        code.push_str("                    std::cmp::Ordering::Greater\n");
+        code.push_str("                } else {\n");
+        code.push_str("                    std::cmp::Ordering::Equal\n");
+        code.push_str("                }\n");
+        code.push_str("            })\n");
+        code.push_str("            .map_or(255, |index| Self::CHAR_CLASS_RANGES[index].2)\n");
         code.push_str("    }\n\n");
 
         code
@@ -171,9 +287,14 @@ impl LookupTableBuilder {
     /// Get statistics about the lookup table
     pub fn stats(&self) -> LookupTableStats {
         LookupTableStats {
-            total_chars: self.char_to_class.len(),
+            total_chars: self
+                .intervals
+                .iter()
+                .map(|&(lo, hi, _)| (hi as u32 - lo as u32) as usize + 1)
+                .sum(),
             total_classes: self.next_class_id as usize,
-            table_size: 256, // ASCII table size
+            table_size: TABLE_SIZE as usize,
+            range_count: self.high_intervals().len(),
         }
     }
 }
@@ -190,16 +311,46 @@ pub struct LookupTableStats {
     pub total_chars: usize,
     pub total_classes: usize,
     pub table_size: usize,
+    /// Intervals searched for characters beyond the array
+    pub range_count: usize,
 }
 
 impl LookupTableStats {
     pub fn memory_bytes(&self) -> usize {
-        self.table_size // 1 byte per entry
+        // 1 byte per array entry, two chars and a class per interval
+        self.table_size + self.range_count * std::mem::size_of::<(char, char, u8)>()
+    }
+}
+
+/// How the interval `lo..=hi` compares to `ch`, for binary search.
+fn interval_order(lo: char, hi: char, ch: char) -> std::cmp::Ordering {
+    if hi < ch {
+        std::cmp::Ordering::Less
+    } else if lo > ch {
+        std::cmp::Ordering::Greater
+    } else {
+        std::cmp::Ordering::Equal
+    }
+}
+
+/// The character before `ch`, skipping the surrogate gap.
+fn char_before(ch: char) -> char {
+    match ch as u32 {
+        0xE000 => '\u{D7FF}',
+        code => char::from_u32(code - 1).unwrap(),
+    }
+}
+
+/// The character after `ch`, if any, skipping the surrogate gap.
+fn char_after(ch: char) -> Option<char> {
+    match ch as u32 {
+        0xD7FF => Some('\u{E000}'),
+        code => char::from_u32(code + 1),
     }
 }
 
 /// Generate optimized character matching using lookup table
-pub fn generate_optimized_char_match(_table: &LookupTableBuilder) -> String {
+pub fn generate_optimized_char_match(table: &LookupTableBuilder) -> String {
     let mut code = String::new();
 
     code.push_str("    /// Match character using lookup table.\n");
@@ -219,6 +370,32 @@ pub fn generate_optimized_char_match(_table: &LookupTableBuilder) -> String {
     code.push_str("        class >= start_class && class <= end_class\n");
     code.push_str("    }\n\n");
 
+    if !table.negated_sets.is_empty() {
+        code.push_str("    /// Excluded ranges of the grammar's negated sets, such as `~[\"\\\\]`.\n");
+        code.push_str(&format!(
+            "    const NEGATED_SETS: [&'static [(char, char)]; {}] = [\n",
+            table.negated_sets.len()
+        ));
+        for set in &table.negated_sets {
+            let ranges: Vec<String> = set
+                .iter()
+                .map(|(lo, hi)| format!("({:?}, {:?})", lo, hi))
+                .collect();
+            code.push_str(&format!("        &[{}],\n", ranges.join(", ")));
+        }
+        code.push_str("    ];\n\n");
+
+        code.push_str("    /// Check if character is outside negated set `set`.\n");
+        code.push_str("    /// \n");
+        code.push_str("    /// End of input is never a match; callers only pass real characters.\n");
+        code.push_str("    #[inline]\n");
+        code.push_str("    fn match_char_negated(&self, ch: char, set: usize) -> bool {\n");
+        code.push_str("        !Self::NEGATED_SETS[set]\n");
+        code.push_str("            .iter()\n");
+        code.push_str("            .any(|&(lo, hi)| lo <= ch && ch <= hi)\n");
+        code.push_str("    }\n\n");
+    }
+
     code
 }
 
@@ -230,7 +407,7 @@ mod tests {
     fn test_lookup_table_builder() {
         let builder = LookupTableBuilder::new();
         assert_eq!(builder.next_class_id, 0);
-        assert_eq!(builder.char_to_class.len(), 0);
+        assert!(builder.intervals.is_empty());
     }
 
     #[test]
@@ -251,6 +428,87 @@ mod tests {
         assert!(code.contains("get_char_class"));
     }
 
+    #[test]
+    fn test_ranges_beyond_the_bmp() {
+        use crate::ast::{Alternative, Element};
+
+        let mut rule = Rule::lexer_rule("EMOJI".to_string());
+        let mut alt = Alternative::new();
+        alt.add_element(Element::CharClass {
+            negated: false,
+            ranges: vec![('a', 'z'), ('\u{1F600}', '\u{1F64F}')],
+        });
+        alt.add_element(Element::string_literal("m\u{1F680}".to_string()));
+        rule.add_alternative(alt);
+
+        let mut builder = LookupTableBuilder::new();
+        builder.build_from_rules(&[&rule]);
+
+        // The literal's 'm' is already in the range class; only the rocket is new
+        assert_eq!(builder.class_of('a'), 0);
+        assert_eq!(builder.class_of('m'), 0);
+        assert_eq!(builder.class_of('\u{1F600}'), 0);
+        assert_eq!(builder.class_of('\u{1F64F}'), 0);
+        assert_eq!(builder.class_of('\u{1F680}'), 1);
+        assert_eq!(builder.class_of('\u{1F650}'), NO_CLASS);
+        assert_eq!(builder.class_of('A'), NO_CLASS);
+
+        let stats = builder.stats();
+        assert_eq!(stats.total_chars, 26 + 80 + 1);
+        assert_eq!(stats.total_classes, 2);
+        assert_eq!(stats.range_count, 2);
+
+        let code = builder.generate_lookup_table();
+        assert!(code.contains("('\\u{1F600}', '\\u{1F64F}', 0),\n"));
+        assert!(code.contains("const CHAR_CLASS_RANGES: [(char, char, u8); 2] = ["));
+    }
+
+    #[test]
+    fn test_overlapping_ranges_keep_their_first_class() {
+        let mut builder = LookupTableBuilder::new();
+        builder.add_class(&[('d', 'f')], String::new);
+        builder.add_class(&[('a', 'z')], String::new);
+        builder.add_class(&[('\u{D000}', '\u{10FFFF}')], String::new);
+        builder.add_class(&[('b', 'e')], String::new);
+
+        assert_eq!(
+            builder.intervals,
+            vec![
+                ('a', 'c', 1),
+                ('d', 'f', 0),
+                ('g', 'z', 1),
+                ('\u{D000}', '\u{10FFFF}', 2),
+            ]
+        );
+        assert_eq!(builder.next_class_id, 3);
+        assert_eq!(builder.class_of('\u{E000}'), 2);
+    }
+
+    #[test]
+    fn test_negated_sets() {
+        use crate::ast::{Alternative, Element};
+
+        let mut rule = Rule::lexer_rule("STRING".to_string());
+        let mut alt = Alternative::new();
+        alt.add_element(Element::string_literal("\"".to_string()));
+        alt.add_element(Element::zero_or_more(Element::Not {
+            element: Box::new(Element::CharClass {
+                negated: false,
+                ranges: vec![('"', '"'), ('\\', '\\')],
+            }),
+        }));
+        alt.add_element(Element::string_literal("\"".to_string()));
+        rule.add_alternative(alt);
+
+        let mut builder = LookupTableBuilder::new();
+        builder.build_from_rules(&[&rule]);
+
+        assert_eq!(builder.negated_sets, vec![vec![('"', '"'), ('\\', '\\')]]);
+        let code = generate_optimized_char_match(&builder);
+        assert!(code.contains("&[('\"', '\"'), ('\\\\', '\\\\')],"));
+        assert!(code.contains("fn match_char_negated(&self, ch: char, set: usize) -> bool"));
+    }
+
     #[test]
     fn test_generate_token_type_table() {
         let builder = LookupTableBuilder::new();
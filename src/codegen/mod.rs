@@ -4,6 +4,7 @@ pub mod actions;
 pub mod common;
 pub mod dfa;
 pub mod javascript;
+pub mod ll1;
 pub mod lookup_table;
 pub mod modes;
 pub mod pattern_match;
@@ -19,9 +20,70 @@ pub use python::PythonCodeGenerator;
 pub use rust::RustCodeGenerator;
 
 use crate::analysis::AnalysisResult;
-use crate::{types::CodeGenConfig, Result};
+use crate::ast::Grammar;
+use crate::{types::CodeGenConfig, CodeGenerator as CodeGeneratorTrait, Diagnostic, Result};
 
-pub use registry::LanguageRegistry;
+pub use registry::{BoxedGenerator, FileLayout, LanguageRegistry};
+
+/// Options interpreted by minipg itself rather than by a target generator.
+const TOOL_OPTIONS: &[&str] = &["language", "tokenVocab"];
+
+/// Warn about grammar options that `generator` does not act on.
+///
+/// Generators silently ignore options they don't implement, so these
+/// diagnostics let users know e.g. `contextSuperClass` has no effect on a
+/// target.
+pub fn unsupported_option_warnings(
+    grammar: &Grammar,
+    generator: &dyn CodeGeneratorTrait<Input = Grammar, Config = CodeGenConfig>,
+) -> Vec<Diagnostic> {
+    let supported = generator.supported_options();
+    let mut ignored: Vec<&String> = grammar
+        .options
+        .keys()
+        .filter(|key| !TOOL_OPTIONS.contains(&key.as_str()) && !supported.contains(&key.as_str()))
+        .collect();
+    ignored.sort();
+
+    ignored
+        .into_iter()
+        .map(|key| {
+            Diagnostic::warning(format!(
+                "option '{}' is not supported by the {} target and will be ignored",
+                key,
+                generator.target_language()
+            ))
+            .with_code("W005")
+        })
+        .collect()
+}
+
+/// Choose the target language for `grammar`.
+///
+/// A language given on the command line wins over the grammar's `language`
+/// option, which wins over `default` (normally
+/// [`CodeGenConfig::target_language`]'s default). When the command line and
+/// the grammar name different languages, a warning is returned as well.
+pub fn resolve_target_language(
+    requested: Option<&str>,
+    grammar: &Grammar,
+    default: &str,
+) -> (String, Option<Diagnostic>) {
+    let from_grammar = grammar.options.get("language").map(String::as_str);
+
+    match (requested, from_grammar) {
+        (Some(requested), Some(option)) if !requested.eq_ignore_ascii_case(option) => {
+            let warning = Diagnostic::warning(format!(
+                "target language '{}' overrides the grammar's language option '{}'",
+                requested, option
+            ))
+            .with_code("W007");
+            (requested.to_string(), Some(warning))
+        }
+        (Some(language), _) | (None, Some(language)) => (language.to_string(), None),
+        (None, None) => (default.to_string(), None),
+    }
+}
 
 /// Main code generator dispatcher.
 pub struct CodeGenerator {
@@ -60,3 +122,64 @@ impl CodeGenerator {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::GrammarType;
+
+    #[test]
+    fn test_unsupported_option_warnings() {
+        let mut grammar = Grammar::new("Calc".to_string(), GrammarType::Combined);
+        grammar.add_option("contextSuperClass".to_string(), "BaseContext".to_string());
+        grammar.add_option("superClass".to_string(), "BaseParser".to_string());
+        grammar.add_option("language".to_string(), "rust".to_string());
+
+        let warnings = unsupported_option_warnings(&grammar, &RustCodeGenerator::new());
+
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].severity, crate::DiagnosticSeverity::Warning);
+        assert_eq!(
+            warnings[0].message,
+            "option 'contextSuperClass' is not supported by the rust target and will be ignored"
+        );
+    }
+
+    #[test]
+    fn test_case_insensitive_is_supported_by_rust_only() {
+        let mut grammar = Grammar::new("Sql".to_string(), GrammarType::Combined);
+        grammar.add_option("caseInsensitive".to_string(), "true".to_string());
+
+        assert!(unsupported_option_warnings(&grammar, &RustCodeGenerator::new()).is_empty());
+        let warnings = unsupported_option_warnings(&grammar, &PythonCodeGenerator::new());
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].code.as_deref(), Some("W005"));
+    }
+
+    #[test]
+    fn test_resolve_target_language_precedence() {
+        let mut grammar = Grammar::new("Calc".to_string(), GrammarType::Combined);
+        let (language, warning) = resolve_target_language(None, &grammar, "rust");
+        assert_eq!(language, "rust");
+        assert!(warning.is_none());
+
+        grammar.add_option("language".to_string(), "python".to_string());
+        assert_eq!(resolve_target_language(None, &grammar, "rust").0, "python");
+        assert!(resolve_target_language(Some("Python"), &grammar, "rust").1.is_none());
+
+        let (language, warning) = resolve_target_language(Some("go"), &grammar, "rust");
+        assert_eq!(language, "go");
+        let warning = warning.unwrap();
+        assert_eq!(warning.code.as_deref(), Some("W007"));
+        assert_eq!(
+            warning.message,
+            "target language 'go' overrides the grammar's language option 'python'"
+        );
+    }
+
+    #[test]
+    fn test_no_warnings_without_options() {
+        let grammar = Grammar::new("Calc".to_string(), GrammarType::Combined);
+        assert!(unsupported_option_warnings(&grammar, &PythonCodeGenerator::new()).is_empty());
+    }
+}
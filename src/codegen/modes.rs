@@ -1,22 +1,81 @@
 //! Lexer modes and channels code generation.
 
-use crate::ast::Grammar;
+use crate::ast::{Grammar, LexerCommand};
+use std::collections::{HashMap, HashSet};
+
+/// Generate Rust statements applying `commands` to a mutable `token`, in order.
+///
+/// `channels` maps channel names to numbers, `token_kinds` lists the
+/// `TokenKind` variants that `type(...)` may switch to, and `modes` lists the
+/// modes the lexer can switch to, empty when it does not track modes. `skip`
+/// returns `None` from the enclosing function once the other commands have
+/// run, so `-> skip, pushMode(X)` still enters `X`. `more` needs a lexer that
+/// can join matches, so it is only noted in a comment.
+pub fn generate_rust_lexer_commands(
+    commands: &[LexerCommand],
+    channels: &HashMap<String, u32>,
+    token_kinds: &HashSet<&str>,
+    modes: &HashSet<&str>,
+    pad: &str,
+) -> String {
+    let mut code = String::new();
+
+    for command in commands {
+        match command {
+            LexerCommand::Skip => {}
+            LexerCommand::Channel(name) => match channels.get(name) {
+                Some(channel) => code.push_str(&format!("{}token.channel = {};\n", pad, channel)),
+                None => code.push_str(&format!("{}// channel({}): unknown channel\n", pad, name)),
+            },
+            LexerCommand::Type(kind) if token_kinds.contains(kind.as_str()) => {
+                code.push_str(&format!("{}token.kind = TokenKind::{};\n", pad, kind));
+            }
+            LexerCommand::Type(kind) => {
+                code.push_str(&format!("{}// type({}): no such token\n", pad, kind));
+            }
+            LexerCommand::Mode(mode) if modes.contains(mode.as_str()) => {
+                code.push_str(&format!("{}self.switch_mode(\"{}\");\n", pad, mode));
+            }
+            LexerCommand::Mode(mode) => {
+                code.push_str(&format!("{}// mode({}): no such mode\n", pad, mode));
+            }
+            LexerCommand::PushMode(mode) if modes.contains(mode.as_str()) => {
+                code.push_str(&format!("{}self.push_mode(\"{}\");\n", pad, mode));
+            }
+            LexerCommand::PushMode(mode) => {
+                code.push_str(&format!("{}// pushMode({}): no such mode\n", pad, mode));
+            }
+            LexerCommand::PopMode if !modes.is_empty() => {
+                code.push_str(&format!("{}self.pop_mode();\n", pad));
+            }
+            LexerCommand::PopMode => {
+                code.push_str(&format!("{}// popMode: the lexer has no modes\n", pad));
+            }
+            LexerCommand::More => {
+                code.push_str(&format!("{}// more: not supported\n", pad));
+            }
+        }
+    }
+
+    if commands.contains(&LexerCommand::Skip) {
+        code.push_str(&format!("{}return None;\n", pad));
+    }
+
+    code
+}
 
 /// Generate lexer mode stack code for Rust.
+///
+/// Channels are not kept here: the Rust lexer stamps each token with its
+/// channel number instead.
 pub fn generate_rust_mode_stack(grammar: &Grammar) -> String {
     if !grammar.has_modes() {
         return String::new();
     }
 
     let mut code = String::new();
-    code.push_str("    /// Stack of active lexer modes\n");
+    code.push_str("    /// Stack of active lexer modes, `DEFAULT_MODE` at the bottom\n");
     code.push_str("    mode_stack: Vec<String>,\n");
-
-    if grammar.has_channels() {
-        code.push_str("    /// Token channels\n");
-        code.push_str("    channels: std::collections::HashMap<String, Vec<Token>>,\n");
-    }
-
     code
 }
 
@@ -31,7 +90,7 @@ pub fn generate_rust_mode_methods(grammar: &Grammar) -> String {
     code.push_str("    /// Get current lexer mode\n");
     code.push_str("    #[inline]\n");
     code.push_str("    pub fn current_mode(&self) -> &str {\n");
-    code.push_str("        self.mode_stack.last().unwrap_or(&\"DEFAULT_MODE\".to_string())\n");
+    code.push_str("        self.mode_stack.last().map(String::as_str).unwrap_or(\"DEFAULT_MODE\")\n");
     code.push_str("    }\n\n");
 
     code.push_str("    /// Switch to a different mode (pop current, push new)\n");
@@ -57,22 +116,6 @@ pub fn generate_rust_mode_methods(grammar: &Grammar) -> String {
     code.push_str("        }\n");
     code.push_str("    }\n\n");
 
-    if grammar.has_channels() {
-        code.push_str("    /// Send token to a channel\n");
-        code.push_str("    #[inline]\n");
-        code.push_str("    pub fn send_to_channel(&mut self, channel: &str, token: Token) {\n");
-        code.push_str("        self.channels.entry(channel.to_string())\n");
-        code.push_str("            .or_insert_with(Vec::new)\n");
-        code.push_str("            .push(token);\n");
-        code.push_str("    }\n\n");
-
-        code.push_str("    /// Get tokens from a channel\n");
-        code.push_str("    #[inline]\n");
-        code.push_str("    pub fn get_channel(&self, channel: &str) -> Vec<Token> {\n");
-        code.push_str("        self.channels.get(channel).cloned().unwrap_or_default()\n");
-        code.push_str("    }\n\n");
-    }
-
     code
 }
 
@@ -82,14 +125,7 @@ pub fn generate_rust_mode_init(grammar: &Grammar) -> String {
         return String::new();
     }
 
-    let mut code = String::new();
-    code.push_str("        mode_stack: vec![\"DEFAULT_MODE\".to_string()],\n");
-
-    if grammar.has_channels() {
-        code.push_str("        channels: std::collections::HashMap::new(),\n");
-    }
-
-    code
+    "            mode_stack: vec![\"DEFAULT_MODE\".to_string()],\n".to_string()
 }
 
 /// Generate lexer mode stack code for Python.
@@ -143,26 +143,11 @@ fn generate_element_match_go(element: &Element, indent: &str) -> String {
             ));
             code.push_str(&format!("{}if l.position < len(l.input) {{\n", indent));
             code.push_str(&format!("{}    ch := l.input[l.position]\n", indent));
-            code.push_str(&format!("{}    matched := false\n", indent));
-
-            for (start, end) in ranges {
-                if start == end {
-                    code.push_str(&format!("{}    if ch == '{}' {{\n", indent, start));
-                } else {
-                    code.push_str(&format!(
-                        "{}    if ch >= '{}' && ch <= '{}' {{\n",
-                        indent, start, end
-                    ));
-                }
-                code.push_str(&format!("{}        matched = true\n", indent));
-                code.push_str(&format!("{}    }}\n", indent));
-            }
-
-            if *negated {
-                code.push_str(&format!("{}    if !matched {{\n", indent));
-            } else {
-                code.push_str(&format!("{}    if matched {{\n", indent));
-            }
+            code.push_str(&format!(
+                "{}    if {} {{\n",
+                indent,
+                class_condition(*negated, ranges)
+            ));
             code.push_str(&format!("{}        l.position++\n", indent));
             code.push_str(&format!("{}    }} else {{\n", indent));
             code.push_str(&format!("{}        return false\n", indent));
@@ -270,6 +255,28 @@ fn generate_element_match_c(element: &Element, indent: &str) -> String {
             code.push_str(&format!("{}    return 0;\n", indent));
             code.push_str(&format!("{}}}\n", indent));
         }
+        Element::CharClass { negated, ranges } => {
+            code.push_str(&format!(
+                "{}if (lexer->position < lexer->length) {{\n",
+                indent
+            ));
+            code.push_str(&format!(
+                "{}    char ch = lexer->input[lexer->position];\n",
+                indent
+            ));
+            code.push_str(&format!(
+                "{}    if ({}) {{\n",
+                indent,
+                class_condition(*negated, ranges)
+            ));
+            code.push_str(&format!("{}        lexer->position++;\n", indent));
+            code.push_str(&format!("{}    }} else {{\n", indent));
+            code.push_str(&format!("{}        return 0;\n", indent));
+            code.push_str(&format!("{}    }}\n", indent));
+            code.push_str(&format!("{}}} else {{\n", indent));
+            code.push_str(&format!("{}    return 0;\n", indent));
+            code.push_str(&format!("{}}}\n", indent));
+        }
         _ => {
             code.push_str(&format!("{}// TODO: Handle element type\n", indent));
         }
@@ -325,6 +332,25 @@ fn generate_element_match_java(element: &Element, indent: &str) -> String {
             code.push_str(&format!("{}    return false;\n", indent));
             code.push_str(&format!("{}}}\n", indent));
         }
+        Element::CharClass { negated, ranges } => {
+            code.push_str(&format!("{}if (position < input.length()) {{\n", indent));
+            code.push_str(&format!(
+                "{}    char ch = input.charAt(position);\n",
+                indent
+            ));
+            code.push_str(&format!(
+                "{}    if ({}) {{\n",
+                indent,
+                class_condition(*negated, ranges)
+            ));
+            code.push_str(&format!("{}        position++;\n", indent));
+            code.push_str(&format!("{}    }} else {{\n", indent));
+            code.push_str(&format!("{}        return false;\n", indent));
+            code.push_str(&format!("{}    }}\n", indent));
+            code.push_str(&format!("{}}} else {{\n", indent));
+            code.push_str(&format!("{}    return false;\n", indent));
+            code.push_str(&format!("{}}}\n", indent));
+        }
         _ => {
             code.push_str(&format!("{}// TODO: Handle element type\n", indent));
         }
@@ -378,6 +404,22 @@ pub fn generate_element_match_cpp(element: &Element, indent: &str) -> String {
             code.push_str(&format!("{}    return false;\n", indent));
             code.push_str(&format!("{}}}\n", indent));
         }
+        Element::CharClass { negated, ranges } => {
+            code.push_str(&format!("{}if (position < input.length()) {{\n", indent));
+            code.push_str(&format!("{}    char ch = input[position];\n", indent));
+            code.push_str(&format!(
+                "{}    if ({}) {{\n",
+                indent,
+                class_condition(*negated, ranges)
+            ));
+            code.push_str(&format!("{}        position++;\n", indent));
+            code.push_str(&format!("{}    }} else {{\n", indent));
+            code.push_str(&format!("{}        return false;\n", indent));
+            code.push_str(&format!("{}    }}\n", indent));
+            code.push_str(&format!("{}}} else {{\n", indent));
+            code.push_str(&format!("{}    return false;\n", indent));
+            code.push_str(&format!("{}}}\n", indent));
+        }
         _ => {
             code.push_str(&format!("{}// TODO: Handle element type\n", indent));
         }
@@ -385,3 +427,66 @@ pub fn generate_element_match_cpp(element: &Element, indent: &str) -> String {
 
     code
 }
+
+/// Condition on `ch` that holds for the chars in `ranges`, or for every char
+/// outside all of them when the class is negated.
+///
+/// The syntax is shared by Go, C, C++ and Java.
+fn class_condition(negated: bool, ranges: &[(char, char)]) -> String {
+    let condition = if ranges.is_empty() {
+        "false".to_string()
+    } else {
+        ranges
+            .iter()
+            .map(|(start, end)| {
+                if start == end {
+                    format!("ch == {}", char_literal(*start))
+                } else {
+                    format!("(ch >= {} && ch <= {})", char_literal(*start), char_literal(*end))
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" || ")
+    };
+    if negated {
+        format!("!({})", condition)
+    } else {
+        condition
+    }
+}
+
+/// A char as a literal all four languages accept: quoted when it is
+/// printable ASCII, its code point otherwise.
+fn char_literal(ch: char) -> String {
+    match ch {
+        '\'' | '\\' => format!("'\\{}'", ch),
+        ' '..='~' => format!("'{}'", ch),
+        _ => (ch as u32).to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_negated_class_excludes_every_range() {
+        let class = Element::CharClass {
+            negated: true,
+            ranges: vec![('\r', '\r'), ('\n', '\n'), ('a', 'z')],
+        };
+
+        for code in [
+            generate_element_match_go(&class, ""),
+            generate_element_match_c(&class, ""),
+            generate_element_match_java(&class, ""),
+            generate_element_match_cpp(&class, ""),
+        ] {
+            assert!(
+                code.contains("!(ch == 13 || ch == 10 || (ch >= 'a' && ch <= 'z'))"),
+                "{}",
+                code
+            );
+        }
+    }
+}
@@ -1,7 +1,9 @@
 //! Python code generator.
 
-use crate::ast::{Grammar, Rule};
-use crate::{types::CodeGenConfig, CodeGenerator as CodeGeneratorTrait, Result};
+use super::actions::translate_action_element;
+use super::common::{rule_commands, single_quoted, unlabeled_references, Emitter};
+use crate::ast::{Alternative, Element, Grammar, LexerCommand, Rule};
+use crate::{types::CodeGenConfig, CodeGenerator as CodeGeneratorTrait, Error, Result};
 
 /// Python code generator.
 pub struct PythonCodeGenerator;
@@ -18,9 +20,30 @@ impl PythonCodeGenerator {
         code.push_str(&format!("class {}:\n", class_name));
         code.push_str("    \"\"\"Lexer with DFA-based tokenization and error recovery.\"\"\"\n\n");
 
+        let (skipped, routed) = token_commands(grammar);
+        code.push_str("    # Token kinds dropped by `-> skip`\n");
+        if skipped.is_empty() {
+            code.push_str("    SKIP = frozenset()\n");
+        } else {
+            let kinds: Vec<String> = skipped.iter().map(|name| format!("TokenKind.{}", name)).collect();
+            code.push_str(&format!("    SKIP = frozenset({{{}}})\n", kinds.join(", ")));
+        }
+        code.push_str("    # Token kinds sent off the main stream by `-> channel(...)`\n");
+        code.push_str("    CHANNELS = {");
+        for (i, (name, channel)) in routed.iter().enumerate() {
+            if i > 0 {
+                code.push_str(", ");
+            }
+            code.push_str(&format!("TokenKind.{}: '{}'", name, channel));
+        }
+        code.push_str("}\n\n");
+
         code.push_str("    def __init__(self, input_text: str):\n");
         code.push_str("        self.input = input_text\n");
-        code.push_str("        self.position = 0\n\n");
+        code.push_str("        self.position = 0\n");
+        code.push_str("        self.hidden_tokens: List[Token] = []\n");
+        code.push_str("        # Index, line, column and byte offset of the last token start\n");
+        code.push_str("        self._cursor = (0, 1, 0, 0)\n\n");
 
         code.push_str("    def next_token(self) -> Token:\n");
         code.push_str("        \"\"\"Get the next token from input.\n\n");
@@ -34,42 +57,84 @@ impl PythonCodeGenerator {
         code.push_str("        start_pos = self.position\n\n");
         code.push_str("        # Check for EOF\n");
         code.push_str("        if self.position >= len(self.input):\n");
-        code.push_str("            return Token(TokenKind.EOF, \"\", start_pos)\n\n");
+        code.push_str("            return self._place(Token(TokenKind.EOF, \"\", start_pos), start_pos, start_pos)\n\n");
         code.push_str("        # Use DFA for tokenization\n");
         code.push_str("        token = self._next_token_dfa()\n");
         code.push_str("        if token:\n");
-        code.push_str("            token.position = start_pos\n");
-        code.push_str("            return token\n\n");
+        code.push_str("            return self._place(token, start_pos, self.position)\n\n");
         code.push_str("        # Error recovery: skip invalid character\n");
         code.push_str("        invalid_char = self.input[self.position]\n");
         code.push_str("        self.position += 1\n");
         code.push_str("        raise ParseError(\n");
-        code.push_str("            message=f\"Unexpected character: '{invalid_char}'\"\n");
+        code.push_str("            message=f\"Unexpected character: '{invalid_char}'\",\n");
         code.push_str("            position=start_pos,\n");
         code.push_str("            expected=[],\n");
         code.push_str("            found=invalid_char\n");
         code.push_str("        )\n\n");
 
-        code.push_str("    def tokenize_all(self) -> Tuple[List[Token], List[ParseError]]:\n");
-        code.push_str("        \"\"\"Tokenize all input and collect errors.\n\n");
-        code.push_str("        Returns:\n");
-        code.push_str("            Tuple of (tokens, errors)\n");
+        code.push_str("    def __iter__(self) -> Iterator[Union[Token, ParseError]]:\n");
+        code.push_str("        \"\"\"Yield tokens one at a time, ending with the EOF token.\n\n");
+        code.push_str("        Skipped tokens are dropped and off-channel tokens are kept in\n");
+        code.push_str("        `hidden_tokens`. Errors are yielded rather than raised, so lexing\n");
+        code.push_str("        goes on after them.\n");
         code.push_str("        \"\"\"\n");
-        code.push_str("        tokens = []\n");
-        code.push_str("        errors = []\n\n");
         code.push_str("        while True:\n");
         code.push_str("            try:\n");
         code.push_str("                token = self.next_token()\n");
-        code.push_str("                tokens.append(token)\n");
-        code.push_str("                if token.kind == TokenKind.EOF:\n");
-        code.push_str("                    break\n");
         code.push_str("            except ParseError as err:\n");
-        code.push_str("                errors.append(err)\n");
-        code.push_str("                # Continue tokenizing after error\n");
-        code.push_str("                if self.position >= len(self.input):\n");
-        code.push_str("                    break\n\n");
+        code.push_str("                yield err\n");
+        code.push_str("                continue\n");
+        code.push_str("            if token.kind in self.SKIP:\n");
+        code.push_str("                continue\n");
+        code.push_str("            if token.kind in self.CHANNELS:\n");
+        code.push_str("                self.hidden_tokens.append(token)\n");
+        code.push_str("                continue\n");
+        code.push_str("            yield token\n");
+        code.push_str("            if token.kind == TokenKind.EOF:\n");
+        code.push_str("                return\n\n");
+
+        code.push_str("    def tokenize_all(self) -> Tuple[List[Token], List[ParseError]]:\n");
+        code.push_str("        \"\"\"Tokenize all input and collect errors, draining the lexer.\n\n");
+        code.push_str("        Returns:\n");
+        code.push_str("            Tuple of (tokens, errors)\n");
+        code.push_str("        \"\"\"\n");
+        code.push_str("        tokens = []\n");
+        code.push_str("        errors = []\n");
+        code.push_str("        for item in self:\n");
+        code.push_str("            if isinstance(item, ParseError):\n");
+        code.push_str("                errors.append(item)\n");
+        code.push_str("            else:\n");
+        code.push_str("                tokens.append(item)\n");
         code.push_str("        return tokens, errors\n\n");
 
+        code.push_str("    def _place(self, token: Token, start: int, end: int) -> Token:\n");
+        code.push_str("        \"\"\"Set the position, line, column and UTF-8 byte offsets of a token\n");
+        code.push_str("        spanning the characters `start:end`.\"\"\"\n");
+        code.push_str("        token.position = start\n");
+        code.push_str("        token.line, token.column, token.start = self._locate(start)\n");
+        code.push_str("        token.stop = token.start + len(self.input[start:end].encode('utf-8'))\n");
+        code.push_str("        return token\n\n");
+
+        code.push_str("    def _locate(self, index: int) -> Tuple[int, int, int]:\n");
+        code.push_str("        \"\"\"Line, column and UTF-8 byte offset of the character at `index`.\n\n");
+        code.push_str("        Walks on from the previous token start; an earlier `index` starts\n");
+        code.push_str("        over from the beginning of the input.\n");
+        code.push_str("        \"\"\"\n");
+        code.push_str("        if index < self._cursor[0]:\n");
+        code.push_str("            self._cursor = (0, 1, 0, 0)\n");
+        code.push_str("        pos, line, column, byte = self._cursor\n");
+        code.push_str("        while pos < index:\n");
+        code.push_str("            ch = self.input[pos]\n");
+        code.push_str("            pos += 1\n");
+        code.push_str("            byte += len(ch.encode('utf-8'))\n");
+        code.push_str("            if ch == '\\n':\n");
+        code.push_str("                line += 1\n");
+        code.push_str("                column = 0\n");
+        code.push_str("            else:\n");
+        code.push_str("                column += 1\n");
+        code.push_str("        self._cursor = (pos, line, column, byte)\n");
+        code.push_str("        return line, column, byte\n\n");
+
         code.push_str("    def _skip_whitespace(self):\n");
         code.push_str("        while self.position < len(self.input):\n");
         code.push_str("            if self.input[self.position] in ' \\t\\r\\n':\n");
@@ -85,12 +150,20 @@ impl PythonCodeGenerator {
         code
     }
 
-    fn generate_parser(&self, grammar: &Grammar) -> String {
+    fn generate_parser(&self, grammar: &Grammar) -> Result<String> {
         let mut code = String::new();
         let class_name = format!("{}Parser", grammar.name);
 
-        code.push_str(&format!("class {}:\n", class_name));
+        // `superClass` must take no constructor arguments
+        let super_class = grammar.options.get("superClass");
+        match super_class {
+            Some(base) => code.push_str(&format!("class {}({}):\n", class_name, base)),
+            None => code.push_str(&format!("class {}:\n", class_name)),
+        }
         code.push_str("    def __init__(self, tokens):\n");
+        if super_class.is_some() {
+            code.push_str("        super().__init__()\n");
+        }
         code.push_str("        self.tokens = tokens\n");
         code.push_str("        self.position = 0\n");
 
@@ -105,13 +178,13 @@ impl PythonCodeGenerator {
 
         // Generate parse methods for each rule
         for rule in grammar.parser_rules() {
-            code.push_str(&self.generate_rule_method(rule));
+            code.push_str(&self.generate_rule_method(rule)?);
         }
 
-        code
+        Ok(code)
     }
 
-    fn generate_rule_method(&self, rule: &Rule) -> String {
+    fn generate_rule_method(&self, rule: &Rule) -> Result<String> {
         let mut code = String::new();
 
         // Generate method signature
@@ -156,8 +229,20 @@ impl PythonCodeGenerator {
 
         code.push_str(":\n");
 
-        // Generate docstring
-        code.push_str(&format!("        \"\"\"Parse {} rule.\n", rule.name));
+        // Generate docstring, from the rule's doc comment when it has one
+        match &rule.doc {
+            Some(doc) => {
+                let doc = doc.replace('\\', "\\\\").replace("\"\"\"", "\\\"\"\"");
+                for (i, line) in doc.lines().enumerate() {
+                    match i {
+                        0 => code.push_str(&format!("        \"\"\"{}\n", line)),
+                        _ if line.is_empty() => code.push('\n'),
+                        _ => code.push_str(&format!("        {}\n", line)),
+                    }
+                }
+            }
+            None => code.push_str(&format!("        \"\"\"Parse {} rule.\n", rule.name)),
+        }
         if !rule.arguments.is_empty() {
             code.push_str("        \n");
             code.push_str("        Args:\n");
@@ -205,7 +290,7 @@ impl PythonCodeGenerator {
         } else if rule.alternatives.len() == 1 {
             // Single alternative
             let alt = &rule.alternatives[0];
-            code.push_str(&self.generate_python_alternative(alt, rule));
+            code.push_str(&self.generate_python_alternative(alt, rule)?);
         } else {
             // Multiple alternatives - try each in order
             code.push_str("        # Try alternatives\n");
@@ -216,7 +301,7 @@ impl PythonCodeGenerator {
                     code.push_str("        self.position = start_pos\n");
                 }
                 code.push_str("        try:\n");
-                let alt_code = self.generate_python_alternative(alt, rule);
+                let alt_code = self.generate_python_alternative(alt, rule)?;
                 // Indent the alternative code
                 for line in alt_code.lines() {
                     if !line.trim().is_empty() {
@@ -233,30 +318,49 @@ impl PythonCodeGenerator {
             code.push('\n');
         }
 
-        code
+        Ok(code)
     }
 
+    /// One `@dataclass` per parser rule, deriving from a base `AstNode`.
+    ///
+    /// Labeled elements become fields named after their labels. Unlabeled
+    /// rule and token references become fields named after what they
+    /// reference (`ID` gives `id`), as lists when they can occur more than
+    /// once. Every field has a default, and `children` on the base holds the
+    /// node's children in order, so nodes work with `match`/`case` keyword
+    /// patterns such as `case PairNode(id=key, value=value)`.
     fn generate_ast_types(&self, grammar: &Grammar) -> String {
         let mut code = String::new();
 
         code.push_str("# AST node type definitions\n\n");
-        
+
+        code.push_str("@dataclass\n");
+        code.push_str("class AstNode:\n");
+        code.push_str("    \"\"\"Base class of all AST nodes.\"\"\"\n");
+        code.push_str("    children: List[Any] = field(default_factory=list)\n\n");
+
         for rule in grammar.parser_rules() {
             let class_name = to_pascal_case(&rule.name);
-            let fields = self.extract_labeled_fields(rule);
-            
+            let fields = node_fields(rule);
+
             code.push_str("@dataclass\n");
-            code.push_str(&format!("class {}Node:\n", class_name));
+            code.push_str(&format!("class {}Node(AstNode):\n", class_name));
             code.push_str(&format!("    \"\"\"AST node for {} rule.\"\"\"\n", rule.name));
-            
+
             if fields.is_empty() {
                 code.push_str("    pass\n\n");
             } else {
                 for (field_name, field_type, is_list) in &fields {
                     if *is_list {
-                        code.push_str(&format!("    {}: List[{}]\n", field_name, field_type));
+                        code.push_str(&format!(
+                            "    {}: List[{}] = field(default_factory=list)\n",
+                            field_name, field_type
+                        ));
                     } else {
-                        code.push_str(&format!("    {}: {}\n", field_name, field_type));
+                        code.push_str(&format!(
+                            "    {}: Optional[{}] = None\n",
+                            field_name, field_type
+                        ));
                     }
                 }
                 code.push('\n');
@@ -266,201 +370,36 @@ impl PythonCodeGenerator {
         code
     }
 
-    fn extract_labeled_fields(&self, rule: &Rule) -> Vec<(String, String, bool)> {
-        let mut fields = Vec::new();
-        let mut seen_labels = std::collections::HashSet::new();
-
-        for alt in &rule.alternatives {
-            for elem in &alt.elements {
-                self.collect_labels_from_element(elem, &mut fields, &mut seen_labels);
-            }
-        }
-
-        fields
-    }
-
-    fn collect_labels_from_element(
-        &self,
-        element: &crate::ast::Element,
-        fields: &mut Vec<(String, String, bool)>,
-        seen_labels: &mut std::collections::HashSet<String>,
-    ) {
-        use crate::ast::Element;
-
-        match element {
-            Element::Terminal { label: Some(label), is_list, .. } => {
-                if seen_labels.insert(label.clone()) {
-                    fields.push((label.clone(), "Token".to_string(), *is_list));
-                }
-            }
-            Element::RuleRef { label: Some(label), is_list, .. } => {
-                if seen_labels.insert(label.clone()) {
-                    fields.push((label.clone(), "AstNode".to_string(), *is_list));
-                }
-            }
-            Element::StringLiteral { label: Some(label), is_list, .. } => {
-                if seen_labels.insert(label.clone()) {
-                    fields.push((label.clone(), "Token".to_string(), *is_list));
-                }
-            }
-            Element::Group { alternatives } => {
-                for alt in alternatives {
-                    for elem in &alt.elements {
-                        self.collect_labels_from_element(elem, fields, seen_labels);
-                    }
-                }
-            }
-            Element::Optional { element, .. } => {
-                self.collect_labels_from_element(element, fields, seen_labels);
-            }
-            Element::ZeroOrMore { element, .. } => {
-                self.collect_labels_from_element(element, fields, seen_labels);
-            }
-            Element::OneOrMore { element, .. } => {
-                self.collect_labels_from_element(element, fields, seen_labels);
-            }
-            _ => {}
-        }
-    }
-
-    fn generate_python_alternative(&self, alt: &crate::ast::Alternative, rule: &Rule) -> String {
-        use crate::ast::Element;
+    fn generate_python_alternative(&self, alt: &Alternative, rule: &Rule) -> Result<String> {
         let mut code = String::new();
 
-        // Collect labeled variables
-        let mut collected_vars = Vec::new();
-        let mut list_vars = Vec::new();
-
-        for element in &alt.elements {
-            match element {
-                Element::RuleRef { label: Some(lbl), is_list, .. }
-                | Element::Terminal { label: Some(lbl), is_list, .. }
-                | Element::StringLiteral { label: Some(lbl), is_list, .. } => {
-                    if *is_list {
-                        list_vars.push(lbl.clone());
-                    } else {
-                        collected_vars.push(lbl.clone());
-                    }
-                }
-                _ => {}
-            }
-        }
-
-        // Initialize list variables
-        for lbl in &list_vars {
-            code.push_str(&format!("        {} = []\\n", lbl));
-        }
-        if !list_vars.is_empty() {
-            code.push_str("\\n");
+        // Every field of the node starts out unset, so ones this alternative
+        // or an optional part skips are bound
+        let fields = node_fields(rule);
+        code.push_str("        children = []\n");
+        for (name, _, is_list) in &fields {
+            let empty = if *is_list { "[]" } else { "None" };
+            code.push_str(&format!("        {} = {}\n", name, empty));
         }
+        code.push('\n');
 
         // Generate parsing code for each element
         for element in &alt.elements {
-            match element {
-                Element::RuleRef { name, label, is_list } => {
-                    let method_name = format!("parse_{}", name);
-                    if *is_list {
-                        if let Some(lbl) = label {
-                            code.push_str(&format!("        {}.append(self.{}())\\n", lbl, method_name));
-                        } else {
-                            code.push_str(&format!("        self.{}()\\n", method_name));
-                        }
-                    } else if let Some(lbl) = label {
-                        code.push_str(&format!("        {} = self.{}()\\n", lbl, method_name));
-                    } else {
-                        code.push_str(&format!("        self.{}()\\n", method_name));
-                    }
-                }
-                Element::Terminal { value, label, is_list } => {
-                    code.push_str(&format!("        # Match terminal: {}\\n", value));
-                    code.push_str("        if self.position >= len(self.tokens):\\n");
-                    code.push_str(&format!("            raise ParseError('Unexpected EOF, expected: {}', self.position, [], None)\\n", value));
-                    code.push_str(&format!("        if self.tokens[self.position].kind == TokenKind.{}:\\n", value));
-                    if *is_list {
-                        if let Some(lbl) = label {
-                            code.push_str(&format!("            {}.append(self.tokens[self.position])\\n", lbl));
-                        }
-                    } else if let Some(lbl) = label {
-                        code.push_str(&format!("            {} = self.tokens[self.position]\\n", lbl));
-                    }
-                    code.push_str("            self.position += 1\\n");
-                    code.push_str("        else:\\n");
-                    code.push_str(&format!("            raise ParseError(f'Expected {}, got {{self.tokens[self.position].kind}}', self.position, [], None)\\n", value));
-                }
-                Element::StringLiteral { value, label, is_list } => {
-                    code.push_str(&format!("        # Match string literal: '{}'\\n", value));
-                    code.push_str("        if self.position >= len(self.tokens):\\n");
-                    code.push_str(&format!("            raise ParseError('Unexpected EOF, expected: \"{}\"', self.position, [], None)\\n", value));
-                    code.push_str(&format!("        if self.tokens[self.position].value == '{}':\\n", value));
-                    if *is_list {
-                        if let Some(lbl) = label {
-                            code.push_str(&format!("            {}.append(self.tokens[self.position])\\n", lbl));
-                        }
-                    } else if let Some(lbl) = label {
-                        code.push_str(&format!("            {} = self.tokens[self.position]\\n", lbl));
-                    }
-                    code.push_str("            self.position += 1\\n");
-                    code.push_str("        else:\\n");
-                    code.push_str(&format!("            raise ParseError(f'Expected \\\"{}\\\", got {{self.tokens[self.position].value}}', self.position, [], None)\\n", value));
-                }
-                Element::Optional { element: _, .. } => {
-                    code.push_str("        # Optional element\\n");
-                    code.push_str("        saved_pos = self.position\\n");
-                    code.push_str("        try:\\n");
-                    code.push_str("            # Try to match optional element\\n");
-                    code.push_str("            pass\\n");
-                    code.push_str("        except ParseError:\\n");
-                    code.push_str("            # Optional failed, restore position\\n");
-                    code.push_str("            self.position = saved_pos\\n");
-                }
-                Element::ZeroOrMore { element: _, .. } => {
-                    code.push_str("        # Zero or more repetition\\n");
-                    code.push_str("        while self.position < len(self.tokens):\\n");
-                    code.push_str("            saved_pos = self.position\\n");
-                    code.push_str("            try:\\n");
-                    code.push_str("                # Try to match element\\n");
-                    code.push_str("                pass\\n");
-                    code.push_str("            except ParseError:\\n");
-                    code.push_str("                self.position = saved_pos\\n");
-                    code.push_str("                break\\n");
-                }
-                Element::OneOrMore { element: _, .. } => {
-                    code.push_str("        # One or more repetition\\n");
-                    code.push_str("        match_count = 0\\n");
-                    code.push_str("        while self.position < len(self.tokens):\\n");
-                    code.push_str("            saved_pos = self.position\\n");
-                    code.push_str("            try:\\n");
-                    code.push_str("                # Try to match element\\n");
-                    code.push_str("                match_count += 1\\n");
-                    code.push_str("            except ParseError:\\n");
-                    code.push_str("                self.position = saved_pos\\n");
-                    code.push_str("                break\\n");
-                    code.push_str("        if match_count == 0:\\n");
-                    code.push_str("            raise ParseError('Expected at least one match', self.position, [], None)\\n");
-                }
-                _ => {
-                    code.push_str("        # TODO: Handle other element types\\n");
-                }
-            }
+            code.push_str(&python_element(element, rule, "        ", 0)?);
         }
 
         // Build result node
-        if !collected_vars.is_empty() || !list_vars.is_empty() {
-            code.push_str("\\n        # Build AST node\\n");
-            code.push_str(&format!("        return {}Node(", to_pascal_case(&rule.name)));
-            let all_vars: Vec<_> = collected_vars.iter().chain(list_vars.iter()).collect();
-            for (i, var) in all_vars.iter().enumerate() {
-                if i > 0 {
-                    code.push_str(", ");
-                }
-                code.push_str(&format!("{}={}", var, var));
-            }
-            code.push_str(")\\n");
-        } else {
-            code.push_str(&format!("        return {}Node()\\n", to_pascal_case(&rule.name)));
-        }
+        code.push_str("\n        # Build AST node\n");
+        let arguments: Vec<String> = std::iter::once("children=children".to_string())
+            .chain(fields.iter().map(|(name, _, _)| format!("{}={}", name, name)))
+            .collect();
+        code.push_str(&format!(
+            "        return {}Node({})\n",
+            to_pascal_case(&rule.name),
+            arguments.join(", ")
+        ));
 
-        code
+        Ok(code)
     }
 }
 
@@ -474,7 +413,7 @@ impl CodeGeneratorTrait for PythonCodeGenerator {
     type Input = Grammar;
     type Config = CodeGenConfig;
 
-    fn generate(&self, input: &Self::Input, _config: &Self::Config) -> Result<String> {
+    fn generate(&self, input: &Self::Input, config: &Self::Config) -> Result<String> {
         let mut code = String::new();
 
         // Header with PEP 8 compliance
@@ -485,8 +424,9 @@ impl CodeGeneratorTrait for PythonCodeGenerator {
 
         // Standard library imports (PEP 8: standard lib first)
         code.push_str("from __future__ import annotations\n\n");
-        code.push_str("from dataclasses import dataclass\n");
-        code.push_str("from typing import Any, List, Optional, Tuple\n");
+        code.push_str("from dataclasses import dataclass, field\n");
+        code.push_str("from enum import Enum\n");
+        code.push_str("from typing import Any, Iterator, List, Optional, Tuple, Union\n");
 
         // Insert @header named action if present
         if let Some(header_code) = input.named_actions.get("header") {
@@ -517,19 +457,31 @@ impl CodeGeneratorTrait for PythonCodeGenerator {
         // Token class
         code.push_str("@dataclass\n");
         code.push_str("class Token:\n");
-        code.push_str("    \"\"\"Token with position information.\"\"\"\n");
+        code.push_str("    \"\"\"Token with position information.\n\n");
+        code.push_str("    `position` is the index of the first character, `line` counts from 1\n");
+        code.push_str("    and `column` from 0, and `start:stop` are the token's UTF-8 byte offsets.\n");
+        code.push_str("    \"\"\"\n");
         code.push_str("    kind: 'TokenKind'\n");
         code.push_str("    text: str\n");
-        code.push_str("    position: int\n\n");
+        code.push_str("    position: int\n");
+        code.push_str("    line: int = 1\n");
+        code.push_str("    column: int = 0\n");
+        code.push_str("    start: int = 0\n");
+        code.push_str("    stop: int = 0\n\n");
 
-        // Token enum
+        // Token enum; `TokenKind[name]` and `kind.name` map between names and members
         code.push_str("class TokenKind(Enum):\n");
+        for name in input.declared_tokens.iter().filter(|name| input.get_rule(name).is_none()) {
+            code.push_str(&format!("    {} = '{}'\n", name, name));
+        }
         for rule in input.lexer_rules() {
             if !rule.is_fragment {
                 code.push_str(&format!("    {} = '{}'\n", rule.name, rule.name));
             }
         }
         code.push_str("    EOF = 'EOF'\n\n");
+        code.push_str("    def __str__(self) -> str:\n");
+        code.push_str("        return self.name\n\n");
 
         // AST types
         code.push_str(&self.generate_ast_types(input));
@@ -538,14 +490,528 @@ impl CodeGeneratorTrait for PythonCodeGenerator {
         code.push_str(&self.generate_lexer(input));
 
         // Parser
-        code.push_str(&self.generate_parser(input));
+        code.push_str(&self.generate_parser(input)?);
 
-        Ok(code)
+        Ok(Emitter::new(config, 4).finish_indentation(code))
     }
 
     fn target_language(&self) -> &str {
         "python"
     }
+
+    fn supported_options(&self) -> &[&str] {
+        &["superClass"]
+    }
+}
+
+/// Python statements matching `element`, each line starting with `indent`.
+///
+/// Statements raise `ParseError` when the input does not match; optional
+/// parts, loops and groups catch it and restore the position saved in a
+/// variable numbered by `depth`, so nested ones do not overwrite it. Loops
+/// stop once an iteration consumes nothing. Elements this target cannot
+/// match in a parser rule, such as Unicode property classes, are an error.
+fn python_element(element: &Element, rule: &Rule, indent: &str, depth: usize) -> Result<String> {
+    let mut code = String::new();
+    let saved = format!("saved_pos_{}", depth);
+    let saved_children = format!("saved_children_{}", depth);
+    // Going back to the saved position also drops the nodes parsed since
+    let save = |indent: &str| {
+        format!(
+            "{indent}{saved} = self.position\n{indent}{saved_children} = len(children)\n"
+        )
+    };
+    let restore = |indent: &str| {
+        format!(
+            "{indent}self.position = {saved}\n{indent}del children[{saved_children}:]\n"
+        )
+    };
+    let store = |label: &Option<String>, is_list: bool, value: &str| match label {
+        Some(lbl) if is_list => format!("{}.append({})", lbl, value),
+        Some(lbl) => format!("{} = {}", lbl, value),
+        None => value.to_string(),
+    };
+
+    match element {
+        Element::RuleRef { name, .. } if name == "EOF" => return python_element(&Element::Eof, rule, indent, depth),
+        // A token reference matches the token like a terminal
+        Element::RuleRef {
+            name,
+            label,
+            is_list,
+            ..
+        } if name.starts_with(|c: char| c.is_uppercase()) => {
+            // An unlabeled token is kept in the field named after it
+            let (label, is_list) = match label {
+                Some(_) => (label.clone(), *is_list),
+                None => match reference_field(rule, name) {
+                    Some((field, is_list)) => (Some(field), is_list),
+                    None => (None, false),
+                },
+            };
+            let terminal = Element::Terminal {
+                value: name.clone(),
+                label,
+                is_list,
+                span: None,
+                options: Vec::new(),
+            };
+            return python_element(&terminal, rule, indent, depth);
+        }
+        Element::RuleRef {
+            name,
+            label,
+            is_list,
+            ..
+        } => {
+            // The node goes in its label, or the field named after the rule,
+            // and in `children`
+            let (field, is_list) = match label {
+                Some(_) => (label.clone(), *is_list),
+                None => match reference_field(rule, name) {
+                    Some((field, is_list)) => (Some(field), is_list),
+                    None => (None, false),
+                },
+            };
+            code.push_str(&format!("{}children.append(self.parse_{}())\n", indent, name));
+            if field.is_some() {
+                code.push_str(&format!("{}{}\n", indent, store(&field, is_list, "children[-1]")));
+            }
+        }
+        Element::Terminal {
+            value,
+            label,
+            is_list,
+            ..
+        } => {
+            code.push_str(&format!("{}# Match terminal: {}\n", indent, value));
+            code.push_str(&format!(
+                "{}if self.position >= len(self.tokens):\n",
+                indent
+            ));
+            code.push_str(&format!(
+                "{}    raise ParseError('Unexpected EOF, expected: {}', self.position, [], None)\n",
+                indent, value
+            ));
+            code.push_str(&format!(
+                "{}if self.tokens[self.position].kind == TokenKind.{}:\n",
+                indent, value
+            ));
+            if label.is_some() {
+                let token = store(label, *is_list, "self.tokens[self.position]");
+                code.push_str(&format!("{}    {}\n", indent, token));
+            }
+            code.push_str(&format!("{}    self.position += 1\n", indent));
+            code.push_str(&format!("{}else:\n", indent));
+            code.push_str(&format!("{}    raise ParseError(f'Expected {}, got {{self.tokens[self.position].kind}}', self.position, [], None)\n", indent, value));
+        }
+        Element::StringLiteral {
+            value,
+            label,
+            is_list,
+            ..
+        } => {
+            let literal = single_quoted(value);
+            code.push_str(&format!("{}# Match string literal: {}\n", indent, literal));
+            code.push_str(&format!(
+                "{}if self.position >= len(self.tokens):\n",
+                indent
+            ));
+            code.push_str(&format!(
+                "{}    raise ParseError({}, self.position, [], None)\n",
+                indent,
+                single_quoted(&format!("Unexpected EOF, expected: {}", literal))
+            ));
+            code.push_str(&format!(
+                "{}if self.tokens[self.position].text == {}:\n",
+                indent, literal
+            ));
+            if label.is_some() {
+                let token = store(label, *is_list, "self.tokens[self.position]");
+                code.push_str(&format!("{}    {}\n", indent, token));
+            }
+            code.push_str(&format!("{}    self.position += 1\n", indent));
+            code.push_str(&format!("{}else:\n", indent));
+            code.push_str(&format!(
+                "{}    raise ParseError({} + str(self.tokens[self.position].text), self.position, [], None)\n",
+                indent,
+                single_quoted(&format!("Expected {}, got ", literal))
+            ));
+        }
+        Element::Wildcard => {
+            code.push_str(&format!("{}# Match any token\n", indent));
+            code.push_str(&format!("{}if self.position >= len(self.tokens) or self.tokens[self.position].kind == TokenKind.EOF:\n", indent));
+            code.push_str(&format!("{}    raise ParseError('Unexpected EOF, expected any token', self.position, [], None)\n", indent));
+            code.push_str(&format!("{}self.position += 1\n", indent));
+        }
+        Element::CharClass { .. } | Element::CharRange { .. } => {
+            code.push_str(&format!(
+                "{}# Match single-character token in class\n",
+                indent
+            ));
+            code.push_str(&format!(
+                "{}ch = self.tokens[self.position].text if self.position < len(self.tokens) else ''\n",
+                indent
+            ));
+            code.push_str(&format!(
+                "{}if not (len(ch) == 1 and {}):\n",
+                indent,
+                python_char_condition(element)
+            ));
+            code.push_str(&format!(
+                "{}    raise ParseError('Expected a character in class', self.position, [], ch)\n",
+                indent
+            ));
+            code.push_str(&format!("{}self.position += 1\n", indent));
+        }
+        Element::Not { element: negated } => {
+            let matched = format!("matched_{}", depth);
+            code.push_str(&format!(
+                "{}# Negation - any token the element does not match\n",
+                indent
+            ));
+            code.push_str(&save(indent));
+            code.push_str(&format!("{}try:\n", indent));
+            code.push_str(&python_element(
+                negated,
+                rule,
+                &format!("{}    ", indent),
+                depth + 1,
+            )?);
+            code.push_str(&format!("{}    {} = True\n", indent, matched));
+            code.push_str(&format!("{}except ParseError:\n", indent));
+            code.push_str(&format!("{}    {} = False\n", indent, matched));
+            code.push_str(&restore(indent));
+            code.push_str(&format!(
+                "{}if {} or self.position >= len(self.tokens) or self.tokens[self.position].kind == TokenKind.EOF:\n",
+                indent, matched
+            ));
+            code.push_str(&format!(
+                "{}    raise ParseError('Expected a token outside the negated set', self.position, [], None)\n",
+                indent
+            ));
+            code.push_str(&format!("{}self.position += 1\n", indent));
+        }
+        Element::Predicate { .. } => {
+            let predicate = translate_action_element(element, "python").unwrap_or_default();
+            // <fail='text'> replaces the message; a <fail={code}> message is Rust code
+            let message = match element.option("fail") {
+                Some(fail) if !fail.starts_with('{') => fail.to_string(),
+                _ => "Semantic predicate failed".to_string(),
+            };
+            code.push_str(&format!("{}# Semantic predicate\n", indent));
+            code.push_str(&format!("{}if not ({}):\n", indent, predicate));
+            code.push_str(&format!(
+                "{}    raise ParseError({}, self.position, [], None)\n",
+                indent,
+                single_quoted(&message)
+            ));
+        }
+        Element::Optional { element, .. } => {
+            code.push_str(&format!("{}# Optional element\n", indent));
+            code.push_str(&save(indent));
+            code.push_str(&format!("{}try:\n", indent));
+            code.push_str(&python_element(
+                element,
+                rule,
+                &format!("{}    ", indent),
+                depth + 1,
+            )?);
+            code.push_str(&format!("{}except ParseError:\n", indent));
+            code.push_str(&format!(
+                "{}    # Optional failed, restore position\n",
+                indent
+            ));
+            code.push_str(&restore(&format!("{}    ", indent)));
+        }
+        Element::ZeroOrMore {
+            element: repeated, ..
+        }
+        | Element::OneOrMore {
+            element: repeated, ..
+        } => {
+            let one_or_more = matches!(element, Element::OneOrMore { .. });
+            let count = format!("match_count_{}", depth);
+            if one_or_more {
+                code.push_str(&format!("{}# One or more repetition\n", indent));
+                code.push_str(&format!("{}{} = 0\n", indent, count));
+            } else {
+                code.push_str(&format!("{}# Zero or more repetition\n", indent));
+            }
+            code.push_str(&format!(
+                "{}while self.position < len(self.tokens):\n",
+                indent
+            ));
+            code.push_str(&save(&format!("{}    ", indent)));
+            code.push_str(&format!("{}    try:\n", indent));
+            code.push_str(&python_element(
+                repeated,
+                rule,
+                &format!("{}        ", indent),
+                depth + 1,
+            )?);
+            code.push_str(&format!("{}    except ParseError:\n", indent));
+            code.push_str(&restore(&format!("{}        ", indent)));
+            code.push_str(&format!("{}        break\n", indent));
+            if one_or_more {
+                code.push_str(&format!("{}    {} += 1\n", indent, count));
+            }
+            code.push_str(&format!("{}    if self.position == {}:\n", indent, saved));
+            code.push_str(&format!("{}        break\n", indent));
+            if one_or_more {
+                code.push_str(&format!("{}if {} == 0:\n", indent, count));
+                code.push_str(&format!(
+                    "{}    raise ParseError('Expected at least one match', self.position, [], None)\n",
+                    indent
+                ));
+            }
+        }
+        Element::Group { alternatives } => {
+            code.push_str(&format!("{}# Group\n", indent));
+            code.push_str(&save(indent));
+            code.push_str(&python_group(alternatives, rule, indent, depth)?);
+        }
+        Element::Eof => {
+            code.push_str(&format!("{}# Match end of input\n", indent));
+            code.push_str(&format!("{}if self.position < len(self.tokens) and self.tokens[self.position].kind != TokenKind.EOF:\n", indent));
+            code.push_str(&format!("{}    raise ParseError(f'Expected EOF, got {{self.tokens[self.position].kind}}', self.position, ['EOF'], None)\n", indent));
+        }
+        other => {
+            return Err(Error::codegen(format!(
+                "rule {}: the python target cannot generate {} in a parser rule",
+                rule.name,
+                element_description(other)
+            )));
+        }
+    }
+    Ok(code)
+}
+
+/// Try each of a group's alternatives in turn, going back to the position
+/// saved at `depth` after one fails. The last one's error propagates.
+///
+/// The alternatives are tried one after another under a `matched_<depth>`
+/// flag rather than nested in each other's `except`, which Python would
+/// reject past 20 alternatives.
+fn python_group(
+    alternatives: &[Alternative],
+    rule: &Rule,
+    indent: &str,
+    depth: usize,
+) -> Result<String> {
+    let body = |alt: &Alternative, indent: &str| -> Result<String> {
+        let mut code = String::new();
+        for element in &alt.elements {
+            code.push_str(&python_element(element, rule, indent, depth + 1)?);
+        }
+        if code.is_empty() {
+            code.push_str(&format!("{}pass\n", indent));
+        }
+        Ok(code)
+    };
+
+    let Some((last, rest)) = alternatives.split_last() else {
+        return Ok(String::new());
+    };
+    if rest.is_empty() {
+        return body(last, indent);
+    }
+
+    let matched = format!("matched_{}", depth);
+    let nested = format!("{}    ", indent);
+    let mut code = format!("{}{} = False\n", indent, matched);
+    for (i, alt) in rest.iter().enumerate() {
+        let mut indent = indent;
+        if i > 0 {
+            code.push_str(&format!("{}if not {}:\n", indent, matched));
+            indent = &nested;
+        }
+        code.push_str(&format!("{}try:\n", indent));
+        code.push_str(&body(alt, &format!("{}    ", indent))?);
+        code.push_str(&format!("{}    {} = True\n", indent, matched));
+        code.push_str(&format!("{}except ParseError:\n", indent));
+        code.push_str(&format!(
+            "{indent}    self.position = saved_pos_{depth}\n{indent}    del children[saved_children_{depth}:]\n"
+        ));
+    }
+    code.push_str(&format!("{}if not {}:\n", indent, matched));
+    code.push_str(&body(last, &nested)?);
+    Ok(code)
+}
+
+/// Python condition on `ch` for a character class or range.
+fn python_char_condition(element: &Element) -> String {
+    let ranges = match element {
+        Element::CharClass { ranges, .. } => ranges.clone(),
+        Element::CharRange { start, end } => vec![(*start, *end)],
+        _ => Vec::new(),
+    };
+    let condition = ranges
+        .iter()
+        .map(|(start, end)| {
+            if start == end {
+                format!("ch == {}", single_quoted(&start.to_string()))
+            } else {
+                format!(
+                    "{} <= ch <= {}",
+                    single_quoted(&start.to_string()),
+                    single_quoted(&end.to_string())
+                )
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" or ");
+    match element {
+        Element::CharClass { negated: true, .. } => format!("not ({})", condition),
+        _ => format!("({})", condition),
+    }
+}
+
+/// What an element a target cannot generate is, for error messages.
+pub(crate) fn element_description(element: &Element) -> &'static str {
+    match element {
+        Element::UnicodeClass { .. } => "a Unicode property class",
+        Element::Action { .. } => "an action",
+        Element::Predicate { .. } => "a semantic predicate",
+        _ => "this element",
+    }
+}
+
+/// Token rules dropped by `-> skip`, and those sent to another channel with
+/// the channel's name.
+///
+/// A skipped token is never routed, and when a rule names several channels
+/// the last one wins. `DEFAULT_TOKEN_CHANNEL` keeps a token in the main stream.
+fn token_commands(grammar: &Grammar) -> (Vec<&str>, Vec<(&str, &str)>) {
+    let mut skipped = Vec::new();
+    let mut routed = Vec::new();
+    for rule in grammar.lexer_rules().filter(|rule| !rule.is_fragment) {
+        let commands = rule_commands(rule);
+        if commands.contains(&LexerCommand::Skip) {
+            skipped.push(rule.name.as_str());
+            continue;
+        }
+        let channel = commands.iter().rev().find_map(|command| match command {
+            LexerCommand::Channel(name) => Some(name.as_str()),
+            _ => None,
+        });
+        if let Some(channel) = channel.filter(|name| !matches!(*name, "DEFAULT_TOKEN_CHANNEL" | "0")) {
+            routed.push((rule.name.as_str(), channel));
+        }
+    }
+    (skipped, routed)
+}
+
+/// Fields for the labeled elements of `rule`, in the order they first
+/// appear.
+fn labeled_fields(rule: &Rule) -> Vec<(String, String, bool)> {
+    let mut fields = Vec::new();
+    let mut seen_labels = std::collections::HashSet::new();
+
+    for alt in &rule.alternatives {
+        for elem in &alt.elements {
+            collect_labels_from_element(elem, &mut fields, &mut seen_labels);
+        }
+    }
+
+    fields
+}
+
+fn collect_labels_from_element(
+    element: &Element,
+    fields: &mut Vec<(String, String, bool)>,
+    seen_labels: &mut std::collections::HashSet<String>,
+) {
+    match element {
+        Element::Terminal { label: Some(label), is_list, .. } if seen_labels.insert(label.clone()) => {
+            fields.push((label.clone(), "Token".to_string(), *is_list));
+        }
+        Element::RuleRef { name, label: Some(label), is_list, .. } if seen_labels.insert(label.clone()) => {
+            fields.push((label.clone(), reference_type(name), *is_list));
+        }
+        Element::StringLiteral { label: Some(label), is_list, .. } if seen_labels.insert(label.clone()) => {
+            fields.push((label.clone(), "Token".to_string(), *is_list));
+        }
+        Element::Group { alternatives } => {
+            for alt in alternatives {
+                for elem in &alt.elements {
+                    collect_labels_from_element(elem, fields, seen_labels);
+                }
+            }
+        }
+        Element::Optional { element, .. }
+        | Element::ZeroOrMore { element, .. }
+        | Element::OneOrMore { element, .. } => {
+            collect_labels_from_element(element, fields, seen_labels);
+        }
+        _ => {}
+    }
+}
+
+/// Fields of the node class of `rule`: its labels, then the unlabeled
+/// references whose field names no label takes.
+fn node_fields(rule: &Rule) -> Vec<(String, String, bool)> {
+    let mut fields = labeled_fields(rule);
+    for implicit in implicit_fields(rule) {
+        if !fields.iter().any(|(name, _, _)| *name == implicit.0) {
+            fields.push(implicit);
+        }
+    }
+    fields
+}
+
+/// Field an unlabeled reference to `name` in `rule` is stored in, and
+/// whether it is a list, unless a label took its name.
+fn reference_field(rule: &Rule, name: &str) -> Option<(String, bool)> {
+    let field_name = python_identifier(&name.to_lowercase());
+    if labeled_fields(rule).iter().any(|(label, _, _)| *label == field_name) {
+        return None;
+    }
+    implicit_fields(rule)
+        .into_iter()
+        .find(|(field, _, _)| *field == field_name)
+        .map(|(field, _, is_list)| (field, is_list))
+}
+
+/// Python type of a reference: `Token` for tokens, the node class for rules.
+fn reference_type(name: &str) -> String {
+    if name.starts_with(|c: char| c.is_uppercase()) {
+        "Token".to_string()
+    } else {
+        format!("{}Node", to_pascal_case(name))
+    }
+}
+
+/// Fields for the unlabeled rule and token references of `rule`, in the
+/// order they first appear.
+///
+/// Names that clash with Python keywords get a trailing `_`.
+fn implicit_fields(rule: &Rule) -> Vec<(String, String, bool)> {
+    let mut fields: Vec<(String, String, bool)> = Vec::new();
+    for (name, is_list) in unlabeled_references(rule) {
+        let field_name = python_identifier(&name.to_lowercase());
+        match fields.iter_mut().find(|(seen, _, _)| *seen == field_name) {
+            Some((_, _, list)) => *list |= is_list,
+            None => fields.push((field_name, reference_type(&name), is_list)),
+        }
+    }
+    fields
+}
+
+/// `name`, with a trailing `_` if it is a Python keyword or soft keyword.
+fn python_identifier(name: &str) -> String {
+    const KEYWORDS: &[&str] = &[
+        "and", "as", "assert", "async", "await", "break", "case", "children", "class",
+        "continue", "def", "del", "elif", "else", "except", "false", "finally", "for",
+        "from", "global", "if", "import", "in", "is", "lambda", "match", "none", "nonlocal",
+        "not", "or", "pass", "raise", "return", "true", "try", "type", "while", "with",
+        "yield",
+    ];
+    if KEYWORDS.contains(&name) {
+        format!("{}_", name)
+    } else {
+        name.to_string()
+    }
 }
 
 fn to_pascal_case(s: &str) -> String {
@@ -580,4 +1046,136 @@ mod tests {
         assert!(code.contains("class CalculatorParser"));
         assert!(code.contains("def parse_expr"));
     }
+
+    #[test]
+    fn test_ast_dataclasses() {
+        use crate::parser::GrammarParser;
+        use crate::traits::GrammarParser as GrammarParserTrait;
+
+        let grammar = GrammarParser::new()
+            .parse_string(
+                "grammar Json; pair: ID ':' value; value: ID | list=ID (',' ID)*; ID: [a-z]+;",
+                "Json.g4",
+            )
+            .unwrap();
+        let code = PythonCodeGenerator::new()
+            .generate(&grammar, &CodeGenConfig::default())
+            .unwrap();
+
+        assert!(code.contains("from dataclasses import dataclass, field\n"));
+        assert!(code.contains(
+            "@dataclass\nclass AstNode:\n    \"\"\"Base class of all AST nodes.\"\"\"\n    children: List[Any] = field(default_factory=list)\n"
+        ));
+        assert!(code.contains(
+            "@dataclass\nclass PairNode(AstNode):\n    \"\"\"AST node for pair rule.\"\"\"\n    id: Optional[Token] = None\n    value: Optional[ValueNode] = None\n"
+        ));
+        // The label keeps its own field; the unlabeled IDs can repeat
+        assert!(code.contains("    list: Optional[Token] = None\n    id: List[Token] = field(default_factory=list)\n"));
+        // Rule methods fill the fields and children in
+        assert!(code.contains("        children.append(self.parse_value())\n        value = children[-1]\n"));
+        assert!(code.contains("        return PairNode(children=children, id=id, value=value)\n"));
+    }
+
+    #[test]
+    fn test_super_class() {
+        let mut grammar = Grammar::new("Calculator".to_string(), GrammarType::Parser);
+        grammar.add_rule(Rule::parser_rule("expr".to_string()));
+        grammar.add_option("superClass".to_string(), "BaseParser".to_string());
+
+        let generator = PythonCodeGenerator::new();
+        let code = generator.generate(&grammar, &CodeGenConfig::default()).unwrap();
+
+        assert!(code.contains(
+            "class CalculatorParser(BaseParser):\n    def __init__(self, tokens):\n        super().__init__()\n"
+        ));
+        assert!(code.contains("class CalculatorLexer:\n"));
+        assert!(crate::codegen::unsupported_option_warnings(&grammar, &generator).is_empty());
+    }
+
+    #[test]
+    fn test_token_kind_enum() {
+        let mut grammar = Grammar::new("Calculator".to_string(), GrammarType::Parser);
+        grammar.add_rule(Rule::parser_rule("expr".to_string()));
+        grammar.add_rule(Rule::lexer_rule("NUMBER".to_string()));
+
+        let generator = PythonCodeGenerator::new();
+        let code = generator.generate(&grammar, &CodeGenConfig::default()).unwrap();
+
+        assert!(code.contains("from enum import Enum"));
+        assert!(code.contains("class TokenKind(Enum):"));
+        assert!(code.contains("    def __str__(self) -> str:\n        return self.name\n"));
+        assert!(code.contains("    NUMBER = 'NUMBER'"));
+        assert!(code.contains("    EOF = 'EOF'"));
+    }
+
+    #[test]
+    fn test_tokens_carry_line_column_and_byte_offsets() {
+        let mut grammar = Grammar::new("Calculator".to_string(), GrammarType::Parser);
+        grammar.add_rule(Rule::parser_rule("expr".to_string()));
+        grammar.add_rule(Rule::lexer_rule("NUMBER".to_string()));
+
+        let code = PythonCodeGenerator::new().generate(&grammar, &CodeGenConfig::default()).unwrap();
+
+        assert!(code.contains("    position: int\n    line: int = 1\n    column: int = 0\n    start: int = 0\n    stop: int = 0\n"));
+        assert!(code.contains("            return self._place(token, start_pos, self.position)\n"));
+        assert!(code.contains("            byte += len(ch.encode('utf-8'))\n"));
+    }
+
+    #[test]
+    fn test_lexer_is_a_generator() {
+        let mut grammar = Grammar::new("Calculator".to_string(), GrammarType::Parser);
+        grammar.add_rule(Rule::parser_rule("expr".to_string()));
+        grammar.add_rule(Rule::lexer_rule("NUMBER".to_string()));
+
+        let code = PythonCodeGenerator::new().generate(&grammar, &CodeGenConfig::default()).unwrap();
+
+        assert!(code.contains("    def __iter__(self) -> Iterator[Union[Token, ParseError]]:\n"));
+        assert!(code.contains("            except ParseError as err:\n                yield err\n"));
+        assert!(code.contains("            yield token\n            if token.kind == TokenKind.EOF:\n                return\n"));
+        assert!(code.contains("        for item in self:\n"));
+    }
+
+    #[test]
+    fn test_skip_and_channel_commands() {
+        use crate::parser::GrammarParser;
+        use crate::traits::GrammarParser as GrammarParserTrait;
+
+        let grammar = GrammarParser::new()
+            .parse_string(
+                "grammar Calc; expr: NUMBER; NUMBER: [0-9]+; WS: [ \\t]+ -> skip; \
+                 COMMENT: '#' ~[\\n]* -> channel(HIDDEN);",
+                "Calc.g4",
+            )
+            .unwrap();
+        let code = PythonCodeGenerator::new()
+            .generate(&grammar, &CodeGenConfig::default())
+            .unwrap();
+
+        assert!(code.contains("    SKIP = frozenset({TokenKind.WS})\n"));
+        assert!(code.contains("    CHANNELS = {TokenKind.COMMENT: 'HIDDEN'}\n"));
+        assert!(code.contains("            if token.kind in self.SKIP:\n                continue\n"));
+        assert!(code.contains(
+            "            if token.kind in self.CHANNELS:\n                self.hidden_tokens.append(token)\n                continue\n"
+        ));
+    }
+
+    #[test]
+    fn test_unsupported_parser_element_is_an_error() {
+        let mut grammar = Grammar::new("Letters".to_string(), GrammarType::Parser);
+        let mut rule = Rule::parser_rule("letter".to_string());
+        let mut alt = Alternative::new();
+        alt.add_element(Element::UnicodeClass {
+            negated: false,
+            category: "L".to_string(),
+        });
+        rule.add_alternative(alt);
+        grammar.add_rule(rule);
+
+        let err = PythonCodeGenerator::new()
+            .generate(&grammar, &CodeGenConfig::default())
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("rule letter: the python target cannot generate a Unicode property class in a parser rule"));
+    }
 }
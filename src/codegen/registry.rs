@@ -1,14 +1,80 @@
 //! Language registry for code generators.
 
 use crate::ast::Grammar;
+use crate::types::GrammarType;
 use crate::{types::CodeGenConfig, CodeGenerator as CodeGeneratorTrait, Error, Result};
 use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// Boxed code generator for storage.
+pub type BoxedGenerator = Box<dyn CodeGeneratorTrait<Input = Grammar, Config = CodeGenConfig>>;
+
+/// Where `minipg generate` puts a target's output, following the
+/// language's file naming conventions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileLayout {
+    /// Directory under the output directory the generated code goes in,
+    /// or `""` for the output directory itself
+    pub directory: &'static str,
+    /// Name of the generated parser file, where `{name}` stands for the
+    /// grammar name in snake case and `{Name}` for it as written
+    pub parser_file: &'static str,
+}
+
+impl FileLayout {
+    pub const fn new(directory: &'static str, parser_file: &'static str) -> Self {
+        Self { directory, parser_file }
+    }
+
+    /// Paths, relative to the output directory, of the files generated for
+    /// `grammar`: the parser, and for a lexer grammar its `.tokens`
+    /// vocabulary, which stays in the output directory itself so parser
+    /// grammars generated there can find it.
+    pub fn files(&self, grammar: &Grammar) -> Vec<PathBuf> {
+        let parser_file = self
+            .parser_file
+            .replace("{name}", &snake_case(&grammar.name))
+            .replace("{Name}", &grammar.name);
+        let mut files = vec![PathBuf::from(self.directory).join(parser_file)];
+        if grammar.grammar_type == GrammarType::Lexer {
+            files.push(PathBuf::from(format!("{}.tokens", grammar.name)));
+        }
+        files
+    }
+}
+
+impl Default for FileLayout {
+    /// `<name>_parser.txt` in the output directory, for targets registered
+    /// without a layout of their own.
+    fn default() -> Self {
+        Self::new("", "{name}_parser.txt")
+    }
+}
+
+/// `CalcParser` as `calc_parser` and `JSONLexer` as `json_lexer`.
+fn snake_case(name: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    let mut snake = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            let prev = chars[i - 1];
+            let next_is_lower = chars.get(i + 1).is_some_and(|next| next.is_lowercase());
+            if prev.is_lowercase() || prev.is_ascii_digit() || (prev.is_uppercase() && next_is_lower) {
+                snake.push('_');
+            }
+        }
+        snake.extend(c.to_lowercase());
+    }
+    snake
+}
 
 /// Language registry for managing code generators.
 pub struct LanguageRegistry {
-    generators:
-        HashMap<String, Box<dyn CodeGeneratorTrait<Input = Grammar, Config = CodeGenConfig>>>,
+    generators: HashMap<String, BoxedGenerator>,
     aliases: HashMap<String, String>, // alias -> canonical name
+    /// Layouts by language name or alias, so an alias such as `ts` can
+    /// name its files differently from its canonical language
+    layouts: HashMap<String, FileLayout>,
 }
 
 impl LanguageRegistry {
@@ -17,27 +83,36 @@ impl LanguageRegistry {
         let mut reg = Self {
             generators: HashMap::new(),
             aliases: HashMap::new(),
+            layouts: HashMap::new(),
         };
 
         // Register core generators (Rust, Python, JavaScript)
-        reg.register("rust", RustCodeGenerator::new());
-        reg.register("python", PythonCodeGenerator::new());
-        reg.register("javascript", JavaScriptCodeGenerator::new());
+        reg.register("rust", &[], Box::new(RustCodeGenerator::new()));
+        reg.register("python", &[], Box::new(PythonCodeGenerator::new()));
+        reg.register(
+            "javascript",
+            &["js", "typescript", "ts"],
+            Box::new(JavaScriptCodeGenerator::new()),
+        );
 
-        // Register aliases
-        reg.register_alias("js", "javascript");
+        reg.set_layout("rust", FileLayout::new("src", "{name}_parser.rs"));
+        reg.set_layout("python", FileLayout::new("", "{name}_parser.py"));
+        reg.set_layout("javascript", FileLayout::new("", "{name}_parser.js"));
+        reg.set_layout("typescript", FileLayout::new("", "{name}_parser.ts"));
+        reg.set_layout("ts", FileLayout::new("", "{name}_parser.ts"));
 
         reg
     }
 
-    /// Register a code generator for a language.
-    pub fn register(
-        &mut self,
-        name: &str,
-        generator: impl CodeGeneratorTrait<Input = Grammar, Config = CodeGenConfig> + 'static,
-    ) {
-        self.generators
-            .insert(name.to_string(), Box::new(generator));
+    /// Register a code generator for a language under `name` and `aliases`.
+    ///
+    /// This is how downstream crates add their own targets. Registering a
+    /// name that already exists replaces its generator.
+    pub fn register(&mut self, name: &str, aliases: &[&str], generator: BoxedGenerator) {
+        self.generators.insert(name.to_string(), generator);
+        for alias in aliases {
+            self.register_alias(alias, name);
+        }
     }
 
     /// Register a language alias.
@@ -46,6 +121,22 @@ impl LanguageRegistry {
             .insert(alias.to_string(), canonical.to_string());
     }
 
+    /// Set the file layout of a language name or alias.
+    pub fn set_layout(&mut self, name: &str, layout: FileLayout) {
+        self.layouts.insert(name.to_string(), layout);
+    }
+
+    /// The file layout of `name`: its own if it has one, else that of the
+    /// language it is an alias of, else [`FileLayout::default`].
+    pub fn layout(&self, name: &str) -> FileLayout {
+        let canonical_name = self.aliases.get(name).map(|s| s.as_str()).unwrap_or(name);
+        self.layouts
+            .get(name)
+            .or_else(|| self.layouts.get(canonical_name))
+            .copied()
+            .unwrap_or_default()
+    }
+
     /// Get a code generator by name (supports aliases).
     pub fn get(
         &self,
@@ -62,9 +153,11 @@ impl LanguageRegistry {
         self.generators.contains_key(canonical_name)
     }
 
-    /// Get list of all supported languages.
+    /// Get list of all supported languages, sorted by name.
     pub fn supported_languages(&self) -> Vec<String> {
-        self.generators.keys().cloned().collect()
+        let mut languages: Vec<String> = self.generators.keys().cloned().collect();
+        languages.sort();
+        languages
     }
 
     /// Generate code using the registered generator.
@@ -113,9 +206,60 @@ mod tests {
         assert!(reg.is_supported("javascript"));
     }
 
+    #[test]
+    fn test_file_layouts() {
+        let reg = LanguageRegistry::new();
+        let grammar = Grammar::new("CalcParser".to_string(), GrammarType::Combined);
+        let files = |language: &str| reg.layout(language).files(&grammar);
+
+        assert_eq!(files("rust"), vec![PathBuf::from("src/calc_parser_parser.rs")]);
+        assert_eq!(files("python"), vec![PathBuf::from("calc_parser_parser.py")]);
+        assert_eq!(files("js"), vec![PathBuf::from("calc_parser_parser.js")]);
+        assert_eq!(files("ts"), vec![PathBuf::from("calc_parser_parser.ts")]);
+        assert_eq!(files("kotlin"), vec![PathBuf::from("calc_parser_parser.txt")]);
+
+        let lexer = Grammar::new("JSONLexer".to_string(), GrammarType::Lexer);
+        assert_eq!(
+            reg.layout("rust").files(&lexer),
+            vec![PathBuf::from("src/json_lexer_parser.rs"), PathBuf::from("JSONLexer.tokens")]
+        );
+    }
+
     #[test]
     fn test_unsupported_language() {
         let reg = LanguageRegistry::new();
         assert!(!reg.is_supported("swift"));
     }
+
+    struct KotlinCodeGenerator;
+
+    impl CodeGeneratorTrait for KotlinCodeGenerator {
+        type Input = Grammar;
+        type Config = CodeGenConfig;
+
+        fn generate(&self, input: &Grammar, _config: &CodeGenConfig) -> Result<String> {
+            Ok(format!("class {}Parser", input.name))
+        }
+
+        fn target_language(&self) -> &str {
+            "kotlin"
+        }
+    }
+
+    #[test]
+    fn test_register_custom_generator() {
+        let mut reg = LanguageRegistry::new();
+        reg.register("kotlin", &["kt"], Box::new(KotlinCodeGenerator));
+
+        assert!(reg.is_supported("kotlin"));
+        assert!(reg.is_supported("kt"));
+        assert_eq!(
+            reg.supported_languages(),
+            vec!["javascript", "kotlin", "python", "rust"]
+        );
+
+        let grammar = Grammar::new("Calc".to_string(), crate::types::GrammarType::Combined);
+        let code = reg.generate("kt", &grammar, &CodeGenConfig::default()).unwrap();
+        assert_eq!(code, "class CalcParser");
+    }
 }
@@ -2,9 +2,20 @@
 //!
 //! This module generates the actual parsing logic for rules based on
 //! their alternatives and elements, including error recovery.
+//!
+//! The generated Rust code is a backtracking recursive-descent parser:
+//! alternatives are tried in order, and every nested construct (choice,
+//! optional, loop) is emitted as a labeled block evaluating to
+//! `Result<(), ParseError>` so a failure unwinds to the nearest choice point.
 
-use crate::ast::{Alternative, Element, Rule};
-use std::collections::HashSet;
+use super::actions::translate_action_element;
+use super::common::{format_identifier, indentation, literal_token_kinds, RuleSignature};
+use super::dfa::case_insensitive_literals;
+use super::visitor_gen::{label_enum, label_variant};
+use crate::analysis::left_recursion::PrecedenceTable;
+use crate::analysis::{FirstFollowSets, SemanticAnalyzer};
+use crate::ast::{unicode_property, Alternative, AstVisitor, Element, Grammar, PredicatePosition, Rule};
+use std::collections::{HashMap, HashSet};
 
 /// Context for rule body generation
 pub struct RuleBodyContext {
@@ -12,7 +23,41 @@ pub struct RuleBodyContext {
     pub current_token_var: String,
     pub peek_token_var: String,
     pub error_recovery: bool,
-    pub visited_rules: HashSet<String>,
+    /// Signatures of the grammar's parser rules, keyed by rule name.
+    pub signatures: HashMap<String, RuleSignature>,
+    /// Enclosing block labels; failures break out of the innermost one.
+    block_labels: Vec<String>,
+    next_label: usize,
+    /// Whether the rule being generated collects sub-rule nodes in `children`.
+    track_children: bool,
+    /// Fields holding unlabeled rule references, as rule name to
+    /// `(field, is_list)`, for the rule being generated.
+    ref_fields: HashMap<String, (String, bool)>,
+    /// Whether choices count attempts in the parser's `alt_stats`.
+    alt_stats: bool,
+    /// Rule whose top-level choice is generated next; only that choice is
+    /// counted, not the groups nested in it.
+    stats_rule: Option<String>,
+    /// Per alternative of the top-level choice generated next, the label
+    /// enum variant recorded in `alt_label` when it matches, if labeled.
+    alt_labels: Option<Vec<Option<String>>>,
+    /// Rules parsed by precedence climbing, whose parse methods take the
+    /// minimum precedence to accept.
+    precedence_rules: HashSet<String>,
+    /// Whether the parser also builds a concrete syntax tree in `self.cst`.
+    cst: bool,
+    /// Synchronization sets when failed rules recover instead of failing.
+    sync: Option<SyncSets>,
+    /// Rule being generated, whose FOLLOW set ends its top-level loops.
+    rule_name: String,
+    /// Exit and resynchronization kinds for the loop generated next.
+    loop_sync: Option<(String, String)>,
+    /// Literals the lexer matches in either case, which tokens match
+    /// regardless of case too.
+    case_insensitive_literals: HashSet<String>,
+    /// Predicate methods, as `(rule, code)` to the method's index and the
+    /// variables passed to it.
+    predicates: HashMap<(String, String), (usize, Vec<String>)>,
 }
 
 impl RuleBodyContext {
@@ -22,7 +67,21 @@ impl RuleBodyContext {
             current_token_var: "current_token".to_string(),
             peek_token_var: "peek_token".to_string(),
             error_recovery: true,
-            visited_rules: HashSet::new(),
+            signatures: HashMap::new(),
+            block_labels: Vec::new(),
+            next_label: 0,
+            track_children: false,
+            ref_fields: HashMap::new(),
+            alt_stats: false,
+            stats_rule: None,
+            alt_labels: None,
+            precedence_rules: HashSet::new(),
+            cst: false,
+            sync: None,
+            rule_name: String::new(),
+            loop_sync: None,
+            case_insensitive_literals: HashSet::new(),
+            predicates: HashMap::new(),
         }
     }
 
@@ -36,6 +95,126 @@ impl RuleBodyContext {
         self.peek_token_var = peek;
         self
     }
+
+    /// Record the signatures of the grammar's parser rules so rule
+    /// references pass the right number of arguments and labels get the
+    /// referenced rule's value type, and which literals ignore case.
+    pub fn with_grammar(mut self, grammar: &Grammar) -> Self {
+        self.signatures = rule_signatures(grammar);
+        self.case_insensitive_literals = case_insensitive_literals(grammar);
+        self.predicates = rust_predicate_methods(grammar)
+            .into_iter()
+            .enumerate()
+            .map(|(index, method)| {
+                let vars = method.params.into_iter().map(|(name, _)| name).collect();
+                ((method.rule, method.source), (index, vars))
+            })
+            .collect();
+        self
+    }
+
+    /// Count how often each rule alternative is tried and matches.
+    pub fn with_alt_stats(mut self, alt_stats: bool) -> Self {
+        self.alt_stats = alt_stats;
+        self
+    }
+
+    /// Call the parse methods of `rules` with a minimum precedence, 0
+    /// unless the reference has a `<prec=N>` option.
+    pub fn with_precedence_rules(mut self, rules: HashSet<String>) -> Self {
+        self.precedence_rules = rules;
+        self
+    }
+
+    /// Record matched tokens and finished rules in the parser's `cst`
+    /// builder, backtracking it along with the token position.
+    pub fn with_cst(mut self, cst: bool) -> Self {
+        self.cst = cst;
+        self
+    }
+
+    /// Recover from errors in loops at the top level of a rule, and run
+    /// rule references that may be backtracked as speculative.
+    pub fn with_recovery(mut self, sync: SyncSets) -> Self {
+        self.sync = Some(sync);
+        self
+    }
+
+    fn pad(&self) -> String {
+        indentation(self.indent)
+    }
+
+    fn fresh_label(&mut self, prefix: &str) -> String {
+        let label = format!("'{}{}", prefix, self.next_label);
+        self.next_label += 1;
+        label
+    }
+
+    /// Statement that fails the current sequence with `err`.
+    fn fail(&self, err: &str) -> String {
+        match self.block_labels.last() {
+            Some(label) => format!("break {} Err({});", label, err),
+            None => format!("return Err({});", err),
+        }
+    }
+
+    /// Expression unwrapping `expr`, failing the current sequence on error.
+    fn try_value(&self, expr: &str) -> String {
+        match self.block_labels.last() {
+            Some(label) => format!(
+                "match {} {{ Ok(value) => value, Err(err) => break {} Err(err) }}",
+                expr, label
+            ),
+            None => format!("{}?", expr),
+        }
+    }
+
+    /// Statements recording the state to backtrack to.
+    fn save_state(&self, pad: &str) -> String {
+        let mut code = format!("{}let saved_pos = self.tokens.position();\n", pad);
+        code.push_str(&format!("{}let saved_leaves = self.leaves.len();\n", pad));
+        if self.cst {
+            code.push_str(&format!("{}let saved_cst = self.cst.len();\n", pad));
+        }
+        if self.track_children {
+            code.push_str(&format!("{}let saved_children = children.len();\n", pad));
+        }
+        code
+    }
+
+    /// Statements backtracking to the state recorded by `save_state`.
+    fn restore_state(&self, pad: &str) -> String {
+        let mut code = format!("{}self.tokens.seek(saved_pos);\n", pad);
+        code.push_str(&format!("{}self.leaves.truncate(saved_leaves);\n", pad));
+        if self.cst {
+            code.push_str(&format!("{}self.cst.truncate(saved_cst);\n", pad));
+        }
+        if self.track_children {
+            code.push_str(&format!("{}children.truncate(saved_children);\n", pad));
+        }
+        code
+    }
+
+    /// Statements consuming the next token, which the caller has checked,
+    /// as a leaf of the rule being parsed.
+    fn consume_token(&self, pad: &str) -> String {
+        if !self.cst {
+            return format!("{}self.leaves.extend(self.tokens.next());\n", pad);
+        }
+        let mut code = format!("{}if let Some(token) = self.tokens.next() {{\n", pad);
+        code.push_str(&format!("{}    self.cst.token(&token);\n", pad));
+        code.push_str(&format!("{}    self.leaves.push(token);\n", pad));
+        code.push_str(&format!("{}}}\n", pad));
+        code
+    }
+
+    /// Value type returned by the parse method of `rule_name`.
+    fn value_type(&self, rule_name: &str) -> String {
+        self.signatures
+            .get(rule_name)
+            .map(|sig| signature_value_type(&sig.returns))
+            .unwrap_or_else(|| "AstNode".to_string())
+    }
 }
 
 impl Default for RuleBodyContext {
@@ -44,225 +223,945 @@ impl Default for RuleBodyContext {
     }
 }
 
-/// Generate Rust code for a rule body
-pub fn generate_rust_rule_body(rule: &Rule, ctx: &mut RuleBodyContext) -> String {
-    let mut code = String::new();
+/// Token kinds that panic-mode recovery synchronizes on.
+///
+/// Recovery skips tokens until one of a rule's FOLLOW set, so literals in
+/// the FIRST and FOLLOW sets are resolved to the kind the lexer gives them.
+#[derive(Debug, Clone)]
+pub struct SyncSets {
+    sets: FirstFollowSets,
+    literal_kinds: HashMap<String, String>,
+}
 
-    if ctx.visited_rules.contains(&rule.name) {
-        // Prevent infinite recursion
-        code.push_str(&format!(
-            "{}// Recursive rule: {}\n",
-            " ".repeat(ctx.indent),
-            rule.name
-        ));
-        code.push_str(&format!(
-            "{}Err(ParseError::new(\"Recursive rule detected\", self.position))\n",
-            " ".repeat(ctx.indent)
-        ));
-        return code;
+impl SyncSets {
+    pub fn new(grammar: &Grammar) -> Self {
+        Self {
+            sets: SemanticAnalyzer::compute_first_follow(grammar),
+            literal_kinds: literal_token_kinds(grammar),
+        }
     }
 
-    ctx.visited_rules.insert(rule.name.clone());
+    /// `&[TokenKind::..]` slice of the tokens that can follow `rule`.
+    pub fn follow(&self, rule: &str) -> Option<String> {
+        self.kinds(self.sets.follow(rule))
+    }
 
-    // Handle multiple alternatives
-    if rule.alternatives.len() > 1 {
-        code.push_str(&generate_alternatives(rule, ctx, "rust"));
-    } else if let Some(alt) = rule.alternatives.first() {
-        code.push_str(&generate_alternative_body(alt, rule, ctx, "rust"));
-    } else {
-        code.push_str(&format!("{}// Empty rule\n", " ".repeat(ctx.indent)));
-        code.push_str(&format!(
-            "{}Ok(AstNode::{}(Box::new(())))\n",
-            " ".repeat(ctx.indent),
-            to_pascal_case(&rule.name)
-        ));
+    /// Tokens that can come after `rest` in `rule`.
+    fn after(&self, rest: &[Element], rule: &str) -> HashSet<String> {
+        let mut tokens = self.sets.first_of_sequence(rest);
+        if self.sets.is_sequence_nullable(rest) {
+            tokens.extend(self.sets.follow(rule).iter().cloned());
+        }
+        tokens
     }
 
-    ctx.visited_rules.remove(&rule.name);
-    code
+    /// `&[TokenKind::..]` slice of `tokens`, or `None` when any token can
+    /// appear. End of input always synchronizes, so `EOF` is left out.
+    fn kinds(&self, tokens: &HashSet<String>) -> Option<String> {
+        if tokens.contains("ANY") {
+            return None;
+        }
+        let mut kinds: Vec<String> = tokens
+            .iter()
+            .filter(|token| token.as_str() != "EOF")
+            .filter_map(|token| match token.strip_prefix('\'').and_then(|t| t.strip_suffix('\'')) {
+                Some(literal) => self.literal_kinds.get(literal).cloned(),
+                None => Some(token.clone()),
+            })
+            .map(|kind| format!("TokenKind::{}", kind))
+            .collect();
+        kinds.sort();
+        kinds.dedup();
+        Some(format!("&[{}]", kinds.join(", ")))
+    }
 }
 
-/// Generate code for multiple alternatives (if/else or match)
-fn generate_alternatives(rule: &Rule, ctx: &mut RuleBodyContext, lang: &str) -> String {
-    let mut code = String::new();
-    let indent_str = " ".repeat(ctx.indent);
+/// Rust type returned by the generated parse method of `rule`.
+pub fn rust_rule_value_type(rule: &Rule) -> String {
+    signature_value_type(&RuleSignature::from_rule(rule).returns)
+}
 
-    if lang == "rust" {
-        // Try each alternative in order (first match wins)
-        for (i, alt) in rule.alternatives.iter().enumerate() {
-            if i == 0 {
-                code.push_str(&format!("{}// Try first alternative\n", indent_str));
-                code.push_str(&format!("{}let start_pos = self.position;\n", indent_str));
-                code.push_str(&format!("{}match {{\n", indent_str));
+fn signature_value_type(returns: &[(String, Option<String>)]) -> String {
+    let type_of = |ret: &(String, Option<String>)| {
+        ret.1.clone().unwrap_or_else(|| "AstNode".to_string())
+    };
+    match returns {
+        [] => "AstNode".to_string(),
+        [single] => type_of(single),
+        _ => format!(
+            "({})",
+            returns.iter().map(type_of).collect::<Vec<_>>().join(", ")
+        ),
+    }
+}
 
-                // Save position for backtracking
-                code.push_str(&format!(
-                    "{}    let saved_pos = self.position;\n",
-                    indent_str
-                ));
-                ctx.indent += 4;
-                code.push_str(&generate_alternative_body(alt, rule, ctx, lang));
-                ctx.indent -= 4;
+/// Fields of a rule's node as `(name, type, is_list)`.
+///
+/// Labeled elements come first, in order of first appearance. Token
+/// references and literals produce `Token` fields; rule references produce
+/// the referenced rule's value type. They are followed by a field for each
+/// parser rule referenced without a label, see [`collect_rule_refs`].
+pub fn rust_labeled_fields(rule: &Rule, grammar: &Grammar) -> Vec<(String, String, bool)> {
+    let ctx = RuleBodyContext::new().with_grammar(grammar);
+    node_fields(rule, &ctx)
+}
 
-                // If successful, return
-                code.push_str(&format!(
-                    "{}    Ok(result) => return Ok(result),\n",
-                    indent_str
-                ));
-                code.push_str(&format!("{}    Err(_) => {{\n", indent_str));
-                code.push_str(&format!(
-                    "{}        self.position = saved_pos;\n",
-                    indent_str
-                ));
-                code.push_str(&format!("{}        // Try next alternative\n", indent_str));
+/// Field each parser rule referenced without a label is stored in, as
+/// `(rule, field)` pairs; see [`collect_rule_refs`].
+/// A semantic predicate `{...}?` of a parser rule, generated as a
+/// `pred_N` method of the parser so its code stays apart from the rule's
+/// control flow.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PredicateMethod {
+    /// Rule the predicate is written in.
+    pub rule: String,
+    /// The predicate's code as written in the grammar.
+    pub source: String,
+    /// `(name, type)` of the rule arguments, return values, locals and
+    /// node fields the code refers to as `$name`, each passed by reference.
+    pub params: Vec<(String, String)>,
+    /// The code in Rust, reading each `$name` through its parameter.
+    pub body: String,
+}
+
+/// The semantic predicates of the grammar's parser rules in order of
+/// appearance, so the one at index `N` becomes `pred_N`. A predicate
+/// written more than once in a rule gets a single method.
+///
+/// `$name` refers to a rule argument, return value or local of that
+/// name, or to a label or the field of an unlabeled reference to rule
+/// `name`. Any other `$name` is left as plain `name`.
+pub fn rust_predicate_methods(grammar: &Grammar) -> Vec<PredicateMethod> {
+    struct Predicates(Vec<Element>);
+
+    impl AstVisitor for Predicates {
+        fn visit_element(&mut self, element: &Element) {
+            if let Element::Predicate { .. } = element {
+                self.0.push(element.clone());
+            }
+            self.walk_element(element);
+        }
+    }
+
+    let mut ctx = RuleBodyContext::new();
+    ctx.signatures = rule_signatures(grammar);
+    let mut methods = Vec::new();
+    let mut seen = HashSet::new();
+    for rule in grammar.parser_rules() {
+        let mut predicates = Predicates(Vec::new());
+        predicates.visit_rule(rule);
+        if predicates.0.is_empty() {
+            continue;
+        }
+
+        let labels = collect_labels(rule, &ctx);
+        let refs = collect_rule_refs(rule, &ctx, &labels);
+        let field_type = |field_type: &str, is_list: bool| {
+            if is_list {
+                format!("Vec<{}>", field_type)
             } else {
-                code.push_str(&format!("{}    match {{\n", indent_str));
-                code.push_str(&format!(
-                    "{}        let saved_pos = self.position;\n",
-                    indent_str
-                ));
-                ctx.indent += 8;
-                code.push_str(&generate_alternative_body(alt, rule, ctx, lang));
-                ctx.indent -= 8;
-                code.push_str(&format!(
-                    "{}        Ok(result) => return Ok(result),\n",
-                    indent_str
-                ));
-                code.push_str(&format!("{}        Err(_) => {{\n", indent_str));
-                code.push_str(&format!(
-                    "{}            self.position = saved_pos;\n",
-                    indent_str
-                ));
+                format!("Option<{}>", field_type)
+            }
+        };
+        let attribute = |name: &str| -> Option<(String, String)> {
+            let typed = |declared: &Option<String>, default: &str| {
+                Some((name.to_string(), declared.as_deref().unwrap_or(default).to_string()))
+            };
+            if let Some(arg) = rule.arguments.iter().find(|arg| arg.name == name) {
+                return typed(&arg.arg_type, "String");
+            }
+            if let Some(ret) = rule.returns.iter().find(|ret| ret.name == name) {
+                return typed(&ret.return_type, "AstNode");
+            }
+            if let Some(local) = rule.locals.iter().find(|local| local.name == name) {
+                return typed(&local.local_type, "String");
             }
+            if let Some((label, ty, is_list)) = labels.iter().find(|(label, _, _)| label == name) {
+                return Some((label.clone(), field_type(ty, *is_list)));
+            }
+            refs.iter()
+                .find(|(referenced, _, _, _)| referenced == name)
+                .map(|(_, field, ty, is_list)| (field.clone(), field_type(ty, *is_list)))
+        };
+
+        for element in predicates.0 {
+            let Element::Predicate { code, .. } = &element else {
+                continue;
+            };
+            if !seen.insert((rule.name.clone(), code.clone())) {
+                continue;
+            }
+            let translated = translate_action_element(&element, "rust").unwrap_or_default();
+            let mut params: Vec<(String, String)> = Vec::new();
+            let body = replace_attributes(&translated, |name| match attribute(name) {
+                Some((var, ty)) => {
+                    if !params.iter().any(|(param, _)| *param == var) {
+                        params.push((var.clone(), ty));
+                    }
+                    format!("(*{})", var)
+                }
+                None => name.to_string(),
+            });
+            methods.push(PredicateMethod {
+                rule: rule.name.clone(),
+                source: code.clone(),
+                params,
+                body,
+            });
+        }
+    }
+    methods
+}
+
+/// `code` with each `$name` replaced by `replace(name)`.
+fn replace_attributes(code: &str, mut replace: impl FnMut(&str) -> String) -> String {
+    let mut result = String::new();
+    let mut rest = code;
+    while let Some(dollar) = rest.find('$') {
+        result.push_str(&rest[..dollar]);
+        let after = &rest[dollar + 1..];
+        let len = after
+            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or(after.len());
+        if len == 0 {
+            result.push('$');
+        } else {
+            result.push_str(&replace(&after[..len]));
+        }
+        rest = &after[len..];
+    }
+    result.push_str(rest);
+    result
+}
+
+fn rule_signatures(grammar: &Grammar) -> HashMap<String, RuleSignature> {
+    grammar
+        .parser_rules()
+        .map(|rule| (rule.name.clone(), RuleSignature::from_rule(rule)))
+        .collect()
+}
+
+pub fn rust_rule_ref_fields(rule: &Rule, grammar: &Grammar) -> Vec<(String, String)> {
+    let ctx = RuleBodyContext::new().with_grammar(grammar);
+    collect_rule_refs(rule, &ctx, &collect_labels(rule, &ctx))
+        .into_iter()
+        .map(|(name, field, _, _)| (name, field))
+        .collect()
+}
+
+fn node_fields(rule: &Rule, ctx: &RuleBodyContext) -> Vec<(String, String, bool)> {
+    let mut fields = collect_labels(rule, ctx);
+    let refs = collect_rule_refs(rule, ctx, &fields);
+    fields.extend(
+        refs.into_iter()
+            .map(|(_, field, field_type, is_list)| (field, field_type, is_list)),
+    );
+    fields
+}
+
+/// Fields for parser rules referenced without a label, as
+/// `(rule, field, type, is_list)` in order of first appearance.
+///
+/// Like ANTLR's context accessors, the field is named after the rule, in
+/// snake case. It is a list when one match of the rule can reference the
+/// other rule more than once, as in `expr '+' expr` or `stmt*`. Names
+/// already used by a label are skipped.
+fn collect_rule_refs(
+    rule: &Rule,
+    ctx: &RuleBodyContext,
+    labels: &[(String, String, bool)],
+) -> Vec<(String, String, String, bool)> {
+    let mut names = Vec::new();
+    for alt in &rule.alternatives {
+        collect_ref_names(&alt.elements, &mut names);
+    }
+    let counts = max_ref_counts(&rule.alternatives);
+
+    let mut taken: HashSet<String> = labels.iter().map(|(name, _, _)| name.clone()).collect();
+    taken.insert("children".to_string());
+    if label_enum(rule).is_some() {
+        taken.insert("label".to_string());
+    }
+
+    names
+        .into_iter()
+        .filter_map(|name| {
+            let field = rust_field_name(&name);
+            if !taken.insert(field.clone()) {
+                return None;
+            }
+            let is_list = counts.get(&name).is_some_and(|count| *count > 1);
+            let field_type = ctx.value_type(&name);
+            Some((name, field, field_type, is_list))
+        })
+        .collect()
+}
+
+fn is_unlabeled_rule_ref(element: &Element) -> Option<&str> {
+    match element {
+        Element::RuleRef {
+            name, label: None, ..
+        } if !is_token_name(name) && name != "EOF" => Some(name),
+        _ => None,
+    }
+}
+
+fn collect_ref_names(elements: &[Element], names: &mut Vec<String>) {
+    for element in elements {
+        if let Some(name) = is_unlabeled_rule_ref(element) {
+            if !names.iter().any(|n| n == name) {
+                names.push(name.to_string());
+            }
+            continue;
+        }
+        match element {
+            Element::Group { alternatives } => {
+                for alt in alternatives {
+                    collect_ref_names(&alt.elements, names);
+                }
+            }
+            Element::Optional { element, .. }
+            | Element::ZeroOrMore { element, .. }
+            | Element::OneOrMore { element, .. } => {
+                collect_ref_names(std::slice::from_ref(element.as_ref()), names);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Most references to each rule in one match of any of `alternatives`,
+/// where 2 stands for "more than one".
+fn max_ref_counts(alternatives: &[Alternative]) -> HashMap<String, usize> {
+    let mut max = HashMap::new();
+    for alt in alternatives {
+        for (name, count) in sequence_ref_counts(&alt.elements) {
+            let entry = max.entry(name).or_insert(0);
+            *entry = count.max(*entry);
+        }
+    }
+    max
+}
+
+fn sequence_ref_counts(elements: &[Element]) -> HashMap<String, usize> {
+    let mut counts = HashMap::new();
+    for element in elements {
+        let element_counts = match element {
+            Element::Group { alternatives } => max_ref_counts(alternatives),
+            Element::Optional { element, .. } => {
+                sequence_ref_counts(std::slice::from_ref(element.as_ref()))
+            }
+            Element::ZeroOrMore { element, .. } | Element::OneOrMore { element, .. } => {
+                let mut repeated = sequence_ref_counts(std::slice::from_ref(element.as_ref()));
+                repeated.values_mut().for_each(|count| *count = 2);
+                repeated
+            }
+            _ => is_unlabeled_rule_ref(element)
+                .map(|name| HashMap::from([(name.to_string(), 1)]))
+                .unwrap_or_default(),
+        };
+        for (name, count) in element_counts {
+            let entry = counts.entry(name).or_insert(0);
+            *entry = (*entry + count).min(2);
+        }
+    }
+    counts
+}
+
+/// Snake-case field name for a rule, escaping Rust keywords.
+fn rust_field_name(rule_name: &str) -> String {
+    const KEYWORDS: &[&str] = &[
+        "as", "async", "await", "break", "const", "continue", "dyn", "else", "enum", "extern",
+        "false", "fn", "for", "gen", "if", "impl", "in", "let", "loop", "match", "mod", "move",
+        "mut", "pub", "ref", "return", "static", "struct", "trait", "true", "try", "type",
+        "unsafe", "use", "where", "while", "yield",
+    ];
+    let name = format_identifier(rule_name, "rust");
+    if KEYWORDS.contains(&name.as_str()) {
+        format!("r#{}", name)
+    } else {
+        name
+    }
+}
+
+fn collect_labels(rule: &Rule, ctx: &RuleBodyContext) -> Vec<(String, String, bool)> {
+    let mut fields = Vec::new();
+    let mut seen = HashSet::new();
+    for alt in &rule.alternatives {
+        for element in &alt.elements {
+            collect_element_labels(element, ctx, &mut fields, &mut seen);
+        }
+    }
+    fields
+}
+
+fn collect_element_labels(
+    element: &Element,
+    ctx: &RuleBodyContext,
+    fields: &mut Vec<(String, String, bool)>,
+    seen: &mut HashSet<String>,
+) {
+    match element {
+        Element::RuleRef {
+            name,
+            label: Some(label),
+            is_list,
+            ..
+        } if seen.insert(label.clone()) => {
+            let field_type = if is_token_name(name) {
+                "Token".to_string()
+            } else {
+                ctx.value_type(name)
+            };
+            fields.push((label.clone(), field_type, *is_list));
+        }
+        Element::Terminal {
+            label: Some(label),
+            is_list,
+            ..
+        }
+        | Element::StringLiteral {
+            label: Some(label),
+            is_list,
+            ..
+        } if seen.insert(label.clone()) => {
+            fields.push((label.clone(), "Token".to_string(), *is_list));
+        }
+        Element::Group { alternatives } => {
+            for alt in alternatives {
+                for elem in &alt.elements {
+                    collect_element_labels(elem, ctx, fields, seen);
+                }
+            }
+        }
+        Element::Optional { element, .. }
+        | Element::ZeroOrMore { element, .. }
+        | Element::OneOrMore { element, .. } => {
+            collect_element_labels(element, ctx, fields, seen);
+        }
+        _ => {}
+    }
+}
+
+/// Token references start with an uppercase letter, as in ANTLR.
+fn is_token_name(name: &str) -> bool {
+    name.chars().next().is_some_and(|c| c.is_uppercase())
+}
+
+/// Generate Rust code for a rule body
+pub fn generate_rust_rule_body(rule: &Rule, ctx: &mut RuleBodyContext) -> String {
+    let mut code = String::new();
+    let pad = ctx.pad();
+
+    ctx.rule_name = rule.name.clone();
+    ctx.track_children = rule.returns.is_empty();
+
+    // Labeled elements and unlabeled rule references are collected into
+    // locals and moved into the node
+    let labels = node_fields(rule, ctx);
+    ctx.ref_fields = collect_rule_refs(rule, ctx, &collect_labels(rule, ctx))
+        .into_iter()
+        .map(|(name, field, _, is_list)| (name, (field, is_list)))
+        .collect();
+    for (name, field_type, is_list) in &labels {
+        if *is_list {
+            code.push_str(&format!(
+                "{}let mut {}: Vec<{}> = Vec::new();\n",
+                pad, name, field_type
+            ));
+        } else {
+            code.push_str(&format!(
+                "{}let mut {}: Option<{}> = None;\n",
+                pad, name, field_type
+            ));
         }
+    }
+    for ret in &rule.returns {
+        let ret_type = ret.return_type.as_deref().unwrap_or("AstNode");
+        code.push_str(&format!(
+            "{}let mut {}: {} = Default::default();\n",
+            pad, ret.name, ret_type
+        ));
+    }
+    if ctx.track_children {
+        code.push_str(&format!("{}let mut children: Vec<AstNode> = Vec::new();\n", pad));
+        code.push_str(&format!("{}let leaves_start = self.leaves.len();\n", pad));
+    }
+    let label_enum = label_enum(rule).filter(|_| ctx.track_children);
+    if let Some((enum_name, _)) = &label_enum {
+        code.push_str(&format!("{}let mut alt_label: Option<{}> = None;\n", pad, enum_name));
+    }
+    code.push('\n');
 
-        // Close all nested blocks
-        for _ in 0..rule.alternatives.len() {
-            code.push_str(&format!("{}    }}\n", indent_str));
+    if rule.alternatives.is_empty() {
+        code.push_str(&format!("{}// Empty rule\n", pad));
+    } else {
+        if ctx.alt_stats {
+            ctx.stats_rule = Some(rule.name.clone());
         }
+        ctx.alt_labels = label_enum
+            .as_ref()
+            .map(|(enum_name, _)| alt_label_values(&rule.alternatives, enum_name));
+        code.push_str(&generate_choice(&rule.alternatives, &rule.name, ctx));
+    }
 
-        // All alternatives failed
-        code.push_str(&format!("{}Err(ParseError::new(\n", indent_str));
+    // Build the rule's value
+    if rule.returns.is_empty() {
+        let type_name = to_pascal_case(&rule.name);
         code.push_str(&format!(
-            "{}    format!(\"Expected one of alternatives in rule '{}'\"),\n",
-            indent_str, rule.name
+            "{}Ok(AstNode::{}(Box::new({}Node {{\n",
+            pad, type_name, type_name
         ));
-        code.push_str(&format!("{}    start_pos\n", indent_str));
-        code.push_str(&format!("{}))\n", indent_str));
+        code.push_str(&format!("{}    children,\n", pad));
+        code.push_str(&format!("{}    tokens: self.leaves.split_off(leaves_start),\n", pad));
+        if label_enum.is_some() {
+            code.push_str(&format!("{}    label: alt_label,\n", pad));
+        }
+        for (name, _, _) in &labels {
+            code.push_str(&format!("{}    {},\n", pad, name));
+        }
+        code.push_str(&format!("{}}})))\n", pad));
+    } else if let [ret] = rule.returns.as_slice() {
+        code.push_str(&format!("{}Ok({})\n", pad, ret.name));
+    } else {
+        let names: Vec<&str> = rule.returns.iter().map(|r| r.name.as_str()).collect();
+        code.push_str(&format!("{}Ok(({}))\n", pad, names.join(", ")));
     }
 
     code
 }
 
-/// Generate code for a single alternative body
-fn generate_alternative_body(
-    alt: &Alternative,
+/// Generate the body of `parse_<rule>(min_prec)` for an operator rule.
+///
+/// The primary alternatives are parsed as an ordinary choice into a node
+/// of the rule. Then, while the next token is an operator binding at least
+/// as tightly as `min_prec`, the operator is consumed, the right operand is
+/// parsed with a higher minimum (the same one for right-associative
+/// operators) and both operands become the children of a new node.
+pub fn generate_rust_precedence_body(
     rule: &Rule,
+    table: &PrecedenceTable,
     ctx: &mut RuleBodyContext,
-    lang: &str,
 ) -> String {
     let mut code = String::new();
-    let indent_str = " ".repeat(ctx.indent);
+    let pad = ctx.pad();
 
-    // Collect all labeled variables first
-    let mut collected_vars = Vec::new();
-    let mut list_vars = Vec::new();
+    ctx.rule_name = rule.name.clone();
+    ctx.track_children = true;
 
-    for element in &alt.elements {
-        match element {
-            Element::RuleRef { label: Some(lbl), is_list, .. }
-            | Element::Terminal { label: Some(lbl), is_list, .. }
-            | Element::StringLiteral { label: Some(lbl), is_list, .. } => {
-                if *is_list {
-                    list_vars.push(lbl.clone());
-                } else {
-                    collected_vars.push(lbl.clone());
-                }
-            }
-            _ => {}
+    // Nodes of the rule have the fields of the whole rule, even though
+    // only the primaries are parsed as a choice
+    let labels = node_fields(rule, ctx);
+    ctx.ref_fields = collect_rule_refs(rule, ctx, &collect_labels(rule, ctx))
+        .into_iter()
+        .map(|(name, field, _, is_list)| (name, (field, is_list)))
+        .collect();
+    for (name, field_type, is_list) in &labels {
+        if *is_list {
+            code.push_str(&format!("{}let mut {}: Vec<{}> = Vec::new();\n", pad, name, field_type));
+        } else {
+            code.push_str(&format!("{}let mut {}: Option<{}> = None;\n", pad, name, field_type));
         }
     }
+    code.push_str(&format!("{}let mut children: Vec<AstNode> = Vec::new();\n", pad));
+    code.push_str(&format!("{}let leaves_start = self.leaves.len();\n", pad));
+    let label_enum = label_enum(rule);
+    if let Some((enum_name, _)) = &label_enum {
+        code.push_str(&format!("{}let mut alt_label: Option<{}> = None;\n", pad, enum_name));
+        ctx.alt_labels = Some(alt_label_values(&table.primaries, enum_name));
+    }
+    code.push('\n');
+
+    code.push_str(&generate_choice(&table.primaries, &rule.name, ctx));
+
+    let type_name = to_pascal_case(&rule.name);
+    code.push_str(&format!("{}let mut node = AstNode::{}(Box::new({}Node {{\n", pad, type_name, type_name));
+    code.push_str(&format!("{}    children,\n", pad));
+    code.push_str(&format!("{}    tokens: self.leaves.split_off(leaves_start),\n", pad));
+    if label_enum.is_some() {
+        code.push_str(&format!("{}    label: alt_label,\n", pad));
+    }
+    for (name, _, _) in &labels {
+        code.push_str(&format!("{}    {},\n", pad, name));
+    }
+    code.push_str(&format!("{}}}));\n", pad));
+    if ctx.cst {
+        code.push_str(&format!("{}self.cst.finish({:?}, cst_start);\n", pad, rule.name));
+    }
+    code.push('\n');
+
+    code.push_str(&format!("{}// Binary operators, tightest first\n", pad));
+    code.push_str(&format!("{}loop {{\n", pad));
+    code.push_str(&format!(
+        "{}    let (operator, precedence, right_assoc) = match self.tokens.peek(0) {{\n",
+        pad
+    ));
+    for (index, op) in table.operators.iter().enumerate() {
+        let tests: Vec<String> = op
+            .tokens
+            .iter()
+            .filter_map(|token| operator_test(token, &ctx.case_insensitive_literals))
+            .collect();
+        let comment = op.label.as_ref().map(|label| format!(" // #{}", label)).unwrap_or_default();
+        code.push_str(&format!(
+            "{}        Some(token) if {} => ({}, {}, {}),{}\n",
+            pad,
+            tests.join(" || "),
+            index,
+            op.precedence,
+            op.right_assoc,
+            comment
+        ));
+    }
+    code.push_str(&format!("{}        _ => break,\n", pad));
+    code.push_str(&format!("{}    }};\n", pad));
+    code.push_str(&format!("{}    if precedence < min_prec {{\n", pad));
+    code.push_str(&format!("{}        break;\n", pad));
+    code.push_str(&format!("{}    }}\n", pad));
+    code.push_str(&format!("{}    let leaves_start = self.leaves.len();\n", pad));
+    code.push_str(&ctx.consume_token(&format!("{}    ", pad)));
+    if table.operators.iter().any(|op| op.element_labels[1].is_some()) {
+        code.push_str(&format!("{}    let operator_token = self.leaves[leaves_start].clone();\n", pad));
+    }
+    code.push_str(&format!(
+        "{}    let right = self.parse_{}(if right_assoc {{ precedence }} else {{ precedence + 1 }})?;\n",
+        pad, rule.name
+    ));
+    code.push_str(&format!("{}    let operands = vec![node, right];\n", pad));
+    code.push_str(&format!("{}    node = AstNode::{}(Box::new({}Node {{\n", pad, type_name, type_name));
+    code.push_str(&format!("{}        children: operands.clone(),\n", pad));
+    code.push_str(&format!("{}        tokens: self.leaves.split_off(leaves_start),\n", pad));
+    if let Some((enum_name, _)) = &label_enum {
+        code.push_str(&format!("{}        label: {},\n", pad, operator_label(table, enum_name)));
+    }
+    let operand_field = ctx.ref_fields.get(&rule.name).filter(|(_, is_list)| *is_list);
+    for (name, _, is_list) in &labels {
+        let value = match operand_field {
+            Some((field, _)) if field == name => "operands".to_string(),
+            _ => labeled_operands(table, name, *is_list),
+        };
+        code.push_str(&format!("{}        {}: {},\n", pad, name, value));
+    }
+    code.push_str(&format!("{}    }}));\n", pad));
+    if ctx.cst {
+        code.push_str(&format!("{}    self.cst.finish({:?}, cst_start);\n", pad, rule.name));
+    }
+    code.push_str(&format!("{}}}\n", pad));
+    code.push_str(&format!("{}Ok(node)\n", pad));
+
+    code
+}
+
+/// Value of the `label` field of the node built for an operator: the
+/// variant for the `#Label` of the operator's alternative.
+fn operator_label(table: &PrecedenceTable, enum_name: &str) -> String {
+    let arms: Vec<String> = table
+        .operators
+        .iter()
+        .enumerate()
+        .filter_map(|(index, op)| {
+            let label = op.label.as_ref()?;
+            Some(format!("{} => Some({}::{})", index, enum_name, label_variant(label)))
+        })
+        .collect();
+    if arms.is_empty() {
+        return "None".to_string();
+    }
+    format!("match operator {{ {}, _ => None }}", arms.join(", "))
+}
+
+/// Value of the node field `name` built for an operator, from the labels
+/// on the operands and operator of each operator alternative.
+fn labeled_operands(table: &PrecedenceTable, name: &str, is_list: bool) -> String {
+    let values = ["operands[0].clone()", "operator_token.clone()", "operands[1].clone()"];
+    let empty = if is_list { "Vec::new()" } else { "None" };
+    let arms: Vec<String> = table
+        .operators
+        .iter()
+        .enumerate()
+        .filter_map(|(index, op)| {
+            let labeled: Vec<&str> = op
+                .element_labels
+                .iter()
+                .zip(values)
+                .filter(|(label, _)| label.as_deref() == Some(name))
+                .map(|(_, value)| value)
+                .collect();
+            let value = match labeled.as_slice() {
+                [] => return None,
+                _ if is_list => format!("vec![{}]", labeled.join(", ")),
+                [.., last] => format!("Some({})", last),
+            };
+            Some(format!("{} => {}", index, value))
+        })
+        .collect();
+    if arms.is_empty() {
+        return empty.to_string();
+    }
+    format!("match operator {{ {}, _ => {} }}", arms.join(", "), empty)
+}
 
-    // Initialize list variables
-    if lang == "rust" {
-        for lbl in &list_vars {
-            code.push_str(&format!("{}let mut {}: Vec<AstNode> = Vec::new();\n", indent_str, lbl));
+/// Condition on `token` that it is the operator token `element`.
+fn operator_test(element: &Element, case_insensitive: &HashSet<String>) -> Option<String> {
+    match element {
+        Element::StringLiteral { value, .. } if case_insensitive.contains(value) => Some(format!(
+            "(token.kind != TokenKind::Eof && token.text.to_lowercase() == {:?}.to_lowercase())",
+            value
+        )),
+        Element::StringLiteral { value, .. } => Some(format!(
+            "(token.kind != TokenKind::Eof && token.text == {:?})",
+            value
+        )),
+        Element::RuleRef { name, .. } | Element::Terminal { value: name, .. } => {
+            Some(format!("token.kind == TokenKind::{}", name))
         }
-        if !list_vars.is_empty() {
-            code.push('\n');
+        _ => None,
+    }
+}
+
+/// Per alternative, the `enum_name` variant for its `#Label`, if it has one.
+fn alt_label_values(alts: &[Alternative], enum_name: &str) -> Vec<Option<String>> {
+    alts.iter()
+        .map(|alt| {
+            alt.label
+                .as_ref()
+                .map(|label| format!("{}::{}", enum_name, label_variant(label)))
+        })
+        .collect()
+}
+
+/// Generate code for an ordered choice between alternatives.
+///
+/// A single alternative is emitted inline. Otherwise each alternative is
+/// tried in turn, restoring the position between attempts, and the error
+/// that got furthest into the input is reported if all of them fail.
+fn generate_choice(alts: &[Alternative], what: &str, ctx: &mut RuleBodyContext) -> String {
+    let stats_rule = ctx.stats_rule.take();
+    let alt_labels = ctx.alt_labels.take();
+    let alt_label = |i: usize| alt_labels.as_ref().and_then(|labels| labels[i].as_ref());
+    if let [alt] = alts {
+        let mut code = generate_sequence(alt, ctx);
+        if let Some(variant) = alt_label(0) {
+            code.push_str(&format!("{}alt_label = Some({});\n", ctx.pad(), variant));
         }
+        return code;
     }
 
-    // Generate code for each element in sequence
-    for (i, element) in alt.elements.iter().enumerate() {
-        let elem_code = generate_element_code(element, rule, ctx, lang, i);
-        code.push_str(&elem_code);
-    }
-
-    // Build result node with collected values
-    if lang == "rust" {
-        if !collected_vars.is_empty() || !list_vars.is_empty() {
-            // Build struct with labeled values
-            code.push_str(&format!("\n{}// Build AST node with collected values\n", indent_str));
-            code.push_str(&format!("{}let node = {}Node {{\n", indent_str, to_pascal_case(&rule.name)));
-            
-            for label in &collected_vars {
-                code.push_str(&format!("{}    {},\n", indent_str, label));
+    let mut code = String::new();
+    let pad = ctx.pad();
+    let choice_label = ctx.fresh_label("choice");
+
+    code.push_str(&format!("{}// Alternatives of {}\n", pad, what));
+    code.push_str(&format!(
+        "{}let choice: Result<(), ParseError> = {}: {{\n",
+        pad, choice_label
+    ));
+    code.push_str(&ctx.save_state(&format!("{}    ", pad)));
+    code.push_str(&format!(
+        "{}    let mut furthest: Option<ParseError> = None;\n",
+        pad
+    ));
+
+    for (i, alt) in alts.iter().enumerate() {
+        code.push_str(&generate_attempt(alt, ctx, 4));
+        code.push_str(&format!("{}    match attempt {{\n", pad));
+        if stats_rule.is_some() || alt_label(i).is_some() {
+            code.push_str(&format!("{}        Ok(()) => {{\n", pad));
+            if let Some(variant) = alt_label(i) {
+                code.push_str(&format!("{}            alt_label = Some({});\n", pad, variant));
             }
-            for label in &list_vars {
-                code.push_str(&format!("{}    {},\n", indent_str, label));
+            if let Some(rule) = &stats_rule {
+                code.push_str(&format!(
+                    "{}            self.alt_stats.hit(\"{}\", {});\n",
+                    pad, rule, i + 1
+                ));
             }
-            
-            code.push_str(&format!("{}}};
-", indent_str));
-            code.push_str(&format!("{}Ok(AstNode::{}(Box::new(node)))\n", indent_str, to_pascal_case(&rule.name)));
+            code.push_str(&format!("{}            break {} Ok(());\n", pad, choice_label));
+            code.push_str(&format!("{}        }}\n", pad));
         } else {
             code.push_str(&format!(
-                "{}Ok(AstNode::{}(Box::new(())))\n",
-                indent_str,
-                to_pascal_case(&rule.name)
+                "{}        Ok(()) => break {} Ok(()),\n",
+                pad, choice_label
+            ));
+        }
+        code.push_str(&format!("{}        Err(err) => {{\n", pad));
+        if let Some(rule) = &stats_rule {
+            code.push_str(&format!(
+                "{}            self.alt_stats.miss(\"{}\", {});\n",
+                pad, rule, i + 1
             ));
         }
+        code.push_str(&ctx.restore_state(&format!("{}            ", pad)));
+        code.push_str(&format!(
+            "{}            Self::keep_furthest(&mut furthest, err);\n",
+            pad
+        ));
+        code.push_str(&format!("{}        }}\n", pad));
+        code.push_str(&format!("{}    }}\n", pad));
     }
 
+    code.push_str(&format!(
+        "{}    Err(furthest.unwrap_or_else(|| self.unexpected(\"{}\")))\n",
+        pad, what
+    ));
+    code.push_str(&format!("{}}};\n", pad));
+    code.push_str(&format!("{}if let Err(err) = choice {{\n", pad));
+    code.push_str(&format!("{}    {}\n", pad, ctx.fail("err")));
+    code.push_str(&format!("{}}}\n", pad));
+
+    code
+}
+
+/// Emit `let attempt: Result<(), ParseError> = 'label: { ... };` running
+/// the alternative's elements in sequence, indented by `extra`.
+fn generate_attempt(alt: &Alternative, ctx: &mut RuleBodyContext, extra: usize) -> String {
+    let mut code = String::new();
+    let label = ctx.fresh_label("seq");
+
+    ctx.indent += extra;
+    let pad = ctx.pad();
+    code.push_str(&format!(
+        "{}let attempt: Result<(), ParseError> = {}: {{\n",
+        pad, label
+    ));
+
+    ctx.block_labels.push(label);
+    ctx.indent += 4;
+    code.push_str(&generate_sequence(alt, ctx));
+    code.push_str(&format!("{}Ok(())\n", ctx.pad()));
+    ctx.indent -= 4;
+    ctx.block_labels.pop();
+
+    code.push_str(&format!("{}}};\n", pad));
+    ctx.indent -= extra;
+
     code
 }
 
+/// Generate code for a single alternative body
+fn generate_sequence(alt: &Alternative, ctx: &mut RuleBodyContext) -> String {
+    let top_level = ctx.block_labels.is_empty();
+    let mut code = String::new();
+    for (i, element) in alt.elements.iter().enumerate() {
+        if let (true, Some(sync), Element::ZeroOrMore { .. } | Element::OneOrMore { .. }) =
+            (top_level, &ctx.sync, element)
+        {
+            // A loop nothing could backtrack stops at what follows it and
+            // skips anything else up to the start of another iteration
+            let exit = sync.after(&alt.elements[i + 1..], &ctx.rule_name);
+            let mut resync = sync.sets.first_of_sequence(std::slice::from_ref(element));
+            resync.extend(exit.iter().cloned());
+            ctx.loop_sync = sync.kinds(&exit).zip(sync.kinds(&resync));
+        }
+        code.push_str(&generate_element_code(element, ctx));
+    }
+    code
+}
+
+/// Wrap a single element so it can be attempted as a sequence.
+fn single(element: &Element) -> Alternative {
+    Alternative {
+        elements: vec![element.clone()],
+        ..Alternative::new()
+    }
+}
+
+/// Condition on a `ch: char` testing membership in a character class.
+fn char_condition(element: &Element) -> String {
+    match element {
+        Element::UnicodeClass { negated, category } => match unicode_property(category) {
+            Some((method, _)) => format!("{}ch.{}()", if *negated { "!" } else { "" }, method),
+            // The parser rejects unknown properties; match nothing
+            None => "false".to_string(),
+        },
+        Element::CharClass { negated, ranges } => {
+            let condition = ranges
+                .iter()
+                .map(|(start, end)| {
+                    if start == end {
+                        format!("ch == {:?}", start)
+                    } else {
+                        format!("({:?}..={:?}).contains(&ch)", start, end)
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join(" || ");
+            if *negated {
+                format!("!({})", condition)
+            } else {
+                format!("({})", condition)
+            }
+        }
+        _ => "false".to_string(),
+    }
+}
+
+/// Code that stores `value` into `label`, or discards it when unlabeled.
+fn store_value(label: &Option<String>, is_list: bool, value: &str, pad: &str) -> String {
+    match label {
+        Some(lbl) if is_list => format!("{}{}.push({});\n", pad, lbl, value),
+        Some(lbl) => format!("{}{} = Some({});\n", pad, lbl, value),
+        None => format!("{}{};\n", pad, value),
+    }
+}
+
 /// Generate code for a single element
-fn generate_element_code(
-    element: &Element,
-    rule: &Rule,
-    ctx: &mut RuleBodyContext,
-    lang: &str,
-    _index: usize,
-) -> String {
+fn generate_element_code(element: &Element, ctx: &mut RuleBodyContext) -> String {
     let mut code = String::new();
-    let indent_str = " ".repeat(ctx.indent);
+    let pad = ctx.pad();
 
     match element {
+        Element::RuleRef { name, .. } if name == "EOF" => {
+            code.push_str(&format!("{}{};\n", pad, ctx.try_value("self.expect_eof()")));
+        }
         Element::RuleRef {
             name,
             label,
             is_list,
+            options,
+            ..
         } => {
-            if lang == "rust" {
-                let method_name = format!("parse_{}", name);
-                if *is_list {
-                    if let Some(lbl) = label {
-                        code.push_str(&format!(
-                            "{}{}.push(self.{}()?);\n",
-                            indent_str, lbl, method_name
-                        ));
+            if is_token_name(name) {
+                let call = format!("self.expect_token(TokenKind::{})", name);
+                code.push_str(&store_value(label, *is_list, &ctx.try_value(&call), &pad));
+            } else {
+                let args = if ctx.precedence_rules.contains(name) {
+                    // `expr<prec=N>` only takes operators binding at least that tightly
+                    options
+                        .iter()
+                        .find(|(key, _)| key == "prec")
+                        .and_then(|(_, value)| value.parse::<u8>().ok())
+                        .unwrap_or(0)
+                        .to_string()
+                } else {
+                    let arg_count = ctx
+                        .signatures
+                        .get(name)
+                        .map_or(0, |sig| sig.arguments.len());
+                    vec!["Default::default()"; arg_count].join(", ")
+                };
+                let mut call = format!("self.parse_{}({})", name, args);
+                if ctx.sync.is_some() && !ctx.block_labels.is_empty() {
+                    // A failure here may be backtracked, so it is not recovered from
+                    call = format!("self.speculate(|parser| parser.parse_{}({}))", name, args);
+                }
+                // Unlabeled references are stored in the field named after the rule
+                let (label, is_list) = match (label, ctx.ref_fields.get(name)) {
+                    (None, Some((field, field_is_list))) => (Some(field.clone()), *field_is_list),
+                    _ => (label.clone(), *is_list),
+                };
+
+                if ctx.track_children && ctx.value_type(name) == "AstNode" {
+                    // Sub-rule nodes also become children of this rule's node
+                    code.push_str(&format!("{}let node = {};\n", pad, ctx.try_value(&call)));
+                    if label.is_some() {
+                        code.push_str(&format!("{}children.push(node.clone());\n", pad));
+                        code.push_str(&store_value(&label, is_list, "node", &pad));
                     } else {
-                        code.push_str(&format!("{}let _ = self.{}()?;\n", indent_str, method_name));
+                        code.push_str(&format!("{}children.push(node);\n", pad));
                     }
-                } else if let Some(lbl) = label {
-                    code.push_str(&format!(
-                        "{}let {} = self.{}()?;\n",
-                        indent_str, lbl, method_name
-                    ));
                 } else {
-                    code.push_str(&format!("{}self.{}()?;\n", indent_str, method_name));
+                    code.push_str(&store_value(&label, is_list, &ctx.try_value(&call), &pad));
                 }
             }
         }
@@ -270,338 +1169,226 @@ fn generate_element_code(
             value,
             label,
             is_list,
+            ..
         } => {
-            if lang == "rust" {
-                // Check current token at position with better error handling
-                code.push_str(&format!("{}// Match terminal: {}\n", indent_str, value));
-                code.push_str(&format!("{}if self.position >= self.tokens.len() {{\n", indent_str));
-                code.push_str(&format!("{}    return Err(ParseError::new(\n", indent_str));
-                code.push_str(&format!("{}        format!(\"Unexpected EOF, expected: {}\"),\n", indent_str, value));
-                code.push_str(&format!("{}        self.position\n", indent_str));
-                code.push_str(&format!("{}    ));\n", indent_str));
-                code.push_str(&format!("{}}}\n", indent_str));
-                
-                code.push_str(&format!("{}if self.tokens[self.position].kind == TokenKind::{} {{\n", indent_str, value));
-
-                if *is_list {
-                    if let Some(lbl) = label {
-                        code.push_str(&format!(
-                            "{}{}.push(self.tokens[self.position].clone());\n",
-                            " ".repeat(ctx.indent + 4),
-                            lbl
-                        ));
-                    }
-                } else if let Some(lbl) = label {
-                    code.push_str(&format!(
-                        "{}let {} = self.tokens[self.position].clone();\n",
-                        " ".repeat(ctx.indent + 4),
-                        lbl
-                    ));
-                }
-
-                code.push_str(&format!(
-                    "{}self.position += 1;\n",
-                    " ".repeat(ctx.indent + 4)
-                ));
-                code.push_str(&format!("{}}} else {{\n", indent_str));
-                code.push_str(&format!("{}    return Err(ParseError::new(\n", indent_str));
-                code.push_str(&format!(
-                    "{}        format!(\"Expected {}, got {{:?}}\", self.tokens[self.position].kind),\n",
-                    indent_str, value
-                ));
-                code.push_str(&format!("{}        self.position\n", indent_str));
-                code.push_str(&format!("{}    ));\n", indent_str));
-                code.push_str(&format!("{}}}\n", indent_str));
-            }
+            let call = format!("self.expect_token(TokenKind::{})", value);
+            code.push_str(&store_value(label, *is_list, &ctx.try_value(&call), &pad));
         }
         Element::StringLiteral {
             value,
             label,
             is_list,
+            ..
         } => {
-            if lang == "rust" {
-                code.push_str(&format!("{}// Match string literal: \"{}\"\n", indent_str, value));
-                code.push_str(&format!("{}if self.position >= self.tokens.len() {{\n", indent_str));
-                code.push_str(&format!("{}    return Err(ParseError::new(\n", indent_str));
-                code.push_str(&format!("{}        format!(\"Unexpected EOF, expected: \\\"{}\\\"\"),\n", indent_str, value));
-                code.push_str(&format!("{}        self.position\n", indent_str));
-                code.push_str(&format!("{}    ));\n", indent_str));
-                code.push_str(&format!("{}}}\n", indent_str));
-                
-                code.push_str(&format!("{}if self.tokens[self.position].text == \"{}\" {{\n", indent_str, value));
-
-                if *is_list {
-                    if let Some(lbl) = label {
-                        code.push_str(&format!(
-                            "{}{}.push(self.tokens[self.position].clone());\n",
-                            " ".repeat(ctx.indent + 4),
-                            lbl
-                        ));
-                    }
-                } else if let Some(lbl) = label {
-                    code.push_str(&format!(
-                        "{}let {} = self.tokens[self.position].clone();\n",
-                        " ".repeat(ctx.indent + 4),
-                        lbl
-                    ));
-                }
-
-                code.push_str(&format!(
-                    "{}self.position += 1;\n",
-                    " ".repeat(ctx.indent + 4)
-                ));
-                code.push_str(&format!("{}}} else {{\n", indent_str));
-                code.push_str(&format!("{}    return Err(ParseError::new(\n", indent_str));
-                code.push_str(&format!(
-                    "{}        format!(\"Expected \\\"{}\\\", got {{:?}}\", self.tokens[self.position].text),\n",
-                    indent_str, value
-                ));
-                code.push_str(&format!("{}        self.position\n", indent_str));
-                code.push_str(&format!("{}    ));\n", indent_str));
-                code.push_str(&format!("{}}}\n", indent_str));
-            }
+            let call = if ctx.case_insensitive_literals.contains(value) {
+                format!("self.expect_literal_ignore_case({:?})", value)
+            } else {
+                format!("self.expect_literal({:?})", value)
+            };
+            code.push_str(&store_value(label, *is_list, &ctx.try_value(&call), &pad));
         }
         Element::Optional { element, .. } => {
-            if lang == "rust" {
-                code.push_str(&format!("{}// Optional element\n", indent_str));
-                code.push_str(&format!("{}let saved_pos = self.position;\n", indent_str));
-                code.push_str(&format!("{}match (|| -> Result<AstNode> {{\n", indent_str));
-                ctx.indent += 4;
-                code.push_str(&generate_element_code(element, rule, ctx, lang, 0));
-                code.push_str(&format!("{}Ok(AstNode::Empty)\n", " ".repeat(ctx.indent)));
-                ctx.indent -= 4;
-                code.push_str(&format!("{}}}()) {{\n", indent_str));
-                code.push_str(&format!("{}    Ok(_) => {{}},\n", indent_str));
-                code.push_str(&format!("{}    Err(_) => {{\n", indent_str));
-                code.push_str(&format!("{}        self.position = saved_pos;\n", indent_str));
-                code.push_str(&format!("{}    }}\n", indent_str));
-                code.push_str(&format!("{}}}\n", indent_str));
-            }
+            code.push_str(&format!("{}// Optional element\n", pad));
+            code.push_str(&format!("{}{{\n", pad));
+            code.push_str(&ctx.save_state(&format!("{}    ", pad)));
+            code.push_str(&generate_attempt(&single(element), ctx, 4));
+            code.push_str(&format!("{}    if attempt.is_err() {{\n", pad));
+            code.push_str(&ctx.restore_state(&format!("{}        ", pad)));
+            code.push_str(&format!("{}    }}\n", pad));
+            code.push_str(&format!("{}}}\n", pad));
         }
         Element::ZeroOrMore { element, .. } => {
-            if lang == "rust" {
-                code.push_str(&format!("{}// Zero or more\n", indent_str));
-                code.push_str(&format!("{}loop {{\n", indent_str));
-                code.push_str(&format!("{}    let saved_pos = self.position;\n", indent_str));
-                code.push_str(&format!("{}    match (|| -> Result<AstNode> {{\n", indent_str));
-                ctx.indent += 8;
-                code.push_str(&generate_element_code(element, rule, ctx, lang, 0));
-                code.push_str(&format!("{}Ok(AstNode::Empty)\n", " ".repeat(ctx.indent)));
-                ctx.indent -= 8;
-                code.push_str(&format!("{}    }}()) {{\n", indent_str));
-                code.push_str(&format!("{}        Ok(_) => continue,\n", indent_str));
-                code.push_str(&format!("{}        Err(_) => {{\n", indent_str));
-                code.push_str(&format!("{}            self.position = saved_pos;\n", indent_str));
-                code.push_str(&format!("{}            break;\n", indent_str));
-                code.push_str(&format!("{}        }}\n", indent_str));
-                code.push_str(&format!("{}    }}\n", indent_str));
-                code.push_str(&format!("{}}}\n", indent_str));
-            }
+            code.push_str(&format!("{}// Zero or more\n", pad));
+            code.push_str(&generate_loop(element, false, ctx));
         }
         Element::OneOrMore { element, .. } => {
-            if lang == "rust" {
-                code.push_str(&format!("{}// One or more (at least one required)\n", indent_str));
-                // First one is required
-                code.push_str(&generate_element_code(element, rule, ctx, lang, 0));
-                // Then zero or more
-                code.push_str(&format!("{}loop {{\n", indent_str));
-                code.push_str(&format!("{}    let saved_pos = self.position;\n", indent_str));
-                code.push_str(&format!("{}    match (|| -> Result<AstNode> {{\n", indent_str));
-                ctx.indent += 8;
-                code.push_str(&generate_element_code(element, rule, ctx, lang, 0));
-                code.push_str(&format!("{}Ok(AstNode::Empty)\n", " ".repeat(ctx.indent)));
-                ctx.indent -= 8;
-                code.push_str(&format!("{}    }}()) {{\n", indent_str));
-                code.push_str(&format!("{}        Ok(_) => continue,\n", indent_str));
-                code.push_str(&format!("{}        Err(_) => {{\n", indent_str));
-                code.push_str(&format!("{}            self.position = saved_pos;\n", indent_str));
-                code.push_str(&format!("{}            break;\n", indent_str));
-                code.push_str(&format!("{}        }}\n", indent_str));
-                code.push_str(&format!("{}    }}\n", indent_str));
-                code.push_str(&format!("{}}}\n", indent_str));
-            }
+            code.push_str(&format!("{}// One or more (at least one required)\n", pad));
+            code.push_str(&generate_loop(element, true, ctx));
         }
         Element::Group { alternatives } => {
-            if alternatives.len() > 1 {
-                // Multiple alternatives in group
-                code.push_str(&generate_group_alternatives(alternatives, rule, ctx, lang));
-            } else if let Some(alt) = alternatives.first() {
-                // Single alternative - just parse elements
-                for elem in &alt.elements {
-                    code.push_str(&generate_element_code(elem, rule, ctx, lang, 0));
-                }
-            }
+            code.push_str(&generate_choice(alternatives, "group", ctx));
         }
         Element::Eof => {
-            if lang == "rust" {
-                code.push_str(&format!(
-                    "{}if self.{}.kind == TokenKind::Eof {{\n",
-                    indent_str, ctx.current_token_var
-                ));
-                code.push_str(&format!("{}    // EOF reached\n", indent_str));
-                code.push_str(&format!("{}}} else {{\n", indent_str));
-                code.push_str(&format!("{}    return Err(ParseError::new(\n", indent_str));
-                code.push_str(&format!(
-                    "{}        \"Expected EOF\".to_string(),\n",
-                    indent_str
-                ));
-                code.push_str(&format!("{}        self.position\n", indent_str));
-                code.push_str(&format!("{}    ));\n", indent_str));
-                code.push_str(&format!("{}}}\n", indent_str));
-            }
+            code.push_str(&format!("{}{};\n", pad, ctx.try_value("self.expect_eof()")));
         }
         Element::Action {
             code: action_code, ..
         } => {
-            code.push_str(&format!("{}{}\n", indent_str, action_code));
+            code.push_str(&format!("{}{}\n", pad, action_code));
         }
-        Element::CharClass { negated, ranges } => {
-            if lang == "rust" {
-                code.push_str(&format!("{}// Match character class\n", indent_str));
-                code.push_str(&format!("{}if self.position >= self.tokens.len() {{\n", indent_str));
-                code.push_str(&format!("{}    return Err(ParseError::new(\n", indent_str));
-                code.push_str(&format!("{}        \"Unexpected EOF\".to_string(),\n", indent_str));
-                code.push_str(&format!("{}        self.position\n", indent_str));
-                code.push_str(&format!("{}    ));\n", indent_str));
-                code.push_str(&format!("{}}}\n", indent_str));
-                
-                code.push_str(&format!("{}let ch = self.tokens[self.position].text.chars().next().unwrap_or('\\0');\n", indent_str));
-                
-                if *negated {
-                    code.push_str(&format!("{}let matches = ", indent_str));
-                    for (i, (start, end)) in ranges.iter().enumerate() {
-                        if i > 0 {
-                            code.push_str(" || ");
-                        }
-                        if start == end {
-                            code.push_str(&format!("ch == '{}'", start));
-                        } else {
-                            code.push_str(&format!("(ch >= '{}' && ch <= '{}')", start, end));
-                        }
-                    }
-                    code.push_str(";\n");
-                    code.push_str(&format!("{}if !matches {{\n", indent_str));
-                } else {
-                    code.push_str(&format!("{}if ", indent_str));
-                    for (i, (start, end)) in ranges.iter().enumerate() {
-                        if i > 0 {
-                            code.push_str(" || ");
-                        }
-                        if start == end {
-                            code.push_str(&format!("ch == '{}'", start));
-                        } else {
-                            code.push_str(&format!("(ch >= '{}' && ch <= '{}')", start, end));
-                        }
-                    }
-                    code.push_str(" {\n");
-                }
-                
-                code.push_str(&format!("{}    self.position += 1;\n", indent_str));
-                code.push_str(&format!("{}}} else {{\n", indent_str));
-                code.push_str(&format!("{}    return Err(ParseError::new(\n", indent_str));
-                code.push_str(&format!("{}        format!(\"Character '{{}}' does not match class\", ch),\n", indent_str));
-                code.push_str(&format!("{}        self.position\n", indent_str));
-                code.push_str(&format!("{}    ));\n", indent_str));
-                code.push_str(&format!("{}}}\n", indent_str));
-            }
+        Element::CharClass { .. } | Element::UnicodeClass { .. } => {
+            let condition = char_condition(element);
+            code.push_str(&format!("{}// Match single-character token in class\n", pad));
+            code.push_str(&format!(
+                "{}let matches = self.tokens.peek(0).is_some_and(|t| {{\n",
+                pad
+            ));
+            code.push_str(&format!("{}    let mut chars = t.text.chars();\n", pad));
+            code.push_str(&format!(
+                "{}    matches!((chars.next(), chars.next()), (Some(ch), None) if {})\n",
+                pad, condition
+            ));
+            code.push_str(&format!("{}}});\n", pad));
+            code.push_str(&format!("{}if !matches {{\n", pad));
+            code.push_str(&format!(
+                "{}    {}\n",
+                pad,
+                ctx.fail("self.unexpected(\"character class\")")
+            ));
+            code.push_str(&format!("{}}}\n", pad));
+            code.push_str(&ctx.consume_token(&pad));
+        }
+        Element::CharRange { start, end } => {
+            let class = Element::CharClass {
+                negated: false,
+                ranges: vec![(*start, *end)],
+            };
+            code.push_str(&generate_element_code(&class, ctx));
         }
         Element::Wildcard => {
-            if lang == "rust" {
-                code.push_str(&format!("{}// Match any character (wildcard)\n", indent_str));
-                code.push_str(&format!("{}if self.position >= self.tokens.len() {{\n", indent_str));
-                code.push_str(&format!("{}    return Err(ParseError::new(\n", indent_str));
-                code.push_str(&format!("{}        \"Unexpected EOF\".to_string(),\n", indent_str));
-                code.push_str(&format!("{}        self.position\n", indent_str));
-                code.push_str(&format!("{}    ));\n", indent_str));
-                code.push_str(&format!("{}}}\n", indent_str));
-                code.push_str(&format!("{}self.position += 1; // Accept any character\n", indent_str));
-            }
+            code.push_str(&format!("{}// Match any token (wildcard)\n", pad));
+            code.push_str(&format!("{}{};\n", pad, ctx.try_value("self.match_any()")));
         }
         Element::Not { element } => {
-            if lang == "rust" {
-                code.push_str(&format!("{}// Negation - must NOT match\n", indent_str));
-                code.push_str(&format!("{}let saved_pos = self.position;\n", indent_str));
-                code.push_str(&format!("{}let result = (|| -> Result<AstNode> {{\n", indent_str));
-                ctx.indent += 4;
-                code.push_str(&generate_element_code(element, rule, ctx, lang, 0));
-                code.push_str(&format!("{}Ok(AstNode::Empty)\n", " ".repeat(ctx.indent)));
-                ctx.indent -= 4;
-                code.push_str(&format!("{}}}());\n", indent_str));
-                code.push_str(&format!("{}self.position = saved_pos;\n", indent_str));
-                code.push_str(&format!("{}if result.is_ok() {{\n", indent_str));
-                code.push_str(&format!("{}    return Err(ParseError::new(\n", indent_str));
-                code.push_str(&format!("{}        \"Negation failed: element matched\".to_string(),\n", indent_str));
-                code.push_str(&format!("{}        self.position\n", indent_str));
-                code.push_str(&format!("{}    ));\n", indent_str));
-                code.push_str(&format!("{}}}\n", indent_str));
-            }
+            code.push_str(&format!(
+                "{}// Negation - any token the element does not match\n",
+                pad
+            ));
+            code.push_str(&format!("{}{{\n", pad));
+            code.push_str(&ctx.save_state(&format!("{}    ", pad)));
+            code.push_str(&generate_attempt(&single(element), ctx, 4));
+            code.push_str(&ctx.restore_state(&format!("{}    ", pad)));
+            code.push_str(&format!("{}    if attempt.is_ok() {{\n", pad));
+            code.push_str(&format!(
+                "{}        {}\n",
+                pad,
+                ctx.fail("self.unexpected(\"a token outside the negated set\")")
+            ));
+            code.push_str(&format!("{}    }}\n", pad));
+            code.push_str(&format!("{}}}\n", pad));
+            code.push_str(&format!("{}{};\n", pad, ctx.try_value("self.match_any()")));
         }
         Element::Predicate {
-            code: pred_code, ..
+            code: pred_code,
+            position,
+            ..
         } => {
-            if lang == "rust" {
-                code.push_str(&format!("{}if !({}) {{\n", indent_str, pred_code));
-                code.push_str(&format!("{}    return Err(ParseError::new(\n", indent_str));
-                code.push_str(&format!(
-                    "{}        \"Semantic predicate failed\".to_string(),\n",
-                    indent_str
-                ));
-                code.push_str(&format!("{}        self.position\n", indent_str));
-                code.push_str(&format!("{}    ));\n", indent_str));
-                code.push_str(&format!("{}}}\n", indent_str));
-            }
-        }
-        _ => {
-            // TODO: Handle other element types
-            code.push_str(&format!("{}// TODO: Handle element type\n", indent_str));
+            // <fail={expr}> gives the message as code, <fail='text'> as text
+            let message = match element.option("fail") {
+                Some(fail) if fail.starts_with('{') && fail.ends_with('}') => {
+                    format!("({}).to_string()", &fail[1..fail.len() - 1])
+                }
+                Some(fail) => format!("{:?}.to_string()", fail),
+                None => "\"Semantic predicate failed\".to_string()".to_string(),
+            };
+            let condition = match ctx.predicates.get(&(ctx.rule_name.clone(), pred_code.clone())) {
+                Some((index, vars)) => {
+                    let args: Vec<String> = vars.iter().map(|var| format!("&{}", var)).collect();
+                    format!("self.pred_{}({})", index, args.join(", "))
+                }
+                None => format!("({})", pred_code),
+            };
+            let err = format!("ParseError::new({}, self.tokens.position())", message);
+            // A leading predicate chooses between alternatives, so failing
+            // tries the next one; a trailing one validates what the
+            // alternative already matched and fails the whole rule
+            let fail = match position {
+                PredicatePosition::Leading => ctx.fail(&err),
+                PredicatePosition::Trailing => format!("return Err({});", err),
+            };
+            code.push_str(&format!("{}if !{} {{\n", pad, condition));
+            code.push_str(&format!("{}    {}\n", pad, fail));
+            code.push_str(&format!("{}}}\n", pad));
         }
     }
 
     code
 }
 
-fn generate_group_alternatives(
-    alts: &[Alternative],
-    rule: &Rule,
-    ctx: &mut RuleBodyContext,
-    lang: &str,
-) -> String {
-    // Similar to generate_alternatives but for nested group
+/// Generate a greedy repetition loop for `*`, or `+` when `at_least_one`.
+///
+/// The loop stops as soon as an iteration fails or consumes nothing, so
+/// elements that can match empty input never spin forever.
+fn generate_loop(element: &Element, at_least_one: bool, ctx: &mut RuleBodyContext) -> String {
     let mut code = String::new();
-    let indent_str = " ".repeat(ctx.indent);
+    let pad = ctx.pad();
+    let loop_sync = ctx.loop_sync.take();
 
-    if lang == "rust" && alts.len() > 1 {
-        for (i, alt) in alts.iter().enumerate() {
-            if i == 0 {
-                code.push_str(&format!("{}let saved_pos = self.position;\n", indent_str));
-            } else {
-                code.push_str(&format!("{}self.position = saved_pos;\n", indent_str));
-            }
-            code.push_str(&format!("{}match {{\n", indent_str));
-            ctx.indent += 4;
-            let alt_code = generate_alternative_body(alt, rule, ctx, lang);
-            ctx.indent -= 4;
-            code.push_str(&alt_code);
-            code.push_str(&format!(
-                "{}    Ok(result) => return Ok(result),\n",
-                indent_str
-            ));
-            code.push_str(&format!("{}    Err(_) => {{\n", indent_str));
-        }
-        // Close all blocks
-        for _ in 0..alts.len() {
-            code.push_str(&format!("{}    }}\n", indent_str));
+    code.push_str(&format!("{}{{\n", pad));
+    if at_least_one {
+        code.push_str(&format!("{}    let mut count = 0usize;\n", pad));
+        code.push_str(&format!(
+            "{}    let mut first_err: Option<ParseError> = None;\n",
+            pad
+        ));
+    }
+    code.push_str(&format!("{}    loop {{\n", pad));
+    code.push_str(&ctx.save_state(&format!("{}        ", pad)));
+    code.push_str(&generate_attempt(&single(element), ctx, 8));
+    code.push_str(&format!("{}        match attempt {{\n", pad));
+    if at_least_one {
+        code.push_str(&format!(
+            "{}            Ok(()) if count == 0 || self.tokens.position() != saved_pos => count += 1,\n",
+            pad
+        ));
+        code.push_str(&format!("{}            Ok(()) => break,\n", pad));
+        code.push_str(&format!("{}            Err(err) => {{\n", pad));
+        code.push_str(&ctx.restore_state(&format!("{}                ", pad)));
+        if let Some((exit, resync)) = &loop_sync {
+            code.push_str(&resync_loop(exit, resync, &pad));
         }
+        code.push_str(&format!("{}                if count == 0 {{\n", pad));
+        code.push_str(&format!("{}                    first_err = Some(err);\n", pad));
+        code.push_str(&format!("{}                }} else {{\n", pad));
+        code.push_str(&format!(
+            "{}                    Self::keep_furthest(&mut self.stalled, err);\n",
+            pad
+        ));
+        code.push_str(&format!("{}                }}\n", pad));
+    } else {
         code.push_str(&format!(
-            "{}Err(ParseError::new(\"No alternative matched\".to_string(), saved_pos))\n",
-            indent_str
+            "{}            Ok(()) if self.tokens.position() != saved_pos => {{}}\n",
+            pad
         ));
-    } else if let Some(alt) = alts.first() {
-        for elem in &alt.elements {
-            code.push_str(&generate_element_code(elem, rule, ctx, lang, 0));
+        code.push_str(&format!("{}            Ok(()) => break,\n", pad));
+        code.push_str(&format!("{}            Err(err) => {{\n", pad));
+        code.push_str(&ctx.restore_state(&format!("{}                ", pad)));
+        if let Some((exit, resync)) = &loop_sync {
+            code.push_str(&resync_loop(exit, resync, &pad));
         }
+        // What follows the loop reports this error if it fails before it
+        code.push_str(&format!(
+            "{}                Self::keep_furthest(&mut self.stalled, err);\n",
+            pad
+        ));
+    }
+    code.push_str(&format!("{}                break;\n", pad));
+    code.push_str(&format!("{}            }}\n", pad));
+    code.push_str(&format!("{}        }}\n", pad));
+    code.push_str(&format!("{}    }}\n", pad));
+    if at_least_one {
+        code.push_str(&format!("{}    if let Some(err) = first_err {{\n", pad));
+        code.push_str(&format!("{}        {}\n", pad, ctx.fail("err")));
+        code.push_str(&format!("{}    }}\n", pad));
     }
+    code.push_str(&format!("{}}}\n", pad));
+
+    code
+}
 
+/// Statements run after a failed loop iteration: unless the next token
+/// can follow the loop, record the error, skip to a token that can start
+/// another iteration or follow the loop, and go on looping.
+fn resync_loop(exit: &str, resync: &str, pad: &str) -> String {
+    let mut code = format!(
+        "{}                if self.speculating == 0 && !self.at_sync({}) {{\n",
+        pad, exit
+    );
+    code.push_str(&format!("{}                    self.errors.push(err);\n", pad));
+    code.push_str(&format!("{}                    self.tokens.next();\n", pad));
+    code.push_str(&format!("{}                    self.recover_to({});\n", pad, resync));
+    code.push_str(&format!("{}                    continue;\n", pad));
+    code.push_str(&format!("{}                }}\n", pad));
     code
 }
 
@@ -1,11 +1,29 @@
 //! Rust code generator.
 
-use super::dfa::{generate_dfa_match, DfaBuilder};
+use super::common::{implicit_literal_tokens, indentation, rule_commands, Emitter};
+use super::actions::{generate_rust_action, translate_action_element};
+use super::dfa::{
+    case_insensitive_literals, generate_dfa_match, generate_named_dfa_match,
+    longest_inline_chain, rules_by_mode, skipped_whitespace, token_rules, DfaBuilder,
+};
+use super::ll1::{Ll1Table, NonterminalKind, Symbol, END};
 use super::lookup_table::{generate_optimized_char_match, LookupTableBuilder};
-use super::rule_body::{generate_rust_rule_body, RuleBodyContext};
-use super::visitor_gen::{generate_listener, generate_visitor};
-use crate::ast::{Grammar, Rule};
-use crate::{types::CodeGenConfig, CodeGenerator as CodeGeneratorTrait, Result};
+use super::modes::{
+    generate_rust_lexer_commands, generate_rust_mode_init, generate_rust_mode_methods,
+    generate_rust_mode_stack,
+};
+use super::rule_body::{
+    generate_rust_precedence_body, generate_rust_rule_body, rust_labeled_fields,
+    rust_predicate_methods, rust_rule_value_type, RuleBodyContext, SyncSets,
+};
+use super::visitor_gen::{
+    generate_listener, generate_visitor, label_enum, label_variant, visit_method_clash,
+};
+use crate::analysis::left_recursion::{precedence_table, PrecedenceTable};
+use crate::ast::{Element, Grammar, LexerCommand, Rule};
+use crate::types::{CodeGenConfig, CstMode, ErrorMode, ParserStrategy, Visibility};
+use crate::{CodeGenerator as CodeGeneratorTrait, Error, Result};
+use std::collections::{HashMap, HashSet};
 
 /// Rust code generator.
 pub struct RustCodeGenerator;
@@ -15,14 +33,38 @@ impl RustCodeGenerator {
         Self
     }
 
-    fn generate_parser_struct(&self, grammar: &Grammar) -> String {
+    fn generate_parser_struct(&self, grammar: &Grammar, config: &CodeGenConfig) -> String {
         let mut code = String::new();
 
         code.push_str(&format!("/// Parser for {} grammar.\n", grammar.name));
         code.push_str("#[derive(Debug)]\n");
-        code.push_str(&format!("pub struct {}Parser {{\n", grammar.name));
-        code.push_str("    tokens: Vec<Token>,\n");
-        code.push_str("    position: usize,\n");
+        code.push_str(&format!(
+            "{}struct {}Parser<S: TokenSource = VecTokenSource> {{\n",
+            item_visibility(config),
+            grammar.name
+        ));
+        code.push_str("    tokens: S,\n");
+        if let Some(base) = grammar.options.get("superClass") {
+            code.push_str("    /// State and helpers of the `superClass`, reached through `Deref`\n");
+            code.push_str(&format!("    pub base: {},\n", base));
+        }
+        code.push_str("    /// Tokens matched by rules that have not built their node yet\n");
+        code.push_str("    leaves: Vec<Token>,\n");
+        code.push_str("    /// Furthest error a loop stopped at, reported instead of errors before it\n");
+        code.push_str("    stalled: Option<ParseError>,\n");
+        if config.alt_stats {
+            code.push_str("    alt_stats: AltStats,\n");
+        }
+        if config.cst_mode == CstMode::On {
+            code.push_str("    /// Concrete syntax tree built so far\n");
+            code.push_str("    cst: CstBuilder,\n");
+        }
+        if config.error_mode == ErrorMode::Collect {
+            code.push_str("    /// Errors recovered from so far\n");
+            code.push_str("    errors: Vec<ParseError>,\n");
+            code.push_str("    /// Depth of rule calls whose failure may still be backtracked\n");
+            code.push_str("    speculating: usize,\n");
+        }
 
         // Insert @members named action if present
         if let Some(members_code) = grammar.named_actions.get("members") {
@@ -37,29 +79,682 @@ impl RustCodeGenerator {
         code
     }
 
-    fn generate_parser_impl(&self, grammar: &Grammar) -> String {
+    /// Rust has no inheritance, so a `superClass` becomes the parser's
+    /// `base` field, which the parser derefs to. Actions and predicates
+    /// then call its methods as `self.method()`. The type must implement
+    /// `Default` and `Debug`.
+    fn generate_super_class_deref(&self, grammar: &Grammar, base: &str) -> String {
+        let mut code = String::new();
+        code.push_str(&format!(
+            "impl<S: TokenSource> std::ops::Deref for {}Parser<S> {{\n",
+            grammar.name
+        ));
+        code.push_str(&format!("    type Target = {};\n\n", base));
+        code.push_str(&format!("    fn deref(&self) -> &{} {{\n", base));
+        code.push_str("        &self.base\n");
+        code.push_str("    }\n");
+        code.push_str("}\n\n");
+        code.push_str(&format!(
+            "impl<S: TokenSource> std::ops::DerefMut for {}Parser<S> {{\n",
+            grammar.name
+        ));
+        code.push_str(&format!("    fn deref_mut(&mut self) -> &mut {} {{\n", base));
+        code.push_str("        &mut self.base\n");
+        code.push_str("    }\n");
+        code.push_str("}\n\n");
+        code
+    }
+
+    fn generate_parser_impl(
+        &self,
+        grammar: &Grammar,
+        config: &CodeGenConfig,
+        table: Option<&Ll1Table>,
+    ) -> String {
         let mut code = String::new();
 
         code.push_str(&format!("impl {}Parser {{\n", grammar.name));
         code.push_str("    #[inline]\n");
         code.push_str("    pub fn new(tokens: Vec<Token>) -> Self {\n");
-        code.push_str("        Self { tokens, position: 0 }\n");
+        code.push_str("        Self::from_source(VecTokenSource::new(tokens))\n");
+        code.push_str("    }\n\n");
+        code.push_str(&self.generate_parse_entry(grammar, config));
+        code.push_str(&self.generate_parse_with_tokens(grammar, config));
+        if config.cst_mode == CstMode::On {
+            code.push_str(&self.generate_parse_cst(grammar, config));
+        }
+        code.push_str("}\n\n");
+
+        if let Some(base) = grammar.options.get("superClass") {
+            code.push_str(&self.generate_super_class_deref(grammar, base));
+        }
+
+        code.push_str("#[allow(unused_mut, unused_variables, unused_labels, non_snake_case)]\n");
+        code.push_str(&format!("impl<S: TokenSource> {}Parser<S> {{\n", grammar.name));
+        code.push_str("    /// Parse tokens pulled from `tokens`.\n");
+        code.push_str("    #[inline]\n");
+        code.push_str("    pub fn from_source(tokens: S) -> Self {\n");
+        let mut fields = vec!["tokens", "leaves: Vec::new()", "stalled: None"];
+        if grammar.options.contains_key("superClass") {
+            fields.push("base: Default::default()");
+        }
+        if config.alt_stats {
+            fields.push("alt_stats: AltStats::default()");
+        }
+        if config.cst_mode == CstMode::On {
+            fields.push("cst: CstBuilder::default()");
+        }
+        if config.error_mode == ErrorMode::Collect {
+            fields.push("errors: Vec::new()");
+            fields.push("speculating: 0");
+        }
+        code.push_str(&format!("        Self {{ {} }}\n", fields.join(", ")));
         code.push_str("    }\n\n");
+        if config.alt_stats {
+            code.push_str("    /// Alternatives tried by this parser so far.\n");
+            code.push_str("    pub fn alt_stats(&self) -> &AltStats {\n");
+            code.push_str("        &self.alt_stats\n");
+            code.push_str("    }\n\n");
+        }
+        if config.cst_mode == CstMode::On {
+            code.push_str("    /// Take the concrete syntax tree of the rule parsed last, leaving the\n");
+            code.push_str("    /// builder empty for the next parse.\n");
+            code.push_str("    pub fn take_cst(&mut self) -> Option<RuleNode> {\n");
+            code.push_str("        self.cst.take()\n");
+            code.push_str("    }\n\n");
+        }
+        if config.error_mode == ErrorMode::Collect {
+            code.push_str("    /// Take the errors rules have recovered from so far.\n");
+            code.push_str("    pub fn take_errors(&mut self) -> Vec<ParseError> {\n");
+            code.push_str("        std::mem::take(&mut self.errors)\n");
+            code.push_str("    }\n\n");
+        }
+
+        code.push_str(&self.generate_parse_recovering(grammar, config));
+        code.push_str(&self.generate_parser_helpers(grammar, config));
 
         // Generate methods for each parser rule
-        for rule in grammar.parser_rules() {
-            code.push_str(&self.generate_rule_method(rule));
+        if let Some(table) = table {
+            code.push_str(&self.generate_ll1_driver(grammar, table));
+            for (index, rule) in grammar.parser_rules().enumerate() {
+                code.push_str(&self.rule_doc(rule));
+                code.push_str(&format!(
+                    "    pub fn parse_{}(&mut self) -> Result<AstNode, ParseError> {{\n",
+                    rule.name
+                ));
+                code.push_str(&format!("        self.ll1_parse({})\n", index));
+                code.push_str("    }\n\n");
+            }
+        } else {
+            let sync = (config.error_mode == ErrorMode::Collect).then(|| SyncSets::new(grammar));
+            for rule in grammar.parser_rules() {
+                code.push_str(&self.generate_rule_method(grammar, rule, config, sync.as_ref()));
+            }
+            code.push_str(&self.generate_predicate_methods(grammar));
+        }
+
+        code.push_str("}\n\n");
+        code
+    }
+
+    /// One `pred_N` method per semantic predicate, called by the rules with
+    /// the variables the predicate refers to.
+    ///
+    /// They take `&mut self` so predicates can look ahead with `lt`, which
+    /// may pull more tokens from a streaming source.
+    fn generate_predicate_methods(&self, grammar: &Grammar) -> String {
+        let mut code = String::new();
+        for (index, method) in rust_predicate_methods(grammar).iter().enumerate() {
+            let source = method.source.trim();
+            if source.contains('\n') || source.contains('`') {
+                code.push_str(&format!("    /// Semantic predicate of rule `{}`.\n", method.rule));
+            } else {
+                code.push_str(&format!(
+                    "    /// Semantic predicate `{{{}}}?` of rule `{}`.\n",
+                    source, method.rule
+                ));
+            }
+            code.push_str(&format!("    fn pred_{}(&mut self", index));
+            for (name, param_type) in &method.params {
+                code.push_str(&format!(", {}: &{}", name, param_type));
+            }
+            code.push_str(") -> bool {\n");
+            code.push_str(&format!("        {}\n", method.body.trim()));
+            code.push_str("    }\n\n");
+        }
+        code
+    }
+
+    /// Top-level `parse` that lexes and parses `input` with the start rule,
+    /// shaped by the configured error mode.
+    fn generate_parse_entry(&self, grammar: &Grammar, config: &CodeGenConfig) -> String {
+        let mut code = String::new();
+        let Some(start) = grammar.parser_rules().next() else {
+            return code;
+        };
+        if !start.arguments.is_empty() {
+            return code;
+        }
+        let tree = rust_rule_value_type(start);
+
+        code.push_str(&format!(
+            "    /// Lex and parse `input` with the start rule `{}`.\n",
+            start.name
+        ));
+        code.push_str("    /// \n");
+        match config.error_mode {
+            ErrorMode::FailFast => {
+                code.push_str("    /// Stops at the first lexer or parser error.\n");
+                code.push_str(&format!(
+                    "    pub fn parse(input: &str) -> Result<{}, ParseError> {{\n",
+                    tree
+                ));
+                code.push_str(&format!(
+                    "        let (tokens, errors) = {}Lexer::new(input).tokenize_all();\n",
+                    grammar.name
+                ));
+                code.push_str("        if let Some(err) = errors.into_iter().next() {\n");
+                code.push_str("            return Err(err);\n");
+                code.push_str("        }\n");
+                code.push_str(&format!("        Self::new(tokens).{}\n", start_rule_call(start, config)));
+            }
+            ErrorMode::Collect => {
+                code.push_str("    /// Recovers from errors to report as many as it can: lexer errors\n");
+                code.push_str("    /// first, then the parser errors from `parse_recovering`.\n");
+                code.push_str(&format!(
+                    "    pub fn parse(input: &str) -> (Option<{}>, Vec<ParseError>) {{\n",
+                    tree
+                ));
+                code.push_str(&format!(
+                    "        let (tokens, mut errors) = {}Lexer::new(input).tokenize_all();\n",
+                    grammar.name
+                ));
+                code.push_str("        let (tree, parse_errors) = Self::new(tokens).parse_recovering();\n");
+                code.push_str("        errors.extend(parse_errors);\n");
+                code.push_str("        (tree, errors)\n");
+            }
+        }
+        code.push_str("    }\n\n");
+
+        code
+    }
+
+    /// Convenience entry point that lexes and parses with the start rule.
+    ///
+    /// Hidden-channel tokens are kept in the returned stream but never
+    /// reach the parser.
+    fn generate_parse_with_tokens(&self, grammar: &Grammar, config: &CodeGenConfig) -> String {
+        let mut code = String::new();
+        let Some(start) = grammar.parser_rules().next() else {
+            return code;
+        };
+        if !start.arguments.is_empty() {
+            return code;
+        }
+
+        code.push_str(&format!(
+            "    /// Lex and parse `input` with the start rule `{}`.\n",
+            start.name
+        ));
+        code.push_str("    /// \n");
+        code.push_str("    /// Returns the tree together with the full token stream, including\n");
+        code.push_str("    /// hidden-channel tokens that the parser itself never sees.\n");
+        code.push_str(&format!(
+            "    pub fn parse_with_tokens(input: &str) -> Result<({}, Vec<Token>), ParseError> {{\n",
+            rust_rule_value_type(start)
+        ));
+        code.push_str(&format!("        let mut lexer = {}Lexer::new(input);\n", grammar.name));
+        code.push_str("        let (tokens, errors) = lexer.tokenize_all();\n");
+        code.push_str("        if let Some(err) = errors.into_iter().next() {\n");
+        code.push_str("            return Err(err);\n");
+        code.push_str("        }\n");
+        if config.error_mode == ErrorMode::Collect {
+            code.push_str("        let mut parser = Self::new(tokens.clone());\n");
+            code.push_str(&format!("        let tree = parser.{}?;\n", start_rule_call(start, config)));
+            code.push_str("        if let Some(err) = parser.take_errors().into_iter().next() {\n");
+            code.push_str("            return Err(err);\n");
+            code.push_str("        }\n");
+        } else {
+            code.push_str(&format!(
+                "        let tree = Self::new(tokens.clone()).{}?;\n",
+                start_rule_call(start, config)
+            ));
+        }
+        code.push_str("        let mut all = tokens;\n");
+        code.push_str("        all.extend_from_slice(lexer.hidden_tokens());\n");
+        code.push_str("        all.sort_by_key(|t| t.position);\n");
+        code.push_str("        Ok((tree, all))\n");
+        code.push_str("    }\n\n");
+
+        code
+    }
+
+    /// `parse_cst`, lexing and parsing `input` with the start rule and
+    /// returning its concrete syntax tree instead of the AST.
+    fn generate_parse_cst(&self, grammar: &Grammar, config: &CodeGenConfig) -> String {
+        let mut code = String::new();
+        let Some(start) = grammar.parser_rules().next() else {
+            return code;
+        };
+        if !start.arguments.is_empty() {
+            return code;
         }
 
+        code.push_str(&format!(
+            "    /// Lex and parse `input` with the start rule `{}`, returning the\n",
+            start.name
+        ));
+        code.push_str("    /// concrete syntax tree rather than the AST.\n");
+        code.push_str("    pub fn parse_cst(input: &str) -> Result<RuleNode, ParseError> {\n");
+        code.push_str(&format!(
+            "        let (tokens, errors) = {}Lexer::new(input).tokenize_all();\n",
+            grammar.name
+        ));
+        code.push_str("        if let Some(err) = errors.into_iter().next() {\n");
+        code.push_str("            return Err(err);\n");
+        code.push_str("        }\n");
+        code.push_str("        let mut parser = Self::new(tokens);\n");
+        code.push_str(&format!("        parser.{}?;\n", start_rule_call(start, config)));
+        if config.error_mode == ErrorMode::Collect {
+            code.push_str("        if let Some(err) = parser.take_errors().into_iter().next() {\n");
+            code.push_str("            return Err(err);\n");
+            code.push_str("        }\n");
+        }
+        code.push_str("        Ok(parser.take_cst().expect(\"a parsed rule has a CST node\"))\n");
+        code.push_str("    }\n\n");
+
+        code
+    }
+
+    /// Error-collecting entry point for the start rule.
+    ///
+    /// Recovery is coarse: after a failure the parser skips the token it
+    /// started from and tries again, so later attempts often fail at the
+    /// same place. The collected errors are deduplicated before returning.
+    /// With `ErrorMode::Collect` the rules recover from errors themselves,
+    /// and the errors they record are included.
+    fn generate_parse_recovering(&self, grammar: &Grammar, config: &CodeGenConfig) -> String {
+        let mut code = String::new();
+        let Some(start) = grammar.parser_rules().next() else {
+            return code;
+        };
+        if !start.arguments.is_empty() {
+            return code;
+        }
+
+        code.push_str(&format!(
+            "    /// Parse with the start rule `{}`, skipping tokens to recover from errors.\n",
+            start.name
+        ));
+        code.push_str("    /// \n");
+        code.push_str("    /// Returns the result of the first attempt that succeeds, if any, and every\n");
+        code.push_str("    /// error along the way, sorted by position with duplicates removed.\n");
+        code.push_str(&format!(
+            "    pub fn parse_recovering(&mut self) -> (Option<{}>, Vec<ParseError>) {{\n",
+            rust_rule_value_type(start)
+        ));
+        code.push_str("        let mut errors = Vec::new();\n");
+        code.push_str("        loop {\n");
+        code.push_str("            let start = self.tokens.position();\n");
+        code.push_str("            self.leaves.clear();\n");
+        code.push_str("            self.stalled = None;\n");
+        if config.cst_mode == CstMode::On {
+            code.push_str("            self.cst.truncate(0);\n");
+        }
+        if config.error_mode == ErrorMode::Collect {
+            code.push_str(&format!("            let result = self.{};\n", start_rule_call(start, config)));
+            code.push_str("            errors.append(&mut self.errors);\n");
+            code.push_str("            match result {\n");
+        } else {
+            code.push_str(&format!("            match self.{} {{\n", start_rule_call(start, config)));
+        }
+        code.push_str("                Ok(tree) => return (Some(tree), dedup_errors(errors)),\n");
+        code.push_str("                Err(err) => errors.push(err),\n");
+        code.push_str("            }\n");
+        code.push_str("            self.tokens.seek(start + 1);\n");
+        code.push_str("            if self.tokens.peek(0).is_none() {\n");
+        code.push_str("                return (None, dedup_errors(errors));\n");
+        code.push_str("            }\n");
+        code.push_str("        }\n");
+        code.push_str("    }\n\n");
+
+        code
+    }
+
+    /// Generic concrete syntax tree types behind `CodeGenConfig::cst_mode`,
+    /// and the `CstBuilder` the parser records them in.
+    ///
+    /// The builder holds the finished nodes of the rules in progress as a
+    /// flat list: tokens are appended as they are matched, and a rule that
+    /// succeeds replaces everything appended since it started with its own
+    /// node. Backtracking truncates the list like the parser's `leaves`.
+    fn generate_cst_types(&self, config: &CodeGenConfig) -> String {
+        let mut code = String::new();
+        let vis = item_visibility(config);
+
+        code.push_str("/// Concrete syntax tree node: a rule invocation or a matched token.\n");
+        code.push_str("#[derive(Debug, Clone, PartialEq)]\n");
+        code.push_str(&format!("{}enum TreeNode {{\n", vis));
+        code.push_str("    Rule(RuleNode),\n");
+        code.push_str("    Terminal(TerminalNode),\n");
+        code.push_str("}\n\n");
+
+        code.push_str("/// A rule invocation and everything it matched, in input order.\n");
+        code.push_str("#[derive(Debug, Clone, PartialEq)]\n");
+        code.push_str(&format!("{}struct RuleNode {{\n", vis));
+        code.push_str("    pub rule: &'static str,\n");
+        code.push_str("    pub children: Vec<TreeNode>,\n");
+        code.push_str("}\n\n");
+
+        code.push_str("/// A token matched by the parser.\n");
+        code.push_str("#[derive(Debug, Clone, PartialEq)]\n");
+        code.push_str(&format!("{}struct TerminalNode {{\n", vis));
+        code.push_str("    pub token: Token,\n");
+        code.push_str("}\n\n");
+
+        code.push_str("impl RuleNode {\n");
+        code.push_str("    /// Render the tree as an S-expression, such as `(expr (term 1) + (term 2))`.\n");
+        code.push_str("    pub fn to_sexpr(&self) -> String {\n");
+        code.push_str("        let mut out = format!(\"({}\", self.rule);\n");
+        code.push_str("        for child in &self.children {\n");
+        code.push_str("            out.push(' ');\n");
+        code.push_str("            match child {\n");
+        code.push_str("                TreeNode::Rule(node) => out.push_str(&node.to_sexpr()),\n");
+        code.push_str("                TreeNode::Terminal(node) => out.push_str(&node.token.text),\n");
+        code.push_str("            }\n");
+        code.push_str("        }\n");
+        code.push_str("        out.push(')');\n");
+        code.push_str("        out\n");
+        code.push_str("    }\n\n");
+        code.push_str("    /// Every token under this node, in input order.\n");
+        code.push_str("    pub fn tokens(&self) -> Vec<&Token> {\n");
+        code.push_str("        let mut tokens = Vec::new();\n");
+        code.push_str("        for child in &self.children {\n");
+        code.push_str("            match child {\n");
+        code.push_str("                TreeNode::Rule(node) => tokens.extend(node.tokens()),\n");
+        code.push_str("                TreeNode::Terminal(node) => tokens.push(&node.token),\n");
+        code.push_str("            }\n");
+        code.push_str("        }\n");
+        code.push_str("        tokens\n");
+        code.push_str("    }\n");
+        code.push_str("}\n\n");
+
+        code.push_str("/// Accumulates the concrete syntax tree while the parser runs.\n");
+        code.push_str("#[derive(Debug, Clone, Default)]\n");
+        code.push_str(&format!("{}struct CstBuilder {{\n", vis));
+        code.push_str("    /// Finished nodes not yet claimed by an enclosing rule\n");
+        code.push_str("    nodes: Vec<TreeNode>,\n");
+        code.push_str("}\n\n");
+
+        code.push_str("impl CstBuilder {\n");
+        code.push_str("    fn len(&self) -> usize {\n");
+        code.push_str("        self.nodes.len()\n");
+        code.push_str("    }\n\n");
+        code.push_str("    fn truncate(&mut self, len: usize) {\n");
+        code.push_str("        self.nodes.truncate(len);\n");
+        code.push_str("    }\n\n");
+        code.push_str("    fn token(&mut self, token: &Token) {\n");
+        code.push_str("        self.nodes.push(TreeNode::Terminal(TerminalNode { token: token.clone() }));\n");
+        code.push_str("    }\n\n");
+        code.push_str("    /// Replace the nodes added since `start` with a node for `rule` holding them.\n");
+        code.push_str("    fn finish(&mut self, rule: &'static str, start: usize) {\n");
+        code.push_str("        let children = self.nodes.split_off(start);\n");
+        code.push_str("        self.nodes.push(TreeNode::Rule(RuleNode { rule, children }));\n");
+        code.push_str("    }\n\n");
+        code.push_str("    fn take(&mut self) -> Option<RuleNode> {\n");
+        code.push_str("        let last = self.nodes.pop();\n");
+        code.push_str("        self.nodes.clear();\n");
+        code.push_str("        match last {\n");
+        code.push_str("            Some(TreeNode::Rule(node)) => Some(node),\n");
+        code.push_str("            _ => None,\n");
+        code.push_str("        }\n");
+        code.push_str("    }\n");
+        code.push_str("}\n\n");
+
+        code
+    }
+
+    /// Token matching helpers shared by the generated rule methods.
+    /// `AltStats`, the per-alternative counters behind `CodeGenConfig::alt_stats`.
+    fn generate_alt_stats(&self, config: &CodeGenConfig) -> String {
+        let mut code = String::new();
+        let vis = item_visibility(config);
+
+        code.push_str("/// How often one alternative was tried.\n");
+        code.push_str("#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]\n");
+        code.push_str(&format!("{}struct AltCounts {{\n", vis));
+        code.push_str("    /// Attempts that matched\n");
+        code.push_str("    pub hits: u64,\n");
+        code.push_str("    /// Attempts that failed and backtracked\n");
+        code.push_str("    pub misses: u64,\n");
+        code.push_str("}\n\n");
+
+        code.push_str("/// Counts per rule alternative, numbered from 1 as in the grammar.\n");
+        code.push_str("/// \n");
+        code.push_str("/// Rules with a single alternative never backtrack and are not counted.\n");
+        code.push_str("#[derive(Debug, Clone, Default)]\n");
+        code.push_str(&format!("{}struct AltStats {{\n", vis));
+        code.push_str("    counts: std::collections::HashMap<&'static str, Vec<AltCounts>>,\n");
+        code.push_str("}\n\n");
+
+        code.push_str("impl AltStats {\n");
+        code.push_str("    /// Counts for alternative `alt` of `rule`.\n");
+        code.push_str("    pub fn get(&self, rule: &str, alt: usize) -> AltCounts {\n");
+        code.push_str("        self.counts\n");
+        code.push_str("            .get(rule)\n");
+        code.push_str("            .and_then(|alts| alts.get(alt.wrapping_sub(1)))\n");
+        code.push_str("            .copied()\n");
+        code.push_str("            .unwrap_or_default()\n");
+        code.push_str("    }\n\n");
+        code.push_str("    /// Every counted alternative as `(rule, alt, counts)`, sorted by rule.\n");
+        code.push_str("    pub fn entries(&self) -> Vec<(&'static str, usize, AltCounts)> {\n");
+        code.push_str("        let mut entries: Vec<_> = self\n");
+        code.push_str("            .counts\n");
+        code.push_str("            .iter()\n");
+        code.push_str("            .flat_map(|(rule, alts)| {\n");
+        code.push_str("                alts.iter().enumerate().map(move |(i, counts)| (*rule, i + 1, *counts))\n");
+        code.push_str("            })\n");
+        code.push_str("            .collect();\n");
+        code.push_str("        entries.sort_by_key(|(rule, alt, _)| (*rule, *alt));\n");
+        code.push_str("        entries\n");
+        code.push_str("    }\n\n");
+        code.push_str("    /// Add the counts of `other`, e.g. to total several parses.\n");
+        code.push_str("    pub fn merge(&mut self, other: &AltStats) {\n");
+        code.push_str("        for (rule, alt, counts) in other.entries() {\n");
+        code.push_str("            let entry = self.entry(rule, alt);\n");
+        code.push_str("            entry.hits += counts.hits;\n");
+        code.push_str("            entry.misses += counts.misses;\n");
+        code.push_str("        }\n");
+        code.push_str("    }\n\n");
+        code.push_str("    fn entry(&mut self, rule: &'static str, alt: usize) -> &mut AltCounts {\n");
+        code.push_str("        let alts = self.counts.entry(rule).or_default();\n");
+        code.push_str("        if alts.len() < alt {\n");
+        code.push_str("            alts.resize(alt, AltCounts::default());\n");
+        code.push_str("        }\n");
+        code.push_str("        &mut alts[alt - 1]\n");
+        code.push_str("    }\n\n");
+        code.push_str("    fn hit(&mut self, rule: &'static str, alt: usize) {\n");
+        code.push_str("        self.entry(rule, alt).hits += 1;\n");
+        code.push_str("    }\n\n");
+        code.push_str("    fn miss(&mut self, rule: &'static str, alt: usize) {\n");
+        code.push_str("        self.entry(rule, alt).misses += 1;\n");
+        code.push_str("    }\n");
         code.push_str("}\n\n");
+
+        code
+    }
+
+    fn generate_parser_helpers(&self, grammar: &Grammar, config: &CodeGenConfig) -> String {
+        let mut code = String::new();
+        let record_token = if config.cst_mode == CstMode::On {
+            "                self.cst.token(&token);\n"
+        } else {
+            ""
+        };
+
+        code.push_str("    /// Build an error for the current token, expecting `expected`, unless a\n");
+        code.push_str("    /// loop stopped at an error further on. A loop that stopped here adds\n");
+        code.push_str("    /// what it expected.\n");
+        code.push_str("    fn unexpected(&mut self, expected: &str) -> ParseError {\n");
+        code.push_str("        let position = self.tokens.position();\n");
+        code.push_str("        let err = match self.tokens.peek(0) {\n");
+        code.push_str("            Some(token) if token.kind != TokenKind::Eof => ParseError::new(\n");
+        code.push_str("                format!(\"Expected {}, found {:?}\", expected, token.text),\n");
+        code.push_str("                token.position,\n");
+        code.push_str("            )\n");
+        code.push_str("            .with_expected(vec![expected.to_string()])\n");
+        code.push_str("            .with_found(token.text.clone()),\n");
+        code.push_str("            token => ParseError::new(\n");
+        code.push_str("                format!(\"Expected {}, found end of input\", expected),\n");
+        code.push_str("                token.map_or(position, |t| t.position),\n");
+        code.push_str("            )\n");
+        code.push_str("            .with_expected(vec![expected.to_string()]),\n");
+        code.push_str("        };\n");
+        code.push_str("        match self.stalled.clone() {\n");
+        code.push_str("            Some(mut stalled) if stalled.position >= err.position => {\n");
+        code.push_str("                Self::merge_expected(&mut stalled, err);\n");
+        code.push_str("                stalled\n");
+        code.push_str("            }\n");
+        code.push_str("            _ => err,\n");
+        code.push_str("        }\n");
+        code.push_str("    }\n\n");
+
+        code.push_str("    /// Lookahead token `k` (1-based, so `lt(1)` is the next token).\n");
+        code.push_str("    /// \n");
+        code.push_str("    /// Intended for semantic predicates, e.g. treating an `ID` as a\n");
+        code.push_str("    /// keyword only where `{self.lt(1).is_some_and(|t| t.text == \"SELECT\")}?` holds.\n");
+        code.push_str("    pub fn lt(&mut self, k: usize) -> Option<&Token> {\n");
+        code.push_str("        self.tokens.peek(k.checked_sub(1)?)\n");
+        code.push_str("    }\n\n");
+
+        code.push_str("    /// Keep whichever error got further into the input, or the first one\n");
+        code.push_str("    /// with what both expected if they got as far.\n");
+        code.push_str("    fn keep_furthest(furthest: &mut Option<ParseError>, err: ParseError) {\n");
+        code.push_str("        match furthest {\n");
+        code.push_str("            Some(f) if f.position >= err.position => Self::merge_expected(f, err),\n");
+        code.push_str("            _ => *furthest = Some(err),\n");
+        code.push_str("        }\n");
+        code.push_str("    }\n\n");
+
+        code.push_str("    /// Add what `err` expected to `into` if both failed at the same token.\n");
+        code.push_str("    fn merge_expected(into: &mut ParseError, err: ParseError) {\n");
+        code.push_str("        if into.position != err.position || into.expected.is_empty() || err.expected.is_empty() {\n");
+        code.push_str("            return;\n");
+        code.push_str("        }\n");
+        code.push_str("        let before = into.expected.len();\n");
+        code.push_str("        for expected in err.expected {\n");
+        code.push_str("            if !into.expected.contains(&expected) {\n");
+        code.push_str("                into.expected.push(expected);\n");
+        code.push_str("            }\n");
+        code.push_str("        }\n");
+        code.push_str("        if into.expected.len() > before {\n");
+        code.push_str("            let found = into.found.as_ref().map_or_else(|| \"end of input\".to_string(), |found| format!(\"{:?}\", found));\n");
+        code.push_str("            into.message = format!(\"Expected {}, found {}\", into.expected.join(\" or \"), found);\n");
+        code.push_str("        }\n");
+        code.push_str("    }\n\n");
+
+        if config.error_mode == ErrorMode::Collect {
+            code.push_str("    /// Run `parse` as one attempt among others, whose failure is left to\n");
+            code.push_str("    /// the caller to backtrack rather than recovered from.\n");
+            code.push_str("    fn speculate<T>(&mut self, parse: impl FnOnce(&mut Self) -> T) -> T {\n");
+            code.push_str("        self.speculating += 1;\n");
+            code.push_str("        let result = parse(self);\n");
+            code.push_str("        self.speculating -= 1;\n");
+            code.push_str("        result\n");
+            code.push_str("    }\n\n");
+
+            code.push_str("    /// Whether the next token is one of `kinds` or the end of input.\n");
+            code.push_str("    fn at_sync(&mut self, kinds: &[TokenKind]) -> bool {\n");
+            code.push_str("        self.tokens\n");
+            code.push_str("            .peek(0)\n");
+            code.push_str("            .is_none_or(|t| t.kind == TokenKind::Eof || kinds.contains(&t.kind))\n");
+            code.push_str("    }\n\n");
+
+            code.push_str("    /// Skip tokens until one of `kinds` or the end of input.\n");
+            code.push_str("    fn recover_to(&mut self, kinds: &[TokenKind]) {\n");
+            code.push_str("        while !self.at_sync(kinds) {\n");
+            code.push_str("            self.tokens.next();\n");
+            code.push_str("        }\n");
+            code.push_str("    }\n\n");
+        }
+
+        code.push_str("    fn expect_token(&mut self, kind: TokenKind) -> Result<Token, ParseError> {\n");
+        code.push_str("        match self.tokens.peek(0) {\n");
+        code.push_str("            Some(token) if token.kind == kind => {\n");
+        code.push_str("                let token = token.clone();\n");
+        code.push_str("                self.tokens.next();\n");
+        code.push_str(record_token);
+        code.push_str("                self.leaves.push(token.clone());\n");
+        code.push_str("                Ok(token)\n");
+        code.push_str("            }\n");
+        code.push_str("            _ => Err(self.unexpected(&format!(\"{:?}\", kind))),\n");
+        code.push_str("        }\n");
+        code.push_str("    }\n\n");
+
+        code.push_str("    fn expect_literal(&mut self, text: &str) -> Result<Token, ParseError> {\n");
+        code.push_str("        match self.tokens.peek(0) {\n");
+        code.push_str("            Some(token) if token.kind != TokenKind::Eof && token.text == text => {\n");
+        code.push_str("                let token = token.clone();\n");
+        code.push_str("                self.tokens.next();\n");
+        code.push_str(record_token);
+        code.push_str("                self.leaves.push(token.clone());\n");
+        code.push_str("                Ok(token)\n");
+        code.push_str("            }\n");
+        code.push_str("            _ => Err(self.unexpected(&format!(\"'{}'\", text))),\n");
+        code.push_str("        }\n");
+        code.push_str("    }\n\n");
+
+        if !case_insensitive_literals(grammar).is_empty() {
+            code.push_str("    /// Like `expect_literal`, for literals the lexer matches in either case.\n");
+            code.push_str("    fn expect_literal_ignore_case(&mut self, text: &str) -> Result<Token, ParseError> {\n");
+            code.push_str("        match self.tokens.peek(0) {\n");
+            code.push_str("            Some(token) if token.kind != TokenKind::Eof && token.text.to_lowercase() == text.to_lowercase() => {\n");
+            code.push_str("                let token = token.clone();\n");
+            code.push_str("                self.tokens.next();\n");
+            code.push_str(record_token);
+            code.push_str("                self.leaves.push(token.clone());\n");
+            code.push_str("                Ok(token)\n");
+            code.push_str("            }\n");
+            code.push_str("            _ => Err(self.unexpected(&format!(\"'{}'\", text))),\n");
+            code.push_str("        }\n");
+            code.push_str("    }\n\n");
+        }
+
+        code.push_str("    fn match_any(&mut self) -> Result<Token, ParseError> {\n");
+        code.push_str("        match self.tokens.peek(0) {\n");
+        code.push_str("            Some(token) if token.kind != TokenKind::Eof => {\n");
+        code.push_str("                let token = token.clone();\n");
+        code.push_str("                self.tokens.next();\n");
+        code.push_str(record_token);
+        code.push_str("                self.leaves.push(token.clone());\n");
+        code.push_str("                Ok(token)\n");
+        code.push_str("            }\n");
+        code.push_str("            _ => Err(self.unexpected(\"any token\")),\n");
+        code.push_str("        }\n");
+        code.push_str("    }\n\n");
+
+        code.push_str("    fn expect_eof(&mut self) -> Result<(), ParseError> {\n");
+        code.push_str("        match self.tokens.peek(0) {\n");
+        code.push_str("            None => Ok(()),\n");
+        code.push_str("            Some(token) if token.kind == TokenKind::Eof => Ok(()),\n");
+        code.push_str("            Some(_) => Err(self.unexpected(\"end of input\")),\n");
+        code.push_str("        }\n");
+        code.push_str("    }\n\n");
+
         code
     }
 
-    fn generate_rule_method(&self, rule: &Rule) -> String {
+    fn generate_rule_method(
+        &self,
+        grammar: &Grammar,
+        rule: &Rule,
+        config: &CodeGenConfig,
+        sync: Option<&SyncSets>,
+    ) -> String {
         let mut code = String::new();
+        let precedence_table = climbing_table(rule, config);
 
-        // Generate documentation
-        code.push_str(&format!("    /// Parse {} rule.\n", rule.name));
+        code.push_str(&self.rule_doc(rule));
         if !rule.arguments.is_empty() {
             code.push_str("    /// \n");
             code.push_str("    /// # Arguments\n");
@@ -85,6 +780,11 @@ impl RustCodeGenerator {
                 code.push_str(&format!("    /// * `{}` - {}\n", ret.name, type_str));
             }
         }
+        if precedence_table.is_some() {
+            code.push_str("    /// \n");
+            code.push_str("    /// Operators are parsed by precedence climbing: only those binding at\n");
+            code.push_str("    /// least as tightly as `min_prec` are taken, so 0 parses a whole expression.\n");
+        }
 
         // Generate function signature
         code.push_str("    pub fn parse_");
@@ -102,68 +802,509 @@ impl RustCodeGenerator {
             );
         }
 
+        if precedence_table.is_some() {
+            code.push_str(", min_prec: u8");
+        }
+
         code.push(')');
 
-        // Add return type
-        if rule.returns.is_empty() {
-            code.push_str(" -> Result<AstNode>");
-        } else if rule.returns.len() == 1 {
-            let ret_type = rule.returns[0]
-                .return_type.as_deref()
-                .unwrap_or("AstNode");
-            code.push_str(&format!(" -> Result<{}>", ret_type));
-        } else {
-            // Multiple returns - use tuple
-            code.push_str(" -> Result<(");
-            for (i, ret) in rule.returns.iter().enumerate() {
-                if i > 0 {
-                    code.push_str(", ");
-                }
-                code.push_str(
-                    ret.return_type.as_deref()
-                        .unwrap_or("AstNode"),
-                );
-            }
-            code.push_str(")>");
-        }
+        // Add return type (multiple returns use a tuple)
+        code.push_str(&format!(
+            " -> Result<{}, ParseError>",
+            rust_rule_value_type(rule)
+        ));
 
         code.push_str(" {\n");
 
+        // With catch/finally clauses the body runs in a closure so the
+        // handlers see its result however it returns. The same goes for
+        // wrapping what the rule matched into its CST node, except that
+        // precedence-climbing bodies do that for every operator themselves,
+        // and for recovering from errors, which a catch clause replaces.
+        let handled = !rule.catch_clauses.is_empty() || rule.finally_clause.is_some();
+        let cst = config.cst_mode == CstMode::On;
+        let wrap_cst = cst && precedence_table.is_none();
+        let recover = sync.filter(|_| rule.catch_clauses.is_empty());
+        let closure = handled || wrap_cst || recover.is_some();
+        let indent = if closure { 12 } else { 8 };
+        let pad = indentation(indent);
+        if cst {
+            code.push_str("        let cst_start = self.cst.len();\n");
+        }
+        if recover.is_some() && rule.returns.is_empty() {
+            code.push_str("        let rule_start = self.leaves.len();\n");
+        }
+        if closure {
+            code.push_str(&format!(
+                "        let result = (|| -> Result<{}, ParseError> {{\n",
+                rust_rule_value_type(rule)
+            ));
+        }
+
         // Generate local variables
         for local in &rule.locals {
             let type_str = local
                 .local_type.as_deref()
                 .unwrap_or("String");
-            code.push_str(&format!("        let mut {}: {};\n", local.name, type_str));
+            code.push_str(&format!("{}let mut {}: {};\n", pad, local.name, type_str));
         }
         if !rule.locals.is_empty() {
             code.push('\n');
         }
 
         // Generate actual rule body using rule_body helper
-        let mut ctx = RuleBodyContext::new().with_indent(8).with_token_vars(
-            "self.tokens[self.position].kind".to_string(),
-            "self.tokens.get(self.position + 1).map(|t| &t.kind)".to_string(),
-        );
-        let rule_body = generate_rust_rule_body(rule, &mut ctx);
+        let mut ctx = RuleBodyContext::new()
+            .with_indent(indent)
+            .with_token_vars(
+                "self.tokens.peek(0).map(|t| t.kind)".to_string(),
+                "self.tokens.peek(1).map(|t| t.kind)".to_string(),
+            )
+            .with_grammar(grammar)
+            .with_alt_stats(config.alt_stats)
+            .with_cst(cst)
+            .with_precedence_rules(
+                grammar
+                    .parser_rules()
+                    .filter(|rule| climbing_table(rule, config).is_some())
+                    .map(|rule| rule.name.clone())
+                    .collect(),
+            );
+        if let Some(sync) = sync {
+            ctx = ctx.with_recovery(sync.clone());
+        }
+        let rule_body = match &precedence_table {
+            Some(table) => generate_rust_precedence_body(rule, table, &mut ctx),
+            None => generate_rust_rule_body(rule, &mut ctx),
+        };
         code.push_str(&rule_body);
 
+        if closure {
+            code.push_str("        })();\n");
+            if let Some(sync) = recover {
+                code.push_str(&self.generate_rule_recovery(grammar, rule, sync));
+            }
+            if wrap_cst {
+                code.push_str("        if result.is_ok() {\n");
+                code.push_str(&format!("            self.cst.finish({:?}, cst_start);\n", rule.name));
+                code.push_str("        }\n");
+            }
+            if handled {
+                code.push_str(&self.generate_exception_handlers(rule));
+            }
+            code.push_str("        result\n");
+        }
+
+        code.push_str("    }\n\n");
+
+        code
+    }
+
+    /// Documentation of a rule's parse method, from the rule's doc comment
+    /// when it has one.
+    fn rule_doc(&self, rule: &Rule) -> String {
+        let Some(doc) = &rule.doc else {
+            return format!("    /// Parse {} rule.\n", rule.name);
+        };
+        let mut code = String::new();
+        for line in doc.lines() {
+            if line.is_empty() {
+                code.push_str("    ///\n");
+            } else {
+                code.push_str(&format!("    /// {}\n", line));
+            }
+        }
+        code
+    }
+
+    /// Code recovering from a failed `result` in parsers that collect
+    /// errors: unless the call may still be backtracked, the error is
+    /// recorded, tokens are skipped up to one that can follow the rule,
+    /// and the rule returns a node of the tokens it matched before failing
+    /// (or its default return values).
+    fn generate_rule_recovery(&self, grammar: &Grammar, rule: &Rule, sync: &SyncSets) -> String {
+        let mut code = String::new();
+
+        code.push_str("        let result = match result {\n");
+        code.push_str("            Err(err) if self.speculating == 0 => {\n");
+        code.push_str("                self.errors.push(err);\n");
+        if let Some(follow) = sync.follow(&rule.name) {
+            code.push_str(&format!("                self.recover_to({});\n", follow));
+        }
+        if rule.returns.is_empty() {
+            let type_name = self.to_pascal_case(&rule.name);
+            code.push_str(&format!(
+                "                Ok(AstNode::{}(Box::new({}Node {{\n",
+                type_name, type_name
+            ));
+            code.push_str("                    children: Vec::new(),\n");
+            code.push_str("                    tokens: self.leaves.split_off(rule_start),\n");
+            if label_enum(rule).is_some() {
+                code.push_str("                    label: None,\n");
+            }
+            for (name, _, is_list) in rust_labeled_fields(rule, grammar) {
+                let value = if is_list { "Vec::new()" } else { "None" };
+                code.push_str(&format!("                    {}: {},\n", name, value));
+            }
+            code.push_str("                })))\n");
+        } else {
+            code.push_str("                Ok(Default::default())\n");
+        }
+        code.push_str("            }\n");
+        code.push_str("            result => result,\n");
+        code.push_str("        };\n");
+
+        code
+    }
+
+    /// Code running a rule's `catch` and `finally` actions on `result`.
+    ///
+    /// Generated parsers have a single error type, so only the first
+    /// `catch` clause can apply; it sees the error by reference under the
+    /// name given in its brackets.
+    fn generate_exception_handlers(&self, rule: &Rule) -> String {
+        let mut code = String::new();
+
+        if let Some(clause) = rule.catch_clauses.first() {
+            code.push_str(&format!("        // catch [{}]\n", clause.exception));
+            code.push_str(&format!(
+                "        if let Err({}) = &result {{\n",
+                clause.variable().unwrap_or("_")
+            ));
+            code.push_str(&format!("            {}\n", clause.action));
+            code.push_str("        }\n");
+        }
+        if let Some(action) = &rule.finally_clause {
+            code.push_str("        // finally\n");
+            code.push_str("        {\n");
+            code.push_str(&format!("            {}\n", action));
+            code.push_str("        }\n");
+        }
+
+        code
+    }
+
+    /// The LL(1) table of a parser generated with
+    /// [`ParserStrategy::TableDriven`], or why there can be none.
+    fn ll1_table(&self, grammar: &Grammar, config: &CodeGenConfig) -> Result<Ll1Table> {
+        let unsupported = [
+            (config.error_mode == ErrorMode::Collect, "collecting errors"),
+            (config.cst_mode == CstMode::On, "concrete syntax trees"),
+            (config.alt_stats, "alternative statistics"),
+            (config.precedence_climbing, "precedence climbing"),
+        ];
+        if let Some((_, what)) = unsupported.iter().find(|(enabled, _)| *enabled) {
+            return Err(Error::codegen(format!(
+                "table-driven parsers do not support {}",
+                what
+            )));
+        }
+        Ll1Table::build(grammar).map_err(|diagnostics| {
+            let messages: Vec<String> = diagnostics.iter().map(|d| d.to_string()).collect();
+            Error::codegen(format!(
+                "grammar {} cannot be parsed by a table-driven parser:\n{}",
+                grammar.name,
+                messages.join("\n")
+            ))
+        })
+    }
+
+    /// Fail if a token rule nests fragment references deeper than
+    /// [`CodeGenConfig::max_inline_depth`], rather than inline them all
+    /// into the lexer's automaton.
+    fn check_inline_depth(&self, grammar: &Grammar, config: &CodeGenConfig) -> Result<()> {
+        let rules = token_rules(grammar);
+        let rule_refs: Vec<&Rule> = rules.iter().collect();
+        let chain = longest_inline_chain(&rule_refs);
+        if chain.len() > config.max_inline_depth + 1 {
+            return Err(Error::codegen(format!(
+                "token rule {} inlines fragments {} deep ({}), more than the maximum of {}",
+                chain[0],
+                chain.len() - 1,
+                chain.join(" -> "),
+                config.max_inline_depth
+            )));
+        }
+        Ok(())
+    }
+
+    /// The stack machine of a table-driven parser and the methods building
+    /// each rule's node from what it matched.
+    fn generate_ll1_driver(&self, grammar: &Grammar, table: &Ll1Table) -> String {
+        let mut code = String::new();
+        let rule_count = grammar.parser_rules().count();
+
+        code.push_str("    /// Parse nonterminal `start` of the LL(1) table, expanding the\n");
+        code.push_str("    /// production the next token selects on an explicit stack instead of\n");
+        code.push_str("    /// recursing.\n");
+        code.push_str("    fn ll1_parse(&mut self, start: usize) -> Result<AstNode, ParseError> {\n");
+        code.push_str("        let mut stack = vec![Ll1Symbol::Nonterminal(start, None)];\n");
+        code.push_str("        let mut frames: Vec<Ll1Frame> = Vec::new();\n");
+        code.push_str("        while let Some(symbol) = stack.pop() {\n");
+        code.push_str("            match symbol {\n");
+        code.push_str("                Ll1Symbol::Token(TokenKind::Eof, _) => self.expect_eof()?,\n");
+        code.push_str("                Ll1Symbol::Token(kind, field) => {\n");
+        code.push_str("                    if self.tokens.peek(0).is_none_or(|t| t.kind != kind) {\n");
+        code.push_str("                        return Err(self.unexpected(ll1_display(kind)));\n");
+        code.push_str("                    }\n");
+        code.push_str("                    let token = self.expect_token(kind)?;\n");
+        code.push_str("                    if let (Some(field), Some(frame)) = (field, frames.last_mut()) {\n");
+        code.push_str("                        frame.captures.push((field, Ll1Value::Token(token)));\n");
+        code.push_str("                    }\n");
+        code.push_str("                }\n");
+        code.push_str("                Ll1Symbol::Nonterminal(nonterminal, field) => {\n");
+        code.push_str("                    let kind = self.tokens.peek(0).map_or(TokenKind::Eof, |t| t.kind);\n");
+        code.push_str("                    let production = LL1_TABLE[nonterminal]\n");
+        code.push_str("                        .iter()\n");
+        code.push_str("                        .find(|(expected, _)| *expected == kind)\n");
+        code.push_str("                        .map(|(_, production)| *production)\n");
+        code.push_str("                        .or(LL1_DEFAULTS[nonterminal]);\n");
+        code.push_str("                    let Some(production) = production else {\n");
+        code.push_str("                        return Err(self.unexpected(LL1_EXPECTED[nonterminal]));\n");
+        code.push_str("                    };\n");
+        code.push_str(&format!(
+            "                    if nonterminal < {} {{\n",
+            rule_count
+        ));
+        code.push_str("                        // A rule: collect what it matches into a node of its own\n");
+        code.push_str("                        frames.push(Ll1Frame {\n");
+        code.push_str("                            field,\n");
+        code.push_str("                            production,\n");
+        code.push_str("                            children: Vec::new(),\n");
+        code.push_str("                            captures: Vec::new(),\n");
+        code.push_str("                            leaves_start: self.leaves.len(),\n");
+        code.push_str("                        });\n");
+        code.push_str("                        stack.push(Ll1Symbol::Finish(nonterminal));\n");
+        code.push_str("                    }\n");
+        code.push_str("                    stack.extend(LL1_PRODUCTIONS[production].iter().rev().copied());\n");
+        code.push_str("                }\n");
+        code.push_str("                Ll1Symbol::Finish(rule) => {\n");
+        code.push_str("                    let frame = frames.pop().expect(\"every rule has a frame\");\n");
+        code.push_str("                    let field = frame.field;\n");
+        code.push_str("                    let node = self.ll1_build(rule, frame);\n");
+        code.push_str("                    let Some(parent) = frames.last_mut() else {\n");
+        code.push_str("                        return Ok(node);\n");
+        code.push_str("                    };\n");
+        code.push_str("                    match field {\n");
+        code.push_str("                        Some(field) => {\n");
+        code.push_str("                            parent.children.push(node.clone());\n");
+        code.push_str("                            parent.captures.push((field, Ll1Value::Node(node)));\n");
+        code.push_str("                        }\n");
+        code.push_str("                        None => parent.children.push(node),\n");
+        code.push_str("                    }\n");
+        code.push_str("                }\n");
+        code.push_str("            }\n");
+        code.push_str("        }\n");
+        code.push_str("        unreachable!(\"the start rule finishes last\")\n");
+        code.push_str("    }\n\n");
+
+        code.push_str("    /// Build the node of parser rule `rule` from what its frame matched.\n");
+        code.push_str("    fn ll1_build(&mut self, rule: usize, frame: Ll1Frame) -> AstNode {\n");
+        code.push_str("        let tokens = self.leaves.split_off(frame.leaves_start);\n");
+        code.push_str("        match rule {\n");
+        for (index, rule) in grammar.parser_rules().enumerate() {
+            let type_name = self.to_pascal_case(&rule.name);
+            let fields = rust_labeled_fields(rule, grammar);
+            code.push_str(&format!("            {} => {{\n", index));
+            for (name, field_type, is_list) in &fields {
+                if *is_list {
+                    code.push_str(&format!(
+                        "                let mut {}: Vec<{}> = Vec::new();\n",
+                        name, field_type
+                    ));
+                } else {
+                    code.push_str(&format!(
+                        "                let mut {}: Option<{}> = None;\n",
+                        name, field_type
+                    ));
+                }
+            }
+            if !fields.is_empty() {
+                code.push_str("                for (field, value) in frame.captures {\n");
+                code.push_str("                    match (field, value) {\n");
+                for (position, (name, field_type, is_list)) in fields.iter().enumerate() {
+                    let variant = if field_type == "Token" { "Token" } else { "Node" };
+                    let store = if *is_list {
+                        format!("{}.push(value)", name)
+                    } else {
+                        format!("{} = Some(value)", name)
+                    };
+                    code.push_str(&format!(
+                        "                        ({}, Ll1Value::{}(value)) => {},\n",
+                        position, variant, store
+                    ));
+                }
+                code.push_str("                        _ => {}\n");
+                code.push_str("                    }\n");
+                code.push_str("                }\n");
+            }
+            code.push_str(&format!(
+                "                AstNode::{}(Box::new({}Node {{\n",
+                type_name, type_name
+            ));
+            code.push_str("                    children: frame.children,\n");
+            code.push_str("                    tokens,\n");
+            if let Some((enum_name, _)) = label_enum(rule) {
+                // The n-th production of a rule's nonterminal is its n-th alternative
+                let arms: Vec<String> = table
+                    .productions
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, production)| production.lhs == index)
+                    .zip(&rule.alternatives)
+                    .filter_map(|((production, _), alt)| {
+                        let label = alt.label.as_ref()?;
+                        Some(format!("{} => Some({}::{})", production, enum_name, label_variant(label)))
+                    })
+                    .collect();
+                code.push_str(&format!(
+                    "                    label: match frame.production {{ {}, _ => None }},\n",
+                    arms.join(", ")
+                ));
+            }
+            for (name, _, _) in &fields {
+                code.push_str(&format!("                    {},\n", name));
+            }
+            code.push_str("                }))\n");
+            code.push_str("            }\n");
+        }
+        code.push_str("            _ => unreachable!(\"not a rule nonterminal: {}\", rule),\n");
+        code.push_str("        }\n");
         code.push_str("    }\n\n");
 
         code
     }
 
-    fn generate_lexer(&self, grammar: &Grammar) -> String {
+    /// The LL(1) parse table and the types its stack machine works with.
+    fn generate_ll1_tables(&self, grammar: &Grammar, table: &Ll1Table) -> String {
+        let mut code = String::new();
+        let rules: Vec<&Rule> = grammar.parser_rules().collect();
+        let nonterminal_name = |id: usize| {
+            let nonterminal = &table.nonterminals[id];
+            let rule = &rules[nonterminal.rule].name;
+            match nonterminal.kind {
+                NonterminalKind::Rule => rule.clone(),
+                _ => format!("{}#{}", rule, id),
+            }
+        };
+
+        code.push_str("/// Symbol of a production of the LL(1) parse table.\n");
+        code.push_str("#[derive(Debug, Clone, Copy)]\n");
+        code.push_str("enum Ll1Symbol {\n");
+        code.push_str("    /// Match a token of this kind, storing it in the rule node's field with this index\n");
+        code.push_str("    Token(TokenKind, Option<usize>),\n");
+        code.push_str("    /// Expand a nonterminal; a rule's node is stored in the field with this index\n");
+        code.push_str("    Nonterminal(usize, Option<usize>),\n");
+        code.push_str("    /// Build the node of a rule whose expansion is complete\n");
+        code.push_str("    Finish(usize),\n");
+        code.push_str("}\n\n");
+
+        code.push_str("/// Something a rule matched, to be stored in one of its node's fields.\n");
+        code.push_str("#[derive(Debug)]\n");
+        code.push_str("enum Ll1Value {\n");
+        code.push_str("    Token(Token),\n");
+        code.push_str("    Node(AstNode),\n");
+        code.push_str("}\n\n");
+
+        code.push_str("/// What a rule being parsed has matched so far.\n");
+        code.push_str("#[derive(Debug)]\n");
+        code.push_str("struct Ll1Frame {\n");
+        code.push_str("    /// Field of the parent rule's node this rule's node goes in\n");
+        code.push_str("    field: Option<usize>,\n");
+        code.push_str("    /// Production the rule was expanded with, telling its alternative\n");
+        code.push_str("    production: usize,\n");
+        code.push_str("    children: Vec<AstNode>,\n");
+        code.push_str("    captures: Vec<(usize, Ll1Value)>,\n");
+        code.push_str("    leaves_start: usize,\n");
+        code.push_str("}\n\n");
+
+        code.push_str("/// Right-hand side of each production.\n");
+        code.push_str("const LL1_PRODUCTIONS: &[&[Ll1Symbol]] = &[\n");
+        for (index, production) in table.productions.iter().enumerate() {
+            let mut names = Vec::new();
+            let mut symbols = Vec::new();
+            for symbol in &production.symbols {
+                match symbol {
+                    Symbol::Token { kind, field } => {
+                        names.push(match table.displays.get(kind) {
+                            _ if kind == END => "EOF".to_string(),
+                            Some(display) => display.clone(),
+                            None => kind.clone(),
+                        });
+                        symbols.push(format!("Ll1Symbol::Token(TokenKind::{}, {:?})", kind, field));
+                    }
+                    Symbol::Nonterminal { id, field } => {
+                        names.push(nonterminal_name(*id));
+                        symbols.push(format!("Ll1Symbol::Nonterminal({}, {:?})", id, field));
+                    }
+                }
+            }
+            code.push_str(&format!(
+                "    // {}: {} -> {}\n",
+                index,
+                nonterminal_name(production.lhs),
+                if names.is_empty() { "ε".to_string() } else { names.join(" ") }
+            ));
+            code.push_str(&format!("    &[{}],\n", symbols.join(", ")));
+        }
+        code.push_str("];\n\n");
+
+        code.push_str("/// Production to expand for each nonterminal and kind of next token.\n");
+        code.push_str("const LL1_TABLE: &[&[(TokenKind, usize)]] = &[\n");
+        for (id, row) in table.table.iter().enumerate() {
+            let cells: Vec<String> = row
+                .iter()
+                .map(|(kind, production)| format!("(TokenKind::{}, {})", kind, production))
+                .collect();
+            code.push_str(&format!("    // {}\n", nonterminal_name(id)));
+            code.push_str(&format!("    &[{}],\n", cells.join(", ")));
+        }
+        code.push_str("];\n\n");
+
+        code.push_str("/// Production of each nonterminal matching empty input, expanded on\n");
+        code.push_str("/// tokens missing from its row of the table.\n");
+        code.push_str(&format!("const LL1_DEFAULTS: &[Option<usize>] = &{:?};\n\n", table.defaults));
+
+        code.push_str("/// Tokens each nonterminal accepts next, for error messages.\n");
+        code.push_str(&format!("const LL1_EXPECTED: &[&str] = &{:?};\n\n", table.expected));
+
+        let mut displays: Vec<_> = table.displays.iter().filter(|(kind, _)| *kind != END).collect();
+        displays.sort();
+        code.push_str("/// How a token kind the table matches is shown in error messages.\n");
+        code.push_str("fn ll1_display(kind: TokenKind) -> &'static str {\n");
+        code.push_str("    match kind {\n");
+        for (kind, display) in displays {
+            code.push_str(&format!("        TokenKind::{} => {:?},\n", kind, display));
+        }
+        code.push_str("        _ => kind.as_str(),\n");
+        code.push_str("    }\n");
+        code.push_str("}\n\n");
+
+        code
+    }
+
+    fn generate_lexer(&self, grammar: &Grammar, config: &CodeGenConfig) -> String {
         let mut code = String::new();
+        let token_rules = token_rules(grammar);
 
         code.push_str(&format!("/// Lexer for {} grammar.\n", grammar.name));
         code.push_str("/// \n");
         code.push_str("/// This lexer uses an optimized DFA (Deterministic Finite Automaton)\n");
         code.push_str("/// generated at compile time for efficient tokenization.\n");
         code.push_str("#[derive(Debug)]\n");
-        code.push_str(&format!("pub struct {}Lexer {{\n", grammar.name));
+        code.push_str(&format!("{}struct {}Lexer {{\n", item_visibility(config), grammar.name));
         code.push_str("    input: Vec<char>,\n");
         code.push_str("    position: usize,\n");
+        code.push_str("    /// Tokens set aside by `tokenize_all` because they are off the default channel\n");
+        code.push_str("    hidden: Vec<Token>,\n");
+        code.push_str("    /// Tokens queued by `emit`, returned before the next match\n");
+        code.push_str("    pending: std::collections::VecDeque<Token>,\n");
+        code.push_str("    /// Start of the token being matched\n");
+        code.push_str("    token_start: usize,\n");
+        code.push_str("    /// Where the last token started, for working out the next one's line\n");
+        code.push_str("    cursor: SourcePoint,\n");
+        code.push_str("    /// Set once iteration has yielded `Eof`\n");
+        code.push_str("    done: bool,\n");
+        code.push_str(&generate_rust_mode_stack(grammar));
         code.push_str("}\n\n");
 
         code.push_str(&format!("impl {}Lexer {{\n", grammar.name));
@@ -173,55 +1314,159 @@ impl RustCodeGenerator {
         code.push_str("        Self {\n");
         code.push_str("            input: input.chars().collect(),\n");
         code.push_str("            position: 0,\n");
+        code.push_str("            hidden: Vec::new(),\n");
+        code.push_str("            pending: std::collections::VecDeque::new(),\n");
+        code.push_str("            token_start: 0,\n");
+        code.push_str("            cursor: SourcePoint::default(),\n");
+        code.push_str("            done: false,\n");
+        code.push_str(&generate_rust_mode_init(grammar));
+        code.push_str("        }\n");
+        code.push_str("    }\n\n");
+
+        code.push_str("    /// Get the next token from the input.\n");
+        code.push_str("    /// \n");
+        code.push_str(
+            "    /// Returns Ok(Token) on success, or Err(ParseError) if tokenization fails.\n",
+        );
+        code.push_str("    pub fn next_token(&mut self) -> Result<Token, ParseError> {\n");
+        code.push_str("        loop {\n");
+        code.push_str("            if let Some(token) = self.pending.pop_front() {\n");
+        code.push_str("                return Ok(token);\n");
+        code.push_str("            }\n\n");
+        if grammar.has_modes() {
+            code.push_str("            // Other modes match their own whitespace, e.g. inside strings\n");
+            code.push_str("            if self.current_mode() == \"DEFAULT_MODE\" {\n");
+            code.push_str("                self.skip_whitespace();\n");
+            code.push_str("            }\n\n");
+        } else {
+            code.push_str("            // Skip whitespace\n");
+            code.push_str("            self.skip_whitespace();\n\n");
+        }
+        code.push_str("            let start_pos = self.position;\n");
+        code.push_str("            self.token_start = start_pos;\n\n");
+        code.push_str("            // EOF sits at the end of the input, after any trailing whitespace\n");
+        code.push_str("            if self.position >= self.input.len() {\n");
+        code.push_str("                let end = self.input.len();\n");
+        code.push_str("                return Ok(self.make_token(TokenKind::Eof, String::new(), end, end));\n");
+        code.push_str("            }\n\n");
+        code.push_str("            // Use DFA for tokenization\n");
+        code.push_str("            match self.next_token_dfa() {\n");
+        code.push_str("                Some(mut token) => {\n");
+        code.push_str("                    self.run_actions(&mut token);\n");
+        code.push_str("                    match self.apply_commands(token) {\n");
+        code.push_str("                        Some(token) => return Ok(token),\n");
+        code.push_str("                        None => continue,\n");
+        code.push_str("                    }\n");
+        code.push_str("                }\n");
+        code.push_str("                None => {\n");
+        code.push_str("                    // Error recovery: skip invalid character and try again\n");
+        code.push_str("                    let invalid_char = self.input[self.position];\n");
+        code.push_str("                    self.position += 1;\n");
+        code.push_str("                    return Err(ParseError::new(\n");
+        code.push_str(
+            "                        format!(\"Unexpected character: '{}'\", invalid_char),\n",
+        );
+        code.push_str("                        start_pos,\n");
+        code.push_str("                    ).with_found(invalid_char.to_string()));\n");
+        code.push_str("                }\n");
+        code.push_str("            }\n");
+        code.push_str("        }\n");
+        code.push_str("    }\n\n");
+
+        code.push_str(&self.generate_apply_commands(grammar, &token_rules));
+        code.push_str(&self.generate_lexer_actions(&token_rules));
+        code.push_str(&generate_rust_mode_methods(grammar));
+
+        code.push_str("    /// Queue a token to be returned before the next match.\n");
+        code.push_str("    /// \n");
+        code.push_str("    /// Meant for lexer actions, so one match can produce several tokens,\n");
+        code.push_str("    /// e.g. `{ self.emit(TokenKind::DEDENT, \"\"); }`. Queued tokens come after\n");
+        code.push_str("    /// the matched token itself unless its rule is skipped, and span the\n");
+        code.push_str("    /// whole match.\n");
+        code.push_str("    pub fn emit(&mut self, kind: TokenKind, text: &str) {\n");
+        code.push_str("        let token = self.make_token(kind, text.to_string(), self.token_start, self.position);\n");
+        code.push_str("        self.pending.push_back(token);\n");
+        code.push_str("    }\n\n");
+
+        code.push_str("    /// A token of `kind` over the characters `start..end`, with its line,\n");
+        code.push_str("    /// column and byte offsets worked out from the input.\n");
+        code.push_str("    fn make_token(&mut self, kind: TokenKind, text: String, start: usize, end: usize) -> Token {\n");
+        code.push_str("        let point = self.locate(start);\n");
+        code.push_str("        let bytes: usize = self.input[start..end].iter().map(|c| c.len_utf8()).sum();\n");
+        code.push_str("        Token {\n");
+        code.push_str("            kind,\n");
+        code.push_str("            text,\n");
+        code.push_str("            position: start,\n");
+        code.push_str("            end,\n");
+        code.push_str("            line: point.line,\n");
+        code.push_str("            column: point.column,\n");
+        code.push_str("            start: point.byte,\n");
+        code.push_str("            stop: point.byte + bytes,\n");
+        code.push_str("            channel: kind.channel(),\n");
+        code.push_str("        }\n");
+        code.push_str("    }\n\n");
+
+        code.push_str("    /// Line, column and byte offset of the character at `index`.\n");
+        code.push_str("    /// \n");
+        code.push_str("    /// Walks on from the previous token start, so lexing the input once\n");
+        code.push_str("    /// reads each character once more; an earlier `index` starts over from\n");
+        code.push_str("    /// the beginning of the input.\n");
+        code.push_str("    fn locate(&mut self, index: usize) -> SourcePoint {\n");
+        code.push_str("        if index < self.cursor.index {\n");
+        code.push_str("            self.cursor = SourcePoint::default();\n");
+        code.push_str("        }\n");
+        code.push_str("        while self.cursor.index < index {\n");
+        code.push_str("            let ch = self.input[self.cursor.index];\n");
+        code.push_str("            self.cursor.index += 1;\n");
+        code.push_str("            self.cursor.byte += ch.len_utf8();\n");
+        code.push_str("            if ch == '\\n' {\n");
+        code.push_str("                self.cursor.line += 1;\n");
+        code.push_str("                self.cursor.column = 0;\n");
+        code.push_str("            } else {\n");
+        code.push_str("                self.cursor.column += 1;\n");
+        code.push_str("            }\n");
         code.push_str("        }\n");
+        code.push_str("        self.cursor\n");
         code.push_str("    }\n\n");
 
-        code.push_str("    /// Get the next token from the input.\n");
+        code.push_str("    /// Look at the next token without consuming it.\n");
         code.push_str("    /// \n");
-        code.push_str(
-            "    /// Returns Ok(Token) on success, or Err(ParseError) if tokenization fails.\n",
-        );
-        code.push_str("    pub fn next_token(&mut self) -> Result<Token, ParseError> {\n");
-        code.push_str("        // Skip whitespace\n");
-        code.push_str("        self.skip_whitespace();\n\n");
-        code.push_str("        let start_pos = self.position;\n\n");
-        code.push_str("        // Check for EOF\n");
-        code.push_str("        if self.position >= self.input.len() {\n");
-        code.push_str("            return Ok(Token {\n");
-        code.push_str("                kind: TokenKind::Eof,\n");
-        code.push_str("                text: String::new(),\n");
-        code.push_str("                position: start_pos,\n");
-        code.push_str("            });\n");
-        code.push_str("        }\n\n");
-        code.push_str("        // Use DFA for tokenization\n");
-        code.push_str("        match self.next_token_dfa() {\n");
-        code.push_str("            Some(mut token) => {\n");
-        code.push_str("                token.position = start_pos;\n");
-        code.push_str("                Ok(token)\n");
-        code.push_str("            }\n");
-        code.push_str("            None => {\n");
-        code.push_str("                // Error recovery: skip invalid character and try again\n");
-        code.push_str("                let invalid_char = self.input[self.position];\n");
-        code.push_str("                self.position += 1;\n");
-        code.push_str("                Err(ParseError::new(\n");
-        code.push_str(
-            "                    format!(\"Unexpected character: '{}'\", invalid_char),\n",
-        );
-        code.push_str("                    start_pos,\n");
-        code.push_str("                ).with_found(invalid_char.to_string()))\n");
-        code.push_str("            }\n");
-        code.push_str("        }\n");
+        code.push_str("    /// The lexer position is restored afterwards, so the following\n");
+        code.push_str("    /// call to `next_token` returns the same token.\n");
+        code.push_str("    pub fn peek_token(&mut self) -> Result<Token, ParseError> {\n");
+        code.push_str("        let saved_pos = self.position;\n");
+        code.push_str("        let saved_start = self.token_start;\n");
+        code.push_str("        let saved_cursor = self.cursor;\n");
+        code.push_str("        let saved_pending = self.pending.clone();\n");
+        if grammar.has_modes() {
+            code.push_str("        let saved_modes = self.mode_stack.clone();\n");
+        }
+        code.push_str("        let token = self.next_token();\n");
+        code.push_str("        self.position = saved_pos;\n");
+        code.push_str("        self.token_start = saved_start;\n");
+        code.push_str("        self.cursor = saved_cursor;\n");
+        code.push_str("        self.pending = saved_pending;\n");
+        if grammar.has_modes() {
+            code.push_str("        self.mode_stack = saved_modes;\n");
+        }
+        code.push_str("        token\n");
         code.push_str("    }\n\n");
 
-        code.push_str("    /// Tokenize all input and collect errors.\n");
+        code.push_str("    /// Look ahead up to `n` tokens without consuming them.\n");
         code.push_str("    /// \n");
-        code.push_str(
-            "    /// Returns all successfully parsed tokens and a list of errors encountered.\n",
-        );
-        code.push_str("    pub fn tokenize_all(&mut self) -> (Vec<Token>, Vec<ParseError>) {\n");
-        code.push_str("        let mut tokens = Vec::new();\n");
-        code.push_str("        let mut errors = Vec::new();\n\n");
-        code.push_str("        loop {\n");
+        code.push_str("    /// Stops early after `Eof`. The lexer position is restored afterwards,\n");
+        code.push_str("    /// whether or not tokenization succeeded.\n");
+        code.push_str("    pub fn peek_n(&mut self, n: usize) -> Result<Vec<Token>, ParseError> {\n");
+        code.push_str("        let saved_pos = self.position;\n");
+        code.push_str("        let saved_start = self.token_start;\n");
+        code.push_str("        let saved_cursor = self.cursor;\n");
+        code.push_str("        let saved_pending = self.pending.clone();\n");
+        if grammar.has_modes() {
+            code.push_str("        let saved_modes = self.mode_stack.clone();\n");
+        }
+        code.push_str("        let mut tokens = Vec::with_capacity(n);\n");
+        code.push_str("        let mut result = Ok(());\n\n");
+        code.push_str("        while tokens.len() < n {\n");
         code.push_str("            match self.next_token() {\n");
         code.push_str("                Ok(token) => {\n");
         code.push_str("                    let is_eof = token.kind == TokenKind::Eof;\n");
@@ -231,29 +1476,64 @@ impl RustCodeGenerator {
         code.push_str("                    }\n");
         code.push_str("                }\n");
         code.push_str("                Err(err) => {\n");
-        code.push_str("                    errors.push(err);\n");
-        code.push_str("                    // Continue tokenizing after error\n");
-        code.push_str("                    if self.position >= self.input.len() {\n");
-        code.push_str("                        break;\n");
-        code.push_str("                    }\n");
+        code.push_str("                    result = Err(err);\n");
+        code.push_str("                    break;\n");
         code.push_str("                }\n");
         code.push_str("            }\n");
         code.push_str("        }\n\n");
-        code.push_str("        (tokens, errors)\n");
+        code.push_str("        self.position = saved_pos;\n");
+        code.push_str("        self.token_start = saved_start;\n");
+        code.push_str("        self.cursor = saved_cursor;\n");
+        code.push_str("        self.pending = saved_pending;\n");
+        if grammar.has_modes() {
+            code.push_str("        self.mode_stack = saved_modes;\n");
+        }
+        code.push_str("        result.map(|_| tokens)\n");
         code.push_str("    }\n\n");
 
-        code.push_str("    #[inline(always)]\n");
-        code.push_str("    fn skip_whitespace(&mut self) {\n");
-        code.push_str("        while self.position < self.input.len() {\n");
-        code.push_str("            match self.input[self.position] {\n");
-        code.push_str("                ' ' | '\\t' | '\\r' | '\\n' => self.position += 1,\n");
-        code.push_str("                _ => break,\n");
+        code.push_str("    /// Tokenize all input and collect errors.\n");
+        code.push_str("    /// \n");
+        code.push_str(
+            "    /// Returns the default-channel tokens and a list of errors encountered.\n",
+        );
+        code.push_str("    /// Tokens on other channels are kept in `hidden_tokens`. This drains\n");
+        code.push_str("    /// the lexer as an iterator, so lexing continues after an error.\n");
+        code.push_str("    pub fn tokenize_all(&mut self) -> (Vec<Token>, Vec<ParseError>) {\n");
+        code.push_str("        let mut tokens = Vec::new();\n");
+        code.push_str("        let mut errors = Vec::new();\n\n");
+        code.push_str("        while let Some(result) = self.next() {\n");
+        code.push_str("            match result {\n");
+        code.push_str("                Ok(token) if token.channel == 0 => tokens.push(token),\n");
+        code.push_str("                Ok(token) => self.hidden.push(token),\n");
+        code.push_str("                Err(err) => errors.push(err),\n");
         code.push_str("            }\n");
-        code.push_str("        }\n");
+        code.push_str("        }\n\n");
+        code.push_str("        (tokens, errors)\n");
+        code.push_str("    }\n\n");
+
+        code.push_str("    /// Off-channel tokens, such as comments sent to `HIDDEN`, seen by `tokenize_all`.\n");
+        code.push_str("    pub fn hidden_tokens(&self) -> &[Token] {\n");
+        code.push_str("        &self.hidden\n");
         code.push_str("    }\n\n");
 
+        code.push_str("    #[inline(always)]\n");
+        let skipped = skipped_whitespace(grammar, &token_rules);
+        if skipped.is_empty() {
+            code.push_str("    fn skip_whitespace(&mut self) {}\n\n");
+        } else {
+            let pattern: Vec<String> = skipped.iter().map(|c| format!("{:?}", c)).collect();
+            code.push_str("    fn skip_whitespace(&mut self) {\n");
+            code.push_str("        while self.position < self.input.len() {\n");
+            code.push_str("            match self.input[self.position] {\n");
+            code.push_str(&format!("                {} => self.position += 1,\n", pattern.join(" | ")));
+            code.push_str("                _ => break,\n");
+            code.push_str("            }\n");
+            code.push_str("        }\n");
+            code.push_str("    }\n\n");
+        }
+
         // Generate lookup table for character classes
-        let lexer_rules: Vec<_> = grammar.lexer_rules().collect();
+        let lexer_rules: Vec<&Rule> = token_rules.iter().collect();
         if !lexer_rules.is_empty() {
             let mut lookup_builder = LookupTableBuilder::new();
             lookup_builder.build_from_rules(&lexer_rules);
@@ -272,10 +1552,14 @@ impl RustCodeGenerator {
             // Generate optimized character matching functions
             code.push_str(&generate_optimized_char_match(&lookup_builder));
 
-            // Generate DFA-based tokenization
-            let mut dfa_builder = DfaBuilder::new();
-            let states = dfa_builder.build_from_rules(&lexer_rules);
-            code.push_str(&generate_dfa_match(&states));
+            // Generate DFA-based tokenization, one automaton per mode
+            if grammar.has_modes() {
+                code.push_str(&self.generate_mode_dfas(grammar, &lexer_rules));
+            } else {
+                let mut dfa_builder = DfaBuilder::new();
+                let states = dfa_builder.build_from_rules(&lexer_rules);
+                code.push_str(&generate_dfa_match(&states));
+            }
 
             // Add statistics as comment
             let stats = lookup_builder.stats();
@@ -294,43 +1578,77 @@ impl RustCodeGenerator {
 
         code.push_str("}\n\n");
 
+        code.push_str("/// Tokens one at a time, so large inputs need not be lexed up front.\n");
+        code.push_str("/// \n");
+        code.push_str("/// Yields every token, whatever its channel, and errors as they occur;\n");
+        code.push_str("/// iteration goes on after an error and ends after `Eof`.\n");
+        code.push_str(&format!("impl Iterator for {}Lexer {{\n", grammar.name));
+        code.push_str("    type Item = Result<Token, ParseError>;\n\n");
+        code.push_str("    fn next(&mut self) -> Option<Self::Item> {\n");
+        code.push_str("        if self.done {\n");
+        code.push_str("            return None;\n");
+        code.push_str("        }\n");
+        code.push_str("        let result = self.next_token();\n");
+        code.push_str("        self.done = matches!(&result, Ok(token) if token.kind == TokenKind::Eof);\n");
+        code.push_str("        Some(result)\n");
+        code.push_str("    }\n");
+        code.push_str("}\n\n");
+
         code
     }
 
-    fn generate_ast_types(&self, grammar: &Grammar) -> String {
+    fn generate_ast_types(&self, grammar: &Grammar, config: &CodeGenConfig) -> String {
         let mut code = String::new();
+        let vis = item_visibility(config);
 
         // Generate struct definitions for each parser rule
         code.push_str("/// AST node type definitions.\n\n");
         
         for rule in grammar.parser_rules() {
             let struct_name = self.to_pascal_case(&rule.name);
-            let fields = self.extract_labeled_fields(rule);
-            
+            let fields = rust_labeled_fields(rule, grammar);
+            let labels = label_enum(rule);
+
+            if let Some((enum_name, labels)) = &labels {
+                code.push_str(&format!("/// Labeled alternatives of the {} rule.\n", rule.name));
+                code.push_str(&format!(
+                    "#[derive(Debug, Clone, Copy, PartialEq, Eq{})]\n",
+                    serde_derives(config)
+                ));
+                code.push_str(&format!("{}enum {} {{\n", vis, enum_name));
+                for label in labels {
+                    code.push_str(&format!("    {},\n", label_variant(label)));
+                }
+                code.push_str("}\n\n");
+            }
+
             code.push_str(&format!("/// AST node for {} rule.\n", rule.name));
-            code.push_str("#[derive(Debug, Clone)]\n");
+            code.push_str(&format!("#[derive(Debug, Clone{})]\n", serde_derives(config)));
             
-            if fields.is_empty() {
-                // Empty struct for rules without labels
-                code.push_str(&format!("pub struct {}Node;\n\n", struct_name));
-            } else {
-                // Struct with fields for labeled elements
-                code.push_str(&format!("pub struct {}Node {{\n", struct_name));
-                for (field_name, field_type, is_list) in &fields {
-                    if *is_list {
-                        code.push_str(&format!("    pub {}: Vec<{}>,\n", field_name, field_type));
-                    } else {
-                        code.push_str(&format!("    pub {}: {},\n", field_name, field_type));
-                    }
+            code.push_str(&format!("{}struct {}Node {{\n", vis, struct_name));
+            code.push_str("    /// Nodes of the rules invoked while parsing this one, in order\n");
+            code.push_str("    pub children: Vec<AstNode>,\n");
+            code.push_str("    /// Tokens matched by this rule itself, in order\n");
+            code.push_str("    pub tokens: Vec<Token>,\n");
+            if let Some((enum_name, _)) = &labels {
+                code.push_str("    /// Label of the alternative matched, unset for unlabeled ones\n");
+                code.push_str(&format!("    pub label: Option<{}>,\n", enum_name));
+            }
+            for (field_name, field_type, is_list) in &fields {
+                if *is_list {
+                    code.push_str(&format!("    pub {}: Vec<{}>,\n", field_name, field_type));
+                } else {
+                    // Labels in alternatives that were not taken stay unset
+                    code.push_str(&format!("    pub {}: Option<{}>,\n", field_name, field_type));
                 }
-                code.push_str("}\n\n");
             }
+            code.push_str("}\n\n");
         }
 
         // Generate main AstNode enum
         code.push_str("/// Main AST node enum.\n");
-        code.push_str("#[derive(Debug, Clone)]\n");
-        code.push_str("pub enum AstNode {\n");
+        code.push_str(&format!("#[derive(Debug, Clone{})]\n", serde_derives(config)));
+        code.push_str(&format!("{}enum AstNode {{\n", vis));
 
         for rule in grammar.parser_rules() {
             let variant_name = self.to_pascal_case(&rule.name);
@@ -338,64 +1656,364 @@ impl RustCodeGenerator {
         }
 
         code.push_str("}\n\n");
+
+        code.push_str(&self.generate_tree_query(grammar, vis));
         code
     }
 
-    fn extract_labeled_fields(&self, rule: &Rule) -> Vec<(String, String, bool)> {
-        let mut fields = Vec::new();
-        let mut seen_labels = std::collections::HashSet::new();
+    /// Generate `AstNode::rule_name`/`children` and the `query` function for
+    /// finding nodes by rule-name path.
+    fn generate_tree_query(&self, grammar: &Grammar, vis: &str) -> String {
+        let mut code = String::new();
+        // Without parser rules the enum is empty, and only matching on the
+        // enum itself rather than a reference to it needs no arms
+        let matched = if grammar.parser_rules().next().is_some() {
+            "match self {\n"
+        } else {
+            "match *self {\n"
+        };
 
-        for alt in &rule.alternatives {
-            for elem in &alt.elements {
-                self.collect_labels_from_element(elem, &mut fields, &mut seen_labels);
-            }
+        code.push_str("impl AstNode {\n");
+        code.push_str("    /// Name of the grammar rule this node was parsed from.\n");
+        code.push_str("    pub fn rule_name(&self) -> &'static str {\n");
+        code.push_str(&format!("        {}", matched));
+        for rule in grammar.parser_rules() {
+            code.push_str(&format!(
+                "            AstNode::{}(_) => \"{}\",\n",
+                self.to_pascal_case(&rule.name),
+                rule.name
+            ));
+        }
+        code.push_str("        }\n");
+        code.push_str("    }\n\n");
+        code.push_str("    /// Child nodes for the rules invoked while parsing this node.\n");
+        code.push_str("    pub fn children(&self) -> &[AstNode] {\n");
+        code.push_str(&format!("        {}", matched));
+        for rule in grammar.parser_rules() {
+            code.push_str(&format!(
+                "            AstNode::{}(node) => &node.children,\n",
+                self.to_pascal_case(&rule.name)
+            ));
+        }
+        code.push_str("        }\n");
+        code.push_str("    }\n\n");
+        code.push_str("    /// Tokens matched by this node's rule itself, not by its children.\n");
+        code.push_str("    pub fn tokens(&self) -> &[Token] {\n");
+        code.push_str(&format!("        {}", matched));
+        for rule in grammar.parser_rules() {
+            code.push_str(&format!(
+                "            AstNode::{}(node) => &node.tokens,\n",
+                self.to_pascal_case(&rule.name)
+            ));
         }
+        code.push_str("        }\n");
+        code.push_str("    }\n\n");
+        code.push_str("    /// Render the tree as an S-expression, such as `(expr (term (factor 1)) + (term (factor 2)))`.\n");
+        code.push_str("    /// \n");
+        code.push_str("    /// Each node is its rule name followed by its child nodes and token\n");
+        code.push_str("    /// texts in input order.\n");
+        code.push_str("    pub fn to_sexpr(&self) -> String {\n");
+        code.push_str("        let mut out = String::new();\n");
+        code.push_str("        self.write_sexpr(&mut out);\n");
+        code.push_str("        out\n");
+        code.push_str("    }\n\n");
+        code.push_str("    fn write_sexpr(&self, out: &mut String) {\n");
+        code.push_str("        out.push('(');\n");
+        code.push_str("        out.push_str(self.rule_name());\n");
+        code.push_str("        let mut tokens = self.tokens().iter().peekable();\n");
+        code.push_str("        for child in self.children() {\n");
+        code.push_str("            let start = child.start_position();\n");
+        code.push_str("            while let Some(token) = tokens.next_if(|t| start.is_some_and(|s| t.position < s)) {\n");
+        code.push_str("                out.push(' ');\n");
+        code.push_str("                out.push_str(&token.text);\n");
+        code.push_str("            }\n");
+        code.push_str("            out.push(' ');\n");
+        code.push_str("            child.write_sexpr(out);\n");
+        code.push_str("        }\n");
+        code.push_str("        for token in tokens {\n");
+        code.push_str("            out.push(' ');\n");
+        code.push_str("            out.push_str(&token.text);\n");
+        code.push_str("        }\n");
+        code.push_str("        out.push(')');\n");
+        code.push_str("    }\n\n");
+        code.push_str("    /// Input position of the first token under this node, if it matched any.\n");
+        code.push_str("    fn start_position(&self) -> Option<usize> {\n");
+        code.push_str("        let own = self.tokens().first().map(|t| t.position);\n");
+        code.push_str("        let nested = self.children().iter().find_map(|c| c.start_position());\n");
+        code.push_str("        own.into_iter().chain(nested).min()\n");
+        code.push_str("    }\n");
+        code.push_str("}\n\n");
+
+        code.push_str("/// Find nodes by rule-name path, such as `expr/term` or `//factor`.\n");
+        code.push_str("///\n");
+        code.push_str("/// `/` steps to child nodes and `//` to descendants at any depth; `*`\n");
+        code.push_str("/// matches any rule. The first step is matched against `tree` itself,\n");
+        code.push_str("/// or against `tree` and all its descendants when the path starts with `//`.\n");
+        code.push_str(&format!(
+            "{}fn query<'a>(tree: &'a AstNode, path: &str) -> Vec<&'a AstNode> {{\n",
+            vis
+        ));
+        code.push_str("    fn descendants<'a>(node: &'a AstNode, out: &mut Vec<&'a AstNode>) {\n");
+        code.push_str("        out.push(node);\n");
+        code.push_str("        for child in node.children() {\n");
+        code.push_str("            descendants(child, out);\n");
+        code.push_str("        }\n");
+        code.push_str("    }\n\n");
+        code.push_str("    let mut matched: Vec<&'a AstNode> = Vec::new();\n");
+        code.push_str("    let mut rest = path;\n");
+        code.push_str("    let mut first = true;\n");
+        code.push_str("    while !rest.is_empty() {\n");
+        code.push_str("        let descendant = rest.starts_with(\"//\");\n");
+        code.push_str("        rest = rest.trim_start_matches('/');\n");
+        code.push_str("        let end = rest.find('/').unwrap_or(rest.len());\n");
+        code.push_str("        let (name, remaining) = rest.split_at(end);\n");
+        code.push_str("        rest = remaining;\n\n");
+        code.push_str("        let mut candidates: Vec<&'a AstNode> = Vec::new();\n");
+        code.push_str("        let context: Vec<&'a AstNode> = if first {\n");
+        code.push_str("            vec![tree]\n");
+        code.push_str("        } else {\n");
+        code.push_str("            matched.iter().flat_map(|node| node.children()).collect()\n");
+        code.push_str("        };\n");
+        code.push_str("        for node in context {\n");
+        code.push_str("            if descendant {\n");
+        code.push_str("                descendants(node, &mut candidates);\n");
+        code.push_str("            } else {\n");
+        code.push_str("                candidates.push(node);\n");
+        code.push_str("            }\n");
+        code.push_str("        }\n\n");
+        code.push_str("        matched.clear();\n");
+        code.push_str("        for node in candidates {\n");
+        code.push_str("            let is_new = !matched.iter().any(|seen| std::ptr::eq(*seen, node));\n");
+        code.push_str("            if (name == \"*\" || node.rule_name() == name) && is_new {\n");
+        code.push_str("                matched.push(node);\n");
+        code.push_str("            }\n");
+        code.push_str("        }\n");
+        code.push_str("        first = false;\n");
+        code.push_str("    }\n");
+        code.push_str("    matched\n");
+        code.push_str("}\n\n");
 
-        fields
+        code
     }
 
-    fn collect_labels_from_element(
-        &self,
-        element: &crate::ast::Element,
-        fields: &mut Vec<(String, String, bool)>,
-        seen_labels: &mut std::collections::HashSet<String>,
-    ) {
-        use crate::ast::Element;
-
-        match element {
-            Element::Terminal { label: Some(label), is_list, .. } => {
-                if seen_labels.insert(label.clone()) {
-                    fields.push((label.clone(), "Token".to_string(), *is_list));
-                }
-            }
-            Element::RuleRef { label: Some(label), is_list, .. } => {
-                if seen_labels.insert(label.clone()) {
-                    fields.push((label.clone(), "AstNode".to_string(), *is_list));
-                }
-            }
-            Element::StringLiteral { label: Some(label), is_list, .. } => {
-                if seen_labels.insert(label.clone()) {
-                    fields.push((label.clone(), "Token".to_string(), *is_list));
-                }
-            }
-            Element::Group { alternatives } => {
-                for alt in alternatives {
-                    for elem in &alt.elements {
-                        self.collect_labels_from_element(elem, fields, seen_labels);
-                    }
-                }
-            }
-            Element::Optional { element, .. } => {
-                self.collect_labels_from_element(element, fields, seen_labels);
-            }
-            Element::ZeroOrMore { element, .. } => {
-                self.collect_labels_from_element(element, fields, seen_labels);
-            }
-            Element::OneOrMore { element, .. } => {
-                self.collect_labels_from_element(element, fields, seen_labels);
+    /// `TokenKind` methods and its conversions to and from token names.
+    /// `variants` lists every variant but `Eof`, which is named `EOF`.
+    fn generate_token_kind_impl(&self, grammar: &Grammar, variants: &[String]) -> String {
+        let mut code = String::new();
+        code.push_str("impl TokenKind {\n");
+        code.push_str("    /// Name of the token as written in the grammar.\n");
+        code.push_str("    pub fn as_str(&self) -> &'static str {\n");
+        code.push_str("        match self {\n");
+        for name in variants {
+            code.push_str(&format!("            TokenKind::{} => \"{}\",\n", name, name));
+        }
+        code.push_str("            TokenKind::Eof => \"EOF\",\n");
+        code.push_str("        }\n");
+        code.push_str("    }\n\n");
+        code.push_str("    /// Channel tokens of this kind are sent to.\n");
+        code.push_str("    pub fn channel(self) -> u32 {\n");
+        code.push_str("        match self {\n");
+        for (rule_name, channel) in token_channels(grammar) {
+            code.push_str(&format!("            TokenKind::{} => {},\n", rule_name, channel));
+        }
+        code.push_str("            _ => 0,\n");
+        code.push_str("        }\n");
+        code.push_str("    }\n\n");
+        code.push_str("    /// Whether tokens of this kind go to a channel other than the default one.\n");
+        code.push_str("    pub fn is_hidden(self) -> bool {\n");
+        code.push_str("        self.channel() != 0\n");
+        code.push_str("    }\n");
+        code.push_str("}\n\n");
+
+        code.push_str("impl fmt::Display for TokenKind {\n");
+        code.push_str("    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {\n");
+        code.push_str("        f.write_str(self.as_str())\n");
+        code.push_str("    }\n");
+        code.push_str("}\n\n");
+
+        code.push_str("impl std::str::FromStr for TokenKind {\n");
+        code.push_str("    type Err = ParseError;\n\n");
+        code.push_str("    /// Look up a token kind by its name in the grammar, e.g. `\"EOF\"`.\n");
+        code.push_str("    fn from_str(s: &str) -> Result<Self, Self::Err> {\n");
+        code.push_str("        match s {\n");
+        for name in variants {
+            code.push_str(&format!("            \"{}\" => Ok(TokenKind::{}),\n", name, name));
+        }
+        code.push_str("            \"EOF\" => Ok(TokenKind::Eof),\n");
+        code.push_str("            _ => Err(ParseError::new(format!(\"unknown token kind '{}'\", s), 0)),\n");
+        code.push_str("        }\n");
+        code.push_str("    }\n");
+        code.push_str("}\n\n");
+        code
+    }
+
+    /// `run_actions`, running the actions written in each token rule.
+    fn generate_lexer_actions(&self, token_rules: &[Rule]) -> String {
+        let with_actions: Vec<(&Rule, Vec<String>)> = token_rules
+            .iter()
+            .filter(|rule| !rule.is_fragment)
+            .map(|rule| (rule, rule_actions(rule)))
+            .filter(|(_, actions)| !actions.is_empty())
+            .collect();
+
+        let mut code = String::new();
+        code.push_str("    /// Run the actions of the rule that matched `token`.\n");
+        if with_actions.is_empty() {
+            code.push_str("    fn run_actions(&mut self, _token: &mut Token) {}\n\n");
+            return code;
+        }
+
+        code.push_str("    fn run_actions(&mut self, token: &mut Token) {\n");
+        code.push_str("        match token.kind {\n");
+        for (rule, actions) in with_actions {
+            code.push_str(&format!("            TokenKind::{} => {{\n", rule.name));
+            for action in actions {
+                code.push_str(&format!("                {}\n", generate_rust_action(&action)));
             }
-            _ => {}
+            code.push_str("            }\n");
+        }
+        code.push_str("            _ => {}\n");
+        code.push_str("        }\n");
+        code.push_str("    }\n\n");
+        code
+    }
+
+    /// `apply_commands`, running each token rule's lexer commands in order.
+    fn generate_apply_commands(&self, grammar: &Grammar, token_rules: &[Rule]) -> String {
+        let channels = channel_numbers(grammar);
+        let kinds: HashSet<&str> = token_rules.iter().map(|rule| rule.name.as_str()).collect();
+        let mut modes: HashSet<&str> = grammar.lexer_modes.keys().map(String::as_str).collect();
+        if !modes.is_empty() {
+            modes.insert("DEFAULT_MODE");
+        }
+        let with_commands: Vec<&Rule> = token_rules
+            .iter()
+            .filter(|rule| !rule_commands(rule).is_empty())
+            .collect();
+
+        let mut code = String::new();
+        code.push_str("    /// Apply the lexer commands of the rule that matched `token`, in order.\n");
+        code.push_str("    /// \n");
+        code.push_str("    /// Returns `None` when the token is skipped.\n");
+        if with_commands.is_empty() {
+            code.push_str("    fn apply_commands(&mut self, token: Token) -> Option<Token> {\n");
+            code.push_str("        Some(token)\n");
+            code.push_str("    }\n\n");
+            return code;
+        }
+
+        // Only `channel` and `type` change the token itself
+        let changes_token = with_commands.iter().any(|rule| {
+            rule_commands(rule)
+                .iter()
+                .any(|command| matches!(command, LexerCommand::Channel(_) | LexerCommand::Type(_)))
+        });
+        code.push_str(&format!(
+            "    fn apply_commands(&mut self, {}token: Token) -> Option<Token> {{\n",
+            if changes_token { "mut " } else { "" }
+        ));
+        code.push_str("        match token.kind {\n");
+        for rule in with_commands {
+            code.push_str(&format!("            TokenKind::{} => {{\n", rule.name));
+            code.push_str(&generate_rust_lexer_commands(
+                rule_commands(rule),
+                &channels,
+                &kinds,
+                &modes,
+                "                ",
+            ));
+            code.push_str("            }\n");
+        }
+        code.push_str("            _ => {}\n");
+        code.push_str("        }\n");
+        code.push_str("        Some(token)\n");
+        code.push_str("    }\n\n");
+        code
+    }
+
+    /// The `TokenSource` trait the parser reads from, and its `Vec` impl.
+    fn generate_token_source(&self, config: &CodeGenConfig) -> String {
+        let mut code = String::new();
+        let vis = item_visibility(config);
+
+        code.push_str("/// Where the parser pulls its tokens from.\n");
+        code.push_str("///\n");
+        code.push_str("/// Parsers backtrack, so a source must be able to `seek` back to any\n");
+        code.push_str("/// position it has already reported. Streaming sources can buffer the\n");
+        code.push_str("/// tokens they have produced to allow this.\n");
+        code.push_str(&format!("{}trait TokenSource {{\n", vis));
+        code.push_str("    /// Return the current token and move past it.\n");
+        code.push_str("    fn next(&mut self) -> Option<Token>;\n");
+        code.push_str("    /// The token `k` places after the current one, so `peek(0)` is the current token.\n");
+        code.push_str("    fn peek(&mut self, k: usize) -> Option<&Token>;\n");
+        code.push_str("    /// Index of the current token.\n");
+        code.push_str("    fn position(&self) -> usize;\n");
+        code.push_str("    /// Move to a position previously returned by `position`.\n");
+        code.push_str("    fn seek(&mut self, position: usize);\n");
+        code.push_str("}\n\n");
+
+        code.push_str("/// Token source over an already lexed token list.\n");
+        code.push_str("#[derive(Debug, Clone)]\n");
+        code.push_str(&format!("{}struct VecTokenSource {{\n", vis));
+        code.push_str("    tokens: Vec<Token>,\n");
+        code.push_str("    position: usize,\n");
+        code.push_str("}\n\n");
+
+        code.push_str("impl VecTokenSource {\n");
+        code.push_str("    pub fn new(tokens: Vec<Token>) -> Self {\n");
+        code.push_str("        Self { tokens, position: 0 }\n");
+        code.push_str("    }\n");
+        code.push_str("}\n\n");
+
+        code.push_str("impl TokenSource for VecTokenSource {\n");
+        code.push_str("    fn next(&mut self) -> Option<Token> {\n");
+        code.push_str("        let token = self.tokens.get(self.position).cloned();\n");
+        code.push_str("        if token.is_some() {\n");
+        code.push_str("            self.position += 1;\n");
+        code.push_str("        }\n");
+        code.push_str("        token\n");
+        code.push_str("    }\n\n");
+        code.push_str("    fn peek(&mut self, k: usize) -> Option<&Token> {\n");
+        code.push_str("        self.tokens.get(self.position + k)\n");
+        code.push_str("    }\n\n");
+        code.push_str("    fn position(&self) -> usize {\n");
+        code.push_str("        self.position\n");
+        code.push_str("    }\n\n");
+        code.push_str("    fn seek(&mut self, position: usize) {\n");
+        code.push_str("        self.position = position;\n");
+        code.push_str("    }\n");
+        code.push_str("}\n\n");
+
+        code
+    }
+
+    /// One `next_token_dfa_<mode>` per lexer mode, and a `next_token_dfa`
+    /// running the one for the mode on top of the stack. See
+    /// [`rules_by_mode`] for which rules each mode's automaton sees.
+    fn generate_mode_dfas(&self, grammar: &Grammar, lexer_rules: &[&Rule]) -> String {
+        let modes = rules_by_mode(grammar, lexer_rules);
+
+        let mut code = String::new();
+        code.push_str("    fn next_token_dfa(&mut self) -> Option<Token> {\n");
+        code.push_str("        match self.current_mode() {\n");
+        for (name, _) in modes.iter().skip(1) {
+            code.push_str(&format!(
+                "            \"{}\" => self.next_token_dfa_{}(),\n",
+                name,
+                name.to_lowercase()
+            ));
+        }
+        code.push_str("            _ => self.next_token_dfa_default_mode(),\n");
+        code.push_str("        }\n");
+        code.push_str("    }\n\n");
+
+        for (name, rules) in &modes {
+            let states = DfaBuilder::new().build_from_rules(rules);
+            let fn_name = format!("next_token_dfa_{}", name.to_lowercase());
+            code.push_str(&generate_named_dfa_match(&states, &fn_name));
+            code.push('\n');
         }
+        code
     }
 
     fn to_pascal_case(&self, s: &str) -> String {
@@ -411,6 +2029,123 @@ impl RustCodeGenerator {
     }
 }
 
+/// Operator table of `rule` if it is generated as a precedence-climbing
+/// method: only with the option on, and only for rules without arguments
+/// or return values.
+/// Visibility keyword, with its trailing space, for generated top-level items.
+///
+/// Methods and fields stay `pub`; the item they belong to limits their reach.
+fn item_visibility(config: &CodeGenConfig) -> &'static str {
+    match config.visibility {
+        Visibility::Public => "pub ",
+        Visibility::Crate => "pub(crate) ",
+        Visibility::Module => "",
+    }
+}
+
+/// Derives, with a leading comma, added to the AST and token types when
+/// `derive_serde` is set.
+fn serde_derives(config: &CodeGenConfig) -> &'static str {
+    if config.derive_serde {
+        ", Serialize, Deserialize"
+    } else {
+        ""
+    }
+}
+
+fn climbing_table(rule: &Rule, config: &CodeGenConfig) -> Option<PrecedenceTable> {
+    if !config.precedence_climbing {
+        return None;
+    }
+    precedence_table(rule)
+}
+
+/// Call of the start rule's parse method from an entry point.
+fn start_rule_call(start: &Rule, config: &CodeGenConfig) -> String {
+    match climbing_table(start, config) {
+        Some(_) => format!("parse_{}(0)", start.name),
+        None => format!("parse_{}()", start.name),
+    }
+}
+
+/// Numbers of the channels used in lexer commands.
+///
+/// `DEFAULT_TOKEN_CHANNEL` is 0 and `HIDDEN` is 1, as in ANTLR, and
+/// `channel(3)` is channel 3. Other named channels are numbered from 2 in
+/// order of first use, skipping numbers that are used directly.
+fn channel_numbers(grammar: &Grammar) -> HashMap<String, u32> {
+    let mut numbers = HashMap::from([
+        ("DEFAULT_TOKEN_CHANNEL".to_string(), 0),
+        ("HIDDEN".to_string(), 1),
+    ]);
+    let channels: Vec<&String> = grammar
+        .lexer_rules()
+        .flat_map(|rule| &rule.alternatives)
+        .flat_map(|alt| &alt.lexer_commands)
+        .filter_map(|command| match command {
+            LexerCommand::Channel(name) => Some(name),
+            _ => None,
+        })
+        .collect();
+
+    let mut used = HashSet::new();
+    for name in &channels {
+        if let Ok(number) = name.parse::<u32>() {
+            numbers.insert(name.to_string(), number);
+            used.insert(number);
+        }
+    }
+    let mut next = 2;
+    for name in channels {
+        if numbers.contains_key(name) {
+            continue;
+        }
+        while used.contains(&next) {
+            next += 1;
+        }
+        numbers.insert(name.clone(), next);
+        next += 1;
+    }
+    numbers
+}
+
+/// Channel numbers of the token rules with a `-> channel(...)` command.
+///
+/// When a rule names several channels, the last one wins, as it would
+/// when its commands run in order.
+fn token_channels(grammar: &Grammar) -> Vec<(String, u32)> {
+    let numbers = channel_numbers(grammar);
+
+    grammar
+        .lexer_rules()
+        .filter(|rule| !rule.is_fragment)
+        .filter_map(|rule| {
+            let channel = rule_commands(rule).iter().rev().find_map(|command| match command {
+                LexerCommand::Channel(name) => numbers.get(name).copied(),
+                _ => None,
+            })?;
+            (channel != 0).then(|| (rule.name.clone(), channel))
+        })
+        .collect()
+}
+
+/// Actions written in a token rule, from its first alternative that has any.
+///
+/// Like lexer commands, actions apply to the rule as a whole.
+fn rule_actions(rule: &Rule) -> Vec<String> {
+    rule.alternatives
+        .iter()
+        .map(|alt| {
+            alt.elements
+                .iter()
+                .filter(|element| matches!(element, Element::Action { .. }))
+                .filter_map(|element| translate_action_element(element, "rust"))
+                .collect::<Vec<_>>()
+        })
+        .find(|actions| !actions.is_empty())
+        .unwrap_or_default()
+}
+
 impl Default for RustCodeGenerator {
     fn default() -> Self {
         Self::new()
@@ -423,29 +2158,56 @@ impl CodeGeneratorTrait for RustCodeGenerator {
 
     fn generate(&self, input: &Self::Input, _config: &Self::Config) -> Result<String> {
         let mut code = String::new();
+        let vis = item_visibility(_config);
+        let table = match _config.parser_strategy {
+            ParserStrategy::RecursiveDescent => None,
+            ParserStrategy::TableDriven => Some(self.ll1_table(input, _config)?),
+        };
+        self.check_inline_depth(input, _config)?;
+        if (_config.generate_visitor || _config.generate_listener)
+            && let Some(clash) = visit_method_clash(input)
+        {
+            return Err(Error::codegen(clash));
+        }
+        if let Some(rule) = input.parser_rules().find(|rule| {
+            label_enum(rule).is_some()
+                && rust_labeled_fields(rule, input).iter().any(|(name, _, _)| name == "label")
+        }) {
+            return Err(Error::codegen(format!(
+                "rule '{}' has #Labels, so its nodes record the alternative matched in field 'label'; rename its element label 'label'",
+                rule.name
+            )));
+        }
 
-        // Header with comprehensive documentation
-        code.push_str(&format!("//! Generated parser for {} grammar\n", input.name));
-        code.push_str("//!\n");
-        code.push_str("//! **DO NOT EDIT** - This file is automatically generated by minipg\n");
-        code.push_str("//!\n");
-        code.push_str("//! # Grammar Information\n");
-        code.push_str(&format!("//! - Grammar name: {}\n", input.name));
-        code.push_str(&format!("//! - Grammar type: {:?}\n", input.grammar_type));
-        code.push_str(&format!("//! - Total rules: {}\n", input.rules.len()));
-        code.push_str(&format!("//! - Parser rules: {}\n", input.parser_rules().count()));
-        code.push_str(&format!("//! - Lexer rules: {}\n", input.lexer_rules().count()));
-        code.push_str("//!\n");
-        code.push_str("//! # Usage\n");
-        code.push_str("//! ```rust,ignore\n");
-        code.push_str(&format!("//! let lexer = {}Lexer::new(input);\n", input.name));
-        code.push_str("//! let tokens = lexer.tokenize()?;\n");
-        code.push_str(&format!("//! let mut parser = {}Parser::new(tokens);\n", input.name));
-        code.push_str("//! let ast = parser.parse()?;\n");
-        code.push_str("//! ```\n\n");
+        // Header with comprehensive documentation. Module-private code is
+        // meant to be `include!`d, where inner doc comments are not allowed
+        let doc = if _config.visibility == Visibility::Module { "//" } else { "//!" };
+        code.push_str(&format!("{} Generated parser for {} grammar\n", doc, input.name));
+        code.push_str(&format!("{}\n", doc));
+        code.push_str(&format!("{} **DO NOT EDIT** - This file is automatically generated by minipg\n", doc));
+        code.push_str(&format!("{}\n", doc));
+        code.push_str(&format!("{} # Grammar Information\n", doc));
+        code.push_str(&format!("{} - Grammar name: {}\n", doc, input.name));
+        code.push_str(&format!("{} - Grammar type: {:?}\n", doc, input.grammar_type));
+        code.push_str(&format!("{} - Total rules: {}\n", doc, input.rules.len()));
+        code.push_str(&format!("{} - Parser rules: {}\n", doc, input.parser_rules().count()));
+        code.push_str(&format!("{} - Lexer rules: {}\n", doc, input.lexer_rules().count()));
+        code.push_str(&format!("{}\n", doc));
+        code.push_str(&format!("{} # Usage\n", doc));
+        code.push_str(&format!("{} ```rust,ignore\n", doc));
+        code.push_str(&format!("{} let mut lexer = {}Lexer::new(input);\n", doc, input.name));
+        code.push_str(&format!("{} let (tokens, errors) = lexer.tokenize_all();\n", doc));
+        code.push_str(&format!("{} let mut parser = {}Parser::new(tokens);\n", doc, input.name));
+        if let Some(start_rule) = input.parser_rules().next() {
+            code.push_str(&format!("{} let ast = parser.{}?;\n", doc, start_rule_call(start_rule, _config)));
+        }
+        code.push_str(&format!("{} ```\n\n", doc));
 
         // Imports
         code.push_str("use std::fmt;\n");
+        if _config.derive_serde {
+            code.push_str("use serde::{Serialize, Deserialize};\n");
+        }
 
         // Insert @header named action if present
         if let Some(header_code) = input.named_actions.get("header") {
@@ -458,7 +2220,7 @@ impl CodeGeneratorTrait for RustCodeGenerator {
         // Error types for parsing
         code.push_str("/// Parse error with context information.\n");
         code.push_str("#[derive(Debug, Clone, PartialEq)]\n");
-        code.push_str("pub struct ParseError {\n");
+        code.push_str(&format!("{}struct ParseError {{\n", vis));
         code.push_str("    pub message: String,\n");
         code.push_str("    pub position: usize,\n");
         code.push_str("    pub expected: Vec<String>,\n");
@@ -499,49 +2261,206 @@ impl CodeGeneratorTrait for RustCodeGenerator {
 
         code.push_str("impl std::error::Error for ParseError {}\n\n");
 
+        code.push_str("/// Sort errors by position and drop repeats of the same message there.\n");
+        code.push_str(&format!(
+            "{}fn dedup_errors(mut errors: Vec<ParseError>) -> Vec<ParseError> {{\n",
+            vis
+        ));
+        code.push_str("    errors.sort_by_key(|err| err.position);\n");
+        code.push_str("    let mut seen = std::collections::HashSet::new();\n");
+        code.push_str("    errors.retain(|err| seen.insert((err.position, err.message.clone())));\n");
+        code.push_str("    errors\n");
+        code.push_str("}\n\n");
+
         // Token type
         code.push_str("/// Token with position information.\n");
-        code.push_str("#[derive(Debug, Clone, PartialEq)]\n");
-        code.push_str("pub struct Token {\n");
+        code.push_str(&format!("#[derive(Debug, Clone, PartialEq{})]\n", serde_derives(_config)));
+        code.push_str(&format!("{}struct Token {{\n", vis));
         code.push_str("    pub kind: TokenKind,\n");
         code.push_str("    pub text: String,\n");
+        code.push_str("    /// Index of the token's first character\n");
         code.push_str("    pub position: usize,\n");
+        code.push_str("    /// Position just past the token's last character\n");
+        code.push_str("    pub end: usize,\n");
+        code.push_str("    /// Line of the first character, counting from 1\n");
+        code.push_str("    pub line: usize,\n");
+        code.push_str("    /// Characters before the token on its line, counting from 0\n");
+        code.push_str("    pub column: usize,\n");
+        code.push_str("    /// Byte offset of the token in the input, so `&input[token.start..token.stop]`\n");
+        code.push_str("    /// is its matched text\n");
+        code.push_str("    pub start: usize,\n");
+        code.push_str("    /// Byte offset just past the token's last character\n");
+        code.push_str("    pub stop: usize,\n");
+        code.push_str("    /// Channel the token was sent to: 0 is the default channel, 1 is `HIDDEN`\n");
+        code.push_str("    pub channel: u32,\n");
         code.push_str("}\n\n");
 
+        code.push_str("/// Line, column and byte offset of a character in the lexer's input.\n");
         code.push_str("#[derive(Debug, Clone, Copy, PartialEq, Eq)]\n");
-        code.push_str("pub enum TokenKind {\n");
+        code.push_str("struct SourcePoint {\n");
+        code.push_str("    index: usize,\n");
+        code.push_str("    line: usize,\n");
+        code.push_str("    column: usize,\n");
+        code.push_str("    byte: usize,\n");
+        code.push_str("}\n\n");
+        code.push_str("impl Default for SourcePoint {\n");
+        code.push_str("    fn default() -> Self {\n");
+        code.push_str("        Self { index: 0, line: 1, column: 0, byte: 0 }\n");
+        code.push_str("    }\n");
+        code.push_str("}\n\n");
+
+        code.push_str("/// Whether `b` starts right where `a` ends, with nothing skipped between them.\n");
+        code.push_str("/// \n");
+        code.push_str("/// Useful in whitespace-significant grammars, e.g. to tell `a.b` from `a . b`.\n");
+        code.push_str(&format!("{}fn are_adjacent(a: &Token, b: &Token) -> bool {{\n", vis));
+        code.push_str("    a.end == b.position\n");
+        code.push_str("}\n\n");
+
+        code.push_str(&format!(
+            "#[derive(Debug, Clone, Copy, PartialEq, Eq{})]\n",
+            serde_derives(_config)
+        ));
+        code.push_str("#[allow(non_camel_case_types)]\n");
+        code.push_str(&format!("{}enum TokenKind {{\n", vis));
+        // Vocabulary tokens come first with their numbers as discriminants,
+        // so the variants match the lexer grammar they were exported from.
+        let mut declared = HashSet::new();
+        let mut variants = Vec::new();
+        if let Some(vocab) = &input.token_vocab {
+            let mut tokens = vocab.tokens().to_vec();
+            tokens.sort_by_key(|(_, number)| *number);
+            for (name, number) in tokens {
+                code.push_str(&format!("    {} = {},\n", name, number));
+                declared.insert(name.clone());
+                variants.push(name);
+            }
+        }
+        for (name, literal) in implicit_literal_tokens(input) {
+            if declared.insert(name.clone()) {
+                code.push_str(&format!("    /// Implicit token for {:?}\n", literal));
+                code.push_str(&format!("    {},\n", name));
+                variants.push(name);
+            }
+        }
+        for name in &input.declared_tokens {
+            if declared.insert(name.clone()) {
+                code.push_str(&format!("    /// Declared in `tokens {{ ... }}`\n    {},\n", name));
+                variants.push(name.clone());
+            }
+        }
         for rule in input.lexer_rules() {
-            if !rule.is_fragment {
+            if !rule.is_fragment && declared.insert(rule.name.clone()) {
                 code.push_str(&format!("    {},\n", rule.name));
+                variants.push(rule.name.clone());
             }
         }
         code.push_str("    Eof,\n");
         code.push_str("}\n\n");
+        code.push_str(&self.generate_token_kind_impl(input, &variants));
+        code.push_str(&self.generate_token_source(_config));
 
         // Generate AST types
-        code.push_str(&self.generate_ast_types(input));
+        code.push_str(&self.generate_ast_types(input, _config));
+        if _config.cst_mode == CstMode::On {
+            code.push_str(&self.generate_cst_types(_config));
+        }
 
         // Generate visitor if requested
         if _config.generate_visitor {
-            code.push_str(&generate_visitor(input));
+            code.push_str(&generate_visitor(input, vis));
         }
 
         // Generate listener if requested
         if _config.generate_listener {
-            code.push_str(&generate_listener(input));
+            code.push_str(&generate_listener(input, vis));
         }
 
         // Generate lexer
-        code.push_str(&self.generate_lexer(input));
+        code.push_str(&self.generate_lexer(input, _config));
 
         // Generate parser
-        code.push_str(&self.generate_parser_struct(input));
-        code.push_str(&self.generate_parser_impl(input));
+        if _config.alt_stats {
+            code.push_str(&self.generate_alt_stats(_config));
+        }
+        code.push_str(&self.generate_parser_struct(input, _config));
+        code.push_str(&self.generate_parser_impl(input, _config, table.as_ref()));
+        if let Some(table) = &table {
+            code.push_str(&self.generate_ll1_tables(input, table));
+        }
 
-        Ok(code)
+        Ok(Emitter::new(_config, 4).finish(code))
     }
 
     fn target_language(&self) -> &str {
         "rust"
     }
+
+    fn supported_options(&self) -> &[&str] {
+        &["superClass", "caseInsensitive"]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{Alternative, Element, Grammar, Rule};
+    use crate::types::GrammarType;
+
+    fn lexer_grammar() -> Grammar {
+        let mut grammar = Grammar::new("Calc".to_string(), GrammarType::Combined);
+        grammar.add_rule(Rule::parser_rule("expr".to_string()));
+
+        let mut number = Rule::lexer_rule("PLUS".to_string());
+        let mut alt = Alternative::new();
+        alt.add_element(Element::string_literal("+".to_string()));
+        number.add_alternative(alt);
+        grammar.add_rule(number);
+
+        grammar
+    }
+
+    #[test]
+    fn test_lexer_peek_token_restores_position() {
+        let generator = RustCodeGenerator::new();
+        let code = generator
+            .generate(&lexer_grammar(), &CodeGenConfig::default())
+            .unwrap();
+
+        let start = code.find("pub fn peek_token(&mut self)").unwrap();
+        let body = &code[start..start + code[start..].find("\n    }\n").unwrap()];
+        let save = body.find("let saved_pos = self.position;").unwrap();
+        let next = body.find("self.next_token()").unwrap();
+        let restore = body.find("self.position = saved_pos;").unwrap();
+        assert!(save < next && next < restore);
+    }
+
+    #[test]
+    fn test_lexer_peek_n() {
+        let generator = RustCodeGenerator::new();
+        let code = generator
+            .generate(&lexer_grammar(), &CodeGenConfig::default())
+            .unwrap();
+
+        assert!(code.contains("pub fn peek_n(&mut self, n: usize) -> Result<Vec<Token>, ParseError>"));
+        let start = code.find("pub fn peek_n").unwrap();
+        assert!(code[start..].contains("self.position = saved_pos;"));
+    }
+
+    #[test]
+    fn test_module_visibility() {
+        let config = CodeGenConfig {
+            visibility: Visibility::Module,
+            ..CodeGenConfig::default()
+        };
+        let code = RustCodeGenerator::new().generate(&lexer_grammar(), &config).unwrap();
+
+        assert!(code.starts_with("// Generated parser for Calc grammar\n"));
+        assert!(!code.contains("//!"));
+        assert!(code.contains("\nstruct CalcLexer {"));
+        assert!(code.contains("\nenum TokenKind {"));
+        assert!(code.contains("\ntrait Listener {"));
+        assert!(!code.contains("\npub "));
+        // Methods stay `pub`, limited by their type
+        assert!(code.contains("    pub fn tokenize_all(&mut self)"));
+    }
 }
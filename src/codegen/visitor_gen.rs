@@ -1,53 +1,173 @@
 //! Visitor pattern code generation.
 
-use crate::ast::Grammar;
+use super::common::format_identifier;
+use crate::ast::{Grammar, Rule};
+use std::collections::HashMap;
 
-/// Generate visitor trait for grammar.
-pub fn generate_visitor(grammar: &Grammar) -> String {
+/// Generate visitor trait for grammar, declared with visibility `vis`
+/// (e.g. `"pub "`).
+pub fn generate_visitor(grammar: &Grammar, vis: &str) -> String {
     let mut code = String::new();
 
     code.push_str("/// Visitor trait for traversing the AST.\n");
-    code.push_str("pub trait Visitor<T> {\n");
+    code.push_str(&format!("{}trait Visitor<T> {{\n", vis));
 
     for rule in grammar.parser_rules() {
-        let method_name = format!("visit_{}", rule.name.to_lowercase());
-        let type_name = to_pascal_case(&rule.name);
-        code.push_str(&format!(
-            "    fn {}(&mut self, node: &{}) -> T;\n",
-            method_name, type_name
-        ));
+        for (method_suffix, type_name, _) in visit_targets(rule) {
+            code.push_str(&format!(
+                "    fn visit_{}(&mut self, node: &{}) -> T;\n",
+                method_suffix, type_name
+            ));
+        }
     }
+    code.push_str(&generate_visit_dispatch(grammar));
 
     code.push_str("}\n\n");
     code
 }
 
-/// Generate listener trait for grammar.
-pub fn generate_listener(grammar: &Grammar) -> String {
+/// Generate the visitor's provided `visit` method, calling the method for
+/// a node's rule or for the `#Label` of the alternative it matched.
+fn generate_visit_dispatch(grammar: &Grammar) -> String {
+    let mut code = String::new();
+    let rules: Vec<&Rule> = grammar.parser_rules().collect();
+    if rules.is_empty() {
+        return code;
+    }
+
+    code.push('\n');
+    code.push_str("    /// Visit `node` with the method for its rule, or for the `#Label` of\n");
+    code.push_str("    /// the alternative it was parsed from. A node of a rule whose every\n");
+    code.push_str("    /// alternative is labeled only lacks a label when it was recovered\n");
+    code.push_str("    /// from an error, and goes to the method of the rule's first label.\n");
+    code.push_str("    fn visit(&mut self, node: &AstNode) -> T {\n");
+    code.push_str("        match node {\n");
+    for rule in rules {
+        let variant = to_pascal_case(&rule.name);
+        let Some((enum_name, labels)) = label_enum(rule) else {
+            code.push_str(&format!(
+                "            AstNode::{}(node) => self.visit_{}(node),\n",
+                variant,
+                rule.name.to_lowercase()
+            ));
+            continue;
+        };
+        code.push_str(&format!("            AstNode::{}(node) => match node.label {{\n", variant));
+        for label in &labels {
+            code.push_str(&format!(
+                "                Some({}::{}) => self.visit_{}(node),\n",
+                enum_name,
+                label_variant(label),
+                format_identifier(label, "rust")
+            ));
+        }
+        let unlabeled = if rule.alternatives.iter().any(|alt| alt.label.is_none()) {
+            rule.name.to_lowercase()
+        } else {
+            format_identifier(&labels[0], "rust")
+        };
+        code.push_str(&format!("                None => self.visit_{}(node),\n", unlabeled));
+        code.push_str("            },\n");
+    }
+    code.push_str("        }\n");
+    code.push_str("    }\n");
+    code
+}
+
+/// The enum a node of `rule` records the `#Label` of the alternative it
+/// matched in, as its name and the distinct labels its variants stand
+/// for, in order. `None` when no alternative of the rule is labeled.
+pub fn label_enum(rule: &Rule) -> Option<(String, Vec<String>)> {
+    let mut labels: Vec<String> = Vec::new();
+    for label in rule.alternatives.iter().filter_map(|alt| alt.label.as_ref()) {
+        if !labels.iter().any(|seen| label_variant(seen) == label_variant(label)) {
+            labels.push(label.clone());
+        }
+    }
+    if labels.is_empty() {
+        return None;
+    }
+    Some((format!("{}Label", to_pascal_case(&rule.name)), labels))
+}
+
+/// Variant of a rule's label enum standing for `label`.
+pub fn label_variant(label: &str) -> String {
+    to_pascal_case(label)
+}
+
+/// Generate listener trait for grammar, declared with visibility `vis`.
+pub fn generate_listener(grammar: &Grammar, vis: &str) -> String {
     let mut code = String::new();
 
     code.push_str("/// Listener trait for AST events.\n");
-    code.push_str("pub trait Listener {\n");
+    code.push_str(&format!("{}trait Listener {{\n", vis));
 
     for rule in grammar.parser_rules() {
-        let enter_method = format!("enter_{}", rule.name.to_lowercase());
-        let exit_method = format!("exit_{}", rule.name.to_lowercase());
-        let type_name = to_pascal_case(&rule.name);
-
-        code.push_str(&format!(
-            "    fn {}(&mut self, _node: &{}) {{}}\n",
-            enter_method, type_name
-        ));
-        code.push_str(&format!(
-            "    fn {}(&mut self, _node: &{}) {{}}\n",
-            exit_method, type_name
-        ));
+        for (method_suffix, type_name, _) in visit_targets(rule) {
+            code.push_str(&format!(
+                "    fn enter_{}(&mut self, _node: &{}) {{}}\n",
+                method_suffix, type_name
+            ));
+            code.push_str(&format!(
+                "    fn exit_{}(&mut self, _node: &{}) {{}}\n",
+                method_suffix, type_name
+            ));
+        }
     }
 
     code.push_str("}\n\n");
     code
 }
 
+/// The first visitor method that two rules would both get, through their
+/// names or `#Label`s, as an error message naming them.
+pub fn visit_method_clash(grammar: &Grammar) -> Option<String> {
+    let mut owners: HashMap<String, (&str, String)> = HashMap::new();
+    for rule in grammar.parser_rules() {
+        for (method_suffix, _, source) in visit_targets(rule) {
+            match owners.get(&method_suffix) {
+                Some((owner, first)) if *owner != rule.name => {
+                    return Some(format!(
+                        "{} and {} both need visitor method visit_{}; rename one of them",
+                        first, source, method_suffix
+                    ));
+                }
+                Some(_) => {}
+                None => {
+                    owners.insert(method_suffix, (&rule.name, source));
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Method suffixes, node types and what they were named after, to generate
+/// for a rule.
+///
+/// Each distinct `#Label` on an alternative gets its own entry. The rule
+/// itself keeps an entry as long as one of its alternatives is unlabeled.
+/// All entries take the rule's generated `<Rule>Node`, which holds the
+/// fields of every alternative.
+fn visit_targets(rule: &Rule) -> Vec<(String, String, String)> {
+    let mut targets: Vec<(String, String, String)> = Vec::new();
+    let node_type = format!("{}Node", to_pascal_case(&rule.name));
+
+    if rule.alternatives.is_empty() || rule.alternatives.iter().any(|alt| alt.label.is_none()) {
+        targets.push((rule.name.to_lowercase(), node_type.clone(), format!("rule '{}'", rule.name)));
+    }
+
+    for label in rule.alternatives.iter().filter_map(|alt| alt.label.as_ref()) {
+        let method_suffix = format_identifier(label, "rust");
+        if !targets.iter().any(|(suffix, _, _)| *suffix == method_suffix) {
+            let source = format!("label '{}' of rule '{}'", label, rule.name);
+            targets.push((method_suffix, node_type.clone(), source));
+        }
+    }
+
+    targets
+}
+
 fn to_pascal_case(s: &str) -> String {
     s.split('_')
         .map(|word| {
@@ -63,7 +183,7 @@ fn to_pascal_case(s: &str) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::ast::{Grammar, Rule};
+    use crate::ast::{Alternative, Grammar, Rule};
     use crate::types::GrammarType;
 
     #[test]
@@ -72,7 +192,7 @@ mod tests {
         grammar.add_rule(Rule::parser_rule("expr".to_string()));
         grammar.add_rule(Rule::parser_rule("term".to_string()));
 
-        let code = generate_visitor(&grammar);
+        let code = generate_visitor(&grammar, "pub ");
 
         assert!(code.contains("trait Visitor"));
         assert!(code.contains("visit_expr"));
@@ -84,10 +204,52 @@ mod tests {
         let mut grammar = Grammar::new("Test".to_string(), GrammarType::Parser);
         grammar.add_rule(Rule::parser_rule("expr".to_string()));
 
-        let code = generate_listener(&grammar);
+        let code = generate_listener(&grammar, "pub ");
 
         assert!(code.contains("trait Listener"));
         assert!(code.contains("enter_expr"));
         assert!(code.contains("exit_expr"));
     }
+
+    #[test]
+    fn test_labeled_alternatives_get_own_methods() {
+        let mut grammar = Grammar::new("Test".to_string(), GrammarType::Parser);
+        let mut rule = Rule::parser_rule("expr".to_string());
+        rule.add_alternative(Alternative::new().with_label("Add".to_string()));
+        rule.add_alternative(Alternative::new().with_label("IntLit".to_string()));
+        grammar.add_rule(rule);
+
+        let visitor = generate_visitor(&grammar, "pub ");
+        assert!(visitor.contains("fn visit_add(&mut self, node: &ExprNode) -> T;"));
+        assert!(visitor.contains("fn visit_int_lit(&mut self, node: &ExprNode) -> T;"));
+        assert!(!visitor.contains("visit_expr"));
+        assert!(visitor.contains("            AstNode::Expr(node) => match node.label {\n                Some(ExprLabel::Add) => self.visit_add(node),\n                Some(ExprLabel::IntLit) => self.visit_int_lit(node),\n                None => self.visit_add(node),\n"));
+
+        let listener = generate_listener(&grammar, "pub ");
+        assert!(listener.contains("fn enter_add("));
+        assert!(listener.contains("fn exit_int_lit("));
+        assert!(!listener.contains("enter_expr"));
+    }
+
+    #[test]
+    fn test_labels_clashing_with_other_rules() {
+        let mut grammar = Grammar::new("Test".to_string(), GrammarType::Parser);
+        let mut expr = Rule::parser_rule("expr".to_string());
+        expr.add_alternative(Alternative::new().with_label("Add".to_string()));
+        expr.add_alternative(Alternative::new().with_label("Expr".to_string()));
+        expr.add_alternative(Alternative::new());
+        grammar.add_rule(expr);
+        assert_eq!(visit_method_clash(&grammar), None);
+
+        grammar.add_rule(Rule::parser_rule("add".to_string()));
+        assert_eq!(
+            visit_method_clash(&grammar).as_deref(),
+            Some("label 'Add' of rule 'expr' and rule 'add' both need visitor method visit_add; rename one of them")
+        );
+
+        let mut term = Rule::parser_rule("term".to_string());
+        term.add_alternative(Alternative::new().with_label("Add".to_string()));
+        grammar.rules[1] = term;
+        assert!(visit_method_clash(&grammar).unwrap().contains("label 'Add' of rule 'term'"));
+    }
 }
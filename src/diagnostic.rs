@@ -72,6 +72,36 @@ impl Diagnostic {
         self.code = Some(code.into());
         self
     }
+
+    /// The diagnostic followed by the source line it points at, with a `^`
+    /// under its column, as rustc and ANTLR show errors.
+    ///
+    /// Tabs before the column are repeated on the caret line, so the caret
+    /// stays under the character however wide tabs are rendered. Without a
+    /// location in `source`, this is just the diagnostic.
+    pub fn render_with_source(&self, source: &str) -> String {
+        let mut rendered = self.to_string();
+        let Some(location) = &self.location else {
+            return rendered;
+        };
+        let Some(line) = location
+            .line
+            .checked_sub(1)
+            .and_then(|index| source.lines().nth(index))
+        else {
+            return rendered;
+        };
+
+        let number = location.line.to_string();
+        let gutter = " ".repeat(number.len());
+        let padding: String = line
+            .chars()
+            .take(location.column.saturating_sub(1))
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        rendered.push_str(&format!("\n{} |\n{} | {}\n{} | {}^", gutter, number, line, gutter, padding));
+        rendered
+    }
 }
 
 impl fmt::Display for Diagnostic {
@@ -95,3 +125,36 @@ impl fmt::Display for Diagnostic {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_with_source_points_at_column() {
+        let source = "grammar Calc;\nexpr: NUMBER +;\n";
+        let diagnostic = Diagnostic::error("unexpected '+'").with_location(Location::new("Calc.g4", 2, 14));
+
+        assert_eq!(
+            diagnostic.render_with_source(source),
+            "Calc.g4:2:14: error: unexpected '+'\n  |\n2 | expr: NUMBER +;\n  |              ^"
+        );
+    }
+
+    #[test]
+    fn test_render_with_source_keeps_tabs_before_caret() {
+        let source = "grammar Calc;\n\texpr:\tNUMBER +;\n";
+        let diagnostic = Diagnostic::error("unexpected '+'").with_location(Location::new("Calc.g4", 2, 15));
+
+        assert!(diagnostic
+            .render_with_source(source)
+            .ends_with("2 | \texpr:\tNUMBER +;\n  | \t     \t       ^"));
+    }
+
+    #[test]
+    fn test_render_with_source_without_line() {
+        let diagnostic = Diagnostic::error("oops").with_location(Location::new("Calc.g4", 9, 1));
+        assert_eq!(diagnostic.render_with_source("grammar Calc;\n"), "Calc.g4:9:1: error: oops");
+        assert_eq!(Diagnostic::warning("oops").render_with_source("x"), "warning: oops");
+    }
+}
@@ -1,5 +1,6 @@
 //! Error types for minipg.
 
+use crate::diagnostic::{Diagnostic, Location};
 use thiserror::Error;
 
 /// Result type alias for minipg operations.
@@ -56,4 +57,41 @@ impl Error {
     pub fn internal(message: impl Into<String>) -> Self {
         Error::Internal(message.into())
     }
+
+    /// This error as a diagnostic. A parse error's `line:column` becomes a
+    /// location in `file`, so it can be shown with
+    /// [`Diagnostic::render_with_source`].
+    pub fn to_diagnostic(&self, file: &str) -> Diagnostic {
+        let Error::Parse { location, message } = self else {
+            return Diagnostic::error(self.to_string());
+        };
+        let position = location
+            .split_once(':')
+            .and_then(|(line, column)| Some((line.parse().ok()?, column.parse().ok()?)));
+        // Enhanced parse errors repeat their location in the message
+        let prefix = format!("Parse error at {}: ", location);
+        let message = message.strip_prefix(&prefix).unwrap_or(message);
+        match position {
+            Some((line, column)) => {
+                Diagnostic::error(message).with_location(Location::new(file, line, column))
+            }
+            None => Diagnostic::error(self.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_error_to_diagnostic() {
+        let diagnostic = Error::parse("2:14", "Parse error at 2:14: Expected ;").to_diagnostic("Calc.g4");
+        assert_eq!(diagnostic.location, Some(Location::new("Calc.g4", 2, 14)));
+        assert_eq!(diagnostic.message, "Expected ;");
+
+        let diagnostic = Error::semantic("cycle").to_diagnostic("Calc.g4");
+        assert_eq!(diagnostic.location, None);
+        assert_eq!(diagnostic.message, "Semantic error: cycle");
+    }
 }
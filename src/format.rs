@@ -0,0 +1,526 @@
+//! Grammar formatting.
+//!
+//! [`format_grammar`] writes a parsed [`Grammar`] back out as `.g4` source
+//! in one canonical layout, so formatting the result again gives the same
+//! text. Doc comments on rules are kept; other comments and anything else
+//! the parser does not keep in the AST, such as actions in parser rules,
+//! are not reproduced. [`dropped_by_format`] lists what would be lost.
+
+use crate::ast::{Alternative, Element, Grammar, LexerCommand, Rule};
+use crate::parser::{Lexer, Parser};
+use crate::types::GrammarType;
+use std::collections::HashMap;
+
+const INDENT: &str = "    ";
+
+/// Render `grammar` as canonically formatted `.g4` source.
+///
+/// The header comes first, then the `options`, `import`, `channels`,
+/// `tokens` and named action sections, then the rules in declaration order with each
+/// lexer mode's rules after a `mode` line. Options, channels and named
+/// actions are sorted by name. A rule with one alternative stays on one
+/// line; otherwise each alternative gets its own line with the `:`, the
+/// `|`s and the closing `;` aligned:
+///
+/// ```text
+/// expr
+///     : expr '*' expr
+///     | INT
+///     ;
+/// ```
+pub fn format_grammar(grammar: &Grammar) -> String {
+    let keyword = match grammar.grammar_type {
+        GrammarType::Lexer => "lexer grammar",
+        GrammarType::Parser => "parser grammar",
+        GrammarType::Combined => "grammar",
+    };
+    let mut sections = vec![format!("{} {};\n", keyword, grammar.name)];
+
+    if !grammar.options.is_empty() {
+        let mut options: Vec<_> = grammar.options.iter().collect();
+        options.sort();
+        let mut block = String::from("options {\n");
+        for (key, value) in options {
+            block.push_str(&format!("{}{} = {};\n", INDENT, key, option_value(value)));
+        }
+        block.push_str("}\n");
+        sections.push(block);
+    }
+
+    if !grammar.imports.is_empty() {
+        let imports: Vec<String> = grammar
+            .imports
+            .iter()
+            .map(|import| match grammar.imported_rules.get(import) {
+                Some(rules) => format!("{} = {{{}}}", import, rules.join(", ")),
+                None => import.clone(),
+            })
+            .collect();
+        sections.push(format!("import {};\n", imports.join(", ")));
+    }
+
+    if !grammar.channels.is_empty() {
+        let mut channels: Vec<&str> = grammar.channels.iter().map(String::as_str).collect();
+        channels.sort_unstable();
+        sections.push(format!("channels {{ {} }}\n", channels.join(", ")));
+    }
+
+    // Declaration order numbers the tokens, so it is kept
+    if !grammar.declared_tokens.is_empty() {
+        sections.push(format!("tokens {{ {} }}\n", grammar.declared_tokens.join(", ")));
+    }
+
+    if !grammar.named_actions.is_empty() {
+        let mut actions: Vec<_> = grammar.named_actions.iter().collect();
+        actions.sort();
+        let actions: Vec<String> = actions
+            .into_iter()
+            .map(|(name, code)| format!("@{} {}\n", name, action_block(code)))
+            .collect();
+        sections.push(actions.concat());
+    }
+
+    let in_mode = |rule: &Rule| grammar.lexer_modes.values().any(|rules| rules.contains(&rule.name));
+    for rule in grammar.rules.iter().filter(|rule| !in_mode(rule)) {
+        sections.push(format_rule(rule));
+    }
+
+    // Modes in the order their first rule is declared; empty ones last
+    let position = |rules: &Vec<String>| {
+        rules
+            .iter()
+            .filter_map(|name| grammar.rules.iter().position(|rule| &rule.name == name))
+            .min()
+            .unwrap_or(usize::MAX)
+    };
+    let mut modes: Vec<_> = grammar.lexer_modes.iter().collect();
+    modes.sort_by(|(a, a_rules), (b, b_rules)| (position(a_rules), a).cmp(&(position(b_rules), b)));
+    for (mode, rule_names) in modes {
+        sections.push(format!("mode {};\n", mode));
+        for rule in grammar.rules.iter().filter(|rule| rule_names.contains(&rule.name)) {
+            sections.push(format_rule(rule));
+        }
+    }
+
+    sections.join("\n")
+}
+
+/// Parts of `source` missing from `formatted`, its formatted text, each
+/// described with the line it starts on: comments other than the rule doc
+/// comments the formatter writes back, and actions in parser rules, which
+/// the parsed grammar does not keep.
+///
+/// Comments are matched by their words, so a doc comment rewrapped by the
+/// formatter or a comment inside a reindented action still counts as kept.
+pub fn dropped_by_format(source: &str, formatted: &str) -> Vec<String> {
+    let read = |text: &str| {
+        let mut parser = Parser::new(Lexer::new(text, "<format>"));
+        let _ = parser.parse_grammar();
+        parser
+    };
+    let original = read(source);
+    let output = read(formatted);
+
+    let comment_words = |comment: &str| -> Vec<String> {
+        comment
+            .split_whitespace()
+            .filter(|word| !word.chars().all(|c| c == '/' || c == '*'))
+            .map(str::to_string)
+            .collect()
+    };
+    let mut kept: HashMap<Vec<String>, usize> = HashMap::new();
+    for (_, comment) in output.comments() {
+        *kept.entry(comment_words(comment)).or_default() += 1;
+    }
+
+    let mut dropped = Vec::new();
+    for (line, comment) in original.comments() {
+        match kept.get_mut(&comment_words(comment)) {
+            Some(count) if *count > 0 => *count -= 1,
+            _ => dropped.push((*line, format!("comment `{}`", first_line(comment)))),
+        }
+    }
+    for (line, code) in original.discarded_actions() {
+        dropped.push((*line, format!("action `{}`", first_line(code))));
+    }
+    dropped.sort();
+    dropped
+        .into_iter()
+        .map(|(line, what)| format!("line {}: {}", line, what))
+        .collect()
+}
+
+/// The first line of `text`, with `...` when more follow.
+fn first_line(text: &str) -> String {
+    let text = text.trim();
+    match text.split_once('\n') {
+        Some((first, _)) => format!("{} ...", first.trim_end()),
+        None => text.to_string(),
+    }
+}
+
+fn format_rule(rule: &Rule) -> String {
+    let mut header = String::new();
+    match rule.doc.as_deref().map(|doc| doc.lines().collect::<Vec<_>>()) {
+        Some(lines) if lines.len() == 1 => header.push_str(&format!("/** {} */\n", lines[0])),
+        Some(lines) => {
+            header.push_str("/**\n");
+            for line in lines {
+                header.push_str(format!(" * {}", line).trim_end());
+                header.push('\n');
+            }
+            header.push_str(" */\n");
+        }
+        None => {}
+    }
+    if rule.is_fragment {
+        header.push_str("fragment ");
+    }
+    header.push_str(&rule.name);
+    if !rule.arguments.is_empty() {
+        let arguments: Vec<String> = rule
+            .arguments
+            .iter()
+            .map(|arg| typed_name(arg.arg_type.as_deref(), &arg.name))
+            .collect();
+        header.push_str(&format!("[{}]", arguments.join(", ")));
+    }
+    if !rule.returns.is_empty() {
+        let returns: Vec<String> = rule
+            .returns
+            .iter()
+            .map(|ret| typed_name(ret.return_type.as_deref(), &ret.name))
+            .collect();
+        header.push_str(&format!(" returns [{}]", returns.join(", ")));
+    }
+    if !rule.locals.is_empty() {
+        let locals: Vec<String> = rule
+            .locals
+            .iter()
+            .map(|local| typed_name(local.local_type.as_deref(), &local.name))
+            .collect();
+        header.push_str(&format!(" locals [{}]", locals.join(", ")));
+    }
+    if !rule.options.is_empty() {
+        let mut options: Vec<_> = rule.options.iter().collect();
+        options.sort();
+        let options: Vec<String> = options
+            .into_iter()
+            .map(|(key, value)| format!("{} = {};", key, option_value(value)))
+            .collect();
+        header.push_str(&format!(" options {{ {} }}", options.join(" ")));
+    }
+
+    let alternatives: Vec<String> = rule.alternatives.iter().map(format_alternative).collect();
+    let mut text = match alternatives.as_slice() {
+        [alternative] => format!("{}: {};\n", header, alternative),
+        _ => {
+            let mut text = format!("{}\n", header);
+            for (i, alternative) in alternatives.iter().enumerate() {
+                let separator = if i == 0 { ':' } else { '|' };
+                text.push_str(format!("{}{} {}", INDENT, separator, alternative).trim_end());
+                text.push('\n');
+            }
+            text.push_str(&format!("{};\n", INDENT));
+            text
+        }
+    };
+
+    for clause in &rule.catch_clauses {
+        text.push_str(&format!("catch [{}] {}\n", clause.exception, action_block(&clause.action)));
+    }
+    if let Some(action) = &rule.finally_clause {
+        text.push_str(&format!("finally {}\n", action_block(action)));
+    }
+    text
+}
+
+fn typed_name(type_name: Option<&str>, name: &str) -> String {
+    match type_name {
+        Some(type_name) => format!("{} {}", type_name, name),
+        None => name.to_string(),
+    }
+}
+
+fn format_alternative(alternative: &Alternative) -> String {
+    let mut parts = Vec::new();
+    if !alternative.options.is_empty() {
+        parts.push(angle_options(&alternative.options));
+    }
+    parts.extend(alternative.elements.iter().map(format_element));
+    if let Some(label) = &alternative.label {
+        parts.push(format!("#{}", label));
+    }
+    if !alternative.lexer_commands.is_empty() {
+        let commands: Vec<String> = alternative.lexer_commands.iter().map(LexerCommand::to_string).collect();
+        parts.push(format!("-> {}", commands.join(", ")));
+    }
+    parts.join(" ")
+}
+
+fn format_element(element: &Element) -> String {
+    match element {
+        Element::RuleRef { name: text, label, is_list, options, .. }
+        | Element::Terminal { value: text, label, is_list, options, .. } => {
+            labelled(label.as_deref(), *is_list, text, options)
+        }
+        Element::StringLiteral { value, label, is_list, options, .. } => {
+            labelled(label.as_deref(), *is_list, &string_literal(value), options)
+        }
+        Element::CharRange { start, end } => {
+            format!("{}..{}", string_literal(&start.to_string()), string_literal(&end.to_string()))
+        }
+        Element::CharClass { negated, ranges } => {
+            let set: String = ranges
+                .iter()
+                .map(|&(start, end)| {
+                    if start == end {
+                        class_char(start)
+                    } else {
+                        format!("{}-{}", class_char(start), class_char(end))
+                    }
+                })
+                .collect();
+            format!("{}[{}]", if *negated { "~" } else { "" }, set)
+        }
+        Element::UnicodeClass { negated, category } => {
+            format!("\\{}{{{}}}", if *negated { 'P' } else { 'p' }, category)
+        }
+        Element::Optional { element, greedy } => suffixed(element, '?', *greedy),
+        Element::ZeroOrMore { element, greedy } => suffixed(element, '*', *greedy),
+        Element::OneOrMore { element, greedy } => suffixed(element, '+', *greedy),
+        Element::Group { alternatives } => {
+            let alternatives: Vec<String> = alternatives.iter().map(format_alternative).collect();
+            format!("({})", alternatives.join(" | "))
+        }
+        Element::Not { element } => format!("~{}", atom(element)),
+        Element::Wildcard => ".".to_string(),
+        Element::Eof => "EOF".to_string(),
+        Element::Action { code, .. } => format!("{{{}}}", code),
+        Element::Predicate { code, options, .. } => {
+            let mut text = format!("{{{}}}?", code);
+            if !options.is_empty() {
+                text.push_str(&angle_options(options));
+            }
+            text
+        }
+    }
+}
+
+fn labelled(label: Option<&str>, is_list: bool, text: &str, options: &[(String, String)]) -> String {
+    let mut result = match label {
+        Some(label) => format!("{}{}{}", label, if is_list { "+=" } else { "=" }, text),
+        None => text.to_string(),
+    };
+    if !options.is_empty() {
+        result.push_str(&angle_options(options));
+    }
+    result
+}
+
+/// An element under a `?`, `*` or `+`, parenthesized unless it is a
+/// single atom the suffix can attach to directly.
+fn suffixed(element: &Element, suffix: char, greedy: bool) -> String {
+    format!("{}{}{}", atom(element), suffix, if greedy { "" } else { "?" })
+}
+
+fn atom(element: &Element) -> String {
+    match element {
+        Element::Optional { .. }
+        | Element::ZeroOrMore { .. }
+        | Element::OneOrMore { .. }
+        | Element::Action { .. }
+        | Element::Predicate { .. } => format!("({})", format_element(element)),
+        _ => format_element(element),
+    }
+}
+
+fn angle_options(options: &[(String, String)]) -> String {
+    let options: Vec<String> = options
+        .iter()
+        .map(|(key, value)| {
+            if value.is_empty() {
+                key.clone()
+            } else {
+                format!("{}={}", key, option_value(value))
+            }
+        })
+        .collect();
+    format!("<{}>", options.join(", "))
+}
+
+/// An option value as written: identifiers, integers and `{...}` blocks
+/// as they are, anything else quoted.
+fn option_value(value: &str) -> String {
+    let bare = value.starts_with('{')
+        || (!value.is_empty() && value.chars().all(|c| c.is_alphanumeric() || c == '_'));
+    if bare { value.to_string() } else { string_literal(value) }
+}
+
+fn string_literal(value: &str) -> String {
+    let mut text = String::from("'");
+    for c in value.chars() {
+        match c {
+            '\'' => text.push_str("\\'"),
+            _ => text.push_str(&escape_char(c)),
+        }
+    }
+    text.push('\'');
+    text
+}
+
+fn class_char(c: char) -> String {
+    match c {
+        '[' | ']' | '-' | '~' => format!("\\{}", c),
+        _ => escape_char(c),
+    }
+}
+
+fn escape_char(c: char) -> String {
+    match c {
+        '\\' => "\\\\".to_string(),
+        '\n' => "\\n".to_string(),
+        '\r' => "\\r".to_string(),
+        '\t' => "\\t".to_string(),
+        c if c.is_control() || c == '\u{feff}' => match c as u32 {
+            code @ 0..=0xffff => format!("\\u{:04X}", code),
+            code => format!("\\u{{{:X}}}", code),
+        },
+        c => c.to_string(),
+    }
+}
+
+/// A `{ ... }` block for a named action, `catch` or `finally`, with the
+/// code's lines reindented one level.
+fn action_block(code: &str) -> String {
+    let code = code.trim();
+    if code.is_empty() {
+        return "{}".to_string();
+    }
+    let margin = code
+        .lines()
+        .skip(1)
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    let mut block = String::from("{\n");
+    for (i, line) in code.lines().enumerate() {
+        let line = if i == 0 { line } else { line.get(margin..).unwrap_or(line.trim_start()) };
+        match line.trim_end() {
+            "" => block.push('\n'),
+            line => block.push_str(&format!("{}{}\n", INDENT, line)),
+        }
+    }
+    block.push('}');
+    block
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{Lexer, Parser};
+
+    fn parse(source: &str) -> Grammar {
+        Parser::new(Lexer::new(source, "test.g4")).parse_grammar().unwrap()
+    }
+
+    const MESSY: &str = r#"
+grammar   Calc ;
+options { language=rust; tokenVocab = CalcLexer ; }
+import Base, Common = {atom , number};
+channels { COMMENTS, WHITESPACE }
+@header { use std::fmt; }
+@members {
+        fn depth(&self) -> usize { 0 }
+}
+
+/**
+ * An expression.
+ *
+ *   Binds right.
+ */
+expr[int depth] returns [Value v] locals [int n]
+  :   <assoc=right> lhs=expr '^'expr   #Power
+  | expr ('*'|'/') expr #MulDiv
+  |args+=atom ( ',' args+=atom )*?
+  |
+  ;
+  catch [RecognitionException e] { recover(e); }
+  finally { cleanup(); }
+atom: {self.ok()}?<fail='not ok'> ID | '(' expr ')' | ~(ID | INT) | . | EOF ;
+/** One digit. */ /* not a doc comment */
+fragment DIGIT : [0-9] ;
+/**/ // nor these
+
+INT : DIGIT+ {count();} ;
+ID options{caseInsensitive=true;}: [a-zA-Z_\-\]] [\p{L}_]* ;
+WS : [ \t\r\n]+ -> channel(WHITESPACE) ;
+STR: '\'' ~['\\]* '\'' -> pushMode(Inside), more ;
+mode Inside;
+CLOSE : '"' -> popMode ;
+TEXT: ~["]+ -> type(STR) ;
+"#;
+
+    #[test]
+    fn test_format_grammar_layout() {
+        let formatted = format_grammar(&parse(MESSY));
+
+        assert!(formatted.starts_with("grammar Calc;\n\noptions {\n    language = rust;\n    tokenVocab = CalcLexer;\n}\n"), "{}", formatted);
+        assert!(formatted.contains("\nimport Base, Common = {atom, number};\n"), "{}", formatted);
+        assert!(formatted.contains("\nchannels { COMMENTS, WHITESPACE }\n"), "{}", formatted);
+        assert!(formatted.contains("\n@members {\n    fn depth"), "{}", formatted);
+        assert!(
+            formatted.contains(
+                "\nexpr[int depth] returns [Value v] locals [int n]\n    : <assoc=right> lhs=expr '^' expr #Power\n    | expr ('*' | '/') expr #MulDiv\n    | args+=atom (',' args+=atom)*?\n    |\n    ;\ncatch [RecognitionException e] {\n    recover(e);\n}\nfinally {\n    cleanup();\n}\n"
+            ),
+            "{}",
+            formatted
+        );
+        assert!(formatted.contains("\n    : {self.ok()}?<fail='not ok'> ID\n"), "{}", formatted);
+        assert!(formatted.contains("\n    | ~(ID | INT)\n    | .\n    | EOF\n"), "{}", formatted);
+        assert!(
+            formatted.contains("\n/**\n * An expression.\n *\n *   Binds right.\n */\nexpr[int depth]"),
+            "{}",
+            formatted
+        );
+        assert!(formatted.contains("\n/** One digit. */\nfragment DIGIT: [0-9];\n\nINT: DIGIT+ {count();};\n"), "{}", formatted);
+        assert!(formatted.contains("\nID options { caseInsensitive = true; }: [a-zA-Z_\\-\\]] (\\p{L} | [_])*;\n"), "{}", formatted);
+        assert!(formatted.contains("\nWS: [ \\t\\r\\n]+ -> channel(WHITESPACE);\n"), "{}", formatted);
+        assert!(formatted.contains("\nSTR: '\\'' ~['\\\\]* '\\'' -> pushMode(Inside), more;\n"), "{}", formatted);
+        assert!(formatted.ends_with("\nmode Inside;\n\nCLOSE: '\"' -> popMode;\n\nTEXT: ~[\"]+ -> type(STR);\n"), "{}", formatted);
+    }
+
+    #[test]
+    fn test_format_grammar_round_trip_is_stable() {
+        let once = format_grammar(&parse(MESSY));
+        let twice = format_grammar(&parse(&once));
+        assert_eq!(once, twice);
+
+        let reparsed = parse(&once);
+        let original = parse(MESSY);
+        assert_eq!(reparsed.rules.len(), original.rules.len());
+        assert_eq!(reparsed.lexer_modes, original.lexer_modes);
+        assert_eq!(reparsed.named_actions, original.named_actions);
+        assert_eq!(reparsed.get_rule("expr").unwrap().doc, original.get_rule("expr").unwrap().doc);
+    }
+
+    #[test]
+    fn test_dropped_by_format_lists_plain_comments_and_parser_actions() {
+        let source = format!("{}\nlist: ID {{ add(); }}* ID;\n", MESSY);
+        let formatted = format_grammar(&parse(&source));
+        let dropped = dropped_by_format(&source, &formatted);
+
+        assert_eq!(
+            dropped,
+            vec![
+                "line 25: comment `/* not a doc comment */`",
+                "line 27: comment `/**/`",
+                "line 27: comment `// nor these`",
+                "line 37: action `add();`",
+            ]
+        );
+        // Doc comments and actions the formatter writes back are not listed
+        assert!(dropped_by_format(&formatted, &format_grammar(&parse(&formatted))).is_empty());
+    }
+}
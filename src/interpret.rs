@@ -0,0 +1,406 @@
+//! Running a grammar on sample input without generating a parser.
+//!
+//! [`parse_input`] lexes the input with the automata the generated Rust
+//! lexer is built from, then parses the tokens by walking the grammar's
+//! rules directly. Parsing follows the generated parsers: alternatives are
+//! tried in order and the first one that matches wins, and `?`, `*` and
+//! `+` match as much as they can. Directly left-recursive rules are
+//! rewritten into loops first, as for precedence climbing.
+//!
+//! Embedded actions are not run and semantic predicates are taken to hold,
+//! since both are code in the target language.
+
+use crate::analysis::left_recursion::eliminate_direct;
+use crate::ast::{unicode_property, Alternative, Element, Grammar, LexerCommand, Rule};
+use crate::codegen::common::rule_commands;
+use crate::codegen::dfa::{
+    case_insensitive_literals, longest_match, rules_by_mode, skipped_whitespace, token_rules,
+    DfaBuilder,
+};
+use crate::diagnostic::{Diagnostic, Location};
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+/// The file name diagnostics about the input are reported against.
+pub const INPUT_NAME: &str = "<input>";
+
+/// Kind of the token ending the input, as named in grammars.
+const EOF: &str = "EOF";
+
+/// Check whether `input` is a `start_rule` of `grammar`.
+///
+/// Returns every character the lexer could not match, or else the point
+/// furthest into the input that parsing reached, with the tokens that
+/// would have let it go on. Locations are 1-based and reported against
+/// [`INPUT_NAME`]. The whole input must match: `start_rule` is followed
+/// by the end of input whether or not the rule ends in `EOF`.
+pub fn parse_input(grammar: &Grammar, input: &str, start_rule: &str) -> Result<(), Vec<Diagnostic>> {
+    if !grammar.get_rule(start_rule).is_some_and(Rule::is_parser_rule) {
+        return Err(vec![Diagnostic::error(format!(
+            "grammar {} has no parser rule '{}'",
+            grammar.name, start_rule
+        ))]);
+    }
+
+    let tokens = tokenize(grammar, input)?;
+
+    let mut grammar = grammar.clone();
+    eliminate_direct(&mut grammar);
+    let mut interpreter = Interpreter::new(&grammar, &tokens);
+    match interpreter.rule(start_rule, 0) {
+        Some(end) if tokens[end].kind == EOF => return Ok(()),
+        Some(end) => interpreter.expect(end, "<EOF>", false),
+        None => None,
+    };
+    Err(vec![interpreter.error(input)])
+}
+
+/// A token of the input, with the byte offset it starts at.
+#[derive(Debug)]
+struct Token {
+    kind: String,
+    text: String,
+    start: usize,
+}
+
+/// The tokens of `input` on the default channel, ending with `EOF`.
+///
+/// Lexer commands are applied as the generated lexer applies them, except
+/// that `more` is honoured: the next token then starts where this one did.
+fn tokenize(grammar: &Grammar, input: &str) -> Result<Vec<Token>, Vec<Diagnostic>> {
+    let rules = token_rules(grammar);
+    let rule_refs: Vec<&Rule> = rules.iter().collect();
+    let modes: Vec<_> = rules_by_mode(grammar, &rule_refs)
+        .into_iter()
+        .map(|(name, rules)| (name, DfaBuilder::new().build_from_rules(&rules)))
+        .collect();
+    let skipped = skipped_whitespace(grammar, &rules);
+    let commands: HashMap<&str, &[LexerCommand]> = rules
+        .iter()
+        .map(|rule| (rule.name.as_str(), rule_commands(rule)))
+        .collect();
+    let mode_index = |name: &str| modes.iter().position(|(mode, _)| *mode == name);
+
+    let mut tokens = Vec::new();
+    let mut errors = Vec::new();
+    let mut mode_stack = vec![0];
+    let mut position = 0;
+    let mut pending_start = None;
+
+    loop {
+        let mode = *mode_stack.last().expect("the default mode is never popped");
+        // Other modes match their own whitespace, as in the generated lexer
+        if mode == 0 && pending_start.is_none() {
+            let rest = &input[position..];
+            position += rest.len() - rest.trim_start_matches(skipped.as_slice()).len();
+        }
+        if position >= input.len() {
+            break;
+        }
+
+        let rest = &input[position..];
+        let Some((chars, name)) = longest_match(&modes[mode].1, rest) else {
+            let ch = rest.chars().next().expect("position is inside the input");
+            errors.push(
+                Diagnostic::error(format!("unexpected character {:?}", ch))
+                    .with_location(locate(input, position)),
+            );
+            position += ch.len_utf8();
+            pending_start = None;
+            continue;
+        };
+
+        let start = pending_start.take().unwrap_or(position);
+        position += rest.chars().take(chars).map(char::len_utf8).sum::<usize>();
+        let mut kind = name.to_string();
+        let mut kept = true;
+        for command in commands.get(name).copied().unwrap_or_default() {
+            match command {
+                LexerCommand::Skip => kept = false,
+                LexerCommand::Channel(channel) => kept &= channel == "DEFAULT_TOKEN_CHANNEL",
+                LexerCommand::Type(name) => kind = name.clone(),
+                LexerCommand::Mode(name) => {
+                    if let Some(index) = mode_index(name) {
+                        *mode_stack.last_mut().expect("the mode stack is never empty") = index;
+                    }
+                }
+                LexerCommand::PushMode(name) => mode_stack.extend(mode_index(name)),
+                LexerCommand::PopMode => {
+                    if mode_stack.len() > 1 {
+                        mode_stack.pop();
+                    }
+                }
+                LexerCommand::More => pending_start = Some(start),
+            }
+        }
+        if kept && pending_start.is_none() {
+            tokens.push(Token { kind, text: input[start..position].to_string(), start });
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    tokens.push(Token { kind: EOF.to_string(), text: String::new(), start: input.len() });
+    Ok(tokens)
+}
+
+/// Where byte `offset` of `input` is, with lines and columns counted from 1
+/// and columns counted in characters.
+fn locate(input: &str, offset: usize) -> Location {
+    let before = &input[..offset];
+    let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+    Location::new(
+        INPUT_NAME,
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    )
+}
+
+/// Backtracking recursive descent over a grammar's parser rules.
+struct Interpreter<'g> {
+    grammar: &'g Grammar,
+    tokens: &'g [Token],
+    case_insensitive: HashSet<String>,
+    /// Results of rules already tried at a token position
+    memo: HashMap<(&'g str, usize), Option<usize>>,
+    /// Rules being tried at a token position, to stop left recursion
+    active: HashSet<(&'g str, usize)>,
+    /// The furthest token position a match failed at
+    furthest: usize,
+    /// What would have matched at `furthest`
+    expected: BTreeSet<String>,
+}
+
+impl<'g> Interpreter<'g> {
+    fn new(grammar: &'g Grammar, tokens: &'g [Token]) -> Self {
+        Self {
+            grammar,
+            tokens,
+            case_insensitive: case_insensitive_literals(grammar),
+            memo: HashMap::new(),
+            active: HashSet::new(),
+            furthest: 0,
+            expected: BTreeSet::new(),
+        }
+    }
+
+    /// Match rule `name` at token `pos`, returning the position after it.
+    fn rule(&mut self, name: &'g str, pos: usize) -> Option<usize> {
+        let rule = self.grammar.get_rule(name)?;
+        if rule.is_lexer_rule() {
+            return self.expect(pos, name, self.tokens[pos].kind == name);
+        }
+        if let Some(&result) = self.memo.get(&(name, pos)) {
+            return result;
+        }
+        // Indirect left recursion: give up on this path rather than loop
+        if !self.active.insert((name, pos)) {
+            return None;
+        }
+        let result = self.alternatives(&rule.alternatives, pos);
+        self.active.remove(&(name, pos));
+        self.memo.insert((name, pos), result);
+        result
+    }
+
+    fn alternatives(&mut self, alternatives: &'g [Alternative], pos: usize) -> Option<usize> {
+        alternatives
+            .iter()
+            .find_map(|alt| alt.elements.iter().try_fold(pos, |pos, element| self.element(element, pos)))
+    }
+
+    fn element(&mut self, element: &'g Element, pos: usize) -> Option<usize> {
+        match element {
+            Element::RuleRef { name, .. } => self.rule(name, pos),
+            Element::Optional { element, .. } => Some(self.element(element, pos).unwrap_or(pos)),
+            Element::ZeroOrMore { element, .. } => Some(self.repeat(element, pos)),
+            Element::OneOrMore { element, .. } => {
+                let first = self.element(element, pos)?;
+                Some(self.repeat(element, first))
+            }
+            Element::Group { alternatives } => self.alternatives(alternatives, pos),
+            Element::Action { .. } | Element::Predicate { .. } => Some(pos),
+            _ => {
+                let matched = token_matches(element, &self.tokens[pos], &self.case_insensitive);
+                self.expect(pos, &describe(element), matched)
+            }
+        }
+    }
+
+    /// Match `element` as many times as it matches and makes progress.
+    fn repeat(&mut self, element: &'g Element, mut pos: usize) -> usize {
+        while let Some(next) = self.element(element, pos).filter(|&next| next > pos) {
+            pos = next;
+        }
+        pos
+    }
+
+    /// Consume the token at `pos` if `matched`, or else note that
+    /// `description` was expected there.
+    fn expect(&mut self, pos: usize, description: &str, matched: bool) -> Option<usize> {
+        if matched {
+            // Matching `EOF` does not move past it, so it can match again
+            return Some(if self.tokens[pos].kind == EOF { pos } else { pos + 1 });
+        }
+        if pos > self.furthest {
+            self.furthest = pos;
+            self.expected.clear();
+        }
+        if pos == self.furthest {
+            self.expected.insert(description.to_string());
+        }
+        None
+    }
+
+    /// The error at the furthest point parsing reached.
+    fn error(&self, input: &str) -> Diagnostic {
+        let token = &self.tokens[self.furthest];
+        let found = if token.kind == EOF {
+            "unexpected end of input".to_string()
+        } else {
+            format!("mismatched input '{}'", token.text)
+        };
+        let expected: Vec<&str> = self.expected.iter().map(String::as_str).collect();
+        let message = match expected.as_slice() {
+            [] => found,
+            [one] => format!("{}, expected {}", found, one),
+            many => format!("{}, expected one of {}", found, many.join(", ")),
+        };
+        Diagnostic::error(message).with_location(locate(input, token.start))
+    }
+}
+
+/// Whether the single-token `element` matches `token`.
+fn token_matches(element: &Element, token: &Token, case_insensitive: &HashSet<String>) -> bool {
+    let is_eof = token.kind == EOF;
+    let single_char = || {
+        let mut chars = token.text.chars();
+        chars.next().filter(|_| chars.next().is_none())
+    };
+    match element {
+        Element::Terminal { value, .. } => token.kind == *value,
+        Element::Eof => is_eof,
+        Element::StringLiteral { value, .. } if case_insensitive.contains(value) => {
+            !is_eof && token.text.to_lowercase() == value.to_lowercase()
+        }
+        Element::StringLiteral { value, .. } => !is_eof && token.text == *value,
+        Element::Wildcard => !is_eof,
+        Element::Not { element } => !is_eof && !token_matches(element, token, case_insensitive),
+        Element::Group { alternatives } => alternatives.iter().any(|alt| match alt.elements.as_slice() {
+            [element] => token_matches(element, token, case_insensitive),
+            _ => false,
+        }),
+        Element::CharRange { start, end } => single_char().is_some_and(|c| (*start..=*end).contains(&c)),
+        Element::CharClass { negated, ranges } => single_char().is_some_and(|c| {
+            ranges.iter().any(|(start, end)| (*start..=*end).contains(&c)) != *negated
+        }),
+        Element::UnicodeClass { negated, category } => single_char().is_some_and(|c| {
+            unicode_property(category).is_some_and(|(_, test)| test(c) != *negated)
+        }),
+        _ => false,
+    }
+}
+
+/// How a single-token element is named in "expected ..." messages.
+fn describe(element: &Element) -> String {
+    match element {
+        Element::Terminal { value, .. } => value.clone(),
+        Element::StringLiteral { value, .. } => format!("'{}'", value),
+        Element::Eof => "<EOF>".to_string(),
+        Element::Wildcard => "any token".to_string(),
+        Element::Not { element } => format!("anything but {}", describe(element)),
+        Element::Group { alternatives } => {
+            let names: Vec<String> = alternatives
+                .iter()
+                .flat_map(|alt| alt.elements.iter().map(describe))
+                .collect();
+            names.join(" or ")
+        }
+        Element::CharRange { start, end } => format!("'{}'..'{}'", start, end),
+        _ => "a character".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::GrammarParser;
+    use crate::traits::GrammarParser as GrammarParserTrait;
+
+    fn parse(source: &str) -> Grammar {
+        GrammarParser::new().parse_string(source, "Test.g4").unwrap()
+    }
+
+    fn message(result: Result<(), Vec<Diagnostic>>) -> String {
+        let errors = result.unwrap_err();
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        errors[0].to_string()
+    }
+
+    const CALC: &str = "grammar Calc;\n\
+        prog: stat+ EOF;\n\
+        stat: ID '=' expr ';' | expr ';';\n\
+        expr: expr ('*' | '/') expr | expr ('+' | '-') expr | '(' expr ')' | NUMBER | ID;\n\
+        ID: [a-z]+;\n\
+        NUMBER: [0-9]+;\n\
+        COMMENT: '//' ~[\\n]* -> channel(HIDDEN);\n\
+        WS: [ \\t\\r\\n]+ -> skip;\n";
+
+    #[test]
+    fn test_accepts_left_recursive_expressions() {
+        let grammar = parse(CALC);
+        assert!(parse_input(&grammar, "x = 1 + 2 * (3 - y); // done\nx / 2;", "prog").is_ok());
+        assert!(parse_input(&grammar, "1 + 2;", "stat").is_ok());
+    }
+
+    #[test]
+    fn test_reports_furthest_failure() {
+        let grammar = parse(CALC);
+        assert_eq!(
+            message(parse_input(&grammar, "x = 1;\ny = (2 + ;", "prog")),
+            "<input>:2:10: error: mismatched input ';', expected one of '(', ID, NUMBER"
+        );
+        assert_eq!(
+            message(parse_input(&grammar, "x = 1", "prog")),
+            "<input>:1:6: error: unexpected end of input, expected one of '*', '+', '-', '/', ';'"
+        );
+        // Leftover input is an error even though `stat` does not end in EOF
+        assert_eq!(
+            message(parse_input(&grammar, "1; 2;", "stat")),
+            "<input>:1:4: error: mismatched input '2', expected <EOF>"
+        );
+    }
+
+    #[test]
+    fn test_lexer_errors_and_unknown_rule() {
+        let grammar = parse(CALC);
+        assert_eq!(
+            message(parse_input(&grammar, "x = 1;\n  é;", "prog")),
+            "<input>:2:3: error: unexpected character 'é'"
+        );
+        assert_eq!(
+            message(parse_input(&grammar, "x;", "expression")),
+            "error: grammar Calc has no parser rule 'expression'"
+        );
+    }
+
+    #[test]
+    fn test_lexer_modes_and_commands() {
+        let grammar = parse(
+            "grammar Str;\n\
+             value: STRING | KEYWORD;\n\
+             KEYWORD: 'null' | 'true' -> type(KEYWORD);\n\
+             OPEN: '\"' -> more, pushMode(IN_STRING);\n\
+             WS: ' ' -> skip;\n\
+             mode IN_STRING;\n\
+             STRING: '\"' -> popMode;\n\
+             CHAR: ~[\"] -> more;\n",
+        );
+        assert!(parse_input(&grammar, " \"a b\" ", "value").is_ok());
+        assert!(parse_input(&grammar, "true", "value").is_ok());
+        assert_eq!(
+            message(parse_input(&grammar, "\"a\" null", "value")),
+            "<input>:1:5: error: mismatched input 'null', expected <EOF>"
+        );
+    }
+}
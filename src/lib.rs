@@ -16,6 +16,8 @@ pub mod parser;
 // Analysis and code generation
 pub mod analysis;
 pub mod codegen;
+pub mod format;
+pub mod interpret;
 
 // CLI (only for binary)
 #[cfg(feature = "cli")]
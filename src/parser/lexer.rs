@@ -17,7 +17,6 @@ pub enum LexerMode {
 /// Lexer for tokenizing grammar files.
 pub struct Lexer {
     source: Vec<char>,
-    #[allow(dead_code)]
     filename: String,
     position: usize,
     line: usize,
@@ -27,6 +26,12 @@ pub struct Lexer {
     last_token_kind: Option<TokenKind>,
     /// When false, '[' will not enter CharClass mode
     pub disable_char_class_mode: bool,
+    /// When true, a `/** ... */` comment is attached to the token after it
+    /// as [`Token::doc`] instead of being discarded.
+    pub keep_doc_comments: bool,
+    doc_comment: Option<String>,
+    /// Comments skipped so far, with the line each starts on
+    comments: Vec<(usize, String)>,
 }
 
 impl Lexer {
@@ -41,9 +46,18 @@ impl Lexer {
             mode_stack: Vec::new(),
             last_token_kind: None,
             disable_char_class_mode: false,
+            keep_doc_comments: false,
+            doc_comment: None,
+            comments: Vec::new(),
         }
     }
 
+    /// Comments skipped so far, doc comments included, with the line each
+    /// starts on.
+    pub fn comments(&self) -> &[(usize, String)] {
+        &self.comments
+    }
+
     pub fn push_mode(&mut self, mode: LexerMode) {
         self.mode_stack.push(self.mode);
         self.mode = mode;
@@ -59,15 +73,57 @@ impl Lexer {
         self.mode
     }
 
+    pub fn filename(&self) -> &str {
+        &self.filename
+    }
+
+    /// Raw source text strictly between two single-character tokens.
+    ///
+    /// Used for action and predicate bodies, where the original spelling
+    /// (quotes, spacing, comments) must survive into generated code.
+    pub fn text_between(&self, open: &Token, close: &Token) -> String {
+        let start = self.offset_of(open.line, open.column) + 1;
+        let end = self.offset_of(close.line, close.column);
+        if start >= end {
+            return String::new();
+        }
+        self.source[start..end].iter().collect()
+    }
+
+    fn offset_of(&self, line: usize, column: usize) -> usize {
+        let mut current_line = 1;
+        let mut current_column = 1;
+        for (offset, &ch) in self.source.iter().enumerate() {
+            if current_line == line && current_column == column {
+                return offset;
+            }
+            if ch == '\n' {
+                current_line += 1;
+                current_column = 1;
+            } else {
+                current_column += 1;
+            }
+        }
+        self.source.len()
+    }
+
     pub fn next_token(&mut self) -> Token {
+        let mut token = self.lex_token();
+        token.doc = self.doc_comment.take();
+        token
+    }
+
+    fn lex_token(&mut self) -> Token {
         // In CharClass mode, don't skip comments - treat / as a regular character
         if self.mode != LexerMode::CharClass {
             self.skip_whitespace_and_comments();
-        } else {
-            // In CharClass mode, only skip spaces and tabs, not newlines
-            while self.current_char() == ' ' || self.current_char() == '\t' {
-                self.advance();
-            }
+        } else if self.current_char() == ' ' || self.current_char() == '\t' {
+            // In CharClass mode, spaces and tabs are members of the set
+            let (line, column) = (self.line, self.column);
+            let text = self.current_char().to_string();
+            self.advance();
+            self.last_token_kind = Some(TokenKind::Identifier);
+            return Token::new(TokenKind::Identifier, text, line, column);
         }
 
         if self.is_at_end() {
@@ -146,6 +202,10 @@ impl Lexer {
                 self.advance();
                 Token::new(TokenKind::At, "@".to_string(), start_line, start_column)
             }
+            '#' if self.mode != LexerMode::CharClass => {
+                self.advance();
+                Token::new(TokenKind::Hash, "#".to_string(), start_line, start_column)
+            }
             '(' => {
                 self.advance();
                 Token::new(
@@ -216,7 +276,12 @@ impl Lexer {
             }
             '=' => {
                 self.advance();
-                Token::new(TokenKind::Equals, "=".to_string(), start_line, start_column)
+                if self.mode != LexerMode::CharClass && self.current_char() == '>' {
+                    self.advance();
+                    Token::new(TokenKind::FatArrow, "=>".to_string(), start_line, start_column)
+                } else {
+                    Token::new(TokenKind::Equals, "=".to_string(), start_line, start_column)
+                }
             }
             '.' => {
                 self.advance();
@@ -391,6 +456,32 @@ impl Lexer {
                     self.advance();
                 }
             }
+        } else if escape_char == 'p' || escape_char == 'P' {
+            // Unicode property: \p{Name}, or \P{Name} for its complement
+            if self.is_at_end() || self.current_char() != '{' {
+                return Token::error(
+                    "invalid unicode property escape: expected '{' after \\p".to_string(),
+                    start_line,
+                    start_column,
+                );
+            }
+            text.push('{');
+            self.advance();
+            while !self.is_at_end()
+                && (self.current_char().is_ascii_alphanumeric() || self.current_char() == '_')
+            {
+                text.push(self.current_char());
+                self.advance();
+            }
+            if self.is_at_end() || self.current_char() != '}' {
+                return Token::error(
+                    "unclosed unicode property escape".to_string(),
+                    start_line,
+                    start_column,
+                );
+            }
+            text.push('}');
+            self.advance();
         } else if escape_char == 'x' {
             // Hex escape: \xXX or \xXXXX (1-4 hex digits)
             let mut digit_count = 0;
@@ -470,8 +561,10 @@ impl Lexer {
             if self.current_char() == '\\' {
                 self.advance();
                 if !self.is_at_end() {
-                    text.push(self.current_char());
-                    self.advance();
+                    match self.lex_literal_escape() {
+                        Ok(ch) => text.push(ch),
+                        Err(message) => return Token::error(message, start_line, start_column),
+                    }
                 }
             } else {
                 text.push(self.current_char());
@@ -502,8 +595,10 @@ impl Lexer {
             if self.current_char() == '\\' {
                 self.advance();
                 if !self.is_at_end() {
-                    text.push(self.current_char());
-                    self.advance();
+                    match self.lex_literal_escape() {
+                        Ok(ch) => text.push(ch),
+                        Err(message) => return Token::error(message, start_line, start_column),
+                    }
                 }
             } else {
                 text.push(self.current_char());
@@ -525,6 +620,48 @@ impl Lexer {
         Token::new(TokenKind::StringLiteral, text, start_line, start_column)
     }
 
+    /// Decode the escape sequence after a `\` in a quoted literal: `\n`,
+    /// `\r`, `\t`, `\b`, `\f`, `\uXXXX` and `\u{X...}` stand for the
+    /// character they name, and any other escaped character for itself.
+    fn lex_literal_escape(&mut self) -> Result<char, String> {
+        let escape_char = self.current_char();
+        self.advance();
+        let decoded = match escape_char {
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            'b' => '\u{8}',
+            'f' => '\u{c}',
+            'u' => {
+                let braced = self.current_char() == '{';
+                if braced {
+                    self.advance();
+                }
+                let mut hex = String::new();
+                while !self.is_at_end()
+                    && self.current_char().is_ascii_hexdigit()
+                    && hex.len() < if braced { 6 } else { 4 }
+                {
+                    hex.push(self.current_char());
+                    self.advance();
+                }
+                if braced {
+                    if hex.is_empty() || self.current_char() != '}' {
+                        return Err("unclosed unicode escape sequence".to_string());
+                    }
+                    self.advance();
+                } else if hex.len() != 4 {
+                    return Err("invalid unicode escape: expected 4 hex digits".to_string());
+                }
+                let code = u32::from_str_radix(&hex, 16).unwrap_or(u32::MAX);
+                char::from_u32(code)
+                    .ok_or_else(|| format!("invalid unicode code point: U+{:04X}", code))?
+            }
+            other => other,
+        };
+        Ok(decoded)
+    }
+
     fn skip_whitespace_and_comments(&mut self) {
         while !self.is_at_end() {
             match self.current_char() {
@@ -537,36 +674,92 @@ impl Lexer {
                 '/' => {
                     if self.peek_char() == '/' {
                         // Line comment
-                        while !self.is_at_end() && self.current_char() != '\n' {
-                            self.advance();
-                        }
+                        self.skip_line_comment();
                     } else if self.peek_char() == '*' {
-                        // Block comment
+                        // Block comment; `/** ... */` is a doc comment, `/**/` is not
+                        let is_doc = self.source.get(self.position + 2) == Some(&'*')
+                            && self.source.get(self.position + 3) != Some(&'/');
+                        let (line, comment_start) = (self.line, self.position);
                         self.advance(); // skip /
                         self.advance(); // skip *
+                        let start = self.position;
+                        let mut end = self.source.len();
                         while !self.is_at_end() {
                             if self.current_char() == '*' && self.peek_char() == '/' {
+                                end = self.position;
                                 self.advance(); // skip *
                                 self.advance(); // skip /
                                 break;
                             }
                             self.advance();
                         }
+                        if is_doc && self.keep_doc_comments {
+                            let body: String = self.source[start + 1..end].iter().collect();
+                            self.doc_comment = Some(doc_comment_text(&body));
+                        }
+                        let text = self.source[comment_start..self.position].iter().collect();
+                        self.comments.push((line, text));
                     } else {
                         break;
                     }
                 }
-                '#' => {
-                    // Inline comment (ANTLR4 style) - skip to end of line
-                    while !self.is_at_end() && self.current_char() != '\n' {
-                        self.advance();
-                    }
+                '#' if !self.at_alternative_label() => {
+                    // Inline comment - skip to end of line. `#Label` ending an
+                    // alternative is lexed as its label instead.
+                    self.skip_line_comment();
                 }
                 _ => break,
             }
         }
     }
 
+    /// Skip a comment running to the end of the line, recording it.
+    fn skip_line_comment(&mut self) {
+        let (line, start) = (self.line, self.position);
+        while !self.is_at_end() && self.current_char() != '\n' {
+            self.advance();
+        }
+        let text = self.source[start..self.position].iter().collect();
+        self.comments.push((line, text));
+    }
+
+    /// Whether the `#` at the current position starts an alternative label:
+    /// an identifier after it on the same line (`#Label` or `# Label`),
+    /// followed by `|`, `;` or `)` once whitespace and comments are skipped.
+    fn at_alternative_label(&self) -> bool {
+        let mut position = self.position + 1;
+        while self.source.get(position).is_some_and(|c| *c == ' ' || *c == '\t') {
+            position += 1;
+        }
+        let is_start = |c: &char| c.is_alphabetic() || *c == '_';
+        if !self.source.get(position).is_some_and(is_start) {
+            return false;
+        }
+        while self.source.get(position).is_some_and(|c| c.is_alphanumeric() || *c == '_') {
+            position += 1;
+        }
+        loop {
+            match (self.source.get(position), self.source.get(position + 1)) {
+                (Some(c), _) if c.is_whitespace() => position += 1,
+                (Some('/'), Some('/')) => {
+                    while self.source.get(position).is_some_and(|c| *c != '\n') {
+                        position += 1;
+                    }
+                }
+                (Some('/'), Some('*')) => {
+                    position += 2;
+                    while position < self.source.len()
+                        && !(self.source[position] == '*' && self.source.get(position + 1) == Some(&'/'))
+                    {
+                        position += 1;
+                    }
+                    position += 2;
+                }
+                (next, _) => return matches!(next, Some('|' | ';' | ')')),
+            }
+        }
+    }
+
     fn current_char(&self) -> char {
         if self.is_at_end() {
             '\0'
@@ -599,3 +792,18 @@ impl Lexer {
         self.position >= self.source.len()
     }
 }
+
+/// The text of a doc comment body, without the leading `*` of each line
+/// and the blank lines around it.
+fn doc_comment_text(body: &str) -> String {
+    let lines: Vec<&str> = body
+        .lines()
+        .map(|line| {
+            let line = line.trim();
+            line.strip_prefix('*').map_or(line, |rest| rest.strip_prefix(' ').unwrap_or(rest))
+        })
+        .collect();
+    let first = lines.iter().position(|line| !line.is_empty()).unwrap_or(lines.len());
+    let last = lines.iter().rposition(|line| !line.is_empty()).map_or(first, |i| i + 1);
+    lines[first..last].join("\n")
+}
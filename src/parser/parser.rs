@@ -2,24 +2,33 @@
 
 use super::lexer::Lexer;
 use super::token::{Token, TokenKind};
-use crate::ast::{Alternative, Element, Grammar, Rule};
-use crate::{types::GrammarType, Error, Result};
+use crate::ast::element::unicode_property_names;
+use crate::ast::{unicode_property, Alternative, Element, Grammar, PredicatePosition, Rule};
+use crate::{types::GrammarType, Error, Location, Result};
 
 /// Parser for grammar files.
 pub struct Parser {
     lexer: Lexer,
     current_token: Token,
     peek_token: Token,
+    /// Whether the rule being parsed is a lexer rule, whose actions are kept.
+    in_lexer_rule: bool,
+    /// Actions in parser rules, which the grammar does not keep, with the
+    /// line each starts on
+    discarded_actions: Vec<(usize, String)>,
 }
 
 impl Parser {
     pub fn new(mut lexer: Lexer) -> Self {
+        lexer.keep_doc_comments = true;
         let current_token = lexer.next_token();
         let peek_token = lexer.next_token();
         Self {
             lexer,
             current_token,
             peek_token,
+            in_lexer_rule: false,
+            discarded_actions: Vec::new(),
         }
     }
 
@@ -27,6 +36,17 @@ impl Parser {
         self.parse_grammar()
     }
 
+    /// Comments read so far; see [`Lexer::comments`].
+    pub fn comments(&self) -> &[(usize, String)] {
+        self.lexer.comments()
+    }
+
+    /// Action blocks in parser rules read so far, with the line each starts
+    /// on. They are skipped rather than kept in the grammar.
+    pub fn discarded_actions(&self) -> &[(usize, String)] {
+        &self.discarded_actions
+    }
+
     pub fn parse_grammar(&mut self) -> Result<Grammar> {
         // Support both patterns:
         // 1. grammar [lexer|parser] <name> ;
@@ -72,7 +92,7 @@ impl Parser {
                 if self.peek_token.kind == TokenKind::LeftBrace {
                     self.parse_options(&mut grammar)?;
                 } else {
-                    let rule = self.parse_rule(&mut grammar)?;
+                    let rule = self.parse_rule()?;
                     grammar.add_rule(rule);
                 }
             } else if self.current_token.kind == TokenKind::Import {
@@ -92,16 +112,16 @@ impl Parser {
             {
                 self.parse_mode(&mut grammar)?;
             } else if self.current_token.kind == TokenKind::Identifier {
-                let rule = self.parse_rule(&mut grammar)?;
+                let rule = self.parse_rule()?;
                 grammar.add_rule(rule);
             } else if self.current_token.kind == TokenKind::Fragment {
-                let rule = self.parse_fragment_rule(&mut grammar)?;
+                let rule = self.parse_fragment_rule()?;
                 grammar.add_rule(rule);
             } else if self.current_token.kind == TokenKind::Parser
                 || self.current_token.kind == TokenKind::Lexer
             {
                 // Keywords can be rule names
-                let rule = self.parse_rule(&mut grammar)?;
+                let rule = self.parse_rule()?;
                 grammar.add_rule(rule);
             } else {
                 use super::enhanced_errors::create_enhanced_error;
@@ -124,40 +144,135 @@ impl Parser {
         Ok(grammar)
     }
 
+    /// Parse a source snippet holding exactly one rule, such as the text of
+    /// a rule just edited in an editor, for use with [`Grammar::replace_rule`].
+    ///
+    /// The grammar the rule belongs to is not modified; an `options { ... }`
+    /// block on the rule is kept on the rule itself. Locations in the rule
+    /// are relative to the snippet.
+    pub fn parse_rule_fragment(&mut self, _grammar: &Grammar) -> Result<Rule> {
+        let rule = match self.current_token.kind {
+            TokenKind::Fragment => self.parse_fragment_rule()?,
+            _ => self.parse_rule()?,
+        };
+
+        if self.current_token.kind != TokenKind::Eof {
+            return Err(Error::parse(
+                format!("{}:{}", self.current_token.line, self.current_token.column),
+                format!(
+                    "Expected a single rule, found '{}' after rule '{}'",
+                    self.current_token.text, rule.name
+                ),
+            ));
+        }
+        Ok(rule)
+    }
+
     fn parse_options(&mut self, grammar: &mut Grammar) -> Result<()> {
+        for (key, value) in self.parse_options_block()? {
+            grammar.add_option(key, value);
+        }
+        Ok(())
+    }
+
+    /// Parse an `options { key = value; ... }` block into its entries.
+    fn parse_options_block(&mut self) -> Result<Vec<(String, String)>> {
         self.expect(TokenKind::Options)?;
         self.expect(TokenKind::LeftBrace)?;
 
+        let mut options = Vec::new();
         while self.current_token.kind != TokenKind::RightBrace {
             let key = self.expect_identifier()?;
             self.expect(TokenKind::Equals)?;
-            let value = self.expect_identifier()?;
+            let value = self.parse_option_value()?;
             self.expect(TokenKind::Semicolon)?;
-            grammar.add_option(key, value);
+            options.push((key, value));
         }
 
         self.expect(TokenKind::RightBrace)?;
-        Ok(())
+        Ok(options)
+    }
+
+    /// Parse an option value: an identifier, a quoted string, an integer or
+    /// an action block.
+    ///
+    /// String values are returned without their quotes; action blocks, as in
+    /// `<fail={"message"}>`, keep their braces so the code can be told apart
+    /// from plain text.
+    fn parse_option_value(&mut self) -> Result<String> {
+        if self.current_token.kind == TokenKind::LeftBrace {
+            return Ok(format!("{{{}}}", self.parse_action_block()?.trim()));
+        }
+
+        if self.current_token.kind == TokenKind::StringLiteral {
+            let value = self.current_token.text.clone();
+            self.advance();
+            return Ok(value);
+        }
+
+        if !is_digits(&self.current_token) {
+            return self.expect_identifier();
+        }
+
+        Ok(self.parse_digits())
+    }
+
+    /// Parse an integer written at the current token.
+    ///
+    /// The grammar lexer emits digits one at a time, so this joins the
+    /// adjacent ones.
+    fn parse_digits(&mut self) -> String {
+        let mut value = String::new();
+        let line = self.current_token.line;
+        let mut column = self.current_token.column;
+        while is_digits(&self.current_token)
+            && self.current_token.line == line
+            && self.current_token.column == column
+        {
+            value.push_str(&self.current_token.text);
+            column += self.current_token.text.chars().count();
+            self.advance();
+        }
+        value
     }
 
     fn parse_import(&mut self, grammar: &mut Grammar) -> Result<()> {
         self.expect(TokenKind::Import)?;
 
         // Parse first import name
-        let import_name = self.expect_identifier()?;
-        grammar.add_import(import_name);
+        self.parse_import_item(grammar)?;
 
         // Parse additional comma-separated imports
         while self.current_token.kind == TokenKind::Comma {
             self.advance(); // consume comma
-            let import_name = self.expect_identifier()?;
-            grammar.add_import(import_name);
+            self.parse_import_item(grammar)?;
         }
 
         self.expect(TokenKind::Semicolon)?;
         Ok(())
     }
 
+    /// Parse `Base` or a selective import `Base = {ruleA, ruleB}`.
+    fn parse_import_item(&mut self, grammar: &mut Grammar) -> Result<()> {
+        let import_name = self.expect_identifier()?;
+        if self.current_token.kind != TokenKind::Equals {
+            grammar.add_import(import_name);
+            return Ok(());
+        }
+
+        self.advance(); // consume =
+        self.expect(TokenKind::LeftBrace)?;
+        let mut rules = vec![self.expect_identifier()?];
+        while self.current_token.kind == TokenKind::Comma {
+            self.advance(); // consume comma
+            rules.push(self.expect_identifier()?);
+        }
+        self.expect(TokenKind::RightBrace)?;
+
+        grammar.add_selective_import(import_name, rules);
+        Ok(())
+    }
+
     fn parse_channels(&mut self, grammar: &mut Grammar) -> Result<()> {
         self.expect(TokenKind::Identifier)?; // consume "channels"
         self.expect(TokenKind::LeftBrace)?;
@@ -185,7 +300,7 @@ impl Parser {
         Ok(())
     }
 
-    fn parse_tokens(&mut self, _grammar: &mut Grammar) -> Result<()> {
+    fn parse_tokens(&mut self, grammar: &mut Grammar) -> Result<()> {
         self.expect(TokenKind::Identifier)?; // consume "tokens"
         self.expect(TokenKind::LeftBrace)?;
 
@@ -193,9 +308,10 @@ impl Parser {
             && self.current_token.kind != TokenKind::Eof
         {
             if self.current_token.kind == TokenKind::Identifier {
-                let _token_name = self.expect_identifier()?;
-                // Tokens are just identifiers, we can store them if needed
-                // For now, just skip them
+                let token_name = self.expect_identifier()?;
+                if !grammar.declared_tokens.contains(&token_name) {
+                    grammar.declared_tokens.push(token_name);
+                }
             }
 
             // Skip comma or semicolon
@@ -265,37 +381,21 @@ impl Parser {
             }
         }
 
-        self.expect(TokenKind::LeftBrace)?;
-
-        // Read the action code until we find the closing brace
-        let mut code = String::new();
-        let mut brace_count = 1;
-
-        while brace_count > 0 && self.current_token.kind != TokenKind::Eof {
-            if self.current_token.kind == TokenKind::LeftBrace {
-                brace_count += 1;
-                code.push('{');
-            } else if self.current_token.kind == TokenKind::RightBrace {
-                brace_count -= 1;
-                if brace_count > 0 {
-                    code.push('}');
-                }
-            } else {
-                code.push_str(&self.current_token.text);
-                code.push(' ');
+        // Keep the code as written; only an unclosed block gets a message
+        // naming the action
+        let opened = self.current_token.kind == TokenKind::LeftBrace;
+        let code = self.parse_action_block().map_err(|error| {
+            if !opened {
+                return error;
             }
-            self.advance();
-        }
-
-        if brace_count > 0 {
-            return Err(Error::parse(
+            Error::parse(
                 format!("{}:{}", self.current_token.line, self.current_token.column),
                 format!(
                     "Unclosed named action '{}': expected '}}' before end of file",
                     action_name
                 ),
-            ));
-        }
+            )
+        })?;
 
         grammar.add_named_action(action_name, code.trim().to_string());
         Ok(())
@@ -319,11 +419,11 @@ impl Parser {
 
             // Parse rules in this mode
             if self.current_token.kind == TokenKind::Identifier {
-                let rule = self.parse_rule(grammar)?;
+                let rule = self.parse_rule()?;
                 mode_rules.push(rule.name.clone());
                 grammar.add_rule(rule);
             } else if self.current_token.kind == TokenKind::Fragment {
-                let rule = self.parse_fragment_rule(grammar)?;
+                let rule = self.parse_fragment_rule()?;
                 mode_rules.push(rule.name.clone());
                 grammar.add_rule(rule);
             } else {
@@ -335,7 +435,9 @@ impl Parser {
         Ok(())
     }
 
-    fn parse_rule(&mut self, grammar: &mut Grammar) -> Result<Rule> {
+    fn parse_rule(&mut self) -> Result<Rule> {
+        let doc = self.current_token.doc.take();
+        let location;
         // Rule names can be identifiers OR keywords (like "options", "parser", "lexer")
         let name = if self.current_token.kind == TokenKind::Identifier
             || self.current_token.kind == TokenKind::Options
@@ -343,6 +445,11 @@ impl Parser {
             || self.current_token.kind == TokenKind::Lexer
         {
             let name = self.current_token.text.clone();
+            location = Location::new(
+                self.lexer.filename(),
+                self.current_token.line,
+                self.current_token.column,
+            );
             self.advance();
             name
         } else {
@@ -360,6 +467,8 @@ impl Parser {
         };
 
         let mut rule = Rule::new(name, rule_type);
+        rule.set_location(location);
+        rule.doc = doc;
 
         // Parse arguments: rule[int x, String name]
         // Check if we have arguments before disabling char class mode
@@ -411,10 +520,11 @@ impl Parser {
 
         // Check for options after rule name (before colon) - like "CURRENT options { ... }:"
         if self.current_token.kind == TokenKind::Options {
-            self.parse_options(grammar)?;
+            rule.options.extend(self.parse_options_block()?);
         }
 
         self.expect(TokenKind::Colon)?;
+        self.in_lexer_rule = rule.is_lexer_rule();
 
         // Parse alternatives
         // Empty alternatives are allowed in ANTLR4 (epsilon productions)
@@ -428,11 +538,54 @@ impl Parser {
         }
 
         self.expect(TokenKind::Semicolon)?;
+        self.parse_exception_group(&mut rule)?;
         Ok(rule)
     }
 
-    fn parse_fragment_rule(&mut self, grammar: &mut Grammar) -> Result<Rule> {
+    /// Parse the `catch [Exception e] { ... }` and `finally { ... }` clauses
+    /// that may follow a rule's semicolon.
+    ///
+    /// `catch` and `finally` are only treated as clauses when followed by
+    /// `[` and `{` respectively, so rules with those names still parse.
+    fn parse_exception_group(&mut self, rule: &mut Rule) -> Result<()> {
+        while self.current_token.kind == TokenKind::Identifier
+            && self.current_token.text == "catch"
+            && self.peek_token.kind == TokenKind::LeftBracket
+        {
+            self.advance(); // consume "catch"
+            let old_disable = self.lexer.disable_char_class_mode;
+            self.lexer.disable_char_class_mode = true;
+            let open = self.current_token.clone();
+            self.advance(); // consume [
+            while self.current_token.kind != TokenKind::RightBracket
+                && self.current_token.kind != TokenKind::Eof
+            {
+                self.advance();
+            }
+            let exception = self.lexer.text_between(&open, &self.current_token);
+            self.expect(TokenKind::RightBracket)?;
+            self.lexer.disable_char_class_mode = old_disable;
+
+            let action = self.parse_action_block()?;
+            rule.add_catch(exception.trim().to_string(), action.trim().to_string());
+        }
+
+        if self.current_token.kind == TokenKind::Identifier
+            && self.current_token.text == "finally"
+            && self.peek_token.kind == TokenKind::LeftBrace
+        {
+            self.advance(); // consume "finally"
+            let action = self.parse_action_block()?;
+            rule.finally_clause = Some(action.trim().to_string());
+        }
+
+        Ok(())
+    }
+
+    fn parse_fragment_rule(&mut self) -> Result<Rule> {
+        let doc = self.current_token.doc.take();
         self.expect(TokenKind::Fragment)?;
+        let location;
 
         // Parse rule name
         let name = if self.current_token.kind == TokenKind::Identifier
@@ -441,6 +594,11 @@ impl Parser {
             || self.current_token.kind == TokenKind::Lexer
         {
             let name = self.current_token.text.clone();
+            location = Location::new(
+                self.lexer.filename(),
+                self.current_token.line,
+                self.current_token.column,
+            );
             self.advance();
             name
         } else {
@@ -459,6 +617,8 @@ impl Parser {
 
         let mut rule = Rule::new(name, rule_type);
         rule.set_fragment(true);
+        rule.set_location(location);
+        rule.doc = doc;
 
         // Parse arguments: rule[int x, String name]
         if self.current_token.kind == TokenKind::LeftBracket {
@@ -486,10 +646,11 @@ impl Parser {
 
         // Check for options after fragment rule name (before colon)
         if self.current_token.kind == TokenKind::Options {
-            self.parse_options(grammar)?;
+            rule.options.extend(self.parse_options_block()?);
         }
 
         self.expect(TokenKind::Colon)?;
+        self.in_lexer_rule = rule.is_lexer_rule();
 
         // Parse alternatives
         let alt = self.parse_alternative()?;
@@ -662,27 +823,60 @@ impl Parser {
         let mut alt = Alternative::new();
 
         while !self.is_alternative_end() {
-            // Skip action blocks { ... } or < ... >
+            // Predicates { ... }? are kept; action blocks { ... } are kept only in
+            // lexer rules, where the Rust lexer runs them after a match
             if self.current_token.kind == TokenKind::LeftBrace {
-                self.skip_action_block()?;
-                // After an action block, there might be a quantifier (? * +)
-                // Skip it for now as it applies to the action, not an element
-                if matches!(
-                    self.current_token.kind,
-                    TokenKind::Question | TokenKind::Star | TokenKind::Plus
-                ) {
+                let line = self.current_token.line;
+                let code = self.parse_action_block()?;
+                if self.current_token.kind == TokenKind::Question {
+                    self.advance();
+                    let options = if self.is_angle_open() {
+                        self.parse_angle_options()?
+                    } else {
+                        Vec::new()
+                    };
+                    // ANTLR 3 gated predicates `{p}? =>` stand before the
+                    // elements they gate, so they are leading like any other
+                    if self.current_token.kind == TokenKind::FatArrow {
+                        self.advance();
+                    }
+                    let position = if alt
+                        .elements
+                        .iter()
+                        .all(|e| matches!(e, Element::Predicate { .. } | Element::Action { .. }))
+                    {
+                        PredicatePosition::Leading
+                    } else {
+                        PredicatePosition::Trailing
+                    };
+                    alt.add_element(Element::Predicate {
+                        code: code.trim().to_string(),
+                        language: None,
+                        options,
+                        position,
+                    });
+                    continue;
+                }
+                // A quantifier after an action applies to the action, not an element
+                if matches!(self.current_token.kind, TokenKind::Star | TokenKind::Plus) {
                     self.advance();
                     // Check for non-greedy modifier
                     if self.current_token.kind == TokenKind::Question {
                         self.advance();
                     }
+                    self.discarded_actions.push((line, code));
+                } else if self.in_lexer_rule {
+                    alt.add_element(Element::action(code.trim().to_string()));
+                } else {
+                    self.discarded_actions.push((line, code));
                 }
                 continue;
             }
 
-            // Skip angle bracket actions: <assoc = right>
-            if self.current_token.kind == TokenKind::Identifier && self.current_token.text == "<" {
-                self.skip_angle_bracket_action()?;
+            // Alternative options: <assoc = right>
+            if self.is_angle_open() {
+                let options = self.parse_angle_options()?;
+                alt.options.extend(options);
                 continue;
             }
 
@@ -690,14 +884,17 @@ impl Parser {
             alt.add_element(element);
         }
 
+        // Handle alternative label: expr '+' expr #Add
+        if self.current_token.kind == TokenKind::Hash {
+            self.advance();
+            alt.label = Some(self.expect_identifier()?);
+        }
+
         // Handle lexer commands: -> skip, -> channel(HIDDEN), etc.
         // Support multiple comma-separated commands: -> skip, pushMode(StringMode)
         if self.current_token.kind == TokenKind::Arrow {
             self.advance();
 
-            // Parse first command
-            let mut first_command = None;
-
             while self.current_token.kind == TokenKind::Identifier
                 || self.current_token.kind == TokenKind::Comma
             {
@@ -717,7 +914,9 @@ impl Parser {
                         // These commands require a parameter in parentheses
                         if self.current_token.kind == TokenKind::LeftParen {
                             self.advance();
-                            let param = if self.current_token.kind == TokenKind::Identifier {
+                            let param = if is_digits(&self.current_token) {
+                                self.parse_digits()
+                            } else if self.current_token.kind == TokenKind::Identifier {
                                 let p = self.current_token.text.clone();
                                 self.advance();
                                 p
@@ -741,68 +940,87 @@ impl Parser {
                     _ => crate::ast::LexerCommand::Skip, // unknown commands default to skip
                 };
 
-                // Store the first command (AST only supports one for now)
-                if first_command.is_none() {
-                    first_command = Some(command);
-                }
+                alt.add_lexer_command(command);
 
                 // Stop if no comma follows
                 if self.current_token.kind != TokenKind::Comma {
                     break;
                 }
             }
-
-            if let Some(cmd) = first_command {
-                alt.set_lexer_command(cmd);
-            }
         }
 
         Ok(alt)
     }
 
-    fn skip_angle_bracket_action(&mut self) -> Result<()> {
-        // Skip angle bracket actions: < ... >
-        // We've already consumed the < token
-        let mut angle_count = 1;
-
-        while angle_count > 0 && self.current_token.kind != TokenKind::Eof {
-            // Check for angle brackets - they're tokenized as Identifier tokens with text "<" or ">"
-            if self.current_token.kind == TokenKind::Identifier {
-                if self.current_token.text == "<" {
-                    angle_count += 1;
-                } else if self.current_token.text == ">" {
-                    angle_count -= 1;
-                    if angle_count == 0 {
-                        self.advance();
-                        break;
-                    }
-                }
+    /// Parse an option block `<key=value, ...>`.
+    ///
+    /// A key without a value is recorded with an empty value. Anything that
+    /// does not fit the `key=value` shape is skipped up to the closing `>`.
+    fn parse_angle_options(&mut self) -> Result<Vec<(String, String)>> {
+        self.advance(); // consume <
+        let mut options = Vec::new();
+
+        while !self.is_angle_close() {
+            if self.current_token.kind != TokenKind::Identifier {
+                self.skip_to_angle_close();
+                break;
             }
+            let key = self.expect_identifier()?;
+            let value = if self.current_token.kind == TokenKind::Equals {
+                self.advance();
+                self.parse_option_value()?
+            } else {
+                String::new()
+            };
+            options.push((key, value));
 
-            // If we hit a semicolon or other statement-ending token, the angle bracket action might be malformed
-            // but we should still try to recover
-            if self.current_token.kind == TokenKind::Semicolon && angle_count > 0 {
-                // Malformed angle bracket action - try to recover by breaking
+            if self.current_token.kind == TokenKind::Comma {
+                self.advance();
+            } else if !self.is_angle_close() {
+                self.skip_to_angle_close();
                 break;
             }
+        }
 
+        if self.is_angle_close() {
             self.advance();
         }
+        Ok(options)
+    }
 
-        // Don't error if angle_count > 0 - might be malformed but we tried to recover
-        Ok(())
+    fn is_angle_open(&self) -> bool {
+        self.current_token.kind == TokenKind::Identifier && self.current_token.text == "<"
     }
 
-    fn skip_action_block(&mut self) -> Result<()> {
-        // Skip action blocks: { ... }
+    fn is_angle_close(&self) -> bool {
+        self.current_token.kind == TokenKind::Identifier && self.current_token.text == ">"
+    }
+
+    /// Skip a malformed option block, stopping at `>` or at the end of the rule.
+    fn skip_to_angle_close(&mut self) {
+        while !self.is_angle_close()
+            && self.current_token.kind != TokenKind::Semicolon
+            && self.current_token.kind != TokenKind::Eof
+        {
+            self.advance();
+        }
+    }
+
+    /// Consume an action block `{ ... }` and return its raw source text.
+    fn parse_action_block(&mut self) -> Result<String> {
+        let open = self.current_token.clone();
         self.expect(TokenKind::LeftBrace)?;
         let mut brace_count = 1;
+        let mut code = String::new();
 
         while brace_count > 0 && self.current_token.kind != TokenKind::Eof {
             if self.current_token.kind == TokenKind::LeftBrace {
                 brace_count += 1;
             } else if self.current_token.kind == TokenKind::RightBrace {
                 brace_count -= 1;
+                if brace_count == 0 {
+                    code = self.lexer.text_between(&open, &self.current_token);
+                }
             }
             self.advance();
         }
@@ -814,7 +1032,7 @@ impl Parser {
             ));
         }
 
-        Ok(())
+        Ok(code)
     }
 
     fn parse_element(&mut self) -> Result<Element> {
@@ -835,10 +1053,21 @@ impl Parser {
             (None, false)
         };
 
+        let span = Location::new(
+            self.lexer.filename(),
+            self.current_token.line,
+            self.current_token.column,
+        );
         let element = match self.current_token.kind {
+            // A bare EOF matches the end of input; a labeled one stays a
+            // reference so it keeps its label
+            TokenKind::Identifier if self.current_token.text == "EOF" && label.is_none() => {
+                self.advance();
+                Element::Eof
+            }
             TokenKind::Identifier => {
                 let name = self.expect_identifier()?;
-                let mut elem = Element::rule_ref(name);
+                let mut elem = Element::rule_ref(name).with_span(span);
                 if let Some(lbl) = label {
                     elem = if is_list {
                         elem.with_list_label(lbl)
@@ -848,6 +1077,9 @@ impl Parser {
                 }
                 elem
             }
+            TokenKind::StringLiteral if is_unicode_property(&self.current_token.text) => {
+                self.parse_unicode_class()?
+            }
             TokenKind::StringLiteral => {
                 let start_value = self.current_token.text.clone();
                 self.advance();
@@ -890,7 +1122,7 @@ impl Parser {
                 }
 
                 // Regular string literal
-                let mut elem = Element::string_literal(start_value);
+                let mut elem = Element::string_literal(start_value).with_span(span);
                 if let Some(lbl) = label {
                     elem = if is_list {
                         elem.with_list_label(lbl)
@@ -965,10 +1197,16 @@ impl Parser {
             }
             TokenKind::Not => {
                 self.advance();
+                let (line, column) = (self.current_token.line, self.current_token.column);
                 let element = self.parse_element()?;
-                Element::Not {
-                    element: Box::new(element),
+                if mixes_unicode_property(&element) {
+                    return Err(Error::parse(
+                        format!("{}:{}", line, column),
+                        "negated character classes can hold a single \\p{...} property and nothing else"
+                            .to_string(),
+                    ));
                 }
+                negate(element)
             }
             _ => {
                 use super::enhanced_errors::create_enhanced_error;
@@ -990,6 +1228,18 @@ impl Parser {
             }
         };
 
+        // Element options: ID<assoc=right>, expr<prec=2>. A block after any
+        // other element is left for the alternative to pick up.
+        let element = if self.is_angle_open()
+            && matches!(
+                element,
+                Element::RuleRef { .. } | Element::Terminal { .. } | Element::StringLiteral { .. }
+            ) {
+            element.with_options(self.parse_angle_options()?)
+        } else {
+            element
+        };
+
         // Handle suffixes (?, *, +) with optional non-greedy modifier (??, *?, +?)
         let element = match self.current_token.kind {
             TokenKind::Question => {
@@ -1038,6 +1288,7 @@ impl Parser {
         };
 
         let mut ranges = Vec::new();
+        let mut properties = Vec::new();
         let mut last_char: Option<char> = None;
 
         // Parse character class contents
@@ -1045,6 +1296,11 @@ impl Parser {
             && self.current_token.kind != TokenKind::Eof
         {
             if self.current_token.kind == TokenKind::StringLiteral
+                && is_unicode_property(&self.current_token.text)
+            {
+                properties.push(self.parse_unicode_class()?);
+                last_char = None;
+            } else if self.current_token.kind == TokenKind::StringLiteral
                 || self.current_token.kind == TokenKind::CharLiteral
                 || self.current_token.kind == TokenKind::Identifier
             {
@@ -1136,6 +1392,33 @@ impl Parser {
             }
         }
 
+        // [\p{L}] is the property itself, and [\p{L}_] any of its members
+        if !properties.is_empty() {
+            if ranges.is_empty() && properties.len() == 1 {
+                let property = properties.pop().unwrap();
+                return Ok(if negated { negate(property) } else { property });
+            }
+            if negated {
+                return Err(Error::parse(
+                    format!("{}:{}", self.current_token.line, self.current_token.column),
+                    "negated character classes can hold a single \\p{...} property and nothing else"
+                        .to_string(),
+                ));
+            }
+            if !ranges.is_empty() {
+                properties.push(Element::CharClass { negated: false, ranges });
+            }
+            let alternatives = properties
+                .into_iter()
+                .map(|element| {
+                    let mut alt = Alternative::new();
+                    alt.add_element(element);
+                    alt
+                })
+                .collect();
+            return Ok(Element::Group { alternatives });
+        }
+
         // Empty character classes are valid in ANTLR4 (matches nothing)
         if ranges.is_empty() {
             return Ok(Element::CharClass {
@@ -1147,6 +1430,28 @@ impl Parser {
         Ok(Element::CharClass { negated, ranges })
     }
 
+    /// Parse a `\p{Name}` or `\P{Name}` escape into a `UnicodeClass`.
+    fn parse_unicode_class(&mut self) -> Result<Element> {
+        let text = self.current_token.text.clone();
+        let category = &text[3..text.len() - 1];
+        if unicode_property(category).is_none() {
+            return Err(Error::parse(
+                format!("{}:{}", self.current_token.line, self.current_token.column),
+                format!(
+                    "unknown Unicode property '{}' in {}; supported properties are {}",
+                    category,
+                    text,
+                    unicode_property_names().collect::<Vec<_>>().join(", ")
+                ),
+            ));
+        }
+        self.advance();
+        Ok(Element::UnicodeClass {
+            negated: text.starts_with("\\P"),
+            category: category.to_string(),
+        })
+    }
+
     fn parse_char_from_literal(&self, literal: &str) -> Result<char> {
         // Parse a character from a string literal (which might be a character literal like '0' or 'a')
         // This handles escape sequences and unicode escapes
@@ -1272,6 +1577,7 @@ impl Parser {
                 | TokenKind::RightParen
                 | TokenKind::RightBracket
                 | TokenKind::Arrow
+                | TokenKind::Hash
                 | TokenKind::Eof
         )
     }
@@ -1311,3 +1617,61 @@ impl Parser {
         self.current_token = std::mem::replace(&mut self.peek_token, self.lexer.next_token());
     }
 }
+
+fn is_digits(token: &Token) -> bool {
+    token.kind == TokenKind::Identifier
+        && !token.text.is_empty()
+        && token.text.chars().all(|c| c.is_ascii_digit())
+}
+
+/// Whether a lexed escape is a Unicode property, `\p{Name}` or `\P{Name}`.
+fn is_unicode_property(text: &str) -> bool {
+    (text.starts_with("\\p{") || text.starts_with("\\P{")) && text.ends_with('}')
+}
+
+/// Whether `element`, under its suffix, is a set mixing a Unicode property
+/// with other characters, like `[\p{L}_]`, which cannot be negated.
+fn mixes_unicode_property(element: &Element) -> bool {
+    match element {
+        Element::Optional { element, .. }
+        | Element::ZeroOrMore { element, .. }
+        | Element::OneOrMore { element, .. } => mixes_unicode_property(element),
+        Element::Group { alternatives } => alternatives.iter().any(|alt| {
+            alt.elements
+                .iter()
+                .any(|element| matches!(element, Element::UnicodeClass { .. }))
+        }),
+        _ => false,
+    }
+}
+
+/// Apply `~` to an element parsed with its suffix, so `~'x'*` negates the
+/// `'x'` rather than the whole loop. A set such as `[a-z]` is negated in
+/// place, so `~[a-z]` is a `CharClass` with `negated` set.
+fn negate(element: Element) -> Element {
+    match element {
+        Element::Optional { element, greedy } => Element::Optional {
+            element: Box::new(negate(*element)),
+            greedy,
+        },
+        Element::ZeroOrMore { element, greedy } => Element::ZeroOrMore {
+            element: Box::new(negate(*element)),
+            greedy,
+        },
+        Element::OneOrMore { element, greedy } => Element::OneOrMore {
+            element: Box::new(negate(*element)),
+            greedy,
+        },
+        Element::CharClass { negated, ranges } => Element::CharClass {
+            negated: !negated,
+            ranges,
+        },
+        Element::UnicodeClass { negated, category } => Element::UnicodeClass {
+            negated: !negated,
+            category,
+        },
+        element => Element::Not {
+            element: Box::new(element),
+        },
+    }
+}
@@ -39,7 +39,9 @@ pub enum TokenKind {
     Equals,
     PlusEquals, // += for list labels
     Arrow,
+    FatArrow, // => after an ANTLR 3 gated predicate
     At, // @ for named actions
+    Hash, // # for alternative labels
 
     // Special
     Eof,
@@ -78,7 +80,9 @@ impl fmt::Display for TokenKind {
             TokenKind::Equals => write!(f, "="),
             TokenKind::PlusEquals => write!(f, "+="),
             TokenKind::Arrow => write!(f, "->"),
+            TokenKind::FatArrow => write!(f, "=>"),
             TokenKind::At => write!(f, "@"),
+            TokenKind::Hash => write!(f, "#"),
             TokenKind::Eof => write!(f, "end of file"),
             TokenKind::Error => write!(f, "error"),
         }
@@ -92,6 +96,8 @@ pub struct Token {
     pub text: String,
     pub line: usize,
     pub column: usize,
+    /// Doc comment written just before the token, when the lexer keeps them.
+    pub doc: Option<String>,
 }
 
 impl Token {
@@ -101,6 +107,7 @@ impl Token {
             text,
             line,
             column,
+            doc: None,
         }
     }
 
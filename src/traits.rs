@@ -19,7 +19,8 @@ pub trait SemanticAnalyzer {
     type Input;
     type Output;
 
-    /// Perform semantic analysis on the input.
+    /// Perform semantic analysis on the input, returning what it found
+    /// along with the diagnostics.
     fn analyze(&self, input: &Self::Input) -> Result<Self::Output>;
 
     /// Get diagnostics from the analysis.
@@ -36,6 +37,11 @@ pub trait CodeGenerator {
 
     /// Get the target language name.
     fn target_language(&self) -> &str;
+
+    /// Grammar options this generator acts on; any others are ignored.
+    fn supported_options(&self) -> &[&str] {
+        &[]
+    }
 }
 
 /// Trait for grammar transformation.
@@ -125,6 +125,144 @@ impl SymbolTable {
     }
 }
 
+/// Shape of the generated top-level `parse` function.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ErrorMode {
+    /// `parse(input) -> Result<Tree, ParseError>`, stopping at the first error.
+    #[default]
+    FailFast,
+    /// `parse(input) -> (Option<Tree>, Vec<ParseError>)`, recovering to
+    /// report every error it can find.
+    Collect,
+}
+
+/// Parse trees built by the generated parser besides its typed AST.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum CstMode {
+    /// Only the typed AST.
+    #[default]
+    Off,
+    /// Also a concrete syntax tree of generic `RuleNode`s and
+    /// `TerminalNode`s, holding every rule invocation and matched token.
+    On,
+}
+
+/// Indentation used in generated code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum IndentStyle {
+    /// This many spaces per level.
+    Spaces(usize),
+    /// One tab per level.
+    Tabs,
+}
+
+impl std::str::FromStr for IndentStyle {
+    type Err = crate::Error;
+
+    /// Parse `tabs` or `spaces-N`, e.g. `spaces-2`.
+    fn from_str(s: &str) -> crate::Result<Self> {
+        if s == "tabs" {
+            return Ok(IndentStyle::Tabs);
+        }
+        s.strip_prefix("spaces-")
+            .and_then(|n| n.parse().ok())
+            .filter(|&n| n > 0)
+            .map(IndentStyle::Spaces)
+            .ok_or_else(|| {
+                crate::Error::InvalidArgument(format!(
+                    "invalid indent style '{}', expected 'tabs' or 'spaces-N'",
+                    s
+                ))
+            })
+    }
+}
+
+/// Where generated code puts the opening brace of a block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum BraceStyle {
+    /// `if x {` on the line that opens the block.
+    #[default]
+    SameLine,
+    /// The `{` on a line of its own, under the start of the statement.
+    NextLine,
+}
+
+impl std::str::FromStr for BraceStyle {
+    type Err = crate::Error;
+
+    /// Parse `same-line` or `next-line`.
+    fn from_str(s: &str) -> crate::Result<Self> {
+        match s {
+            "same-line" => Ok(BraceStyle::SameLine),
+            "next-line" => Ok(BraceStyle::NextLine),
+            _ => Err(crate::Error::InvalidArgument(format!(
+                "invalid brace style '{}', expected 'same-line' or 'next-line'",
+                s
+            ))),
+        }
+    }
+}
+
+/// Visibility of the items in generated code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Visibility {
+    /// Usable from any crate: `pub` in Rust, exported in JavaScript.
+    #[default]
+    Public,
+    /// Usable within the crate that includes the generated code: `pub(crate)`
+    /// in Rust. Targets without such a level treat it as `Public`.
+    Crate,
+    /// Private to the module the generated code is placed in, e.g. with
+    /// `include!`: no `pub` in Rust and no `export` in JavaScript.
+    Module,
+}
+
+impl std::str::FromStr for Visibility {
+    type Err = crate::Error;
+
+    /// Parse `public`, `crate` or `module`.
+    fn from_str(s: &str) -> crate::Result<Self> {
+        match s {
+            "public" => Ok(Visibility::Public),
+            "crate" => Ok(Visibility::Crate),
+            "module" => Ok(Visibility::Module),
+            _ => Err(crate::Error::InvalidArgument(format!(
+                "invalid visibility '{}', expected 'public', 'crate' or 'module'",
+                s
+            ))),
+        }
+    }
+}
+
+/// How generated parsers decide which alternative to take.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ParserStrategy {
+    /// A method per rule that tries alternatives in order, backtracking
+    /// when one fails.
+    #[default]
+    RecursiveDescent,
+    /// A stack machine driven by an LL(1) parse table built from the FIRST
+    /// and FOLLOW sets, for grammars that need one token of lookahead at
+    /// most (Rust target).
+    TableDriven,
+}
+
+impl std::str::FromStr for ParserStrategy {
+    type Err = crate::Error;
+
+    /// Parse `recursive-descent` or `table-driven`.
+    fn from_str(s: &str) -> crate::Result<Self> {
+        match s {
+            "recursive-descent" => Ok(ParserStrategy::RecursiveDescent),
+            "table-driven" => Ok(ParserStrategy::TableDriven),
+            _ => Err(crate::Error::InvalidArgument(format!(
+                "invalid parser strategy '{}', expected 'recursive-descent' or 'table-driven'",
+                s
+            ))),
+        }
+    }
+}
+
 /// Configuration options for code generation.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CodeGenConfig {
@@ -133,6 +271,50 @@ pub struct CodeGenConfig {
     pub package_name: Option<String>,
     pub generate_listener: bool,
     pub generate_visitor: bool,
+    pub error_mode: ErrorMode,
+    /// Count how often each alternative of each parser rule is tried and
+    /// matches, for finding hot or dead alternatives when tuning a grammar.
+    pub alt_stats: bool,
+    /// Parse left-recursive binary-operator rules such as
+    /// `expr: expr '*' expr | expr '+' expr | NUMBER;` with one
+    /// precedence-climbing method instead of ordinary recursive descent.
+    #[serde(default)]
+    pub precedence_climbing: bool,
+    /// Indentation of the generated code; `None` keeps the generator's own
+    /// (four spaces for Rust and Python, two for JavaScript).
+    #[serde(default)]
+    pub indent: Option<IndentStyle>,
+    /// Placement of opening braces in targets that use them.
+    #[serde(default)]
+    pub brace_style: BraceStyle,
+    /// Whether the generated parser also builds a concrete syntax tree.
+    #[serde(default)]
+    pub cst_mode: CstMode,
+    /// Visibility of the generated types and functions.
+    #[serde(default)]
+    pub visibility: Visibility,
+    /// How the generated parser chooses between alternatives.
+    #[serde(default)]
+    pub parser_strategy: ParserStrategy,
+    /// Emit JavaScript as a CommonJS-compatible script that assigns
+    /// `module.exports`, instead of an ES module with `export` declarations.
+    /// TypeScript output is unaffected.
+    #[serde(default)]
+    pub commonjs: bool,
+    /// How many fragment references deep a token rule may inline before
+    /// code generation fails, since each level copies the fragment into
+    /// the rule referencing it.
+    #[serde(default = "default_max_inline_depth")]
+    pub max_inline_depth: usize,
+    /// Derive serde's `Serialize` and `Deserialize` for the generated AST
+    /// nodes, tokens and token kinds (Rust target). The crate using the
+    /// generated code must depend on serde with its `derive` feature.
+    #[serde(default)]
+    pub derive_serde: bool,
+}
+
+fn default_max_inline_depth() -> usize {
+    crate::codegen::dfa::DEFAULT_MAX_INLINE_DEPTH
 }
 
 impl Default for CodeGenConfig {
@@ -143,6 +325,17 @@ impl Default for CodeGenConfig {
             package_name: None,
             generate_listener: true,
             generate_visitor: false,
+            error_mode: ErrorMode::FailFast,
+            alt_stats: false,
+            precedence_climbing: false,
+            indent: None,
+            brace_style: BraceStyle::default(),
+            cst_mode: CstMode::Off,
+            visibility: Visibility::Public,
+            parser_strategy: ParserStrategy::RecursiveDescent,
+            commonjs: false,
+            max_inline_depth: default_max_inline_depth(),
+            derive_serde: false,
         }
     }
 }
@@ -202,6 +395,30 @@ mod tests {
         assert_eq!(config.package_name, None);
         assert!(config.generate_listener);
         assert!(!config.generate_visitor);
+        assert_eq!(config.indent, None);
+        assert_eq!(config.brace_style, BraceStyle::SameLine);
+        assert_eq!(config.visibility, Visibility::Public);
+        assert_eq!(config.parser_strategy, ParserStrategy::RecursiveDescent);
+        assert!(!config.commonjs);
+        assert_eq!(config.max_inline_depth, 32);
+        assert!(!config.derive_serde);
+    }
+
+    #[test]
+    fn test_config_enums_from_str() {
+        assert_eq!("tabs".parse::<IndentStyle>().unwrap(), IndentStyle::Tabs);
+        assert_eq!("spaces-2".parse::<IndentStyle>().unwrap(), IndentStyle::Spaces(2));
+        assert!("spaces-0".parse::<IndentStyle>().is_err());
+        assert!("2".parse::<IndentStyle>().is_err());
+        assert_eq!("next-line".parse::<BraceStyle>().unwrap(), BraceStyle::NextLine);
+        assert!("allman".parse::<BraceStyle>().is_err());
+        assert_eq!("crate".parse::<Visibility>().unwrap(), Visibility::Crate);
+        assert!("pub".parse::<Visibility>().is_err());
+        assert_eq!(
+            "table-driven".parse::<ParserStrategy>().unwrap(),
+            ParserStrategy::TableDriven
+        );
+        assert!("ll1".parse::<ParserStrategy>().is_err());
     }
 
     #[test]
@@ -212,12 +429,27 @@ mod tests {
             package_name: Some("my_parser".to_string()),
             generate_listener: false,
             generate_visitor: true,
+            error_mode: ErrorMode::Collect,
+            alt_stats: true,
+            precedence_climbing: true,
+            indent: Some(IndentStyle::Tabs),
+            brace_style: BraceStyle::NextLine,
+            cst_mode: CstMode::On,
+            visibility: Visibility::Module,
+            parser_strategy: ParserStrategy::TableDriven,
+            commonjs: true,
+            max_inline_depth: 8,
+            derive_serde: true,
         };
 
         assert_eq!(config.target_language, "python");
         assert_eq!(config.output_directory, "output");
         assert_eq!(config.package_name, Some("my_parser".to_string()));
         assert!(!config.generate_listener);
+        assert_eq!(config.error_mode, ErrorMode::Collect);
         assert!(config.generate_visitor);
+        assert!(config.alt_stats);
+        assert_eq!(config.cst_mode, CstMode::On);
+        assert!(config.derive_serde);
     }
 }
@@ -0,0 +1,663 @@
+//! Integration tests for the `minipg` command-line interface.
+//!
+//! These tests run the built binary and inspect its output.
+
+#![cfg(feature = "cli")]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+// ============================================================================
+// HELPER FUNCTIONS
+// ============================================================================
+
+/// Write `grammar_text` to a temporary `.g4` file and return its path.
+fn write_grammar(name: &str, grammar_text: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("minipg_cli_{}_{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(format!("{}.g4", name));
+    fs::write(&path, grammar_text).unwrap();
+    path
+}
+
+fn minipg(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_minipg"))
+        .args(args)
+        .env_remove("RUST_LOG")
+        .output()
+        .expect("Failed to run minipg")
+}
+
+/// Combined stdout and stderr of a run.
+fn all_output(output: &Output) -> String {
+    format!(
+        "{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    )
+}
+
+// ============================================================================
+// VERBOSITY
+// ============================================================================
+
+const INVALID: &str = "grammar Broken;\nexpr: missing;\nNUMBER: [0-9]+;\n";
+const VALID: &str = "grammar Fine;\nexpr: NUMBER;\nNUMBER: [0-9]+;\n";
+
+#[test]
+fn test_default_prints_info() {
+    let path = write_grammar("default", VALID);
+    let output = minipg(&["validate", path.to_str().unwrap()]);
+
+    assert!(output.status.success());
+    let text = all_output(&output);
+    assert!(text.contains("Validating grammar"), "{}", text);
+    assert!(!text.contains("Analyzed 2 rules"), "{}", text);
+}
+
+#[test]
+fn test_quiet_suppresses_info_but_keeps_errors() {
+    let path = write_grammar("quiet_valid", VALID);
+    let output = minipg(&["--quiet", "validate", path.to_str().unwrap()]);
+    assert!(output.status.success());
+    assert_eq!(all_output(&output), "");
+
+    let path = write_grammar("quiet_invalid", INVALID);
+    let output = minipg(&["validate", "-q", path.to_str().unwrap()]);
+    assert!(!output.status.success());
+    let text = all_output(&output);
+    assert!(!text.contains("Validating grammar"), "{}", text);
+    assert!(text.contains("undefined rule: missing"), "{}", text);
+    assert!(text.contains("Grammar validation failed"), "{}", text);
+}
+
+#[test]
+fn test_verbose_prints_debug() {
+    let path = write_grammar("verbose", VALID);
+    let output = minipg(&["-v", "validate", path.to_str().unwrap()]);
+
+    assert!(output.status.success());
+    let text = all_output(&output);
+    assert!(text.contains("Validating grammar"), "{}", text);
+    assert!(text.contains("Analyzed 2 rules"), "{}", text);
+}
+
+#[test]
+fn test_quiet_and_verbose_conflict() {
+    let path = write_grammar("conflict", VALID);
+    let output = minipg(&["--quiet", "--verbose", "validate", path.to_str().unwrap()]);
+
+    assert!(!output.status.success());
+}
+
+// ============================================================================
+// GENERATE
+// ============================================================================
+
+#[test]
+fn test_cli_language_overrides_grammar_option() {
+    let path = write_grammar(
+        "language_conflict",
+        "grammar Lang;\noptions { language = rust; }\nexpr: NUMBER;\nNUMBER: [0-9]+;\n",
+    );
+    let out_dir = path.parent().unwrap().join("out");
+    let output = minipg(&[
+        "generate",
+        path.to_str().unwrap(),
+        "-o",
+        out_dir.to_str().unwrap(),
+        "--lang",
+        "python",
+    ]);
+
+    assert!(output.status.success(), "{}", all_output(&output));
+    let text = all_output(&output);
+    assert!(
+        text.contains("target language 'python' overrides the grammar's language option 'rust'"),
+        "{}",
+        text
+    );
+    let generated = fs::read_to_string(out_dir.join("lang_parser.py")).unwrap();
+    assert!(generated.contains("class LangParser"), "{}", generated);
+}
+
+#[test]
+fn test_generate_dry_run_writes_nothing() {
+    let path = write_grammar("dry_run", VALID);
+    let out_dir = path.parent().unwrap().join("dry_out");
+    let output = minipg(&[
+        "generate",
+        path.to_str().unwrap(),
+        "-o",
+        out_dir.to_str().unwrap(),
+        "--dry-run",
+    ]);
+
+    assert!(output.status.success(), "{}", all_output(&output));
+    assert!(!out_dir.exists());
+
+    // The reported size is that of the file a real run writes
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    let out_dir = path.parent().unwrap().join("real_out");
+    let output = minipg(&["generate", path.to_str().unwrap(), "-o", out_dir.to_str().unwrap()]);
+    assert!(output.status.success(), "{}", all_output(&output));
+    let written = fs::metadata(out_dir.join("src/fine_parser.rs")).unwrap().len();
+    let expected = format!("fine_parser.rs ({} bytes)\n", written);
+    assert!(stdout.ends_with(&expected), "{}", stdout);
+}
+
+#[test]
+fn test_generate_follows_language_file_layout() {
+    let path = write_grammar("layout", "lexer grammar CalcLexer;\nNUMBER: [0-9]+;\n");
+    let out_dir = path.parent().unwrap().join("out");
+    let planned = |lang: &str| {
+        let output = minipg(&[
+            "generate",
+            path.to_str().unwrap(),
+            "-o",
+            out_dir.to_str().unwrap(),
+            "--lang",
+            lang,
+            "--dry-run",
+        ]);
+        assert!(output.status.success(), "{}", all_output(&output));
+        let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+        stdout
+            .lines()
+            .map(|line| {
+                let path = line.split(" (").next().unwrap();
+                Path::new(path).strip_prefix(&out_dir).unwrap().to_string_lossy().replace('\\', "/")
+            })
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(planned("rust"), ["src/calc_lexer_parser.rs", "CalcLexer.tokens"]);
+    assert_eq!(planned("python"), ["calc_lexer_parser.py", "CalcLexer.tokens"]);
+    assert_eq!(planned("typescript"), ["calc_lexer_parser.ts", "CalcLexer.tokens"]);
+}
+
+#[test]
+fn test_generate_split_grammar_shares_token_vocab() {
+    let lexer = write_grammar("split_vocab", "lexer grammar CalcLexer;\nPLUS: '+';\nNUMBER: [0-9]+;\n");
+    let dir = lexer.parent().unwrap();
+    let parser = dir.join("CalcParser.g4");
+    fs::write(
+        &parser,
+        "parser grammar CalcParser;\noptions { tokenVocab = CalcLexer; }\nexpr: NUMBER ('+' NUMBER)*;\n",
+    )
+    .unwrap();
+    let out_dir = dir.join("out");
+    let _ = fs::remove_dir_all(&out_dir);
+
+    // Without the lexer's vocabulary the parser grammar can't be generated
+    let output = minipg(&["generate", parser.to_str().unwrap(), "-o", out_dir.to_str().unwrap()]);
+    assert!(!output.status.success());
+    assert!(all_output(&output).contains("CalcLexer.tokens not found"), "{}", all_output(&output));
+
+    let output = minipg(&["generate", lexer.to_str().unwrap(), "-o", out_dir.to_str().unwrap()]);
+    assert!(output.status.success(), "{}", all_output(&output));
+    let vocab = fs::read_to_string(out_dir.join("CalcLexer.tokens")).unwrap();
+    assert_eq!(vocab, "PLUS=1\nNUMBER=2\n'+'=1\n");
+
+    let output = minipg(&["generate", parser.to_str().unwrap(), "-o", out_dir.to_str().unwrap()]);
+    assert!(output.status.success(), "{}", all_output(&output));
+    assert!(!all_output(&output).contains("W005"), "{}", all_output(&output));
+    let code = fs::read_to_string(out_dir.join("src/calc_parser_parser.rs")).unwrap();
+    assert!(code.contains("    PLUS = 1,\n    NUMBER = 2,\n"), "{}", code);
+    assert!(!code.contains("T__0"));
+}
+
+#[test]
+fn test_rust_only_flags_are_rejected_for_other_targets() {
+    let path = write_grammar("rust_only", "grammar Pair;\npair: ID ':' ID;\nID: [a-z]+;\n");
+    let out_dir = path.parent().unwrap().join("out");
+    let out = out_dir.to_str().unwrap();
+
+    let output = minipg(&["generate", path.to_str().unwrap(), "-o", out, "-l", "python", "--cst"]);
+    assert!(!output.status.success());
+    assert!(all_output(&output).contains("--cst is only supported by the rust target, not python"));
+
+    let output = minipg(&[
+        "generate",
+        path.to_str().unwrap(),
+        "-o",
+        out,
+        "-l",
+        "typescript",
+        "--parser-strategy",
+        "table-driven",
+        "--derive-serde",
+    ]);
+    assert!(!output.status.success());
+    assert!(all_output(&output).contains(
+        "--parser-strategy table-driven, --derive-serde are only supported by the rust target, not typescript"
+    ));
+    assert!(!out_dir.exists());
+}
+
+#[test]
+fn test_precedence_climbing_silences_left_recursion_warnings() {
+    let path = write_grammar(
+        "climbing",
+        "grammar Calc;\nexpr: expr '*' expr | expr '+' expr | NUMBER;\n\
+         list: list ',' | NUMBER;\nNUMBER: [0-9]+;\n",
+    );
+    let out_dir = path.parent().unwrap().join("out");
+    let out = out_dir.to_str().unwrap();
+
+    // Without climbing, both rules are rewritten into loops
+    let output = minipg(&["generate", path.to_str().unwrap(), "-o", out]);
+    let text = all_output(&output);
+    assert!(output.status.success(), "{}", text);
+    assert!(text.contains("direct left recursion in rule 'expr' is rewritten into a loop; generate with --precedence-climbing"), "{}", text);
+    assert!(text.contains("direct left recursion in rule 'list' is rewritten into a loop, as it cannot be parsed by precedence climbing"), "{}", text);
+    let code = fs::read_to_string(out_dir.join("src/calc_parser.rs")).unwrap();
+    assert!(code.contains("pub fn parse_expr_1(&mut self)"), "{}", code);
+    assert!(code.contains("pub fn parse_expr(&mut self)"), "{}", code);
+
+    let output = minipg(&["generate", path.to_str().unwrap(), "-o", out, "--precedence-climbing"]);
+    let text = all_output(&output);
+    assert!(output.status.success(), "{}", text);
+    assert!(!text.contains("rule 'expr'"), "{}", text);
+    assert!(text.contains("direct left recursion in rule 'list' is rewritten into a loop"), "{}", text);
+    let code = fs::read_to_string(out_dir.join("src/calc_parser.rs")).unwrap();
+    assert!(code.contains("pub fn parse_expr(&mut self, min_prec: u8)"), "{}", code);
+    assert!(!code.contains("parse_expr_1"), "{}", code);
+}
+
+#[test]
+fn test_labels_on_rewritten_left_recursion_are_an_error() {
+    let path = write_grammar(
+        "labeled_loop",
+        "grammar Calc;\nexpr: expr '+' expr #Add | NUMBER #Num;\nNUMBER: [0-9]+;\n",
+    );
+    let out_dir = path.parent().unwrap().join("out");
+    let out = out_dir.to_str().unwrap();
+
+    let output = minipg(&["generate", path.to_str().unwrap(), "-o", out]);
+    let text = all_output(&output);
+    assert!(!output.status.success(), "{}", text);
+    assert!(text.contains(":2:1: warning: direct left recursion in rule 'expr'"), "{}", text);
+    assert!(text.contains(":2:1: error: rule 'expr' labels its alternatives"), "{}", text);
+    assert!(text.contains("E011"), "{}", text);
+    assert!(!out_dir.exists());
+
+    let output = minipg(&["generate", path.to_str().unwrap(), "-o", out, "--precedence-climbing", "--visitor"]);
+    let text = all_output(&output);
+    assert!(output.status.success(), "{}", text);
+    let code = fs::read_to_string(out_dir.join("src/calc_parser.rs")).unwrap();
+    assert!(code.contains("fn visit_add("), "{}", code);
+    assert!(code.contains("fn visit_num("), "{}", code);
+}
+
+#[test]
+fn test_rule_recursive_in_every_alternative_is_an_error() {
+    let path = write_grammar("endless", "grammar Endless;
+r: r 'x' | r 'y';
+");
+    let out_dir = path.parent().unwrap().join("out");
+
+    let output = minipg(&["validate", path.to_str().unwrap()]);
+    let text = all_output(&output);
+    assert!(!output.status.success(), "{}", text);
+    assert!(text.contains("E010"), "{}", text);
+    assert!(text.contains("rule 'r' is left-recursive in every alternative"), "{}", text);
+
+    let output = minipg(&["generate", path.to_str().unwrap(), "-o", out_dir.to_str().unwrap()]);
+    assert!(!output.status.success(), "{}", all_output(&output));
+    assert!(!out_dir.exists());
+}
+
+#[test]
+fn test_generate_and_validate_resolve_imports() {
+    let path = write_grammar("imports", "grammar Main;\nimport Base;\nr: ID NUMBER;\nNUMBER: [0-9]+;\n");
+    let dir = path.parent().unwrap();
+    fs::write(dir.join("Base.g4"), "grammar Base;\nID: [a-z]+;\nNUMBER: 'n';\nWS: [ ]+ -> skip;\n").unwrap();
+    let out_dir = dir.join("out");
+
+    let output = minipg(&["validate", path.to_str().unwrap()]);
+    assert!(output.status.success(), "{}", all_output(&output));
+    let output = minipg(&["generate", path.to_str().unwrap(), "-o", out_dir.to_str().unwrap()]);
+    assert!(output.status.success(), "{}", all_output(&output));
+    let code = fs::read_to_string(out_dir.join("src/main_parser.rs")).unwrap();
+    assert!(code.contains("    NUMBER,\n    ID,\n    WS,\n"), "{}", code);
+
+    // Main's NUMBER overrides Base's, so digits are numbers
+    let input = dir.join("input.txt");
+    fs::write(&input, "abc 42").unwrap();
+    let output = minipg(&["-q", "test", path.to_str().unwrap(), input.to_str().unwrap()]);
+    assert!(output.status.success(), "{}", all_output(&output));
+
+    // A selective import only brings in the rules it names
+    fs::write(&path, "grammar Main;\nimport Base = {ID};\nr: ID;\n").unwrap();
+    let output = minipg(&["validate", path.to_str().unwrap()]);
+    assert!(output.status.success(), "{}", all_output(&output));
+    fs::write(&path, "grammar Main;\nimport Base = {ID};\nr: ID WS;\n").unwrap();
+    let output = minipg(&["validate", path.to_str().unwrap()]);
+    assert!(!output.status.success());
+    assert!(all_output(&output).contains("undefined token: WS"), "{}", all_output(&output));
+
+    // As is a missing grammar
+    fs::write(&path, "grammar Main;\nimport Missing;\nr: ID;\n").unwrap();
+    let output = minipg(&["generate", path.to_str().unwrap(), "-o", out_dir.to_str().unwrap()]);
+    assert!(!output.status.success());
+    assert!(all_output(&output).contains("Missing.g4"), "{}", all_output(&output));
+}
+
+/// Poll `condition` until it holds or ten seconds have passed.
+fn eventually(condition: impl Fn() -> bool) -> bool {
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+    while std::time::Instant::now() < deadline {
+        if condition() {
+            return true;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    }
+    false
+}
+
+#[test]
+fn test_generate_watch_regenerates_after_errors() {
+    let path = write_grammar("watch", VALID);
+    let out_dir = path.parent().unwrap().join("out");
+    let _ = fs::remove_dir_all(&out_dir);
+    let generated = out_dir.join("src/fine_parser.rs");
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_minipg"))
+        .args(["generate", path.to_str().unwrap(), "-o", out_dir.to_str().unwrap(), "--watch", "-q"])
+        .env_remove("RUST_LOG")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("Failed to run minipg");
+
+    let first_run = eventually(|| generated.exists());
+    // A broken grammar is reported without ending the watch
+    fs::write(&path, "grammar Fine;\nexpr: missing;\nNUMBER: [0-9]+;\n").unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(600));
+    let still_running = child.try_wait().unwrap().is_none();
+    fs::write(&path, "grammar Fine;\nexpr: other;\nother: NUMBER;\nNUMBER: [0-9]+;\n").unwrap();
+    let regenerated = eventually(|| {
+        fs::read_to_string(&generated).is_ok_and(|code| code.contains("fn parse_other"))
+    });
+    child.kill().unwrap();
+    child.wait().unwrap();
+
+    assert!(first_run);
+    assert!(still_running);
+    assert!(regenerated);
+}
+
+// ============================================================================
+// VALIDATE
+// ============================================================================
+
+const UNUSED_AND_INVALID: &str = "grammar Extra;\nexpr: missing;\nunused: NUMBER;\nNUMBER: [0-9]+;\n";
+
+#[test]
+fn test_validate_prints_diagnostics_with_location() {
+    let path = write_grammar("validate_text", UNUSED_AND_INVALID);
+    let output = minipg(&["-q", "validate", path.to_str().unwrap()]);
+
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("error: undefined rule: missing [E001]"), "{}", stdout);
+    assert!(
+        stdout.contains(&format!("{}:3:1: warning: unreachable rule: unused [W003]", path.display())),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn test_parse_error_points_at_column() {
+    let path = write_grammar("parse_error", "grammar Broken;\nexpr: NUMBER\t| ) ;\nNUMBER: [0-9]+;\n");
+    let output = minipg(&["-q", "validate", path.to_str().unwrap()]);
+
+    assert!(!output.status.success());
+    let text = all_output(&output);
+    assert!(text.contains(&format!("{}:2:16: error: Expected ;", path.display())), "{}", text);
+    assert!(text.contains("  |\n    2 | expr: NUMBER\t| ) ;\n      |             \t  ^\n"), "{}", text);
+}
+
+#[test]
+fn test_validate_json_format() {
+    let path = write_grammar("validate_json", UNUSED_AND_INVALID);
+    let output = minipg(&["-q", "validate", "--format", "json", path.to_str().unwrap()]);
+
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.trim_start().starts_with('['), "{}", stdout);
+    assert!(stdout.contains("\"severity\": \"Error\""), "{}", stdout);
+    assert!(stdout.contains("\"message\": \"undefined rule: missing\""), "{}", stdout);
+    assert!(stdout.contains("\"line\": 3"), "{}", stdout);
+
+    let path = write_grammar("validate_json_ok", VALID);
+    let output = minipg(&["validate", "--format", "json", path.to_str().unwrap()]);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "[]");
+}
+
+// ============================================================================
+// GRAPH
+// ============================================================================
+
+#[test]
+fn test_graph_writes_dot_file() {
+    let path = write_grammar("graph", VALID);
+    let dot_path = path.with_extension("dot");
+    let output = minipg(&["graph", path.to_str().unwrap(), "-o", dot_path.to_str().unwrap()]);
+
+    assert!(output.status.success(), "{}", all_output(&output));
+    let dot = fs::read_to_string(&dot_path).unwrap();
+    assert!(dot.starts_with("digraph \"Fine\" {"), "{}", dot);
+    assert!(dot.contains("\"expr\" -> \"NUMBER\";"), "{}", dot);
+}
+
+#[test]
+fn test_graph_prints_to_stdout() {
+    let path = write_grammar("graph_stdout", VALID);
+    let output = minipg(&["graph", path.to_str().unwrap()]);
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("\"expr\" [shape=box, peripheries=2, label=\"expr (start)\"];"), "{}", stdout);
+}
+
+#[test]
+fn test_graph_includes_imported_rules() {
+    let path = write_grammar("graph_imports", "grammar Main;\nimport Base;\nr: item+;\n");
+    fs::write(path.with_file_name("Base.g4"), "grammar Base;\nitem: ID;\nID: [a-z]+;\n").unwrap();
+    let output = minipg(&["graph", path.to_str().unwrap()]);
+
+    assert!(output.status.success(), "{}", all_output(&output));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("\"r\" -> \"item\";"), "{}", stdout);
+    assert!(stdout.contains("\"item\" -> \"ID\";"), "{}", stdout);
+}
+
+// ============================================================================
+// AST
+// ============================================================================
+
+#[test]
+fn test_ast_writes_json() {
+    let path = write_grammar("ast", VALID);
+    let json_path = path.with_extension("json");
+    let output = minipg(&["ast", path.to_str().unwrap(), "-o", json_path.to_str().unwrap()]);
+
+    assert!(output.status.success(), "{}", all_output(&output));
+    let ast: serde_json::Value = serde_json::from_str(&fs::read_to_string(&json_path).unwrap()).unwrap();
+    assert_eq!(ast["name"], "Fine");
+    assert_eq!(ast["rules"][0]["name"], "expr");
+    assert_eq!(ast["rules"][1]["name"], "NUMBER");
+    let number = &ast["rules"][1]["alternatives"][0]["elements"][0];
+    assert_eq!(
+        number["OneOrMore"]["element"]["CharClass"]["ranges"],
+        serde_json::json!([["0", "9"]])
+    );
+}
+
+// ============================================================================
+// FMT
+// ============================================================================
+
+#[test]
+fn test_fmt_prints_to_stdout() {
+    let path = write_grammar("fmt_stdout", "grammar Fine;\nexpr :  NUMBER|'('expr')' ;\nNUMBER:[0-9]+ ;\n");
+    let output = minipg(&["fmt", path.to_str().unwrap()]);
+
+    assert!(output.status.success(), "{}", all_output(&output));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "grammar Fine;\n\nexpr\n    : NUMBER\n    | '(' expr ')'\n    ;\n\nNUMBER: [0-9]+;\n"
+    );
+}
+
+#[test]
+fn test_fmt_write_rewrites_file() {
+    let path = write_grammar("fmt_write", "grammar Fine;\nexpr:NUMBER;NUMBER:[0-9]+;");
+    let output = minipg(&["fmt", "--write", path.to_str().unwrap()]);
+
+    assert!(output.status.success(), "{}", all_output(&output));
+    assert!(output.stdout.is_empty());
+    assert_eq!(fs::read_to_string(&path).unwrap(), "grammar Fine;\n\nexpr: NUMBER;\n\nNUMBER: [0-9]+;\n");
+}
+
+#[test]
+fn test_fmt_write_keeps_tokens_and_actions() {
+    let source = "grammar Block;\ntokens{INDENT,DEDENT}\n@members{\n  // depth of nesting\n  depth: usize,\n}\n\
+                  block:ID ':' INDENT {self.ok()}? ID+ DEDENT;\n\
+                  OPEN:'{' {self.emit(TokenKind::INDENT, \"\");}->skip;\nID:[a-z]+;\n";
+    let path = write_grammar("fmt_keeps", source);
+    let output = minipg(&["fmt", "--write", path.to_str().unwrap()]);
+
+    assert!(output.status.success(), "{}", all_output(&output));
+    let formatted = fs::read_to_string(&path).unwrap();
+    assert!(formatted.contains("\ntokens { INDENT, DEDENT }\n"), "{}", formatted);
+    assert!(formatted.contains("\n@members {\n    // depth of nesting\n    depth: usize,\n}\n"), "{}", formatted);
+    assert!(formatted.contains("\nblock: ID ':' INDENT {self.ok()}? ID+ DEDENT;\n"), "{}", formatted);
+    assert!(formatted.contains("\nOPEN: '{' {self.emit(TokenKind::INDENT, \"\");} -> skip;\n"), "{}", formatted);
+
+    // Formatting the result again changes nothing
+    let output = minipg(&["fmt", path.to_str().unwrap()]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), formatted);
+}
+
+#[test]
+fn test_fmt_write_refuses_to_drop_comments_and_parser_actions() {
+    let source = "grammar Calc;\n// Parser rules\nexpr: NUMBER {$v = 1;} /* the value */;\nNUMBER: [0-9]+;\n";
+    let path = write_grammar("fmt_refuses", source);
+    let output = minipg(&["fmt", "--write", path.to_str().unwrap()]);
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("formatting would drop"), "{}", stderr);
+    assert!(stderr.contains("line 2: comment `// Parser rules`"), "{}", stderr);
+    assert!(stderr.contains("line 3: action `$v = 1;`"), "{}", stderr);
+    assert!(stderr.contains("line 3: comment `/* the value */`"), "{}", stderr);
+    assert_eq!(fs::read_to_string(&path).unwrap(), source);
+
+    // Printing is still allowed
+    assert!(minipg(&["fmt", path.to_str().unwrap()]).status.success());
+}
+
+// ============================================================================
+// STATS
+// ============================================================================
+
+#[test]
+fn test_stats_table_and_json() {
+    let path = write_grammar(
+        "stats",
+        "grammar Fine;\nexpr: NUMBER ('+' (NUMBER | '(' expr ')'))*;\nNUMBER: DIGIT+;\nfragment DIGIT: [0-9];\n",
+    );
+
+    let output = minipg(&["-q", "stats", path.to_str().unwrap()]);
+    assert!(output.status.success(), "{}", all_output(&output));
+    let table = String::from_utf8_lossy(&output.stdout);
+    assert!(table.starts_with("Grammar            Fine\nParser rules       1\n"), "{}", table);
+    assert!(table.contains("Max nesting depth  2\n"), "{}", table);
+    assert!(table.contains("LL(1)              yes\n"), "{}", table);
+
+    let output = minipg(&["-q", "stats", "--format", "json", path.to_str().unwrap()]);
+    assert!(output.status.success(), "{}", all_output(&output));
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["lexer_rules"], 1);
+    assert_eq!(json["fragment_rules"], 1);
+    assert_eq!(json["max_nesting_depth"], 2);
+    assert_eq!(json["ll1"], true);
+}
+
+#[test]
+fn test_stats_counts_imported_rules() {
+    let path = write_grammar("stats_imports", "grammar Main;\nimport Base;\nr: ID NUMBER;\nNUMBER: [0-9]+;\n");
+    fs::write(path.with_file_name("Base.g4"), "grammar Base;\nID: [a-z]+;\nWS: [ ]+ -> skip;\n").unwrap();
+
+    let output = minipg(&["-q", "stats", "--format", "json", path.to_str().unwrap()]);
+    assert!(output.status.success(), "{}", all_output(&output));
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["parser_rules"], 1);
+    assert_eq!(json["lexer_rules"], 3);
+}
+
+// ============================================================================
+// TEST
+// ============================================================================
+
+const CALC: &str = "grammar Calc;\nstat: ID '=' expr ';';\nexpr: expr '+' expr | NUMBER | ID;\n\
+                    ID: [a-z]+;\nNUMBER: [0-9]+;\nWS: [ \\n]+ -> skip;\n";
+
+#[test]
+fn test_test_accepts_input_file() {
+    let path = write_grammar("test_accept", CALC);
+    let input = path.with_file_name("input.txt");
+    fs::write(&input, "x = 1 + y;\n").unwrap();
+    let output = minipg(&["-q", "test", path.to_str().unwrap(), input.to_str().unwrap()]);
+
+    assert!(output.status.success(), "{}", all_output(&output));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "accepted\n");
+}
+
+#[test]
+fn test_test_rejects_stdin_with_location() {
+    use std::io::Write;
+
+    let path = write_grammar("test_reject", CALC);
+    let mut child = Command::new(env!("CARGO_BIN_EXE_minipg"))
+        .args(["-q", "test", path.to_str().unwrap(), "--rule", "expr"])
+        .env_remove("RUST_LOG")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to run minipg");
+    child.stdin.take().unwrap().write_all(b"1 +\n  + 2").unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.starts_with("rejected\n"), "{}", stdout);
+    assert!(
+        stdout.contains("<stdin>:2:3: error: mismatched input '+', expected one of ID, NUMBER"),
+        "{}",
+        stdout
+    );
+    assert!(all_output(&output).contains("Input does not match rule expr"));
+}
+
+// ============================================================================
+// COMPLETIONS
+// ============================================================================
+
+#[test]
+fn test_bash_completions() {
+    let output = minipg(&["completions", "bash"]);
+
+    assert!(output.status.success());
+    let script = String::from_utf8_lossy(&output.stdout);
+    assert!(!script.trim().is_empty());
+    assert!(script.contains("minipg"), "{}", script);
+    assert!(script.contains("validate"), "{}", script);
+}
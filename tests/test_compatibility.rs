@@ -118,6 +118,55 @@ fn test_grammar_options_language() {
     assert_eq!(grammar.name, "Test");
 }
 
+#[test]
+fn test_grammar_options_value_forms() {
+    let grammar_text = r#"
+        grammar Test;
+
+        options {
+            language = java;
+            tokenVocab = "Common";
+            superClass = 'MyBase';
+            k = 3;
+            maxDepth = 128;
+        }
+
+        expr: NUMBER;
+
+        NUMBER: [0-9]+;
+    "#;
+
+    let grammar = parse_grammar(grammar_text);
+    assert_eq!(grammar.options.get("language").map(String::as_str), Some("java"));
+    assert_eq!(grammar.options.get("tokenVocab").map(String::as_str), Some("Common"));
+    assert_eq!(grammar.options.get("superClass").map(String::as_str), Some("MyBase"));
+    assert_eq!(grammar.options.get("k").map(String::as_str), Some("3"));
+    assert_eq!(grammar.options.get("maxDepth").map(String::as_str), Some("128"));
+}
+
+#[test]
+fn test_rule_options_stay_on_rule() {
+    let grammar_text = r#"
+        grammar Test;
+
+        options { caseInsensitive = true; }
+
+        expr: SELECT ID;
+
+        SELECT: 'select';
+        ID options { caseInsensitive = false; }: [a-z]+;
+        fragment DIGIT options { caseInsensitive = false; }: [0-9];
+    "#;
+
+    let grammar = parse_grammar(grammar_text);
+    assert_eq!(grammar.options.get("caseInsensitive").map(String::as_str), Some("true"));
+    let id = grammar.get_rule("ID").unwrap();
+    assert_eq!(id.options.get("caseInsensitive").map(String::as_str), Some("false"));
+    let digit = grammar.get_rule("DIGIT").unwrap();
+    assert_eq!(digit.options.get("caseInsensitive").map(String::as_str), Some("false"));
+    assert!(grammar.get_rule("SELECT").unwrap().options.is_empty());
+}
+
 #[test]
 fn test_grammar_imports() {
     let grammar_text = r#"
@@ -572,6 +621,17 @@ fn test_grammars_v4_code_generation_all_languages() {
             package_name: None,
             generate_listener: false,
             generate_visitor: false,
+            error_mode: minipg::types::ErrorMode::FailFast,
+            alt_stats: false,
+            precedence_climbing: false,
+            indent: None,
+            brace_style: minipg::types::BraceStyle::default(),
+            cst_mode: minipg::types::CstMode::Off,
+            visibility: minipg::types::Visibility::Public,
+            parser_strategy: minipg::types::ParserStrategy::RecursiveDescent,
+            commonjs: false,
+            max_inline_depth: 32,
+            derive_serde: false,
         };
         
         let generator = minipg::codegen::CodeGenerator::new(config);
@@ -12,12 +12,11 @@
 //!
 //! Tests are organized by feature category for easy navigation.
 
-use minipg::{CodeGenerator, SemanticAnalyzer, GrammarParser, types::CodeGenConfig};
+use minipg::{CodeGenerator, GrammarParser, types::CodeGenConfig};
 use minipg::codegen::{rust::RustCodeGenerator, python::PythonCodeGenerator, javascript::JavaScriptCodeGenerator};
 use minipg::parser::{Lexer, Parser};
-use minipg::ast::{Element, Grammar, Rule};
-use minipg::traits::GrammarParser as GrammarParserTrait;
-use minipg::types::GrammarType;
+use minipg::ast::{Element, Grammar, LexerCommand, PredicatePosition};
+use minipg::DiagnosticSeverity;
 
 // ============================================================================
 // HELPER FUNCTIONS
@@ -134,6 +133,52 @@ ID: [a-zA-Z]+;
     assert_eq!(rule.arguments[0].arg_type, None);
 }
 
+#[test]
+fn test_parse_rule_fragment_and_replace_rule() {
+    let mut grammar = parse_grammar(
+        r#"
+grammar Test;
+
+prog: stmt+;
+stmt: ID ';';
+expr: ID;
+ID: [a-z]+;
+"#,
+    );
+
+    let unreachable = |grammar: &Grammar| {
+        grammar
+            .validate()
+            .iter()
+            .any(|d| d.code.as_deref() == Some("W003"))
+    };
+    assert!(unreachable(&grammar));
+
+    let mut parser = Parser::new(Lexer::new("stmt: ID '=' expr ';' | expr ';';", "stmt.g4"));
+    let stmt = parser.parse_rule_fragment(&grammar).unwrap();
+    assert_eq!(stmt.name, "stmt");
+    assert_eq!(stmt.alternatives.len(), 2);
+
+    let old = grammar.replace_rule(stmt).unwrap();
+    assert_eq!(old.alternatives.len(), 1);
+    let names: Vec<&str> = grammar.rules.iter().map(|r| r.name.as_str()).collect();
+    assert_eq!(names, ["prog", "stmt", "expr", "ID"]);
+    assert_eq!(grammar.get_rule("stmt").unwrap().alternatives.len(), 2);
+    // The edited rule makes `expr` reachable
+    assert!(!unreachable(&grammar));
+
+    let mut parser = Parser::new(Lexer::new("fragment DIGIT: [0-9];", "digit.g4"));
+    let digit = parser.parse_rule_fragment(&grammar).unwrap();
+    assert!(digit.is_fragment);
+    assert!(grammar.replace_rule(digit).is_none());
+    assert_eq!(grammar.rules.last().unwrap().name, "DIGIT");
+
+    // A snippet must hold exactly one rule
+    let mut parser = Parser::new(Lexer::new("a: ID; b: ID;", "two.g4"));
+    let err = parser.parse_rule_fragment(&grammar).unwrap_err();
+    assert!(err.to_string().contains("Expected a single rule"), "{}", err);
+}
+
 #[test]
 fn test_rust_codegen_multiple_returns() {
     let grammar = r#"
@@ -244,7 +289,7 @@ rule: ids+=ID (',' ids+=ID)*;
     
     // First element should be ids+=ID (list label)
     match &alt.elements[0] {
-        Element::RuleRef { name, label, is_list } => {
+        Element::RuleRef { name, label, is_list, .. } => {
             assert_eq!(name, "ID");
             assert_eq!(label, &Some("ids".to_string()));
             assert_eq!(*is_list, true, "Should be a list label");
@@ -253,6 +298,20 @@ rule: ids+=ID (',' ids+=ID)*;
     }
 }
 
+#[test]
+fn test_elements_record_their_spans() {
+    let grammar = parse_grammar("grammar Test;\nexpr: term\n  '+' e=term;\nterm: 'x';\n");
+
+    let elements = &grammar.get_rule("expr").unwrap().alternatives[0].elements;
+    let spans: Vec<_> = elements
+        .iter()
+        .map(|element| element.span().map(|s| (s.line, s.column)))
+        .collect();
+    // A labeled element is located at the reference, after `e=`
+    assert_eq!(spans, vec![Some((2, 7)), Some((3, 3)), Some((3, 9))]);
+    assert!(Element::rule_ref("term".to_string()).span().is_none());
+}
+
 #[test]
 fn test_parse_regular_label() {
     let grammar = r#"
@@ -270,7 +329,7 @@ ID: [a-zA-Z]+;
     let alt = &rule.alternatives[0];
     
     match &alt.elements[0] {
-        Element::RuleRef { name, label, is_list } => {
+        Element::RuleRef { name, label, is_list, .. } => {
             assert_eq!(name, "ID");
             assert_eq!(label, &Some("id".to_string()));
             assert_eq!(*is_list, false, "Should not be a list label");
@@ -279,6 +338,228 @@ ID: [a-zA-Z]+;
     }
 }
 
+#[test]
+fn test_parse_alternative_labels() {
+    let grammar_text = r#"
+        grammar Calc;
+
+        expr: expr '+' expr #Add
+            | INT           #IntLit
+            | '(' expr ')'
+            ;
+
+        INT: [0-9]+;
+    "#;
+
+    let grammar = parse_grammar(grammar_text);
+    let expr = grammar.get_rule("expr").unwrap();
+
+    assert_eq!(expr.alternatives.len(), 3);
+    assert_eq!(expr.alternatives[0].label.as_deref(), Some("Add"));
+    assert_eq!(expr.alternatives[0].elements.len(), 3);
+    assert_eq!(expr.alternatives[1].label.as_deref(), Some("IntLit"));
+    assert_eq!(expr.alternatives[2].label, None);
+}
+
+#[test]
+fn test_alternative_labels_in_visitor() {
+    let grammar_text = r#"
+        grammar Calc;
+
+        expr: expr '+' expr #Add
+            | INT           #IntLit
+            ;
+
+        INT: [0-9]+;
+    "#;
+
+    let grammar = parse_grammar(grammar_text);
+    let generator = RustCodeGenerator::new();
+    let config = CodeGenConfig {
+        generate_visitor: true,
+        ..CodeGenConfig::default()
+    };
+    let code = generator.generate(&grammar, &config).expect("Failed to generate");
+
+    assert!(code.contains("fn visit_add("));
+    assert!(code.contains("fn visit_int_lit("));
+    assert!(code.contains("fn enter_add("));
+}
+
+#[test]
+fn test_parse_alternative_options() {
+    let grammar = parse_grammar(
+        r#"
+grammar Test;
+
+expr: <assoc=right> expr '^' expr | expr '*' expr | INT;
+INT: [0-9]+;
+"#,
+    );
+
+    let expr = grammar.get_rule("expr").unwrap();
+    assert_eq!(expr.alternatives.len(), 3);
+    assert!(expr.alternatives[0].is_right_assoc());
+    assert_eq!(expr.alternatives[0].elements.len(), 3);
+    assert!(!expr.alternatives[1].is_right_assoc());
+}
+
+#[test]
+fn test_parse_element_options() {
+    let grammar = parse_grammar(
+        r#"
+grammar Test;
+
+expr: expr '^'<assoc=right> expr | e=expr<prec=2> '*' expr | INT<odd, ???> | {ok()}?<fail={"not ok"}> ID;
+INT: [0-9]+;
+ID: [a-z]+;
+"#,
+    );
+
+    let expr = grammar.get_rule("expr").unwrap();
+    let alts = &expr.alternatives;
+    assert_eq!(alts.len(), 4);
+
+    // The option belongs to '^', and still makes the alternative right-associative
+    assert!(alts[0].options.is_empty());
+    assert_eq!(alts[0].elements[1].option("assoc"), Some("right"));
+    assert!(alts[0].is_right_assoc());
+
+    // Options follow the label, and do not disturb the rest of the alternative
+    assert_eq!(alts[1].elements[0].option("prec"), Some("2"));
+    assert!(matches!(&alts[1].elements[0], Element::RuleRef { label: Some(l), .. } if l == "e"));
+    assert_eq!(alts[1].elements.len(), 3);
+    assert!(!alts[1].is_right_assoc());
+
+    // Unknown keys are kept; malformed entries are skipped
+    assert_eq!(alts[2].elements[0].option("odd"), Some(""));
+    assert_eq!(alts[2].elements.len(), 1);
+
+    assert!(matches!(&alts[3].elements[0], Element::Predicate { .. }));
+    assert_eq!(alts[3].elements[0].option("fail"), Some("{\"not ok\"}"));
+}
+
+#[test]
+fn test_parse_predicate_positions() {
+    let grammar = parse_grammar(
+        r#"
+grammar Test;
+
+s: {a()}? {init();} {b()}? => ID {c()}? | {d()}?=> INT;
+INT: [0-9]+;
+ID: [a-z]+;
+"#,
+    );
+
+    let positions = |alt: &minipg::ast::Alternative| -> Vec<PredicatePosition> {
+        alt.elements
+            .iter()
+            .filter_map(|e| match e {
+                Element::Predicate { position, .. } => Some(*position),
+                _ => None,
+            })
+            .collect()
+    };
+    let s = grammar.get_rule("s").unwrap();
+
+    // Predicates before the first matched element are leading, even after
+    // an action; `=>` and parser rule actions are dropped
+    assert_eq!(
+        positions(&s.alternatives[0]),
+        vec![PredicatePosition::Leading, PredicatePosition::Leading, PredicatePosition::Trailing]
+    );
+    assert_eq!(s.alternatives[0].elements.len(), 4);
+    assert_eq!(positions(&s.alternatives[1]), vec![PredicatePosition::Leading]);
+    assert_eq!(s.alternatives[1].elements.len(), 2);
+}
+
+#[test]
+fn test_hash_comment_still_skipped() {
+    let grammar_text = r#"
+        grammar Test;
+        # plain comment
+        rule: ID;
+        ID: [a-z#]+;
+    "#;
+
+    let grammar = parse_grammar(grammar_text);
+    assert_eq!(grammar.rules.len(), 2);
+    assert_eq!(grammar.rules[0].alternatives[0].label, None);
+}
+
+#[test]
+fn test_hash_label_only_ends_an_alternative() {
+    let grammar_text = r#"
+        grammar Test;
+        #comment without a space
+        rule: ID #todo: more tokens
+            | ID ID #Pair // the label comes first
+            | '(' (ID #Inner /* in a group */ ) ')' #Outer
+            ;
+        ID: [a-z]+;
+    "#;
+
+    let grammar = parse_grammar(grammar_text);
+    let rule = grammar.get_rule("rule").unwrap();
+    assert_eq!(rule.alternatives.len(), 3);
+    assert_eq!(rule.alternatives[0].label, None);
+    assert_eq!(rule.alternatives[0].elements.len(), 1);
+    assert_eq!(rule.alternatives[1].label.as_deref(), Some("Pair"));
+    assert_eq!(rule.alternatives[2].label.as_deref(), Some("Outer"));
+}
+
+#[test]
+fn test_hash_label_allows_a_space_after_the_hash() {
+    let grammar_text = r#"
+        grammar Test;
+        # a comment with a space
+        rule: ID # Single
+            | ID ID #	Pair
+            | '(' ID ')' # the closing comment
+            ;
+        ID: [a-z]+;
+    "#;
+
+    let grammar = parse_grammar(grammar_text);
+    let rule = grammar.get_rule("rule").unwrap();
+    assert_eq!(rule.alternatives.len(), 3);
+    assert_eq!(rule.alternatives[0].label.as_deref(), Some("Single"));
+    assert_eq!(rule.alternatives[1].label.as_deref(), Some("Pair"));
+    assert_eq!(rule.alternatives[2].label, None);
+}
+
+#[test]
+fn test_parse_catch_and_finally_clauses() {
+    let grammar = parse_grammar(
+        r#"
+grammar Test;
+
+stmt: ID ';' ;
+    catch [RecognitionException e] { report(e); }
+    finally { cleanup(); }
+catch: ID;
+ID: [a-z]+;
+"#,
+    );
+
+    let stmt = grammar.get_rule("stmt").unwrap();
+    assert_eq!(stmt.catch_clauses.len(), 1);
+    assert_eq!(stmt.catch_clauses[0].exception, "RecognitionException e");
+    assert_eq!(stmt.catch_clauses[0].variable(), Some("e"));
+    assert_eq!(stmt.catch_clauses[0].action, "report(e);");
+    assert_eq!(stmt.finally_clause.as_deref(), Some("cleanup();"));
+    // A rule may still be named `catch`
+    assert!(grammar.get_rule("catch").unwrap().catch_clauses.is_empty());
+
+    let code = RustCodeGenerator::new()
+        .generate(&grammar, &CodeGenConfig::default())
+        .unwrap();
+    let method = &code[code.find("pub fn parse_stmt").unwrap()..];
+    let method = &method[..method.find("\n    }\n").unwrap()];
+    assert!(method.contains("if let Err(e) = &result {\n            report(e);"));
+    assert!(method.contains("// finally\n        {\n            cleanup();\n        }\n        result"));
+}
+
 // ============================================================================
 // NAMED ACTIONS - @header, @members
 // ============================================================================
@@ -381,6 +662,33 @@ ID: [a-zA-Z]+;
     assert!(code.contains("count"));
 }
 
+// ============================================================================
+// SEMANTIC PREDICATES
+// ============================================================================
+
+#[test]
+fn test_parse_semantic_predicate() {
+    let grammar = parse_grammar(
+        r#"
+grammar Test;
+
+stmt: {self.lt(1).is_some_and(|t| t.text == "SELECT")}? ID ID | ID { let _ = 1; } ID;
+ID: [a-zA-Z]+;
+"#,
+    );
+
+    let stmt = grammar.get_rule("stmt").unwrap();
+    match &stmt.alternatives[0].elements[0] {
+        Element::Predicate { code, .. } => {
+            assert_eq!(code, r#"self.lt(1).is_some_and(|t| t.text == "SELECT")"#);
+        }
+        other => panic!("expected predicate, got {:?}", other),
+    }
+    assert_eq!(stmt.alternatives[0].elements.len(), 3);
+    // Plain actions are still skipped
+    assert_eq!(stmt.alternatives[1].elements.len(), 2);
+}
+
 // ============================================================================
 // LEXER MODES
 // ============================================================================
@@ -428,6 +736,49 @@ fn test_parse_lexer_mode_with_rules() {
     assert_eq!(default_rules.len(), 2);
 }
 
+#[test]
+fn test_parse_multiple_lexer_commands() {
+    let grammar = parse_grammar(
+        "lexer grammar Test; ID: [a-z]+; KW: 'if' -> type(ID), channel(HIDDEN);",
+    );
+
+    let alt = &grammar.get_rule("KW").unwrap().alternatives[0];
+    assert_eq!(
+        alt.lexer_commands,
+        vec![
+            LexerCommand::Type("ID".to_string()),
+            LexerCommand::Channel("HIDDEN".to_string()),
+        ]
+    );
+    assert_eq!(alt.lexer_command(), Some(&LexerCommand::Type("ID".to_string())));
+}
+
+#[test]
+fn test_parse_numeric_channel() {
+    let grammar = parse_grammar("lexer grammar Test; X: 'a' -> channel(3); Y: 'b' -> channel(12);");
+
+    let command = |name: &str| grammar.get_rule(name).unwrap().alternatives[0].lexer_commands.clone();
+    assert_eq!(command("X"), vec![LexerCommand::Channel("3".to_string())]);
+    assert_eq!(command("Y"), vec![LexerCommand::Channel("12".to_string())]);
+}
+
+#[test]
+fn test_parse_keeps_actions_in_lexer_rules_only() {
+    let grammar = parse_grammar("grammar Test; s: ID { log(); } ; ID: [a-z]+ { count += 1; } -> skip;");
+
+    let lexer_alt = &grammar.get_rule("ID").unwrap().alternatives[0];
+    assert!(lexer_alt
+        .elements
+        .iter()
+        .any(|element| matches!(element, Element::Action { code, .. } if code == "count += 1;")));
+
+    let parser_alt = &grammar.get_rule("s").unwrap().alternatives[0];
+    assert!(!parser_alt
+        .elements
+        .iter()
+        .any(|element| matches!(element, Element::Action { .. })));
+}
+
 // ============================================================================
 // UNICODE ESCAPES
 // ============================================================================
@@ -460,6 +811,66 @@ SAFE: ["];
     assert!(result.is_ok(), "Should parse quote in character class");
 }
 
+#[test]
+fn test_negation_binds_tighter_than_suffix() {
+    let grammar = parse_grammar("grammar Test; STRING: '\"' ~[\"]* '\"';");
+
+    let string = grammar.get_rule("STRING").unwrap();
+    match &string.alternatives[0].elements[1] {
+        Element::ZeroOrMore { element, .. } => {
+            assert!(matches!(element.as_ref(), Element::CharClass { negated: true, .. }));
+        }
+        other => panic!("expected a loop over the negated set, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_negated_charclass_with_ranges() {
+    let grammar = parse_grammar("grammar Test; OTHER: ~[a-zA-Z0-9_ ];");
+
+    let other = grammar.get_rule("OTHER").unwrap();
+    match &other.alternatives[0].elements[0] {
+        Element::CharClass { negated, ranges } => {
+            assert!(*negated);
+            assert_eq!(
+                ranges,
+                &vec![('a', 'z'), ('A', 'Z'), ('0', '9'), ('_', '_'), (' ', ' ')]
+            );
+        }
+        other => panic!("expected a negated char class, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_unicode_property_escapes() {
+    let grammar = parse_grammar(
+        r#"grammar Test; ID: [\p{L}_] \P{Digit} ~[\p{Lu}] ~\p{White_Space};"#,
+    );
+
+    let elements = &grammar.get_rule("ID").unwrap().alternatives[0].elements;
+    match &elements[0] {
+        Element::Group { alternatives } => {
+            assert!(matches!(
+                &alternatives[0].elements[0],
+                Element::UnicodeClass { negated: false, category } if category == "L"
+            ));
+            assert!(matches!(&alternatives[1].elements[0], Element::CharClass { .. }));
+        }
+        other => panic!("expected a property or '_', got {:?}", other),
+    }
+    assert!(matches!(&elements[1], Element::UnicodeClass { negated: true, category } if category == "Digit"));
+    assert!(matches!(&elements[2], Element::UnicodeClass { negated: true, category } if category == "Lu"));
+    assert!(matches!(&elements[3], Element::UnicodeClass { negated: true, category } if category == "White_Space"));
+
+    let lexer = Lexer::new(r#"grammar Test; ID: \p{Klingon}+;"#, "test.g4");
+    let err = Parser::new(lexer).parse().unwrap_err().to_string();
+    assert!(err.contains("unknown Unicode property 'Klingon'"), "{}", err);
+
+    let lexer = Lexer::new(r#"grammar Test; NOT_ID: ~[\p{L}_]+;"#, "test.g4");
+    let err = Parser::new(lexer).parse().unwrap_err().to_string();
+    assert!(err.contains("single \\p{...} property"), "{}", err);
+}
+
 #[test]
 fn test_negated_charclass() {
     let grammar = r#"
@@ -536,6 +947,117 @@ fn test_javascript_codegen_with_locals() {
     assert!(code.contains("parseExpr"));
 }
 
+#[test]
+fn test_eof_element_in_every_target() {
+    let grammar = parse_grammar(
+        "grammar Calc;\nprog: NUMBER EOF;\nlast: end=EOF;\nNUMBER: [0-9]+;\n",
+    );
+
+    let prog = grammar.get_rule("prog").unwrap();
+    assert!(matches!(prog.alternatives[0].elements[1], Element::Eof));
+    // A labeled EOF stays a reference so it keeps its label
+    let last = grammar.get_rule("last").unwrap();
+    assert!(matches!(
+        &last.alternatives[0].elements[0],
+        Element::RuleRef { name, label: Some(_), .. } if name == "EOF"
+    ));
+    let diagnostics = grammar.validate();
+    assert!(!diagnostics.iter().any(|d| d.message.contains("EOF")), "{:?}", diagnostics);
+
+    let config = CodeGenConfig::default();
+    let code = PythonCodeGenerator::new().generate(&grammar, &config).unwrap();
+    assert!(code.contains(
+        "        # Match end of input\n        if self.position < len(self.tokens) and self.tokens[self.position].kind != TokenKind.EOF:\n"
+    ));
+
+    for language in ["javascript", "typescript"] {
+        let config = CodeGenConfig {
+            target_language: language.to_string(),
+            ..CodeGenConfig::default()
+        };
+        let code = JavaScriptCodeGenerator::new().generate(&grammar, &config).unwrap();
+        assert!(code.contains(
+            "      // Match end of input\n      if (this.position < this.tokens.length && this.tokens[this.position].kind !== TokenKind.EOF) {\n"
+        ));
+    }
+}
+
+#[test]
+fn test_literal_escapes_are_decoded() {
+    let grammar = parse_grammar(
+        "grammar T;\nNL: '\\n';\nQUOTE: '\\'';\nPATH: \"a\\\\b\";\nARROW: '\\u2192';\nSMILE: '\\u{1F600}';\n\
+         UPPER: '\\u0041'..'\\u005A';\n",
+    );
+
+    let literal = |name: &str| match &grammar.get_rule(name).unwrap().alternatives[0].elements[0] {
+        Element::StringLiteral { value, .. } => value.clone(),
+        other => panic!("expected a literal, got {:?}", other),
+    };
+    assert_eq!(literal("NL"), "\n");
+    assert_eq!(literal("QUOTE"), "'");
+    assert_eq!(literal("PATH"), "a\\b");
+    assert_eq!(literal("ARROW"), "\u{2192}");
+    assert_eq!(literal("SMILE"), "\u{1F600}");
+    assert!(matches!(
+        &grammar.get_rule("UPPER").unwrap().alternatives[0].elements[0],
+        Element::CharClass { ranges, .. } if ranges == &vec![('A', 'Z')]
+    ));
+}
+
+#[test]
+fn test_mutual_left_recursion_is_an_error() {
+    let grammar = parse_grammar(
+        "grammar T;\nstat: expr ';';\nexpr: call | NUMBER;\ncall: SIGN? expr '(' ')';\n\
+         sum: sum '+' NUMBER | NUMBER;\nSIGN: '-';\nNUMBER: [0-9]+;\n",
+    );
+
+    let diagnostics = grammar.validate();
+    let errors: Vec<_> = diagnostics.iter().filter(|d| d.code.as_deref() == Some("E005")).collect();
+    assert_eq!(errors.len(), 1, "{:?}", diagnostics);
+    assert_eq!(errors[0].severity, DiagnosticSeverity::Error);
+    assert!(errors[0].message.ends_with("expr -> call -> expr"), "{}", errors[0].message);
+    assert_eq!(errors[0].location.as_ref().unwrap().line, 3);
+    // Direct left recursion is still only a warning
+    assert!(diagnostics
+        .iter()
+        .any(|d| d.code.as_deref() == Some("W002") && d.message.contains("'sum'") && d.severity == DiagnosticSeverity::Warning));
+}
+
+#[test]
+fn test_rule_doc_comments_reach_generated_code() {
+    let grammar = parse_grammar(
+        "grammar Calc;\n/**\n * A whole program.\n *\n * Ends at end of input.\n */\nprog: expr EOF;\n\
+         /* plain */ // comments\nexpr: NUMBER;\n/** Digits. */\nNUMBER: DIGIT+;\n/** One digit. */ fragment DIGIT: [0-9];\n",
+    );
+
+    assert_eq!(
+        grammar.get_rule("prog").unwrap().doc.as_deref(),
+        Some("A whole program.\n\nEnds at end of input.")
+    );
+    assert_eq!(grammar.get_rule("expr").unwrap().doc, None);
+    assert_eq!(grammar.get_rule("NUMBER").unwrap().doc.as_deref(), Some("Digits."));
+    assert_eq!(grammar.get_rule("DIGIT").unwrap().doc.as_deref(), Some("One digit."));
+
+    let config = CodeGenConfig::default();
+    let code = RustCodeGenerator::new().generate(&grammar, &config).unwrap();
+    assert!(code.contains(
+        "    /// A whole program.\n    ///\n    /// Ends at end of input.\n    pub fn parse_prog("
+    ));
+    assert!(code.contains("    /// Parse expr rule.\n"));
+
+    let code = PythonCodeGenerator::new().generate(&grammar, &config).unwrap();
+    assert!(code.contains(
+        "        \"\"\"A whole program.\n\n        Ends at end of input.\n        \"\"\"\n"
+    ));
+
+    let config = CodeGenConfig {
+        target_language: "javascript".to_string(),
+        ..CodeGenConfig::default()
+    };
+    let code = JavaScriptCodeGenerator::new().generate(&grammar, &config).unwrap();
+    assert!(code.contains("  /**\n   * A whole program.\n   *\n   * Ends at end of input.\n"));
+}
+
 // ============================================================================
 // INTEGRATION TESTS
 // ============================================================================
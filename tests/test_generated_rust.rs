@@ -0,0 +1,2522 @@
+//! Round-trip tests for generated Rust parsers.
+//!
+//! These tests generate Rust code from a grammar, compile it with `rustc`
+//! together with a small driver program, and run the result.
+//!
+//! # Test Organization
+//!
+//! Tests are organized by the part of the generated code they exercise.
+
+use minipg::ast::{Grammar, Rule};
+use minipg::codegen::lookup_table::LookupTableBuilder;
+use minipg::codegen::rust::RustCodeGenerator;
+use minipg::parser::GrammarParser;
+use minipg::traits::GrammarParser as GrammarParserTrait;
+use minipg::types::{
+    BraceStyle, CodeGenConfig, CstMode, ErrorMode, IndentStyle, ParserStrategy, Visibility,
+};
+use minipg::CodeGenerator;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+// ============================================================================
+// HELPER FUNCTIONS
+// ============================================================================
+
+fn parse_grammar(grammar_text: &str) -> Grammar {
+    let parser = GrammarParser::new();
+    parser.parse_string(grammar_text, "test.g4").expect("Failed to parse grammar")
+}
+
+fn generate(grammar: &Grammar) -> String {
+    let config = CodeGenConfig {
+        generate_listener: false,
+        generate_visitor: false,
+        ..CodeGenConfig::default()
+    };
+    RustCodeGenerator::new()
+        .generate(grammar, &config)
+        .expect("Failed to generate code")
+}
+
+/// Compile the generated module with a driver `main` and return its stdout.
+///
+/// The generated code is available to `main_body` through `use generated::*;`.
+fn run_generated(name: &str, generated: &str, main_body: &str) -> String {
+    let dir: PathBuf = std::env::temp_dir().join(format!("minipg_{}_{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+
+    fs::write(dir.join("generated.rs"), generated).unwrap();
+    fs::write(dir.join("main.rs"), driver_source(main_body)).unwrap();
+
+    let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let binary = dir.join("driver");
+    let compile = Command::new(rustc)
+        .args(["--edition", "2021", "-A", "warnings", "-o"])
+        .arg(&binary)
+        .arg(dir.join("main.rs"))
+        .output()
+        .expect("Failed to run rustc");
+    assert!(
+        compile.status.success(),
+        "Generated code failed to compile:\n{}",
+        String::from_utf8_lossy(&compile.stderr)
+    );
+
+    let run = Command::new(&binary).output().expect("Failed to run driver");
+    let _ = fs::remove_dir_all(&dir);
+    assert!(
+        run.status.success(),
+        "Driver failed:\n{}",
+        String::from_utf8_lossy(&run.stderr)
+    );
+    String::from_utf8(run.stdout).unwrap()
+}
+
+/// Like [`run_generated`], building the driver as a throwaway Cargo
+/// project with `dependencies` as its `[dependencies]` table.
+///
+/// The build runs offline, so the dependencies must be ones this crate
+/// has already fetched, and shares a target directory next to this
+/// crate's own so later runs reuse it.
+fn run_generated_with_cargo(name: &str, generated: &str, main_body: &str, dependencies: &str) -> String {
+    let dir: PathBuf = std::env::temp_dir().join(format!("minipg_{}_{}", name, std::process::id()));
+    fs::create_dir_all(dir.join("src")).unwrap();
+
+    fs::write(
+        dir.join("Cargo.toml"),
+        format!(
+            "[package]\nname = \"driver\"\nversion = \"0.0.0\"\nedition = \"2021\"\n\n[dependencies]\n{}\n\n[workspace]\n",
+            dependencies
+        ),
+    )
+    .unwrap();
+    fs::write(dir.join("src/generated.rs"), generated).unwrap();
+    fs::write(dir.join("src/main.rs"), driver_source(main_body)).unwrap();
+
+    // The test binary is in <target>/<profile>/deps
+    let target = std::env::current_exe().unwrap().ancestors().nth(3).unwrap().join("generated-drivers");
+    let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+    let run = Command::new(cargo)
+        .args(["run", "--quiet", "--offline", "--manifest-path"])
+        .arg(dir.join("Cargo.toml"))
+        .env("CARGO_TARGET_DIR", target)
+        .env("RUSTFLAGS", "-A warnings")
+        .output()
+        .expect("Failed to run cargo");
+    let _ = fs::remove_dir_all(&dir);
+    assert!(
+        run.status.success(),
+        "Driver failed to build or run:\n{}",
+        String::from_utf8_lossy(&run.stderr)
+    );
+    String::from_utf8(run.stdout).unwrap()
+}
+
+/// A `main.rs` running `main_body` with the generated module in scope.
+fn driver_source(main_body: &str) -> String {
+    format!(
+        "#[path = \"generated.rs\"]\nmod generated;\nuse generated::*;\n\nfn main() {{\n{}\n}}\n",
+        main_body
+    )
+}
+
+// ============================================================================
+// PARSER RULE BODIES
+// ============================================================================
+
+const CALCULATOR: &str = r#"
+grammar Calculator;
+
+calc: expr EOF;
+expr: term (('+' | '-') term)*;
+term: factor (('*' | '/') factor)*;
+factor: NUMBER | '(' expr ')';
+
+NUMBER: [0-9]+;
+WS: [ \t\r\n]+ -> skip;
+"#;
+
+#[test]
+fn test_calculator_round_trip() {
+    let code = generate(&parse_grammar(CALCULATOR));
+    let output = run_generated(
+        "calculator",
+        &code,
+        r#"
+    for input in ["1+2*3", "(1 + 2) * 3", "42", "1+*3", "1+"] {
+        let mut lexer = CalculatorLexer::new(input);
+        let (tokens, errors) = lexer.tokenize_all();
+        assert!(errors.is_empty(), "{:?}", errors);
+        let mut parser = CalculatorParser::new(tokens);
+        println!("{} {}", input, parser.parse_calc().is_ok());
+    }
+"#,
+    );
+
+    assert_eq!(
+        output,
+        "1+2*3 true\n(1 + 2) * 3 true\n42 true\n1+*3 false\n1+ false\n"
+    );
+}
+
+#[test]
+fn test_indent_and_brace_style() {
+    let config = CodeGenConfig {
+        generate_listener: false,
+        indent: Some(IndentStyle::Tabs),
+        brace_style: BraceStyle::NextLine,
+        ..CodeGenConfig::default()
+    };
+    let code = RustCodeGenerator::new()
+        .generate(&parse_grammar(CALCULATOR), &config)
+        .unwrap();
+    assert!(code.contains("\n\tpub fn parse(input: &str) -> Result<AstNode, ParseError>\n\t{\n\t\t"));
+    assert!(!code.contains("\n    "));
+
+    let output = run_generated(
+        "indent_style",
+        &code,
+        r#"
+    let (tokens, _) = CalculatorLexer::new("(1 + 2) * 3").tokenize_all();
+    println!("{}", CalculatorParser::new(tokens).parse_calc().is_ok());
+"#,
+    );
+    assert_eq!(output, "true\n");
+}
+
+#[test]
+fn test_token_kind_names() {
+    let output = run_generated(
+        "token_kind_names",
+        &generate(&parse_grammar(CALCULATOR)),
+        r#"
+    println!("{} {} {}", TokenKind::NUMBER.as_str(), TokenKind::Eof, TokenKind::T__0);
+    let kind: TokenKind = "NUMBER".parse().unwrap();
+    println!("{:?} {:?}", kind, "EOF".parse::<TokenKind>().unwrap());
+    println!("{}", "Number".parse::<TokenKind>().unwrap_err().message);
+"#,
+    );
+    assert_eq!(output, "NUMBER EOF T__0\nNUMBER Eof\nunknown token kind 'Number'\n");
+}
+
+#[test]
+fn test_crate_visibility() {
+    let config = CodeGenConfig {
+        generate_visitor: true,
+        alt_stats: true,
+        cst_mode: CstMode::On,
+        visibility: Visibility::Crate,
+        ..CodeGenConfig::default()
+    };
+    let code = RustCodeGenerator::new()
+        .generate(&parse_grammar(CALCULATOR), &config)
+        .unwrap();
+    assert!(code.contains("\npub(crate) struct CalculatorParser<"));
+    assert!(code.contains("\npub(crate) trait Listener {"));
+    assert!(!code.contains("\npub "));
+
+    let output = run_generated(
+        "crate_visibility",
+        &code,
+        r#"
+    let tree: AstNode = CalculatorParser::parse("1 + 2").unwrap();
+    println!("{}", tree.to_sexpr());
+"#,
+    );
+    assert_eq!(output, "(calc (expr (term (factor 1)) + (term (factor 2))))\n");
+}
+
+#[test]
+fn test_parse_recovering_dedups_cascading_errors() {
+    let code = generate(&parse_grammar(CALCULATOR));
+    let output = run_generated(
+        "recovering",
+        &code,
+        r#"
+    let (tokens, _) = CalculatorLexer::new("1 + 2 3").tokenize_all();
+    let (tree, errors) = CalculatorParser::new(tokens).parse_recovering();
+    println!("{}", tree.is_some());
+    for err in errors {
+        println!("{} {}", err.position, err.message);
+    }
+"#,
+    );
+
+    // The attempts from "1", "+" and "2" fail at positions 6, 2 and 6
+    // again before "3" parses; the repeat is dropped and the rest sorted
+    assert_eq!(
+        output,
+        "true\n\
+         2 Expected NUMBER or '(', found \"+\"\n\
+         6 Expected '*' or '/' or '+' or '-' or end of input, found \"3\"\n"
+    );
+}
+
+#[test]
+fn test_labeled_elements_fill_ast_node() {
+    let grammar = parse_grammar(
+        r#"
+grammar Assign;
+
+assign: name=ID '=' value=NUMBER (',' extra+=NUMBER)*;
+
+ID: [a-z]+;
+NUMBER: [0-9]+;
+WS: [ \t]+ -> skip;
+"#,
+    );
+    let output = run_generated(
+        "labels",
+        &generate(&grammar),
+        r#"
+    let (tokens, _) = AssignLexer::new("x = 1, 2, 3").tokenize_all();
+    match AssignParser::new(tokens).parse_assign().unwrap() {
+        AstNode::Assign(node) => {
+            println!("{}", node.name.unwrap().text);
+            println!("{}", node.value.unwrap().text);
+            println!("{}", node.extra.len());
+        }
+    }
+"#,
+    );
+
+    assert_eq!(output, "x\n1\n2\n");
+}
+
+#[test]
+fn test_list_labels_collect_into_vec_fields() {
+    let grammar = parse_grammar(
+        r#"
+grammar List;
+
+list: ids+=ID (',' ids+=ID)* (';' items+=item)* EOF;
+item: '#' ID;
+
+ID: [a-z]+;
+WS: [ ]+ -> skip;
+"#,
+    );
+
+    for strategy in [ParserStrategy::RecursiveDescent, ParserStrategy::TableDriven] {
+        let config = CodeGenConfig {
+            generate_listener: false,
+            parser_strategy: strategy,
+            ..CodeGenConfig::default()
+        };
+        let code = RustCodeGenerator::new().generate(&grammar, &config).unwrap();
+        assert!(code.contains("    pub ids: Vec<Token>,\n    pub items: Vec<AstNode>,\n"));
+        assert!(code.contains("let mut ids: Vec<Token> = Vec::new();"));
+
+        let output = run_generated(
+            "list_labels",
+            &code,
+            r#"
+    let (tokens, _) = ListLexer::new("a, b, c; #x; #y").tokenize_all();
+    let AstNode::List(node) = ListParser::new(tokens).parse_list().unwrap() else { panic!() };
+    let ids: Vec<&str> = node.ids.iter().map(|id| id.text.as_str()).collect();
+    println!("{} {}", ids.join(" "), node.items.len());
+"#,
+        );
+
+        assert_eq!(output, "a b c 2\n", "{:?}", strategy);
+    }
+}
+
+#[test]
+fn test_parse_entry_follows_error_mode() {
+    let grammar = parse_grammar(CALCULATOR);
+    let generate_with = |error_mode| {
+        let config = CodeGenConfig {
+            generate_listener: false,
+            error_mode,
+            ..CodeGenConfig::default()
+        };
+        RustCodeGenerator::new().generate(&grammar, &config).unwrap()
+    };
+
+    let fail_fast = generate_with(ErrorMode::FailFast);
+    assert!(fail_fast.contains("pub fn parse(input: &str) -> Result<AstNode, ParseError> {"));
+
+    let collect = generate_with(ErrorMode::Collect);
+    assert!(collect.contains("pub fn parse(input: &str) -> (Option<AstNode>, Vec<ParseError>) {"));
+
+    let output = run_generated(
+        "collect_errors",
+        &collect,
+        r#"
+    let (tree, errors) = CalculatorParser::parse("1 + 2");
+    println!("{} {}", tree.is_some(), errors.len());
+    let (tree, errors) = CalculatorParser::parse("1 + $");
+    println!("{} {}", tree.is_some(), errors.is_empty());
+"#,
+    );
+    // Rules recover from their errors, so a tree comes back either way
+    assert_eq!(output, "true 0\ntrue false\n");
+}
+
+#[test]
+fn test_collect_mode_recovers_to_follow_set() {
+    let grammar = parse_grammar(
+        r#"
+grammar Stmts;
+
+prog: stmt* EOF;
+stmt: ID '=' expr ';' | 'print' expr ';';
+expr: NUMBER | ID | '(' expr ')';
+
+ID: [a-z]+;
+NUMBER: [0-9]+;
+WS: [ \t\n]+ -> skip;
+"#,
+    );
+    let config = CodeGenConfig {
+        generate_listener: false,
+        error_mode: ErrorMode::Collect,
+        ..CodeGenConfig::default()
+    };
+    let code = RustCodeGenerator::new().generate(&grammar, &config).unwrap();
+    assert!(code.contains("fn recover_to(&mut self, kinds: &[TokenKind])"));
+
+    let output = run_generated(
+        "follow_recovery",
+        &code,
+        r#"
+    let (tree, errors) = StmtsParser::parse("a = 1; b = ; print 2; c = ( 3; d = 4;");
+    println!("{}", tree.unwrap().to_sexpr());
+    for err in errors {
+        println!("{} {}", err.position, err.message);
+    }
+    let (tokens, _) = StmtsLexer::new("print ;").tokenize_all();
+    let mut parser = StmtsParser::new(tokens);
+    println!("{}", parser.parse_stmt().unwrap().to_sexpr());
+    println!("{}", parser.take_errors().len());
+"#,
+    );
+
+    // Each bad statement is reported once and parsing picks up again at
+    // the next token that can start a statement
+    assert_eq!(
+        output,
+        "(prog (stmt a = (expr 1) ;) (stmt print (expr 2) ;) (stmt d = (expr 4) ;))\n\
+         11 Expected NUMBER or ID or '(', found \";\"\n\
+         29 Expected ')', found \";\"\n\
+         (stmt)\n\
+         1\n"
+    );
+}
+
+#[test]
+fn test_alt_stats_count_alternative_attempts() {
+    let grammar = parse_grammar(CALCULATOR);
+    let plain = generate(&grammar);
+    assert!(!plain.contains("AltStats"));
+
+    let config = CodeGenConfig {
+        generate_listener: false,
+        alt_stats: true,
+        ..CodeGenConfig::default()
+    };
+    let code = RustCodeGenerator::new().generate(&grammar, &config).unwrap();
+    let output = run_generated(
+        "alt_stats",
+        &code,
+        r#"
+    let mut total = AltStats::default();
+    for _ in 0..3 {
+        for input in ["1+2", "(3)", "4*(5+6)"] {
+            let (tokens, _) = CalculatorLexer::new(input).tokenize_all();
+            let mut parser = CalculatorParser::new(tokens);
+            parser.parse_calc().unwrap();
+            total.merge(parser.alt_stats());
+        }
+    }
+    for (rule, alt, counts) in total.entries() {
+        println!("{} {} {} {}", rule, alt, counts.hits, counts.misses);
+    }
+"#,
+    );
+
+    // Per round: six numbers hit `factor` alternative 1, and the two
+    // parenthesized factors miss it before alternative 2 matches
+    assert_eq!(output, "factor 1 18 6\nfactor 2 6 0\n");
+}
+
+#[test]
+fn test_parse_with_tokens_keeps_hidden_tokens() {
+    let grammar = parse_grammar(
+        r#"
+grammar Sum;
+
+sum: first=NUMBER ('+' rest+=NUMBER)* EOF;
+
+NUMBER: [0-9]+;
+COMMENT: '/*' .*? '*/' -> channel(HIDDEN);
+WS: [ \t\r\n]+ -> skip;
+"#,
+    );
+    let output = run_generated(
+        "with_tokens",
+        &generate(&grammar),
+        r#"
+    let (tree, tokens) = SumParser::parse_with_tokens("1 + /* two */ 2").unwrap();
+    for token in &tokens {
+        println!("{:?} {:?} {}", token.kind, token.text, token.kind.is_hidden());
+    }
+    // The comment sits between '+' and NUMBER, so parsing only succeeds
+    // because hidden tokens never reach the parser
+    match tree {
+        AstNode::Sum(node) => println!("{} {}", node.first.unwrap().text, node.rest.len()),
+    }
+"#,
+    );
+
+    assert_eq!(
+        output,
+        "NUMBER \"1\" false\n\
+         T__0 \"+\" false\n\
+         COMMENT \"/* two */\" true\n\
+         NUMBER \"2\" false\n\
+         Eof \"\" false\n\
+         1 1\n"
+    );
+}
+
+#[test]
+fn test_error_after_loop_reports_furthest_failure() {
+    let grammar = parse_grammar(
+        r#"
+grammar Prog;
+
+prog: stmt* EOF;
+stmt: ID '=' NUMBER ';';
+
+ID: [a-z]+;
+NUMBER: [0-9]+;
+WS: [ \t]+ -> skip;
+"#,
+    );
+    let output = run_generated(
+        "loop_furthest",
+        &generate(&grammar),
+        r#"
+    for input in ["a = ;", "a = 1; 2"] {
+        let (tokens, _) = ProgLexer::new(input).tokenize_all();
+        if let Err(err) = ProgParser::new(tokens).parse_prog() {
+            println!("{} {}", err.position, err.message);
+        }
+    }
+"#,
+    );
+
+    // The statement that got as far as ";" is reported, not the end of
+    // input expected at "a"; failing at the same token merges both
+    assert_eq!(
+        output,
+        "4 Expected NUMBER, found \";\"\n\
+         7 Expected ID or end of input, found \"2\"\n"
+    );
+}
+
+#[test]
+fn test_parser_reads_from_custom_token_source() {
+    let grammar = parse_grammar(
+        r#"
+grammar Sum;
+
+sum: first=NUMBER ('+' rest+=NUMBER)* EOF;
+
+NUMBER: [0-9]+;
+WS: [ \t\r\n]+ -> skip;
+"#,
+    );
+    let output = run_generated(
+        "token_source",
+        &generate(&grammar),
+        r#"
+    struct SliceSource<'a> {
+        tokens: &'a [Token],
+        position: usize,
+    }
+
+    impl TokenSource for SliceSource<'_> {
+        fn next(&mut self) -> Option<Token> {
+            let token = self.tokens.get(self.position)?.clone();
+            self.position += 1;
+            Some(token)
+        }
+
+        fn peek(&mut self, k: usize) -> Option<&Token> {
+            self.tokens.get(self.position + k)
+        }
+
+        fn position(&self) -> usize {
+            self.position
+        }
+
+        fn seek(&mut self, position: usize) {
+            self.position = position;
+        }
+    }
+
+    for input in ["1 + 2 + 3", "1 + + 3"] {
+        let (tokens, _) = SumLexer::new(input).tokenize_all();
+        let source = SliceSource { tokens: &tokens, position: 0 };
+        match SumParser::from_source(source).parse_sum() {
+            Ok(AstNode::Sum(node)) => println!("{} {}", node.first.unwrap().text, node.rest.len()),
+            Err(err) => println!("{} {}", err.position, err.message),
+        }
+    }
+"#,
+    );
+
+    assert_eq!(output, "1 2\n4 Expected NUMBER, found \"+\"\n");
+}
+
+// ============================================================================
+// VISITORS
+// ============================================================================
+
+#[test]
+fn test_visitor_reads_unlabeled_rule_fields() {
+    let grammar = parse_grammar(
+        r#"
+grammar Assign;
+
+assignment: ID '=' expr;
+expr: term ('+' term)*;
+term: ID | NUMBER;
+
+ID: [a-z]+;
+NUMBER: [0-9]+;
+WS: [ \t]+ -> skip;
+"#,
+    );
+    let config = CodeGenConfig {
+        generate_listener: false,
+        generate_visitor: true,
+        ..CodeGenConfig::default()
+    };
+    let code = RustCodeGenerator::new().generate(&grammar, &config).unwrap();
+    assert!(code.contains("fn visit_assignment(&mut self, node: &AssignmentNode) -> T;"));
+
+    let output = run_generated(
+        "visitor",
+        &code,
+        r#"
+    struct Printer;
+
+    impl Printer {
+        fn visit_node(&mut self, node: &AstNode) -> String {
+            match node {
+                AstNode::Assignment(node) => self.visit_assignment(node),
+                AstNode::Expr(node) => self.visit_expr(node),
+                AstNode::Term(node) => self.visit_term(node),
+            }
+        }
+    }
+
+    impl Visitor<String> for Printer {
+        fn visit_assignment(&mut self, node: &AssignmentNode) -> String {
+            let expr = node.expr.as_ref().unwrap();
+            format!("assign {}", self.visit_node(expr))
+        }
+
+        fn visit_expr(&mut self, node: &ExprNode) -> String {
+            let terms: Vec<String> = node.term.iter().map(|t| self.visit_node(t)).collect();
+            format!("sum({})", terms.join(", "))
+        }
+
+        fn visit_term(&mut self, node: &TermNode) -> String {
+            "term".to_string()
+        }
+    }
+
+    let (tokens, _) = AssignLexer::new("x = a + 1 + b").tokenize_all();
+    let tree = AssignParser::new(tokens).parse_assignment().unwrap();
+    println!("{}", Printer.visit_node(&tree));
+"#,
+    );
+
+    assert_eq!(output, "assign sum(term, term, term)\n");
+}
+
+#[test]
+fn test_visitor_dispatches_on_alternative_labels() {
+    let climbing = parse_grammar(
+        r#"
+grammar Calc;
+
+calc: expr EOF;
+expr: expr '*' expr #Mul
+    | expr '+' expr #Add
+    | NUMBER        #Num
+    | '(' expr ')'  #Parens
+    ;
+
+NUMBER: [0-9]+;
+WS: [ ]+ -> skip;
+"#,
+    );
+    let config = CodeGenConfig {
+        generate_listener: false,
+        generate_visitor: true,
+        precedence_climbing: true,
+        ..CodeGenConfig::default()
+    };
+    let code = RustCodeGenerator::new().generate(&climbing, &config).unwrap();
+    assert!(code.contains("pub enum ExprLabel {\n    Mul,\n    Add,\n    Num,\n    Parens,\n}"));
+    assert!(code.contains("    pub label: Option<ExprLabel>,\n"));
+
+    let driver = r#"
+    struct Printer;
+
+    impl Visitor<String> for Printer {
+        fn visit_calc(&mut self, node: &CalcNode) -> String {
+            self.visit(&node.children[0])
+        }
+        fn visit_mul(&mut self, node: &ExprNode) -> String {
+            format!("mul({}, {})", self.visit(&node.children[0]), self.visit(&node.children[1]))
+        }
+        fn visit_add(&mut self, node: &ExprNode) -> String {
+            format!("add({}, {})", self.visit(&node.children[0]), self.visit(&node.children[1]))
+        }
+        fn visit_num(&mut self, node: &ExprNode) -> String {
+            node.tokens[0].text.clone()
+        }
+        fn visit_parens(&mut self, node: &ExprNode) -> String {
+            self.visit(&node.children[0])
+        }
+    }
+
+    for input in ["1+2*3", "(1+2)*3"] {
+        let tree = CalcParser::parse(input).unwrap();
+        println!("{}", Printer.visit(&tree));
+    }
+"#;
+    let output = run_generated("visitor_labels", &code, driver);
+    assert_eq!(output, "add(1, mul(2, 3))\nmul(add(1, 2), 3)\n");
+
+    // Recursive descent and the table-driven parser record the label of
+    // the alternative taken too
+    let statements = parse_grammar(
+        r#"
+grammar Stmt;
+
+stmt: 'let' ID '=' expr #Let | 'print' expr #Print;
+expr: NUMBER #Num | ID #Var;
+
+ID: [a-z]+;
+NUMBER: [0-9]+;
+WS: [ ]+ -> skip;
+"#,
+    );
+    for strategy in [ParserStrategy::RecursiveDescent, ParserStrategy::TableDriven] {
+        let config = CodeGenConfig {
+            generate_listener: false,
+            generate_visitor: true,
+            parser_strategy: strategy,
+            ..CodeGenConfig::default()
+        };
+        let code = RustCodeGenerator::new().generate(&statements, &config).unwrap();
+        let output = run_generated(
+            &format!("visitor_labels_{:?}", strategy),
+            &code,
+            r#"
+    struct Printer;
+
+    impl Visitor<String> for Printer {
+        fn visit_let(&mut self, node: &StmtNode) -> String {
+            format!("let({}, {})", node.tokens[1].text, self.visit(&node.children[0]))
+        }
+        fn visit_print(&mut self, node: &StmtNode) -> String {
+            format!("print({})", self.visit(&node.children[0]))
+        }
+        fn visit_num(&mut self, node: &ExprNode) -> String {
+            format!("num {}", node.tokens[0].text)
+        }
+        fn visit_var(&mut self, node: &ExprNode) -> String {
+            format!("var {}", node.tokens[0].text)
+        }
+    }
+
+    for input in ["let x = 1", "print y"] {
+        let tree = StmtParser::parse(input).unwrap();
+        println!("{}", Printer.visit(&tree));
+    }
+"#,
+        );
+        assert_eq!(output, "let(x, num 1)\nprint(var y)\n", "{:?}", strategy);
+    }
+}
+
+// ============================================================================
+// GRAMMAR TRANSFORMATIONS
+// ============================================================================
+
+#[test]
+fn test_desugared_grammar_accepts_same_inputs() {
+    use minipg::analysis::EbnfDesugarer;
+
+    let source = r#"
+grammar List;
+
+list: '[' (item (',' item)*)? ']' EOF;
+item: NUMBER+ | list_body;
+list_body: '(' NUMBER? ')';
+
+NUMBER: [0-9]+;
+WS: [ ]+ -> skip;
+"#;
+    let driver = r#"
+    for input in ["[]", "[1]", "[1 2, 3]", "[(), (4)]", "[1,]", "[,]", "[(1 2)]", "[1"] {
+        let (tokens, _) = ListLexer::new(input).tokenize_all();
+        println!("{} {}", input, ListParser::new(tokens).parse_list().is_ok());
+    }
+"#;
+
+    let grammar = parse_grammar(source);
+    let desugared = EbnfDesugarer::new().desugar(&grammar);
+
+    let original = run_generated("list_ebnf", &generate(&grammar), driver);
+    let bnf = run_generated("list_bnf", &generate(&desugared), driver);
+
+    assert_eq!(
+        original,
+        "[] true\n[1] true\n[1 2, 3] true\n[(), (4)] true\n[1,] false\n[,] false\n[(1 2)] false\n[1 false\n"
+    );
+    assert_eq!(bnf, original);
+}
+
+#[test]
+fn test_factored_suffixes_parse_the_same() {
+    use minipg::analysis::factoring::factor_common_suffixes;
+
+    let source = r#"
+grammar Stmts;
+
+prog: stmt* EOF;
+stmt: ID '=' NUMBER ';' | PRINT ID ';' | RETURN ';' | '{' stmt* '}';
+
+PRINT: 'print';
+RETURN: 'return';
+ID: [a-z]+;
+NUMBER: [0-9]+;
+WS: [ ]+ -> skip;
+"#;
+    let driver = r#"
+    for input in ["x = 1;", "print x; return;", "{ return; x = 2; }", "print;", "return", "x = ;"] {
+        let (tokens, _) = StmtsLexer::new(input).tokenize_all();
+        match StmtsParser::new(tokens).parse_prog() {
+            Ok(tree) => println!("{}", tree.to_sexpr()),
+            Err(_) => println!("{} error", input),
+        }
+    }
+"#;
+
+    let grammar = parse_grammar(source);
+    let mut factored = grammar.clone();
+    assert_eq!(factor_common_suffixes(&mut factored), vec!["stmt".to_string()]);
+    assert_eq!(factored.get_rule("stmt").unwrap().alternatives.len(), 2);
+
+    let original = run_generated("stmts", &generate(&grammar), driver);
+    let right_factored = run_generated("stmts_factored", &generate(&factored), driver);
+
+    assert!(original.contains("(stmt x = 1 ;)"), "{}", original);
+    assert!(original.contains("print; error"), "{}", original);
+    assert_eq!(right_factored, original);
+}
+
+#[test]
+fn test_eliminated_left_recursion_parses() {
+    use minipg::analysis::left_recursion::eliminate_direct;
+
+    let mut grammar = parse_grammar(
+        r#"
+grammar Arith;
+
+calc: expr EOF;
+expr: expr '+' term | expr '-' term | term;
+term: term '*' NUMBER | NUMBER;
+
+NUMBER: [0-9]+;
+WS: [ ]+ -> skip;
+"#,
+    );
+    assert!(eliminate_direct(&mut grammar).is_empty());
+
+    let output = run_generated(
+        "left_recursion",
+        &generate(&grammar),
+        r#"
+    for input in ["1", "1 + 2 - 3", "1 * 2 + 3 * 4", "1 +", "+ 1"] {
+        let (tokens, _) = ArithLexer::new(input).tokenize_all();
+        println!("{} {}", input, ArithParser::new(tokens).parse_calc().is_ok());
+    }
+"#,
+    );
+
+    assert_eq!(
+        output,
+        "1 true\n1 + 2 - 3 true\n1 * 2 + 3 * 4 true\n1 + false\n+ 1 false\n"
+    );
+}
+
+#[test]
+fn test_recursive_rules_call_themselves() {
+    let grammar = parse_grammar(
+        r#"
+grammar Nested;
+
+start: expr EOF;
+expr: '(' expr ')' | list | NUMBER;
+list: '[' (expr (',' expr)*)? ']';
+
+NUMBER: [0-9]+;
+WS: [ ]+ -> skip;
+"#,
+    );
+
+    let output = run_generated(
+        "recursive_rules",
+        &generate(&grammar),
+        r#"
+    for input in ["1", "((1))", "[1, (2), [[3]]]", "((1)", "[1,]"] {
+        let (tokens, _) = NestedLexer::new(input).tokenize_all();
+        println!("{} {}", input, NestedParser::new(tokens).parse_start().is_ok());
+    }
+"#,
+    );
+
+    assert_eq!(output, "1 true\n((1)) true\n[1, (2), [[3]]] true\n((1) false\n[1,] false\n");
+}
+
+#[test]
+fn test_fragment_nesting_beyond_max_inline_depth_fails() {
+    let grammar = parse_grammar(
+        "grammar Deep;\nstart: A;\nA: B B;\nfragment B: C C;\nfragment C: D D;\nfragment D: 'x';\n",
+    );
+    let config = |max_inline_depth| CodeGenConfig { max_inline_depth, ..CodeGenConfig::default() };
+
+    assert!(RustCodeGenerator::new().generate(&grammar, &config(3)).is_ok());
+    let err = RustCodeGenerator::new().generate(&grammar, &config(2)).unwrap_err();
+    assert!(
+        err.to_string().contains("token rule A inlines fragments 3 deep (A -> B -> C -> D), more than the maximum of 2"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn test_fragments_are_inlined() {
+    let grammar = parse_grammar(
+        r#"
+grammar Frag;
+
+calc: (NUMBER | ID)* EOF;
+
+NUMBER: DIGIT+ ('.' DIGIT+)?;
+ID: LETTER (LETTER | DIGIT)*;
+fragment DIGIT: [0-9];
+fragment LETTER: [a-z] | UPPER;
+fragment UPPER: [A-Z];
+WS: [ ]+ -> skip;
+"#,
+    );
+    let output = run_generated(
+        "fragments",
+        &generate(&grammar),
+        r#"
+    let (tokens, errors) = FragLexer::new("12.5 aB3 7").tokenize_all();
+    assert!(errors.is_empty(), "{:?}", errors);
+    for token in &tokens {
+        println!("{:?} {:?}", token.kind, token.text);
+    }
+"#,
+    );
+
+    assert_eq!(output, "NUMBER \"12.5\"\nID \"aB3\"\nNUMBER \"7\"\nEof \"\"\n");
+}
+
+#[test]
+fn test_lexer_wildcard_matches_one_char() {
+    let grammar = parse_grammar(
+        r#"
+lexer grammar Any;
+
+OPEN: '<' -> pushMode(TAG);
+ANY: .;
+
+mode TAG;
+CLOSE: '>' -> popMode;
+TAG_CHAR: .;
+"#,
+    );
+    let output = run_generated(
+        "wildcard",
+        &generate(&grammar),
+        r#"
+    let (tokens, errors) = AnyLexer::new("a\u{20AC}<b >c").tokenize_all();
+    assert!(errors.is_empty(), "{:?}", errors);
+    for token in &tokens {
+        println!("{:?} {:?}", token.kind, token.text);
+    }
+"#,
+    );
+
+    // One token per character, whitespace included inside the mode, and
+    // a single Eof once the input runs out
+    assert_eq!(
+        output,
+        "ANY \"a\"\nANY \"\u{20AC}\"\nOPEN \"<\"\nTAG_CHAR \"b\"\nTAG_CHAR \" \"\n\
+         CLOSE \">\"\nANY \"c\"\nEof \"\"\n"
+    );
+}
+
+#[test]
+fn test_case_insensitive_lexing() {
+    let grammar = parse_grammar(
+        r#"
+grammar Sql;
+
+options { caseInsensitive = true; }
+
+query: 'select' ID (',' ID)* FROM ID EOF;
+
+FROM: 'from';
+NOT: 'not'<caseInsensitive=false>;
+ID options { caseInsensitive = false; }: [a-z]+;
+WS: [ ]+ -> skip;
+"#,
+    );
+    let output = run_generated(
+        "case_insensitive",
+        &generate(&grammar),
+        r#"
+    for input in ["SeLeCt a, b FROM t", "select a from T", "NOT not"] {
+        let (tokens, errors) = SqlLexer::new(input).tokenize_all();
+        let kinds: Vec<String> = tokens.iter().map(|t| format!("{:?}", t.kind)).collect();
+        println!("{} {}", kinds.join(" "), errors.len());
+    }
+    println!("{}", SqlParser::parse("SELECT x, y From z").unwrap().to_sexpr());
+"#,
+    );
+
+    // Keywords match in any case, while ID and NOT opt out of it
+    assert_eq!(
+        output,
+        "T__0 ID T__1 ID FROM ID Eof 0\n\
+         T__0 ID FROM Eof 1\n\
+         NOT Eof 3\n\
+         (query SELECT x , y From z)\n"
+    );
+}
+
+#[test]
+fn test_precedence_climbing() {
+    let grammar = parse_grammar(
+        r#"
+grammar Calculator;
+
+calc: expr EOF;
+expr: <assoc=right> expr '^' expr
+    | expr ('*' | '/') expr
+    | expr ('+' | '-') expr
+    | NUMBER
+    | '(' expr ')'
+    ;
+
+NUMBER: [0-9]+;
+WS: [ ]+ -> skip;
+"#,
+    );
+    let config = CodeGenConfig {
+        generate_listener: false,
+        precedence_climbing: true,
+        ..CodeGenConfig::default()
+    };
+    let code = RustCodeGenerator::new().generate(&grammar, &config).unwrap();
+    assert!(code.contains("pub fn parse_expr(&mut self, min_prec: u8) -> Result<AstNode, ParseError> {"));
+
+    let output = run_generated(
+        "precedence_climbing",
+        &code,
+        r#"
+    fn show(node: &AstNode) -> String {
+        match node.children() {
+            [left, right] if node.rule_name() == "expr" => {
+                format!("({} {} {})", show(left), node.tokens()[0].text, show(right))
+            }
+            [inner] => show(inner),
+            _ => node.tokens().iter().map(|t| t.text.clone()).collect(),
+        }
+    }
+    for input in ["1+2*3-4", "2^3^2", "(1+2)*3", "1+"] {
+        match CalculatorParser::parse(input) {
+            Ok(tree) => println!("{}", show(&tree)),
+            Err(_) => println!("{} error", input),
+        }
+    }
+"#,
+    );
+
+    assert_eq!(
+        output,
+        "((1 + (2 * 3)) - 4)\n(2 ^ (3 ^ 2))\n((1 + 2) * 3)\n1+ error\n"
+    );
+}
+
+#[test]
+fn test_precedence_climbing_prefix_operators() {
+    let grammar = parse_grammar(
+        r#"
+grammar Calculator;
+
+calc: expr EOF;
+expr: '-' expr
+    | expr '*' expr
+    | 'not' expr
+    | expr '+' expr
+    | NUMBER
+    ;
+
+NUMBER: [0-9]+;
+WS: [ ]+ -> skip;
+"#,
+    );
+    let config = CodeGenConfig {
+        generate_listener: false,
+        precedence_climbing: true,
+        ..CodeGenConfig::default()
+    };
+    let code = RustCodeGenerator::new().generate(&grammar, &config).unwrap();
+
+    let output = run_generated(
+        "precedence_prefix",
+        &code,
+        r#"
+    fn show(node: &AstNode) -> String {
+        match node.children() {
+            [left, right] if node.rule_name() == "expr" => {
+                format!("({} {} {})", show(left), node.tokens()[0].text, show(right))
+            }
+            [operand] if node.rule_name() == "expr" && !node.tokens().is_empty() => {
+                format!("({} {})", node.tokens()[0].text, show(operand))
+            }
+            [inner] => show(inner),
+            _ => node.tokens().iter().map(|t| t.text.clone()).collect(),
+        }
+    }
+    for input in ["-1*2", "-1+2", "not 1*2", "not 1+2", "--1"] {
+        match CalculatorParser::parse(input) {
+            Ok(tree) => println!("{}", show(&tree)),
+            Err(err) => println!("{} error: {}", input, err),
+        }
+    }
+"#,
+    );
+
+    // '-' binds tighter than '*', 'not' only tighter than '+'
+    assert_eq!(
+        output,
+        "((- 1) * 2)\n((- 1) + 2)\n(not (1 * 2))\n((not 1) + 2)\n(- (- 1))\n"
+    );
+}
+
+#[test]
+fn test_prefix_operators_agree_with_and_without_precedence_climbing() {
+    let source = r#"
+grammar Calculator;
+
+calc: expr EOF;
+expr: '-' expr
+    | expr '*' expr
+    | expr '+' expr
+    | NUMBER
+    ;
+
+NUMBER: [0-9]+;
+WS: [ ]+ -> skip;
+"#;
+    // Evaluate the CST, whose shape differs between the two parsers: an
+    // operator applies to the operand after it, and a node's operators
+    // apply left to right
+    let driver = r#"
+    fn operand(children: &[TreeNode], i: &mut usize) -> i64 {
+        *i += 1;
+        match &children[*i - 1] {
+            TreeNode::Rule(node) => eval(&node.children),
+            TreeNode::Terminal(t) if t.token.text == "-" => -operand(children, i),
+            TreeNode::Terminal(t) => t.token.text.parse().unwrap(),
+        }
+    }
+    fn eval(children: &[TreeNode]) -> i64 {
+        let mut i = 0;
+        let mut value = operand(children, &mut i);
+        while let Some(TreeNode::Terminal(op)) = children.get(i) {
+            if op.token.text.is_empty() || op.token.text == "<EOF>" {
+                break;
+            }
+            i += 1;
+            let right = operand(children, &mut i);
+            value = if op.token.text == "*" { value * right } else { value + right };
+        }
+        value
+    }
+    for input in ["-1+2", "1+-2*3", "-2*3+1", "--1+2"] {
+        let tree = CalculatorParser::parse_cst(input).unwrap();
+        match &tree.children[0] {
+            TreeNode::Rule(expr) => println!("{} = {}", input, eval(&expr.children)),
+            TreeNode::Terminal(_) => println!("terminal"),
+        }
+    }
+"#;
+
+    let mut grammar = parse_grammar(source);
+    minipg::analysis::left_recursion::eliminate_unclimbable(&mut grammar, false);
+    let config = CodeGenConfig {
+        generate_listener: false,
+        cst_mode: CstMode::On,
+        ..CodeGenConfig::default()
+    };
+    let code = RustCodeGenerator::new().generate(&grammar, &config).unwrap();
+    let looped = run_generated("prefix_looped", &code, driver);
+
+    let config = CodeGenConfig {
+        precedence_climbing: true,
+        ..config
+    };
+    let code = RustCodeGenerator::new()
+        .generate(&parse_grammar(source), &config)
+        .unwrap();
+    let climbed = run_generated("prefix_climbed", &code, driver);
+
+    // '-' binds tighter than both operators
+    assert_eq!(looped, "-1+2 = 1\n1+-2*3 = -5\n-2*3+1 = -5\n--1+2 = 3\n");
+    assert_eq!(looped, climbed);
+}
+
+#[test]
+fn test_precedence_climbing_with_labeled_operators() {
+    let grammar = parse_grammar(
+        r#"
+grammar Calculator;
+
+calc: expr EOF;
+expr: left=expr op=('*' | '/') right=expr #MulDiv
+    | left=expr op='+' right=expr         #Add
+    | expr '-' expr                       #Sub
+    | NUMBER                              #Num
+    | '(' expr ')'                        #Parens
+    ;
+
+NUMBER: [0-9]+;
+WS: [ ]+ -> skip;
+"#,
+    );
+    let config = CodeGenConfig {
+        generate_listener: false,
+        generate_visitor: true,
+        precedence_climbing: true,
+        ..CodeGenConfig::default()
+    };
+    let code = RustCodeGenerator::new().generate(&grammar, &config).unwrap();
+    assert!(code.contains("pub fn parse_expr(&mut self, min_prec: u8) -> Result<AstNode, ParseError> {"));
+    assert!(code.contains("fn visit_mul_div(&mut self, node: &ExprNode) -> T;"));
+
+    let output = run_generated(
+        "precedence_labeled",
+        &code,
+        r#"
+    fn show(node: &AstNode) -> String {
+        match node.children() {
+            [left, right] if node.rule_name() == "expr" => {
+                format!("({} {} {})", show(left), node.tokens()[0].text, show(right))
+            }
+            [inner] => show(inner),
+            _ => node.tokens().iter().map(|t| t.text.clone()).collect(),
+        }
+    }
+    for input in ["1 + 2 * 3 - 4", "8 / (2 + 2)"] {
+        match CalculatorParser::parse(input) {
+            Ok(tree) => println!("{}", show(&tree)),
+            Err(err) => println!("{} error: {}", input, err),
+        }
+    }
+
+    // Element labels of the operator taken are filled in
+    let (tokens, _) = CalculatorLexer::new("1 + 2").tokenize_all();
+    let AstNode::Expr(add) = CalculatorParser::new(tokens).parse_expr(0).unwrap() else { panic!() };
+    println!("{} {} {}", show(add.left.as_ref().unwrap()), add.op.as_ref().unwrap().text, show(add.right.as_ref().unwrap()));
+    let (tokens, _) = CalculatorLexer::new("1 - 2").tokenize_all();
+    let AstNode::Expr(sub) = CalculatorParser::new(tokens).parse_expr(0).unwrap() else { panic!() };
+    println!("{} {} {}", sub.left.is_some(), sub.op.is_some(), sub.right.is_some());
+"#,
+    );
+
+    assert_eq!(output, "((1 + (2 * 3)) - 4)\n(8 / (2 + 2))\n1 + 2\nfalse false false\n");
+}
+
+#[test]
+fn test_cst_mode() {
+    let grammar = parse_grammar(
+        r#"
+grammar Tree;
+
+stmt: ID '=' expr ';' | expr ';';
+expr: term (('+' | '-') term)*;
+term: ID | NUMBER | '(' expr ')';
+
+ID: [a-z]+;
+NUMBER: [0-9]+;
+WS: [ ]+ -> skip;
+"#,
+    );
+    assert!(!generate(&grammar).contains("pub struct RuleNode"));
+
+    let config = CodeGenConfig {
+        generate_listener: false,
+        cst_mode: CstMode::On,
+        ..CodeGenConfig::default()
+    };
+    let code = RustCodeGenerator::new().generate(&grammar, &config).unwrap();
+    let output = run_generated(
+        "cst_mode",
+        &code,
+        r#"
+    // The second input backtracks out of the first alternative of stmt
+    for input in ["x = a + (1 - 2);", "a + 1;"] {
+        let tree = TreeParser::parse_cst(input).unwrap();
+        println!("{} {}", tree.to_sexpr(), tree.tokens().len());
+    }
+
+    // The typed AST is still built alongside
+    let (tokens, _) = TreeLexer::new("b;").tokenize_all();
+    let mut parser = TreeParser::new(tokens);
+    let ast = parser.parse_stmt().unwrap();
+    println!("{}", ast.to_sexpr());
+    let cst = parser.take_cst().unwrap();
+    match &cst.children[0] {
+        TreeNode::Rule(expr) => println!("{} {}", cst.rule, expr.rule),
+        TreeNode::Terminal(_) => println!("terminal"),
+    }
+    println!("{:?}", parser.take_cst());
+"#,
+    );
+
+    assert_eq!(
+        output,
+        "(stmt x = (expr (term a) + (term ( (expr (term 1) - (term 2)) ))) ;) 10\n\
+         (stmt (expr (term a) + (term 1)) ;) 4\n\
+         (stmt (expr (term b)) ;)\n\
+         stmt expr\n\
+         None\n"
+    );
+}
+
+#[test]
+fn test_right_assoc_left_recursion_nests_to_the_right() {
+    use minipg::analysis::left_recursion::eliminate_direct;
+
+    let mut grammar = parse_grammar(
+        r#"
+grammar Power;
+
+calc: expr EOF;
+expr: <assoc=right> expr '^' expr | expr '-' expr | atom;
+atom: NUMBER;
+
+NUMBER: [0-9]+;
+"#,
+    );
+    assert!(eliminate_direct(&mut grammar).is_empty());
+
+    let output = run_generated(
+        "right_assoc",
+        &generate(&grammar),
+        r#"
+    fn show(node: &AstNode) -> String {
+        let children: Vec<String> = node.children().iter().map(show).collect();
+        if children.is_empty() {
+            node.rule_name().to_string()
+        } else {
+            format!("{}({})", node.rule_name(), children.join(" "))
+        }
+    }
+    for input in ["2^3^4", "2-3-4", "2^3-4", "2-3^4"] {
+        let (tokens, _) = PowerLexer::new(input).tokenize_all();
+        let tree = PowerParser::new(tokens).parse_expr().unwrap();
+        println!("{} {}", input, show(&tree));
+    }
+"#,
+    );
+
+    // 2^(3^4) nests each exponent inside the previous one, while
+    // subtraction folds its operands into a single level. The right operand
+    // of '-' is expr_1, which only takes the tighter '^', so 2^3-4 is
+    // (2^3)-4 and 2-3^4 is 2-(3^4)
+    assert_eq!(
+        output,
+        "2^3^4 expr(atom expr_1(atom expr_1(atom)))\n\
+         2-3-4 expr(atom expr_1(atom) expr_1(atom))\n\
+         2^3-4 expr(atom expr_1(atom) expr_1(atom))\n\
+         2-3^4 expr(atom expr_1(atom expr_1(atom)))\n"
+    );
+}
+
+#[test]
+fn test_catch_and_finally_run_around_rule() {
+    let grammar = parse_grammar(
+        r#"
+grammar Pair;
+
+pair: ID '=' ID;
+    catch [ParseError err] { println!("caught at {}", err.position); }
+    finally { println!("done"); }
+
+ID: [a-z]+;
+WS: [ \t]+ -> skip;
+"#,
+    );
+    let output = run_generated(
+        "catch_finally",
+        &generate(&grammar),
+        r#"
+    for input in ["a = b", "a b"] {
+        let (tokens, _) = PairLexer::new(input).tokenize_all();
+        println!("{}", PairParser::new(tokens).parse_pair().is_ok());
+    }
+"#,
+    );
+
+    assert_eq!(output, "done\ntrue\ncaught at 2\ndone\nfalse\n");
+}
+
+// ============================================================================
+// SEMANTIC PREDICATES
+// ============================================================================
+
+#[test]
+fn test_predicate_treats_id_as_contextual_keyword() {
+    // SELECT is only a keyword at the start of a statement; elsewhere it is
+    // an ordinary identifier, so the lexer cannot make it a token of its own.
+    let grammar = parse_grammar(
+        r#"
+grammar Sql;
+
+stmt: (select | call) EOF;
+select: {self.lt(1).is_some_and(|t| t.text.eq_ignore_ascii_case("select"))}? ID ID;
+call: ID ID;
+
+ID: [a-zA-Z]+;
+WS: [ ]+ -> skip;
+"#,
+    );
+    let output = run_generated(
+        "keywords",
+        &generate(&grammar),
+        r#"
+    for input in ["SELECT name", "select select", "show select", "call select"] {
+        let (tokens, _) = SqlLexer::new(input).tokenize_all();
+        let tree = SqlParser::new(tokens).parse_stmt().unwrap();
+        println!("{} {}", input, tree.children()[0].rule_name());
+    }
+"#,
+    );
+
+    assert_eq!(
+        output,
+        "SELECT name select
+select select select
+show select call
+call select call
+"
+    );
+}
+
+#[test]
+fn test_predicates_become_methods_reading_rule_attributes() {
+    let grammar = parse_grammar(
+        r#"
+grammar Limit;
+
+s[i32 x]: nums+=NUMBER+ { $x > 0 }? { $nums.len() <= $x as usize }? EOF;
+
+NUMBER: [0-9]+;
+WS: [ ]+ -> skip;
+"#,
+    );
+    let code = generate(&grammar);
+    assert!(code.contains("    fn pred_0(&mut self, x: &i32) -> bool {\n        (*x) > 0\n    }\n"));
+    assert!(code.contains(
+        "    fn pred_1(&mut self, nums: &Vec<Token>, x: &i32) -> bool {\n        (*nums).len() <= (*x) as usize\n"
+    ));
+    assert!(code.contains("if !self.pred_1(&nums, &x) {"));
+
+    let output = run_generated(
+        "predicate_methods",
+        &code,
+        r#"
+    for (input, x) in [("1 2", 2), ("1 2", 0), ("1 2 3", 2)] {
+        let (tokens, _) = LimitLexer::new(input).tokenize_all();
+        match LimitParser::new(tokens).parse_s(x) {
+            Ok(_) => println!("{} / {} ok", input, x),
+            Err(err) => println!("{} / {} {}", input, x, err.message),
+        }
+    }
+"#,
+    );
+
+    assert_eq!(
+        output,
+        "1 2 / 2 ok\n\
+         1 2 / 0 Semantic predicate failed\n\
+         1 2 3 / 2 Semantic predicate failed\n"
+    );
+}
+
+#[test]
+fn test_trailing_predicates_fail_the_rule() {
+    let grammar = parse_grammar(
+        r#"
+grammar Gate;
+
+s[i32 n]
+    : { $n > 0 }? => ID NUMBER EOF
+    | ID NUMBER { $n > -5 }?<fail='n too small'> EOF
+    | ID NUMBER NUMBER? EOF
+    ;
+
+ID: [a-z]+;
+NUMBER: [0-9]+;
+WS: [ ]+ -> skip;
+"#,
+    );
+    let code = generate(&grammar);
+
+    // A failing leading predicate moves on to the next alternative, a
+    // failing trailing one rejects the input without trying the third
+    let output = run_generated(
+        "trailing_predicates",
+        &code,
+        r#"
+    for n in [1, 0, -9] {
+        let (tokens, _) = GateLexer::new("a 1").tokenize_all();
+        match GateParser::new(tokens).parse_s(n) {
+            Ok(_) => println!("{} ok", n),
+            Err(err) => println!("{} {}", n, err.message),
+        }
+    }
+"#,
+    );
+
+    assert_eq!(output, "1 ok\n0 ok\n-9 n too small\n");
+}
+
+#[test]
+fn test_super_class_is_a_base_field_the_parser_derefs_to() {
+    let grammar = parse_grammar(
+        r#"
+grammar Pair;
+
+options { superClass = Base; }
+
+@header {
+#[derive(Debug, Default)]
+pub struct Base {
+    pub strict: bool,
+}
+
+impl Base {
+    fn lenient(&self) -> bool {
+        !self.strict
+    }
+}
+}
+
+s: { self.lenient() }? ID ID EOF | ID EOF;
+
+ID: [a-z]+;
+WS: [ ]+ -> skip;
+"#,
+    );
+    let code = generate(&grammar);
+    assert!(code.contains("    pub base: Base,\n"));
+    assert!(code.contains("impl<S: TokenSource> std::ops::Deref for PairParser<S> {\n    type Target = Base;\n"));
+
+    let output = run_generated(
+        "super_class",
+        &code,
+        r#"
+    for strict in [false, true] {
+        let (tokens, _) = PairLexer::new("a b").tokenize_all();
+        let mut parser = PairParser::new(tokens);
+        parser.base.strict = strict;
+        println!("{} {}", strict, parser.parse_s().is_ok());
+    }
+"#,
+    );
+
+    assert_eq!(output, "false true\ntrue false\n");
+}
+
+// ============================================================================
+// TREE QUERIES
+// ============================================================================
+
+#[test]
+fn test_query_rule_paths() {
+    let code = generate(&parse_grammar(CALCULATOR));
+    let output = run_generated(
+        "query",
+        &code,
+        r#"
+    let (tokens, _) = CalculatorLexer::new("1+2*3").tokenize_all();
+    let tree = CalculatorParser::new(tokens).parse_expr().unwrap();
+    for path in ["expr/term", "//factor", "expr//factor", "term", "//term/factor", "expr/*"] {
+        let nodes = query(&tree, path);
+        let shape: Vec<String> = nodes
+            .iter()
+            .map(|node| format!("{}:{}", node.rule_name(), node.children().len()))
+            .collect();
+        println!("{} {:?}", path, shape);
+    }
+"#,
+    );
+
+    assert_eq!(
+        output,
+        "expr/term [\"term:1\", \"term:2\"]\n\
+         //factor [\"factor:0\", \"factor:0\", \"factor:0\"]\n\
+         expr//factor [\"factor:0\", \"factor:0\", \"factor:0\"]\n\
+         term []\n\
+         //term/factor [\"factor:0\", \"factor:0\", \"factor:0\"]\n\
+         expr/* [\"term:1\", \"term:2\"]\n"
+    );
+}
+
+#[test]
+fn test_tree_to_sexpr() {
+    let code = generate(&parse_grammar(CALCULATOR));
+    let output = run_generated(
+        "sexpr",
+        &code,
+        r#"
+    for input in ["1+2", "(1 + 2) * 3"] {
+        let (tokens, _) = CalculatorLexer::new(input).tokenize_all();
+        let tree = CalculatorParser::new(tokens).parse_expr().unwrap();
+        println!("{}", tree.to_sexpr());
+    }
+"#,
+    );
+
+    assert!(output.contains("(expr"), "{}", output);
+    assert_eq!(
+        output,
+        "(expr (term (factor 1)) + (term (factor 2)))\n\
+         (expr (term (factor ( (expr (term (factor 1)) + (term (factor 2))) )) * (factor 3)))\n"
+    );
+}
+
+#[test]
+fn test_backtracking_discards_children() {
+    let grammar = parse_grammar(
+        r#"
+grammar Retry;
+
+s: a 'x' | a 'y' | a*;
+a: 'a';
+
+WS: [ ]+ -> skip;
+"#,
+    );
+    let output = run_generated(
+        "retry",
+        &generate(&grammar),
+        r#"
+    for input in ["a x", "a y", "a a a"] {
+        let (tokens, _) = RetryLexer::new(input).tokenize_all();
+        let tree = RetryParser::new(tokens).parse_s().unwrap();
+        println!("{}", query(&tree, "s/a").len());
+    }
+"#,
+    );
+
+    assert_eq!(output, "1\n1\n3\n");
+}
+
+// ============================================================================
+// SERDE
+// ============================================================================
+
+#[test]
+fn test_derive_serde_round_trips_the_tree() {
+    let grammar = parse_grammar(
+        r#"
+grammar Sum;
+
+prog: expr EOF;
+expr: left=term ('+' rest+=term)*;
+term: NUMBER | '(' expr ')';
+
+NUMBER: [0-9]+;
+WS: [ ]+ -> skip;
+"#,
+    );
+    let config = CodeGenConfig {
+        generate_listener: false,
+        derive_serde: true,
+        ..CodeGenConfig::default()
+    };
+    let code = RustCodeGenerator::new().generate(&grammar, &config).unwrap();
+    assert!(code.contains("use serde::{Serialize, Deserialize};\n"));
+    assert!(code.contains("#[derive(Debug, Clone, Serialize, Deserialize)]\npub enum AstNode {"));
+    assert!(code.contains("#[derive(Debug, Clone, Serialize, Deserialize)]\npub struct TermNode {"));
+    assert!(code.contains("#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]\npub struct Token {"));
+    assert!(!generate(&grammar).contains("Serialize"));
+
+    let output = run_generated_with_cargo(
+        "derive_serde",
+        &code,
+        r#"
+    let (tokens, _) = SumLexer::new("1 + (2 + 3)").tokenize_all();
+    let tree = SumParser::new(tokens).parse_prog().unwrap();
+    let json = serde_json::to_string(&tree).unwrap();
+    let back: AstNode = serde_json::from_str(&json).unwrap();
+    println!("{}", serde_json::to_string(&back).unwrap() == json);
+    println!("{}", json.contains("\"kind\":\"NUMBER\",\"text\":\"3\""));
+    println!("{}", query(&back, "//term").len());
+"#,
+        "serde = { version = \"1.0\", features = [\"derive\"] }\nserde_json = \"1.0\"",
+    );
+
+    assert_eq!(output, "true\ntrue\n4\n");
+}
+
+// ============================================================================
+// LEXER
+// ============================================================================
+
+#[test]
+fn test_longest_match_wins_and_ties_go_to_the_first_rule() {
+    let grammar = parse_grammar(
+        r#"
+grammar Compare;
+
+file: .* EOF;
+
+GT: '>';
+GE: '>=';
+IF: 'if';
+ID: [a-z]+;
+WS: [ ]+ -> skip;
+"#,
+    );
+    let output = run_generated(
+        "longest_match",
+        &generate(&grammar),
+        r#"
+    let (tokens, errors) = CompareLexer::new(">= > if iff").tokenize_all();
+    assert!(errors.is_empty(), "{:?}", errors);
+    for token in tokens {
+        println!("{:?} {:?}", token.kind, token.text);
+    }
+"#,
+    );
+
+    // GT is declared first but GE matches more; IF and ID both match `if`,
+    // and IF comes first
+    assert_eq!(
+        output,
+        "GE \">=\"\nGT \">\"\nIF \"if\"\nID \"iff\"\nEof \"\"\n"
+    );
+}
+
+#[test]
+fn test_fragments_inline_nested_fragments() {
+    let grammar = parse_grammar(
+        r#"
+grammar Hex;
+
+file: NUMBER* EOF;
+
+NUMBER: HEXPAIR+;
+fragment HEXPAIR: HEX HEX;
+fragment HEX: [0-9a-fA-F];
+WS: [ ]+ -> skip;
+"#,
+    );
+    let output = run_generated(
+        "nested_fragments",
+        &generate(&grammar),
+        r#"
+    let (tokens, errors) = HexLexer::new("ff 0A1b 7").tokenize_all();
+    for token in tokens {
+        println!("{:?} {:?}", token.kind, token.text);
+    }
+    println!("{}", errors.len());
+"#,
+    );
+
+    // A lone hex digit is not a pair
+    assert_eq!(
+        output,
+        "NUMBER \"ff\"\nNUMBER \"0A1b\"\nEof \"\"\n1\n"
+    );
+}
+
+#[test]
+fn test_non_greedy_block_comments() {
+    let grammar = parse_grammar(
+        r#"
+grammar Comments;
+
+file: COMMENT* EOF;
+
+COMMENT: '/*' .*? '*/';
+WS: [ \t\r\n]+ -> skip;
+"#,
+    );
+    let output = run_generated(
+        "comments",
+        &generate(&grammar),
+        r#"
+    let (tokens, errors) = CommentsLexer::new("/* a */ /* b */").tokenize_all();
+    assert!(errors.is_empty(), "{:?}", errors);
+    for token in tokens {
+        println!("{:?} {:?}", token.kind, token.text);
+    }
+"#,
+    );
+
+    assert_eq!(
+        output,
+        "COMMENT \"/* a */\"\nCOMMENT \"/* b */\"\nEof \"\"\n"
+    );
+}
+
+#[test]
+fn test_negated_set_matches_string_body() {
+    let grammar = parse_grammar(
+        r#"
+grammar Strings;
+
+file: STRING* EOF;
+
+STRING: '"' ~["]* '"';
+WS: [ ]+ -> skip;
+"#,
+    );
+    let output = run_generated(
+        "negated_set",
+        &generate(&grammar),
+        r#"
+    let (tokens, errors) = StringsLexer::new("\"hello big world\" \"x1 y2\"").tokenize_all();
+    assert!(errors.is_empty(), "{:?}", errors);
+    for token in tokens {
+        println!("{:?} {}", token.kind, token.text);
+    }
+"#,
+    );
+
+    assert_eq!(
+        output,
+        "STRING \"hello big world\"\nSTRING \"x1 y2\"\nEof \n"
+    );
+}
+
+#[test]
+fn test_eof_token_at_end_of_input() {
+    let grammar = parse_grammar(
+        r#"
+grammar Words;
+
+words: WORD* EOF;
+
+WORD: [a-z]+;
+WS: [ \t\r\n]+ -> skip;
+"#,
+    );
+    let output = run_generated(
+        "eof_position",
+        &generate(&grammar),
+        r#"
+    for input in ["abc", "abc  ", ""] {
+        let (tokens, _) = WordsLexer::new(input).tokenize_all();
+        let eof = tokens.last().unwrap();
+        println!("{:?} {} {}", eof.kind, eof.position, input.len());
+    }
+"#,
+    );
+
+    assert_eq!(output, "Eof 3 3\nEof 5 5\nEof 0 0\n");
+}
+
+#[test]
+fn test_lexer_iterates_tokens_until_eof() {
+    let grammar = parse_grammar(
+        r#"
+grammar Words;
+
+words: WORD* EOF;
+
+WORD: [a-z]+;
+WS: [ \t\r\n]+ -> skip;
+"#,
+    );
+    let output = run_generated(
+        "lexer_iterator",
+        &generate(&grammar),
+        r#"
+    for result in WordsLexer::new("ab 1 cd!") {
+        match result {
+            Ok(token) => println!("{:?} {:?}", token.kind, token.text),
+            Err(err) => println!("error at {}", err.position),
+        }
+    }
+    let words = WordsLexer::new("x y z").filter_map(Result::ok).take(2).count();
+    println!("{}", words);
+"#,
+    );
+
+    assert_eq!(
+        output,
+        "WORD \"ab\"\nerror at 3\nWORD \"cd\"\nerror at 7\nEof \"\"\n2\n"
+    );
+}
+
+#[test]
+fn test_channel_tokens_are_set_aside() {
+    let grammar = parse_grammar(
+        r#"
+grammar Doc;
+
+doc: WORD* EOF;
+
+WORD: [a-z]+;
+COMMENT: '#' ~[\r\n]* -> channel(HIDDEN);
+PRAGMA: '@' [a-z]+ -> channel(PRAGMAS);
+WS: [ \t\r\n]+ -> skip;
+"#,
+    );
+    let output = run_generated(
+        "channels",
+        &generate(&grammar),
+        r#"
+    let mut lexer = DocLexer::new("one @fast two # note\nthree");
+    let (tokens, errors) = lexer.tokenize_all();
+    assert!(errors.is_empty(), "{:?}", errors);
+    for token in &tokens {
+        println!("{:?} {:?} {}", token.kind, token.text, token.channel);
+    }
+    for token in lexer.hidden_tokens() {
+        println!("hidden {:?} {:?} {}", token.kind, token.text, token.channel);
+    }
+    println!("{}", DocParser::new(tokens).parse_doc().is_ok());
+"#,
+    );
+
+    assert_eq!(
+        output,
+        "WORD \"one\" 0\n\
+         WORD \"two\" 0\n\
+         WORD \"three\" 0\n\
+         Eof \"\" 0\n\
+         hidden PRAGMA \"@fast\" 2\n\
+         hidden COMMENT \"# note\" 1\n\
+         true\n"
+    );
+}
+
+#[test]
+fn test_numeric_channel() {
+    let grammar = parse_grammar(
+        r#"
+grammar Doc;
+
+doc: Y* EOF;
+
+X: 'a' -> channel(3);
+Y: 'b';
+WS: ' ' -> skip;
+"#,
+    );
+    let output = run_generated(
+        "numeric_channel",
+        &generate(&grammar),
+        r#"
+    let mut lexer = DocLexer::new("b a");
+    let (tokens, _) = lexer.tokenize_all();
+    println!("{}", tokens.len());
+    for token in lexer.hidden_tokens() {
+        println!("{:?} {}", token.kind, token.channel);
+    }
+"#,
+    );
+
+    assert_eq!(output, "2\nX 3\n");
+}
+
+#[test]
+fn test_line_comment_stops_at_newline() {
+    let grammar = parse_grammar(
+        r#"
+grammar Comments;
+
+s: (ID | COMMENT)* EOF;
+
+ID: [a-z]+;
+COMMENT: '//' ~[\r\n]*;
+WS: [ \r\n]+ -> skip;
+"#,
+    );
+    let output = run_generated(
+        "comments",
+        &generate(&grammar),
+        r#"
+    let (tokens, errors) = CommentsLexer::new("a // b, ~c!\r\nd //\ne").tokenize_all();
+    assert!(errors.is_empty(), "{:?}", errors);
+    for token in &tokens {
+        println!("{:?} {:?}", token.kind, token.text);
+    }
+"#,
+    );
+
+    assert_eq!(
+        output,
+        "ID \"a\"\n\
+         COMMENT \"// b, ~c!\"\n\
+         ID \"d\"\n\
+         COMMENT \"//\"\n\
+         ID \"e\"\n\
+         Eof \"\"\n"
+    );
+}
+
+#[test]
+fn test_are_adjacent() {
+    let grammar = parse_grammar(
+        r#"
+grammar Dots;
+
+s: (ID | DOT)* EOF;
+
+ID: [a-z]+;
+DOT: '.';
+WS: [ \t]+ -> skip;
+"#,
+    );
+    let output = run_generated(
+        "adjacent",
+        &generate(&grammar),
+        r#"
+    for input in ["a.b", "a . b", "a b", "ab"] {
+        let (tokens, errors) = DotsLexer::new(input).tokenize_all();
+        assert!(errors.is_empty(), "{:?}", errors);
+        let pairs: Vec<bool> = tokens.windows(2).map(|w| are_adjacent(&w[0], &w[1])).collect();
+        println!("{:?} {:?}", input, pairs);
+    }
+"#,
+    );
+
+    assert_eq!(
+        output,
+        "\"a.b\" [true, true, true]\n\
+         \"a . b\" [false, false, true]\n\
+         \"a b\" [false, true]\n\
+         \"ab\" [true]\n"
+    );
+}
+
+#[test]
+fn test_tokens_carry_line_column_and_byte_offsets() {
+    let grammar = parse_grammar(
+        r#"
+grammar Words;
+
+s: WORD* EOF;
+
+WORD: ~[ \t\r\n]+;
+WS: [ \t\r\n]+ -> skip;
+"#,
+    );
+    let output = run_generated(
+        "token_positions",
+        &generate(&grammar),
+        r#"
+    let input = "héllo wörld\n  日本 🦀x\r\nend";
+    let mut lexer = WordsLexer::new(input);
+    println!("{}", lexer.peek_n(3).unwrap().len());
+    let (tokens, errors) = lexer.tokenize_all();
+    assert!(errors.is_empty(), "{:?}", errors);
+    for token in &tokens {
+        assert_eq!(&input[token.start..token.stop], token.text);
+        println!(
+            "{:?} {}:{} {}..{} {}..{}",
+            token.text, token.line, token.column, token.start, token.stop, token.position, token.end
+        );
+    }
+"#,
+    );
+
+    assert_eq!(
+        output,
+        "3\n\
+         \"héllo\" 1:0 0..6 0..5\n\
+         \"wörld\" 1:6 7..13 6..11\n\
+         \"日本\" 2:2 16..22 14..16\n\
+         \"🦀x\" 2:5 23..28 17..19\n\
+         \"end\" 3:0 30..33 21..24\n\
+         \"\" 3:3 33..33 24..24\n"
+    );
+}
+
+#[test]
+fn test_lexer_action_emits_extra_tokens() {
+    let grammar = parse_grammar(
+        r#"
+grammar Pairs;
+
+s: (LT | GT | ID)* EOF;
+
+ID: [a-z]+;
+LT: '<';
+GT: '>';
+PAIR: '<>' { self.emit(TokenKind::LT, "<"); self.emit(TokenKind::GT, ">"); } -> skip;
+WS: ' ' -> skip;
+"#,
+    );
+    let output = run_generated(
+        "emit",
+        &generate(&grammar),
+        r#"
+    let mut lexer = PairsLexer::new("a <> b");
+    println!("{:?}", lexer.peek_n(2).unwrap().iter().map(|t| t.kind).collect::<Vec<_>>());
+    let (tokens, errors) = lexer.tokenize_all();
+    assert!(errors.is_empty(), "{:?}", errors);
+    for token in &tokens {
+        println!("{:?} {:?} {}", token.kind, token.text, token.position);
+    }
+    println!("{}", PairsParser::new(tokens).parse_s().is_ok());
+"#,
+    );
+
+    assert_eq!(
+        output,
+        "[ID, LT]\n\
+         ID \"a\" 0\n\
+         LT \"<\" 2\n\
+         GT \">\" 2\n\
+         ID \"b\" 5\n\
+         Eof \"\" 6\n\
+         true\n"
+    );
+}
+
+#[test]
+fn test_lexer_action_emits_declared_tokens() {
+    let grammar = parse_grammar(
+        r#"
+grammar Block;
+
+tokens { INDENT, DEDENT }
+
+block: ID ':' INDENT ID+ DEDENT EOF;
+
+ID: [a-z]+;
+OPEN: '{' { self.emit(TokenKind::INDENT, ""); } -> skip;
+CLOSE: '}' { self.emit(TokenKind::DEDENT, ""); } -> skip;
+WS: ' ' -> skip;
+"#,
+    );
+    assert_eq!(grammar.declared_tokens, vec!["INDENT", "DEDENT"]);
+    let diagnostics = grammar.validate();
+    assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+
+    let output = run_generated(
+        "declared_tokens",
+        &generate(&grammar),
+        r#"
+    let (tokens, errors) = BlockLexer::new("a: { b c }").tokenize_all();
+    assert!(errors.is_empty(), "{:?}", errors);
+    for token in &tokens {
+        println!("{:?}", token.kind);
+    }
+    println!("{}", BlockParser::new(tokens).parse_block().is_ok());
+"#,
+    );
+
+    assert_eq!(output, "ID\nT__0\nINDENT\nID\nID\nDEDENT\nEof\ntrue\n");
+}
+
+#[test]
+fn test_peek_token_leaves_the_lexer_untouched() {
+    let grammar = parse_grammar(
+        r#"
+lexer grammar Peek;
+
+ID: [a-z]+;
+LT: '<';
+GT: '>';
+PAIR: '<>' { self.emit(TokenKind::LT, "<"); self.emit(TokenKind::GT, ">"); } -> skip;
+QUOTE: '"' -> pushMode(STR);
+WS: ' ' -> skip;
+
+mode STR;
+TEXT: [a-z ]+;
+END: '"' -> popMode;
+"#,
+    );
+    let output = run_generated(
+        "peek_token",
+        &generate(&grammar),
+        r#"
+    // The lexer's Debug output covers its position, cursor, pending queue
+    // and mode stack, so comparing it shows peeking changed none of them
+    let mut lexer = PeekLexer::new("a <> \"b c\" d");
+    loop {
+        let before = format!("{:?}", lexer);
+        let peeked = lexer.peek_token().unwrap();
+        assert_eq!(before, format!("{:?}", lexer), "peeking {:?}", peeked);
+        assert_eq!(lexer.peek_token().unwrap(), peeked);
+        let next = lexer.next_token().unwrap();
+        assert_eq!(peeked, next);
+        println!("{:?} {:?} {}", next.kind, next.text, lexer.current_mode());
+        if next.kind == TokenKind::Eof {
+            break;
+        }
+    }
+"#,
+    );
+
+    assert_eq!(
+        output,
+        "ID \"a\" DEFAULT_MODE\n\
+         LT \"<\" DEFAULT_MODE\n\
+         GT \">\" DEFAULT_MODE\n\
+         QUOTE \"\\\"\" STR\n\
+         TEXT \"b c\" STR\n\
+         END \"\\\"\" DEFAULT_MODE\n\
+         ID \"d\" DEFAULT_MODE\n\
+         Eof \"\" DEFAULT_MODE\n"
+    );
+}
+
+#[test]
+fn test_lexer_commands_apply_in_order() {
+    let grammar = parse_grammar(
+        r#"
+grammar Doc;
+
+doc: WORD* EOF;
+
+WORD: [a-z]+;
+NOTE: '#' [a-z]+ -> type(WORD), channel(HIDDEN);
+WS: [ \t\r\n]+ -> channel(HIDDEN), skip;
+"#,
+    );
+    let output = run_generated(
+        "commands",
+        &generate(&grammar),
+        r#"
+    let mut lexer = DocLexer::new("one #two three");
+    let (tokens, errors) = lexer.tokenize_all();
+    assert!(errors.is_empty(), "{:?}", errors);
+    for token in tokens.iter().chain(lexer.hidden_tokens()) {
+        println!("{:?} {:?} {}", token.kind, token.text, token.channel);
+    }
+"#,
+    );
+
+    assert_eq!(
+        output,
+        "WORD \"one\" 0\n\
+         WORD \"three\" 0\n\
+         Eof \"\" 0\n\
+         WORD \"#two\" 1\n"
+    );
+}
+
+#[test]
+fn test_lexer_modes_push_and_pop() {
+    let grammar = parse_grammar(
+        r#"
+grammar Str;
+
+prog: (ID | str)* EOF;
+str: QUOTE TEXT? CLOSE;
+
+QUOTE: '"' -> pushMode(STRING_MODE);
+ID: [a-z]+;
+
+mode STRING_MODE;
+TEXT: ~["]+;
+CLOSE: '"' -> popMode;
+"#,
+    );
+    let output = run_generated(
+        "modes",
+        &generate(&grammar),
+        r#"
+    let mut lexer = StrLexer::new("say \"hi there\" done");
+    let (tokens, errors) = lexer.tokenize_all();
+    assert!(errors.is_empty(), "{:?}", errors);
+    for token in &tokens {
+        println!("{:?} {:?}", token.kind, token.text);
+    }
+    println!("{}", lexer.current_mode());
+"#,
+    );
+
+    assert_eq!(
+        output,
+        "ID \"say\"\n\
+         QUOTE \"\\\"\"\n\
+         TEXT \"hi there\"\n\
+         CLOSE \"\\\"\"\n\
+         ID \"done\"\n\
+         Eof \"\"\n\
+         DEFAULT_MODE\n"
+    );
+}
+
+#[test]
+fn test_parser_grammar_uses_token_vocab() {
+    let mut grammar = parse_grammar(
+        r#"
+parser grammar CalcParser;
+options { tokenVocab = CalcLexer; }
+expr: NUMBER ('+' NUMBER)*;
+"#,
+    );
+    let lexer = parse_grammar("lexer grammar CalcLexer; NUMBER: [0-9]+; PLUS: '+';");
+    grammar.token_vocab = Some(lexer.export_token_vocab().parse().unwrap());
+
+    let output = run_generated(
+        "token_vocab",
+        &generate(&grammar),
+        r#"
+    let mut lexer = CalcParserLexer::new("+");
+    let token = lexer.next_token().unwrap();
+    println!("{:?} {}", token.kind, token.kind as u32);
+"#,
+    );
+
+    assert_eq!(output, "PLUS 2\n");
+}
+
+#[test]
+fn test_unicode_property_classes() {
+    let grammar = parse_grammar(
+        r#"
+grammar Uni;
+
+prog: (WORD | NUM | OTHER)* EOF;
+
+WORD: [\p{L}_] [\p{L}\p{N}_]*;
+NUM: \p{Digit}+;
+OTHER: \P{Alnum};
+"#,
+    );
+    let code = generate(&grammar);
+    assert!(code.contains("ch.is_alphabetic()"));
+    let output = run_generated(
+        "unicode_classes",
+        &code,
+        r#"
+    let mut lexer = UniLexer::new("héllo wörld_2 ١٢٣ _x €");
+    let (tokens, errors) = lexer.tokenize_all();
+    assert!(errors.is_empty(), "{:?}", errors);
+    for token in &tokens {
+        println!("{:?} {}", token.kind, token.text);
+    }
+"#,
+    );
+
+    assert_eq!(
+        output,
+        "WORD héllo\n\
+         WORD wörld_2\n\
+         NUM ١٢٣\n\
+         WORD _x\n\
+         OTHER €\n\
+         Eof \n"
+    );
+}
+
+#[test]
+fn test_escaped_literals_match_the_characters_they_name() {
+    let grammar = parse_grammar(
+        r#"
+grammar Lines;
+
+lines: (WORD | NL | TAB | QUOTE | ARROW)* EOF;
+
+NL: '\n';
+TAB: '\t';
+QUOTE: '\'';
+ARROW: '\u2192';
+WORD: [a-z]+;
+"#,
+    );
+    let output = run_generated(
+        "escaped_literals",
+        &generate(&grammar),
+        r#"
+    for result in LinesLexer::new("a\nb\t'\u{2192}n") {
+        match result {
+            Ok(token) => println!("{:?} {:?}", token.kind, token.text),
+            Err(err) => println!("error at {}", err.position),
+        }
+    }
+"#,
+    );
+
+    assert_eq!(
+        output,
+        "WORD \"a\"\nNL \"\\n\"\nWORD \"b\"\nTAB \"\\t\"\nQUOTE \"'\"\nARROW \"→\"\nWORD \"n\"\nEof \"\"\n"
+    );
+}
+
+#[test]
+fn test_set_of_line_breaks_is_not_skipped() {
+    let grammar = parse_grammar(
+        r#"
+grammar Lines;
+
+lines: (WORD | NL)* EOF;
+
+NL: [\t\n]+;
+WORD: [a-z]+;
+"#,
+    );
+    let output = run_generated(
+        "line_break_set",
+        &generate(&grammar),
+        r#"
+    for result in LinesLexer::new("a\nb\tc") {
+        match result {
+            Ok(token) => println!("{:?} {:?}", token.kind, token.text),
+            Err(err) => println!("error at {}", err.position),
+        }
+    }
+"#,
+    );
+
+    assert_eq!(
+        output,
+        "WORD \"a\"\nNL \"\\n\"\nWORD \"b\"\nNL \"\\t\"\nWORD \"c\"\nEof \"\"\n"
+    );
+}
+
+#[test]
+fn test_table_driven_parser_builds_the_same_trees() {
+    let grammar = parse_grammar(
+        r#"
+grammar Stmts;
+
+prog: stmt* EOF;
+stmt: name=ID '=' expr ';' | 'print' args+=expr (',' args+=expr)* ';';
+expr: NUMBER | ID | '(' expr ')';
+
+NUMBER: [0-9]+;
+ID: [a-z]+;
+WS: [ ]+ -> skip;
+"#,
+    );
+    let driver = r#"
+    for input in ["a = 1; print a, (2);", "print 1", "b = ;", ""] {
+        match StmtsParser::parse(input) {
+            Ok(tree) => println!("{:?}", tree),
+            Err(_) => println!("error"),
+        }
+    }
+"#;
+    let table_driven = CodeGenConfig {
+        generate_listener: false,
+        parser_strategy: ParserStrategy::TableDriven,
+        ..CodeGenConfig::default()
+    };
+    let code = RustCodeGenerator::new().generate(&grammar, &table_driven).unwrap();
+    assert!(code.contains("const LL1_TABLE: &[&[(TokenKind, usize)]] = &["));
+    assert!(code.contains("        self.ll1_parse(1)\n"));
+
+    let output = run_generated("table_driven", &code, driver);
+    assert_eq!(output, run_generated("table_driven_rd", &generate(&grammar), driver));
+    assert!(output.ends_with("error\nerror\nProg(ProgNode { children: [], tokens: [], stmt: [] })\n"));
+}
+
+#[test]
+fn test_table_driven_errors_show_literals() {
+    let grammar = parse_grammar(
+        r#"
+grammar Semi;
+
+s: 'a' ID ';' EOF;
+
+ID: [a-z]+;
+WS: [ ]+ -> skip;
+"#,
+    );
+    let table_driven = CodeGenConfig {
+        parser_strategy: ParserStrategy::TableDriven,
+        ..CodeGenConfig::default()
+    };
+    let driver = r#"
+    for input in ["a x", "b"] {
+        if let Err(err) = SemiParser::parse(input) {
+            println!("{}", err.message);
+        }
+    }
+"#;
+    let code = RustCodeGenerator::new().generate(&grammar, &table_driven).unwrap();
+    let output = run_generated("table_driven_literals", &code, driver);
+
+    // Quoted the same way as by recursive descent
+    assert_eq!(output, "Expected ';', found end of input\nExpected 'a', found \"b\"\n");
+    assert_eq!(output, run_generated("table_driven_literals_rd", &generate(&grammar), driver));
+}
+
+#[test]
+fn test_lookup_table_classifies_chars_beyond_the_bmp() {
+    let grammar = parse_grammar(
+        r#"
+grammar Emoji;
+
+items: (EMOJI | WORD)* EOF;
+
+EMOJI: [\u{1F600}-\u{1F64F}];
+WORD: [a-z]+;
+WS: [ ]+ -> skip;
+"#,
+    );
+    let lexer_rules: Vec<&Rule> = grammar.lexer_rules().collect();
+    let mut builder = LookupTableBuilder::new();
+    builder.build_from_rules(&lexer_rules);
+    let generated = format!(
+        "{}\npub struct Classes;\n\nimpl Classes {{\n{}    pub fn class(ch: char) -> u8 {{\n        Self::get_char_class(ch)\n    }}\n}}\n",
+        generate(&grammar),
+        builder.generate_lookup_table()
+    );
+
+    let output = run_generated(
+        "lookup_beyond_bmp",
+        &generated,
+        r#"
+    for ch in ['😀', '🙏', '🚀', 'a', '!'] {
+        println!("{} {}", ch.len_utf8(), Classes::class(ch));
+    }
+    for token in EmojiLexer::new("hi 😄 there") {
+        let token = token.unwrap();
+        println!("{:?} {:?}", token.kind, token.text);
+    }
+"#,
+    );
+
+    assert_eq!(
+        output,
+        "4 0\n4 0\n4 255\n1 1\n1 255\n\
+         WORD \"hi\"\nEMOJI \"😄\"\nWORD \"there\"\nEof \"\"\n"
+    );
+}
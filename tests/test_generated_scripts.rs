@@ -0,0 +1,220 @@
+//! Round-trip tests for generated Python and JavaScript parsers.
+//!
+//! These tests generate code from a grammar and run it with `python3` or
+//! `node` together with a small driver script. The generated lexers do not
+//! tokenize yet, so drivers build the token list by hand.
+
+use minipg::ast::Grammar;
+use minipg::codegen::javascript::JavaScriptCodeGenerator;
+use minipg::codegen::python::PythonCodeGenerator;
+use minipg::parser::GrammarParser;
+use minipg::traits::GrammarParser as GrammarParserTrait;
+use minipg::types::CodeGenConfig;
+use minipg::CodeGenerator;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+// ============================================================================
+// HELPER FUNCTIONS
+// ============================================================================
+
+fn parse_grammar(grammar_text: &str) -> Grammar {
+    let parser = GrammarParser::new();
+    parser.parse_string(grammar_text, "test.g4").expect("Failed to parse grammar")
+}
+
+/// Run `driver` with `interpreter` next to the generated module and return
+/// its stdout. The module is written as `generated.<extension>`.
+fn run_script(name: &str, interpreter: &str, extension: &str, generated: &str, driver: &str) -> String {
+    let dir: PathBuf = std::env::temp_dir().join(format!("minipg_{}_{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+
+    fs::write(dir.join(format!("generated.{}", extension)), generated).unwrap();
+    let main = dir.join(format!("main.{}", extension));
+    fs::write(&main, driver).unwrap();
+
+    let run = Command::new(interpreter)
+        .arg(&main)
+        .current_dir(&dir)
+        .output()
+        .unwrap_or_else(|err| panic!("Failed to run {}: {}", interpreter, err));
+    let _ = fs::remove_dir_all(&dir);
+    assert!(
+        run.status.success(),
+        "Driver failed:\n{}",
+        String::from_utf8_lossy(&run.stderr)
+    );
+    String::from_utf8(run.stdout).unwrap()
+}
+
+// ============================================================================
+// QUANTIFIERS
+// ============================================================================
+
+const ITEMS: &str = r#"
+grammar Items;
+
+items: names+=ID* SEMI EOF;
+pairs: (ID EQ (ID | NUM))+ EOF;
+maybe: (ID?)* SEMI;
+
+ID: [a-z]+;
+NUM: [0-9]+;
+EQ: '=';
+SEMI: ';';
+"#;
+
+#[test]
+fn test_python_quantifiers_match_tokens() {
+    let code = PythonCodeGenerator::new()
+        .generate(&parse_grammar(ITEMS), &CodeGenConfig::default())
+        .unwrap();
+    let output = run_script(
+        "python_quantifiers",
+        "python3",
+        "py",
+        &code,
+        r#"from generated import *
+
+def tokens(*kinds):
+    toks = [Token(TokenKind[kind], kind.lower(), i) for i, kind in enumerate(kinds)]
+    return toks + [Token(TokenKind.EOF, '', len(kinds))]
+
+print(len(ItemsParser(tokens('ID', 'ID', 'ID', 'SEMI')).parse_items().names))
+print(len(ItemsParser(tokens('SEMI')).parse_items().names))
+ItemsParser(tokens('ID', 'EQ', 'NUM', 'ID', 'EQ', 'ID')).parse_pairs()
+ItemsParser(tokens('ID', 'SEMI')).parse_maybe()
+for kinds, parse in [(('ID', 'NUM'), 'parse_items'), (('ID', 'EQ'), 'parse_pairs')]:
+    try:
+        getattr(ItemsParser(tokens(*kinds)), parse)()
+    except ParseError as err:
+        print(err.message)
+"#,
+    );
+    assert_eq!(output, "3\n0\nExpected SEMI, got NUM\nExpected at least one match\n");
+}
+
+#[test]
+fn test_javascript_quantifiers_match_tokens() {
+    let code = JavaScriptCodeGenerator::new()
+        .generate(&parse_grammar(ITEMS), &CodeGenConfig::default())
+        .unwrap();
+    let output = run_script(
+        "javascript_quantifiers",
+        "node",
+        "mjs",
+        &code,
+        r#"import { Token, TokenKind, ItemsParser, ParseError } from './generated.mjs';
+
+const tokens = (...kinds) => [
+  ...kinds.map((kind, i) => new Token(TokenKind[kind], kind.toLowerCase(), i)),
+  new Token(TokenKind.EOF, '', kinds.length),
+];
+
+console.log(new ItemsParser(tokens('ID', 'ID', 'ID', 'SEMI')).parseItems().names.length);
+console.log(new ItemsParser(tokens('SEMI')).parseItems().names.length);
+new ItemsParser(tokens('ID', 'EQ', 'NUM', 'ID', 'EQ', 'ID')).parsePairs();
+new ItemsParser(tokens('ID', 'SEMI')).parseMaybe();
+for (const [kinds, parse] of [[['ID', 'NUM'], 'parseItems'], [['ID', 'EQ'], 'parsePairs']]) {
+  try {
+    new ItemsParser(tokens(...kinds))[parse]();
+  } catch (err) {
+    if (!(err instanceof ParseError)) throw err;
+    console.log(err.message);
+  }
+}
+"#,
+    );
+    assert_eq!(output, "3\n0\nExpected SEMI, got NUM\nExpected at least one match\n");
+}
+
+// ============================================================================
+// AST NODES
+// ============================================================================
+
+const PAIRS: &str = r#"
+grammar Pairs;
+
+pair: id=ID ':' value;
+value: ID | NUM;
+
+ID: [a-z]+;
+NUM: [0-9]+;
+COLON: ':';
+"#;
+
+#[test]
+fn test_python_nodes_hold_matched_tokens_and_children() {
+    let code = PythonCodeGenerator::new()
+        .generate(&parse_grammar(PAIRS), &CodeGenConfig::default())
+        .unwrap();
+    let output = run_script(
+        "python_nodes",
+        "python3",
+        "py",
+        &code,
+        r#"from generated import *
+
+toks = [Token(TokenKind.ID, 'a', 0), Token(TokenKind.COLON, ':', 2), Token(TokenKind.NUM, '1', 4)]
+pair = PairsParser(toks + [Token(TokenKind.EOF, '', 5)]).parse_pair()
+print(pair.id.text, pair.value.num.text, pair.value.id)
+print(pair.children == [pair.value], pair.value.children)
+"#,
+    );
+    assert_eq!(output, "a 1 None\nTrue []\n");
+}
+
+#[test]
+fn test_javascript_nodes_hold_matched_tokens_and_children() {
+    let code = JavaScriptCodeGenerator::new()
+        .generate(&parse_grammar(PAIRS), &CodeGenConfig::default())
+        .unwrap();
+    let output = run_script(
+        "javascript_nodes",
+        "node",
+        "mjs",
+        &code,
+        r#"import { Token, TokenKind, PairsParser } from './generated.mjs';
+
+const toks = [new Token(TokenKind.ID, 'a', 0), new Token(TokenKind.COLON, ':', 2), new Token(TokenKind.NUM, '1', 4)];
+const pair = new PairsParser([...toks, new Token(TokenKind.EOF, '', 5)]).parsePair();
+console.log(pair.id.text, pair.value.num.text, pair.value.id);
+console.log(pair.children.length === 1 && pair.children[0] === pair.value, pair.value.children.length);
+"#,
+    );
+    assert_eq!(output, "a 1 null\ntrue 0\n");
+}
+
+// ============================================================================
+// ALTERNATIVES
+// ============================================================================
+
+#[test]
+fn test_python_group_with_many_alternatives_compiles() {
+    // Python allows at most 20 nested blocks, so the alternatives must not
+    // nest in each other
+    let names: Vec<String> = (1..=25).map(|i| format!("K{}", i)).collect();
+    let tokens: String = names.iter().map(|name| format!("{}: 'k{}';\n", name, &name[1..])).collect();
+    let grammar = format!("grammar Keys;\n\nkey: ({}) EOF;\n\n{}", names.join(" | "), tokens);
+    let code = PythonCodeGenerator::new()
+        .generate(&parse_grammar(&grammar), &CodeGenConfig::default())
+        .unwrap();
+    let output = run_script(
+        "python_alternatives",
+        "python3",
+        "py",
+        &code,
+        r#"from generated import *
+
+for kind in ['K1', 'K25']:
+    toks = [Token(TokenKind[kind], kind.lower(), 0), Token(TokenKind.EOF, '', 3)]
+    print(KeysParser(toks).parse_key().children)
+try:
+    KeysParser([Token(TokenKind.EOF, '', 0)]).parse_key()
+except ParseError as err:
+    print(err.message)
+"#,
+    );
+    assert_eq!(output, "[]\n[]\nExpected K25, got EOF\n");
+}
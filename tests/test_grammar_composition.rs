@@ -332,3 +332,79 @@ fn test_grammar_composition_complex_scenario() {
     assert_eq!(grammar.named_actions.len(), 1);
     assert!(grammar.rules.len() > 0);
 }
+
+#[test]
+fn test_grammar_merge_lexer_and_parser() {
+    let parser = minipg::parser::GrammarParser::new();
+    let lexer = parser
+        .parse_string(
+            r#"
+            lexer grammar CalcLexer;
+            channels { COMMENTS }
+            @members { let depth = 0; }
+            NUMBER: [0-9]+;
+            PLUS: '+';
+            COMMENT: '#' ~[\n]* -> channel(COMMENTS);
+            QUOTE: '"' -> pushMode(STR);
+            mode STR;
+            TEXT: ~["]+;
+            CLOSE: '"' -> popMode;
+            "#,
+            "CalcLexer.g4",
+        )
+        .expect("Failed to parse lexer grammar");
+    let calc = parser
+        .parse_string(
+            r#"
+            parser grammar CalcParser;
+            options { tokenVocab = CalcLexer; language = rust; }
+            @header { use std::fmt; }
+            calc: expr EOF;
+            expr: NUMBER ('+' NUMBER)*;
+            "#,
+            "CalcParser.g4",
+        )
+        .expect("Failed to parse parser grammar");
+
+    let merged = Grammar::merge(lexer, calc).expect("Failed to merge");
+
+    assert_eq!(merged.name, "Calc");
+    assert_eq!(merged.grammar_type, GrammarType::Combined);
+    let names: Vec<&str> = merged.rules.iter().map(|r| r.name.as_str()).collect();
+    assert_eq!(names, ["calc", "expr", "NUMBER", "PLUS", "COMMENT", "QUOTE", "TEXT", "CLOSE"]);
+    assert_eq!(merged.options.get("language").map(String::as_str), Some("rust"));
+    assert!(!merged.options.contains_key("tokenVocab"));
+    assert!(merged.named_actions.contains_key("header"));
+    assert!(merged.named_actions.contains_key("lexer::members"));
+    assert!(merged.channels.contains("COMMENTS"));
+    assert_eq!(merged.lexer_modes.get("STR").map(Vec::len), Some(2));
+
+    // Tokens only the lexer defined now resolve, and '+' uses PLUS
+    let errors: Vec<_> = merged
+        .validate()
+        .into_iter()
+        .filter(|d| d.severity == minipg::DiagnosticSeverity::Error)
+        .collect();
+    assert!(errors.is_empty(), "{:?}", errors);
+    assert!(minipg::codegen::common::implicit_literal_tokens(&merged).is_empty());
+}
+
+#[test]
+fn test_grammar_merge_rejects_conflicts() {
+    let parser = minipg::parser::GrammarParser::new();
+    let lexer = || {
+        parser
+            .parse_string("lexer grammar L; NUMBER: [0-9]+;", "L.g4")
+            .unwrap()
+    };
+    let calc = parser
+        .parse_string("parser grammar P; expr: NUMBER; NUMBER: [0-9]+ '.';", "P.g4")
+        .unwrap();
+
+    let err = Grammar::merge(lexer(), calc.clone()).unwrap_err();
+    assert!(err.to_string().contains("rule NUMBER is defined in both L and P"), "{}", err);
+
+    // The grammars must be passed in lexer, parser order
+    let err = Grammar::merge(calc, lexer()).unwrap_err();
+    assert!(err.to_string().contains("cannot merge parser grammar P"), "{}", err);
+}